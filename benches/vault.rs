@@ -0,0 +1,139 @@
+//! Benchmarks of the indexing and query hot paths over a synthetic vault.
+//!
+//! The vault has 10,000 notes unless `TYPST_OXIDE_BENCH_NOTES` says otherwise. Run with
+//! `scripts/bench.sh`, or `cargo bench --bench vault`.
+
+use chrono::NaiveDate;
+use criterion::{Criterion, criterion_group, criterion_main};
+use lsp_types::Position;
+use std::hint::black_box;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use typst_oxide::config::DailyNotesConfig;
+use typst_oxide::index::Index;
+use typst_oxide::lsp::completion;
+use typst_oxide::parser::Parser;
+
+const DEFAULT_NOTES: usize = 10_000;
+// Every note links here, so it has the most backlinks
+const HUB: &str = "hub";
+
+struct Vault {
+    // Removes the vault when dropped
+    _dir: TempDir,
+    root: PathBuf,
+    notes: Vec<PathBuf>,
+    index: Index,
+    parser: Parser,
+}
+
+fn note_count() -> usize {
+    std::env::var("TYPST_OXIDE_BENCH_NOTES")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_NOTES)
+}
+
+/// The text of note `i` of `count`, linking to the hub and to a few other notes picked by a
+/// fixed pseudo-random sequence, so every run builds the same vault.
+fn note_text(i: usize, count: usize) -> String {
+    let mut text = format!("= Note {i}\n\nBack to [[{HUB}]]. <note-{i}>\n\n");
+    let mut next = i;
+    for paragraph in 0..4 {
+        next = (next * 7919 + 104_729) % count;
+        text.push_str(&format!(
+            "Paragraph {paragraph} of note {i} relates to [[note-{next}]] and \
+             [[note-{}:note-{}|its neighbor]], see @note-{i}. ^p{paragraph}\n\n",
+            (next + 1) % count,
+            (next + 1) % count,
+        ));
+    }
+    text.push_str("$ sum_(k=1)^n k = (n(n+1))/2 $\n\n```typ\n[[not-a-link]]\n```\n");
+    text
+}
+
+fn vault() -> Vault {
+    let count = note_count();
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().to_path_buf();
+    let parser = Parser::new().unwrap();
+
+    let hub = root.join(format!("{HUB}.typ"));
+    std::fs::write(&hub, "= Hub\n").unwrap();
+    let mut notes = vec![hub];
+    for i in 0..count {
+        let folder = root.join(format!("notes/{}", i / 1000));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(format!("note-{i}.typ"));
+        std::fs::write(&path, note_text(i, count)).unwrap();
+        notes.push(path);
+    }
+
+    let parsed: Vec<_> = notes
+        .iter()
+        .map(|path| {
+            let content = std::fs::read_to_string(path).unwrap();
+            parser.parse_content(&content, path).unwrap()
+        })
+        .collect();
+    let mut index = Index::new(&root).unwrap();
+    index.store_files_bulk(&parsed).unwrap();
+
+    Vault {
+        _dir: dir,
+        root,
+        notes,
+        index,
+        parser,
+    }
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let mut vault = vault();
+    let count = vault.notes.len() - 1;
+    let note = vault.notes[count / 2].clone();
+    let text = std::fs::read_to_string(&note).unwrap();
+
+    c.bench_function("parse_content", |b| {
+        b.iter(|| vault.parser.parse_content(black_box(&text), &note).unwrap())
+    });
+
+    let parsed = vault.parser.parse_content(&text, &note).unwrap();
+    c.bench_function("store_file", |b| {
+        b.iter(|| vault.index.store_file(&note, black_box(&parsed)).unwrap())
+    });
+
+    let hub = vault.root.join(format!("{HUB}.typ"));
+    c.bench_function("backlinks/hub", |b| {
+        b.iter(|| vault.index.get_backward_links(black_box(&hub)).unwrap())
+    });
+    c.bench_function("backlinks/note", |b| {
+        b.iter(|| vault.index.get_backward_links(black_box(&note)).unwrap())
+    });
+
+    // Completing a link target with a prefix most notes share
+    let line = "See [[note-1";
+    let position = Position::new(0, line.len() as u32);
+    let daily_notes = DailyNotesConfig::default();
+    let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let cx = completion::TargetContext {
+        note: &note,
+        daily_notes: &daily_notes,
+        today,
+        snippets: None,
+    };
+    c.bench_function("completion", |b| {
+        b.iter(|| {
+            completion::complete(&vault.index, &vault.parser, &cx, black_box(line), position)
+                .unwrap()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    // Setting up the vault dominates otherwise
+    config = Criterion::default().sample_size(20);
+    targets = benchmarks
+}
+criterion_main!(benches);
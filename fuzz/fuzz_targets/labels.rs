@@ -0,0 +1,24 @@
+//! The label scanner: `<label>` definitions with the elements they attach to, `@label`
+//! references, and lookups at every position of a line.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::sync::LazyLock;
+use typst_oxide::parser::labels::LabelParser;
+
+static PARSER: LazyLock<LabelParser> = LazyLock::new(|| LabelParser::new().unwrap());
+
+fuzz_target!(|content: &str| {
+    for label in PARSER.parse_labels(content) {
+        if let Some(element) = label.element {
+            assert!((element.line, element.column) <= (element.end_line, element.end_column));
+        }
+    }
+    PARSER.parse_references(content);
+
+    let line = content.lines().next().unwrap_or_default();
+    for character in 0..=line.chars().count() + 1 {
+        PARSER.label_at(line, character);
+    }
+});
@@ -0,0 +1,12 @@
+//! The JSON `typst query` prints for a note's metadata, which may hold any value a note
+//! author writes.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use typst_oxide::parser::metadata::parse_metadata_json;
+
+fuzz_target!(|json: &str| {
+    // Errors are expected; panics are not
+    let _ = parse_metadata_json(json);
+});
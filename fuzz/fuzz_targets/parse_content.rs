@@ -0,0 +1,50 @@
+//! Whole notes: every extractor must finish without panicking and report positions inside
+//! the note.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::path::Path;
+use std::sync::LazyLock;
+use typst_oxide::parser::Parser;
+
+static PARSER: LazyLock<Parser> = LazyLock::new(|| Parser::new().unwrap());
+
+fuzz_target!(|content: &str| {
+    let parsed = PARSER
+        .parse_content(content, Path::new("note.typ"))
+        .unwrap();
+
+    let lines: Vec<&str> = content.split('\n').collect();
+    let positions = parsed
+        .wikilinks
+        .iter()
+        .map(|link| (link.line, link.column))
+        .chain(parsed.labels.iter().map(|label| (label.line, label.column)))
+        .chain(
+            parsed
+                .headings
+                .iter()
+                .map(|label| (label.line, label.column)),
+        )
+        .chain(parsed.blocks.iter().map(|block| (block.line, block.column)))
+        .chain(
+            parsed
+                .figures
+                .iter()
+                .map(|figure| (figure.line, figure.column)),
+        )
+        .chain(
+            parsed
+                .citations
+                .iter()
+                .map(|citation| (citation.line, citation.column)),
+        );
+    for (line, column) in positions {
+        assert!((1..=lines.len()).contains(&line), "line {line}");
+        assert!(
+            (1..=lines[line - 1].chars().count() + 1).contains(&column),
+            "column {column} of line {line}"
+        );
+    }
+});
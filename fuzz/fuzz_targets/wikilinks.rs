@@ -0,0 +1,39 @@
+//! The wikilink scanner in every syntax, over whole notes and at every position of a line as
+//! the language server asks for completions and definitions.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::path::Path;
+use std::sync::LazyLock;
+use typst_oxide::parser::wikilinks::{WikilinkParser, WikilinkSyntax};
+
+static PARSERS: LazyLock<Vec<WikilinkParser>> = LazyLock::new(|| {
+    [(':', false), (':', true), ('#', false), ('#', true)]
+        .into_iter()
+        .map(|(label_separator, alias_first)| {
+            WikilinkParser::with_syntax(&WikilinkSyntax {
+                label_separator,
+                alias_first,
+            })
+            .unwrap()
+        })
+        .collect()
+});
+
+fuzz_target!(|input: (u8, &str)| {
+    let (syntax, content) = input;
+    let parser = &PARSERS[syntax as usize % PARSERS.len()];
+
+    for (wikilink, span) in parser.wikilink_spans(content) {
+        let line = content.split('\n').nth(wikilink.line - 1).unwrap();
+        assert!(span.end <= line.chars().count());
+    }
+    parser.parse_wikilinks(content, Path::new("note.typ"));
+
+    let line = content.lines().next().unwrap_or_default();
+    for character in 0..=line.chars().count() + 1 {
+        parser.target_prefix_at(line, character);
+        parser.wikilink_at(line, 1, character);
+    }
+});
@@ -0,0 +1,41 @@
+#!/bin/bash
+
+# Benchmark script for typst-oxide
+# Usage: ./scripts/bench.sh [--quick] [--notes <count>] [--save <baseline>] [--compare <baseline>]
+# Example: ./scripts/bench.sh --save main, then ./scripts/bench.sh --compare main on a branch
+#
+# Runs the criterion benchmarks in benches/ over a synthetic vault of 10,000 notes. Results and
+# saved baselines are kept under target/criterion. --quick trades precision for time, for CI.
+
+set -e
+
+ARGS=()
+
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --quick)
+            ARGS+=(--quick)
+            ;;
+        --notes)
+            export TYPST_OXIDE_BENCH_NOTES="$2"
+            shift
+            ;;
+        --save)
+            ARGS+=(--save-baseline "$2")
+            shift
+            ;;
+        --compare)
+            ARGS+=(--baseline "$2")
+            shift
+            ;;
+        *)
+            echo "Unknown option: $1" >&2
+            echo "Usage: $0 [--quick] [--notes <count>] [--save <baseline>] [--compare <baseline>]" >&2
+            exit 1
+            ;;
+    esac
+    shift
+done
+
+# Plain output, as CI logs don't render colors
+cargo bench --bench vault -- --color never --noplot "${ARGS[@]}"
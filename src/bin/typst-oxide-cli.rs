@@ -0,0 +1,613 @@
+use anyhow::{Context, Result};
+use clap::{Parser as _, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use typst_oxide::command::{self, DailyNoteRequest};
+use typst_oxide::config::Config;
+use typst_oxide::diagnostics::{self, Problem, Severity};
+use typst_oxide::export::markdown::{LinkStyle, MarkdownExporter};
+use typst_oxide::graph::{self, Direction, GraphFormat};
+use typst_oxide::index::{Index, IndexReport};
+use typst_oxide::parser::Parser;
+use typst_oxide::parser::models::Wikilink;
+use typst_oxide::publish::Publisher;
+use typst_oxide::refactor::dead_labels::{DeadLabel, cleanup_edits, dead_labels};
+use typst_oxide::refactor::{self, rewrite::rewrite_links};
+use typst_oxide::sdk;
+use typst_oxide::workspace;
+
+/// Query a vault's index from scripts and CI without an editor.
+///
+/// Every command but `verify` brings the index up to date first; unchanged notes are not
+/// re-parsed.
+#[derive(clap::Parser)]
+#[command(name = "typst-oxide-cli", version)]
+struct Cli {
+    /// Vault root, defaults to the current directory
+    #[arg(long, global = true)]
+    root: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    #[value(alias = "text")]
+    Table,
+    Json,
+    /// GitHub Actions annotations for `check`; other commands print a table
+    Github,
+    /// Graphviz for `graph`; other commands print a table
+    Dot,
+    /// GraphML for `graph`; other commands print a table
+    Graphml,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Update the index and print the number of notes
+    Index,
+    /// List links pointing to a note
+    Backlinks { file: PathBuf },
+    /// List links contained in a note
+    Forwardlinks { file: PathBuf },
+    /// Find notes whose path, title, aliases, or tags contain the query
+    Search { query: String },
+    /// List notes with no links in or out
+    Orphans,
+    /// List links that match no note or attachment; exits with status 1 if any exist
+    BrokenLinks,
+    /// List attachments (PDFs, images, ...) that no note links to or embeds
+    UnusedAttachments,
+    /// Report broken links, missing labels or blocks, and duplicate labels under `path`
+    /// (the whole vault by default); exits with status 1 if any are found
+    Check { path: Option<PathBuf> },
+    /// List labels under `path` (the whole vault by default) that no `@label` or
+    /// `[[note:label]]` refers to; exits with status 1 if any exist, unless `--fix` removes them
+    DeadLabels {
+        path: Option<PathBuf>,
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Export the note graph with titles and tags as node attributes
+    Graph {
+        /// Keep archived notes in the graph
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Convert notes to Markdown under `out`, mirroring the vault layout
+    ExportMarkdown {
+        out: PathBuf,
+        /// Only export the notes within `depth` links of this note
+        #[arg(long)]
+        from: Option<PathBuf>,
+        #[arg(long, default_value_t = 1)]
+        depth: usize,
+        /// Rewrite wikilinks as relative `.md` links instead of keeping `[[...]]`
+        #[arg(long)]
+        relative_links: bool,
+    },
+    /// Create today's daily note, or the one for `--date YYYY-MM-DD`, and print its path
+    Daily {
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Point every wikilink to `from` at `to` and save the changed notes, e.g. after
+    /// renaming a note outside the editor
+    RewriteLinks { from: String, to: String },
+    /// Compile every note to HTML under `out` as a static site; requires `typst`
+    Publish {
+        out: PathBuf,
+        /// Site title, defaults to the vault's directory name
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Cross-check the index against the notes on disk and repair it: missing, stale and new
+    /// notes, rows orphaned by removed notes, and a damaged database, which is rebuilt
+    Verify {
+        /// Only report the problems, exiting with status 1 if there are any
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the TypeScript types of the server's requests and responses, with `methods.ts`
+    /// mapping each `typst-oxide/...` method to them, into `out` (`bindings/src` by default)
+    Bindings { out: Option<PathBuf> },
+}
+
+#[derive(Serialize)]
+struct LinkRow {
+    file: PathBuf,
+    line: usize,
+    column: usize,
+    target: String,
+    label: Option<String>,
+    block: Option<String>,
+}
+
+#[derive(Serialize)]
+struct NoteRow {
+    file: PathBuf,
+}
+
+#[derive(Serialize)]
+struct IssueRow {
+    problem: &'static str,
+    detail: String,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    // Needs no vault
+    if let Command::Bindings { out } = &cli.command {
+        let out = out
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(sdk::BINDINGS_DIR));
+        let count = sdk::export_bindings(&out)?;
+        println!(
+            "Wrote {count} types for SDK version {} to {}",
+            sdk::SDK_VERSION,
+            out.display()
+        );
+        return Ok(());
+    }
+    let root = match &cli.root {
+        Some(root) => root.clone(),
+        None => std::env::current_dir()?,
+    };
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Vault root {} not found", root.display()))?;
+
+    let config = Config::load(&root, None)?;
+    let parser = Arc::new(Parser::with_syntax(&config.wikilink_syntax)?);
+    let mut index = Index::open_with_key(
+        &root,
+        &config.index_path(&root)?,
+        config.index_key()?.as_deref(),
+    )?;
+    index.set_case_insensitive_links(config.case_insensitive_links)?;
+    index.set_archive_folders(&config.archive_folders)?;
+    index.set_max_index_rows(config.max_index_rows)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    // Checks the index as it was left, before the usual update would repair some of it
+    if let Command::Verify { dry_run } = cli.command {
+        let report = runtime.block_on(workspace::verify_index(
+            &parser,
+            &mut index,
+            &config.note_filter()?,
+            !dry_run,
+        ))?;
+        return print_report(cli.format, &root, report, dry_run);
+    }
+    let note_count = runtime.block_on(workspace::index_workspace(
+        &parser,
+        &mut index,
+        &config.note_filter()?,
+        &mut |_, _| {},
+    ))?;
+    workspace::record_git_history(&index, config.git_history)?;
+
+    let relative = |path: &Path| path.strip_prefix(&root).unwrap_or(path).to_path_buf();
+    let link_row = |file: &Path, wikilink: Wikilink| LinkRow {
+        file: relative(file),
+        line: wikilink.line,
+        column: wikilink.column,
+        target: wikilink.target,
+        label: wikilink.label,
+        block: wikilink.block,
+    };
+
+    match cli.command {
+        Command::Index => match cli.format {
+            Format::Json => println!("{}", serde_json::json!({ "notes": note_count })),
+            _ => println!("Indexed {note_count} notes"),
+        },
+        Command::Backlinks { file } => {
+            let file = note_path(&file)?;
+            let rows = index
+                .get_backward_links(&file)?
+                .into_iter()
+                .map(|(source, wikilink)| link_row(&source, wikilink))
+                .collect::<Vec<_>>();
+            print_links(cli.format, &rows)?;
+        }
+        Command::Forwardlinks { file } => {
+            let file = note_path(&file)?;
+            let rows = index
+                .get_forward_links(&file)?
+                .into_iter()
+                .map(|wikilink| link_row(&file, wikilink))
+                .collect::<Vec<_>>();
+            print_links(cli.format, &rows)?;
+        }
+        Command::Search { query } => {
+            let rows = index
+                .search(&query)?
+                .iter()
+                .map(|file| NoteRow {
+                    file: relative(file),
+                })
+                .collect::<Vec<_>>();
+            print_notes(cli.format, &rows)?;
+        }
+        Command::Orphans => {
+            let rows = index
+                .get_orphans()?
+                .iter()
+                .map(|file| NoteRow {
+                    file: relative(file),
+                })
+                .collect::<Vec<_>>();
+            print_notes(cli.format, &rows)?;
+        }
+        Command::UnusedAttachments => {
+            let rows = workspace::unused_attachments(&index, &config.note_filter()?)?
+                .iter()
+                .map(|file| NoteRow {
+                    file: relative(file),
+                })
+                .collect::<Vec<_>>();
+            print_notes(cli.format, &rows)?;
+        }
+        Command::BrokenLinks => {
+            let rows = index
+                .get_broken_links(None)?
+                .into_iter()
+                .map(|(source, wikilink)| link_row(&source, wikilink))
+                .collect::<Vec<_>>();
+            print_links(cli.format, &rows)?;
+            if !rows.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Check { path } => {
+            let scope = match path {
+                Some(path) => note_path(&path)?,
+                None => root.clone(),
+            };
+            let problems = diagnostics::check(&index)?
+                .into_iter()
+                .filter(|problem| problem.file.starts_with(&scope))
+                .collect();
+            let problems: Vec<Problem> =
+                diagnostics::apply_rules(problems, &config.severity, |path| {
+                    std::fs::read_to_string(path).ok()
+                })
+                .into_iter()
+                .map(|problem| Problem {
+                    file: relative(&problem.file),
+                    ..problem
+                })
+                .collect();
+            print_problems(cli.format, &problems)?;
+            if problems
+                .iter()
+                .any(|problem| problem.severity != Severity::Information)
+            {
+                std::process::exit(1);
+            }
+        }
+        Command::DeadLabels { path, fix } => {
+            let scope = match path {
+                Some(path) => note_path(&path)?,
+                None => root.clone(),
+            };
+            let notes: Vec<PathBuf> = index
+                .get_all_files()?
+                .into_iter()
+                .filter(|note| note.starts_with(&scope))
+                .collect();
+            let read = |path: &Path| std::fs::read_to_string(path).ok();
+            let dead = dead_labels(&index, &parser, &notes, read)?;
+            if fix {
+                let edits = cleanup_edits(&dead, read);
+                refactor::write_edits(&edits)?;
+                for path in edits.keys() {
+                    runtime.block_on(workspace::index_file(&parser, &mut index, path))?;
+                }
+                match cli.format {
+                    Format::Json => println!(
+                        "{}",
+                        serde_json::json!({ "labels": dead.len(), "notes": edits.len() })
+                    ),
+                    _ => println!("Removed {} labels in {} notes", dead.len(), edits.len()),
+                }
+            } else {
+                let rows: Vec<DeadLabel> = dead
+                    .into_iter()
+                    .map(|label| DeadLabel {
+                        file: relative(&label.file),
+                        ..label
+                    })
+                    .collect();
+                print_rows(
+                    cli.format,
+                    &rows,
+                    &["FILE", "LINE", "COLUMN", "LABEL"],
+                    |row| {
+                        vec![
+                            row.file.display().to_string(),
+                            row.line.to_string(),
+                            row.column.to_string(),
+                            format!("<{}>", row.name),
+                        ]
+                    },
+                )?;
+                if !rows.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Graph { include_archived } => {
+            let graph_format = match cli.format {
+                Format::Dot => Some(GraphFormat::Dot),
+                Format::Graphml => Some(GraphFormat::GraphMl),
+                Format::Json => Some(GraphFormat::Json),
+                _ => None,
+            };
+            match graph_format {
+                Some(graph_format) => {
+                    println!(
+                        "{}",
+                        graph::export(&index, graph_format, include_archived)?.trim_end()
+                    )
+                }
+                None => {
+                    let edges = graph::build(&index, include_archived)?.edges;
+                    print_rows(cli.format, &edges, &["SOURCE", "TARGET", "LINKS"], |edge| {
+                        vec![
+                            edge.source.display().to_string(),
+                            edge.target.display().to_string(),
+                            edge.weight.to_string(),
+                        ]
+                    })?;
+                }
+            }
+        }
+        Command::ExportMarkdown {
+            out,
+            from,
+            depth,
+            relative_links,
+        } => {
+            let notes = match from {
+                Some(from) => {
+                    graph::neighborhood(&index, &note_path(&from)?, depth, Direction::Both)?
+                }
+                None => index.get_all_files()?,
+            };
+            let style = if relative_links {
+                LinkStyle::Relative
+            } else {
+                LinkStyle::Wikilink
+            };
+            let written = MarkdownExporter::new(&index, &parser, style, notes)?.write(&out)?;
+            match cli.format {
+                Format::Json => println!("{}", serde_json::json!({ "notes": written })),
+                _ => println!("Exported {written} notes to {}", out.display()),
+            }
+        }
+        Command::Daily { date } => {
+            let today = chrono::Local::now().date_naive();
+            let response = command::handle_daily_note(
+                &root,
+                &config.daily_notes,
+                today,
+                DailyNoteRequest { date },
+            )?;
+            if response.created {
+                runtime.block_on(workspace::index_file(&parser, &mut index, &response.path))?;
+            }
+            match cli.format {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&response)?),
+                _ => println!("{}", response.path.display()),
+            }
+        }
+        Command::RewriteLinks { from, to } => {
+            let edits = rewrite_links(
+                &index,
+                &parser,
+                &config.wikilink_syntax,
+                &from,
+                &to,
+                |path| std::fs::read_to_string(path).ok(),
+            )?;
+            refactor::write_edits(&edits)?;
+            for path in edits.keys() {
+                runtime.block_on(workspace::index_file(&parser, &mut index, path))?;
+            }
+            let links: usize = edits.values().map(Vec::len).sum();
+            match cli.format {
+                Format::Json => println!(
+                    "{}",
+                    serde_json::json!({ "links": links, "notes": edits.len() })
+                ),
+                _ => println!("Rewrote {links} links in {} notes", edits.len()),
+            }
+        }
+        Command::Publish { out, title } => {
+            let title = title.unwrap_or_else(|| {
+                root.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let report = runtime.block_on(Publisher::new(&index, &parser, title).publish(&out))?;
+            for (note, error) in &report.failed {
+                eprintln!("Failed to publish {}: {error}", relative(note).display());
+            }
+            match cli.format {
+                Format::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "published": report.published,
+                        "failed": report.failed.len(),
+                    })
+                ),
+                _ => println!("Published {} notes to {}", report.published, out.display()),
+            }
+            if !report.failed.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        // Handled before indexing
+        Command::Bindings { .. } | Command::Verify { .. } => {}
+    }
+
+    Ok(())
+}
+
+fn note_path(file: &Path) -> Result<PathBuf> {
+    file.canonicalize()
+        .with_context(|| format!("Note {} not found", file.display()))
+}
+
+fn print_links(format: Format, rows: &[LinkRow]) -> Result<()> {
+    print_rows(format, rows, &["FILE", "LINE", "COLUMN", "TARGET"], |row| {
+        let mut target = row.target.clone();
+        if let Some(block) = &row.block {
+            target = format!("{target}#^{block}");
+        }
+        if let Some(label) = &row.label {
+            target = format!("{target}:{label}");
+        }
+        vec![
+            row.file.display().to_string(),
+            row.line.to_string(),
+            row.column.to_string(),
+            target,
+        ]
+    })
+}
+
+fn print_problems(format: Format, problems: &[Problem]) -> Result<()> {
+    if let Format::Github = format {
+        for problem in problems {
+            println!(
+                "::{} file={},line={},col={}::{}",
+                severity_name(problem.severity),
+                problem.file.display(),
+                problem.line,
+                problem.column,
+                problem.message
+            );
+        }
+        return Ok(());
+    }
+
+    print_rows(
+        format,
+        problems,
+        &["FILE", "LINE", "COLUMN", "SEVERITY", "MESSAGE"],
+        |problem| {
+            vec![
+                problem.file.display().to_string(),
+                problem.line.to_string(),
+                problem.column.to_string(),
+                severity_name(problem.severity).to_string(),
+                problem.message.clone(),
+            ]
+        },
+    )
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Information => "info",
+    }
+}
+
+fn print_notes(format: Format, rows: &[NoteRow]) -> Result<()> {
+    print_rows(format, rows, &["FILE"], |row| {
+        vec![row.file.display().to_string()]
+    })
+}
+
+fn print_report(format: Format, root: &Path, mut report: IndexReport, dry_run: bool) -> Result<()> {
+    let relative = |path: &mut PathBuf| {
+        if let Ok(stripped) = path.strip_prefix(root) {
+            *path = stripped.to_path_buf();
+        }
+    };
+    report.missing_files.iter_mut().for_each(relative);
+    report.stale_files.iter_mut().for_each(relative);
+    report.unindexed_files.iter_mut().for_each(relative);
+    if let Format::Json = format {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if report.is_clean() {
+        println!("The index matches the vault");
+    } else {
+        let notes = |problem, files: &[PathBuf]| {
+            files
+                .iter()
+                .map(|file| IssueRow {
+                    problem,
+                    detail: file.display().to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut rows: Vec<IssueRow> = report
+            .integrity_errors
+            .iter()
+            .map(|error| IssueRow {
+                problem: "damaged",
+                detail: error.clone(),
+            })
+            .collect();
+        rows.extend(notes("missing", &report.missing_files));
+        rows.extend(notes("stale", &report.stale_files));
+        rows.extend(notes("unindexed", &report.unindexed_files));
+        rows.extend(report.orphaned_rows.iter().map(|(table, count)| IssueRow {
+            problem: "orphaned",
+            detail: format!("{count} rows in {table}"),
+        }));
+        print_rows(format, &rows, &["PROBLEM", "DETAIL"], |row| {
+            vec![row.problem.to_string(), row.detail.clone()]
+        })?;
+        if report.rebuilt {
+            println!("Rebuilt the index from the notes");
+        } else if report.repaired {
+            println!("Repaired the index");
+        }
+    }
+    if dry_run && !report.is_clean() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn print_rows<T: Serialize>(
+    format: Format,
+    rows: &[T],
+    headers: &[&str],
+    cells: impl Fn(&T) -> Vec<String>,
+) -> Result<()> {
+    if let Format::Json = format {
+        println!("{}", serde_json::to_string_pretty(rows)?);
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = rows.iter().map(cells).collect();
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let headers = headers.iter().map(|header| header.to_string()).collect();
+    for row in std::iter::once(headers).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+
+    Ok(())
+}
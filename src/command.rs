@@ -2,7 +2,43 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use ts_rs::TS;
 
-use crate::{index::Index, parser::models::Wikilink};
+use crate::{
+    config::{Config, DailyNotesConfig, NoteFilter},
+    daily,
+    events::{Event, EventKind},
+    frontmatter::{self, MetadataEdit},
+    git::CommitInfo,
+    graph::{self, Direction, Graph, algorithms::Cluster},
+    index::{Index, IndexReport, NoteSnapshot, StarredNote, VaultStats, VisitOrder, VisitedNote},
+    lsp::path_to_uri,
+    mentions::{MentionFinder, UnlinkedMention},
+    outline::{self, OutlineSection},
+    parser::{
+        Parser,
+        headings::heading_text,
+        models::{ContentStats, Figure, FigureKind, Wikilink},
+        wikilinks::WikilinkSyntax,
+    },
+    query::{
+        self, QueryRow,
+        moc::{self, MocSource},
+    },
+    refactor::{
+        self,
+        dead_labels::{self, DeadLabel},
+        find_replace::find_replace,
+        merge::{Merge, merge_notes},
+        move_file,
+        rewrite::{redirect_edits, rewrite_links},
+    },
+    related::{self, RelatedNote},
+    storage::Storage,
+    templates::{self, TemplateVars},
+    workspace,
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 
 // Forward Links Commands
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
@@ -17,33 +53,1000 @@ pub struct ForwardLinksResponse {
     pub links: Vec<Wikilink>,
 }
 
+/// `typst-oxide/forwardLinks`
+pub enum ForwardLinks {}
+
+impl lsp_types::request::Request for ForwardLinks {
+    type Params = ForwardLinksRequest;
+    type Result = ForwardLinksResponse;
+    const METHOD: &'static str = "typst-oxide/forwardLinks";
+}
+
 // Backward Links Commands
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export)]
-pub struct BackwardLinksRequest {
-    pub file_path: PathBuf,
+pub struct BackwardLinksRequest {
+    pub file_path: PathBuf,
+    /// Lines of context on each side of a backlink, 2 by default
+    pub context_lines: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BackwardLinksResponse {
+    pub links: Vec<BacklinkInfo>,
+}
+
+/// `typst-oxide/backlinks`
+pub enum BackwardLinks {}
+
+impl lsp_types::request::Request for BackwardLinks {
+    type Params = BackwardLinksRequest;
+    type Result = BackwardLinksResponse;
+    const METHOD: &'static str = "typst-oxide/backlinks";
+}
+
+// Label Backlinks Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LabelBacklinksRequest {
+    pub file_path: PathBuf,
+    pub label: String,
+    /// Lines of context on each side of a backlink, 2 by default
+    pub context_lines: Option<usize>,
+}
+
+/// `typst-oxide/labelBacklinks`
+pub enum LabelBacklinks {}
+
+impl lsp_types::request::Request for LabelBacklinks {
+    type Params = LabelBacklinksRequest;
+    type Result = BackwardLinksResponse;
+    const METHOD: &'static str = "typst-oxide/labelBacklinks";
+}
+
+// Helper struct for backward links that includes source file information
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BacklinkInfo {
+    pub source_file: PathBuf,
+    pub wikilink: Wikilink,
+    /// Text of the nearest heading above the link
+    pub section: Option<String>,
+    /// Source lines around the link, starting at line `context_start` (1-based)
+    pub context: Vec<String>,
+    pub context_start: usize,
+}
+
+// Local Graph Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocalGraphRequest {
+    pub file: PathBuf,
+    /// Maximum number of hops from `file`, 1 by default
+    pub depth: Option<usize>,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Keep archived notes other than `file` in the graph
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LocalGraphResponse {
+    pub graph: Graph,
+}
+
+/// `typst-oxide/localGraph`
+pub enum LocalGraph {}
+
+impl lsp_types::request::Request for LocalGraph {
+    type Params = LocalGraphRequest;
+    type Result = LocalGraphResponse;
+    const METHOD: &'static str = "typst-oxide/localGraph";
+}
+
+// Path Between Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PathBetweenRequest {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    #[serde(default)]
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PathBetweenResponse {
+    /// Notes from `from` to `to` inclusive, `None` if they aren't connected
+    pub path: Option<Vec<PathBuf>>,
+}
+
+/// `typst-oxide/pathBetween`
+pub enum PathBetween {}
+
+impl lsp_types::request::Request for PathBetween {
+    type Params = PathBetweenRequest;
+    type Result = PathBetweenResponse;
+    const METHOD: &'static str = "typst-oxide/pathBetween";
+}
+
+// Clusters Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ClustersRequest {
+    /// Omit clusters with fewer notes, 1 by default
+    pub min_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ClustersResponse {
+    pub clusters: Vec<Cluster>,
+}
+
+/// `typst-oxide/clusters`
+pub enum Clusters {}
+
+impl lsp_types::request::Request for Clusters {
+    type Params = ClustersRequest;
+    type Result = ClustersResponse;
+    const METHOD: &'static str = "typst-oxide/clusters";
+}
+
+// Related Notes Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RelatedRequest {
+    pub file: PathBuf,
+    /// Maximum number of notes returned, 10 by default
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RelatedResponse {
+    pub notes: Vec<RelatedNote>,
+}
+
+/// `typst-oxide/related`
+pub enum Related {}
+
+impl lsp_types::request::Request for Related {
+    type Params = RelatedRequest;
+    type Result = RelatedResponse;
+    const METHOD: &'static str = "typst-oxide/related";
+}
+
+// Query Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct QueryRequest {
+    /// e.g. `tag:project AND modified > 2024-01-01 SORT title LIMIT 10`
+    pub query: String,
+    /// Fields returned for each note
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct QueryResponse {
+    pub notes: Vec<QueryRow>,
+}
+
+/// `typst-oxide/query`
+pub enum Query {}
+
+impl lsp_types::request::Request for Query {
+    type Params = QueryRequest;
+    type Result = QueryResponse;
+    const METHOD: &'static str = "typst-oxide/query";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MaterializeQueriesRequest {
+    /// Only this note, or every note with a `#pkm-query` marker
+    pub file_path: Option<PathBuf>,
+    /// Ask the client to confirm the changes before applying them
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// `typst-oxide/materializeQueries`: returns a `WorkspaceEdit` rewriting the notes whose
+/// generated sections are out of date
+pub enum MaterializeQueries {}
+
+impl lsp_types::request::Request for MaterializeQueries {
+    type Params = MaterializeQueriesRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/materializeQueries";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct GenerateMocRequest {
+    /// List the notes with this tag, grouped by subtag
+    pub tag: Option<String>,
+    /// Or the notes in this vault-relative folder, grouped by subfolder
+    pub folder: Option<PathBuf>,
+    /// The map note; `<tag>.typ` at the vault root or `<folder>/index.typ` by default
+    pub file_path: Option<PathBuf>,
+}
+
+/// `typst-oxide/generateMoc`: returns a `WorkspaceEdit` that creates or updates the map note,
+/// empty if it is up to date
+pub enum GenerateMoc {}
+
+impl lsp_types::request::Request for GenerateMoc {
+    type Params = GenerateMocRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/generateMoc";
+}
+
+// Metadata Editing Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UpdateMetadataRequest {
+    pub file_path: PathBuf,
+    /// Applied in order
+    pub edits: Vec<MetadataEdit>,
+}
+
+/// `typst-oxide/updateMetadata`: returns the `WorkspaceEdit` for the client to apply
+pub enum UpdateMetadata {}
+
+impl lsp_types::request::Request for UpdateMetadata {
+    type Params = UpdateMetadataRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/updateMetadata";
+}
+
+// Refactoring Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MoveFileRequest {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// `typst-oxide/moveFile`: returns a `WorkspaceEdit` that fixes links, then renames the file or
+/// folder
+pub enum MoveFile {}
+
+impl lsp_types::request::Request for MoveFile {
+    type Params = MoveFileRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/moveFile";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ArchiveNoteRequest {
+    pub file: PathBuf,
+}
+
+/// `typst-oxide/archiveNote`: returns a `WorkspaceEdit` that fixes links, then moves the note
+/// into the first archive folder, keeping its path below the vault root
+pub enum ArchiveNote {}
+
+impl lsp_types::request::Request for ArchiveNote {
+    type Params = ArchiveNoteRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/archiveNote";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MergeNotesRequest {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// Leave a note linking to `target` instead of deleting `source`
+    #[serde(default)]
+    pub stub: bool,
+    /// Ask the client to confirm the changes before applying them
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// `typst-oxide/mergeNotes`: returns a `WorkspaceEdit` that appends `source` to `target`,
+/// redirects its links, then deletes or stubs it
+pub enum MergeNotes {}
+
+impl lsp_types::request::Request for MergeNotes {
+    type Params = MergeNotesRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/mergeNotes";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RewriteLinksRequest {
+    pub from_target: String,
+    pub to_target: String,
+    /// Ask the client to confirm the changes before applying them
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// `typst-oxide/rewriteLinks`: returns a `WorkspaceEdit` that points every wikilink to
+/// `from_target` at `to_target`
+pub enum RewriteLinks {}
+
+impl lsp_types::request::Request for RewriteLinks {
+    type Params = RewriteLinksRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/rewriteLinks";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FixRedirectsRequest {
+    /// Ask the client to confirm the changes before applying them
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// `typst-oxide/fixRedirects`: returns a `WorkspaceEdit` that points every link to a note
+/// with a `redirect` field at the note it redirects to
+pub enum FixRedirects {}
+
+impl lsp_types::request::Request for FixRedirects {
+    type Params = FixRedirectsRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/fixRedirects";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FindReplaceRequest {
+    pub pattern: String,
+    /// With `regex`, may refer to groups as `$1` or `${name}`
+    pub replacement: String,
+    /// Whether `pattern` is a regular expression rather than literal text
+    #[serde(default)]
+    pub regex: bool,
+    /// Only replace in this note or the notes under this folder
+    #[serde(default)]
+    pub scope: Option<PathBuf>,
+    /// Ask the client to confirm the changes before applying them
+    #[serde(default)]
+    pub preview: bool,
+}
+
+/// `typst-oxide/findReplace`: returns a `WorkspaceEdit` replacing `pattern` in the prose of
+/// every note, leaving raw text and comments alone
+pub enum FindReplace {}
+
+impl lsp_types::request::Request for FindReplace {
+    type Params = FindReplaceRequest;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "typst-oxide/findReplace";
+}
+
+// Event Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SubscribeRequest {
+    /// Event kinds to receive, every kind if empty
+    #[serde(default)]
+    pub kinds: Vec<EventKind>,
+}
+
+/// `typst-oxide/subscribe`: replaces any earlier subscription from the same client
+pub enum Subscribe {}
+
+impl lsp_types::request::Request for Subscribe {
+    type Params = SubscribeRequest;
+    type Result = ();
+    const METHOD: &'static str = "typst-oxide/subscribe";
+}
+
+/// `typst-oxide/event`, sent to subscribed clients
+pub enum EventNotification {}
+
+impl lsp_types::notification::Notification for EventNotification {
+    type Params = Event;
+    const METHOD: &'static str = "typst-oxide/event";
+}
+
+// Semantic Search Commands
+#[cfg(feature = "embeddings")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SemanticSearchRequest {
+    pub query: String,
+    /// Maximum number of sections returned, 10 by default
+    pub limit: Option<usize>,
+}
+
+#[cfg(feature = "embeddings")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SemanticSearchResponse {
+    pub matches: Vec<crate::embeddings::SectionMatch>,
+}
+
+/// `typst-oxide/semanticSearch`
+#[cfg(feature = "embeddings")]
+pub enum SemanticSearch {}
+
+#[cfg(feature = "embeddings")]
+impl lsp_types::request::Request for SemanticSearch {
+    type Params = SemanticSearchRequest;
+    type Result = SemanticSearchResponse;
+    const METHOD: &'static str = "typst-oxide/semanticSearch";
+}
+
+// Export Commands
+#[cfg(feature = "compile")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ExportRequest {
+    pub file: PathBuf,
+    pub format: crate::compile::ExportFormat,
+    /// Compile the note bundled with what it embeds, and optionally the notes it links to
+    #[serde(default)]
+    pub context: Option<crate::export::bundle::BundleOptions>,
+}
+
+#[cfg(feature = "compile")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ExportResponse {
+    /// The file written
+    pub path: PathBuf,
+}
+
+/// `typst-oxide/export`
+#[cfg(feature = "compile")]
+pub enum Export {}
+
+#[cfg(feature = "compile")]
+impl lsp_types::request::Request for Export {
+    type Params = ExportRequest;
+    type Result = ExportResponse;
+    const METHOD: &'static str = "typst-oxide/export";
+}
+
+// Search Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SearchRequest {
+    pub query: String,
+    /// Also match archived notes
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SearchResponse {
+    pub files: Vec<PathBuf>,
+}
+
+/// `typst-oxide/search`: notes whose path, title, aliases or tags contain the query
+pub enum Search {}
+
+impl lsp_types::request::Request for Search {
+    type Params = SearchRequest;
+    type Result = SearchResponse;
+    const METHOD: &'static str = "typst-oxide/search";
+}
+
+// Tags Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TagsResponse {
+    pub tags: Vec<TagCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// `typst-oxide/tags`: every tag with the number of notes using it, most used first
+pub enum Tags {}
+
+impl lsp_types::request::Request for Tags {
+    type Params = ();
+    type Result = TagsResponse;
+    const METHOD: &'static str = "typst-oxide/tags";
+}
+
+// Graph Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct GraphRequest {
+    /// Keep archived notes in the graph
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+/// `typst-oxide/graph`: the whole resolved note graph
+pub enum VaultGraph {}
+
+impl lsp_types::request::Request for VaultGraph {
+    type Params = GraphRequest;
+    type Result = Graph;
+    const METHOD: &'static str = "typst-oxide/graph";
+}
+
+// Stats Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StatsRequest {
+    /// Length of the most-linked and recently modified lists, 10 by default
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StatsResponse {
+    pub stats: VaultStats,
+    /// Notes the language server has yet to reindex; always 0 elsewhere
+    #[serde(default)]
+    pub queue_depth: usize,
+}
+
+/// `typst-oxide/stats`
+pub enum Stats {}
+
+impl lsp_types::request::Request for Stats {
+    type Params = StatsRequest;
+    type Result = StatsResponse;
+    const METHOD: &'static str = "typst-oxide/stats";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VerifyIndexRequest {
+    /// Report the problems without repairing them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VerifyIndexResponse {
+    pub report: IndexReport,
+}
+
+/// `typst-oxide/verifyIndex`: cross-check the index against the notes on disk and repair it
+pub enum VerifyIndex {}
+
+impl lsp_types::request::Request for VerifyIndex {
+    type Params = VerifyIndexRequest;
+    type Result = VerifyIndexResponse;
+    const METHOD: &'static str = "typst-oxide/verifyIndex";
+}
+
+// Attachment Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UnusedAttachmentsResponse {
+    pub files: Vec<PathBuf>,
+}
+
+/// `typst-oxide/unusedAttachments`
+pub enum UnusedAttachments {}
+
+impl lsp_types::request::Request for UnusedAttachments {
+    type Params = ();
+    type Result = UnusedAttachmentsResponse;
+    const METHOD: &'static str = "typst-oxide/unusedAttachments";
+}
+
+// Daily Note Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DailyNoteRequest {
+    /// `YYYY-MM-DD`, today by default
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DailyNoteResponse {
+    pub uri: String,
+    pub path: PathBuf,
+    /// Whether the note was created by this request
+    pub created: bool,
+}
+
+/// `typst-oxide/dailyNote`
+pub enum DailyNote {}
+
+impl lsp_types::request::Request for DailyNote {
+    type Params = DailyNoteRequest;
+    type Result = DailyNoteResponse;
+    const METHOD: &'static str = "typst-oxide/dailyNote";
+}
+
+// Periodic Note Commands
+/// The span of notes a `typst-oxide/periodicNote` request resolves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum Period {
+    /// One daily note
+    #[default]
+    Day,
+    /// The daily notes of a Monday-to-Sunday week
+    Week,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PeriodicNoteRequest {
+    /// Days, or weeks for `week`, from the starting date: -1 for yesterday or last week
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default)]
+    pub period: Period,
+    /// Daily note to start from, its date parsed from its name
+    pub from: Option<PathBuf>,
+    /// `YYYY-MM-DD` to start from without `from`, today by default
+    pub date: Option<String>,
+    /// Count only daily notes that exist, skipping days without one (`day` only)
+    #[serde(default)]
+    pub existing_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PeriodicNoteResponse {
+    /// The day's note, or each day of the week; empty if no note is that far away
+    pub notes: Vec<DatedNote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DatedNote {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub path: PathBuf,
+    pub uri: String,
+    pub exists: bool,
+}
+
+/// `typst-oxide/periodicNote`
+pub enum PeriodicNote {}
+
+impl lsp_types::request::Request for PeriodicNote {
+    type Params = PeriodicNoteRequest;
+    type Result = PeriodicNoteResponse;
+    const METHOD: &'static str = "typst-oxide/periodicNote";
+}
+
+// Calendar Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CalendarRequest {
+    /// First day (`YYYY-MM-DD`), a year before `to` by default
+    pub from: Option<String>,
+    /// Last day (`YYYY-MM-DD`), today by default
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CalendarResponse {
+    /// Days in the range with a daily note or a modified note, oldest first
+    pub days: Vec<CalendarDay>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CalendarDay {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub daily_note: Option<PathBuf>,
+    /// Number of notes last modified that day
+    pub modified_count: usize,
+    pub modified: Vec<PathBuf>,
+}
+
+/// `typst-oxide/calendar`
+pub enum Calendar {}
+
+impl lsp_types::request::Request for Calendar {
+    type Params = CalendarRequest;
+    type Result = CalendarResponse;
+    const METHOD: &'static str = "typst-oxide/calendar";
+}
+
+// New Note Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewNoteRequest {
+    pub title: String,
+    /// Template name from `template_folder`, `default_template` if omitted
+    pub template: Option<String>,
+    /// Folder relative to the vault root, the root if omitted
+    pub folder: Option<PathBuf>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NewNoteResponse {
+    pub uri: String,
+    pub path: PathBuf,
+}
+
+/// `typst-oxide/newNote`
+pub enum NewNote {}
+
+impl lsp_types::request::Request for NewNote {
+    type Params = NewNoteRequest;
+    type Result = NewNoteResponse;
+    const METHOD: &'static str = "typst-oxide/newNote";
+}
+
+// Unlinked Mentions Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UnlinkedMentionsRequest {
+    /// Only scan this file, every note if omitted
+    pub file_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export)]
-pub struct BackwardLinksResponse {
-    pub links: Vec<BacklinkInfo>,
+pub struct UnlinkedMentionsResponse {
+    pub mentions: Vec<UnlinkedMention>,
+}
+
+/// `typst-oxide/unlinkedMentions`
+pub enum UnlinkedMentions {}
+
+impl lsp_types::request::Request for UnlinkedMentions {
+    type Params = UnlinkedMentionsRequest;
+    type Result = UnlinkedMentionsResponse;
+    const METHOD: &'static str = "typst-oxide/unlinkedMentions";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeadLabelsRequest {
+    /// Only check this file, every note if omitted
+    pub file_path: Option<PathBuf>,
 }
 
-// Helper struct for backward links that includes source file information
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export)]
-pub struct BacklinkInfo {
-    pub source_file: PathBuf,
-    pub wikilink: Wikilink,
+pub struct DeadLabelsResponse {
+    pub labels: Vec<DeadLabel>,
+}
+
+/// `typst-oxide/deadLabels`
+pub enum DeadLabels {}
+
+impl lsp_types::request::Request for DeadLabels {
+    type Params = DeadLabelsRequest;
+    type Result = DeadLabelsResponse;
+    const METHOD: &'static str = "typst-oxide/deadLabels";
+}
+
+// Outline Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OutlineRequest {
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OutlineResponse {
+    pub sections: Vec<OutlineSection>,
+}
+
+/// `typst-oxide/outline`
+pub enum Outline {}
+
+impl lsp_types::request::Request for Outline {
+    type Params = OutlineRequest;
+    type Result = OutlineResponse;
+    const METHOD: &'static str = "typst-oxide/outline";
+}
+
+// Recent Notes Commands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RecentNotesRequest {
+    #[serde(default)]
+    pub order: VisitOrder,
+    /// Maximum number of notes, 20 by default
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RecentNotesResponse {
+    pub notes: Vec<VisitedNote>,
+}
+
+/// `typst-oxide/recentNotes`
+pub enum RecentNotes {}
+
+impl lsp_types::request::Request for RecentNotes {
+    type Params = RecentNotesRequest;
+    type Result = RecentNotesResponse;
+    const METHOD: &'static str = "typst-oxide/recentNotes";
+}
+
+// Starred Notes Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StarNoteRequest {
+    pub file: PathBuf,
+}
+
+/// `typst-oxide/starNote`
+pub enum StarNote {}
+
+impl lsp_types::request::Request for StarNote {
+    type Params = StarNoteRequest;
+    type Result = ();
+    const METHOD: &'static str = "typst-oxide/starNote";
+}
+
+/// `typst-oxide/unstarNote`
+pub enum UnstarNote {}
+
+impl lsp_types::request::Request for UnstarNote {
+    type Params = StarNoteRequest;
+    type Result = ();
+    const METHOD: &'static str = "typst-oxide/unstarNote";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StarredNotesResponse {
+    pub notes: Vec<StarredNote>,
+}
+
+/// `typst-oxide/starredNotes`
+pub enum StarredNotes {}
+
+impl lsp_types::request::Request for StarredNotes {
+    type Params = ();
+    type Result = StarredNotesResponse;
+    const METHOD: &'static str = "typst-oxide/starredNotes";
+}
+
+// File Info Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileInfoRequest {
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FileInfoResponse {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub stats: ContentStats,
+    /// Only known when `git_history` is on and git tracks the note
+    pub last_commit: Option<CommitInfo>,
+}
+
+/// `typst-oxide/fileInfo`
+pub enum FileInfo {}
+
+impl lsp_types::request::Request for FileInfo {
+    type Params = FileInfoRequest;
+    type Result = FileInfoResponse;
+    const METHOD: &'static str = "typst-oxide/fileInfo";
+}
+
+// History Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct HistoryRequest {
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct HistoryResponse {
+    /// Oldest first, at most one per day
+    pub snapshots: Vec<NoteSnapshot>,
+}
+
+/// `typst-oxide/history`
+pub enum History {}
+
+impl lsp_types::request::Request for History {
+    type Params = HistoryRequest;
+    type Result = HistoryResponse;
+    const METHOD: &'static str = "typst-oxide/history";
+}
+
+// Figure Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FiguresRequest {
+    /// Matched case-insensitively against each figure's caption and label
+    pub query: Option<String>,
+    pub kind: Option<FigureKind>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FigureEntry {
+    pub path: PathBuf,
+    pub figure: Figure,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FiguresResponse {
+    pub figures: Vec<FigureEntry>,
+}
+
+/// `typst-oxide/figures`
+pub enum Figures {}
+
+impl lsp_types::request::Request for Figures {
+    type Params = FiguresRequest;
+    type Result = FiguresResponse;
+    const METHOD: &'static str = "typst-oxide/figures";
+}
+
+// Package Commands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PackagesResponse {
+    /// Ordered by namespace and name
+    pub packages: Vec<PackageUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PackageUsage {
+    pub namespace: String,
+    pub name: String,
+    /// Oldest first
+    pub versions: Vec<PackageVersion>,
+    /// The newest version any note imports
+    pub latest: String,
+    /// Whether notes import more than one version
+    pub inconsistent: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PackageVersion {
+    pub version: String,
+    /// The notes importing this version, ordered by path
+    pub notes: Vec<PathBuf>,
+}
+
+/// `typst-oxide/packages`
+pub enum Packages {}
+
+impl lsp_types::request::Request for Packages {
+    type Params = ();
+    type Result = PackagesResponse;
+    const METHOD: &'static str = "typst-oxide/packages";
 }
 
 // Command handlers that wrap index module functionality
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 
 /// Process forward links request by wrapping Index::get_forward_links
 pub fn handle_forward_links(
-    index: &Index,
+    index: &dyn Storage,
     request: ForwardLinksRequest,
 ) -> Result<ForwardLinksResponse> {
     let links = index.get_forward_links(&request.file_path)?;
@@ -52,16 +1055,1024 @@ pub fn handle_forward_links(
 
 /// Process backward links request by wrapping Index::get_backward_links
 pub fn handle_backward_links(
-    index: &Index,
+    index: &dyn Storage,
     request: BackwardLinksRequest,
 ) -> Result<BackwardLinksResponse> {
     let backlinks = index.get_backward_links(&request.file_path)?;
-    let links = backlinks
-        .into_iter()
-        .map(|(source_file, wikilink)| BacklinkInfo {
+    Ok(BackwardLinksResponse {
+        links: with_context(backlinks, request.context_lines),
+    })
+}
+
+/// Process label backlinks request by wrapping Index::get_backlinks_to_label
+pub fn handle_label_backlinks(
+    index: &Index,
+    request: LabelBacklinksRequest,
+) -> Result<BackwardLinksResponse> {
+    let backlinks = index.get_backlinks_to_label(&request.file_path, &request.label)?;
+    Ok(BackwardLinksResponse {
+        links: with_context(backlinks, request.context_lines),
+    })
+}
+
+fn with_context(
+    backlinks: Vec<(PathBuf, Wikilink)>,
+    context_lines: Option<usize>,
+) -> Vec<BacklinkInfo> {
+    let context_lines = context_lines.unwrap_or(2);
+    let mut sources: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut links = Vec::new();
+    for (source_file, wikilink) in backlinks {
+        if !sources.contains_key(&source_file) {
+            let content = std::fs::read_to_string(&source_file).unwrap_or_default();
+            let lines = content.lines().map(str::to_string).collect();
+            sources.insert(source_file.clone(), lines);
+        }
+        let lines = &sources[&source_file];
+
+        let line_idx = wikilink.line.saturating_sub(1);
+        let section = lines[..line_idx.min(lines.len())]
+            .iter()
+            .rev()
+            .find_map(|line| heading_text(line));
+        let start = line_idx.saturating_sub(context_lines).min(lines.len());
+        let end = (line_idx + context_lines + 1).min(lines.len());
+
+        links.push(BacklinkInfo {
             source_file,
             wikilink,
+            section,
+            context: lines[start..end].to_vec(),
+            context_start: start + 1,
+        });
+    }
+    links
+}
+
+/// Process search request by wrapping Index::search
+pub fn handle_search(index: &dyn Storage, request: SearchRequest) -> Result<SearchResponse> {
+    let mut files = index.search(&request.query)?;
+    if !request.include_archived {
+        let archived = index.archived_notes()?;
+        files.retain(|file| !archived.contains(file));
+    }
+    Ok(SearchResponse { files })
+}
+
+/// Every tag with the number of notes using it, most used first
+pub fn handle_tags(index: &dyn Storage) -> Result<TagsResponse> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (_, key, value) in index.get_all_metadata()? {
+        if key == "tags" {
+            *counts.entry(value).or_default() += 1;
+        }
+    }
+    let mut tags: Vec<TagCount> = counts
+        .into_iter()
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect();
+    tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    Ok(TagsResponse { tags })
+}
+
+/// Process stats request by wrapping Index::stats
+pub fn handle_stats(index: &Index, request: StatsRequest) -> Result<StatsResponse> {
+    let stats = index.stats(request.limit.unwrap_or(10))?;
+    Ok(StatsResponse {
+        stats,
+        queue_depth: 0,
+    })
+}
+
+/// Process local graph request by wrapping graph::local_graph
+pub fn handle_unused_attachments(
+    index: &Index,
+    filter: &NoteFilter,
+) -> Result<UnusedAttachmentsResponse> {
+    let files = workspace::unused_attachments(index, filter)?;
+    Ok(UnusedAttachmentsResponse { files })
+}
+
+/// Process graph request by wrapping graph::build
+pub fn handle_graph(index: &dyn Storage, request: GraphRequest) -> Result<Graph> {
+    graph::build(index, request.include_archived)
+}
+
+pub fn handle_local_graph(
+    index: &dyn Storage,
+    request: LocalGraphRequest,
+) -> Result<LocalGraphResponse> {
+    let graph = graph::local_graph(
+        index,
+        &request.file,
+        request.depth.unwrap_or(1),
+        request.direction,
+        request.include_archived,
+    )?;
+    Ok(LocalGraphResponse { graph })
+}
+
+/// Process path between request by wrapping graph::algorithms::shortest_path
+pub fn handle_path_between(
+    index: &dyn Storage,
+    request: PathBetweenRequest,
+) -> Result<PathBetweenResponse> {
+    let path =
+        graph::algorithms::shortest_path(index, &request.from, &request.to, request.direction)?;
+    Ok(PathBetweenResponse { path })
+}
+
+/// Process clusters request by wrapping graph::algorithms::clusters
+pub fn handle_clusters(index: &dyn Storage, request: ClustersRequest) -> Result<ClustersResponse> {
+    let min_size = request.min_size.unwrap_or(1);
+    let clusters = graph::algorithms::clusters(index)?
+        .into_iter()
+        .filter(|cluster| cluster.notes.len() >= min_size)
+        .collect();
+    Ok(ClustersResponse { clusters })
+}
+
+/// Process related request by wrapping related::related
+pub fn handle_related(index: &dyn Storage, request: RelatedRequest) -> Result<RelatedResponse> {
+    let notes = related::related(index, &request.file, request.limit.unwrap_or(10))?;
+    Ok(RelatedResponse { notes })
+}
+
+pub fn handle_query(index: &Index, request: QueryRequest) -> Result<QueryResponse> {
+    let query = query::parse(&request.query)?;
+    let notes = query::run(index, &query, &request.fields)?;
+    Ok(QueryResponse { notes })
+}
+
+/// Regenerate saved query sections, replacing the content of each changed note
+pub fn handle_materialize_queries(
+    index: &Index,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+    request: MaterializeQueriesRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let paths = match request.file_path {
+        Some(path) => vec![path],
+        None => index.get_all_files()?,
+    };
+
+    let mut edits = refactor::FileEdits::new();
+    for path in paths {
+        let Some(content) = read(&path) else {
+            continue;
+        };
+        if !content.contains(query::materialize::MARKER) {
+            continue;
+        }
+        let Some(materialized) = query::materialize::materialize(index, syntax, &path, &content)
+            .with_context(|| format!("Failed to materialize {}", path.display()))?
+        else {
+            continue;
+        };
+        let whole = lsp_types::Range::new(
+            lsp_types::Position::new(0, 0),
+            refactor::end_position(&content),
+        );
+        edits.insert(path, vec![lsp_types::TextEdit::new(whole, materialized)]);
+    }
+    let label = format!("Update the query sections of {} notes", edits.len());
+    confirmable_edit(edits, request.preview.then_some(label))
+}
+
+/// Create or regenerate a map of content note for a tag or folder
+pub fn handle_generate_moc(
+    index: &Index,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+    request: GenerateMocRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let source = match (&request.tag, &request.folder) {
+        (Some(tag), None) => MocSource::Tag(tag),
+        (None, Some(folder)) => MocSource::Folder(folder),
+        _ => bail!("Give either a tag or a folder"),
+    };
+    let path = moc::moc_path(index.root(), source, request.file_path.clone())?;
+    let existing = read(&path);
+    let content = moc::generate(index, syntax, source, &path, existing.as_deref())?;
+    match existing {
+        None => refactor::create_file_edit(&path, content, refactor::FileEdits::new()),
+        Some(text) if text == content => Ok(lsp_types::WorkspaceEdit::default()),
+        Some(text) => {
+            let whole = lsp_types::Range::new(
+                lsp_types::Position::new(0, 0),
+                refactor::end_position(&text),
+            );
+            let edits = BTreeMap::from([(path, vec![lsp_types::TextEdit::new(whole, content)])]);
+            refactor::workspace_edit(edits, &[])
+        }
+    }
+}
+
+/// Edits to the metadata block of `text`, the current content of `request.file_path`
+pub fn handle_update_metadata(
+    text: &str,
+    request: UpdateMetadataRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let edits = frontmatter::metadata_edits(text, &request.edits)?;
+    let uri = path_to_uri(&request.file_path)?;
+    Ok(lsp_types::WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, edits)])),
+        ..Default::default()
+    })
+}
+
+/// Fix links for moving a note, attachment or folder, then rename it
+pub fn handle_move_file(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+    request: MoveFileRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    if !request.from.exists() {
+        bail!("{} does not exist", request.from.display());
+    }
+    if request.to.exists() {
+        bail!("{} already exists", request.to.display());
+    }
+    let moves = [(request.from, request.to)];
+    let edits = move_file::move_edits(index, parser, syntax, &moves, read)?;
+    refactor::workspace_edit(edits, &moves)
+}
+
+/// Move a note under the first of `archive_folders`, e.g. `notes/a.typ` to
+/// `archive/notes/a.typ`
+pub fn handle_archive_note(
+    index: &Index,
+    parser: &Parser,
+    config: &Config,
+    read: impl Fn(&Path) -> Option<String>,
+    request: ArchiveNoteRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let Some(folder) = config.archive_folders.first() else {
+        bail!("No archive folder is configured");
+    };
+    let relative = request
+        .file
+        .strip_prefix(index.root())
+        .with_context(|| format!("{} is not in the vault", request.file.display()))?;
+    if config
+        .archive_folders
+        .iter()
+        .any(|folder| relative.starts_with(folder))
+    {
+        bail!("{} is already archived", request.file.display());
+    }
+    let to = index.root().join(folder).join(relative);
+    handle_move_file(
+        index,
+        parser,
+        &config.wikilink_syntax,
+        read,
+        MoveFileRequest {
+            from: request.file,
+            to,
+        },
+    )
+}
+
+pub fn handle_merge_notes(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+    request: MergeNotesRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    for path in [&request.source, &request.target] {
+        if !path.exists() {
+            bail!("{} does not exist", path.display());
+        }
+    }
+    if request.source == request.target {
+        bail!("Cannot merge a note into itself");
+    }
+    let merge = Merge {
+        source: &request.source,
+        target: &request.target,
+        stub: request.stub,
+    };
+    let mut edit = merge_notes(index, parser, syntax, merge, read)?;
+    if request.preview {
+        let name = |path: &Path| {
+            path.strip_prefix(index.root())
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        };
+        refactor::needs_confirmation(
+            &mut edit,
+            format!(
+                "Merge {} into {}",
+                name(&request.source),
+                name(&request.target)
+            ),
+        );
+    }
+    Ok(edit)
+}
+
+/// Retarget every wikilink to `from_target` across the vault
+pub fn handle_rewrite_links(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+    request: RewriteLinksRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let edits = rewrite_links(
+        index,
+        parser,
+        syntax,
+        &request.from_target,
+        &request.to_target,
+        read,
+    )?;
+    let label = format!(
+        "Rewrite links to {} as {}",
+        request.from_target, request.to_target
+    );
+    confirmable_edit(edits, request.preview.then_some(label))
+}
+
+/// Point every link to a note with `redirect` metadata at the note its redirects end at
+pub fn handle_fix_redirects(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+    request: FixRedirectsRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let edits = redirect_edits(index, parser, syntax, read)?;
+    let label = "Update links to redirected notes".to_string();
+    confirmable_edit(edits, request.preview.then_some(label))
+}
+
+/// Replace text across the vault or `scope`
+pub fn handle_find_replace(
+    index: &Index,
+    parser: &Parser,
+    read: impl Fn(&Path) -> Option<String>,
+    request: FindReplaceRequest,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let edits = find_replace(
+        index,
+        parser,
+        &request.pattern,
+        &request.replacement,
+        request.regex,
+        request.scope.as_deref(),
+        read,
+    )?;
+    let count: usize = edits.values().map(Vec::len).sum();
+    let label = format!(
+        "Replace {count} occurrences of {} with {}",
+        request.pattern, request.replacement
+    );
+    confirmable_edit(edits, request.preview.then_some(label))
+}
+
+// Text edits across the vault, marked as needing confirmation under `preview` if given
+fn confirmable_edit(
+    edits: refactor::FileEdits,
+    preview: Option<String>,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let Some(label) = preview else {
+        return refactor::workspace_edit(edits, &[]);
+    };
+    // Confirmation annotations need `documentChanges`
+    let mut edit = lsp_types::WorkspaceEdit {
+        document_changes: Some(lsp_types::DocumentChanges::Operations(
+            refactor::text_document_edits(edits)?,
+        )),
+        ..Default::default()
+    };
+    refactor::needs_confirmation(&mut edit, label);
+    Ok(edit)
+}
+
+/// Embed changed notes, then find the sections nearest to the query. Takes the handle rather
+/// than a locked index, since embedding may wait on a model
+#[cfg(feature = "embeddings")]
+pub fn handle_semantic_search(
+    index: &crate::index::IndexHandle,
+    config: &crate::config::EmbeddingsConfig,
+    request: SemanticSearchRequest,
+) -> Result<SemanticSearchResponse> {
+    let embedder = crate::embeddings::embedder(config)?;
+    crate::embeddings::update(index, embedder.as_ref())?;
+    let matches = crate::embeddings::search(
+        index,
+        embedder.as_ref(),
+        &request.query,
+        request.limit.unwrap_or(10),
+    )?;
+    Ok(SemanticSearchResponse { matches })
+}
+
+/// Compile a note, bundled with its context if asked, and write it next to the note or to the
+/// same place under `build_folder`
+#[cfg(feature = "compile")]
+pub fn handle_export(
+    index: &Index,
+    parser: &Parser,
+    build_folder: Option<&Path>,
+    request: ExportRequest,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<ExportResponse> {
+    let root = index.root();
+    let relative = request
+        .file
+        .strip_prefix(root)
+        .with_context(|| format!("{} is outside the vault", request.file.display()))?;
+    let extension = request.format.extension();
+    // A bundle doesn't replace the note's own export
+    let extension = match request.context {
+        Some(_) => format!("bundle.{extension}"),
+        None => extension.to_string(),
+    };
+    let path = build_folder
+        .map_or_else(|| root.to_path_buf(), |folder| root.join(folder))
+        .join(relative)
+        .with_extension(extension);
+    let source = match &request.context {
+        Some(options) => Some(crate::export::bundle::bundle(
+            index,
+            parser,
+            &request.file,
+            options,
+            read,
+        )?),
+        None => None,
+    };
+    let output = crate::compile::export(root, &request.file, source, request.format)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, output)?;
+    Ok(ExportResponse { path })
+}
+
+/// Create or find the daily note for the requested date
+pub fn handle_daily_note(
+    root: &Path,
+    config: &DailyNotesConfig,
+    today: NaiveDate,
+    request: DailyNoteRequest,
+) -> Result<DailyNoteResponse> {
+    let date = match &request.date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+        None => today,
+    };
+    let (path, created) = daily::ensure_daily_note(root, config, date)?;
+    Ok(DailyNoteResponse {
+        uri: path_to_uri(&path)?.as_str().to_string(),
+        path,
+        created,
+    })
+}
+
+/// Find the daily note (or week of daily notes) `offset` periods from a starting date,
+/// without creating any
+pub fn handle_periodic_note(
+    root: &Path,
+    config: &DailyNotesConfig,
+    today: NaiveDate,
+    request: PeriodicNoteRequest,
+) -> Result<PeriodicNoteResponse> {
+    let start = match (&request.from, &request.date) {
+        (Some(from), _) => daily::daily_note_date(root, config, from)
+            .with_context(|| format!("{} is not a daily note", from.display()))?,
+        (None, Some(date)) => NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+        (None, None) => today,
+    };
+    let dates: Vec<NaiveDate> = match request.period {
+        Period::Day => daily::step(root, config, start, request.offset, request.existing_only)
+            .into_iter()
+            .collect(),
+        Period::Week => {
+            let monday = start.week(chrono::Weekday::Mon).first_day();
+            match chrono::TimeDelta::try_weeks(request.offset)
+                .and_then(|weeks| monday.checked_add_signed(weeks))
+            {
+                Some(monday) => monday.iter_days().take(7).collect(),
+                None => Vec::new(),
+            }
+        }
+    };
+
+    let mut notes = Vec::new();
+    for date in dates {
+        let path = daily::daily_note_path(root, config, date)?;
+        notes.push(DatedNote {
+            date: date.to_string(),
+            uri: path_to_uri(&path)?.as_str().to_string(),
+            exists: path.exists(),
+            path,
+        });
+    }
+    Ok(PeriodicNoteResponse { notes })
+}
+
+/// Daily notes and modified notes per day between two dates, bucketing modification times
+/// into days in `tz`
+pub fn handle_calendar<Tz: TimeZone>(
+    index: &Index,
+    config: &DailyNotesConfig,
+    tz: &Tz,
+    today: NaiveDate,
+    request: CalendarRequest,
+) -> Result<CalendarResponse> {
+    let parse = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d");
+    let to = request
+        .to
+        .as_deref()
+        .map(parse)
+        .transpose()?
+        .unwrap_or(today);
+    let from = match request.from.as_deref() {
+        Some(from) => parse(from)?,
+        None => to - chrono::Months::new(12),
+    };
+
+    let mut days: BTreeMap<NaiveDate, CalendarDay> = BTreeMap::new();
+    for (date, path) in daily::existing_daily_notes(index.root(), config) {
+        if (from..=to).contains(&date) {
+            calendar_day(&mut days, date).daily_note = Some(path);
+        }
+    }
+
+    let midnight = |date: NaiveDate| {
+        tz.from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .map_or(i64::MAX, |time| time.timestamp())
+    };
+    let end = to.succ_opt().map_or(i64::MAX, midnight);
+    for note in index.modified_between(midnight(from), end)? {
+        let Some(time) = DateTime::from_timestamp(note.modified_at, 0) else {
+            continue;
+        };
+        let entry = calendar_day(&mut days, time.with_timezone(tz).date_naive());
+        entry.modified_count += 1;
+        entry.modified.push(note.path);
+    }
+    Ok(CalendarResponse {
+        days: days.into_values().collect(),
+    })
+}
+
+fn calendar_day(days: &mut BTreeMap<NaiveDate, CalendarDay>, date: NaiveDate) -> &mut CalendarDay {
+    days.entry(date).or_insert_with(|| CalendarDay {
+        date: date.to_string(),
+        daily_note: None,
+        modified_count: 0,
+        modified: Vec::new(),
+    })
+}
+
+/// Instantiate a template as a new note
+pub fn handle_new_note(
+    root: &Path,
+    config: &Config,
+    today: NaiveDate,
+    request: NewNoteRequest,
+) -> Result<NewNoteResponse> {
+    let vars = TemplateVars {
+        title: &request.title,
+        date: today,
+        tags: &request.tags,
+    };
+    let folder = request.folder.unwrap_or_default();
+    let path = templates::create_note(root, config, request.template.as_deref(), &folder, &vars)?;
+    Ok(NewNoteResponse {
+        uri: path_to_uri(&path)?.as_str().to_string(),
+        path,
+    })
+}
+
+/// Find plain-text mentions of note titles and aliases in one or every note
+pub fn handle_unlinked_mentions(
+    index: &Index,
+    parser: &Parser,
+    request: UnlinkedMentionsRequest,
+) -> Result<UnlinkedMentionsResponse> {
+    let finder = MentionFinder::cached(index)?;
+    let files = match request.file_path {
+        Some(path) => vec![path],
+        None => index.get_all_files()?,
+    };
+
+    let mut mentions = Vec::new();
+    for file in files {
+        index.check_cancelled()?;
+        let content = std::fs::read_to_string(&file)?;
+        mentions.extend(finder.find(parser, &file, &content));
+    }
+    Ok(UnlinkedMentionsResponse { mentions })
+}
+
+/// Find labels that nothing references in one or every note
+pub fn handle_dead_labels(
+    index: &Index,
+    parser: &Parser,
+    request: DeadLabelsRequest,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<DeadLabelsResponse> {
+    let files = match request.file_path {
+        Some(path) => vec![path],
+        None => index.get_all_files()?,
+    };
+    let labels = dead_labels::dead_labels(index, parser, &files, read)?;
+    Ok(DeadLabelsResponse { labels })
+}
+
+/// Notes ranked by how recently or often they were visited, as of `now` (Unix seconds)
+pub fn handle_recent_notes(
+    index: &Index,
+    now: i64,
+    request: RecentNotesRequest,
+) -> Result<RecentNotesResponse> {
+    let notes = index.recent_notes(request.order, request.limit.unwrap_or(20), now)?;
+    Ok(RecentNotesResponse { notes })
+}
+
+/// Star (or unstar) a note at `now` (Unix seconds)
+pub fn handle_star_note(
+    index: &Index,
+    starred: bool,
+    now: i64,
+    request: StarNoteRequest,
+) -> Result<()> {
+    index.set_starred(&request.file, starred, now)
+}
+
+/// Process starred notes request by wrapping Index::starred_notes
+pub fn handle_starred_notes(index: &Index) -> Result<StarredNotesResponse> {
+    let notes = index.starred_notes()?;
+    Ok(StarredNotesResponse { notes })
+}
+
+/// Title, size and last commit of an indexed note
+pub fn handle_file_info(index: &Index, request: FileInfoRequest) -> Result<FileInfoResponse> {
+    let parsed = index
+        .get_file(&request.file)?
+        .with_context(|| format!("{} is not an indexed note", request.file.display()))?;
+    Ok(FileInfoResponse {
+        last_commit: index.last_commit(&request.file)?,
+        path: request.file,
+        title: parsed.metadata.title,
+        stats: parsed.stats,
+    })
+}
+
+/// Process history request by wrapping Index::history
+pub fn handle_history(index: &Index, request: HistoryRequest) -> Result<HistoryResponse> {
+    let snapshots = index.history(&request.file)?;
+    Ok(HistoryResponse { snapshots })
+}
+
+/// Figures and labeled equations across the vault, filtered by kind and caption or label
+pub fn handle_figures(index: &Index, request: FiguresRequest) -> Result<FiguresResponse> {
+    let query = request.query.map(|query| query.to_lowercase());
+    let matches = |text: &Option<String>, query: &str| {
+        text.as_ref()
+            .is_some_and(|text| text.to_lowercase().contains(query))
+    };
+    let figures = index
+        .get_figures()?
+        .into_iter()
+        .filter(|(_, figure)| request.kind.is_none_or(|kind| figure.kind == kind))
+        .filter(|(_, figure)| {
+            query.as_deref().is_none_or(|query| {
+                matches(&figure.caption, query) || matches(&figure.label, query)
+            })
+        })
+        .map(|(path, figure)| FigureEntry { path, figure })
+        .collect();
+    Ok(FiguresResponse { figures })
+}
+
+/// Which versions of each package notes import, flagging packages used at several versions
+pub fn handle_packages(index: &Index) -> Result<PackagesResponse> {
+    let mut usage: BTreeMap<(String, String), BTreeMap<String, Vec<PathBuf>>> = BTreeMap::new();
+    for (path, package) in index.get_packages()? {
+        let notes = usage
+            .entry((package.namespace, package.name))
+            .or_default()
+            .entry(package.version)
+            .or_default();
+        if notes.last() != Some(&path) {
+            notes.push(path);
+        }
+    }
+
+    let packages = usage
+        .into_iter()
+        .map(|((namespace, name), versions)| {
+            let mut versions: Vec<_> = versions
+                .into_iter()
+                .map(|(version, notes)| PackageVersion { version, notes })
+                .collect();
+            versions.sort_by_cached_key(|version| version_key(&version.version));
+            PackageUsage {
+                namespace,
+                name,
+                latest: versions
+                    .last()
+                    .map(|v| v.version.clone())
+                    .unwrap_or_default(),
+                inconsistent: versions.len() > 1,
+                versions,
+            }
         })
         .collect();
-    Ok(BackwardLinksResponse { links })
+    Ok(PackagesResponse { packages })
+}
+
+// Orders `0.10.0` after `0.2.0`, comparing each dot-separated part as a number when it is one
+fn version_key(version: &str) -> Vec<(u64, String)> {
+    version
+        .split('.')
+        .map(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            (
+                digits.parse().unwrap_or(0),
+                part[digits.len()..].to_string(),
+            )
+        })
+        .collect()
+}
+
+/// The heading tree of a note's current `text`
+pub fn handle_outline(
+    parser: &Parser,
+    text: &str,
+    request: OutlineRequest,
+) -> Result<OutlineResponse> {
+    let sections = outline::outline(parser, &request.file, text)?;
+    Ok(OutlineResponse { sections })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, vault};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_figures() {
+        let content = "#figure(table(), caption: [Survey results]) <fig-survey>\n\
+                       #figure(image(\"a.png\"), caption: [A plot])\n\
+                       $ x = 1 $ <eq-x>";
+        let (_temp_dir, index) = vault(&[("note.typ", content)]);
+        let figures = |query: Option<&str>, kind| {
+            let request = FiguresRequest {
+                query: query.map(str::to_string),
+                kind,
+            };
+            handle_figures(&index, request)
+                .unwrap()
+                .figures
+                .into_iter()
+                .map(|entry| (entry.figure.kind, entry.figure.line))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(figures(None, None).len(), 3);
+        assert_eq!(figures(Some("SURVEY"), None), vec![(FigureKind::Table, 1)]);
+        assert_eq!(figures(Some("eq-"), None), vec![(FigureKind::Equation, 3)]);
+        assert_eq!(
+            figures(None, Some(FigureKind::Image)),
+            vec![(FigureKind::Image, 2)]
+        );
+    }
+
+    #[cfg(feature = "compile")]
+    #[test]
+    fn test_export() {
+        use crate::compile::ExportFormat;
+        use crate::export::bundle::BundleOptions;
+
+        let (temp_dir, index) = vault(&[("notes/a.typ", "= A\n![[b:sec]]"), ("b.typ", "= Sec\nB")]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+
+        let mut request = ExportRequest {
+            file: root.join("notes/a.typ"),
+            format: ExportFormat::Pdf,
+            context: None,
+        };
+        let response = handle_export(&index, &parser, None, request.clone(), read).unwrap();
+        assert_eq!(response.path, root.join("notes/a.pdf"));
+        assert!(response.path.is_file());
+
+        let build = Some(Path::new("build"));
+        let response = handle_export(&index, &parser, build, request.clone(), read).unwrap();
+        assert_eq!(response.path, root.join("build/notes/a.pdf"));
+        assert!(response.path.is_file());
+
+        request.context = Some(BundleOptions::default());
+        let response = handle_export(&index, &parser, None, request, read).unwrap();
+        assert_eq!(response.path, root.join("notes/a.bundle.pdf"));
+        assert!(response.path.is_file());
+    }
+
+    #[test]
+    fn test_packages() {
+        let (temp_dir, index) = vault(&[
+            (
+                "a.typ",
+                "#import \"@preview/cetz:0.10.0\"\n#import \"@preview/cetz:0.10.0\"",
+            ),
+            ("b.typ", "#import \"@preview/cetz:0.2.0\": canvas"),
+            ("c.typ", "#import \"@local/notes:1.0.0\""),
+        ]);
+        let root = temp_dir.path();
+
+        let packages = handle_packages(&index).unwrap().packages;
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "notes");
+        assert!(!packages[0].inconsistent);
+        let cetz = &packages[1];
+        assert!(cetz.inconsistent);
+        assert_eq!(cetz.latest, "0.10.0");
+        assert_eq!(
+            cetz.versions,
+            vec![
+                PackageVersion {
+                    version: "0.2.0".to_string(),
+                    notes: vec![root.join("b.typ")],
+                },
+                PackageVersion {
+                    version: "0.10.0".to_string(),
+                    notes: vec![root.join("a.typ")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calendar() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        let config = DailyNotesConfig::default();
+        let today = chrono::Utc::now().date_naive();
+        let (daily, _) =
+            daily::ensure_daily_note(root, &config, today - chrono::Days::new(3)).unwrap();
+        let note = add_note(&mut index, "note.typ", "");
+
+        let calendar = |from: NaiveDate| {
+            let request = CalendarRequest {
+                from: Some(from.to_string()),
+                to: None,
+            };
+            handle_calendar(&index, &config, &chrono::Utc, today, request)
+                .unwrap()
+                .days
+        };
+        let days = calendar(today - chrono::Days::new(7));
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].daily_note, Some(daily));
+        assert_eq!(days[0].modified_count, 0);
+        assert_eq!(days[1].date, today.to_string());
+        assert_eq!(
+            (days[1].modified_count, &days[1].modified),
+            (1, &vec![note])
+        );
+        assert!(
+            calendar(today - chrono::Days::new(2))[0]
+                .daily_note
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_archive_note() {
+        let (temp_dir, index) = vault(&[("notes/old.typ", ""), ("a.typ", "[[notes/old]]")]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let config = Config {
+            archive_folders: vec![PathBuf::from("archive")],
+            ..Config::default()
+        };
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+        let archive = |file: PathBuf| {
+            handle_archive_note(&index, &parser, &config, read, ArchiveNoteRequest { file })
+        };
+
+        let edit = archive(root.join("notes/old.typ")).unwrap();
+        let Some(lsp_types::DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document operations");
+        };
+        let [
+            lsp_types::DocumentChangeOperation::Edit(link),
+            lsp_types::DocumentChangeOperation::Op(lsp_types::ResourceOp::Rename(rename)),
+        ] = operations.as_slice()
+        else {
+            panic!("expected a link edit and a rename: {operations:?}");
+        };
+        let lsp_types::OneOf::Left(text_edit) = &link.edits[0] else {
+            panic!("expected a plain text edit");
+        };
+        assert_eq!(text_edit.new_text, "[[archive/notes/old]]");
+        assert!(rename.new_uri.as_str().ends_with("/archive/notes/old.typ"));
+        assert!(archive(root.join("archive/old.typ")).is_err());
+    }
+
+    #[test]
+    fn test_periodic_note() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let config = DailyNotesConfig::default();
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2024, 5, 8).unwrap();
+        let (monday, _) =
+            daily::ensure_daily_note(root, &config, today - chrono::Days::new(2)).unwrap();
+
+        let periodic = |request| handle_periodic_note(root, &config, today, request).unwrap();
+        let yesterday = periodic(PeriodicNoteRequest {
+            offset: -1,
+            ..Default::default()
+        });
+        assert_eq!(yesterday.notes[0].date, "2024-05-07");
+        assert!(!yesterday.notes[0].exists);
+
+        let previous = periodic(PeriodicNoteRequest {
+            offset: -1,
+            existing_only: true,
+            ..Default::default()
+        });
+        assert_eq!(previous.notes[0].path, monday);
+        assert!(previous.notes[0].exists);
+        let before_first = periodic(PeriodicNoteRequest {
+            offset: -1,
+            from: Some(monday),
+            existing_only: true,
+            ..Default::default()
+        });
+        assert!(before_first.notes.is_empty());
+
+        let next_week = periodic(PeriodicNoteRequest {
+            offset: 1,
+            period: Period::Week,
+            ..Default::default()
+        });
+        let dates: Vec<_> = next_week.notes.iter().map(|n| n.date.as_str()).collect();
+        assert_eq!(dates.first(), Some(&"2024-05-13"));
+        assert_eq!(dates.last(), Some(&"2024-05-19"));
+
+        // Offsets beyond the calendar find nothing rather than failing
+        for period in [Period::Day, Period::Week] {
+            let far = periodic(PeriodicNoteRequest {
+                offset: i64::MAX,
+                period,
+                ..Default::default()
+            });
+            assert!(far.notes.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_backlink_context() {
+        let content = "= Intro\n\n== Details <details>\none\nsee [[target:intro]]\ntwo\nthree\n";
+        let (temp_dir, index) = vault(&[("target.typ", ""), ("source.typ", content)]);
+        let root = temp_dir.path();
+        let target = root.join("target.typ");
+
+        let request = BackwardLinksRequest {
+            file_path: target,
+            context_lines: Some(1),
+        };
+        let links = handle_backward_links(&index, request).unwrap().links;
+
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].section.as_deref(), Some("Details"));
+        assert_eq!(links[0].context, vec!["one", "see [[target:intro]]", "two"]);
+        assert_eq!(links[0].context_start, 4);
+
+        let request = LabelBacklinksRequest {
+            file_path: root.join("target.typ"),
+            label: "intro".to_string(),
+            context_lines: Some(0),
+        };
+        let links = handle_label_backlinks(&index, request).unwrap().links;
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].context, vec!["see [[target:intro]]"]);
+        let request = LabelBacklinksRequest {
+            file_path: root.join("target.typ"),
+            label: "outro".to_string(),
+            context_lines: None,
+        };
+        assert!(
+            handle_label_backlinks(&index, request)
+                .unwrap()
+                .links
+                .is_empty()
+        );
+    }
 }
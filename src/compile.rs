@@ -0,0 +1,366 @@
+use crate::config::platform_cache_dir;
+use crate::diagnostics::{Problem, ProblemKind, Severity};
+use anyhow::{Context, Result, anyhow};
+use chrono::{Datelike, Duration, Local, Timelike, Utc};
+use comemo::Prehashed;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use ts_rs::TS;
+use typst::diag::{self, FileError, FileResult, PackageError, SourceDiagnostic};
+use typst::eval::Tracer;
+use typst::foundations::{Bytes, Datetime, Smart};
+use typst::layout::Abs;
+use typst::syntax::{FileId, Source, Span, VirtualPath};
+use typst::text::{Font, FontBook};
+use typst::visualize::Color;
+use typst::{Library, World};
+
+// PNG resolution: 144 pixels per inch, as the `typst` CLI renders by default
+const PIXELS_PER_POINT: f32 = 2.0;
+
+/// What `export` encodes a compiled note as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum ExportFormat {
+    Pdf,
+    Svg,
+    Png,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Png => "png",
+        }
+    }
+}
+
+/// Compile the note at `path` with the embedded Typst compiler and report its errors and
+/// warnings as problems in that note.
+///
+/// Imports resolve against the vault `root`. Packages are read from the Typst package cache
+/// and never downloaded; fonts are the ones bundled with Typst.
+pub fn check(root: &Path, path: &Path) -> Result<Vec<Problem>> {
+    let world = NoteWorld::new(root, path)?;
+    let main = world.main();
+    let mut tracer = Tracer::new();
+    let errors = typst::compile(&world, &mut tracer)
+        .err()
+        .map(|errors| errors.to_vec())
+        .unwrap_or_default();
+
+    let mut problems: Vec<Problem> = errors
+        .iter()
+        .chain(&tracer.warnings())
+        .map(|diagnostic| to_problem(&world, &main, path, diagnostic))
+        .collect();
+    problems.sort_by_key(|problem| (problem.line, problem.column));
+    Ok(problems)
+}
+
+/// Compile the note at `path`, or `source` in its place, and encode it as `format`, failing
+/// with the first compiler error. SVG and PNG stack the pages in one image.
+pub fn export(
+    root: &Path,
+    path: &Path,
+    source: Option<String>,
+    format: ExportFormat,
+) -> Result<Vec<u8>> {
+    let world = NoteWorld::new(root, path)?;
+    if let Some(source) = source {
+        world.set_main(source);
+    }
+    let document = typst::compile(&world, &mut Tracer::new()).map_err(|errors| {
+        let problem = to_problem(&world, &world.main(), path, &errors[0]);
+        anyhow!(
+            "{} does not compile: {} (line {}, column {})",
+            path.display(),
+            problem.message,
+            problem.line,
+            problem.column
+        )
+    })?;
+
+    let gap = Abs::pt(10.0);
+    Ok(match format {
+        ExportFormat::Pdf => typst_pdf::pdf(&document, Smart::Auto, now()),
+        ExportFormat::Svg => typst_svg::svg_merged(&document, gap).into_bytes(),
+        ExportFormat::Png => typst_render::render_merged(
+            &document,
+            PIXELS_PER_POINT,
+            Color::WHITE,
+            gap,
+            Color::SILVER,
+        )
+        .encode_png()?,
+    })
+}
+
+// The PDF creation date, in UTC
+fn now() -> Option<Datetime> {
+    let now = Utc::now();
+    Datetime::from_ymd_hms(
+        now.year(),
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    )
+}
+
+// The standard library and bundled fonts, loaded on the first compile
+struct Assets {
+    library: Prehashed<Library>,
+    book: Prehashed<FontBook>,
+    fonts: Vec<Font>,
+}
+
+fn assets() -> &'static Assets {
+    static ASSETS: OnceLock<Assets> = OnceLock::new();
+    ASSETS.get_or_init(|| {
+        let fonts: Vec<Font> = typst_assets::fonts()
+            .flat_map(|data| Font::iter(Bytes::from_static(data)))
+            .collect();
+        Assets {
+            library: Prehashed::new(Library::default()),
+            book: Prehashed::new(FontBook::from_fonts(&fonts)),
+            fonts,
+        }
+    })
+}
+
+struct NoteWorld {
+    root: PathBuf,
+    main: FileId,
+    // Sources read during this compile, so each file is read once
+    sources: Mutex<HashMap<FileId, Source>>,
+}
+
+impl NoteWorld {
+    fn new(root: &Path, path: &Path) -> Result<Self> {
+        let vpath = VirtualPath::within_root(path, root)
+            .with_context(|| format!("{} is outside the vault", path.display()))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            main: FileId::new(None, vpath),
+            sources: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Compile `text` as the main note instead of its file
+    fn set_main(&self, text: String) {
+        let source = Source::new(self.main, text);
+        self.sources
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(self.main, source);
+    }
+
+    // Files of `@namespace/name:version` packages live under the Typst package cache
+    fn path(&self, id: FileId) -> FileResult<PathBuf> {
+        let root = match id.package() {
+            Some(spec) => platform_cache_dir()
+                .map(|dir| {
+                    dir.join("typst")
+                        .join("packages")
+                        .join(spec.namespace.as_str())
+                        .join(spec.name.as_str())
+                        .join(spec.version.to_string())
+                })
+                .filter(|dir| dir.is_dir())
+                .ok_or_else(|| FileError::Package(PackageError::NotFound(spec.clone())))?,
+            None => self.root.clone(),
+        };
+        id.vpath().resolve(&root).ok_or(FileError::AccessDenied)
+    }
+
+    fn read(&self, id: FileId) -> FileResult<Vec<u8>> {
+        let path = self.path(id)?;
+        if path.is_dir() {
+            return Err(FileError::IsDirectory);
+        }
+        std::fs::read(&path).map_err(|e| FileError::from_io(e, &path))
+    }
+}
+
+impl World for NoteWorld {
+    fn library(&self) -> &Prehashed<Library> {
+        &assets().library
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        &assets().book
+    }
+
+    fn main(&self) -> Source {
+        // A note that cannot be read compiles as empty; reading it again reports why
+        self.source(self.main)
+            .unwrap_or_else(|_| Source::new(self.main, String::new()))
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(source) = sources.get(&id) {
+            return Ok(source.clone());
+        }
+        let text = String::from_utf8(self.read(id)?).map_err(|_| FileError::InvalidUtf8)?;
+        // Typst skips a leading byte order mark
+        let text = text
+            .strip_prefix('\u{feff}')
+            .map(str::to_string)
+            .unwrap_or(text);
+        let source = Source::new(id, text);
+        sources.insert(id, source.clone());
+        Ok(source)
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        self.read(id).map(Bytes::from)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        assets().fonts.get(index).cloned()
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        let date = match offset {
+            Some(hours) => (Utc::now() + Duration::hours(hours)).date_naive(),
+            None => Local::now().date_naive(),
+        };
+        Datetime::from_ymd(date.year(), date.month() as u8, date.day() as u8)
+    }
+}
+
+// Problems in imported files are placed where the note calls into them, or at its start
+fn to_problem(
+    world: &NoteWorld,
+    main: &Source,
+    path: &Path,
+    diagnostic: &SourceDiagnostic,
+) -> Problem {
+    let span = std::iter::once(diagnostic.span)
+        .chain(diagnostic.trace.iter().map(|point| point.span))
+        .find(|span| span.id() == Some(main.id()));
+    let range = span.and_then(|span| main.range(span)).unwrap_or(0..0);
+    let (line, column) = position(main, range.start);
+
+    let mut message = diagnostic.message.to_string();
+    if span != Some(diagnostic.span)
+        && let Some(file) = source_path(world, diagnostic.span)
+    {
+        message = format!("{message} (in `{}`)", file.display());
+    }
+    for hint in &diagnostic.hints {
+        message.push_str(&format!("\nhint: {hint}"));
+    }
+
+    let mut problem = Problem::new(
+        ProblemKind::Compiler,
+        path.to_path_buf(),
+        line,
+        column,
+        message,
+    );
+    problem.severity = match diagnostic.severity {
+        diag::Severity::Error => Severity::Error,
+        diag::Severity::Warning => Severity::Warning,
+    };
+    problem.end = Some(position(main, range.end));
+    problem
+}
+
+// 1-based line and column of a byte offset, counting characters like the parser
+fn position(source: &Source, offset: usize) -> (usize, usize) {
+    let line = source.byte_to_line(offset).unwrap_or(0);
+    let column = source.byte_to_column(offset).unwrap_or(0);
+    (line + 1, column + 1)
+}
+
+// The file a span points into, as written in the vault or the package
+fn source_path(world: &NoteWorld, span: Span) -> Option<PathBuf> {
+    let id = span.id()?;
+    match id.package() {
+        Some(spec) => Some(PathBuf::from(format!("{spec}")).join(id.vpath().as_rootless_path())),
+        None => id.vpath().resolve(&world.root).map(|path| {
+            path.strip_prefix(&world.root)
+                .map(Path::to_path_buf)
+                .unwrap_or(path)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("lib.typ"), "#let f() = panic(\"boom\")").unwrap();
+        let path = root.join("note.typ");
+        std::fs::write(
+            &path,
+            "= Note\n#import \"lib.typ\": f\nHello #undefined here\n#f()",
+        )
+        .unwrap();
+
+        let problems = check(root, &path).unwrap();
+        assert_eq!(problems.len(), 1);
+        let problem = &problems[0];
+        assert_eq!(problem.kind, ProblemKind::Compiler);
+        assert_eq!(problem.severity, Severity::Error);
+        assert_eq!((problem.line, problem.column), (3, 8));
+        assert_eq!(problem.end, Some((3, 17)));
+        assert_eq!(problem.message, "unknown variable: undefined");
+
+        std::fs::write(&path, "#import \"lib.typ\": f\n#f()").unwrap();
+        let problems = check(root, &path).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!((problems[0].line, problems[0].column), (2, 2));
+        assert!(problems[0].message.contains("(in `lib.typ`)"));
+    }
+
+    #[test]
+    fn test_export() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let path = root.join("note.typ");
+        std::fs::write(&path, "= Title\nSome text.").unwrap();
+
+        let pdf = export(root, &path, None, ExportFormat::Pdf).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        let svg = export(root, &path, None, ExportFormat::Svg).unwrap();
+        assert!(String::from_utf8(svg).unwrap().starts_with("<svg"));
+        let png = export(root, &path, None, ExportFormat::Png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        std::fs::write(&path, "#undefined").unwrap();
+        let error = export(root, &path, None, ExportFormat::Pdf).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("unknown variable: undefined (line 1, column 2)")
+        );
+
+        let source = "#let x = 1\n#x".to_string();
+        let svg = export(root, &path, Some(source), ExportFormat::Svg).unwrap();
+        assert!(String::from_utf8(svg).unwrap().starts_with("<svg"));
+    }
+
+    #[test]
+    fn test_check_clean() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let path = root.join("note.typ");
+        std::fs::write(&path, "= Title\nSome *text* and $x^2$.").unwrap();
+        assert_eq!(check(root, &path).unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,458 @@
+use crate::parser::attachments::is_attachment_path;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::paths::PathStyle;
+use anyhow::{Context, Result, bail};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE_NAME: &str = ".typst-oxide.toml";
+/// Environment variable holding the key of an encrypted index.
+pub const INDEX_KEY_VAR: &str = "TYPST_OXIDE_INDEX_KEY";
+// Service name of the OS keychain entry holding the key of an encrypted index
+const KEYCHAIN_SERVICE: &str = "typst-oxide";
+
+/// Workspace configuration merged from `.typst-oxide.toml` and LSP `initializationOptions`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Globs (relative to the vault root) selecting which `.typ` files are notes.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Folder where new attachments are stored, relative to the vault root.
+    pub attachment_folder: Option<PathBuf>,
+    pub wikilink_syntax: WikilinkSyntax,
+    pub daily_notes: DailyNotesConfig,
+    /// Folder holding note templates, relative to the vault root.
+    pub template_folder: Option<PathBuf>,
+    /// Template (by name, from `template_folder`) used when creating a note without one.
+    pub default_template: Option<String>,
+    pub embeddings: EmbeddingsConfig,
+    pub inlay_hints: InlayHintsConfig,
+    pub index_location: IndexLocation,
+    /// Explicit index database path; overrides `index_location`.
+    pub index_path: Option<PathBuf>,
+    /// How command responses write paths.
+    pub path_style: PathStyle,
+    /// Match link targets to note paths and names ignoring ASCII case, as the file systems of
+    /// macOS and Windows do. On by default there.
+    pub case_insensitive_links: bool,
+    /// Folders, relative to the vault root, whose notes are archived. `archiveNote` moves notes
+    /// into the first one.
+    pub archive_folders: Vec<PathBuf>,
+    /// Rows of each kind, such as links or labels, indexed per note. A note with more, like a
+    /// multi-megabyte log, has the rest left out and is reported; `0` indexes everything.
+    pub max_index_rows: usize,
+    /// Milliseconds the language server waits after a note is saved or changed on disk before
+    /// reindexing it, so a burst of changes is indexed at once.
+    pub reindex_debounce_ms: u64,
+    /// Encrypt the index database with SQLCipher (requires the `encryption` feature), keyed by
+    /// `TYPST_OXIDE_INDEX_KEY` or the OS keychain.
+    pub encrypt_index: bool,
+    /// Record each note's last git commit for hovers, `typst-oxide/fileInfo` and queries; reads
+    /// the history whenever the vault is indexed and whenever `HEAD` moves.
+    pub git_history: bool,
+    /// Compile each saved note with the embedded Typst compiler (requires the `compile`
+    /// feature) and report its errors and warnings alongside the vault's problems.
+    pub compile_on_save: bool,
+    /// Folder, relative to the vault root, that `typst-oxide/export` writes to, mirroring the
+    /// vault's folders. Exports go next to their note by default.
+    pub build_folder: Option<PathBuf>,
+    /// How severe each kind of vault problem is, overriding the defaults.
+    pub severity: SeverityConfig,
+}
+
+/// Where the SQLite index lives when no explicit `index_path` is configured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexLocation {
+    /// A per-vault directory under the platform cache dir.
+    #[default]
+    Cache,
+    /// `.pkm-cache.db` in the vault root.
+    Vault,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyNotesConfig {
+    pub folder: PathBuf,
+    /// A chrono `strftime` pattern used to name daily notes.
+    pub date_format: String,
+    pub template: Option<PathBuf>,
+}
+
+/// Which inlay hints are shown after wikilinks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InlayHintsConfig {
+    /// The title of the note a link without an alias resolves to.
+    pub titles: bool,
+    /// A marker on links that resolve to nothing.
+    pub unresolved: bool,
+}
+
+impl Default for InlayHintsConfig {
+    fn default() -> Self {
+        Self {
+            titles: true,
+            unresolved: true,
+        }
+    }
+}
+
+/// Severities of the configurable diagnostic rules; `None` keeps the default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeverityConfig {
+    /// Links that match no note or file.
+    pub broken_link: Option<RuleSeverity>,
+    /// Links to a label or block missing from their note.
+    pub broken_label: Option<RuleSeverity>,
+    /// Links by a name that several notes share.
+    pub ambiguous_link: Option<RuleSeverity>,
+    /// `![[...]]` embeds of a missing note, label or block.
+    pub unresolved_embed: Option<RuleSeverity>,
+    /// Images and other assets that don't exist.
+    pub missing_asset: Option<RuleSeverity>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleSeverity {
+    Error,
+    Warning,
+    #[serde(alias = "information")]
+    Info,
+    /// Don't report the problem at all.
+    Ignore,
+}
+
+/// Embedding backend for semantic search (used with the `embeddings` feature).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingsConfig {
+    pub backend: EmbeddingBackend,
+    /// OpenAI-compatible `/embeddings` URL for the `http` backend.
+    pub endpoint: Option<String>,
+    pub model: Option<String>,
+    /// Vector size of the `hashing` backend.
+    pub dimensions: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingBackend {
+    /// Hashed bag-of-words vectors computed locally, with no model to download.
+    #[default]
+    Hashing,
+    Http,
+}
+
+impl Default for EmbeddingsConfig {
+    fn default() -> Self {
+        Self {
+            backend: EmbeddingBackend::default(),
+            endpoint: None,
+            model: None,
+            dimensions: 256,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            include: vec!["**/*.typ".to_string()],
+            exclude: Vec::new(),
+            attachment_folder: None,
+            wikilink_syntax: WikilinkSyntax::default(),
+            daily_notes: DailyNotesConfig::default(),
+            template_folder: None,
+            default_template: None,
+            embeddings: EmbeddingsConfig::default(),
+            inlay_hints: InlayHintsConfig::default(),
+            index_location: IndexLocation::default(),
+            index_path: None,
+            path_style: PathStyle::default(),
+            case_insensitive_links: cfg!(any(target_os = "macos", windows)),
+            archive_folders: Vec::new(),
+            max_index_rows: 10_000,
+            reindex_debounce_ms: 300,
+            encrypt_index: false,
+            git_history: false,
+            compile_on_save: false,
+            build_folder: None,
+            severity: SeverityConfig::default(),
+        }
+    }
+}
+
+impl Default for DailyNotesConfig {
+    fn default() -> Self {
+        Self {
+            folder: PathBuf::from("daily"),
+            date_format: "%Y-%m-%d".to_string(),
+            template: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the vault's config file and merge it over the editor-supplied options.
+    ///
+    /// Values in `.typst-oxide.toml` take precedence, so a vault behaves the same in every editor.
+    pub fn load(root: &Path, options: Option<&Value>) -> Result<Self> {
+        let mut merged = match options {
+            Some(value) if value.is_object() => value.clone(),
+            _ => Value::Object(Default::default()),
+        };
+
+        let config_path = root.join(CONFIG_FILE_NAME);
+        if config_path.exists() {
+            let content = std::fs::read_to_string(&config_path)?;
+            let file_value: toml::Value = toml::from_str(&content)
+                .with_context(|| format!("Invalid {}", config_path.display()))?;
+            merge(&mut merged, serde_json::to_value(file_value)?);
+        }
+
+        serde_json::from_value(merged).context("Invalid configuration")
+    }
+
+    /// Resolve the index database path for the vault at `root`.
+    pub fn index_path(&self, root: &Path) -> Result<PathBuf> {
+        if let Some(path) = &self.index_path {
+            return Ok(root.join(path));
+        }
+
+        match self.index_location {
+            IndexLocation::Vault => Ok(root.join(".pkm-cache.db")),
+            IndexLocation::Cache => {
+                let cache_dir = platform_cache_dir().context("No platform cache directory")?;
+                Ok(cache_dir
+                    .join("typst-oxide")
+                    .join(vault_cache_key(root))
+                    .join("index.db"))
+            }
+        }
+    }
+
+    /// The key to open the index with: `None` unless `encrypt_index` is set, then the value of
+    /// `TYPST_OXIDE_INDEX_KEY` or else the keychain's `typst-oxide` entry.
+    pub fn index_key(&self) -> Result<Option<String>> {
+        if !self.encrypt_index {
+            return Ok(None);
+        }
+        if cfg!(not(feature = "encryption")) {
+            bail!(
+                "encrypt_index is set but typst-oxide was built without the `encryption` feature"
+            );
+        }
+        if let Some(key) = std::env::var(INDEX_KEY_VAR)
+            .ok()
+            .filter(|key| !key.is_empty())
+        {
+            return Ok(Some(key));
+        }
+        keychain_key().map(Some).with_context(|| {
+            format!(
+                "encrypt_index is set but neither {INDEX_KEY_VAR} nor a `{KEYCHAIN_SERVICE}` \
+                 keychain entry holds a key"
+            )
+        })
+    }
+
+    pub fn note_filter(&self) -> Result<NoteFilter> {
+        Ok(NoteFilter {
+            include: build_glob_set(&self.include)?,
+            exclude: build_glob_set(&self.exclude)?,
+        })
+    }
+}
+
+/// Decides which vault-relative paths are indexed as notes.
+#[derive(Debug, Clone)]
+pub struct NoteFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl NoteFilter {
+    pub fn is_note(&self, relative_path: &Path) -> bool {
+        relative_path.extension().is_some_and(|ext| ext == "typ")
+            && self.include.is_match(relative_path)
+            && !self.exclude.is_match(relative_path)
+    }
+
+    pub fn is_attachment(&self, relative_path: &Path) -> bool {
+        relative_path.to_str().is_some_and(is_attachment_path)
+            && !self.exclude.is_match(relative_path)
+    }
+}
+
+impl Default for NoteFilter {
+    fn default() -> Self {
+        Config::default()
+            .note_filter()
+            .expect("default globs are valid")
+    }
+}
+
+pub(crate) fn platform_cache_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| {
+        std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+
+    if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    }
+}
+
+// The password stored in the keychain by `security` on macOS or `secret-tool` (libsecret)
+// elsewhere
+fn keychain_key() -> Option<String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("security");
+        command.args(["find-generic-password", "-s", KEYCHAIN_SERVICE, "-w"]);
+        command
+    } else {
+        let mut command = std::process::Command::new("secret-tool");
+        command.args(["lookup", "service", KEYCHAIN_SERVICE]);
+        command
+    };
+    let output = command.output().ok()?;
+    let key = String::from_utf8(output.stdout).ok()?;
+    let key = key.trim_end_matches(['\r', '\n']);
+    (output.status.success() && !key.is_empty()).then(|| key.to_string())
+}
+
+/// A directory name unique to the vault: its folder name plus a hash of its canonical path.
+fn vault_cache_key(root: &Path) -> String {
+    let canonical = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let hash = xxhash_rust::xxh3::xxh3_64(canonical.to_string_lossy().as_bytes());
+    let name = canonical
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "vault".to_string());
+
+    format!("{name}-{hash:016x}")
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("Invalid glob: {pattern}"))?);
+    }
+    Ok(builder.build()?)
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_default_when_missing() {
+        let temp_dir = tempdir().unwrap();
+        let config = Config::load(temp_dir.path(), None).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_partial_wikilink_syntax() {
+        let temp_dir = tempdir().unwrap();
+        let options = json!({ "wikilink_syntax": { "label_separator": "#" } });
+        let config = Config::load(temp_dir.path(), Some(&options)).unwrap();
+
+        assert_eq!(config.wikilink_syntax.label_separator, '#');
+        assert!(!config.wikilink_syntax.alias_first);
+    }
+
+    #[test]
+    fn test_file_overrides_options() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE_NAME),
+            r#"
+exclude = ["archive/**"]
+attachment_folder = "assets"
+
+[daily_notes]
+folder = "journal"
+"#,
+        )
+        .unwrap();
+        let options = json!({
+            "attachment_folder": "files",
+            "daily_notes": { "date_format": "%d.%m.%Y" },
+        });
+
+        let config = Config::load(temp_dir.path(), Some(&options)).unwrap();
+
+        assert_eq!(config.exclude, vec!["archive/**"]);
+        assert_eq!(config.attachment_folder, Some(PathBuf::from("assets")));
+        assert_eq!(config.daily_notes.folder, PathBuf::from("journal"));
+        assert_eq!(config.daily_notes.date_format, "%d.%m.%Y");
+    }
+
+    #[test]
+    fn test_index_path() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+
+        let vault = Config {
+            index_location: IndexLocation::Vault,
+            ..Config::default()
+        };
+        assert_eq!(vault.index_path(root).unwrap(), root.join(".pkm-cache.db"));
+
+        let other_dir = tempdir().unwrap();
+        let custom = other_dir.path().join("custom.db");
+        let explicit = Config {
+            index_path: Some(custom.clone()),
+            ..Config::default()
+        };
+        assert_eq!(explicit.index_path(root).unwrap(), custom);
+
+        let cached = Config::default();
+        let path = cached.index_path(root).unwrap();
+        assert!(!path.starts_with(root));
+        assert_eq!(path, cached.index_path(root).unwrap());
+        assert_ne!(path, cached.index_path(other_dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_note_filter() {
+        let config = Config {
+            include: vec!["notes/**/*.typ".to_string()],
+            exclude: vec!["notes/drafts/**".to_string()],
+            ..Config::default()
+        };
+        let filter = config.note_filter().unwrap();
+
+        assert!(filter.is_note(Path::new("notes/a.typ")));
+        assert!(filter.is_note(Path::new("notes/deep/b.typ")));
+        assert!(!filter.is_note(Path::new("notes/drafts/c.typ")));
+        assert!(!filter.is_note(Path::new("other/d.typ")));
+        assert!(!filter.is_note(Path::new("notes/e.pdf")));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::config::DailyNotesConfig;
+use crate::templates::{self, TemplateVars};
+use anyhow::{Context, Result, anyhow};
+use chrono::{Days, NaiveDate};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Format `date` with a `strftime` pattern, rejecting invalid patterns instead of panicking.
+pub fn format_date(date: NaiveDate, pattern: &str) -> Result<String> {
+    let mut out = String::new();
+    write!(out, "{}", date.format(pattern))
+        .map_err(|_| anyhow!("Invalid date format `{pattern}`"))?;
+    Ok(out)
+}
+
+/// Path of the daily note for `date`.
+pub fn daily_note_path(root: &Path, config: &DailyNotesConfig, date: NaiveDate) -> Result<PathBuf> {
+    let name = format_date(date, &config.date_format)?;
+    Ok(root.join(&config.folder).join(format!("{name}.typ")))
+}
+
+/// Create the daily note for `date` unless it exists, returning its path and whether it
+/// was created. New notes are filled from the configured template, if any.
+pub fn ensure_daily_note(
+    root: &Path,
+    config: &DailyNotesConfig,
+    date: NaiveDate,
+) -> Result<(PathBuf, bool)> {
+    let path = daily_note_path(root, config, date)?;
+    if path.exists() {
+        return Ok((path, false));
+    }
+
+    let title = format_date(date, &config.date_format)?;
+    let content = match &config.template {
+        Some(template) => {
+            let path = root.join(template);
+            let template = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read template {}", path.display()))?;
+            let content = templates::render(
+                &template,
+                &TemplateVars {
+                    title: &title,
+                    date,
+                    tags: &[],
+                },
+            );
+            // The latest earlier entry, so `[[{{prev}}]]` skips days without a note
+            let prev = existing_daily_notes(root, config)
+                .into_iter()
+                .rev()
+                .find(|(existing, _)| *existing < date)
+                .map_or_else(|| date - Days::new(1), |(existing, _)| existing);
+            content
+                .replace("{{prev}}", &note_name(config, prev)?)
+                .replace("{{next}}", &note_name(config, date + Days::new(1))?)
+        }
+        None => format!("#meta(\n  title: \"{title}\",\n)\n\n"),
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, content)?;
+    Ok((path, true))
+}
+
+// The link target naming the daily note for `date`
+fn note_name(config: &DailyNotesConfig, date: NaiveDate) -> Result<String> {
+    format_date(date, &config.date_format)
+}
+
+/// The date the daily note at `path` is for, parsed from its name (and any folders the date
+/// format adds below the daily folder).
+pub fn daily_note_date(root: &Path, config: &DailyNotesConfig, path: &Path) -> Option<NaiveDate> {
+    let relative = path
+        .strip_prefix(root.join(&config.folder))
+        .ok()?
+        .with_extension("");
+    let name: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    NaiveDate::parse_from_str(&name.join("/"), &config.date_format).ok()
+}
+
+/// Every existing daily note with its date, oldest first.
+pub fn existing_daily_notes(root: &Path, config: &DailyNotesConfig) -> Vec<(NaiveDate, PathBuf)> {
+    let mut notes: Vec<_> = WalkDir::new(root.join(&config.folder))
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "typ"))
+        .filter_map(|path| Some((daily_note_date(root, config, &path)?, path)))
+        .collect();
+    notes.sort();
+    notes
+}
+
+/// The date `offset` daily notes away from `date`: calendar days, or with `existing_only`,
+/// the `offset`-th existing note before (negative) or after (positive) `date`.
+pub fn step(
+    root: &Path,
+    config: &DailyNotesConfig,
+    date: NaiveDate,
+    offset: i64,
+    existing_only: bool,
+) -> Option<NaiveDate> {
+    if !existing_only {
+        return chrono::TimeDelta::try_days(offset).and_then(|days| date.checked_add_signed(days));
+    }
+    let dates: Vec<NaiveDate> = existing_daily_notes(root, config)
+        .into_iter()
+        .map(|(date, _)| date)
+        .collect();
+    let steps = offset.unsigned_abs() as usize;
+    match offset {
+        0 => Some(date),
+        ..0 => {
+            let before = dates.iter().filter(|d| **d < date).count();
+            before.checked_sub(steps).map(|i| dates[i])
+        }
+        _ => {
+            let after = dates.iter().position(|d| *d > date)?;
+            dates.get(after + steps - 1).copied()
+        }
+    }
+}
+
+/// Daily notes within a week of `today` that don't exist yet, as `(link target, date)`.
+pub fn missing_daily_notes(
+    root: &Path,
+    config: &DailyNotesConfig,
+    today: NaiveDate,
+) -> Result<Vec<(String, NaiveDate)>> {
+    let mut missing = Vec::new();
+    for date in (today - Days::new(7)).iter_days().take(15) {
+        // Named by its path below the daily folder, which with a format such as `%Y/%m/%d`
+        // includes folders
+        if !daily_note_path(root, config, date)?.exists() {
+            missing.push((note_name(config, date)?, date));
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ensure_daily_note() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let mut config = DailyNotesConfig::default();
+
+        let (path, created) = ensure_daily_note(root, &config, date).unwrap();
+        assert!(created);
+        assert_eq!(path, root.join("daily").join("2024-05-01.typ"));
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("title: \"2024-05-01\"")
+        );
+        assert!(!ensure_daily_note(root, &config, date).unwrap().1);
+
+        std::fs::write(root.join("tpl.typ"), "= {{title}} ({{date}})\n").unwrap();
+        config.template = Some(PathBuf::from("tpl.typ"));
+        config.date_format = "%d.%m.%Y".to_string();
+        let (path, _) = ensure_daily_note(root, &config, date).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "= 01.05.2024 (2024-05-01)\n"
+        );
+
+        config.date_format = "%Q".to_string();
+        assert!(ensure_daily_note(root, &config, date).is_err());
+    }
+
+    #[test]
+    fn test_navigation() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+        let mut config = DailyNotesConfig {
+            date_format: "%Y/%m/%d".to_string(),
+            ..Default::default()
+        };
+        for d in [1, 3, 4] {
+            ensure_daily_note(root, &config, day(d)).unwrap();
+        }
+        let note = root.join("daily").join("2024").join("05").join("03.typ");
+        assert_eq!(daily_note_date(root, &config, &note), Some(day(3)));
+        assert_eq!(daily_note_date(root, &config, &root.join("x.typ")), None);
+        assert_eq!(existing_daily_notes(root, &config).len(), 3);
+
+        assert_eq!(step(root, &config, day(3), -1, false), Some(day(2)));
+        assert_eq!(step(root, &config, day(3), -1, true), Some(day(1)));
+        assert_eq!(step(root, &config, day(3), -2, true), None);
+        assert_eq!(step(root, &config, day(2), 2, true), Some(day(4)));
+        assert_eq!(step(root, &config, day(4), 1, true), None);
+        assert_eq!(step(root, &config, day(3), i64::MAX, false), None);
+        assert_eq!(step(root, &config, day(3), i64::MIN, true), None);
+
+        std::fs::write(root.join("tpl.typ"), "[[{{prev}}]] [[{{next}}]]").unwrap();
+        config.template = Some(PathBuf::from("tpl.typ"));
+        let (path, _) = ensure_daily_note(root, &config, day(10)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "[[2024/05/04]] [[2024/05/11]]"
+        );
+    }
+
+    #[test]
+    fn test_missing_daily_notes() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let config = DailyNotesConfig::default();
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        ensure_daily_note(root, &config, today).unwrap();
+
+        let missing = missing_daily_notes(root, &config, today).unwrap();
+        assert_eq!(missing.len(), 14);
+        assert_eq!(missing[0].0, "2024-05-03");
+        assert!(missing.iter().all(|(_, date)| *date != today));
+
+        let nested = DailyNotesConfig {
+            date_format: "%Y/%m/%d".to_string(),
+            ..DailyNotesConfig::default()
+        };
+        let missing = missing_daily_notes(root, &nested, today).unwrap();
+        assert_eq!(missing[0].0, "2024/05/03");
+    }
+}
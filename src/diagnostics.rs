@@ -0,0 +1,593 @@
+use crate::config::{RuleSeverity, SeverityConfig};
+use crate::index::Index;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// A comment hiding the problems on the line below it, or only those of the listed kinds,
+/// e.g. `// pkm-ignore-next-line broken-link`.
+pub const IGNORE_NEXT_LINE: &str = "// pkm-ignore-next-line";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "kebab-case")]
+#[ts(export)]
+pub enum ProblemKind {
+    BrokenLink,
+    MissingLabel,
+    MissingBlock,
+    DuplicateLabel,
+    SharedLabel,
+    MissingAsset,
+    RedirectedLink,
+    MergeConflict,
+    UnknownCitation,
+    /// An error or warning from the Typst compiler
+    Compiler,
+    /// A link by a name that several notes share
+    AmbiguousLink,
+    /// A broken link, label or block in an `![[...]]` embed
+    UnresolvedEmbed,
+    /// A note with more rows of some kind than `max_index_rows`, only partly indexed
+    TruncatedNote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+}
+
+impl ProblemKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProblemKind::BrokenLink => "broken-link",
+            ProblemKind::MissingLabel => "missing-label",
+            ProblemKind::MissingBlock => "missing-block",
+            ProblemKind::DuplicateLabel => "duplicate-label",
+            ProblemKind::SharedLabel => "shared-label",
+            ProblemKind::MissingAsset => "missing-asset",
+            ProblemKind::RedirectedLink => "redirected-link",
+            ProblemKind::MergeConflict => "merge-conflict",
+            ProblemKind::UnknownCitation => "unknown-citation",
+            ProblemKind::Compiler => "compiler",
+            ProblemKind::AmbiguousLink => "ambiguous-link",
+            ProblemKind::UnresolvedEmbed => "unresolved-embed",
+            ProblemKind::TruncatedNote => "truncated-note",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            ProblemKind::DuplicateLabel
+            | ProblemKind::RedirectedLink
+            | ProblemKind::UnknownCitation
+            | ProblemKind::AmbiguousLink
+            | ProblemKind::TruncatedNote => Severity::Warning,
+            ProblemKind::SharedLabel => Severity::Information,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// A problem found in the vault, positioned 1-based like the parser models.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// Other places involved in the problem, such as the other definitions of a label
+    #[serde(default)]
+    pub related: Vec<Location>,
+    /// The 1-based line and column the problem ends at, if it spans a range
+    #[serde(default)]
+    pub end: Option<(usize, usize)>,
+}
+
+/// A 1-based position in a note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Problem {
+    pub(crate) fn new(
+        kind: ProblemKind,
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    ) -> Self {
+        Self {
+            kind,
+            severity: kind.severity(),
+            file,
+            line,
+            column,
+            message,
+            related: Vec::new(),
+            end: None,
+        }
+    }
+}
+
+/// Broken wikilinks, links to missing labels or blocks, to a name several notes share or to
+/// notes that redirect elsewhere, duplicate or shared labels, missing images or other assets,
+/// unresolved merge conflicts, citations of keys missing from the note's bibliography, and
+/// notes too large to index whole across the indexed vault, ordered by file and position.
+pub fn check(index: &Index) -> Result<Vec<Problem>> {
+    collect(index, None)
+}
+
+/// The problems [`check`] finds in `file`, querying only its links, labels and citations.
+pub fn check_file(index: &Index, file: &Path) -> Result<Vec<Problem>> {
+    let mut problems = collect(index, Some(file))?;
+    // Repeated labels come with their definitions in other notes
+    problems.retain(|problem| problem.file == file);
+    Ok(problems)
+}
+
+fn collect(index: &Index, file: Option<&Path>) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+
+    for (file, link) in index.get_broken_links(file)? {
+        let message = format!("No note or file matches `{}`", link.target);
+        problems.push(Problem::new(
+            ProblemKind::BrokenLink,
+            file,
+            link.line,
+            link.column,
+            message,
+        ));
+    }
+
+    for (file, link) in index.get_missing_label_links(file)? {
+        let label = link.label.unwrap_or_default();
+        let message = format!("Label `{label}` not found in `{}`", link.target);
+        problems.push(Problem::new(
+            ProblemKind::MissingLabel,
+            file,
+            link.line,
+            link.column,
+            message,
+        ));
+    }
+
+    for (file, link) in index.get_missing_block_links(file)? {
+        let block = link.block.unwrap_or_default();
+        let message = format!("Block `^{block}` not found in `{}`", link.target);
+        problems.push(Problem::new(
+            ProblemKind::MissingBlock,
+            file,
+            link.line,
+            link.column,
+            message,
+        ));
+    }
+
+    for (file, link, target, count) in index.get_ambiguous_links(file)? {
+        let name = target
+            .strip_prefix(index.root())
+            .unwrap_or(&target)
+            .with_extension("");
+        let message = format!(
+            "`{}` matches {count} notes and links to `{}`; use a path to choose",
+            link.target,
+            name.display()
+        );
+        problems.push(Problem::new(
+            ProblemKind::AmbiguousLink,
+            file,
+            link.line,
+            link.column,
+            message,
+        ));
+    }
+
+    for (file, link, destination) in index.get_redirected_links(file)? {
+        let name = destination
+            .strip_prefix(index.root())
+            .unwrap_or(&destination)
+            .with_extension("");
+        let message = format!(
+            "`{}` redirects to `{}`; update the link",
+            link.target,
+            name.display()
+        );
+        problems.push(Problem::new(
+            ProblemKind::RedirectedLink,
+            file,
+            link.line,
+            link.column,
+            message,
+        ));
+    }
+
+    // A label defined twice in one note is invalid; one shared between notes only makes
+    // references to it depend on the note
+    let repeated = index.get_repeated_labels(file)?;
+    for definitions in repeated.chunk_by(|a, b| a.1.name == b.1.name) {
+        for (file, label) in definitions {
+            let related = definitions
+                .iter()
+                .filter(|(other_file, other)| (other_file, other) != (file, label))
+                .map(|(file, label)| Location {
+                    file: file.clone(),
+                    line: label.line,
+                    column: label.column,
+                })
+                .collect::<Vec<_>>();
+            let (kind, message) = if related.iter().any(|other| &other.file == file) {
+                (
+                    ProblemKind::DuplicateLabel,
+                    format!("Label `<{}>` is defined more than once", label.name),
+                )
+            } else {
+                (
+                    ProblemKind::SharedLabel,
+                    format!(
+                        "Label `<{}>` is also defined in {} other note(s)",
+                        label.name,
+                        related.len()
+                    ),
+                )
+            };
+            let mut problem = Problem::new(kind, file.clone(), label.line, label.column, message);
+            problem.related = related;
+            problems.push(problem);
+        }
+    }
+
+    for (file, attachment) in index.get_missing_attachments(file)? {
+        let message = format!("File `{}` does not exist", attachment.target);
+        problems.push(Problem::new(
+            ProblemKind::MissingAsset,
+            file,
+            attachment.line,
+            attachment.column,
+            message,
+        ));
+    }
+
+    // Links and labels inside a conflict may be indexed twice or half-written
+    for (file, conflict) in index.get_conflicts(file)? {
+        let message = format!(
+            "Unresolved merge conflict (lines {}-{}); keep one side and remove the markers",
+            conflict.line, conflict.end_line
+        );
+        problems.push(Problem::new(
+            ProblemKind::MergeConflict,
+            file,
+            conflict.line,
+            1,
+            message,
+        ));
+    }
+
+    for (file, citation) in index.get_unknown_citations(file)? {
+        let message = format!("No bibliography entry or label `{}`", citation.key);
+        problems.push(Problem::new(
+            ProblemKind::UnknownCitation,
+            file,
+            citation.line,
+            citation.column,
+            message,
+        ));
+    }
+
+    // Links and labels past the cap are missing, so the problems above may be incomplete too
+    if let Some(max) = index.max_index_rows()? {
+        for (file, dropped) in index.get_truncated_files(file)? {
+            let message = format!(
+                "Note too large to index whole: {dropped} link(s), label(s) or other items past \
+                 the first {max} of their kind were left out (see `max_index_rows`)"
+            );
+            problems.push(Problem::new(
+                ProblemKind::TruncatedNote,
+                file,
+                1,
+                1,
+                message,
+            ));
+        }
+    }
+
+    problems.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+    Ok(problems)
+}
+
+/// `problems` as configured: broken targets of `![[...]]` embeds become `UnresolvedEmbed`,
+/// problems below an `IGNORE_NEXT_LINE` comment are dropped, and `severity` overrides the
+/// default severities, dropping the ignored kinds. `read` returns the current text of a note.
+pub fn apply_rules(
+    problems: Vec<Problem>,
+    severity: &SeverityConfig,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Vec<Problem> {
+    let mut texts: HashMap<PathBuf, Option<String>> = HashMap::new();
+    for problem in &problems {
+        if !texts.contains_key(&problem.file) {
+            texts.insert(problem.file.clone(), read(&problem.file));
+        }
+    }
+    // Each note is split once, however many problems it has
+    let lines: HashMap<&Path, Vec<&str>> = texts
+        .iter()
+        .map(|(file, text)| {
+            let lines = text.as_deref().unwrap_or_default().lines().collect();
+            (file.as_path(), lines)
+        })
+        .collect();
+
+    let mut applied = Vec::new();
+    for mut problem in problems {
+        let lines = &lines[problem.file.as_path()];
+        let line = |number: usize| number.checked_sub(1).and_then(|i| lines.get(i).copied());
+
+        if matches!(
+            problem.kind,
+            ProblemKind::BrokenLink | ProblemKind::MissingLabel | ProblemKind::MissingBlock
+        ) && line(problem.line).is_some_and(|line| {
+            problem.column >= 2 && line.chars().nth(problem.column - 2) == Some('!')
+        }) {
+            problem.kind = ProblemKind::UnresolvedEmbed;
+            problem.severity = problem.kind.severity();
+        }
+        if line(problem.line.saturating_sub(1)).is_some_and(|line| ignores(line, problem.kind)) {
+            continue;
+        }
+
+        let rule = match problem.kind {
+            ProblemKind::BrokenLink => severity.broken_link,
+            ProblemKind::MissingLabel | ProblemKind::MissingBlock => severity.broken_label,
+            ProblemKind::AmbiguousLink => severity.ambiguous_link,
+            ProblemKind::UnresolvedEmbed => severity.unresolved_embed,
+            ProblemKind::MissingAsset => severity.missing_asset,
+            _ => None,
+        };
+        problem.severity = match rule {
+            None => problem.severity,
+            Some(RuleSeverity::Error) => Severity::Error,
+            Some(RuleSeverity::Warning) => Severity::Warning,
+            Some(RuleSeverity::Info) => Severity::Information,
+            Some(RuleSeverity::Ignore) => continue,
+        };
+        applied.push(problem);
+    }
+    applied
+}
+
+// Whether `line` is an `IGNORE_NEXT_LINE` comment covering `kind`
+fn ignores(line: &str, kind: ProblemKind) -> bool {
+    let Some(kinds) = line.trim().strip_prefix(IGNORE_NEXT_LINE) else {
+        return false;
+    };
+    if !kinds.is_empty() && !kinds.starts_with(char::is_whitespace) {
+        return false;
+    }
+    let mut kinds = kinds
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .peekable();
+    kinds.peek().is_none() || kinds.any(|name| name == kind.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, add_note_with, vault};
+
+    #[test]
+    fn test_check() {
+        let (temp_dir, index) = vault(&[
+            (
+                "a.typ",
+                "[[missing]] [[b:nope]] [[b#^gone]] [[b:sec]] [[b#^kept]]",
+            ),
+            (
+                "b.typ",
+                "= B <sec>\nText ^kept\n<dup> <dup>\n<<<<<<< HEAD\n=======\n>>>>>>> x",
+            ),
+            (
+                "c.typ",
+                "#image(\"b.typ\")\n#image(\"gone.png\")\n= C <sec>",
+            ),
+        ]);
+        let root = temp_dir.path();
+
+        let problems = check(&index).unwrap();
+        let kinds: Vec<_> = problems.iter().map(|p| p.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ProblemKind::BrokenLink,
+                ProblemKind::MissingLabel,
+                ProblemKind::MissingBlock,
+                ProblemKind::SharedLabel,
+                ProblemKind::DuplicateLabel,
+                ProblemKind::DuplicateLabel,
+                ProblemKind::MergeConflict,
+                ProblemKind::MissingAsset,
+                ProblemKind::SharedLabel,
+            ]
+        );
+        assert_eq!(problems[0].file, root.join("a.typ"));
+        assert_eq!(problems[3].severity, Severity::Information);
+        assert_eq!(
+            problems[3].related,
+            vec![Location {
+                file: root.join("c.typ"),
+                line: 3,
+                column: 5,
+            }]
+        );
+        assert_eq!(problems[4].severity, Severity::Warning);
+        assert_eq!(problems[4].related.len(), 1);
+        assert_eq!((problems[6].line, problems[6].column), (4, 1));
+        assert_eq!(problems[6].severity, Severity::Error);
+        assert_eq!((problems[7].line, problems[7].column), (2, 8));
+
+        for name in ["a.typ", "b.typ", "c.typ", "gone.typ"] {
+            let file = root.join(name);
+            let in_file: Vec<_> = problems.iter().filter(|p| p.file == file).collect();
+            assert_eq!(
+                check_file(&index, &file)
+                    .unwrap()
+                    .iter()
+                    .collect::<Vec<_>>(),
+                in_file
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_citation() {
+        let (temp_dir, mut index) = vault(&[]);
+        std::fs::write(
+            temp_dir.path().join("refs.bib"),
+            "@book{known, title = {Known}}",
+        )
+        .unwrap();
+        add_note(
+            &mut index,
+            "a.typ",
+            "@known @unknown\n#bibliography(\"refs.bib\")",
+        );
+        index.refresh_bibliographies(None).unwrap();
+
+        let problems = check(&index).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, ProblemKind::UnknownCitation);
+        assert_eq!((problems[0].line, problems[0].column), (1, 8));
+        assert_eq!(
+            problems[0].message,
+            "No bibliography entry or label `unknown`"
+        );
+    }
+
+    #[test]
+    fn test_truncated_note() {
+        let (_temp_dir, mut index) = vault(&[]);
+        index.set_max_index_rows(2).unwrap();
+        let path = add_note(&mut index, "log.typ", "[[a]] [[b]] [[c]]\n<x> <y>");
+
+        assert_eq!(index.get_forward_links(&path).unwrap().len(), 2);
+        assert_eq!(
+            index.get_truncated_files(None).unwrap(),
+            vec![(path.clone(), 1)]
+        );
+        let problems: Vec<_> = check(&index)
+            .unwrap()
+            .into_iter()
+            .filter(|problem| problem.kind == ProblemKind::TruncatedNote)
+            .collect();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            (problems[0].line, problems[0].severity),
+            (1, Severity::Warning)
+        );
+
+        // Lifting the cap marks the note for re-parsing, which indexes it whole
+        index.set_max_index_rows(0).unwrap();
+        assert_eq!(index.get_content_hash(&path).unwrap(), None);
+        add_note(&mut index, "log.typ", "[[a]] [[b]] [[c]]\n<x> <y>");
+        assert_eq!(index.get_forward_links(&path).unwrap().len(), 3);
+        assert!(index.get_truncated_files(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_redirected_link() {
+        let (_temp_dir, mut index) = vault(&[("new.typ", ""), ("a.typ", "See [[old]].")]);
+        add_note_with(&mut index, "old.typ", "", |parsed| {
+            parsed
+                .metadata
+                .custom
+                .insert("redirect".to_string(), "new".into());
+        });
+
+        let problems = check(&index).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, ProblemKind::RedirectedLink);
+        assert_eq!(problems[0].severity, Severity::Warning);
+        assert_eq!(
+            problems[0].message,
+            "`old` redirects to `new`; update the link"
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_link() {
+        let (_temp_dir, index) = vault(&[
+            ("x/idea.typ", ""),
+            ("y/idea.typ", ""),
+            ("a.typ", "[[idea]] [[y/idea]]"),
+        ]);
+
+        let problems = check(&index).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, ProblemKind::AmbiguousLink);
+        assert_eq!(problems[0].severity, Severity::Warning);
+        assert_eq!(
+            problems[0].message,
+            "`idea` matches 2 notes and links to `x/idea`; use a path to choose"
+        );
+
+        // Names differing only in case are shared once links ignore case
+        let (_temp_dir, index) = vault(&[
+            ("x/Plan.typ", ""),
+            ("y/plan.typ", ""),
+            ("a.typ", "[[plan]]"),
+        ]);
+        assert!(check(&index).unwrap().is_empty());
+        index.set_case_insensitive_links(true).unwrap();
+        let problems = check(&index).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.starts_with("`plan` matches 2 notes"));
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let file = PathBuf::from("a.typ");
+        let text = "![[gone]]\n// pkm-ignore-next-line\n[[gone]]\n\
+                    // pkm-ignore-next-line missing-asset\n[[gone]] #image(\"x.png\")\n[[b:nope]]";
+        let problem = |kind: ProblemKind, line: usize, column: usize| {
+            Problem::new(kind, file.clone(), line, column, String::new())
+        };
+        let problems = vec![
+            problem(ProblemKind::BrokenLink, 1, 2),
+            problem(ProblemKind::BrokenLink, 3, 1),
+            problem(ProblemKind::BrokenLink, 5, 1),
+            problem(ProblemKind::MissingAsset, 5, 16),
+            problem(ProblemKind::MissingLabel, 6, 1),
+        ];
+        let severity = SeverityConfig {
+            broken_label: Some(RuleSeverity::Ignore),
+            broken_link: Some(RuleSeverity::Warning),
+            ..SeverityConfig::default()
+        };
+
+        let applied = apply_rules(problems, &severity, |_| Some(text.to_string()));
+        let kinds: Vec<_> = applied
+            .iter()
+            .map(|problem| (problem.kind, problem.line, problem.severity))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ProblemKind::UnresolvedEmbed, 1, Severity::Error),
+                (ProblemKind::BrokenLink, 5, Severity::Warning),
+            ]
+        );
+    }
+}
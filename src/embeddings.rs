@@ -0,0 +1,422 @@
+use crate::config::{EmbeddingBackend, EmbeddingsConfig};
+use crate::index::{IndexHandle, StoredSection, content_hash};
+use crate::parser::headings::heading_text;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use ts_rs::TS;
+
+// Longer sections are truncated before embedding
+const MAX_SECTION_CHARS: usize = 2000;
+// Sections sent to the backend per call
+const BATCH_SIZE: usize = 64;
+// How long one call to an HTTP backend may take, including a slow model's first load
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Turns text into vectors. Implementations must return one vector per input text.
+pub trait Embedder {
+    /// Identifies the model, so vectors from different models are never compared.
+    fn model(&self) -> String;
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+pub fn embedder(config: &EmbeddingsConfig) -> Result<Box<dyn Embedder>> {
+    Ok(match config.backend {
+        EmbeddingBackend::Hashing => Box::new(HashingEmbedder {
+            dimensions: config.dimensions.max(1),
+        }),
+        EmbeddingBackend::Http => Box::new(HttpEmbedder::new(
+            config
+                .endpoint
+                .clone()
+                .context("The http embedding backend needs an endpoint")?,
+            config.model.clone().unwrap_or_default(),
+        )),
+    })
+}
+
+/// Signed feature hashing of lowercased words: no model, but similar wording gives
+/// similar vectors.
+pub struct HashingEmbedder {
+    pub dimensions: usize,
+}
+
+impl Embedder for HashingEmbedder {
+    fn model(&self) -> String {
+        format!("hashing-{}", self.dimensions)
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let mut vector = vec![0.0; self.dimensions];
+                for word in text
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|word| !word.is_empty())
+                {
+                    let hash = xxhash_rust::xxh3::xxh3_64(word.to_lowercase().as_bytes());
+                    let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+                    vector[(hash % self.dimensions as u64) as usize] += sign;
+                }
+                vector
+            })
+            .collect())
+    }
+}
+
+/// An OpenAI-compatible embeddings endpoint (e.g. a local Ollama or llama.cpp server).
+pub struct HttpEmbedder {
+    pub endpoint: String,
+    pub model: String,
+    agent: ureq::Agent,
+}
+
+impl HttpEmbedder {
+    pub fn new(endpoint: String, model: String) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .build()
+            .into();
+        Self {
+            endpoint,
+            model,
+            agent,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsReply {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+impl Embedder for HttpEmbedder {
+    fn model(&self) -> String {
+        format!("http:{}:{}", self.endpoint, self.model)
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let reply: EmbeddingsReply = self
+            .agent
+            .post(&self.endpoint)
+            .send_json(serde_json::json!({ "model": self.model, "input": texts }))
+            .context("Embedding request failed")?
+            .body_mut()
+            .read_json()
+            .context("Invalid embeddings response")?;
+        if reply.data.len() != texts.len() {
+            bail!(
+                "Expected {} embeddings, got {}",
+                texts.len(),
+                reply.data.len()
+            );
+        }
+        Ok(reply.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+/// A run of lines under one heading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub heading: Option<String>,
+    /// 1-based line of the heading, or of the note's first line
+    pub line: usize,
+    pub content: String,
+}
+
+/// Split a note at its headings, dropping sections with no text.
+pub fn chunk_sections(content: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut current = Section {
+        heading: None,
+        line: 1,
+        content: String::new(),
+    };
+    for (line_idx, line) in content.lines().enumerate() {
+        if let Some(heading) = heading_text(line) {
+            sections.push(current);
+            current = Section {
+                heading: Some(heading),
+                line: line_idx + 1,
+                content: String::new(),
+            };
+        }
+        current.content.push_str(line);
+        current.content.push('\n');
+    }
+    sections.push(current);
+
+    sections.retain(|section| {
+        let heading_lines = usize::from(section.heading.is_some());
+        section
+            .content
+            .lines()
+            .skip(heading_lines)
+            .any(|line| !line.trim().is_empty())
+    });
+    for section in &mut sections {
+        if let Some((end, _)) = section.content.char_indices().nth(MAX_SECTION_CHARS) {
+            section.content.truncate(end);
+        }
+    }
+    sections
+}
+
+/// Embed the sections of every note whose content changed since it was last embedded,
+/// returning how many notes were embedded. Only sections whose text is new are sent to
+/// `embedder`, and the index is locked only to look up and store vectors, never while
+/// embedding, so edits and reindexing carry on meanwhile.
+pub fn update(index: &IndexHandle, embedder: &dyn Embedder) -> Result<usize> {
+    let model = embedder.model();
+    let stale = index.read(|index| {
+        index
+            .stale_embeddings(&model)?
+            .into_iter()
+            .map(|(path, hash)| {
+                let known = index.file_embeddings(&path, &model)?;
+                Ok((path, hash, known))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut embedded = 0;
+    for (path, hash, known) in stale {
+        let content = std::fs::read(&path).unwrap_or_default();
+        // Changed on disk since it was indexed, so it is embedded after the next reindex
+        if content_hash(&content) != hash {
+            continue;
+        }
+        let sections = embed_sections(embedder, &path, &String::from_utf8_lossy(&content), known)?;
+        if index.write(|index| index.store_embeddings(&path, &hash, &model, &sections))? {
+            embedded += 1;
+        }
+    }
+    Ok(embedded)
+}
+
+// The sections of `content` with their vectors, reusing those in `known` for sections whose
+// text is unchanged
+fn embed_sections(
+    embedder: &dyn Embedder,
+    path: &Path,
+    content: &str,
+    known: Vec<StoredSection>,
+) -> Result<Vec<StoredSection>> {
+    let mut known: HashMap<String, Vec<f32>> = known
+        .into_iter()
+        .map(|section| (section.content, section.vector))
+        .collect();
+    let sections = chunk_sections(content);
+    let new: Vec<String> = sections
+        .iter()
+        .filter(|section| !known.contains_key(&section.content))
+        .map(|section| section.content.clone())
+        .collect();
+    for batch in new.chunks(BATCH_SIZE) {
+        let vectors = embedder.embed(batch)?;
+        known.extend(batch.iter().cloned().zip(vectors));
+    }
+
+    Ok(sections
+        .into_iter()
+        .map(|section| StoredSection {
+            path: path.to_path_buf(),
+            vector: known.get(&section.content).cloned().unwrap_or_default(),
+            heading: section.heading,
+            line: section.line,
+            content: section.content,
+        })
+        .collect())
+}
+
+/// A section ranked by cosine similarity to a query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SectionMatch {
+    pub path: PathBuf,
+    pub heading: Option<String>,
+    pub line: usize,
+    pub score: f32,
+    pub content: String,
+}
+
+/// The `limit` sections most similar to `query`, by brute-force cosine similarity. The query
+/// is embedded before the index is read.
+pub fn search(
+    index: &IndexHandle,
+    embedder: &dyn Embedder,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SectionMatch>> {
+    let query_vector = embedder
+        .embed(&[query.to_string()])?
+        .pop()
+        .context("Embedder returned no vector")?;
+
+    let mut matches: Vec<SectionMatch> = index
+        .read(|index| index.get_embeddings(&embedder.model()))?
+        .into_iter()
+        .map(|section| SectionMatch {
+            score: cosine(&query_vector, &section.vector),
+            path: section.path,
+            heading: section.heading,
+            line: section.line,
+            content: section.content,
+        })
+        .filter(|m| m.score > 0.0)
+        .collect();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(limit);
+    Ok(matches)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, vault};
+
+    #[test]
+    fn test_chunk_sections() {
+        let sections = chunk_sections("#meta()\n\n= Intro\ntext\n== Empty\n\n= Next\nmore\n");
+        let headings: Vec<_> = sections.iter().map(|s| s.heading.as_deref()).collect();
+        assert_eq!(headings, vec![None, Some("Intro"), Some("Next")]);
+        assert_eq!(sections[1].line, 3);
+        assert_eq!(sections[1].content, "= Intro\ntext\n");
+    }
+
+    #[test]
+    fn test_semantic_search() {
+        let (temp_dir, _) = vault(&[
+            (
+                "garden.typ",
+                "= Roses\nPrune roses in early spring.\n= Soil\nCompost feeds the soil.\n",
+            ),
+            (
+                "code.typ",
+                "= Borrowing\nThe borrow checker enforces ownership.\n",
+            ),
+        ]);
+        let root = temp_dir.path();
+        let index = IndexHandle::open(root, &root.join(".pkm-cache.db")).unwrap();
+
+        let embedder = CountingEmbedder::default();
+        assert_eq!(update(&index, &embedder).unwrap(), 2);
+        assert_eq!(embedder.take(), 3);
+        assert_eq!(update(&index, &embedder).unwrap(), 0);
+
+        let matches = search(&index, &embedder, "when to prune roses", 2).unwrap();
+        assert_eq!(matches[0].path, root.join("garden.typ"));
+        assert_eq!(matches[0].heading.as_deref(), Some("Roses"));
+
+        // Only the edited section is embedded again
+        index
+            .write(|index| {
+                add_note(
+                    index,
+                    "garden.typ",
+                    "= Roses\nPrune roses in early spring.\n= Soil\nMulch keeps it moist.\n",
+                );
+                Ok(())
+            })
+            .unwrap();
+        embedder.take();
+        assert_eq!(update(&index, &embedder).unwrap(), 1);
+        assert_eq!(embedder.take(), 1);
+
+        // A note edited on disk since it was indexed waits for the next reindex
+        index
+            .write(|index| {
+                add_note(index, "code.typ", "= Borrowing\nLifetimes.\n");
+                Ok(())
+            })
+            .unwrap();
+        std::fs::write(root.join("code.typ"), "= Borrowing\nMoves.\n").unwrap();
+        assert_eq!(update(&index, &embedder).unwrap(), 0);
+        assert_eq!(embedder.take(), 0);
+    }
+
+    #[test]
+    fn test_http_embedder() {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/embeddings", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let reply = r#"{"data":[{"embedding":[1.0,0.5]}]}"#;
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{reply}",
+                reply.len()
+            )
+            .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        });
+
+        let embedder = HttpEmbedder::new(endpoint, "nomic".to_string());
+        let vectors = embedder.embed(&["roses".to_string()]).unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.5]]);
+        assert_eq!(
+            server.join().unwrap(),
+            serde_json::json!({ "model": "nomic", "input": ["roses"] })
+        );
+    }
+
+    // Hashes words like `HashingEmbedder`, counting the texts it was asked to embed
+    #[derive(Default)]
+    struct CountingEmbedder(std::sync::atomic::AtomicUsize);
+
+    impl CountingEmbedder {
+        fn take(&self) -> usize {
+            self.0.swap(0, std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    impl Embedder for CountingEmbedder {
+        fn model(&self) -> String {
+            "counting".to_string()
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.0
+                .fetch_add(texts.len(), std::sync::atomic::Ordering::Relaxed);
+            HashingEmbedder { dimensions: 128 }.embed(texts)
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::diagnostics;
+use crate::index::{Changes, Index};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use ts_rs::TS;
+
+/// A change pushed to subscribed clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+#[ts(export)]
+pub enum Event {
+    /// A note was added or its content changed
+    FileIndexed {
+        path: PathBuf,
+    },
+    /// The resolved links out of these notes changed
+    LinkGraphChanged {
+        paths: Vec<PathBuf>,
+    },
+    DiagnosticsUpdated {
+        problem_count: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub enum EventKind {
+    FileIndexed,
+    LinkGraphChanged,
+    DiagnosticsUpdated,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::FileIndexed { .. } => EventKind::FileIndexed,
+            Event::LinkGraphChanged { .. } => EventKind::LinkGraphChanged,
+            Event::DiagnosticsUpdated { .. } => EventKind::DiagnosticsUpdated,
+        }
+    }
+}
+
+type Sink = Box<dyn FnMut(&Event) -> Result<()> + Send>;
+
+struct Subscriber {
+    id: usize,
+    kinds: HashSet<EventKind>,
+    sink: Sink,
+}
+
+/// Delivers events to subscribers. A subscriber whose sink fails is dropped.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+    next_id: usize,
+}
+
+impl EventBus {
+    /// Send events of `kinds` (every kind if empty) to `sink`, returning the subscription id.
+    pub fn subscribe(
+        &mut self,
+        kinds: impl IntoIterator<Item = EventKind>,
+        sink: impl FnMut(&Event) -> Result<()> + Send + 'static,
+    ) -> usize {
+        let mut kinds: HashSet<EventKind> = kinds.into_iter().collect();
+        if kinds.is_empty() {
+            kinds = HashSet::from([
+                EventKind::FileIndexed,
+                EventKind::LinkGraphChanged,
+                EventKind::DiagnosticsUpdated,
+            ]);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.push(Subscriber {
+            id,
+            kinds,
+            sink: Box::new(sink),
+        });
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: usize) {
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.subscribers.iter().any(|s| s.kinds.contains(&kind))
+    }
+
+    pub fn publish(&mut self, event: &Event) {
+        self.subscribers.retain_mut(|subscriber| {
+            !subscriber.kinds.contains(&event.kind()) || (subscriber.sink)(event).is_ok()
+        });
+    }
+
+    /// Run `update` and publish the events it caused, as recorded by the index while it
+    /// writes. Changes are only recorded while someone is subscribed.
+    pub fn track<T>(
+        &mut self,
+        index: &mut Index,
+        update: impl FnOnce(&mut Index) -> Result<T>,
+    ) -> Result<T> {
+        if self.is_empty() {
+            return update(index);
+        }
+
+        let (result, changes) = index.track_changes(update)?;
+        let mut events = events(changes);
+        if !events.is_empty() && self.wants(EventKind::DiagnosticsUpdated) {
+            let problem_count = diagnostics::check(index)?.len();
+            events.push(Event::DiagnosticsUpdated { problem_count });
+        }
+        for event in &events {
+            self.publish(event);
+        }
+        Ok(result)
+    }
+}
+
+// A `FileIndexed` event per stored note, then one `LinkGraphChanged` for all relinked notes
+fn events(changes: Changes) -> Vec<Event> {
+    let mut events: Vec<Event> = changes
+        .indexed
+        .into_iter()
+        .map(|path| Event::FileIndexed { path })
+        .collect();
+    if !changes.relinked.is_empty() {
+        events.push(Event::LinkGraphChanged {
+            paths: changes.relinked,
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, vault};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_track_publishes_changes() {
+        let (temp_dir, mut index) = vault(&[("target.typ", "")]);
+        let root = temp_dir.path();
+        let store = |index: &mut Index, name: &str, content: &str| {
+            add_note(index, name, content);
+            Ok(())
+        };
+
+        let mut bus = EventBus::default();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        bus.subscribe([], move |event| {
+            sink.lock().unwrap().push(event.clone());
+            Ok(())
+        });
+        let failing = bus.subscribe([EventKind::FileIndexed], |_| anyhow::bail!("closed"));
+
+        bus.track(&mut index, |index| store(index, "source.typ", "[[target]]"))
+            .unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                Event::FileIndexed {
+                    path: root.join("source.typ")
+                },
+                Event::LinkGraphChanged {
+                    paths: vec![root.join("source.typ")]
+                },
+                Event::DiagnosticsUpdated { problem_count: 0 },
+            ]
+        );
+        assert_eq!(bus.subscribers.len(), 1);
+        bus.unsubscribe(failing);
+
+        received.lock().unwrap().clear();
+        bus.track(&mut index, |index| store(index, "source.typ", "[[target]]"))
+            .unwrap();
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
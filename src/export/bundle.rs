@@ -0,0 +1,468 @@
+use crate::frontmatter::MetaBlock;
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::headings::heading_level;
+use crate::parser::models::{Label, LabelKind, Wikilink};
+use crate::parser::names::slug;
+use crate::publish::escape_markup;
+use crate::refactor::move_file::{asset_paths, include_paths, include_regex, rewrite_path};
+use crate::refactor::{apply_edits, relative_path};
+use anyhow::{Context, Result};
+use lsp_types::{Position, Range, TextEdit};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// What a bundle adds to its note.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[serde(default)]
+#[ts(export)]
+pub struct BundleOptions {
+    /// Append each `![[...]]` embed as an appendix, linked from where it was, instead of
+    /// inlining it
+    pub appendix: bool,
+    /// Append every note the note links to as well
+    pub linked_notes: bool,
+}
+
+/// A single Typst source holding `note` and the content it embeds with `![[...]]`, plus with
+/// `linked_notes` the notes it links to as appendices. Labels of other notes are prefixed so
+/// they can't clash, wikilinks to notes in the bundle become `#link`s, other wikilinks become
+/// their text, and relative paths are rewritten to resolve from `note`'s folder. `read`
+/// returns the current text of a note.
+pub fn bundle(
+    index: &Index,
+    parser: &Parser,
+    note: &Path,
+    options: &BundleOptions,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<String> {
+    let content = read(note).with_context(|| format!("Cannot read {}", note.display()))?;
+    let relative = note
+        .strip_prefix(index.root())
+        .with_context(|| format!("{} is not in the vault", note.display()))?;
+    let mut bundler = Bundler {
+        index,
+        parser,
+        options,
+        read: &read,
+        root_dir: relative.parent().unwrap_or(Path::new("")).to_path_buf(),
+        notes: HashMap::new(),
+        appendices: Vec::new(),
+        embeds: 0,
+    };
+
+    // Every note in the bundle is known before rendering, so links between them resolve
+    bundler.add_note(note, String::new(), &content)?;
+    let mut linked: Vec<(PathBuf, String)> = Vec::new();
+    if options.linked_notes {
+        for link in parser.parse_content(&content, note)?.wikilinks {
+            let Some(target) = index.resolve_target(&link.target)? else {
+                continue;
+            };
+            if !is_note(&target) || bundler.notes.contains_key(&target) {
+                continue;
+            }
+            let Some(text) = read(&target) else {
+                continue;
+            };
+            let prefix = slug(&relative_path(
+                Path::new(""),
+                &target
+                    .strip_prefix(index.root())
+                    .unwrap_or(&target)
+                    .with_extension(""),
+            ));
+            bundler.add_note(&target, prefix, &text)?;
+            linked.push((target, text));
+        }
+    }
+
+    let mut out = bundler.render(note, &content, "", &mut vec![note.to_path_buf()])?;
+    for (target, text) in linked {
+        let prefix = bundler.notes[&target].prefix.clone();
+        let mut visiting = vec![note.to_path_buf(), target.clone()];
+        let body = bundler.render(&target, &text, &prefix, &mut visiting)?;
+        let title = bundler.title(&target)?;
+        bundler.appendices.push(appendix(&title, &prefix, &body));
+    }
+    for appendix in &bundler.appendices {
+        let separator = if out.ends_with('\n') { "\n" } else { "\n\n" };
+        out.push_str(separator);
+        out.push_str(appendix);
+    }
+    Ok(out)
+}
+
+// A note in the bundle as a whole: the prefix of its labels, empty for the bundled note, and
+// the labels links can jump to
+struct Anchors {
+    prefix: String,
+    labels: HashSet<String>,
+}
+
+struct Bundler<'a, R> {
+    index: &'a Index,
+    parser: &'a Parser,
+    options: &'a BundleOptions,
+    read: &'a R,
+    // The bundled note's folder, relative to the vault root
+    root_dir: PathBuf,
+    notes: HashMap<PathBuf, Anchors>,
+    appendices: Vec<String>,
+    embeds: usize,
+}
+
+impl<R: Fn(&Path) -> Option<String>> Bundler<'_, R> {
+    fn add_note(&mut self, note: &Path, prefix: String, content: &str) -> Result<()> {
+        let parsed = self.parser.parse_content(content, note)?;
+        // Only headings, figures and equations can be linked to
+        let mut labels: HashSet<String> = parsed
+            .labels
+            .iter()
+            .filter(|label| {
+                matches!(
+                    label.kind,
+                    LabelKind::Heading | LabelKind::Figure | LabelKind::Equation
+                )
+            })
+            .map(|label| label.name.clone())
+            .collect();
+        labels.extend(
+            slug_labels(&parsed.labels, &parsed.headings).map(|heading| heading.name.clone()),
+        );
+        self.notes
+            .insert(note.to_path_buf(), Anchors { prefix, labels });
+        Ok(())
+    }
+
+    /// `content` of `note` as it reads in the bundle, with its labels prefixed by `prefix`.
+    /// `visiting` holds the notes being embedded, so an embed cycle stops at a link.
+    fn render(
+        &mut self,
+        note: &Path,
+        content: &str,
+        prefix: &str,
+        visiting: &mut Vec<PathBuf>,
+    ) -> Result<String> {
+        let parsed = self.parser.parse_content(content, note)?;
+        let lines: Vec<&str> = content.lines().collect();
+        let mut edits = Vec::new();
+        let mut replace = |line: usize, start: usize, end: usize, text: String| {
+            edits.push(TextEdit::new(
+                Range::new(
+                    Position::new(line as u32, start as u32),
+                    Position::new(line as u32, end as u32),
+                ),
+                text,
+            ));
+        };
+
+        if !prefix.is_empty() {
+            for label in &parsed.labels {
+                let start = label.column - 1;
+                let end = start + label.name.chars().count() + 2;
+                replace(
+                    label.line - 1,
+                    start,
+                    end,
+                    format!("<{}>", prefixed(prefix, &label.name)),
+                );
+            }
+            let defined = |name: &str| {
+                parsed
+                    .labels
+                    .iter()
+                    .chain(&parsed.headings)
+                    .any(|label| label.name == name)
+            };
+            for reference in self.parser.label_references(content) {
+                if defined(&reference.name) {
+                    let start = reference.column - 1;
+                    let end = start + reference.name.chars().count() + 1;
+                    replace(
+                        reference.line - 1,
+                        start,
+                        end,
+                        format!("@{}", prefixed(prefix, &reference.name)),
+                    );
+                }
+            }
+        }
+
+        // Headings get their slug as a real label, so links to them can jump there
+        for heading in slug_labels(&parsed.labels, &parsed.headings) {
+            let end = lines[heading.line - 1].trim_end().chars().count();
+            replace(
+                heading.line - 1,
+                end,
+                end,
+                format!(" <{}>", prefixed(prefix, &heading.name)),
+            );
+        }
+
+        for (link, span) in self.parser.wikilink_spans(content) {
+            let line = link.line - 1;
+            let is_embed = span.start > 0 && lines[line].chars().nth(span.start - 1) == Some('!');
+            if !is_embed {
+                replace(line, span.start, span.end, self.render_link(&link)?);
+                continue;
+            }
+            // An embed that can't be shown, such as one of a note already being embedded,
+            // reads like a link
+            let text = match self.embed(&link, visiting)? {
+                Some(text) => text,
+                None => self.render_link(&link)?,
+            };
+            replace(line, span.start - 1, span.end, text);
+        }
+
+        let note_dir = note
+            .strip_prefix(self.index.root())
+            .ok()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        let mut literals = include_paths(&include_regex()?, content);
+        literals.extend(asset_paths(self.parser, note, content)?);
+        for literal in literals {
+            if let Some(path) = rewrite_path(&literal.path, note_dir, &self.root_dir, |_| None) {
+                replace(
+                    literal.line as usize,
+                    literal.start as usize,
+                    literal.end as usize,
+                    path,
+                );
+            }
+        }
+
+        // Other notes' metadata blocks are left out
+        if !prefix.is_empty()
+            && let Some(block) = MetaBlock::locate(content)
+        {
+            let line_of = |offset: usize| content[..offset].matches('\n').count();
+            let meta = line_of(block.open)..=line_of(block.close);
+            edits.retain(|edit| !meta.contains(&(edit.range.start.line as usize)));
+            for line in meta {
+                let end = lines.get(line).map_or(0, |line| line.chars().count());
+                edits.push(TextEdit::new(
+                    Range::new(
+                        Position::new(line as u32, 0),
+                        Position::new(line as u32, end as u32),
+                    ),
+                    String::new(),
+                ));
+            }
+        }
+
+        Ok(apply_edits(content, &edits))
+    }
+
+    // The text an embed is replaced by: its content in a block, or a link to its appendix
+    fn embed(&mut self, link: &Wikilink, visiting: &mut Vec<PathBuf>) -> Result<Option<String>> {
+        let Some(target) = self.index.resolve_target(&link.target)? else {
+            return Ok(None);
+        };
+        if !is_note(&target) || visiting.contains(&target) {
+            return Ok(None);
+        }
+        let Some(content) = (self.read)(&target) else {
+            return Ok(None);
+        };
+        let Some(selected) = self.select(&target, &content, link)? else {
+            return Ok(None);
+        };
+
+        self.embeds += 1;
+        let prefix = format!("embed-{}", self.embeds);
+        visiting.push(target.clone());
+        let body = self.render(&target, &selected, &prefix, visiting)?;
+        visiting.pop();
+
+        if self.options.appendix {
+            let title = match &link.alias {
+                Some(alias) => alias.clone(),
+                None => self.title(&target)?,
+            };
+            self.appendices.push(appendix(&title, &prefix, &body));
+            Ok(Some(format!(
+                "#link(<{prefix}>)[{}]",
+                escape_markup(&title)
+            )))
+        } else {
+            Ok(Some(format!("#block[\n{}\n]", body.trim_matches('\n'))))
+        }
+    }
+
+    // The part of `content` an embed shows: the block's line, the labeled heading's section or
+    // element, or the whole note
+    fn select(&self, target: &Path, content: &str, link: &Wikilink) -> Result<Option<String>> {
+        let lines: Vec<&str> = content.lines().collect();
+        let range = if let Some(block) = &link.block {
+            let Some(block) = self.index.get_block(target, block)? else {
+                return Ok(None);
+            };
+            block.line - 1..block.line
+        } else if let Some(label) = &link.label {
+            let Some(label) = self.index.get_label(target, label)? else {
+                return Ok(None);
+            };
+            match (label.kind, label.element) {
+                (LabelKind::Heading, Some(element)) => {
+                    let start = element.line - 1;
+                    let level = lines.get(start).and_then(|line| heading_level(line));
+                    let end = (start + 1..lines.len())
+                        .find(|&i| heading_level(lines[i]).is_some_and(|l| Some(l) <= level))
+                        .unwrap_or(lines.len());
+                    start..end
+                }
+                (_, Some(element)) => element.line - 1..element.end_line,
+                (_, None) => label.line - 1..label.line,
+            }
+        } else {
+            return Ok(Some(content.to_string()));
+        };
+        Ok(lines.get(range).map(|lines| lines.join("\n")))
+    }
+
+    // A `#link` to the note or label if it is in the bundle, else the link's text
+    fn render_link(&self, link: &Wikilink) -> Result<String> {
+        let Some(target) = self.index.resolve_target(&link.target)? else {
+            return Ok(escape_markup(link.alias.as_deref().unwrap_or(&link.target)));
+        };
+        let text = match &link.alias {
+            Some(alias) => alias.clone(),
+            None if is_note(&target) => self.title(&target)?,
+            None => link.target.clone(),
+        };
+        let Some(anchors) = self.notes.get(&target) else {
+            return Ok(escape_markup(&text));
+        };
+        let anchor = match link.label.as_deref() {
+            Some(label) if link.block.is_none() && anchors.labels.contains(label) => {
+                Some(prefixed(&anchors.prefix, label))
+            }
+            _ => (!anchors.prefix.is_empty()).then(|| anchors.prefix.clone()),
+        };
+        Ok(match anchor {
+            Some(anchor) => format!("#link(<{anchor}>)[{}]", escape_markup(&text)),
+            None => escape_markup(&text),
+        })
+    }
+
+    fn title(&self, note: &Path) -> Result<String> {
+        let title = self
+            .index
+            .get_file(note)?
+            .and_then(|file| file.metadata.title);
+        Ok(title.unwrap_or_else(|| {
+            note.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }))
+    }
+}
+
+fn is_note(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "typ")
+}
+
+fn prefixed(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}:{name}")
+    }
+}
+
+// The heading slugs that can become labels: those of headings without an explicit label,
+// unless an explicit label elsewhere has the same name
+fn slug_labels<'a>(labels: &'a [Label], headings: &'a [Label]) -> impl Iterator<Item = &'a Label> {
+    headings.iter().filter(|heading| {
+        !labels
+            .iter()
+            .any(|label| label.line == heading.line || label.name == heading.name)
+    })
+}
+
+// A section titled `title` holding `body` one heading level down
+fn appendix(title: &str, anchor: &str, body: &str) -> String {
+    let body: Vec<String> = body
+        .trim_matches('\n')
+        .lines()
+        .map(|line| match heading_level(line) {
+            Some(_) => {
+                let indent = line.len() - line.trim_start().len();
+                format!("{}={}", &line[..indent], &line[indent..])
+            }
+            None => line.to_string(),
+        })
+        .collect();
+    format!(
+        "= {} <{anchor}>\n\n{}\n",
+        escape_markup(title),
+        body.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    fn read(path: &Path) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    #[test]
+    fn test_bundle_inline() {
+        let (temp_dir, index) = vault(&[
+            ("main.typ", "= Main\n![[notes/idea:proof]]\nSee [[other]]."),
+            (
+                "notes/idea.typ",
+                "#meta(\n  title: \"Idea\",\n)\n= Proof <proof>\nBy @eq.\n$ x $ <eq>\n#image(\"plot.png\")\n= Next",
+            ),
+            ("other.typ", "= Other"),
+        ]);
+        let parser = Parser::new().unwrap();
+        let note = temp_dir.path().join("main.typ");
+
+        let source = bundle(&index, &parser, &note, &BundleOptions::default(), read).unwrap();
+        assert_eq!(
+            source,
+            "= Main <main>\n#block[\n= Proof <embed-1:proof>\nBy @embed-1:eq.\n$ x $ <embed-1:eq>\n\
+             #image(\"notes/plot.png\")\n]\nSee other."
+        );
+    }
+
+    #[test]
+    fn test_bundle_appendix() {
+        let (temp_dir, index) = vault(&[
+            (
+                "main.typ",
+                "= Main\n![[idea]]\nSee [[other:sec|the section]] and [[idea]].",
+            ),
+            ("idea.typ", "= Idea\n![[main]]"),
+            (
+                "other.typ",
+                "#meta(\n  tags: (\"a\",),\n)\n= Sec <sec>\nText",
+            ),
+        ]);
+        let parser = Parser::new().unwrap();
+        let note = temp_dir.path().join("main.typ");
+        let options = BundleOptions {
+            appendix: true,
+            linked_notes: true,
+        };
+
+        let source = bundle(&index, &parser, &note, &options, read).unwrap();
+        assert_eq!(
+            source,
+            "= Main <main>\n#link(<embed-1>)[idea]\n\
+             See #link(<other:sec>)[the section] and #link(<idea>)[idea].\n\n\
+             = idea <embed-1>\n\n== Idea <embed-1:idea>\nmain\n\n\
+             = idea <idea>\n\n== Idea <idea:idea>\nmain\n\n\
+             = other <other>\n\n== Sec <other:sec>\nText\n"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use crate::export::{link_destination, relative_path_between};
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::models::{Metadata, Wikilink};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// Keep `[[target#^anchor|alias]]` links for Obsidian and Logseq
+    Wikilink,
+    /// Rewrite links to relative `.md` paths for static site generators
+    Relative,
+}
+
+/// Converts notes from Typst markup to Markdown.
+///
+/// Headings, lists, strong/emphasis, `#link`, labels, and wikilinks are translated and the
+/// note's metadata becomes YAML front matter; any other markup is copied verbatim.
+pub struct MarkdownExporter<'a> {
+    index: &'a Index,
+    parser: &'a Parser,
+    style: LinkStyle,
+    // Notes being exported; relative links to anything else become plain text
+    notes: BTreeSet<PathBuf>,
+    patterns: Patterns,
+}
+
+struct Patterns {
+    heading: Regex,
+    numbered: Regex,
+    link_with_body: Regex,
+    link: Regex,
+    strong: Regex,
+    emph: Regex,
+    label: Regex,
+    block: Regex,
+}
+
+impl<'a> MarkdownExporter<'a> {
+    pub fn new(
+        index: &'a Index,
+        parser: &'a Parser,
+        style: LinkStyle,
+        notes: impl IntoIterator<Item = PathBuf>,
+    ) -> Result<Self> {
+        Ok(Self {
+            index,
+            parser,
+            style,
+            notes: notes.into_iter().collect(),
+            patterns: Patterns {
+                heading: Regex::new(r"^(=+)\s")?,
+                numbered: Regex::new(r"^(\s*)\+\s")?,
+                link_with_body: Regex::new(r#"#link\("([^"]*)"\)\[([^\]]*)\]"#)?,
+                link: Regex::new(r#"#link\("([^"]*)"\)"#)?,
+                strong: Regex::new(r"\*([^*\s][^*]*)\*")?,
+                emph: Regex::new(r"(^|\W)_([^_\s][^_]*)_(\W|$)")?,
+                label: Regex::new(r"\s*<([a-zA-Z0-9_:.-]+)>")?,
+                block: Regex::new(r"(?:^|\s)\^([a-zA-Z0-9-]+)\s*$")?,
+            },
+        })
+    }
+
+    /// Write every note to `out_dir` as `.md`, mirroring the vault layout and copying the
+    /// attachments they link to. Returns the number of notes written.
+    pub fn write(&self, out_dir: &Path) -> Result<usize> {
+        let root = self.index.root();
+        let mut attachments = HashSet::new();
+
+        for note in &self.notes {
+            let content = std::fs::read_to_string(note)
+                .with_context(|| format!("Failed to read {}", note.display()))?;
+            let relative = note.strip_prefix(root).unwrap_or(note);
+            let out_path = out_dir.join(relative).with_extension("md");
+            if let Some(parent) = out_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&out_path, self.convert(note, &content)?)?;
+
+            for link in self.index.get_forward_links(note)? {
+                if let Some(target) = self.index.resolve_target(&link.target)?
+                    && target.extension().is_some_and(|ext| ext != "typ")
+                {
+                    attachments.insert(target);
+                }
+            }
+        }
+
+        for attachment in attachments {
+            let relative = attachment.strip_prefix(root).unwrap_or(&attachment);
+            let out_path = out_dir.join(relative);
+            if let Some(parent) = out_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&attachment, &out_path)?;
+        }
+
+        Ok(self.notes.len())
+    }
+
+    /// Convert the Typst source of `note` to Markdown.
+    pub fn convert(&self, note: &Path, content: &str) -> Result<String> {
+        let mut out = String::new();
+        if let Some(file) = self.index.get_file(note)? {
+            out.push_str(&front_matter(&file.metadata));
+        }
+
+        let content = strip_meta(content);
+        let links = self.parser.wikilink_spans(&content);
+        let mut in_fence = false;
+        for (idx, line) in content.lines().enumerate() {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+            }
+            if in_fence || line.trim_start().starts_with("```") {
+                out.push_str(line);
+            } else {
+                let spans: Vec<_> = links
+                    .iter()
+                    .filter(|(link, _)| link.line == idx + 1)
+                    .collect();
+                out.push_str(&self.convert_line(note, line, &spans)?);
+            }
+            out.push('\n');
+        }
+
+        Ok(out)
+    }
+
+    fn convert_line(
+        &self,
+        note: &Path,
+        line: &str,
+        links: &[&(Wikilink, Range<usize>)],
+    ) -> Result<String> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut out = String::new();
+        let mut text = String::new();
+        let mut links = links.iter().peekable();
+        let mut pos = 0;
+
+        while pos < chars.len() {
+            if let Some((link, range)) = links.peek()
+                && range.start == pos
+            {
+                out.push_str(&self.convert_text(&std::mem::take(&mut text)));
+                out.push_str(&self.render_link(note, link)?);
+                pos = range.end;
+                links.next();
+                continue;
+            }
+
+            // Escapes, raw, and math are copied verbatim
+            let verbatim_end = match chars[pos] {
+                '\\' => Some((pos + 2).min(chars.len())),
+                '`' | '$' => chars[pos + 1..]
+                    .iter()
+                    .position(|&c| c == chars[pos])
+                    .map(|offset| pos + offset + 2),
+                _ => None,
+            };
+            match verbatim_end {
+                Some(end) => {
+                    out.push_str(&self.convert_text(&std::mem::take(&mut text)));
+                    out.extend(&chars[pos..end]);
+                    pos = end;
+                }
+                None => {
+                    text.push(chars[pos]);
+                    pos += 1;
+                }
+            }
+        }
+        out.push_str(&self.convert_text(&text));
+
+        let patterns = &self.patterns;
+        let out = patterns
+            .heading
+            .replace(&out, |caps: &regex::Captures| {
+                format!("{} ", "#".repeat(caps[1].len()))
+            })
+            .into_owned();
+        let out = patterns.numbered.replace(&out, "${1}1. ").into_owned();
+        let out = patterns
+            .label
+            .replace_all(&out, |caps: &regex::Captures| self.anchor(&caps[1]))
+            .into_owned();
+        Ok(match self.style {
+            LinkStyle::Wikilink => out,
+            LinkStyle::Relative => patterns
+                .block
+                .replace(&out, |caps: &regex::Captures| {
+                    let marker = caps[0].trim_start();
+                    let space = &caps[0][..caps[0].len() - marker.len()];
+                    format!("{space}<a id=\"{}\"></a>", &caps[1])
+                })
+                .into_owned(),
+        })
+    }
+
+    // Inline markup in text outside links, raw, and math
+    fn convert_text(&self, text: &str) -> String {
+        let patterns = &self.patterns;
+        let text = patterns.link_with_body.replace_all(text, "[$2]($1)");
+        let text = patterns.link.replace_all(&text, "<$1>");
+        let text = patterns.strong.replace_all(&text, "**$1**");
+        let text = patterns.emph.replace_all(&text, "$1*$2*$3");
+        text.into_owned()
+    }
+
+    // Labels become Obsidian block ids or HTML anchors, matching how links point at them
+    fn anchor(&self, label: &str) -> String {
+        let id = anchor_id(label);
+        match self.style {
+            LinkStyle::Wikilink => format!(" ^{id}"),
+            LinkStyle::Relative => format!(" <a id=\"{id}\"></a>"),
+        }
+    }
+
+    fn render_link(&self, note: &Path, link: &Wikilink) -> Result<String> {
+        let anchor = link
+            .block
+            .as_deref()
+            .or(link.label.as_deref())
+            .map(anchor_id);
+
+        if self.style == LinkStyle::Wikilink {
+            let mut out = format!("[[{}", link.target);
+            if let Some(anchor) = anchor {
+                out.push_str(&format!("#^{anchor}"));
+            }
+            if let Some(alias) = &link.alias {
+                out.push_str(&format!("|{alias}"));
+            }
+            out.push_str("]]");
+            return Ok(out);
+        }
+
+        let target = self.index.resolve_target(&link.target)?;
+        let title = match &target {
+            Some(target) => self
+                .index
+                .get_file(target)?
+                .and_then(|file| file.metadata.title),
+            None => None,
+        };
+        let text = link.alias.clone().or(title).unwrap_or(link.target.clone());
+        let from_dir = note.parent().unwrap_or(note);
+
+        Ok(match target {
+            Some(target) if target.extension().is_some_and(|ext| ext != "typ") => {
+                let destination = relative_path_between(from_dir, &target);
+                format!("[{text}]({})", link_destination(&destination))
+            }
+            Some(target) if self.notes.contains(&target) => {
+                let destination = relative_path_between(from_dir, &target.with_extension("md"));
+                let fragment = anchor.map(|a| format!("#{a}")).unwrap_or_default();
+                format!("[{text}]({}{fragment})", link_destination(&destination))
+            }
+            _ => text,
+        })
+    }
+}
+
+// Markdown anchors and block ids only allow letters, digits, and `-`
+fn anchor_id(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn front_matter(metadata: &Metadata) -> String {
+    // JSON scalars and arrays are valid YAML flow values
+    let mut fields = Vec::new();
+    if let Some(title) = &metadata.title {
+        fields.push(format!(
+            "title: {}",
+            serde_json::Value::from(title.as_str())
+        ));
+    }
+    if !metadata.tags.is_empty() {
+        fields.push(format!("tags: {}", serde_json::json!(metadata.tags)));
+    }
+    if !metadata.alias.is_empty() {
+        fields.push(format!("aliases: {}", serde_json::json!(metadata.alias)));
+    }
+    let mut custom: Vec<_> = metadata.custom.iter().collect();
+    custom.sort_by_key(|(key, _)| key.as_str());
+    for (key, value) in custom {
+        fields.push(format!("{key}: {value}"));
+    }
+
+    if fields.is_empty() {
+        return String::new();
+    }
+    format!("---\n{}\n---\n\n", fields.join("\n"))
+}
+
+/// Remove `#meta(...)` calls that start a line, since they become front matter.
+fn strip_meta(content: &str) -> String {
+    let mut out = String::new();
+    let mut rest = content;
+
+    while let Some(start) = rest
+        .match_indices("#meta(")
+        .map(|(idx, _)| idx)
+        .find(|&idx| idx == 0 || rest[..idx].ends_with('\n'))
+    {
+        out.push_str(&rest[..start]);
+        let body = &rest[start + "#meta(".len()..];
+        let mut depth = 1;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut end = body.len();
+        for (idx, c) in body.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '(' if !in_string => depth += 1,
+                ')' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = idx + 1;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let after = &body[end..];
+        rest = match after.find('\n') {
+            Some(newline) => &after[newline + 1..],
+            None => "",
+        };
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, vault};
+
+    const NOTE: &str = "#meta(
+  title: \"Idea (draft)\",
+)
+= Heading <intro>
+Some *bold* and _emph_ text with `*raw*` and $a_1 * b$.
++ first
+- [[other:sec|see other]] and #link(\"https://typst.app\")[Typst]
+A claim. ^claim
+";
+
+    fn setup() -> (tempfile::TempDir, Index, Parser) {
+        let (temp_dir, mut index) = vault(&[("sub/other.typ", "<sec>")]);
+        add_note(&mut index, "note.typ", NOTE);
+        (temp_dir, index, Parser::new().unwrap())
+    }
+
+    #[test]
+    fn test_convert_with_wikilinks() {
+        let (temp_dir, index, parser) = setup();
+        let note = temp_dir.path().join("note.typ");
+        let exporter =
+            MarkdownExporter::new(&index, &parser, LinkStyle::Wikilink, [note.clone()]).unwrap();
+
+        let markdown = exporter.convert(&note, NOTE).unwrap();
+        assert_eq!(
+            markdown,
+            "---
+title: \"Idea (draft)\"
+---
+
+# Heading ^intro
+Some **bold** and *emph* text with `*raw*` and $a_1 * b$.
+1. first
+- [[other#^sec|see other]] and [Typst](https://typst.app)
+A claim. ^claim
+"
+        );
+    }
+
+    #[test]
+    fn test_write_with_relative_links() {
+        let (temp_dir, index, parser) = setup();
+        let root = temp_dir.path();
+        let out_dir = root.join("out");
+        let notes = [root.join("note.typ"), root.join("sub").join("other.typ")];
+        let exporter = MarkdownExporter::new(&index, &parser, LinkStyle::Relative, notes).unwrap();
+
+        assert_eq!(exporter.write(&out_dir).unwrap(), 2);
+        let markdown = std::fs::read_to_string(out_dir.join("note.md")).unwrap();
+        assert!(markdown.contains("# Heading <a id=\"intro\"></a>"));
+        assert!(markdown.contains("- [see other](sub/other.md#sec) and"));
+        assert!(markdown.contains("A claim. <a id=\"claim\"></a>"));
+        assert!(out_dir.join("sub").join("other.md").exists());
+    }
+
+    #[test]
+    fn test_strip_meta() {
+        assert_eq!(strip_meta("#meta(a: \")\")\nbody"), "body");
+        assert_eq!(strip_meta("x #meta(a: 1)\n"), "x #meta(a: 1)\n");
+    }
+}
@@ -0,0 +1,52 @@
+pub mod bundle;
+pub mod markdown;
+
+use std::path::{Path, PathBuf};
+
+/// The path of `to` relative to the directory `from_dir`, e.g. `../b/note.md`.
+pub fn relative_path_between(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+// Links in Markdown always use `/`, and paths with spaces need angle brackets
+fn link_destination(path: &Path) -> String {
+    let path = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.contains(' ') {
+        format!("<{path}>")
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_path_between() {
+        assert_eq!(
+            relative_path_between(Path::new("/v/a"), Path::new("/v/b/note.md")),
+            PathBuf::from("../b/note.md")
+        );
+        assert_eq!(
+            relative_path_between(Path::new("/v"), Path::new("/v/note.md")),
+            PathBuf::from("note.md")
+        );
+        assert_eq!(link_destination(Path::new("a b/c.md")), "<a b/c.md>");
+    }
+}
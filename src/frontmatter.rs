@@ -0,0 +1,797 @@
+use anyhow::{Result, bail};
+use lsp_types::{Position, Range, TextEdit};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+/// One change to a note's metadata block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[serde(tag = "op", rename_all = "camelCase")]
+#[ts(export)]
+pub enum MetadataEdit {
+    Set {
+        key: String,
+        #[ts(type = "any")]
+        value: Value,
+    },
+    Remove {
+        key: String,
+    },
+    AddTag {
+        tag: String,
+    },
+    RemoveTag {
+        tag: String,
+    },
+    AddAlias {
+        alias: String,
+    },
+    RemoveAlias {
+        alias: String,
+    },
+}
+
+/// The `#meta(...)` or `#metadata((...))` call at the start of a line, by byte offsets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaBlock {
+    /// The `(` opening the entries
+    pub open: usize,
+    /// The matching `)`
+    pub close: usize,
+    pub entries: Vec<Entry>,
+}
+
+/// A `key: value` pair inside a metadata block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    /// From the key to the end of the value, including a trailing comma
+    pub span: (usize, usize),
+    pub value: (usize, usize),
+    pub has_comma: bool,
+}
+
+impl MetaBlock {
+    pub fn locate(content: &str) -> Option<Self> {
+        let mut offset = 0;
+        for line in content.split_inclusive('\n') {
+            let indent = line.len() - line.trim_start().len();
+            let start = offset + indent;
+            offset += line.len();
+            let call = &content[start..];
+            let open = if let Some(rest) = call.strip_prefix("#metadata(") {
+                // `#metadata` wraps a dictionary literal
+                let inner = rest.trim_start();
+                if !inner.starts_with('(') {
+                    continue;
+                }
+                content.len() - inner.len()
+            } else if call.starts_with("#meta(") {
+                start + "#meta".len()
+            } else {
+                continue;
+            };
+
+            let close = matching_paren(content, open)?;
+            let entries = parse_entries(content, open + 1, close);
+            return Some(MetaBlock {
+                open,
+                close,
+                entries,
+            });
+        }
+        None
+    }
+
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+}
+
+/// Minimal text edits applying `edits` to the metadata block in `content`, creating a
+/// `#meta(...)` block at the top of the note if there is none.
+pub fn metadata_edits(content: &str, edits: &[MetadataEdit]) -> Result<Vec<TextEdit>> {
+    let block = MetaBlock::locate(content);
+    let mut changes: Vec<(String, Option<Value>)> = Vec::new();
+
+    let current = |changes: &[(String, Option<Value>)], key: &str| -> Result<Option<Value>> {
+        if let Some((_, value)) = changes.iter().find(|(k, _)| k == key) {
+            return Ok(value.clone());
+        }
+        let Some(entry) = block.as_ref().and_then(|block| block.entry(key)) else {
+            return Ok(None);
+        };
+        match parse_literal(&content[entry.value.0..entry.value.1]) {
+            Some(value) => Ok(Some(value)),
+            None => bail!("`{key}` is not a literal value"),
+        }
+    };
+    let set =
+        |changes: &mut Vec<(String, Option<Value>)>, key: &str, value: Option<Value>| match changes
+            .iter_mut()
+            .find(|(k, _)| k == key)
+        {
+            Some(change) => change.1 = value,
+            None => changes.push((key.to_string(), value)),
+        };
+
+    for edit in edits {
+        let (key, item, add) = match edit {
+            MetadataEdit::Set { key, value } => {
+                set(&mut changes, key, Some(value.clone()));
+                continue;
+            }
+            MetadataEdit::Remove { key } => {
+                set(&mut changes, key, None);
+                continue;
+            }
+            MetadataEdit::AddTag { tag } => ("tags", tag, true),
+            MetadataEdit::RemoveTag { tag } => ("tags", tag, false),
+            MetadataEdit::AddAlias { alias } => ("alias", alias, true),
+            MetadataEdit::RemoveAlias { alias } => ("alias", alias, false),
+        };
+
+        let mut items = match current(&changes, key)? {
+            Some(Value::Array(items)) => items,
+            Some(Value::String(single)) => vec![Value::String(single)],
+            Some(Value::Null) | None => Vec::new(),
+            Some(_) => bail!("`{key}` is not a list"),
+        };
+        let item = Value::String(item.clone());
+        let present = items.contains(&item);
+        if add && !present {
+            items.push(item);
+        } else if !add && present {
+            items.retain(|existing| existing != &item);
+        } else {
+            continue;
+        }
+        set(&mut changes, key, Some(Value::Array(items)));
+    }
+
+    let Some(block) = block else {
+        let entries: Vec<String> = changes
+            .iter()
+            .filter_map(|(key, value)| {
+                Some(format!(
+                    "  {}: {},\n",
+                    key_text(key),
+                    to_typst(value.as_ref()?)
+                ))
+            })
+            .collect();
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+        return Ok(vec![TextEdit::new(
+            Range::new(Position::new(0, 0), Position::new(0, 0)),
+            format!("#meta(\n{})\n\n", entries.concat()),
+        )]);
+    };
+
+    let mut text_edits = Vec::new();
+    let mut inserts = Vec::new();
+    for (key, value) in &changes {
+        match (block.entry(key), value) {
+            (Some(entry), Some(value)) => {
+                let printed = to_typst(value);
+                if content[entry.value.0..entry.value.1] != printed {
+                    text_edits.push(edit(content, entry.value, printed));
+                }
+            }
+            (Some(entry), None) => {
+                text_edits.push(edit(content, removal_span(content, entry), String::new()))
+            }
+            (None, Some(value)) => inserts.push(format!("{}: {}", key_text(key), to_typst(value))),
+            (None, None) => {}
+        }
+    }
+
+    if !inserts.is_empty() {
+        let inner = &content[block.open + 1..block.close];
+        let last = block.entries.last();
+        let last_removed = last.is_some_and(|last| {
+            changes
+                .iter()
+                .any(|(key, value)| key == &last.key && value.is_none())
+        });
+        let close_line_start = content[..block.close].rfind('\n').map_or(0, |i| i + 1);
+        if inner.contains('\n') && content[close_line_start..block.close].trim().is_empty() {
+            if let Some(last) = last
+                && !last.has_comma
+                && !last_removed
+            {
+                text_edits.push(edit(content, (last.value.1, last.value.1), ",".to_string()));
+            }
+            let indent = last.map_or("  ", |entry| {
+                let line_start = content[..entry.span.0].rfind('\n').map_or(0, |i| i + 1);
+                &content[line_start..entry.span.0]
+            });
+            let lines: String = inserts
+                .iter()
+                .map(|insert| format!("{indent}{insert},\n"))
+                .collect();
+            text_edits.push(edit(content, (close_line_start, close_line_start), lines));
+        } else {
+            let trimmed = inner.trim_end();
+            let joined = inserts.join(", ");
+            if trimmed.trim() == ":" {
+                // The empty dictionary `(:)`
+                text_edits.push(edit(content, (block.open + 1, block.close), joined));
+            } else {
+                let at = block.open + 1 + trimmed.len();
+                // A removed last entry leaves the previous entry's comma in place
+                let separator = if trimmed.trim().is_empty() || last_removed {
+                    ""
+                } else if trimmed.ends_with(',') {
+                    " "
+                } else {
+                    ", "
+                };
+                text_edits.push(edit(content, (at, at), format!("{separator}{joined}")));
+            }
+        }
+    }
+
+    Ok(text_edits)
+}
+
+/// An entry's span, widened to whole lines when it is alone on its line(s).
+fn removal_span(content: &str, entry: &Entry) -> (usize, usize) {
+    let (start, end) = entry.span;
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[end..]
+        .find('\n')
+        .map_or(content.len(), |i| end + i + 1);
+    let before = &content[line_start..start];
+    let after = &content[end..line_end];
+    if before.trim().is_empty() && after.trim().is_empty() && after.ends_with('\n') {
+        (line_start, line_end)
+    } else {
+        let spaces = content[end..].len() - content[end..].trim_start_matches(' ').len();
+        (start, end + spaces)
+    }
+}
+
+fn edit(content: &str, (start, end): (usize, usize), new_text: String) -> TextEdit {
+    TextEdit::new(
+        Range::new(position(content, start), position(content, end)),
+        new_text,
+    )
+}
+
+/// The LSP position of byte `offset`, counting characters within the line.
+pub(crate) fn position(content: &str, offset: usize) -> Position {
+    let before = &content[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(line as u32, before[line_start..].chars().count() as u32)
+}
+
+/// The `)` matching the `(` at `open`, skipping strings and comments.
+fn matching_paren(content: &str, open: usize) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            b'"' => i = string_end(bytes, i)?,
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i += content[i..].find('\n').unwrap_or(content.len() - i);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += content[i..].find("*/")? + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The index of the quote closing the string opened at `start`.
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Split `content[start..end]` at top-level commas into `key: value` entries.
+fn parse_entries(content: &str, start: usize, end: usize) -> Vec<Entry> {
+    let bytes = content.as_bytes();
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut segment_start = start;
+    let mut i = start;
+    while i < end {
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b'"' => i = string_end(bytes, i).unwrap_or(end),
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i += content[i..end].find('\n').unwrap_or(end - i);
+            }
+            b',' if depth == 0 => {
+                segments.push((segment_start, i, true));
+                segment_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    segments.push((segment_start, end, false));
+
+    segments
+        .into_iter()
+        .filter_map(|(start, end, has_comma)| {
+            let text = &content[start..end];
+            let key_start = start + (text.len() - text.trim_start().len());
+            let colon = key_start + content[key_start..end].find(':')?;
+            let key = content[key_start..colon]
+                .trim()
+                .trim_matches('"')
+                .to_string();
+            if key.is_empty() {
+                return None;
+            }
+            let value_text = &content[colon + 1..end];
+            let value_start = colon + 1 + (value_text.len() - value_text.trim_start().len());
+            let value_end = colon + 1 + value_text.trim_end().len();
+            Some(Entry {
+                key,
+                span: (key_start, if has_comma { end + 1 } else { value_end }),
+                value: (value_start, value_end),
+                has_comma,
+            })
+        })
+        .collect()
+}
+
+/// What the cursor is on inside a metadata block, for completion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaCursor {
+    /// A key being typed, starting at this byte offset
+    Key { start: usize },
+    /// A string literal in the value of `key`, whose text starts at this byte offset
+    Value { key: String, start: usize },
+}
+
+/// What the cursor at byte `offset` is on inside the metadata block of `content`, if it is on
+/// a key or in a string of a value.
+pub fn cursor_at(content: &str, offset: usize) -> Option<MetaCursor> {
+    let block = MetaBlock::locate(content)?;
+    if !(block.open < offset && offset <= block.close) {
+        return None;
+    }
+
+    if let Some(entry) = block
+        .entries
+        .iter()
+        .find(|entry| entry.value.0 <= offset && offset <= entry.value.1)
+    {
+        let bytes = content.as_bytes();
+        let mut i = entry.value.0;
+        while i < offset {
+            if bytes[i] != b'"' {
+                i += 1;
+                continue;
+            }
+            match string_end(bytes, i) {
+                Some(close) if close < offset => i = close + 1,
+                _ => {
+                    return Some(MetaCursor::Value {
+                        key: entry.key.clone(),
+                        start: i + 1,
+                    });
+                }
+            }
+        }
+        return None;
+    }
+
+    let before = &content[block.open..offset];
+    let start = block.open
+        + before
+            .trim_end_matches(|c: char| c.is_alphanumeric() || matches!(c, '_' | '-'))
+            .len();
+    let preceding = content[block.open..start].trim_end();
+    (preceding.ends_with('(') || preceding.ends_with(',')).then_some(MetaCursor::Key { start })
+}
+
+/// The tag written at byte `offset` inside the `tags` entry of the metadata block, with the
+/// byte range of its string literal.
+pub fn tag_at(content: &str, offset: usize) -> Option<(String, (usize, usize))> {
+    let block = MetaBlock::locate(content)?;
+    let (start, end) = block.entries.iter().find(|e| e.key == "tags")?.value;
+    let bytes = content.as_bytes();
+    let mut i = start;
+    while i < end.min(offset + 1) {
+        if bytes[i] != b'"' {
+            i += 1;
+            continue;
+        }
+        let close = string_end(bytes, i)?;
+        if offset <= close + 1 {
+            let tag = parse_literal(&content[i..=close])?;
+            return Some((tag.as_str()?.to_string(), (i, close + 1)));
+        }
+        i = close + 1;
+    }
+    None
+}
+
+/// Parse a Typst literal (string, number, boolean, `none`, array or dictionary) as JSON.
+pub fn parse_literal(text: &str) -> Option<Value> {
+    let mut parser = LiteralParser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    (parser.pos == text.len()).then_some(value)
+}
+
+struct LiteralParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl LiteralParser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.text.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        if self.rest().starts_with('"') {
+            return self.string().map(Value::String);
+        }
+        if self.eat("(") {
+            return self.collection();
+        }
+
+        let word_len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '.' | '-' | '+' | '_')))
+            .unwrap_or(self.rest().len());
+        let word = &self.rest()[..word_len];
+        let value = match word {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "none" => Value::Null,
+            _ => match word.parse::<i64>() {
+                Ok(int) => int.into(),
+                Err(_) => serde_json::Number::from_f64(word.parse().ok()?)?.into(),
+            },
+        };
+        self.pos += word_len;
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let end = string_end(self.text.as_bytes(), self.pos)?;
+        let raw = &self.text[self.pos + 1..end];
+        self.pos = end + 1;
+
+        let mut text = String::new();
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next()? {
+                'n' => text.push('\n'),
+                't' => text.push('\t'),
+                'r' => text.push('\r'),
+                'u' => {
+                    let code: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                    text.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                c => text.push(c),
+            }
+        }
+        Some(text)
+    }
+
+    /// The rest of an array, dictionary, or parenthesized value after `(`.
+    fn collection(&mut self) -> Option<Value> {
+        if self.eat(")") {
+            return Some(Value::Array(Vec::new()));
+        }
+        if self.eat(":") {
+            return self.eat(")").then(|| Value::Object(Default::default()));
+        }
+
+        let mut items = Vec::new();
+        let mut object = serde_json::Map::new();
+        let mut trailing_comma = false;
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let key = if self.rest().starts_with('"') {
+                self.string()
+            } else {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-')))
+                    .unwrap_or(self.rest().len());
+                self.pos += len;
+                (len > 0).then(|| self.text[start..self.pos].to_string())
+            };
+            match key {
+                Some(key) if self.eat(":") => {
+                    object.insert(key, self.value()?);
+                }
+                _ => {
+                    self.pos = start;
+                    items.push(self.value()?);
+                }
+            }
+
+            if self.eat(")") {
+                break;
+            }
+            if !self.eat(",") {
+                return None;
+            }
+            trailing_comma = true;
+            if self.eat(")") {
+                break;
+            }
+            trailing_comma = false;
+        }
+
+        match (items.len(), object.is_empty()) {
+            (0, false) => Some(Value::Object(object)),
+            // `(x)` is just a parenthesized value
+            (1, true) if !trailing_comma => items.pop(),
+            (_, true) => Some(Value::Array(items)),
+            _ => None,
+        }
+    }
+}
+
+/// Print a JSON value as a Typst literal.
+pub fn to_typst(value: &Value) -> String {
+    match value {
+        Value::Null => "none".to_string(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => quote(text),
+        Value::Array(items) => match items.as_slice() {
+            [] => "()".to_string(),
+            [item] => format!("({},)", to_typst(item)),
+            items => format!(
+                "({})",
+                items.iter().map(to_typst).collect::<Vec<_>>().join(", ")
+            ),
+        },
+        Value::Object(map) if map.is_empty() => "(:)".to_string(),
+        Value::Object(map) => format!(
+            "({})",
+            map.iter()
+                .map(|(key, value)| format!("{}: {}", key_text(key), to_typst(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// `text` as a Typst string literal.
+pub(crate) fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Dictionary keys that aren't identifiers must be quoted.
+fn key_text(key: &str) -> String {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if is_identifier {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn apply(content: &str, edits: &[MetadataEdit]) -> String {
+        let offset = |position: Position| -> usize {
+            let line_start: usize = content
+                .split_inclusive('\n')
+                .take(position.line as usize)
+                .map(str::len)
+                .sum();
+            line_start
+                + content[line_start..]
+                    .chars()
+                    .take(position.character as usize)
+                    .map(char::len_utf8)
+                    .sum::<usize>()
+        };
+        let mut text_edits = metadata_edits(content, edits).unwrap();
+        text_edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        let mut result = content.to_string();
+        for edit in text_edits.iter().rev() {
+            result.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+        }
+        result
+    }
+
+    #[test]
+    fn test_parse_literal() {
+        assert_eq!(
+            parse_literal(r#"("a", "b\"c",)"#),
+            Some(json!(["a", "b\"c"]))
+        );
+        assert_eq!(parse_literal(r#"("é",)"#), Some(json!(["é"])));
+        assert_eq!(parse_literal("(1.5)"), Some(json!(1.5)));
+        assert_eq!(
+            parse_literal("(due: \"2024-01-01\", n: -2, ok: true, x: none)"),
+            Some(json!({ "due": "2024-01-01", "n": -2, "ok": true, "x": null }))
+        );
+        assert_eq!(parse_literal("(:)"), Some(json!({})));
+        assert_eq!(parse_literal("datetime.today()"), None);
+        assert_eq!(to_typst(&json!(["a"])), "(\"a\",)");
+        assert_eq!(to_typst(&json!({ "my key": 1 })), "(\"my key\": 1)");
+    }
+
+    #[test]
+    fn test_edit_multiline_block() {
+        let content = "#meta(\n  title: \"Old\",\n  tags: (\"a\",),\n  alias: (\"x\",),\n  status: draft\n)\n\nBody\n";
+        let result = apply(
+            content,
+            &[
+                MetadataEdit::Set {
+                    key: "title".into(),
+                    value: json!("New"),
+                },
+                MetadataEdit::AddTag { tag: "b".into() },
+                MetadataEdit::AddTag { tag: "a".into() },
+                MetadataEdit::Remove {
+                    key: "alias".into(),
+                },
+                MetadataEdit::Set {
+                    key: "priority".into(),
+                    value: json!(2),
+                },
+            ],
+        );
+        assert_eq!(
+            result,
+            "#meta(\n  title: \"New\",\n  tags: (\"a\", \"b\"),\n  status: draft,\n  priority: 2,\n)\n\nBody\n"
+        );
+
+        // Values that aren't literals can be replaced but not edited as lists
+        let edits = [MetadataEdit::Set {
+            key: "status".into(),
+            value: json!("done"),
+        }];
+        assert!(apply(content, &edits).contains("status: \"done\"\n"));
+        let computed = "#meta(tags: base-tags)\n";
+        assert!(metadata_edits(computed, &[MetadataEdit::AddTag { tag: "b".into() }]).is_err());
+    }
+
+    #[test]
+    fn test_edit_inline_and_missing_block() {
+        let inline = "#metadata((title: \"A\")) <meta>\n";
+        assert_eq!(
+            apply(inline, &[MetadataEdit::AddAlias { alias: "Ay".into() }]),
+            "#metadata((title: \"A\", alias: (\"Ay\",))) <meta>\n"
+        );
+        assert_eq!(
+            apply(
+                inline,
+                &[MetadataEdit::Remove {
+                    key: "title".into()
+                }]
+            ),
+            "#metadata(()) <meta>\n"
+        );
+        assert_eq!(
+            apply(
+                "#meta(a: 1, b: 2)\n",
+                &[
+                    MetadataEdit::Remove { key: "b".into() },
+                    MetadataEdit::Set {
+                        key: "c".into(),
+                        value: json!(3)
+                    },
+                ]
+            ),
+            "#meta(a: 1, c: 3)\n"
+        );
+
+        assert_eq!(
+            apply("Body\n", &[MetadataEdit::AddTag { tag: "new".into() }]),
+            "#meta(\n  tags: (\"new\",),\n)\n\nBody\n"
+        );
+        assert!(
+            metadata_edits("Body\n", &[MetadataEdit::RemoveTag { tag: "x".into() }])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tag_at() {
+        let content =
+            "#meta(\n  title: \"a, \\\"b\\\"\",\n  tags: (\"rust\", \"ideas\"),\n)\n\"rust\"";
+        let at = |needle: &str, skip: usize| content.find(needle).unwrap() + skip;
+        assert_eq!(
+            tag_at(content, at("\"ideas", 3)),
+            Some(("ideas".to_string(), (at("\"ideas", 0), at("\"ideas", 7))))
+        );
+        assert_eq!(tag_at(content, at("\"rust", 0)).unwrap().0, "rust");
+        assert_eq!(tag_at(content, at("title", 0)), None);
+        assert_eq!(tag_at(content, content.len() - 2), None);
+    }
+
+    #[test]
+    fn test_cursor_at() {
+        let content = "#meta(\n  title: \"A\",\n  tags: (\"rust\", \"id\"),\n  ta\n)\nBody";
+        let at = |needle: &str, skip: usize| content.find(needle).unwrap() + skip;
+        assert_eq!(
+            cursor_at(content, at("ta\n", 2)),
+            Some(MetaCursor::Key {
+                start: at("ta\n", 0)
+            })
+        );
+        assert_eq!(
+            cursor_at(content, at("\"id", 3)),
+            Some(MetaCursor::Value {
+                key: "tags".to_string(),
+                start: at("\"id", 1)
+            })
+        );
+        assert_eq!(cursor_at(content, at("(\"rust", 1)), None);
+        assert_eq!(cursor_at(content, at("Body", 2)), None);
+    }
+}
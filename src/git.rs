@@ -0,0 +1,198 @@
+use crate::stored_path::os_string_from_bytes;
+use anyhow::{Context, Result};
+use git2::{DiffOptions, ErrorCode, ObjectType, Repository, Sort, TreeWalkMode, TreeWalkResult};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// The last commit that changed a note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub author: String,
+    /// Seconds since the Unix epoch
+    pub committed_at: i64,
+    /// Commits made on the current branch since this one, 0 for `HEAD`
+    pub commits_ago: usize,
+}
+
+impl CommitInfo {
+    /// The abbreviated hash shown to users.
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(7)]
+    }
+}
+
+/// The commit `HEAD` points to in the repository containing `root`, or `None` before the
+/// first commit. Fails if `root` is not inside a repository.
+pub fn head(root: &Path) -> Result<Option<String>> {
+    let repo = Repository::discover(root).context("Failed to open the git repository")?;
+    match repo.head() {
+        Ok(head) => Ok(head.target().map(|id| id.to_string())),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The last commit to touch each file under `root` that `HEAD` tracks, keyed by absolute
+/// path. Like `git log`, commits are walked newest first and merges name no files. Fails if
+/// `root` is not inside a repository with a working tree.
+pub fn last_commits(root: &Path) -> Result<HashMap<PathBuf, CommitInfo>> {
+    let repo = Repository::discover(root).context("Failed to open the git repository")?;
+    let workdir = repo
+        .workdir()
+        .context("The git repository has no working tree")?;
+    let prefix = root
+        .canonicalize()?
+        .strip_prefix(workdir.canonicalize()?)?
+        .to_path_buf();
+    let head = match repo.head() {
+        Ok(head) => head.peel_to_commit()?,
+        Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    // The files under `root` waiting for the newest commit that changed them, so the walk can
+    // stop once every one has been seen
+    let mut pending = tracked_files(&repo, &head.tree()?, &prefix)?;
+    let mut options = DiffOptions::new();
+    if !prefix.as_os_str().is_empty() {
+        options.pathspec(&prefix);
+    }
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TIME)?;
+    walk.push(head.id())?;
+
+    let mut commits = HashMap::new();
+    for (commits_ago, id) in walk.enumerate() {
+        if pending.is_empty() {
+            break;
+        }
+        let commit = repo.find_commit(id?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        let parent = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let diff =
+            repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), Some(&mut options))?;
+        let info = CommitInfo {
+            hash: commit.id().to_string(),
+            author: String::from_utf8_lossy(commit.author().name_bytes()).into_owned(),
+            committed_at: commit.time().seconds(),
+            commits_ago,
+        };
+        for delta in diff.deltas() {
+            for path in [delta.old_file().path(), delta.new_file().path()]
+                .into_iter()
+                .flatten()
+            {
+                if let Ok(relative) = path.strip_prefix(&prefix)
+                    && pending.remove(relative)
+                {
+                    commits.insert(root.join(relative), info.clone());
+                }
+            }
+        }
+    }
+    Ok(commits)
+}
+
+// The files in `tree` under the folder `prefix`, relative to it
+fn tracked_files(repo: &Repository, tree: &git2::Tree, prefix: &Path) -> Result<HashSet<PathBuf>> {
+    let subtree = if prefix.as_os_str().is_empty() {
+        tree.clone()
+    } else {
+        match tree.get_path(prefix) {
+            Ok(entry) => entry.to_object(repo)?.peel_to_tree()?,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(HashSet::new()),
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let mut files = HashSet::new();
+    subtree.walk(TreeWalkMode::PreOrder, |folder, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            let name = os_string_from_bytes(entry.name_bytes().to_vec());
+            files.insert(Path::new(folder).join(name));
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(files)
+}
+
+/// Whether `path` is inside a `.git` folder, where commits and checkouts change `HEAD` and
+/// branches.
+pub fn is_git_metadata(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str() == ".git")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+    use tempfile::tempdir;
+
+    // Commit the working tree as it is, by `author` at `time`
+    fn commit(repo: &Repository, author: &str, time: i64) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::new(author, "author@example.com", &Time::new(time, 0)).unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Update notes",
+            &tree,
+            parent.as_slice().iter().collect::<Vec<_>>().as_slice(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_last_commits() {
+        let temp_dir = tempdir().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        let root = temp_dir.path().join("vault");
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+        assert_eq!(head(&root).unwrap(), None);
+
+        std::fs::write(root.join("a.typ"), "one").unwrap();
+        std::fs::write(root.join("b.typ"), "one").unwrap();
+        std::fs::write(root.join("notes/b.typ"), "one").unwrap();
+        std::fs::write(temp_dir.path().join("README.md"), "one").unwrap();
+        commit(&repo, "Ada", 100);
+        std::fs::write(temp_dir.path().join("README.md"), "two").unwrap();
+        commit(&repo, "Bob", 200);
+        std::fs::write(root.join("b.typ"), "two").unwrap();
+        commit(&repo, "Ada", 300);
+        std::fs::write(root.join("untracked.typ"), "").unwrap();
+
+        let commits = last_commits(&root).unwrap();
+        assert_eq!(commits.len(), 3);
+        let b = &commits[&root.join("b.typ")];
+        assert_eq!(
+            (b.author.as_str(), b.committed_at, b.commits_ago),
+            ("Ada", 300, 0)
+        );
+        assert_eq!(b.hash, head(&root).unwrap().unwrap());
+        assert_eq!(commits[&root.join("a.typ")].commits_ago, 2);
+        assert_eq!(commits[&root.join("notes/b.typ")].committed_at, 100);
+
+        assert!(is_git_metadata(
+            &temp_dir.path().join(".git/refs/heads/main")
+        ));
+        assert!(!is_git_metadata(&root.join("a.typ")));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::graph::{Direction, adjacency};
+use crate::storage::Storage;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+// Local moving usually settles in a handful of rounds
+const MAX_ROUNDS: usize = 20;
+
+/// A group of densely linked notes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Cluster {
+    pub id: usize,
+    /// Absolute paths, sorted
+    pub notes: Vec<PathBuf>,
+    /// The note with the most links inside the cluster
+    pub representative: PathBuf,
+}
+
+/// The shortest chain of notes from `from` to `to` following links in `direction`,
+/// including both ends, or `None` if they aren't connected.
+pub fn shortest_path(
+    index: &dyn Storage,
+    from: &Path,
+    to: &Path,
+    direction: Direction,
+) -> Result<Option<Vec<PathBuf>>> {
+    if from == to {
+        return Ok(Some(vec![from.to_path_buf()]));
+    }
+
+    let adjacent = adjacency(index, direction)?;
+    let mut previous: HashMap<&Path, &Path> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(note) = queue.pop_front() {
+        index.check_cancelled()?;
+        for neighbor in adjacent.get(note).into_iter().flatten() {
+            if neighbor == from || previous.contains_key(neighbor.as_path()) {
+                continue;
+            }
+            previous.insert(neighbor, note);
+            if neighbor == to {
+                let mut path = vec![to.to_path_buf()];
+                let mut current = to;
+                while let Some(&prev) = previous.get(current) {
+                    path.push(prev.to_path_buf());
+                    current = prev;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            queue.push_back(neighbor);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Partition every note into communities by greedily moving notes to the neighboring
+/// community with the best modularity gain (the local-moving phase of Louvain), over the
+/// undirected link graph. Clusters are ordered largest first; isolated notes form their own.
+pub fn clusters(index: &dyn Storage) -> Result<Vec<Cluster>> {
+    let adjacent = adjacency(index, Direction::Both)?;
+    let notes = index.get_all_files()?;
+    let ids: HashMap<&Path, usize> = notes
+        .iter()
+        .enumerate()
+        .map(|(id, note)| (note.as_path(), id))
+        .collect();
+    let neighbors: Vec<Vec<usize>> = notes
+        .iter()
+        .map(|note| {
+            adjacent
+                .get(note)
+                .into_iter()
+                .flatten()
+                .filter(|neighbor| *neighbor != note)
+                .filter_map(|neighbor| ids.get(neighbor.as_path()).copied())
+                .collect()
+        })
+        .collect();
+
+    let degree = |note: usize| neighbors[note].len() as f64;
+    let double_edges: f64 = (0..notes.len()).map(degree).sum();
+    let mut labels: Vec<usize> = (0..notes.len()).collect();
+    let mut totals: Vec<f64> = (0..notes.len()).map(degree).collect();
+
+    // Visiting notes in a fixed order and breaking ties towards the current, then the
+    // smallest, community keeps the result deterministic
+    for _ in 0..MAX_ROUNDS {
+        index.check_cancelled()?;
+        let mut changed = false;
+        for note in 0..notes.len() {
+            if neighbors[note].is_empty() {
+                continue;
+            }
+            let current = labels[note];
+            totals[current] -= degree(note);
+
+            let mut links: BTreeMap<usize, f64> = BTreeMap::from([(current, 0.0)]);
+            for &neighbor in &neighbors[note] {
+                *links.entry(labels[neighbor]).or_default() += 1.0;
+            }
+            let gain = |label: usize| links[&label] - totals[label] * degree(note) / double_edges;
+            let mut best = current;
+            for &label in links.keys() {
+                if gain(label) > gain(best) + f64::EPSILON {
+                    best = label;
+                }
+            }
+
+            totals[best] += degree(note);
+            if best != current {
+                labels[note] = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (note, &label) in labels.iter().enumerate() {
+        groups.entry(label).or_default().push(note);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().collect();
+    groups.sort_by_key(|members| std::cmp::Reverse(members.len()));
+
+    Ok(groups
+        .into_iter()
+        .enumerate()
+        .map(|(id, members)| {
+            let internal_degree = |note: usize| {
+                neighbors[note]
+                    .iter()
+                    .filter(|&&neighbor| labels[neighbor] == labels[note])
+                    .count()
+            };
+            let representative = members
+                .iter()
+                .copied()
+                .max_by_key(|&note| (internal_degree(note), std::cmp::Reverse(note)))
+                .expect("clusters are never empty");
+            Cluster {
+                id,
+                notes: members.iter().map(|&note| notes[note].clone()).collect(),
+                representative: notes[representative].clone(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::from_index::tests::linked_index;
+    use crate::index::{CancellationToken, Cancelled};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_shortest_path() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        // a -> b -> c -> d, a -> c, e alone
+        let mut index = linked_index(
+            root,
+            &[
+                ("a", &["b", "c"]),
+                ("b", &["c"]),
+                ("c", &["d"]),
+                ("d", &[]),
+                ("e", &[]),
+            ],
+        );
+        let note = |name: &str| root.join(format!("{name}.typ"));
+        let path =
+            |from, to, direction| shortest_path(&index, &note(from), &note(to), direction).unwrap();
+
+        assert_eq!(
+            path("a", "d", Direction::Forward),
+            Some(vec![note("a"), note("c"), note("d")])
+        );
+        assert_eq!(path("d", "a", Direction::Forward), None);
+        assert_eq!(
+            path("d", "b", Direction::Both),
+            Some(vec![note("d"), note("c"), note("b")])
+        );
+        assert_eq!(path("a", "e", Direction::Both), None);
+        assert_eq!(path("a", "a", Direction::Both), Some(vec![note("a")]));
+
+        let token = CancellationToken::new();
+        index.set_cancellation(Some(token.clone()));
+        token.cancel();
+        let error = shortest_path(&index, &note("a"), &note("d"), Direction::Forward).unwrap_err();
+        assert!(error.is::<Cancelled>());
+    }
+
+    #[test]
+    fn test_clusters() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        // Two triangles joined by a single bridge, plus an isolated note
+        let index = linked_index(
+            root,
+            &[
+                ("a1", &["a2", "a3"]),
+                ("a2", &["a3"]),
+                ("a3", &["b1"]),
+                ("b1", &["b2", "b3"]),
+                ("b2", &["b3"]),
+                ("b3", &[]),
+                ("c", &[]),
+            ],
+        );
+        let note = |name: &str| root.join(format!("{name}.typ"));
+
+        let clusters = clusters(&index).unwrap();
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0].notes, vec![note("a1"), note("a2"), note("a3")]);
+        assert_eq!(clusters[0].representative, note("a1"));
+        assert_eq!(clusters[1].notes, vec![note("b1"), note("b2"), note("b3")]);
+        assert_eq!(clusters[2].notes, vec![note("c")]);
+        assert_eq!(clusters[2].id, 2);
+    }
+}
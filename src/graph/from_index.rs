@@ -0,0 +1,248 @@
+use crate::graph::{Direction, Graph, GraphFormat, build, to_dot, to_graphml};
+use crate::storage::Storage;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Notes within `depth` links of `start`, following links in `direction`.
+/// Paths are absolute and include `start` itself.
+pub fn neighborhood(
+    index: &dyn Storage,
+    start: &Path,
+    depth: usize,
+    direction: Direction,
+) -> Result<Vec<PathBuf>> {
+    let adjacent = adjacency(index, direction)?;
+    let mut seen = BTreeSet::from([start.to_path_buf()]);
+    let mut frontier = vec![start.to_path_buf()];
+    for _ in 0..depth {
+        index.check_cancelled()?;
+        let mut next = Vec::new();
+        for note in &frontier {
+            for neighbor in adjacent.get(note).into_iter().flatten() {
+                if seen.insert(neighbor.clone()) {
+                    next.push(neighbor.clone());
+                }
+            }
+        }
+        frontier = next;
+    }
+
+    Ok(seen.into_iter().collect())
+}
+
+/// Each note's neighbors by absolute path, following links in `direction`.
+pub(crate) fn adjacency(
+    index: &dyn Storage,
+    direction: Direction,
+) -> Result<HashMap<PathBuf, Vec<PathBuf>>> {
+    let root = index.root();
+    let mut adjacent: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for (source, target, _) in index.get_link_graph()? {
+        let (source, target) = (root.join(source), root.join(target));
+        if direction != Direction::Backward {
+            adjacent
+                .entry(source.clone())
+                .or_default()
+                .push(target.clone());
+        }
+        if direction != Direction::Forward {
+            adjacent.entry(target).or_default().push(source);
+        }
+    }
+    Ok(adjacent)
+}
+
+/// The subgraph of notes within `depth` links of `start` and the edges between them. Archived
+/// notes other than `start` are left out unless `include_archived`.
+pub fn local_graph(
+    index: &dyn Storage,
+    start: &Path,
+    depth: usize,
+    direction: Direction,
+    include_archived: bool,
+) -> Result<Graph> {
+    let root = index.root();
+    let notes: BTreeSet<PathBuf> = neighborhood(index, start, depth, direction)?
+        .into_iter()
+        .filter_map(|path| path.strip_prefix(root).ok().map(Path::to_path_buf))
+        .collect();
+
+    let mut graph = build(index, true)?;
+    let archived = if include_archived {
+        Default::default()
+    } else {
+        index.archived_notes()?
+    };
+    let notes: BTreeSet<PathBuf> = notes
+        .into_iter()
+        .filter(|note| root.join(note) == start || !archived.contains(&root.join(note)))
+        .collect();
+    graph.nodes.retain(|node| notes.contains(&node.id));
+    graph
+        .edges
+        .retain(|edge| notes.contains(&edge.source) && notes.contains(&edge.target));
+    Ok(graph)
+}
+
+/// Serialize the vault's note graph for Graphviz, Gephi, or D3.
+pub fn export(index: &dyn Storage, format: GraphFormat, include_archived: bool) -> Result<String> {
+    let graph = build(index, include_archived)?;
+    Ok(match format {
+        GraphFormat::Dot => to_dot(&graph),
+        GraphFormat::GraphMl => to_graphml(&graph),
+        GraphFormat::Json => serde_json::to_string_pretty(&graph)?,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::graph::Edge;
+    use crate::index::Index;
+    use crate::parser::models::{Metadata, ParsedFile, Wikilink};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+
+        let link = |target: &str| Wikilink {
+            target: target.to_string(),
+            alias: None,
+            label: None,
+            block: None,
+            line: 1,
+            column: 1,
+        };
+        let notes = [
+            (
+                "a",
+                Some("A \"quoted\" <note>"),
+                vec![link("b"), link("b"), link("gone")],
+            ),
+            ("b", None, vec![]),
+        ];
+        for (name, title, wikilinks) in notes {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, name).unwrap();
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata {
+                    title: title.map(str::to_string),
+                    tags: vec!["t".to_string()],
+                    ..Default::default()
+                },
+                wikilinks,
+                labels: vec![],
+                headings: vec![],
+                blocks: vec![],
+                attachments: vec![],
+                stats: Default::default(),
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let graph = build(&index, false).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes[0].tags, vec!["t"]);
+        assert_eq!(
+            graph.edges,
+            vec![Edge {
+                source: PathBuf::from("a.typ"),
+                target: PathBuf::from("b.typ"),
+                weight: 2,
+            }]
+        );
+
+        let dot = export(&index, GraphFormat::Dot, false).unwrap();
+        assert!(dot.contains(r#""a.typ" [label="A \"quoted\" <note>", tags="t"];"#));
+        assert!(dot.contains(r#""a.typ" -> "b.typ" [weight=2];"#));
+
+        let graphml = export(&index, GraphFormat::GraphMl, false).unwrap();
+        assert!(graphml.contains("A &quot;quoted&quot; &lt;note&gt;"));
+
+        let json: Graph =
+            serde_json::from_str(&export(&index, GraphFormat::Json, false).unwrap()).unwrap();
+        assert_eq!(json, graph);
+    }
+
+    /// An index of `notes`, each given as a name and the names it links to.
+    pub(crate) fn linked_index(root: &Path, notes: &[(&str, &[&str])]) -> Index {
+        let mut index = Index::new(root).unwrap();
+        for (name, targets) in notes {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, name).unwrap();
+            let wikilinks = targets
+                .iter()
+                .map(|target| Wikilink {
+                    target: target.to_string(),
+                    alias: None,
+                    label: None,
+                    block: None,
+                    line: 1,
+                    column: 1,
+                })
+                .collect();
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata::default(),
+                wikilinks,
+                labels: vec![],
+                headings: vec![],
+                blocks: vec![],
+                attachments: vec![],
+                stats: Default::default(),
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn test_local_graph() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        // a -> b -> c, d -> b
+        let index = linked_index(
+            root,
+            &[("a", &["b"]), ("b", &["c"]), ("c", &[]), ("d", &["b"])],
+        );
+
+        let ids = |direction, depth| {
+            local_graph(&index, &root.join("b.typ"), depth, direction, false)
+                .unwrap()
+                .nodes
+                .into_iter()
+                .map(|node| node.id.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(Direction::Forward, 1), vec!["b.typ", "c.typ"]);
+        assert_eq!(ids(Direction::Backward, 1), vec!["a.typ", "b.typ", "d.typ"]);
+        assert_eq!(ids(Direction::Both, 0), vec!["b.typ"]);
+
+        let graph = local_graph(&index, &root.join("a.typ"), 2, Direction::Forward, false).unwrap();
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 2);
+
+        index
+            .set_archive_folders(&[PathBuf::from("c.typ")])
+            .unwrap();
+        assert_eq!(ids(Direction::Forward, 1), vec!["b.typ"]);
+        assert_eq!(build(&index, false).unwrap().edges.len(), 2);
+        let graph = local_graph(&index, &root.join("c.typ"), 1, Direction::Both, true).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+    }
+}
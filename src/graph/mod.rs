@@ -0,0 +1,188 @@
+#[cfg(feature = "native")]
+pub mod algorithms;
+#[cfg(feature = "native")]
+mod from_index;
+
+use crate::storage::Storage;
+use anyhow::Result;
+#[cfg(feature = "native")]
+pub(crate) use from_index::adjacency;
+#[cfg(feature = "native")]
+pub use from_index::{export, local_graph, neighborhood};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use ts_rs::TS;
+
+/// The resolved note graph. Node ids are vault-relative paths.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Node {
+    pub id: PathBuf,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// All links from `source` to `target`, collapsed into one edge.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Edge {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub weight: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    GraphMl,
+    Json,
+}
+
+/// Which links to follow when walking the graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum Direction {
+    /// Links out of a note
+    Forward,
+    /// Links into a note
+    Backward,
+    #[default]
+    Both,
+}
+
+/// Build the graph of the stored notes and the links between them, leaving out archived
+/// notes unless `include_archived`.
+pub fn build(index: &dyn Storage, include_archived: bool) -> Result<Graph> {
+    let archived = if include_archived {
+        Default::default()
+    } else {
+        index.archived_notes()?
+    };
+    let mut nodes: BTreeMap<PathBuf, Node> = index
+        .get_all_files()?
+        .into_iter()
+        .filter(|path| !archived.contains(path))
+        .map(|path| {
+            let id = path
+                .strip_prefix(index.root())
+                .unwrap_or(&path)
+                .to_path_buf();
+            let node = Node {
+                id: id.clone(),
+                title: None,
+                tags: Vec::new(),
+            };
+            (id, node)
+        })
+        .collect();
+
+    for (path, key, value) in index.get_all_metadata()? {
+        let Some(node) = nodes.get_mut(&path) else {
+            continue;
+        };
+        match key.as_str() {
+            "title" => node.title = Some(value),
+            "tags" => node.tags.push(value),
+            _ => {}
+        }
+    }
+
+    let edges = index
+        .get_link_graph()?
+        .into_iter()
+        .filter(|(source, target, _)| nodes.contains_key(source) && nodes.contains_key(target))
+        .map(|(source, target, weight)| Edge {
+            source,
+            target,
+            weight,
+        })
+        .collect();
+
+    Ok(Graph {
+        nodes: nodes.into_values().collect(),
+        edges,
+    })
+}
+
+pub fn to_dot(graph: &Graph) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut out = String::from("digraph vault {\n");
+    for node in &graph.nodes {
+        let id = node.id.to_string_lossy();
+        let label = node.title.as_deref().unwrap_or(&id);
+        let _ = write!(out, "  {} [label={}", quote(&id), quote(label));
+        if !node.tags.is_empty() {
+            let _ = write!(out, ", tags={}", quote(&node.tags.join(",")));
+        }
+        out.push_str("];\n");
+    }
+    for edge in &graph.edges {
+        let _ = writeln!(
+            out,
+            "  {} -> {} [weight={}];",
+            quote(&edge.source.to_string_lossy()),
+            quote(&edge.target.to_string_lossy()),
+            edge.weight
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+pub fn to_graphml(graph: &Graph) -> String {
+    let escape = |s: &str| {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    };
+
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n  \
+         <key id=\"title\" for=\"node\" attr.name=\"title\" attr.type=\"string\"/>\n  \
+         <key id=\"tags\" for=\"node\" attr.name=\"tags\" attr.type=\"string\"/>\n  \
+         <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n  \
+         <graph id=\"vault\" edgedefault=\"directed\">\n",
+    );
+    for node in &graph.nodes {
+        let _ = writeln!(
+            out,
+            "    <node id=\"{}\">",
+            escape(&node.id.to_string_lossy())
+        );
+        if let Some(title) = &node.title {
+            let _ = writeln!(out, "      <data key=\"title\">{}</data>", escape(title));
+        }
+        if !node.tags.is_empty() {
+            let _ = writeln!(
+                out,
+                "      <data key=\"tags\">{}</data>",
+                escape(&node.tags.join(","))
+            );
+        }
+        out.push_str("    </node>\n");
+    }
+    for edge in &graph.edges {
+        let _ = writeln!(
+            out,
+            "    <edge source=\"{}\" target=\"{}\"><data key=\"weight\">{}</data></edge>",
+            escape(&edge.source.to_string_lossy()),
+            escape(&edge.target.to_string_lossy()),
+            edge.weight
+        );
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
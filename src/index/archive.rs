@@ -0,0 +1,128 @@
+use crate::index::Index;
+use anyhow::Result;
+use rusqlite::OptionalExtension;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// `archived_notes` as of a `PRAGMA data_version` and the connection's `total_changes`
+pub(super) type Archived = ((i64, u64), Arc<HashSet<PathBuf>>);
+
+impl Index {
+    /// Vault-relative folders whose notes count as archived.
+    pub fn archive_folders(&self) -> Result<Vec<PathBuf>> {
+        let value: Option<String> = self
+            .conn
+            .prepare_cached("SELECT value FROM settings WHERE key = 'archive_folders'")?
+            .query_row([], |row| row.get(0))
+            .optional()?;
+        Ok(match value {
+            Some(value) => serde_json::from_str(&value)?,
+            None => Vec::new(),
+        })
+    }
+
+    /// Set the folders whose notes count as archived, as configured by `archive_folders`.
+    pub fn set_archive_folders(&self, folders: &[PathBuf]) -> Result<()> {
+        self.conn
+            .prepare_cached(
+                "INSERT INTO settings (key, value) VALUES ('archive_folders', ?)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            )?
+            .execute([serde_json::to_string(folders)?])?;
+        Ok(())
+    }
+
+    /// Notes marked `archived: true` in their metadata or stored under an archive folder.
+    /// They still resolve, but completion, search, and the graph leave them out by default.
+    pub fn archived_notes(&self) -> Result<Arc<HashSet<PathBuf>>> {
+        // Our own writes bump `total_changes`, other connections' commits `data_version`
+        let version: i64 = self
+            .conn
+            .prepare_cached("PRAGMA data_version")?
+            .query_row([], |row| row.get(0))?;
+        let state = (version, self.conn.total_changes());
+        if let Some((read_at, archived)) = &*self.archived.borrow()
+            && *read_at == state
+        {
+            return Ok(archived.clone());
+        }
+
+        let mut archived: HashSet<PathBuf> = self
+            .conn
+            .prepare_cached(
+                "SELECT f.path FROM metadata m JOIN files f ON f.id = m.file_id
+                 WHERE m.key = 'archived' AND m.value = 'true'",
+            )?
+            .query_map([], |row| Ok(self.vault_path(&row.get::<_, String>(0)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let folders = self.archive_folders()?;
+        if !folders.is_empty() {
+            for note in self.get_all_files()? {
+                let relative = note.strip_prefix(&self.root).unwrap_or(&note);
+                if folders.iter().any(|folder| relative.starts_with(folder)) {
+                    archived.insert(note);
+                }
+            }
+        }
+        let archived = Arc::new(archived);
+        *self.archived.borrow_mut() = Some((state, archived.clone()));
+        Ok(archived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archived_notes() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+        let parser = crate::parser::Parser::new().unwrap();
+        std::fs::create_dir_all(root.join("archive/old")).unwrap();
+        for (name, archived) in [
+            ("archive/old/a", None),
+            ("archived", Some(true)),
+            ("active", Some(false)),
+            ("archive-notes", None),
+        ] {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, "").unwrap();
+            let mut parsed = parser.parse_content("", &path).unwrap();
+            if let Some(archived) = archived {
+                parsed
+                    .metadata
+                    .custom
+                    .insert("archived".to_string(), archived.into());
+            }
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let archived = |index: &Index| {
+            let mut notes: Vec<_> = index.archived_notes().unwrap().iter().cloned().collect();
+            notes.sort();
+            notes
+        };
+        assert_eq!(archived(&index), vec![root.join("archived.typ")]);
+
+        index
+            .set_archive_folders(&[PathBuf::from("archive")])
+            .unwrap();
+        assert_eq!(
+            index.archive_folders().unwrap(),
+            vec![PathBuf::from("archive")]
+        );
+        assert_eq!(
+            archived(&index),
+            vec![root.join("archive/old/a.typ"), root.join("archived.typ")]
+        );
+
+        // The cached set follows other connections' commits
+        Index::new(root).unwrap().set_archive_folders(&[]).unwrap();
+        assert_eq!(archived(&index), vec![root.join("archived.typ")]);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::index::{Index, stored_path};
+use crate::parser::models::Attachment;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+/// The vault-relative path of the file `attachment` refers to from the note at `source`
+/// (vault-relative), if it exists. Like Typst, `#image` paths are relative to the note unless
+/// they start with `/`; wikilinks are tried against the vault root first.
+pub(crate) fn resolve(root: &Path, source: &Path, attachment: &Attachment) -> Option<String> {
+    let target = Path::new(&attachment.target);
+    let note_dir = source.parent().unwrap_or(Path::new(""));
+    let candidates = if let Ok(absolute) = target.strip_prefix("/") {
+        vec![absolute.to_path_buf()]
+    } else if attachment.embedded {
+        vec![note_dir.join(target)]
+    } else {
+        vec![target.to_path_buf(), note_dir.join(target)]
+    };
+
+    candidates
+        .into_iter()
+        .filter_map(|candidate| normalize(&candidate))
+        .find(|candidate| root.join(candidate).is_file())
+        .map(|candidate| stored_path::encode(&candidate))
+}
+
+/// Resolve `.` and `..` in a vault-relative path without touching the file system; `None` if
+/// the path leaves the vault.
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+impl Index {
+    /// An attachment some note refers to whose path ends with `target`, e.g. `paper.pdf`
+    /// for `files/paper.pdf`.
+    pub fn find_attachment(&self, target: &str) -> Result<Option<PathBuf>> {
+        let target = Path::new(target);
+        Ok(self
+            .get_referenced_attachments()?
+            .into_iter()
+            .find(|path| path.ends_with(target)))
+    }
+
+    /// Embedded attachments, such as `#image("...")` paths, whose file does not exist; only
+    /// those in `file` if given.
+    pub fn get_missing_attachments(
+        &self,
+        file: Option<&Path>,
+    ) -> Result<Vec<(PathBuf, Attachment)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT f.path, a.target, a.line, a.column
+             FROM attachments a
+             JOIN files f ON f.id = a.file_id
+             WHERE a.embedded AND a.path IS NULL {}
+             ORDER BY f.path, a.line, a.column",
+            scope.condition("a.file_id")
+        ))?;
+        let missing = stmt
+            .query_map(scope.params(), |row| {
+                let attachment = Attachment {
+                    target: row.get(1)?,
+                    embedded: true,
+                    line: row.get::<_, i64>(2)? as usize,
+                    column: row.get::<_, i64>(3)? as usize,
+                };
+                Ok((self.vault_path(&row.get::<_, String>(0)?), attachment))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(missing)
+    }
+
+    /// Every existing file some note links to or embeds, ordered by path.
+    pub fn get_referenced_attachments(&self) -> Result<BTreeSet<PathBuf>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT DISTINCT path FROM attachments WHERE path IS NOT NULL")?;
+        let paths = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(|path| self.vault_path(&path)))
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn attachment(target: &str, embedded: bool) -> Attachment {
+        Attachment {
+            target: target.to_string(),
+            embedded,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("notes/img")).unwrap();
+        std::fs::write(root.join("notes/img/plot.png"), "").unwrap();
+        std::fs::write(root.join("paper.pdf"), "").unwrap();
+        let source = Path::new("notes/note.typ");
+
+        let resolved = |target, embedded| resolve(root, source, &attachment(target, embedded));
+        assert_eq!(
+            resolved("img/plot.png", true).as_deref(),
+            Some("notes/img/plot.png")
+        );
+        assert_eq!(
+            resolved("/notes/img/plot.png", true).as_deref(),
+            Some("notes/img/plot.png")
+        );
+        assert_eq!(resolved("../paper.pdf", true).as_deref(), Some("paper.pdf"));
+        assert_eq!(resolved("paper.pdf", false).as_deref(), Some("paper.pdf"));
+        assert_eq!(
+            resolved("img/plot.png", false).as_deref(),
+            Some("notes/img/plot.png")
+        );
+        assert_eq!(resolved("paper.pdf", true), None);
+        assert_eq!(resolved("../../outside.pdf", true), None);
+    }
+}
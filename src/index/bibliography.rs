@@ -0,0 +1,273 @@
+use crate::index::{Index, attachments, content_hash, stored_path};
+use crate::parser::bibliography::parse_bibliography;
+use crate::parser::models::{Attachment, BibEntry, Citation};
+use anyhow::Result;
+use rusqlite::{OptionalExtension, params};
+use std::path::{Path, PathBuf};
+
+/// The vault-relative path of the bibliography file `target` names from the note at `source`
+/// (vault-relative), if it exists. Like other asset paths it is relative to the note.
+pub(super) fn resolve(root: &Path, source: &Path, target: &str) -> Option<String> {
+    let attachment = Attachment {
+        target: target.to_string(),
+        embedded: true,
+        line: 0,
+        column: 0,
+    };
+    attachments::resolve(root, source, &attachment)
+}
+
+impl Index {
+    /// Re-read the entries of the bibliography files notes use whose content changed since
+    /// they were last read, and forget files no note uses any more. With `changed`, only the
+    /// bibliographies of those notes and those among them that are `.bib` files are read;
+    /// otherwise every one is.
+    pub fn refresh_bibliographies(&self, changed: Option<&[PathBuf]>) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        // A bibliography created after its note was indexed resolves now
+        let unresolved: Vec<(i64, String, String)> = tx
+            .prepare_cached(
+                "SELECT b.id, f.path, b.target FROM bibliographies b
+                 JOIN files f ON f.id = b.file_id WHERE b.path IS NULL",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut resolved = Vec::new();
+        for (id, source, target) in unresolved {
+            let path = resolve(&self.root, &stored_path::decode(&source), &target);
+            if let Some(path) = path {
+                tx.prepare_cached("UPDATE bibliographies SET path = ? WHERE id = ?")?
+                    .execute(params![path, id])?;
+                resolved.push(path);
+            }
+        }
+
+        let mut used: Vec<String> = match changed {
+            None => tx
+                .prepare_cached("SELECT DISTINCT path FROM bibliographies WHERE path IS NOT NULL")?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?,
+            Some(changed) => {
+                let mut used = resolved;
+                for path in changed {
+                    let Ok(stored) = self.stored_path(path) else {
+                        continue;
+                    };
+                    let rows: Vec<String> = tx
+                        .prepare_cached(
+                            "SELECT b.path FROM bibliographies b JOIN files f ON f.id = b.file_id
+                             WHERE f.path = ?1 AND b.path IS NOT NULL
+                             UNION SELECT path FROM bibliographies WHERE path = ?1",
+                        )?
+                        .query_map([&stored], |row| row.get(0))?
+                        .collect::<rusqlite::Result<_>>()?;
+                    used.extend(rows);
+                }
+                used
+            }
+        };
+        used.sort();
+        used.dedup();
+        for path in used {
+            let file_path = self.vault_path(&path);
+            let content = match std::fs::read_to_string(&file_path) {
+                Ok(content) => content,
+                Err(e) => {
+                    tracing::warn!("Failed to read {}: {e}", file_path.display());
+                    continue;
+                }
+            };
+            let hash = content_hash(content.as_bytes());
+            let stored_hash: Option<String> = tx
+                .prepare_cached("SELECT content_hash FROM bib_files WHERE path = ?")?
+                .query_row([&path], |row| row.get(0))
+                .optional()?;
+            if stored_hash.as_ref() == Some(&hash) {
+                continue;
+            }
+
+            let bib_id: i64 = tx
+                .prepare_cached(
+                    "INSERT INTO bib_files (path, content_hash) VALUES (?, ?)
+                     ON CONFLICT(path) DO UPDATE SET content_hash = excluded.content_hash
+                     RETURNING id",
+                )?
+                .query_row(params![path, hash], |row| row.get(0))?;
+            tx.prepare_cached("DELETE FROM bib_entries WHERE bib_id = ?")?
+                .execute([bib_id])?;
+            for entry in parse_bibliography(&file_path, &content) {
+                tx.prepare_cached(
+                    "INSERT INTO bib_entries (bib_id, key, kind, title, authors, year)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )?
+                .execute(params![
+                    bib_id,
+                    entry.key,
+                    entry.kind,
+                    entry.title,
+                    serde_json::to_string(&entry.authors)?,
+                    entry.year
+                ])?;
+            }
+        }
+
+        tx.execute(
+            "DELETE FROM bib_files
+             WHERE path NOT IN (SELECT path FROM bibliographies WHERE path IS NOT NULL)",
+            [],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The entries of the bibliographies a note uses, ordered by key.
+    pub fn bib_entries(&self, file_path: &Path) -> Result<Vec<BibEntry>> {
+        let Ok(relative_path) = self.stored_path(file_path) else {
+            return Ok(Vec::new());
+        };
+        let entries = self
+            .conn
+            .prepare_cached(
+                "SELECT DISTINCT e.key, e.kind, e.title, e.authors, e.year
+                 FROM bib_entries e
+                 JOIN bib_files bf ON bf.id = e.bib_id
+                 JOIN bibliographies b ON b.path = bf.path
+                 JOIN files f ON f.id = b.file_id
+                 WHERE f.path = ?
+                 ORDER BY e.key",
+            )?
+            .query_map([&relative_path], |row| {
+                Ok(BibEntry {
+                    key: row.get(0)?,
+                    kind: row.get(1)?,
+                    title: row.get(2)?,
+                    authors: serde_json::from_str(&row.get::<_, String>(3)?).unwrap_or_default(),
+                    year: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+
+    /// The notes using the bibliography file `bib`.
+    pub fn bibliography_users(&self, bib: &Path) -> Result<Vec<PathBuf>> {
+        let Ok(relative_path) = self.stored_path(bib) else {
+            return Ok(Vec::new());
+        };
+        let notes = self
+            .conn
+            .prepare_cached(
+                "SELECT DISTINCT f.path FROM bibliographies b JOIN files f ON f.id = b.file_id
+                 WHERE b.path = ? ORDER BY f.path",
+            )?
+            .query_map([&relative_path], |row| {
+                Ok(self.vault_path(&row.get::<_, String>(0)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(notes)
+    }
+
+    /// `@key` references in notes with a bibliography that neither it nor any label in the
+    /// vault defines, ordered by file and position; only those in `file` if given.
+    pub fn get_unknown_citations(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Citation)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        let citations = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT f.path, c.key, c.line, c.column
+                 FROM citations c JOIN files f ON f.id = c.file_id
+                 WHERE EXISTS (
+                     SELECT 1 FROM bibliographies b
+                     WHERE b.file_id = c.file_id AND b.path IS NOT NULL
+                 )
+                 AND NOT EXISTS (
+                     SELECT 1 FROM bibliographies b
+                     JOIN bib_files bf ON bf.path = b.path
+                     JOIN bib_entries e ON e.bib_id = bf.id
+                     WHERE b.file_id = c.file_id AND e.key = c.key
+                 )
+                 AND NOT EXISTS (SELECT 1 FROM labels l WHERE l.name = c.key) {}
+                 ORDER BY f.path, c.line, c.column",
+                scope.condition("c.file_id")
+            ))?
+            .query_map(scope.params(), |row| {
+                let citation = Citation {
+                    key: row.get(1)?,
+                    line: row.get::<_, i64>(2)? as usize,
+                    column: row.get::<_, i64>(3)? as usize,
+                };
+                Ok((self.vault_path(&row.get::<_, String>(0)?), citation))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(citations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_bibliographies() {
+        let content = "= Intro <intro>\nAs @knuth84 and @nobody show in @intro and @other.\n\
+                       #bibliography(\"refs.bib\")";
+        let (temp_dir, index) = vault(&[("paper.typ", content), ("other.typ", "<other>")]);
+        let root = temp_dir.path();
+        let note = root.join("paper.typ");
+
+        // The file does not exist yet, so no citation is reported
+        index.refresh_bibliographies(None).unwrap();
+        assert!(index.get_unknown_citations(None).unwrap().is_empty());
+
+        std::fs::write(
+            root.join("refs.bib"),
+            "@article{knuth84, title = {Literate Programming}}",
+        )
+        .unwrap();
+        index.refresh_bibliographies(None).unwrap();
+        let entries = index.bib_entries(&note).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title.as_deref(), Some("Literate Programming"));
+        let unknown = index.get_unknown_citations(None).unwrap();
+        assert_eq!(
+            unknown,
+            vec![(
+                note.clone(),
+                Citation {
+                    key: "nobody".to_string(),
+                    line: 2,
+                    column: 17,
+                }
+            )]
+        );
+
+        std::fs::write(root.join("refs.bib"), "@book{nobody, title = {Nothing}}").unwrap();
+        // Only the changed notes' bibliographies and changed `.bib` files are read
+        index
+            .refresh_bibliographies(Some(&[root.join("other.typ")]))
+            .unwrap();
+        assert_eq!(index.bib_entries(&note).unwrap()[0].key, "knuth84");
+        index
+            .refresh_bibliographies(Some(&[root.join("refs.bib")]))
+            .unwrap();
+        assert_eq!(
+            index.bibliography_users(&root.join("refs.bib")).unwrap(),
+            vec![note.clone()]
+        );
+        let keys: Vec<_> = index
+            .bib_entries(&note)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        assert_eq!(keys, vec!["nobody"]);
+        assert_eq!(
+            index.get_unknown_citations(None).unwrap()[0].1.key,
+            "knuth84"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::parser::models::ParsedFile;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Parsed files kept in memory per vault
+pub const DEFAULT_CAPACITY: usize = 256;
+
+// Generations come from one counter, so no two caches ever share one
+static GENERATIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_generation() -> u64 {
+    GENERATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A least-recently-used cache of parsed files, shared by every connection to one index.
+///
+/// `generation` changes on every invalidation so a reader can tell whether the value it
+/// loaded from SQLite may already be stale by the time it inserts it. Generations are unique
+/// across caches, so one also identifies a state of its index.
+pub struct FileCache {
+    capacity: usize,
+    entries: HashMap<PathBuf, Entry>,
+    tick: u64,
+    generation: u64,
+}
+
+struct Entry {
+    file: ParsedFile,
+    last_used: u64,
+}
+
+impl FileCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+            generation: next_generation(),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn get(&mut self, path: &Path) -> Option<ParsedFile> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(path)?;
+        entry.last_used = self.tick;
+        Some(entry.file.clone())
+    }
+
+    /// Cache `file` unless something was invalidated since `generation` was read.
+    pub fn insert(&mut self, generation: u64, file: ParsedFile) {
+        if generation != self.generation || self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity
+            && !self.entries.contains_key(&file.path)
+            && let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone())
+        {
+            self.entries.remove(&oldest);
+        }
+
+        self.tick += 1;
+        self.entries.insert(
+            file.path.clone(),
+            Entry {
+                file,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    pub fn invalidate(&mut self, path: &Path) {
+        self.generation = next_generation();
+        self.entries.remove(path);
+    }
+
+    pub fn clear(&mut self) {
+        self.generation = next_generation();
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::models::Metadata;
+
+    fn parsed(path: &str) -> ParsedFile {
+        ParsedFile {
+            path: PathBuf::from(path),
+            metadata: Metadata::default(),
+            wikilinks: vec![],
+            labels: vec![],
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = FileCache::new(2);
+        let generation = cache.generation();
+        cache.insert(generation, parsed("a.typ"));
+        cache.insert(generation, parsed("b.typ"));
+        assert!(cache.get(Path::new("a.typ")).is_some());
+
+        cache.insert(generation, parsed("c.typ"));
+        assert!(cache.get(Path::new("a.typ")).is_some());
+        assert!(cache.get(Path::new("b.typ")).is_none());
+        assert!(cache.get(Path::new("c.typ")).is_some());
+    }
+
+    #[test]
+    fn test_stale_insert_is_dropped() {
+        let mut cache = FileCache::new(2);
+        let generation = cache.generation();
+        cache.invalidate(Path::new("a.typ"));
+
+        cache.insert(generation, parsed("a.typ"));
+        assert!(cache.get(Path::new("a.typ")).is_none());
+    }
+}
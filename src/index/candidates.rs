@@ -0,0 +1,49 @@
+use crate::index::Index;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// A note as a wikilink target, with what ranks it against the note being edited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkCandidate {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    /// Tags it has in common with the edited note
+    pub shared_tags: usize,
+    /// Whether the edited note already links to it
+    pub linked: bool,
+    /// Seconds since the Unix epoch
+    pub modified_at: Option<i64>,
+}
+
+impl Index {
+    /// Every note in path order, ranked against `note` as a link target.
+    pub fn link_candidates(&self, note: &Path) -> Result<Vec<LinkCandidate>> {
+        // Unsaved and outside notes share nothing with any note
+        let current = self.stored_path(note).ok();
+        let mut stmt = self.conn.prepare_cached(
+            "WITH current AS (SELECT id FROM files WHERE path = ?1)
+             SELECT f.path,
+                    (SELECT value FROM metadata WHERE file_id = f.id AND key = 'title'),
+                    (SELECT COUNT(DISTINCT m.value) FROM metadata m
+                     JOIN metadata c ON c.key = 'tags' AND c.value = m.value
+                     WHERE m.file_id = f.id AND m.key = 'tags'
+                       AND c.file_id IN (SELECT id FROM current)),
+                    EXISTS (SELECT 1 FROM resolved_links r
+                            WHERE r.target_file_id = f.id
+                              AND r.source_file_id IN (SELECT id FROM current)),
+                    f.modified_at
+             FROM files f
+             ORDER BY f.path",
+        )?;
+        let rows = stmt.query_map([current], |row| {
+            Ok(LinkCandidate {
+                path: self.vault_path(&row.get::<_, String>(0)?),
+                title: row.get(1)?,
+                shared_tags: row.get(2)?,
+                linked: row.get(3)?,
+                modified_at: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
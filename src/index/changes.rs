@@ -0,0 +1,175 @@
+use crate::index::Index;
+use anyhow::Result;
+use std::path::PathBuf;
+
+// Temporary triggers that record, as the writes happen, which notes were stored or moved and
+// how many resolved links each note gained or lost to each target. They roll back with the
+// transaction that fired them
+const TRACK: &str = "
+    CREATE TEMP TABLE IF NOT EXISTS changed_files (
+        file_id INTEGER PRIMARY KEY,
+        moved INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TEMP TABLE IF NOT EXISTS removed_files (
+        file_id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL
+    );
+    CREATE TEMP TABLE IF NOT EXISTS changed_links (
+        source_file_id INTEGER NOT NULL,
+        target_file_id INTEGER NOT NULL,
+        delta INTEGER NOT NULL,
+        PRIMARY KEY (source_file_id, target_file_id)
+    );
+    CREATE TEMP TRIGGER IF NOT EXISTS track_file_insert AFTER INSERT ON files BEGIN
+        INSERT OR IGNORE INTO changed_files (file_id) VALUES (NEW.id);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS track_file_content AFTER UPDATE OF content_hash ON files
+    WHEN OLD.content_hash IS NOT NEW.content_hash BEGIN
+        INSERT OR IGNORE INTO changed_files (file_id) VALUES (NEW.id);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS track_file_move AFTER UPDATE OF path ON files
+    WHEN OLD.path != NEW.path BEGIN
+        INSERT INTO changed_files (file_id, moved) VALUES (NEW.id, 1)
+        ON CONFLICT(file_id) DO UPDATE SET moved = 1;
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS track_file_delete AFTER DELETE ON files BEGIN
+        INSERT OR REPLACE INTO removed_files (file_id, path) VALUES (OLD.id, OLD.path);
+        DELETE FROM changed_files WHERE file_id = OLD.id;
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS track_link_insert AFTER INSERT ON resolved_links BEGIN
+        INSERT INTO changed_links (source_file_id, target_file_id, delta)
+        VALUES (NEW.source_file_id, NEW.target_file_id, 1)
+        ON CONFLICT(source_file_id, target_file_id) DO UPDATE SET delta = delta + 1;
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS track_link_delete AFTER DELETE ON resolved_links BEGIN
+        INSERT INTO changed_links (source_file_id, target_file_id, delta)
+        VALUES (OLD.source_file_id, OLD.target_file_id, -1)
+        ON CONFLICT(source_file_id, target_file_id) DO UPDATE SET delta = delta - 1;
+    END;";
+
+const UNTRACK: &str = "
+    DROP TRIGGER IF EXISTS temp.track_file_insert;
+    DROP TRIGGER IF EXISTS temp.track_file_content;
+    DROP TRIGGER IF EXISTS temp.track_file_move;
+    DROP TRIGGER IF EXISTS temp.track_file_delete;
+    DROP TRIGGER IF EXISTS temp.track_link_insert;
+    DROP TRIGGER IF EXISTS temp.track_link_delete;
+    DROP TABLE IF EXISTS temp.changed_files;
+    DROP TABLE IF EXISTS temp.removed_files;
+    DROP TABLE IF EXISTS temp.changed_links;";
+
+/// What an update changed in the index, as sorted note paths.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Changes {
+    /// Notes that were added, moved, or stored with new content
+    pub indexed: Vec<PathBuf>,
+    /// Notes whose resolved links gained or lost a target, or whose targets moved; removed
+    /// notes that had links are listed under their last path
+    pub relinked: Vec<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.indexed.is_empty() && self.relinked.is_empty()
+    }
+}
+
+impl Index {
+    /// Run `update`, returning its result with the notes it changed. Only the rows the update
+    /// writes are looked at, so this costs the same however large the vault is.
+    pub fn track_changes<T>(
+        &mut self,
+        update: impl FnOnce(&mut Index) -> Result<T>,
+    ) -> Result<(T, Changes)> {
+        self.conn.execute_batch(UNTRACK)?;
+        self.conn.execute_batch(TRACK)?;
+        let result = update(self).and_then(|result| Ok((result, self.changes()?)));
+        self.conn.execute_batch(UNTRACK)?;
+        result
+    }
+
+    // The changes recorded since tracking started
+    fn changes(&self) -> Result<Changes> {
+        let indexed = self.tracked_paths(
+            "SELECT f.path FROM changed_files c JOIN files f ON f.id = c.file_id ORDER BY f.path",
+        )?;
+        let relinked = self.tracked_paths(
+            "WITH relinked (file_id) AS (
+                SELECT source_file_id FROM changed_links
+                GROUP BY source_file_id HAVING SUM(delta != 0) > 0
+                UNION SELECT r.source_file_id FROM resolved_links r
+                    JOIN changed_files c ON c.moved AND c.file_id IN (r.source_file_id, r.target_file_id)
+            )
+            SELECT DISTINCT COALESCE(f.path, d.path) AS note FROM relinked l
+            LEFT JOIN files f ON f.id = l.file_id
+            LEFT JOIN removed_files d ON d.file_id = l.file_id
+            WHERE note IS NOT NULL
+            ORDER BY note",
+        )?;
+        Ok(Changes { indexed, relinked })
+    }
+
+    fn tracked_paths(&self, sql: &str) -> Result<Vec<PathBuf>> {
+        // Not cached, since the tables it reads are dropped once tracking ends
+        let mut statement = self.conn.prepare(sql)?;
+        let paths = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(paths.iter().map(|path| self.vault_path(path)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::test_support::{add_note, vault};
+
+    #[test]
+    fn test_track_changes() {
+        let (temp_dir, mut index) = vault(&[("target.typ", ""), ("other.typ", "")]);
+        let root = temp_dir.path();
+
+        let (_, changes) = index
+            .track_changes(|index| {
+                add_note(index, "source.typ", "[[target]] [[target]]");
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(changes.indexed, vec![root.join("source.typ")]);
+        assert_eq!(changes.relinked, vec![root.join("source.typ")]);
+
+        // Storing the same content again changes nothing, even though its rows are rewritten
+        let (_, changes) = index
+            .track_changes(|index| {
+                add_note(index, "source.typ", "[[target]] [[target]]");
+                Ok(())
+            })
+            .unwrap();
+        assert!(changes.is_empty());
+
+        // Links by name follow a target moved to another folder, which still changes the
+        // graph by path
+        let moved = root.join("sub").join("target.typ");
+        let (_, changes) = index
+            .track_changes(|index| index.rename_file(&root.join("target.typ"), &moved))
+            .unwrap();
+        assert_eq!(changes.indexed, vec![moved]);
+        assert_eq!(changes.relinked, vec![root.join("source.typ")]);
+
+        let (_, changes) = index
+            .track_changes(|index| index.remove_file(&root.join("source.typ")))
+            .unwrap();
+        assert!(changes.indexed.is_empty());
+        assert_eq!(changes.relinked, vec![root.join("source.typ")]);
+
+        // A failed update leaves no triggers behind
+        let result = index.track_changes(|_| -> anyhow::Result<()> { anyhow::bail!("failed") });
+        assert!(result.is_err());
+        let temporary: i64 = index
+            .conn
+            .query_row("SELECT COUNT(*) FROM sqlite_temp_master", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(temporary, 0);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::index::{Index, lock};
+use anyhow::Result;
+use rusqlite::{OptionalExtension, params};
+use std::path::{Path, PathBuf};
+
+/// A note section and its embedding vector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSection {
+    pub path: PathBuf,
+    pub heading: Option<String>,
+    /// 1-based line of the section's first line
+    pub line: usize,
+    pub content: String,
+    pub vector: Vec<f32>,
+}
+
+impl Index {
+    /// Notes, with their content hash, that have no embeddings from `model` for their current
+    /// content.
+    pub fn stale_embeddings(&self, model: &str) -> Result<Vec<(PathBuf, String)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path, f.content_hash FROM files f
+             WHERE f.content_hash IS NOT NULL
+               AND NOT EXISTS (
+                 SELECT 1 FROM embeddings e
+                 WHERE e.file_id = f.id AND e.model = ? AND e.file_hash = f.content_hash
+               )
+             ORDER BY f.path",
+        )?;
+        let rows = stmt.query_map([model], |row| {
+            Ok((self.vault_path(&row.get::<_, String>(0)?), row.get(1)?))
+        })?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+        Ok(files)
+    }
+
+    /// Replace the embeddings of `file_path`, computed from content with hash `file_hash`.
+    /// Returns false, storing nothing, if the note was removed or reindexed with other content
+    /// since.
+    pub fn store_embeddings(
+        &mut self,
+        file_path: &Path,
+        file_hash: &str,
+        model: &str,
+        sections: &[StoredSection],
+    ) -> Result<bool> {
+        let relative_path = self.stored_path(file_path)?;
+
+        let tx = self.conn.transaction()?;
+        let file_id: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM files WHERE path = ? AND content_hash = ?",
+                params![relative_path, file_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(file_id) = file_id else {
+            return Ok(false);
+        };
+        tx.execute("DELETE FROM embeddings WHERE file_id = ?", [file_id])?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO embeddings (file_id, file_hash, model, heading, line, content, vector)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for section in sections {
+                insert.execute(params![
+                    file_id,
+                    file_hash,
+                    model,
+                    section.heading,
+                    section.line,
+                    section.content,
+                    encode_vector(&section.vector),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        lock(&self.cache).invalidate(file_path);
+        Ok(true)
+    }
+
+    /// The sections of `file_path` embedded with `model`, whatever content they came from.
+    pub fn file_embeddings(&self, file_path: &Path, model: &str) -> Result<Vec<StoredSection>> {
+        let relative_path = self.stored_path(file_path)?;
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT e.heading, e.line, e.content, e.vector
+             FROM embeddings e
+             JOIN files f ON f.id = e.file_id
+             WHERE f.path = ? AND e.model = ?
+             ORDER BY e.line",
+        )?;
+        let rows = stmt.query_map(params![relative_path, model], |row| {
+            Ok(StoredSection {
+                path: file_path.to_path_buf(),
+                heading: row.get(0)?,
+                line: row.get(1)?,
+                content: row.get(2)?,
+                vector: decode_vector(&row.get::<_, Vec<u8>>(3)?),
+            })
+        })?;
+
+        let mut sections = Vec::new();
+        for row in rows {
+            sections.push(row?);
+        }
+        Ok(sections)
+    }
+
+    /// Every section embedded with `model`.
+    pub fn get_embeddings(&self, model: &str) -> Result<Vec<StoredSection>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path, e.heading, e.line, e.content, e.vector
+             FROM embeddings e
+             JOIN files f ON f.id = e.file_id
+             WHERE e.model = ?
+             ORDER BY f.path, e.line",
+        )?;
+        let rows = stmt.query_map([model], |row| {
+            Ok(StoredSection {
+                path: self.vault_path(&row.get::<_, String>(0)?),
+                heading: row.get(1)?,
+                line: row.get(2)?,
+                content: row.get(3)?,
+                vector: decode_vector(&row.get::<_, Vec<u8>>(4)?),
+            })
+        })?;
+
+        let mut sections = Vec::new();
+        for row in rows {
+            sections.push(row?);
+        }
+        Ok(sections)
+    }
+}
+
+// Vectors are stored as little-endian f32s
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
@@ -0,0 +1,112 @@
+use crate::git::CommitInfo;
+use crate::index::Index;
+use anyhow::Result;
+use rusqlite::{OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+impl Index {
+    /// Replace the recorded last commit of every note with `commits`, keyed by absolute path,
+    /// as of the commit `head`. Files that are not indexed notes are ignored.
+    pub fn set_last_commits(
+        &self,
+        head: Option<&str>,
+        commits: &HashMap<PathBuf, CommitInfo>,
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM git_commits", [])?;
+        tx.execute("DELETE FROM settings WHERE key = 'git_head'", [])?;
+        if let Some(head) = head {
+            tx.execute(
+                "INSERT INTO settings (key, value) VALUES ('git_head', ?)",
+                [head],
+            )?;
+        }
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO git_commits (file_id, hash, author, committed_at, commits_ago)
+                 SELECT id, ?, ?, ?, ? FROM files WHERE path = ?",
+            )?;
+            for (path, commit) in commits {
+                let Ok(relative_path) = self.stored_path(path) else {
+                    continue;
+                };
+                insert.execute(params![
+                    commit.hash,
+                    commit.author,
+                    commit.committed_at,
+                    commit.commits_ago as i64,
+                    relative_path
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The `HEAD` commit the recorded history was read at, if any.
+    pub fn git_head(&self) -> Result<Option<String>> {
+        let head = self
+            .conn
+            .prepare_cached("SELECT value FROM settings WHERE key = 'git_head'")?
+            .query_row([], |row| row.get(0))
+            .optional()?;
+        Ok(head)
+    }
+
+    /// The last commit that changed a note, if git history is recorded and tracks it.
+    pub fn last_commit(&self, file_path: &Path) -> Result<Option<CommitInfo>> {
+        let relative_path = self.stored_path(file_path)?;
+        let commit = self
+            .conn
+            .prepare_cached(
+                "SELECT g.hash, g.author, g.committed_at, g.commits_ago
+                 FROM git_commits g JOIN files f ON f.id = g.file_id
+                 WHERE f.path = ?",
+            )?
+            .query_row([&relative_path], |row| {
+                Ok(CommitInfo {
+                    hash: row.get(0)?,
+                    author: row.get(1)?,
+                    committed_at: row.get(2)?,
+                    commits_ago: row.get::<_, i64>(3)? as usize,
+                })
+            })
+            .optional()?;
+        Ok(commit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_last_commits() {
+        let (temp_dir, index) = vault(&[("a.typ", ""), ("b.typ", "")]);
+        let root = temp_dir.path();
+        let (a, b) = (root.join("a.typ"), root.join("b.typ"));
+
+        let commit = CommitInfo {
+            hash: "0123456789".to_string(),
+            author: "Ada".to_string(),
+            committed_at: 100,
+            commits_ago: 1,
+        };
+        let commits = HashMap::from([
+            (a.clone(), commit.clone()),
+            (root.join("README.md"), commit.clone()),
+        ]);
+        index
+            .set_last_commits(Some("0123456789"), &commits)
+            .unwrap();
+        assert_eq!(index.last_commit(&a).unwrap(), Some(commit));
+        assert_eq!(index.git_head().unwrap().as_deref(), Some("0123456789"));
+        assert_eq!(index.last_commit(&b).unwrap(), None);
+
+        index.set_last_commits(None, &HashMap::new()).unwrap();
+        assert_eq!(index.last_commit(&a).unwrap(), None);
+        assert_eq!(index.git_head().unwrap(), None);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::index::cache::{DEFAULT_CAPACITY, FileCache};
+use crate::index::{CancellationToken, Index, lock};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, TryLockError};
+
+// Idle read connections kept open for reuse
+const MAX_IDLE_READERS: usize = 8;
+
+/// A cloneable, thread-safe handle to a vault's index.
+///
+/// Writes are serialized through a single connection while reads check out a pooled
+/// connection of their own, so queries keep running while the index is being updated.
+#[derive(Clone)]
+pub struct IndexHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    root: PathBuf,
+    db_path: PathBuf,
+    key: Option<String>,
+    writer: Mutex<Index>,
+    readers: Mutex<Vec<Index>>,
+    cache: Arc<Mutex<FileCache>>,
+}
+
+impl IndexHandle {
+    pub fn open(root: &Path, db_path: &Path) -> Result<Self> {
+        Self::open_with_key(root, db_path, None)
+    }
+
+    /// Open the index like [`IndexHandle::open`], with every connection using `key`.
+    pub fn open_with_key(root: &Path, db_path: &Path, key: Option<String>) -> Result<Self> {
+        // Opening the writer first runs any pending migrations
+        let cache = Arc::new(Mutex::new(FileCache::new(DEFAULT_CAPACITY)));
+        let writer =
+            Index::open_with_key(root, db_path, key.as_deref())?.with_cache(Arc::clone(&cache));
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                root: root.to_path_buf(),
+                db_path: db_path.to_path_buf(),
+                key,
+                writer: Mutex::new(writer),
+                readers: Mutex::new(Vec::new()),
+                cache,
+            }),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.shared.root
+    }
+
+    pub fn read<T>(&self, f: impl FnOnce(&Index) -> Result<T>) -> Result<T> {
+        self.read_cancellable(None, f)
+    }
+
+    /// Like [`IndexHandle::read`], but queries stop with
+    /// [`Cancelled`](crate::index::Cancelled) once `token` is cancelled.
+    pub fn read_cancellable<T>(
+        &self,
+        token: Option<&CancellationToken>,
+        f: impl FnOnce(&Index) -> Result<T>,
+    ) -> Result<T> {
+        let mut reader = match lock(&self.shared.readers).pop() {
+            Some(reader) => reader,
+            None => Index::open_with_key(
+                &self.shared.root,
+                &self.shared.db_path,
+                self.shared.key.as_deref(),
+            )?
+            .with_cache(Arc::clone(&self.shared.cache)),
+        };
+        reader.set_cancellation(token.cloned());
+        let reader = PooledReader {
+            index: Some(reader),
+            shared: &self.shared,
+        };
+
+        f(reader.index.as_ref().unwrap())
+    }
+
+    pub fn write<T>(&self, f: impl FnOnce(&mut Index) -> Result<T>) -> Result<T> {
+        f(&mut lock(&self.shared.writer))
+    }
+
+    /// Like [`IndexHandle::write`], but `None` instead of waiting while another write runs.
+    pub fn try_write<T>(&self, f: impl FnOnce(&mut Index) -> Result<T>) -> Option<Result<T>> {
+        let mut writer = match self.shared.writer.try_lock() {
+            Ok(writer) => writer,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        Some(f(&mut writer))
+    }
+}
+
+/// Returns its connection to the pool when dropped, even if the reader panicked.
+struct PooledReader<'a> {
+    index: Option<Index>,
+    shared: &'a Shared,
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        let mut readers = lock(&self.shared.readers);
+        if readers.len() < MAX_IDLE_READERS
+            && let Some(mut index) = self.index.take()
+        {
+            index.set_cancellation(None);
+            readers.push(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::models::{Metadata, ParsedFile};
+    use tempfile::tempdir;
+
+    fn parsed(path: PathBuf) -> ParsedFile {
+        ParsedFile {
+            path,
+            metadata: Metadata::default(),
+            wikilinks: vec![],
+            labels: vec![],
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_reads_see_committed_writes() {
+        let temp_dir = tempdir().unwrap();
+        let handle = IndexHandle::open(temp_dir.path(), &temp_dir.path().join("index.db")).unwrap();
+
+        let path = temp_dir.path().join("note.typ");
+        std::fs::write(&path, "content").unwrap();
+        handle
+            .write(|index| index.store_file(&path, &parsed(path.clone())))
+            .unwrap();
+
+        let files = handle.read(|index| index.get_all_files()).unwrap();
+        assert_eq!(files, vec![path]);
+    }
+
+    #[test]
+    fn test_writes_invalidate_cached_files() {
+        let temp_dir = tempdir().unwrap();
+        let handle = IndexHandle::open(temp_dir.path(), &temp_dir.path().join("index.db")).unwrap();
+
+        let path = temp_dir.path().join("note.typ");
+        std::fs::write(&path, "content").unwrap();
+        handle
+            .write(|index| index.store_file(&path, &parsed(path.clone())))
+            .unwrap();
+        let cached = handle.read(|index| index.get_file(&path)).unwrap().unwrap();
+        assert_eq!(cached.metadata.title, None);
+
+        let mut updated = parsed(path.clone());
+        updated.metadata.title = Some("Updated".to_string());
+        handle
+            .write(|index| index.store_file(&path, &updated))
+            .unwrap();
+        let file = handle.read(|index| index.get_file(&path)).unwrap().unwrap();
+        assert_eq!(file.metadata.title.as_deref(), Some("Updated"));
+
+        handle.write(|index| index.remove_file(&path)).unwrap();
+        assert!(
+            handle
+                .read(|index| index.get_file(&path))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_cancelled_reads() {
+        let temp_dir = tempdir().unwrap();
+        let handle = IndexHandle::open(temp_dir.path(), &temp_dir.path().join("index.db")).unwrap();
+        let path = temp_dir.path().join("note.typ");
+        std::fs::write(&path, "content").unwrap();
+        handle
+            .write(|index| index.store_file(&path, &parsed(path.clone())))
+            .unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let error = handle
+            .read_cancellable(Some(&token), |index| index.search("note"))
+            .unwrap_err();
+        assert!(error.is::<crate::index::Cancelled>());
+
+        // The pooled connection forgets the token
+        let files = handle.read(|index| index.search("note")).unwrap();
+        assert_eq!(files, vec![path]);
+    }
+
+    #[test]
+    fn test_concurrent_reads_during_writes() {
+        let temp_dir = tempdir().unwrap();
+        let handle = IndexHandle::open(temp_dir.path(), &temp_dir.path().join("index.db")).unwrap();
+
+        let writer = {
+            let handle = handle.clone();
+            let root = temp_dir.path().to_path_buf();
+            std::thread::spawn(move || {
+                for i in 0..20 {
+                    let path = root.join(format!("note{i}.typ"));
+                    std::fs::write(&path, "content").unwrap();
+                    handle
+                        .write(|index| index.store_file(&path, &parsed(path.clone())))
+                        .unwrap();
+                }
+            })
+        };
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        handle.read(|index| index.get_all_files()).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let files = handle.read(|index| index.get_all_files()).unwrap();
+        assert_eq!(files.len(), 20);
+    }
+
+    #[test]
+    fn test_try_write_skips_while_writing() {
+        let temp_dir = tempdir().unwrap();
+        let handle = IndexHandle::open(temp_dir.path(), &temp_dir.path().join("index.db")).unwrap();
+
+        let nested = handle
+            .write(|_| Ok(handle.try_write(|_| Ok(())).is_none()))
+            .unwrap();
+        assert!(nested);
+        assert!(handle.try_write(|_| Ok(())).is_some());
+    }
+}
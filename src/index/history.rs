@@ -0,0 +1,218 @@
+use crate::index::Index;
+use anyhow::Result;
+use rusqlite::{Transaction, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use ts_rs::TS;
+
+// Snapshots are kept per note per UTC day; later writes that day replace the day's snapshot
+const SNAPSHOT_PERIOD_SECS: i64 = 24 * 60 * 60;
+// Snapshots older than this many periods are thinned to the last one of each week
+const DAILY_PERIODS: i64 = 90;
+
+/// A note's size and connectivity as of `taken_at`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct NoteSnapshot {
+    /// Seconds since the Unix epoch
+    pub taken_at: i64,
+    pub words: usize,
+    pub characters: usize,
+    pub headings: usize,
+    /// Wikilinks in the note, resolved or not
+    pub links: usize,
+    /// Resolved links from other notes
+    pub backlinks: usize,
+    pub tags: Vec<String>,
+}
+
+/// Record the stored state of `file_id` as its snapshot for the period containing `now`, and
+/// thin out its old snapshots.
+pub(super) fn record_snapshot(tx: &Transaction, file_id: i64, now: i64) -> Result<()> {
+    let period = now.div_euclid(SNAPSHOT_PERIOD_SECS);
+    let tags: Vec<String> = tx
+        .prepare_cached(
+            "SELECT value FROM metadata WHERE file_id = ? AND key = 'tags' ORDER BY id",
+        )?
+        .query_map([file_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    tx.prepare_cached(
+        "INSERT INTO snapshots (file_id, period, taken_at, words, characters, headings, links,
+            backlinks, tags)
+         SELECT id, ?2, ?3, words, characters, headings,
+            (SELECT COUNT(*) FROM wikilinks w WHERE w.file_id = f.id),
+            (SELECT COUNT(*) FROM resolved_links r
+             WHERE r.target_file_id = f.id AND r.source_file_id != f.id),
+            ?4
+         FROM files f WHERE id = ?1
+         ON CONFLICT(file_id, period) DO UPDATE SET
+            taken_at = excluded.taken_at,
+            words = excluded.words,
+            characters = excluded.characters,
+            headings = excluded.headings,
+            links = excluded.links,
+            backlinks = excluded.backlinks,
+            tags = excluded.tags",
+    )?
+    .execute(params![file_id, period, now, serde_json::to_string(&tags)?])?;
+    tx.prepare_cached(
+        "DELETE FROM snapshots WHERE file_id = ?1 AND period < ?2 AND period NOT IN (
+            SELECT MAX(period) FROM snapshots WHERE file_id = ?1 AND period < ?2
+            GROUP BY period / 7)",
+    )?
+    .execute(params![file_id, period - DAILY_PERIODS])?;
+    Ok(())
+}
+
+/// Backlinks from other notes to each note, counted over the wikilinks selected by `links`,
+/// whose first column must be the link's id.
+pub(super) fn backlink_counts(
+    tx: &Transaction,
+    links: &str,
+    params: impl rusqlite::Params,
+) -> Result<HashMap<i64, i64>> {
+    let counts = tx
+        .prepare_cached(&format!(
+            "SELECT r.target_file_id, COUNT(*) FROM resolved_links r
+             WHERE r.source_file_id != r.target_file_id
+                AND r.wikilink_id IN (SELECT id FROM ({links}))
+             GROUP BY r.target_file_id"
+        ))?
+        .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(counts)
+}
+
+/// Snapshot the notes, other than `skip`, whose backlinks went from `before` to `after`.
+pub(super) fn record_backlink_changes(
+    tx: &Transaction,
+    skip: Option<i64>,
+    before: &HashMap<i64, i64>,
+    after: &HashMap<i64, i64>,
+    now: i64,
+) -> Result<()> {
+    for &target in before.keys().chain(after.keys()) {
+        if Some(target) != skip && before.get(&target) != after.get(&target) {
+            record_snapshot(tx, target, now)?;
+        }
+    }
+    Ok(())
+}
+
+impl Index {
+    /// A note's snapshots, oldest first. Notes get one per day in which they were indexed.
+    pub fn history(&self, file_path: &Path) -> Result<Vec<NoteSnapshot>> {
+        let relative_path = self.stored_path(file_path)?;
+        let rows: Vec<(NoteSnapshot, String)> = self
+            .conn
+            .prepare_cached(
+                "SELECT s.taken_at, s.words, s.characters, s.headings, s.links, s.backlinks,
+                    s.tags
+                 FROM snapshots s JOIN files f ON f.id = s.file_id
+                 WHERE f.path = ?
+                 ORDER BY s.period",
+            )?
+            .query_map([&relative_path], |row| {
+                let snapshot = NoteSnapshot {
+                    taken_at: row.get(0)?,
+                    words: row.get::<_, i64>(1)? as usize,
+                    characters: row.get::<_, i64>(2)? as usize,
+                    headings: row.get::<_, i64>(3)? as usize,
+                    links: row.get::<_, i64>(4)? as usize,
+                    backlinks: row.get::<_, i64>(5)? as usize,
+                    tags: Vec::new(),
+                };
+                Ok((snapshot, row.get(6)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        rows.into_iter()
+            .map(|(mut snapshot, tags)| {
+                snapshot.tags = serde_json::from_str(&tags)?;
+                Ok(snapshot)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, add_note_with, vault};
+
+    #[test]
+    fn test_history() {
+        let (_temp_dir, mut index) = vault(&[]);
+        let [a, b] =
+            [("a.typ", "One two"), ("b.typ", "[[a]] [[a]] [[c]]")].map(|(name, content)| {
+                add_note_with(&mut index, name, content, |parsed| {
+                    parsed.metadata.tags = vec!["draft".to_string()];
+                })
+            });
+
+        let history = index.history(&b).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].links, history[0].backlinks), (3, 0));
+        assert_eq!(history[0].tags, vec!["draft"]);
+
+        // Rewriting the note the same day replaces that day's snapshot
+        add_note(&mut index, "a.typ", "One two three");
+        let history = index.history(&a).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].words, history[0].backlinks), (3, 2));
+        assert!(history[0].tags.is_empty());
+
+        let tx = index.conn.unchecked_transaction().unwrap();
+        let file_id: i64 = tx
+            .query_row("SELECT id FROM files WHERE path = 'a.typ'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        record_snapshot(&tx, file_id, history[0].taken_at - SNAPSHOT_PERIOD_SECS).unwrap();
+        tx.commit().unwrap();
+        let history = index.history(&a).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].taken_at < history[1].taken_at);
+    }
+
+    #[test]
+    fn test_history_of_link_targets() {
+        let (_temp_dir, mut index) = vault(&[]);
+        let a = add_note(&mut index, "a.typ", "One");
+        let b = add_note(&mut index, "b.typ", "[[a]]");
+        assert_eq!(index.history(&a).unwrap()[0].backlinks, 1);
+
+        index.remove_file(&b).unwrap();
+        assert_eq!(index.history(&a).unwrap()[0].backlinks, 0);
+    }
+
+    #[test]
+    fn test_history_retention() {
+        let (_temp_dir, mut index) = vault(&[]);
+        let a = add_note(&mut index, "a.typ", "One");
+        let now = index.history(&a).unwrap()[0].taken_at;
+
+        let tx = index.conn.unchecked_transaction().unwrap();
+        let file_id: i64 = tx
+            .query_row("SELECT id FROM files WHERE path = 'a.typ'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        for days in (1..=DAILY_PERIODS + 70).rev() {
+            record_snapshot(&tx, file_id, now - days * SNAPSHOT_PERIOD_SECS).unwrap();
+        }
+        record_snapshot(&tx, file_id, now).unwrap();
+        tx.commit().unwrap();
+
+        // The last 90 days stay daily, the 70 days before them weekly
+        let history = index.history(&a).unwrap();
+        let days = |snapshot: &NoteSnapshot| (now - snapshot.taken_at) / SNAPSHOT_PERIOD_SECS;
+        assert_eq!(
+            history.iter().filter(|s| days(s) <= DAILY_PERIODS).count(),
+            91
+        );
+        let weekly = history.iter().filter(|s| days(s) > DAILY_PERIODS).count();
+        assert!((10..=11).contains(&weekly));
+    }
+}
@@ -0,0 +1,728 @@
+use crate::index::records::{MetadataType, classify};
+use crate::index::{resolve_links, stored_path, target_stem};
+use crate::parser::names;
+use anyhow::{Result, bail};
+use rusqlite::{Connection, Transaction, params};
+use std::path::Path;
+
+/// A schema change applied once, in order, to bring an index up to date.
+struct Migration {
+    version: u32,
+    apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        apply: initial_schema,
+    },
+    Migration {
+        version: 2,
+        apply: add_blocks,
+    },
+    Migration {
+        version: 3,
+        apply: add_content_hash,
+    },
+    Migration {
+        version: 4,
+        apply: add_resolved_links,
+    },
+    Migration {
+        version: 5,
+        apply: add_embeddings,
+    },
+    Migration {
+        version: 6,
+        apply: add_metadata_types,
+    },
+    Migration {
+        version: 7,
+        apply: add_attachments,
+    },
+    Migration {
+        version: 8,
+        apply: encode_paths,
+    },
+    Migration {
+        version: 9,
+        apply: add_settings,
+    },
+    Migration {
+        version: 10,
+        apply: normalize_names,
+    },
+    Migration {
+        version: 11,
+        apply: add_content_stats,
+    },
+    Migration {
+        version: 12,
+        apply: add_visits,
+    },
+    Migration {
+        version: 13,
+        apply: add_stars,
+    },
+    Migration {
+        version: 14,
+        apply: add_git_commits,
+    },
+    Migration {
+        version: 15,
+        apply: add_conflicts,
+    },
+    Migration {
+        version: 16,
+        apply: add_snapshots,
+    },
+    Migration {
+        version: 17,
+        apply: add_label_elements,
+    },
+    Migration {
+        version: 18,
+        apply: add_figures,
+    },
+    Migration {
+        version: 19,
+        apply: add_bibliographies,
+    },
+    Migration {
+        version: 20,
+        apply: add_packages,
+    },
+    Migration {
+        version: 21,
+        apply: add_truncated_rows,
+    },
+    Migration {
+        version: 22,
+        apply: add_nocase_indexes,
+    },
+];
+
+pub const LATEST_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Apply every migration newer than the database's `user_version`, each in its own transaction.
+pub fn run(conn: &mut Connection) -> Result<()> {
+    let version = current_version(conn)?;
+    if version > LATEST_VERSION {
+        bail!(
+            "Index schema version {version} is newer than supported version {LATEST_VERSION}; \
+             delete the index to rebuild it"
+        );
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({table})"))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if row.get::<_, String>(1)? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Unversioned caches already contain these tables, so creation must be idempotent
+fn initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS files (
+            id INTEGER PRIMARY KEY,
+            path TEXT UNIQUE NOT NULL,
+            created_at DATETIME,
+            modified_at DATETIME,
+            last_parsed DATETIME
+        );
+
+        CREATE TABLE IF NOT EXISTS metadata (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER,
+            key TEXT NOT NULL,
+            value TEXT,
+            FOREIGN KEY (file_id) REFERENCES files(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS wikilinks (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER,
+            target TEXT NOT NULL,
+            alias TEXT,
+            label TEXT,
+            line INTEGER,
+            column INTEGER,
+            FOREIGN KEY (file_id) REFERENCES files(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS labels (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER,
+            name TEXT NOT NULL,
+            line INTEGER,
+            column INTEGER,
+            FOREIGN KEY (file_id) REFERENCES files(id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_files_path ON files(path);
+        CREATE INDEX IF NOT EXISTS idx_metadata_file_id ON metadata(file_id);
+        CREATE INDEX IF NOT EXISTS idx_wikilinks_file_id ON wikilinks(file_id);
+        CREATE INDEX IF NOT EXISTS idx_wikilinks_target ON wikilinks(target);
+        CREATE INDEX IF NOT EXISTS idx_labels_file_id ON labels(file_id);
+        CREATE INDEX IF NOT EXISTS idx_labels_name ON labels(name);",
+    )
+}
+
+fn add_blocks(tx: &Transaction) -> rusqlite::Result<()> {
+    if !has_column(tx, "wikilinks", "block")? {
+        tx.execute("ALTER TABLE wikilinks ADD COLUMN block TEXT", [])?;
+    }
+
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS blocks (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER,
+            block_id TEXT NOT NULL,
+            line INTEGER,
+            column INTEGER,
+            FOREIGN KEY (file_id) REFERENCES files(id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_blocks_file_id ON blocks(file_id);",
+    )
+}
+
+fn add_content_hash(tx: &Transaction) -> rusqlite::Result<()> {
+    if !has_column(tx, "files", "content_hash")? {
+        tx.execute("ALTER TABLE files ADD COLUMN content_hash TEXT", [])?;
+    }
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_files_content_hash ON files(content_hash)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_resolved_links(tx: &Transaction) -> rusqlite::Result<()> {
+    if !has_column(tx, "files", "stem")? {
+        tx.execute("ALTER TABLE files ADD COLUMN stem TEXT", [])?;
+    }
+    if !has_column(tx, "wikilinks", "target_stem")? {
+        tx.execute("ALTER TABLE wikilinks ADD COLUMN target_stem TEXT", [])?;
+    }
+
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS resolved_links (
+            wikilink_id INTEGER PRIMARY KEY REFERENCES wikilinks(id) ON DELETE CASCADE,
+            source_file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            target_file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            target_label_id INTEGER REFERENCES labels(id) ON DELETE SET NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_files_stem ON files(stem);
+        CREATE INDEX IF NOT EXISTS idx_wikilinks_target_stem ON wikilinks(target_stem);
+        CREATE INDEX IF NOT EXISTS idx_resolved_links_source ON resolved_links(source_file_id);
+        CREATE INDEX IF NOT EXISTS idx_resolved_links_target ON resolved_links(target_file_id);",
+    )?;
+
+    // Backfill the lookup columns, then resolve every existing link
+    let files: Vec<(i64, String)> = tx
+        .prepare("SELECT id, path FROM files")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, path) in files {
+        tx.execute(
+            "UPDATE files SET stem = ? WHERE id = ?",
+            params![target_stem(&path), id],
+        )?;
+    }
+
+    let links: Vec<(i64, String)> = tx
+        .prepare("SELECT id, target FROM wikilinks")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, target) in links {
+        tx.execute(
+            "UPDATE wikilinks SET target_stem = ? WHERE id = ?",
+            params![target_stem(&target), id],
+        )?;
+    }
+
+    resolve_links(tx, "SELECT id, file_id, target, label FROM wikilinks", [])
+}
+
+/// Section vectors for semantic search. The table exists in every build so one database
+/// works with and without the `embeddings` feature.
+fn add_embeddings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS embeddings (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            file_hash TEXT NOT NULL,
+            model TEXT NOT NULL,
+            heading TEXT,
+            line INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            vector BLOB NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_embeddings_file ON embeddings(file_id);",
+    )
+}
+
+/// Typed copies of metadata values, backfilled from the stored JSON.
+fn add_metadata_types(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE metadata ADD COLUMN value_type TEXT;
+         ALTER TABLE metadata ADD COLUMN number_value REAL;",
+    )?;
+
+    let rows: Vec<(i64, String, Option<String>)> = tx
+        .prepare("SELECT id, key, value FROM metadata")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, key, value) in rows {
+        let json = value
+            .as_deref()
+            .and_then(|value| serde_json::from_str(value).ok());
+        let (value_type, number) = match json {
+            Some(json) if !matches!(key.as_str(), "title" | "tags" | "alias") => classify(&json),
+            _ => (MetadataType::Text, None),
+        };
+        tx.execute(
+            "UPDATE metadata SET value_type = ?, number_value = ? WHERE id = ?",
+            params![value_type.as_str(), number, id],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_attachments(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER,
+            target TEXT NOT NULL,
+            path TEXT,
+            embedded INTEGER NOT NULL,
+            line INTEGER,
+            column INTEGER,
+            FOREIGN KEY (file_id) REFERENCES files(id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_attachments_file_id ON attachments(file_id);
+        CREATE INDEX IF NOT EXISTS idx_attachments_path ON attachments(path);
+
+        -- Force a reparse so existing notes record their attachments
+        UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// Rewrite paths stored verbatim into the `/`-separated, `%`-escaped form of `stored_path`.
+fn encode_paths(tx: &Transaction) -> rusqlite::Result<()> {
+    for table in ["files", "attachments"] {
+        let rows: Vec<(i64, String)> = tx
+            .prepare(&format!(
+                "SELECT id, path FROM {table} WHERE path IS NOT NULL"
+            ))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (id, path) in rows {
+            let encoded = stored_path::encode(Path::new(&path));
+            if encoded != path {
+                tx.execute(
+                    &format!("UPDATE {table} SET path = ? WHERE id = ?"),
+                    params![encoded, id],
+                )?;
+            }
+        }
+    }
+
+    resolve_links(tx, "SELECT id, file_id, target, label FROM wikilinks", [])
+}
+
+/// Options that change how the index resolves links, such as `case_insensitive_links`.
+fn add_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value
+        );",
+    )
+}
+
+/// Compare note stems and labels in NFC and record heading slugs as labels. Every file is
+/// marked unparsed so the next scan adds its headings.
+fn normalize_names(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "ALTER TABLE labels ADD COLUMN heading INTEGER NOT NULL DEFAULT 0",
+        [],
+    )?;
+    for (table, column) in [
+        ("files", "stem"),
+        ("wikilinks", "target_stem"),
+        ("labels", "name"),
+    ] {
+        let rows: Vec<(i64, String)> = tx
+            .prepare(&format!(
+                "SELECT id, {column} FROM {table} WHERE {column} IS NOT NULL"
+            ))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (id, name) in rows {
+            let normalized = names::normalize(&name);
+            if normalized != name {
+                tx.execute(
+                    &format!("UPDATE {table} SET {column} = ? WHERE id = ?"),
+                    params![normalized, id],
+                )?;
+            }
+        }
+    }
+    tx.execute("UPDATE files SET content_hash = NULL", [])?;
+
+    resolve_links(tx, "SELECT id, file_id, target, label FROM wikilinks", [])
+}
+
+/// Word, character, and heading counts of each note. Every file is marked unparsed so the
+/// next scan counts them.
+fn add_content_stats(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE files ADD COLUMN words INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE files ADD COLUMN characters INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE files ADD COLUMN headings INTEGER NOT NULL DEFAULT 0;
+         UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// How often and how recently each note was opened, for quick-switcher rankings.
+fn add_visits(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS visits (
+            file_id INTEGER PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            count INTEGER NOT NULL,
+            last_visited INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Notes the user starred, kept with the index rather than in the notes themselves.
+fn add_stars(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS stars (
+            file_id INTEGER PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            starred_at INTEGER NOT NULL
+        );",
+    )
+}
+
+/// The last git commit to change each note, recorded when `git_history` is on.
+fn add_git_commits(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS git_commits (
+            file_id INTEGER PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            hash TEXT NOT NULL,
+            author TEXT NOT NULL,
+            committed_at INTEGER NOT NULL,
+            commits_ago INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Git conflict blocks left in notes, reported as errors until resolved. Existing notes are
+/// re-parsed so conflicts already on disk are found.
+fn add_conflicts(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS conflicts (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            line INTEGER NOT NULL,
+            base_line INTEGER,
+            separator_line INTEGER NOT NULL,
+            end_line INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_conflicts_file_id ON conflicts(file_id);
+        UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// Daily snapshots of each note's size, links and tags, for `typst-oxide/history`.
+fn add_snapshots(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS snapshots (
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            period INTEGER NOT NULL,
+            taken_at INTEGER NOT NULL,
+            words INTEGER NOT NULL,
+            characters INTEGER NOT NULL,
+            headings INTEGER NOT NULL,
+            links INTEGER NOT NULL,
+            backlinks INTEGER NOT NULL,
+            tags TEXT NOT NULL,
+            PRIMARY KEY (file_id, period)
+        );",
+    )
+}
+
+/// What each label is attached to and where that element is, as JSON. Notes are re-parsed to
+/// fill them in.
+fn add_label_elements(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE labels ADD COLUMN kind TEXT;
+         ALTER TABLE labels ADD COLUMN element TEXT;
+         UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// Figures and labeled equations with their kind and caption. Notes are re-parsed to find
+/// them.
+fn add_figures(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS figures (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            kind TEXT NOT NULL,
+            caption TEXT,
+            label TEXT,
+            line INTEGER NOT NULL,
+            column INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_figures_file_id ON figures(file_id);
+        UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// `@key` citations, the bibliography files each note uses, and the entries read from those
+/// files. Notes are re-parsed to find citations and bibliographies.
+fn add_bibliographies(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS citations (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            key TEXT NOT NULL,
+            line INTEGER NOT NULL,
+            column INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_citations_file_id ON citations(file_id);
+        CREATE TABLE IF NOT EXISTS bibliographies (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            target TEXT NOT NULL,
+            path TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_bibliographies_file_id ON bibliographies(file_id);
+        CREATE TABLE IF NOT EXISTS bib_files (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            content_hash TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS bib_entries (
+            id INTEGER PRIMARY KEY,
+            bib_id INTEGER NOT NULL REFERENCES bib_files(id) ON DELETE CASCADE,
+            key TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            title TEXT,
+            authors TEXT NOT NULL,
+            year TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_bib_entries_bib_id ON bib_entries(bib_id, key);
+        UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// `#import "@namespace/name:version"` package imports. Notes are re-parsed to find them.
+fn add_packages(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS packages (
+            id INTEGER PRIMARY KEY,
+            file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            namespace TEXT NOT NULL,
+            name TEXT NOT NULL,
+            version TEXT NOT NULL,
+            line INTEGER NOT NULL,
+            column INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_packages_file_id ON packages(file_id);
+        UPDATE files SET content_hash = NULL;",
+    )
+}
+
+/// How many parsed rows of each note were left out of the index by `max_index_rows`.
+fn add_truncated_rows(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE files ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0;")
+}
+
+/// Indexes for matching links to notes regardless of case.
+fn add_nocase_indexes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_files_path_nocase ON files(path COLLATE NOCASE);
+         CREATE INDEX IF NOT EXISTS idx_files_stem_nocase ON files(stem COLLATE NOCASE);
+         CREATE INDEX IF NOT EXISTS idx_wikilinks_target_stem_nocase
+             ON wikilinks(target_stem COLLATE NOCASE);",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrates_unversioned_cache() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // The schema shipped before versioning existed
+        conn.execute_batch(
+            "CREATE TABLE files (id INTEGER PRIMARY KEY, path TEXT UNIQUE NOT NULL,
+                created_at DATETIME, modified_at DATETIME, last_parsed DATETIME);
+             CREATE TABLE wikilinks (id INTEGER PRIMARY KEY, file_id INTEGER,
+                target TEXT NOT NULL, alias TEXT, label TEXT, line INTEGER, column INTEGER);
+             INSERT INTO files (path) VALUES ('kept.typ');
+             INSERT INTO wikilinks (file_id, target) VALUES (1, 'kept');",
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), LATEST_VERSION);
+        let kept: i64 = conn
+            .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(kept, 1);
+        let resolved: i64 = conn
+            .query_row("SELECT COUNT(*) FROM resolved_links", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(resolved, 1);
+        conn.execute(
+            "INSERT INTO wikilinks (file_id, target, block) VALUES (1, 'a', 'b')",
+            [],
+        )
+        .unwrap();
+        conn.execute("INSERT INTO blocks (file_id, block_id) VALUES (1, 'b')", [])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_encodes_stored_paths() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE files (id INTEGER PRIMARY KEY, path TEXT UNIQUE NOT NULL,
+                created_at DATETIME, modified_at DATETIME, last_parsed DATETIME);
+             INSERT INTO files (path) VALUES ('notes/100%.typ'), ('plain.typ');",
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+
+        let paths: Vec<String> = conn
+            .prepare("SELECT path FROM files ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(paths, vec!["notes/100%25.typ", "plain.typ"]);
+    }
+
+    #[test]
+    fn test_normalizes_names() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE files (id INTEGER PRIMARY KEY, path TEXT UNIQUE NOT NULL,
+                created_at DATETIME, modified_at DATETIME, last_parsed DATETIME);
+             CREATE TABLE wikilinks (id INTEGER PRIMARY KEY, file_id INTEGER,
+                target TEXT NOT NULL, alias TEXT, label TEXT, line INTEGER, column INTEGER);
+             CREATE TABLE labels (id INTEGER PRIMARY KEY, file_id INTEGER,
+                name TEXT NOT NULL, line INTEGER, column INTEGER);",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO files (path) VALUES (?), ('index.typ')",
+            ["cafe\u{301}.typ"],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO wikilinks (file_id, target, label) VALUES (2, 'café', 'crème')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO labels (file_id, name) VALUES (1, ?)",
+            ["cre\u{300}me"],
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+
+        let (stem, hash): (String, Option<String>) = conn
+            .query_row(
+                "SELECT stem, content_hash FROM files WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((stem.as_str(), hash), ("café", None));
+        let resolved: (i64, Option<i64>) = conn
+            .query_row(
+                "SELECT target_file_id, target_label_id FROM resolved_links",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(resolved, (1, Some(1)));
+    }
+
+    #[test]
+    fn test_rejects_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", LATEST_VERSION + 1)
+            .unwrap();
+
+        assert!(run(&mut conn).is_err());
+    }
+
+    #[test]
+    fn test_backfills_metadata_types() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE files (id INTEGER PRIMARY KEY, path TEXT UNIQUE NOT NULL,
+                created_at DATETIME, modified_at DATETIME, last_parsed DATETIME);
+             CREATE TABLE metadata (id INTEGER PRIMARY KEY, file_id INTEGER,
+                key TEXT NOT NULL, value TEXT);
+             INSERT INTO files (path) VALUES ('note.typ');
+             INSERT INTO metadata (file_id, key, value)
+                VALUES (1, 'due', '\"2024-01-02\"'), (1, 'priority', '3'), (1, 'title', '42');",
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+
+        let types: Vec<(String, Option<f64>)> = conn
+            .prepare("SELECT value_type, number_value FROM metadata ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            types,
+            vec![
+                ("date".to_string(), Some(1_704_153_600.0)),
+                ("number".to_string(), Some(3.0)),
+                ("text".to_string(), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,2479 @@
+mod archive;
+pub(crate) mod attachments;
+mod bibliography;
+mod cache;
+mod cancel;
+mod candidates;
+mod changes;
+#[cfg(feature = "embeddings")]
+mod embeddings;
+mod git;
+mod handle;
+mod history;
+mod migrations;
+mod records;
+mod redirects;
+mod stars;
+mod stats;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod verify;
+mod visits;
+
+pub(crate) use attachments::normalize;
+pub use cancel::{CancellationToken, Cancelled};
+pub use candidates::LinkCandidate;
+pub use changes::Changes;
+#[cfg(feature = "embeddings")]
+pub use embeddings::StoredSection;
+pub use handle::IndexHandle;
+pub use history::NoteSnapshot;
+pub use records::{MetadataEntry, MetadataType, NoteRecord};
+pub use stars::StarredNote;
+pub use stats::{LinkedNote, ModifiedNote, VaultStats};
+pub(crate) use stored_path::os_string_from_bytes;
+pub use verify::IndexReport;
+pub use visits::{VisitOrder, VisitedNote};
+
+use crate::parser::models::{
+    Attachment, Block, Citation, Conflict, ContentStats, Figure, FigureKind, Label, LabelKind,
+    PackageImport, ParsedFile, Wikilink,
+};
+use crate::parser::names;
+use crate::parser::stats::reading_minutes;
+use crate::resolver::{self, target_stem};
+use crate::stored_path;
+use anyhow::{Context, Result};
+use cache::FileCache;
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Index {
+    conn: Connection,
+    root: PathBuf,
+    cache: Arc<Mutex<FileCache>>,
+    cancellation: Option<CancellationToken>,
+    // `case_insensitive_links` as of a `PRAGMA data_version`
+    folds_case: Cell<Option<(i64, bool)>>,
+    archived: RefCell<Option<archive::Archived>>,
+}
+
+impl Index {
+    pub fn new(root: &Path) -> Result<Self> {
+        Self::open(root, &root.join(".pkm-cache.db"))
+    }
+
+    /// Open the index for the vault at `root`, storing the database at `db_path`.
+    pub fn open(root: &Path, db_path: &Path) -> Result<Self> {
+        Self::open_with_key(root, db_path, None)
+    }
+
+    /// Open the index like [`Index::open`], encrypting the database with `key` if given. Keys
+    /// need the `encryption` feature, which swaps SQLite for SQLCipher.
+    pub fn open_with_key(root: &Path, db_path: &Path, key: Option<&str>) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut conn = Connection::open(db_path)?;
+        if let Some(key) = key {
+            apply_key(&conn, key)?;
+            // SQLCipher only checks the key on the first read
+            conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+                .with_context(|| {
+                    format!(
+                        "Cannot decrypt the index at {}; check the key, or delete the file \
+                         if it was created without encryption",
+                        db_path.display()
+                    )
+                })?;
+        }
+        // WAL lets readers proceed while a writer holds the database
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.set_prepared_statement_cache_capacity(64);
+        migrations::run(&mut conn)
+            .with_context(|| format!("Failed to migrate index at {}", db_path.display()))?;
+
+        Ok(Index {
+            conn,
+            root: root.to_path_buf(),
+            cache: Arc::new(Mutex::new(FileCache::new(cache::DEFAULT_CAPACITY))),
+            cancellation: None,
+            folds_case: Cell::new(None),
+            archived: RefCell::new(None),
+        })
+    }
+
+    // Share one parsed-file cache between connections to the same database
+    fn with_cache(mut self, cache: Arc<Mutex<FileCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Make long-running queries stop with [`Cancelled`] once `token` is cancelled.
+    pub fn set_cancellation(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// Fail with [`Cancelled`] if the current query's token has been cancelled.
+    pub fn check_cancelled(&self) -> Result<()> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => Err(Cancelled.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Changes whenever a note is stored or removed, so values derived from the index can be
+    /// cached until then.
+    pub fn generation(&self) -> u64 {
+        lock(&self.cache).generation()
+    }
+
+    pub fn schema_version(&self) -> Result<u32> {
+        migrations::current_version(&self.conn)
+    }
+
+    pub fn store_file(&mut self, file_path: &Path, parsed: &ParsedFile) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        write_file(&tx, &self.root, file_path, parsed)?;
+        tx.commit()?;
+        lock(&self.cache).invalidate(file_path);
+        Ok(())
+    }
+
+    /// Store many parsed files in a single transaction.
+    pub fn store_files_bulk(&mut self, files: &[ParsedFile]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for parsed in files {
+            write_file(&tx, &self.root, &parsed.path, parsed)?;
+        }
+        tx.commit()?;
+
+        let mut cache = lock(&self.cache);
+        for parsed in files {
+            cache.invalidate(&parsed.path);
+        }
+        Ok(())
+    }
+
+    pub fn get_file(&self, file_path: &Path) -> Result<Option<ParsedFile>> {
+        let generation = {
+            let mut cache = lock(&self.cache);
+            if let Some(file) = cache.get(file_path) {
+                return Ok(Some(file));
+            }
+            cache.generation()
+        };
+
+        let file = self.load_file(file_path)?;
+        if let Some(file) = &file {
+            lock(&self.cache).insert(generation, file.clone());
+        }
+        Ok(file)
+    }
+
+    fn load_file(&self, file_path: &Path) -> Result<Option<ParsedFile>> {
+        let relative_path = self.stored_path(file_path)?;
+
+        let file: Option<(i64, ContentStats)> = self
+            .conn
+            .query_row(
+                "SELECT id, words, characters, headings FROM files WHERE path = ?",
+                [&relative_path],
+                |row| {
+                    let words = row.get::<_, i64>(1)? as usize;
+                    let stats = ContentStats {
+                        words,
+                        characters: row.get::<_, i64>(2)? as usize,
+                        headings: row.get::<_, i64>(3)? as usize,
+                        reading_minutes: reading_minutes(words),
+                    };
+                    Ok((row.get(0)?, stats))
+                },
+            )
+            .optional()?;
+
+        let Some((file_id, stats)) = file else {
+            return Ok(None);
+        };
+
+        let mut metadata = crate::parser::models::Metadata::default();
+
+        // Get metadata
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT key, value FROM metadata WHERE file_id = ?")?;
+        let metadata_rows = stmt.query_map([file_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        for row in metadata_rows {
+            let (key, value) = row?;
+            match key.as_str() {
+                "title" => metadata.title = Some(value),
+                "tags" => metadata.tags.push(value),
+                "alias" => metadata.alias.push(value),
+                _ => {
+                    if let Ok(json_value) = serde_json::from_str(&value) {
+                        metadata.custom.insert(key, json_value);
+                    }
+                }
+            }
+        }
+
+        // Get wikilinks
+        let mut wikilinks = Vec::new();
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT target, alias, label, block, line, column FROM wikilinks WHERE file_id = ?",
+        )?;
+        let wikilink_rows = stmt.query_map([file_id], |row| {
+            Ok(Wikilink {
+                target: row.get(0)?,
+                alias: row.get(1)?,
+                label: row.get(2)?,
+                block: row.get(3)?,
+                line: row.get::<_, i64>(4)? as usize,
+                column: row.get::<_, i64>(5)? as usize,
+            })
+        })?;
+
+        for wikilink in wikilink_rows {
+            wikilinks.push(wikilink?);
+        }
+
+        // Get labels and heading slugs
+        let (mut labels, mut headings) = (Vec::new(), Vec::new());
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {LABEL_COLUMNS}, heading FROM labels WHERE file_id = ?"
+        ))?;
+        let label_rows = stmt.query_map([file_id], |row| {
+            Ok((label_from_row(row, 0)?, row.get::<_, bool>(5)?))
+        })?;
+
+        for row in label_rows {
+            match row? {
+                (label, true) => headings.push(label),
+                (label, false) => labels.push(label),
+            }
+        }
+
+        // Get blocks
+        let mut blocks = Vec::new();
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT block_id, line, column FROM blocks WHERE file_id = ?")?;
+        let block_rows = stmt.query_map([file_id], |row| {
+            Ok(Block {
+                id: row.get(0)?,
+                line: row.get::<_, i64>(1)? as usize,
+                column: row.get::<_, i64>(2)? as usize,
+            })
+        })?;
+
+        for block in block_rows {
+            blocks.push(block?);
+        }
+
+        // Get attachments
+        let mut attachments = Vec::new();
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT target, embedded, line, column FROM attachments WHERE file_id = ?",
+        )?;
+        let attachment_rows = stmt.query_map([file_id], |row| {
+            Ok(Attachment {
+                target: row.get(0)?,
+                embedded: row.get(1)?,
+                line: row.get::<_, i64>(2)? as usize,
+                column: row.get::<_, i64>(3)? as usize,
+            })
+        })?;
+
+        for attachment in attachment_rows {
+            attachments.push(attachment?);
+        }
+
+        let conflicts = self
+            .conn
+            .prepare_cached(
+                "SELECT line, base_line, separator_line, end_line
+                 FROM conflicts WHERE file_id = ? ORDER BY line",
+            )?
+            .query_map([file_id], conflict_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let figures = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {FIGURE_COLUMNS} FROM figures WHERE file_id = ? ORDER BY line, column"
+            ))?
+            .query_map([file_id], |row| figure_from_row(row, 0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let citations = self
+            .conn
+            .prepare_cached(
+                "SELECT key, line, column FROM citations WHERE file_id = ? ORDER BY line, column",
+            )?
+            .query_map([file_id], |row| {
+                Ok(Citation {
+                    key: row.get(0)?,
+                    line: row.get::<_, i64>(1)? as usize,
+                    column: row.get::<_, i64>(2)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let bibliographies = self
+            .conn
+            .prepare_cached("SELECT target FROM bibliographies WHERE file_id = ? ORDER BY id")?
+            .query_map([file_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let packages = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {PACKAGE_COLUMNS} FROM packages WHERE file_id = ? ORDER BY line, column"
+            ))?
+            .query_map([file_id], |row| package_from_row(row, 0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(Some(ParsedFile {
+            path: file_path.to_path_buf(),
+            metadata,
+            wikilinks,
+            labels,
+            headings,
+            blocks,
+            attachments,
+            stats,
+            conflicts,
+            figures,
+            citations,
+            bibliographies,
+            packages,
+        }))
+    }
+
+    pub fn get_block(&self, file_path: &Path, block_id: &str) -> Result<Option<Block>> {
+        let relative_path = self.stored_path(file_path)?;
+
+        let block = self
+            .conn
+            .query_row(
+                "SELECT b.block_id, b.line, b.column
+                 FROM blocks b
+                 JOIN files f ON b.file_id = f.id
+                 WHERE f.path = ? AND b.block_id = ?",
+                params![&relative_path, block_id],
+                |row| {
+                    Ok(Block {
+                        id: row.get(0)?,
+                        line: row.get::<_, i64>(1)? as usize,
+                        column: row.get::<_, i64>(2)? as usize,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(block)
+    }
+
+    /// Every unresolved merge conflict in the vault, or only in `file` if given, ordered by
+    /// file and line.
+    pub fn get_conflicts(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Conflict)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        let conflicts = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT c.line, c.base_line, c.separator_line, c.end_line, f.path
+                 FROM conflicts c JOIN files f ON f.id = c.file_id
+                 WHERE true {}
+                 ORDER BY f.path, c.line",
+                scope.condition("c.file_id")
+            ))?
+            .query_map(scope.params(), |row| {
+                let path = self.vault_path(&row.get::<_, String>(4)?);
+                Ok((path, conflict_from_row(row)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(conflicts)
+    }
+
+    /// Every figure and labeled equation in the vault, ordered by file and position.
+    pub fn get_figures(&self) -> Result<Vec<(PathBuf, Figure)>> {
+        let figures = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT f.path, {FIGURE_COLUMNS} FROM figures JOIN files f ON f.id = file_id
+                 ORDER BY f.path, line, column"
+            ))?
+            .query_map([], |row| {
+                let path = self.vault_path(&row.get::<_, String>(0)?);
+                Ok((path, figure_from_row(row, 1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(figures)
+    }
+
+    /// Every package import in the vault, ordered by package, version, file and position.
+    pub fn get_packages(&self) -> Result<Vec<(PathBuf, PackageImport)>> {
+        let packages = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT f.path, {PACKAGE_COLUMNS} FROM packages JOIN files f ON f.id = file_id
+                 ORDER BY namespace, name, version, f.path, line, column"
+            ))?
+            .query_map([], |row| {
+                let path = self.vault_path(&row.get::<_, String>(0)?);
+                Ok((path, package_from_row(row, 1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(packages)
+    }
+
+    /// The label `name` in a note, or the heading it is the slug of.
+    pub fn get_label(&self, file_path: &Path, name: &str) -> Result<Option<Label>> {
+        let relative_path = self.stored_path(file_path)?;
+        let file_id: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT id FROM files WHERE path = ?",
+                [&relative_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(file_id) = file_id else {
+            return Ok(None);
+        };
+
+        let label = self
+            .conn
+            .query_row(
+                &format!("SELECT {LABEL_COLUMNS} FROM labels {FIND_LABEL}"),
+                params![file_id, names::normalize(name), names::slug(name)],
+                |row| label_from_row(row, 0),
+            )
+            .optional()?;
+
+        Ok(label)
+    }
+
+    /// Resolve a wikilink target to a file in the workspace.
+    ///
+    /// Targets with a non-`.typ` extension are looked up on disk; note targets match
+    /// an exact vault-relative path first and fall back to any note with the same stem.
+    pub fn resolve_target(&self, target: &str) -> Result<Option<PathBuf>> {
+        let target_path = Path::new(target);
+
+        if let Some(extension) = target_path.extension()
+            && extension != "typ"
+        {
+            let path = self.root.join(target_path);
+            if path.exists() {
+                return Ok(Some(path));
+            }
+            return self.find_attachment(target);
+        }
+
+        let note = find_note(&self.conn, target, self.case_insensitive_links()?)?;
+        Ok(note.map(|(_, path)| self.vault_path(&path)))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Links from any note that resolve to `target_file`.
+    pub fn get_backward_links(&self, target_file: &Path) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let relative_path = self.stored_path(target_file)?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column
+             FROM resolved_links r
+             JOIN wikilinks w ON w.id = r.wikilink_id
+             JOIN files f ON f.id = r.source_file_id
+             JOIN files t ON t.id = r.target_file_id
+             WHERE t.path = ?
+             ORDER BY f.path, w.line, w.column",
+        )?;
+
+        let rows = stmt.query_map([&relative_path], |row| self.sourced_link(row))?;
+
+        let mut backlinks = Vec::new();
+        for row in rows {
+            backlinks.push(row?);
+        }
+
+        Ok(backlinks)
+    }
+
+    /// Links to `label` in `target_file`, e.g. `[[target:label]]`, including links that name
+    /// it in another Unicode form or, for a heading's slug, by the heading text.
+    pub fn get_backlinks_to_label(
+        &self,
+        target_file: &Path,
+        label: &str,
+    ) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let relative_path = self.stored_path(target_file)?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column
+             FROM resolved_links r
+             JOIN wikilinks w ON w.id = r.wikilink_id
+             JOIN files f ON f.id = r.source_file_id
+             JOIN files t ON t.id = r.target_file_id
+             LEFT JOIN labels l ON l.id = r.target_label_id
+             WHERE t.path = ?1 AND (w.label = ?2 OR l.name = ?3)
+             ORDER BY f.path, w.line, w.column",
+        )?;
+
+        let rows = stmt.query_map(
+            params![&relative_path, label, names::normalize(label)],
+            |row| self.sourced_link(row),
+        )?;
+
+        let mut backlinks = Vec::new();
+        for row in rows {
+            backlinks.push(row?);
+        }
+
+        Ok(backlinks)
+    }
+
+    /// Links whose target does not match any note in the index.
+    pub fn get_unresolved_links(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        self.query_links(
+            &format!(
+                "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column
+                 FROM wikilinks w
+                 JOIN files f ON f.id = w.file_id
+                 LEFT JOIN resolved_links r ON r.wikilink_id = w.id
+                 WHERE r.wikilink_id IS NULL {}
+                 ORDER BY f.path, w.line, w.column",
+                scope.condition("w.file_id")
+            ),
+            &scope,
+        )
+    }
+
+    pub fn get_forward_links(&self, file_path: &Path) -> Result<Vec<Wikilink>> {
+        let relative_path = self.stored_path(file_path)?;
+
+        let file_id: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT id FROM files WHERE path = ?",
+                [&relative_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let Some(file_id) = file_id else {
+            return Ok(Vec::new());
+        };
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT target, alias, label, block, line, column FROM wikilinks WHERE file_id = ?",
+        )?;
+
+        let rows = stmt.query_map([file_id], |row| {
+            Ok(Wikilink {
+                target: row.get(0)?,
+                alias: row.get(1)?,
+                label: row.get(2)?,
+                block: row.get(3)?,
+                line: row.get::<_, i64>(4)? as usize,
+                column: row.get::<_, i64>(5)? as usize,
+            })
+        })?;
+
+        let mut wikilinks = Vec::new();
+        for wikilink in rows {
+            wikilinks.push(wikilink?);
+        }
+
+        Ok(wikilinks)
+    }
+
+    /// Every link in the vault with the note it is in, resolved or not.
+    pub fn get_all_links(&self) -> Result<Vec<(PathBuf, Wikilink)>> {
+        self.query_links(
+            "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column
+             FROM wikilinks w
+             JOIN files f ON f.id = w.file_id
+             ORDER BY f.path, w.line, w.column",
+            &FileScope(None),
+        )
+    }
+
+    /// Resolved links whose `:label` does not exist in the target note, only those in `file`
+    /// if given.
+    pub fn get_missing_label_links(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        self.query_links(
+            &format!(
+                "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column
+                 FROM resolved_links r
+                 JOIN wikilinks w ON w.id = r.wikilink_id
+                 JOIN files f ON f.id = r.source_file_id
+                 WHERE w.label IS NOT NULL AND r.target_label_id IS NULL {}
+                 ORDER BY f.path, w.line, w.column",
+                scope.condition("r.source_file_id")
+            ),
+            &scope,
+        )
+    }
+
+    /// Resolved links whose `#^block` does not exist in the target note, only those in `file`
+    /// if given.
+    pub fn get_missing_block_links(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        self.query_links(
+            &format!(
+                "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column
+                 FROM resolved_links r
+                 JOIN wikilinks w ON w.id = r.wikilink_id
+                 JOIN files f ON f.id = r.source_file_id
+                 WHERE w.block IS NOT NULL AND NOT EXISTS (
+                     SELECT 1 FROM blocks b
+                     WHERE b.file_id = r.target_file_id AND b.block_id = w.block) {}
+                 ORDER BY f.path, w.line, w.column",
+                scope.condition("r.source_file_id")
+            ),
+            &scope,
+        )
+    }
+
+    /// Links resolved by a name that several notes share, with the note they resolve to and
+    /// how many notes have that name, only those in `file` if given. Links by path are never
+    /// ambiguous.
+    pub fn get_ambiguous_links(
+        &self,
+        file: Option<&Path>,
+    ) -> Result<Vec<(PathBuf, Wikilink, PathBuf, usize)>> {
+        let fold_case = self.case_insensitive_links()?;
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        // Count the notes per name once, rather than for every link
+        let collation = if fold_case { "COLLATE NOCASE" } else { "" };
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "WITH shared AS (
+                 SELECT stem, COUNT(*) AS notes FROM files
+                 GROUP BY stem {collation} HAVING COUNT(*) > 1
+             )
+             SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column, t.path,
+                s.notes
+             FROM resolved_links r
+             JOIN wikilinks w ON w.id = r.wikilink_id
+             JOIN shared s ON s.stem = w.target_stem {collation}
+             JOIN files f ON f.id = r.source_file_id
+             JOIN files t ON t.id = r.target_file_id
+             WHERE true {}
+             ORDER BY f.path, w.line, w.column",
+            scope.condition("r.source_file_id")
+        ))?;
+        let rows = stmt.query_map(scope.params(), |row| {
+            let (source, wikilink) = self.sourced_link(row)?;
+            let target: String = row.get(7)?;
+            Ok((source, wikilink, target, row.get::<_, i64>(8)? as usize))
+        })?;
+
+        let mut links = Vec::new();
+        for row in rows {
+            let (source, wikilink, target, count) = row?;
+            let by_path = stored_path::encode(&Path::new(&wikilink.target).with_extension("typ"));
+            let exact = if fold_case {
+                names::normalize(&by_path).eq_ignore_ascii_case(&target)
+            } else {
+                names::normalize(&by_path) == target
+            };
+            if !exact {
+                links.push((source, wikilink, self.vault_path(&target), count));
+            }
+        }
+        Ok(links)
+    }
+
+    /// Every definition of a label defined more than once in the vault, ordered by name. Given
+    /// `file`, only the labels it defines are looked at, though with all their definitions.
+    pub fn get_repeated_labels(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Label)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        let names = match file {
+            Some(_) => "AND l.name IN (SELECT name FROM labels WHERE file_id = ? AND NOT heading)",
+            None => "",
+        };
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT f.path, l.name, l.line, l.column, l.kind, l.element
+             FROM labels l
+             JOIN files f ON f.id = l.file_id
+             WHERE NOT l.heading {names}
+               AND (SELECT COUNT(*) FROM labels d WHERE d.name = l.name AND NOT d.heading) > 1
+             ORDER BY l.name, f.path, l.line, l.column",
+        ))?;
+        let labels = stmt
+            .query_map(scope.params(), |row| {
+                Ok((
+                    self.vault_path(&row.get::<_, String>(0)?),
+                    label_from_row(row, 1)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(labels)
+    }
+
+    /// Links that match neither a note nor an attachment on disk, only those in `file` if given.
+    pub fn get_broken_links(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let mut broken = Vec::new();
+        for (source, wikilink) in self.get_unresolved_links(file)? {
+            if self.resolve_target(&wikilink.target)?.is_none() {
+                broken.push((source, wikilink));
+            }
+        }
+        Ok(broken)
+    }
+
+    /// Vault-relative `(source, target, link count)` for every pair of linked notes.
+    pub fn get_link_graph(&self) -> Result<Vec<(PathBuf, PathBuf, usize)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT s.path, t.path, COUNT(*)
+             FROM resolved_links r
+             JOIN files s ON s.id = r.source_file_id
+             JOIN files t ON t.id = r.target_file_id
+             GROUP BY r.source_file_id, r.target_file_id
+             ORDER BY s.path, t.path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                stored_path::decode(&row.get::<_, String>(0)?),
+                stored_path::decode(&row.get::<_, String>(1)?),
+                row.get::<_, i64>(2)? as usize,
+            ))
+        })?;
+
+        let mut edges = Vec::new();
+        for row in rows {
+            self.check_cancelled()?;
+            edges.push(row?);
+        }
+        Ok(edges)
+    }
+
+    /// Notes whose path, title, aliases, or tags contain `query`, ignoring ASCII case.
+    pub fn search(&self, query: &str) -> Result<Vec<PathBuf>> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{escaped}%");
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT DISTINCT f.path FROM files f
+             LEFT JOIN metadata m
+               ON m.file_id = f.id AND m.key IN ('title', 'alias', 'tags')
+             WHERE f.path LIKE ?1 ESCAPE '\\' OR m.value LIKE ?1 ESCAPE '\\'
+             ORDER BY f.path",
+        )?;
+        let rows = stmt.query_map([pattern], |row| row.get::<_, String>(0))?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            self.check_cancelled()?;
+            files.push(self.vault_path(&row?));
+        }
+
+        Ok(files)
+    }
+
+    /// Vault-relative `(path, key, value)` for every metadata entry.
+    pub fn get_all_metadata(&self) -> Result<Vec<(PathBuf, String, String)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path, m.key, m.value 
+             FROM metadata m 
+             JOIN files f ON m.file_id = f.id
+             ORDER BY f.path, m.key",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                stored_path::decode(&row.get::<_, String>(0)?),
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+
+        let mut metadata = Vec::new();
+        for row in rows {
+            metadata.push(row?);
+        }
+
+        Ok(metadata)
+    }
+
+    /// How many notes use each metadata key, most used first.
+    pub fn metadata_key_counts(&self) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT key, COUNT(DISTINCT file_id) AS notes
+             FROM metadata
+             GROUP BY key
+             ORDER BY notes DESC, key",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// How many notes have each string value of `key` (each tag, for `tags`), most used first.
+    pub fn metadata_value_counts(&self, key: &str) -> Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT value, COUNT(DISTINCT file_id) AS notes
+             FROM metadata
+             WHERE key = ?
+             GROUP BY value
+             ORDER BY notes DESC, value",
+        )?;
+        let rows = stmt.query_map([key], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?))
+        })?;
+        let builtin = matches!(key, "title" | "tags" | "alias");
+        let mut counts = Vec::new();
+        for row in rows {
+            let (value, count) = row?;
+            // Custom values are stored as JSON
+            let value = if builtin {
+                Some(value)
+            } else {
+                serde_json::from_str::<serde_json::Value>(&value)
+                    .ok()
+                    .and_then(|value| value.as_str().map(str::to_string))
+            };
+            counts.extend(value.map(|value| (value, count)));
+        }
+        Ok(counts)
+    }
+
+    /// `(stem, name)` for each note's stem, title, and aliases that are `lengths` characters
+    /// long, e.g. to look for names close to a link target.
+    pub fn note_names(&self, lengths: RangeInclusive<usize>) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT stem, stem FROM files WHERE length(stem) BETWEEN ?1 AND ?2
+             UNION ALL
+             SELECT f.stem, m.value FROM metadata m JOIN files f ON f.id = m.file_id
+             WHERE m.key IN ('title', 'alias') AND length(m.value) BETWEEN ?1 AND ?2",
+        )?;
+        let rows = stmt.query_map(params![lengths.start(), lengths.end()], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Notes tagged `tag` with their title and backlink count, most linked first.
+    pub fn tagged_notes(&self, tag: &str) -> Result<Vec<(PathBuf, Option<String>, usize)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path,
+                    (SELECT value FROM metadata WHERE file_id = f.id AND key = 'title'),
+                    (SELECT COUNT(*) FROM resolved_links WHERE target_file_id = f.id) AS backlinks
+             FROM files f
+             WHERE EXISTS (
+                 SELECT 1 FROM metadata WHERE file_id = f.id AND key = 'tags' AND value = ?)
+             ORDER BY backlinks DESC, f.path",
+        )?;
+        let rows = stmt.query_map([tag], |row| {
+            Ok((
+                self.vault_path(&row.get::<_, String>(0)?),
+                row.get(1)?,
+                row.get(2)?,
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn get_all_files(&self) -> Result<Vec<PathBuf>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT path FROM files ORDER BY path")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(self.vault_path(&row?));
+        }
+
+        Ok(files)
+    }
+
+    /// When each note was last modified on disk, in seconds since the Unix epoch.
+    pub fn modified_times(&self) -> Result<HashMap<PathBuf, i64>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT path, modified_at FROM files WHERE modified_at IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        let mut times = HashMap::new();
+        for row in rows {
+            let (path, modified_at) = row?;
+            times.insert(self.vault_path(&path), modified_at);
+        }
+        Ok(times)
+    }
+
+    pub fn remove_file(&self, file_path: &Path) -> Result<()> {
+        let relative_path = self.stored_path(file_path)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        delete_file(&tx, &relative_path)?;
+        tx.commit()?;
+        lock(&self.cache).invalidate(file_path);
+
+        Ok(())
+    }
+
+    /// Store `stored` and remove `removed` in a single transaction.
+    pub fn store_changes(&mut self, stored: &[ParsedFile], removed: &[PathBuf]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for parsed in stored {
+            write_file(&tx, &self.root, &parsed.path, parsed)?;
+        }
+        for path in removed {
+            delete_file(&tx, &stored_path::encode(&relative_path(&self.root, path)?))?;
+        }
+        tx.commit()?;
+
+        let mut cache = lock(&self.cache);
+        for path in stored.iter().map(|parsed| &parsed.path).chain(removed) {
+            cache.invalidate(path);
+        }
+        Ok(())
+    }
+
+    /// The content hash recorded when `file_path` was last indexed.
+    pub fn get_content_hash(&self, file_path: &Path) -> Result<Option<String>> {
+        let relative_path = self.stored_path(file_path)?;
+
+        let hash = self
+            .conn
+            .query_row(
+                "SELECT content_hash FROM files WHERE path = ?",
+                [&relative_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(hash.flatten())
+    }
+
+    /// Move an indexed file to a new path, keeping its id and everything stored for it.
+    pub fn rename_file(&self, from: &Path, to: &Path) -> Result<()> {
+        let from = self.stored_path(from)?;
+        let stem = stored_path::stem(to);
+        let to = self.stored_path(to)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let file_id: Option<i64> = tx
+            .query_row(
+                "UPDATE files SET path = ?, stem = ? WHERE path = ? RETURNING id",
+                params![to, stem, from],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(file_id) = file_id {
+            let before = affected_backlinks(&tx, file_id, &stem)?;
+            reresolve(&tx, Some(file_id), &stem)?;
+            let after = affected_backlinks(&tx, file_id, &stem)?;
+            history::record_backlink_changes(&tx, None, &before, &after, unix_now())?;
+        }
+        tx.commit()?;
+        lock(&self.cache).clear();
+
+        Ok(())
+    }
+
+    /// Move every indexed note under the folder `from` to the same place under `to`, in one
+    /// transaction, then re-resolve all links since path-qualified targets may now match
+    /// different notes.
+    /// Returns the number of notes moved.
+    pub fn rename_folder(&self, from: &Path, to: &Path) -> Result<usize> {
+        let from = self.stored_path(from)?;
+        let to = self.stored_path(to)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        // Compare prefixes with `substr` rather than `LIKE`, whose wildcards may be in names
+        let moved = tx.execute(
+            "UPDATE files SET path = ?2 || substr(path, length(?1) + 1)
+             WHERE substr(path, 1, length(?1) + 1) = ?1 || '/'",
+            params![from, to],
+        )?;
+        tx.execute(
+            "UPDATE attachments SET path = ?2 || substr(path, length(?1) + 1)
+             WHERE substr(path, 1, length(?1) + 1) = ?1 || '/'",
+            params![from, to],
+        )?;
+        relink_all(&tx)?;
+        tx.commit()?;
+        lock(&self.cache).clear();
+
+        Ok(moved)
+    }
+
+    /// Whether link targets match note paths and names regardless of ASCII case.
+    pub fn case_insensitive_links(&self) -> Result<bool> {
+        // Changes when another connection commits, which may have switched the setting
+        let version: i64 = self
+            .conn
+            .prepare_cached("PRAGMA data_version")?
+            .query_row([], |row| row.get(0))?;
+        if let Some((read_at, enabled)) = self.folds_case.get()
+            && read_at == version
+        {
+            return Ok(enabled);
+        }
+        let enabled = folds_case(&self.conn)?;
+        self.folds_case.set(Some((version, enabled)));
+        Ok(enabled)
+    }
+
+    /// Switch case-insensitive link matching, re-resolving every link if it changes.
+    pub fn set_case_insensitive_links(&self, enabled: bool) -> Result<()> {
+        if self.case_insensitive_links()? == enabled {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO settings (key, value) VALUES ('case_insensitive_links', ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [enabled],
+        )?;
+        relink_all(&tx)?;
+        tx.commit()?;
+        // Our own commits leave `data_version` alone
+        self.folds_case.set(None);
+
+        Ok(())
+    }
+
+    /// How many rows of each kind, such as links or labels, one note may add to the index;
+    /// `None` if unlimited.
+    pub fn max_index_rows(&self) -> Result<Option<usize>> {
+        Ok(row_cap(&self.conn)?)
+    }
+
+    /// Cap the rows of each kind one note may add, `0` for no cap, marking every note for
+    /// re-parsing if it changes.
+    pub fn set_max_index_rows(&self, max: usize) -> Result<()> {
+        if self.max_index_rows()?.unwrap_or(0) == max {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO settings (key, value) VALUES ('max_index_rows', ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [max as i64],
+        )?;
+        tx.execute("UPDATE files SET content_hash = NULL", [])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Notes with rows left out by [`Index::set_max_index_rows`], with how many, ordered by path;
+    /// only `file` if given.
+    pub fn get_truncated_files(&self, file: Option<&Path>) -> Result<Vec<(PathBuf, usize)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        let files = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT path, truncated FROM files WHERE truncated > 0 {} ORDER BY path",
+                scope.condition("id")
+            ))?
+            .query_map(scope.params(), |row| {
+                Ok((
+                    self.vault_path(&row.get::<_, String>(0)?),
+                    row.get::<_, i64>(1)? as usize,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(files)
+    }
+
+    fn query_links(&self, sql: &str, scope: &FileScope) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let links = stmt
+            .query_map(scope.params(), |row| self.sourced_link(row))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(links)
+    }
+
+    /// The scope of a query over the vault, narrowed to `file` if given; `None` if `file` is
+    /// not indexed, so nothing in it can match.
+    pub(crate) fn file_scope(&self, file: Option<&Path>) -> Result<Option<FileScope>> {
+        let Some(file) = file else {
+            return Ok(Some(FileScope(None)));
+        };
+        let Ok(stored) = self.stored_path(file) else {
+            return Ok(None);
+        };
+        let file_id = self
+            .conn
+            .prepare_cached("SELECT id FROM files WHERE path = ?")?
+            .query_row([stored], |row| row.get(0))
+            .optional()?;
+        Ok(file_id.map(|id| FileScope(Some(id))))
+    }
+
+    // Maps `path, target, alias, label, block, line, column` to the link and its note
+    fn sourced_link(&self, row: &rusqlite::Row) -> rusqlite::Result<(PathBuf, Wikilink)> {
+        let wikilink = Wikilink {
+            target: row.get(1)?,
+            alias: row.get(2)?,
+            label: row.get(3)?,
+            block: row.get(4)?,
+            line: row.get::<_, i64>(5)? as usize,
+            column: row.get::<_, i64>(6)? as usize,
+        };
+        Ok((self.vault_path(&row.get::<_, String>(0)?), wikilink))
+    }
+
+    fn stored_path(&self, file_path: &Path) -> Result<String> {
+        Ok(stored_path::encode(&relative_path(&self.root, file_path)?))
+    }
+
+    /// The absolute path of a note or attachment from its stored vault-relative form.
+    fn vault_path(&self, stored: &str) -> PathBuf {
+        self.root.join(stored_path::decode(stored))
+    }
+}
+
+/// Restricts a query over the vault to the rows of one note.
+pub(crate) struct FileScope(Option<i64>);
+
+impl FileScope {
+    /// `AND <column> = ?` when narrowed to a note, nothing otherwise.
+    pub(crate) fn condition(&self, column: &str) -> String {
+        match self.0 {
+            Some(_) => format!("AND {column} = ?"),
+            None => String::new(),
+        }
+    }
+
+    pub(crate) fn params(&self) -> impl rusqlite::Params {
+        rusqlite::params_from_iter(self.0)
+    }
+}
+
+// A panic while holding the lock leaves the guarded value itself usable
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Key SQLCipher with `key`; plain SQLite has no encryption to key
+#[cfg(feature = "encryption")]
+fn apply_key(conn: &Connection, key: &str) -> Result<()> {
+    conn.pragma_update(None, "key", key)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn apply_key(_conn: &Connection, _key: &str) -> Result<()> {
+    anyhow::bail!("Encrypting the index needs typst-oxide built with the `encryption` feature")
+}
+
+/// Hash of a note's contents, used to skip re-parsing unchanged files.
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:016x}", xxhash_rust::xxh3::xxh3_64(content))
+}
+
+/// [`content_hash`] of the file at `path`, read a buffer at a time.
+pub fn file_hash(path: &Path) -> std::io::Result<String> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        hasher.update(buffer);
+        let read = buffer.len();
+        reader.consume(read);
+    }
+    Ok(format!("{:016x}", hasher.digest()))
+}
+
+fn relative_path(root: &Path, file_path: &Path) -> Result<PathBuf> {
+    file_path
+        .strip_prefix(root)
+        .map(|p| p.to_path_buf())
+        .with_context(|| format!("File {} is not in workspace", file_path.display()))
+}
+
+// Whether links match notes ignoring ASCII case. Migrations resolve links before the settings
+// table exists
+fn folds_case(conn: &Connection) -> rusqlite::Result<bool> {
+    let has_settings: bool = conn
+        .prepare_cached("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'settings')")?
+        .query_row([], |row| row.get(0))?;
+    if !has_settings {
+        return Ok(false);
+    }
+    let enabled: Option<bool> = conn
+        .prepare_cached("SELECT value FROM settings WHERE key = 'case_insensitive_links'")?
+        .query_row([], |row| row.get(0))
+        .optional()?;
+    Ok(enabled.unwrap_or(false))
+}
+
+// The configured cap on rows of each kind per note, if any
+fn row_cap(conn: &Connection) -> rusqlite::Result<Option<usize>> {
+    let max: Option<i64> = conn
+        .prepare_cached("SELECT value FROM settings WHERE key = 'max_index_rows'")?
+        .query_row([], |row| row.get(0))
+        .optional()?;
+    Ok(max.filter(|&max| max > 0).map(|max| max as usize))
+}
+
+// The first `cap` of `rows`, counting the rest in `dropped`
+fn capped<'a, T>(rows: &'a [T], cap: Option<usize>, dropped: &mut usize) -> &'a [T] {
+    let kept = cap.map_or(rows.len(), |cap| rows.len().min(cap));
+    *dropped += rows.len() - kept;
+    &rows[..kept]
+}
+
+// Whether labels include heading slugs. Migrations resolve links before the column exists
+fn has_heading_labels(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.prepare_cached(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info('labels') WHERE name = 'heading')",
+    )?
+    .query_row([], |row| row.get(0))
+}
+
+// An exact vault-relative path wins over the first note (by path) with the same stem. Paths
+// match in composed or decomposed form, as either may be on disk. Under case-insensitive
+// matching, a match in the target's own case wins over one in another case. `resolver::Resolver`
+// follows the same rules in memory. Each case is its own statement so that SQLite can search
+// the plain or `NOCASE` index on `path` and `stem` instead of scanning `files`
+fn find_note(
+    conn: &Connection,
+    target: &str,
+    fold_case: bool,
+) -> rusqlite::Result<Option<(i64, String)>> {
+    let Some((composed, decomposed)) = resolver::note_paths(target) else {
+        tracing::debug!(target = ?target, "link target is not a note");
+        return Ok(None);
+    };
+    let mut exact = first_note(
+        conn,
+        "SELECT id, path FROM files WHERE path IN (?1, ?2) ORDER BY path LIMIT 1",
+        params![composed, decomposed],
+    )?;
+    if exact.is_none() && fold_case {
+        exact = first_note(
+            conn,
+            "SELECT id, path FROM files WHERE path COLLATE NOCASE IN (?1, ?2)
+             ORDER BY path LIMIT 1",
+            params![composed, decomposed],
+        )?;
+    }
+    if let Some((_, path)) = &exact {
+        tracing::debug!(target = ?target, %path, "link resolved by path");
+        return Ok(exact);
+    }
+
+    let stem = target_stem(target);
+    let mut note = first_note(
+        conn,
+        "SELECT id, path FROM files WHERE stem = ? ORDER BY path LIMIT 1",
+        [&stem],
+    )?;
+    if note.is_none() && fold_case {
+        note = first_note(
+            conn,
+            "SELECT id, path FROM files WHERE stem = ? COLLATE NOCASE ORDER BY path LIMIT 1",
+            [&stem],
+        )?;
+    }
+    match &note {
+        Some((_, path)) => {
+            tracing::debug!(target = ?target, ?stem, %path, "link resolved by stem")
+        }
+        None => tracing::debug!(target = ?target, ?stem, "link matches no note path or stem"),
+    }
+    Ok(note)
+}
+
+// The `id, path` of the first note `sql` selects
+fn first_note(
+    conn: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> rusqlite::Result<Option<(i64, String)>> {
+    conn.prepare_cached(sql)?
+        .query_row(params, |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()
+}
+
+/// Recompute `resolved_links` for the wikilinks selected by `query`, which must return
+/// `id, file_id, target, label`.
+fn resolve_links(
+    tx: &Transaction,
+    query: &str,
+    params: impl rusqlite::Params,
+) -> rusqlite::Result<()> {
+    let fold_case = folds_case(tx)?;
+    let headings = has_heading_labels(tx)?;
+    let links: Vec<(i64, i64, String, Option<String>)> = tx
+        .prepare_cached(query)?
+        .query_map(params, |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for (wikilink_id, source_file_id, target, label) in links {
+        tx.prepare_cached("DELETE FROM resolved_links WHERE wikilink_id = ?")?
+            .execute([wikilink_id])?;
+        let Some((target_file_id, _)) = find_note(tx, &target, fold_case)? else {
+            continue;
+        };
+
+        let target_label_id: Option<i64> = match &label {
+            Some(label) if headings => tx
+                .prepare_cached(&format!("SELECT id FROM labels {FIND_LABEL}"))?
+                .query_row(
+                    params![target_file_id, names::normalize(label), names::slug(label)],
+                    |row| row.get(0),
+                )
+                .optional()?,
+            Some(label) => tx
+                .prepare_cached("SELECT id FROM labels WHERE file_id = ? AND name = ? LIMIT 1")?
+                .query_row(params![target_file_id, label], |row| row.get(0))
+                .optional()?,
+            None => None,
+        };
+        tx.prepare_cached(
+            "INSERT INTO resolved_links (wikilink_id, source_file_id, target_file_id, target_label_id)
+             VALUES (?, ?, ?, ?)",
+        )?
+        .execute(params![
+            wikilink_id,
+            source_file_id,
+            target_file_id,
+            target_label_id
+        ])?;
+    }
+
+    Ok(())
+}
+
+// Matches the label of note `?1` that a link's `:label` names, given the label in NFC as `?2`
+// and slugged as `?3`: an explicit label by name, else a heading by its slug
+const FIND_LABEL: &str = "WHERE file_id = ?1 AND (name = ?2 OR (heading AND name = ?3))
+     ORDER BY heading, line, column LIMIT 1";
+
+// Links whose resolution can change when the note `?1` with stem `?2` is written, moved,
+// or removed: its own links, links that resolved to it, and links matching its stem in any
+// case
+const AFFECTED_LINKS: &str = "SELECT id, file_id, target, label FROM wikilinks WHERE file_id = ?1
+     UNION SELECT id, file_id, target, label FROM wikilinks WHERE target_stem = ?2 COLLATE NOCASE
+     UNION SELECT w.id, w.file_id, w.target, w.label
+           FROM resolved_links r JOIN wikilinks w ON w.id = r.wikilink_id
+           WHERE r.target_file_id = ?1";
+
+fn reresolve(tx: &Transaction, file_id: Option<i64>, stem: &str) -> rusqlite::Result<()> {
+    resolve_links(tx, AFFECTED_LINKS, params![file_id, stem])
+}
+
+// Backlinks counted over the links `reresolve` recomputes for note `file_id` with stem `stem`
+fn affected_backlinks(tx: &Transaction, file_id: i64, stem: &str) -> Result<HashMap<i64, i64>> {
+    history::backlink_counts(tx, AFFECTED_LINKS, params![file_id, stem])
+}
+
+// Re-resolve every link, snapshotting the notes whose backlinks change
+fn relink_all(tx: &Transaction) -> Result<()> {
+    const ALL_LINKS: &str = "SELECT id, file_id, target, label FROM wikilinks";
+    let before = history::backlink_counts(tx, ALL_LINKS, [])?;
+    resolve_links(tx, ALL_LINKS, [])?;
+    let after = history::backlink_counts(tx, ALL_LINKS, [])?;
+    history::record_backlink_changes(tx, None, &before, &after, unix_now())
+}
+
+// The figure columns `figure_from_row` reads, in order
+const FIGURE_COLUMNS: &str = "kind, caption, label, line, column";
+
+fn figure_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Figure> {
+    Ok(Figure {
+        kind: FigureKind::parse(&row.get::<_, String>(first)?).unwrap_or(FigureKind::Figure),
+        caption: row.get(first + 1)?,
+        label: row.get(first + 2)?,
+        line: row.get::<_, i64>(first + 3)? as usize,
+        column: row.get::<_, i64>(first + 4)? as usize,
+    })
+}
+
+// The package columns `package_from_row` reads, in order
+const PACKAGE_COLUMNS: &str = "namespace, name, version, line, column";
+
+fn package_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<PackageImport> {
+    Ok(PackageImport {
+        namespace: row.get(first)?,
+        name: row.get(first + 1)?,
+        version: row.get(first + 2)?,
+        line: row.get::<_, i64>(first + 3)? as usize,
+        column: row.get::<_, i64>(first + 4)? as usize,
+    })
+}
+
+// The label columns `label_from_row` reads, in order
+const LABEL_COLUMNS: &str = "name, line, column, kind, element";
+
+fn label_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Label> {
+    let element = row.get::<_, Option<String>>(first + 4)?;
+    Ok(Label {
+        name: row.get(first)?,
+        line: row.get::<_, i64>(first + 1)? as usize,
+        column: row.get::<_, i64>(first + 2)? as usize,
+        kind: row
+            .get::<_, Option<String>>(first + 3)?
+            .as_deref()
+            .and_then(LabelKind::parse)
+            .unwrap_or_default(),
+        element: element.and_then(|element| serde_json::from_str(&element).ok()),
+    })
+}
+
+fn conflict_from_row(row: &rusqlite::Row) -> rusqlite::Result<Conflict> {
+    Ok(Conflict {
+        line: row.get::<_, i64>(0)? as usize,
+        base_line: row.get::<_, Option<i64>>(1)?.map(|line| line as usize),
+        separator_line: row.get::<_, i64>(2)? as usize,
+        end_line: row.get::<_, i64>(3)? as usize,
+    })
+}
+
+// Clear the rows parsed from a note: metadata, links, labels, blocks and the rest
+fn clear_file(tx: &Transaction, file_id: i64) -> Result<()> {
+    tx.prepare_cached("DELETE FROM metadata WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM wikilinks WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM labels WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM blocks WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM attachments WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM conflicts WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM figures WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM citations WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM bibliographies WHERE file_id = ?")?
+        .execute([file_id])?;
+    tx.prepare_cached("DELETE FROM packages WHERE file_id = ?")?
+        .execute([file_id])?;
+    Ok(())
+}
+
+// Seconds since the Unix epoch
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+// Remove the note stored as `relative_path`, if any
+fn delete_file(tx: &Transaction, relative_path: &str) -> Result<()> {
+    let file_id: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM files WHERE path = ?",
+            [relative_path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(file_id) = file_id {
+        let stem = stored_path::stem(&stored_path::decode(relative_path));
+        let before = affected_backlinks(tx, file_id, &stem)?;
+        clear_file(tx, file_id)?;
+        tx.execute("DELETE FROM files WHERE id = ?", [file_id])?;
+        // Links that pointed here may now match another note with the same stem
+        reresolve(tx, None, &stem)?;
+        let after = affected_backlinks(tx, file_id, &stem)?;
+        history::record_backlink_changes(tx, Some(file_id), &before, &after, unix_now())?;
+    }
+    Ok(())
+}
+
+fn write_file(tx: &Transaction, root: &Path, file_path: &Path, parsed: &ParsedFile) -> Result<()> {
+    let relative_path = relative_path(root, file_path)?;
+    let stem = stored_path::stem(&relative_path);
+    let metadata = std::fs::metadata(file_path)?;
+    let hash = file_hash(file_path)?;
+    let cap = row_cap(tx)?;
+    let mut dropped = 0;
+    let wikilinks = capped(&parsed.wikilinks, cap, &mut dropped);
+    let labels = capped(&parsed.labels, cap, &mut dropped);
+    let headings = capped(&parsed.headings, cap, &mut dropped);
+    let blocks = capped(&parsed.blocks, cap, &mut dropped);
+    let attachments = capped(&parsed.attachments, cap, &mut dropped);
+    let conflicts = capped(&parsed.conflicts, cap, &mut dropped);
+    let figures = capped(&parsed.figures, cap, &mut dropped);
+    let citations = capped(&parsed.citations, cap, &mut dropped);
+    let bibliographies = capped(&parsed.bibliographies, cap, &mut dropped);
+    let packages = capped(&parsed.packages, cap, &mut dropped);
+    let now = unix_now();
+
+    // Upsert so an existing file keeps its id
+    let file_id: i64 = tx
+        .prepare_cached(
+            "INSERT INTO files (path, stem, created_at, modified_at, last_parsed, content_hash,
+                words, characters, headings, truncated)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(path) DO UPDATE SET
+                stem = excluded.stem,
+                created_at = excluded.created_at,
+                modified_at = excluded.modified_at,
+                last_parsed = excluded.last_parsed,
+                content_hash = excluded.content_hash,
+                words = excluded.words,
+                characters = excluded.characters,
+                headings = excluded.headings,
+                truncated = excluded.truncated
+             RETURNING id",
+        )?
+        .query_row(
+            params![
+                stored_path::encode(&relative_path),
+                stem,
+                metadata
+                    .created()
+                    .ok()
+                    .map(|t| t.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64),
+                metadata
+                    .modified()
+                    .ok()
+                    .map(|t| t.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64),
+                now,
+                hash,
+                parsed.stats.words as i64,
+                parsed.stats.characters as i64,
+                parsed.stats.headings as i64,
+                dropped as i64
+            ],
+            |row| row.get(0),
+        )?;
+
+    // Notes whose backlinks change get a snapshot too
+    let before = affected_backlinks(tx, file_id, &stem)?;
+    clear_file(tx, file_id)?;
+
+    // Insert metadata
+    let mut insert_metadata = tx.prepare_cached(
+        "INSERT INTO metadata (file_id, key, value, value_type, number_value)
+         VALUES (?, ?, ?, ?, ?)",
+    )?;
+    let text = MetadataType::Text.as_str();
+
+    if let Some(title) = &parsed.metadata.title {
+        insert_metadata.execute(params![file_id, "title", title, text, None::<f64>])?;
+    }
+
+    for tag in &parsed.metadata.tags {
+        insert_metadata.execute(params![file_id, "tags", tag, text, None::<f64>])?;
+    }
+
+    for alias in &parsed.metadata.alias {
+        insert_metadata.execute(params![file_id, "alias", alias, text, None::<f64>])?;
+    }
+
+    // Insert custom metadata with its type, so queries can compare numbers and dates
+    for (key, value) in &parsed.metadata.custom {
+        let (value_type, number) = records::classify(value);
+        insert_metadata.execute(params![
+            file_id,
+            key,
+            value.to_string(),
+            value_type.as_str(),
+            number
+        ])?;
+    }
+
+    // Insert wikilinks
+    for wikilink in wikilinks {
+        tx.prepare_cached(
+            "INSERT INTO wikilinks (file_id, target, target_stem, alias, label, block, line, column)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            wikilink.target,
+            target_stem(&wikilink.target),
+            wikilink.alias,
+            wikilink.label,
+            wikilink.block,
+            wikilink.line as i64,
+            wikilink.column as i64
+        ])?;
+    }
+
+    // Insert labels and heading slugs, named in NFC
+    let labels = labels.iter().map(|label| (label, false));
+    for (label, heading) in labels.chain(headings.iter().map(|label| (label, true))) {
+        tx.prepare_cached(
+            "INSERT INTO labels (file_id, name, line, column, heading, kind, element)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            names::normalize(&label.name),
+            label.line as i64,
+            label.column as i64,
+            heading,
+            label.kind.as_str(),
+            label
+                .element
+                .map(|element| serde_json::to_string(&element))
+                .transpose()?
+        ])?;
+    }
+
+    // Insert blocks
+    for block in blocks {
+        tx.prepare_cached(
+            "INSERT INTO blocks (file_id, block_id, line, column)
+             VALUES (?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            block.id,
+            block.line as i64,
+            block.column as i64
+        ])?;
+    }
+
+    // Insert attachments with the file they point to, if it exists
+    for attachment in attachments {
+        tx.prepare_cached(
+            "INSERT INTO attachments (file_id, target, path, embedded, line, column)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            attachment.target,
+            attachments::resolve(root, &relative_path, attachment),
+            attachment.embedded,
+            attachment.line as i64,
+            attachment.column as i64
+        ])?;
+    }
+
+    for conflict in conflicts {
+        tx.prepare_cached(
+            "INSERT INTO conflicts (file_id, line, base_line, separator_line, end_line)
+             VALUES (?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            conflict.line as i64,
+            conflict.base_line.map(|line| line as i64),
+            conflict.separator_line as i64,
+            conflict.end_line as i64
+        ])?;
+    }
+
+    for figure in figures {
+        tx.prepare_cached(
+            "INSERT INTO figures (file_id, kind, caption, label, line, column)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            figure.kind.as_str(),
+            figure.caption,
+            figure.label,
+            figure.line as i64,
+            figure.column as i64
+        ])?;
+    }
+
+    for citation in citations {
+        tx.prepare_cached(
+            "INSERT INTO citations (file_id, key, line, column)
+             VALUES (?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            names::normalize(&citation.key),
+            citation.line as i64,
+            citation.column as i64
+        ])?;
+    }
+
+    for target in bibliographies {
+        tx.prepare_cached(
+            "INSERT INTO bibliographies (file_id, target, path)
+             VALUES (?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            target,
+            bibliography::resolve(root, &relative_path, target)
+        ])?;
+    }
+
+    for package in packages {
+        tx.prepare_cached(
+            "INSERT INTO packages (file_id, namespace, name, version, line, column)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )?
+        .execute(params![
+            file_id,
+            package.namespace,
+            package.name,
+            package.version,
+            package.line as i64,
+            package.column as i64
+        ])?;
+    }
+
+    reresolve(tx, Some(file_id), &stem)?;
+    history::record_snapshot(tx, file_id, now)?;
+    let after = affected_backlinks(tx, file_id, &stem)?;
+    history::record_backlink_changes(tx, Some(file_id), &before, &after, now)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::add_note;
+    use crate::parser::models::{
+        Attachment, Block, Conflict, ElementRange, Label, Metadata, Wikilink,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cache_initialization() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Index::new(temp_dir.path()).unwrap();
+
+        // Should not panic
+        drop(cache);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Index::new(temp_dir.path()).unwrap();
+        assert_eq!(cache.schema_version().unwrap(), migrations::LATEST_VERSION);
+        drop(cache);
+
+        // Reopening an up-to-date index is a no-op
+        let cache = Index::new(temp_dir.path()).unwrap();
+        assert_eq!(cache.schema_version().unwrap(), migrations::LATEST_VERSION);
+    }
+
+    #[test]
+    fn test_store_and_retrieve_file() {
+        let temp_dir = tempdir().unwrap();
+        let mut cache = Index::new(temp_dir.path()).unwrap();
+
+        let file_path = temp_dir.path().join("test.typ");
+        std::fs::write(&file_path, "test content").unwrap();
+
+        let parsed = ParsedFile {
+            path: file_path.clone(),
+            metadata: Metadata {
+                title: Some("Test Note".to_string()),
+                tags: vec!["tag1".to_string(), "tag2".to_string()],
+                alias: vec!["alias1".to_string()],
+                custom: std::collections::HashMap::new(),
+            },
+            wikilinks: vec![Wikilink {
+                target: "other".to_string(),
+                alias: Some("Other Note".to_string()),
+                label: None,
+                block: None,
+                line: 1,
+                column: 1,
+            }],
+            labels: vec![Label {
+                name: "test-label".to_string(),
+                line: 2,
+                column: 5,
+                kind: LabelKind::Figure,
+                element: Some(ElementRange {
+                    line: 1,
+                    column: 1,
+                    end_line: 2,
+                    end_column: 4,
+                }),
+            }],
+            headings: vec![],
+            blocks: vec![Block {
+                id: "claim".to_string(),
+                line: 3,
+                column: 10,
+            }],
+            attachments: vec![Attachment {
+                target: "plot.png".to_string(),
+                embedded: true,
+                line: 4,
+                column: 1,
+            }],
+            stats: ContentStats {
+                words: 12,
+                characters: 60,
+                headings: 2,
+                reading_minutes: 1,
+            },
+            conflicts: vec![Conflict {
+                line: 5,
+                base_line: None,
+                separator_line: 7,
+                end_line: 9,
+            }],
+            figures: vec![Figure {
+                kind: FigureKind::Image,
+                caption: Some("A plot".to_string()),
+                label: Some("test-label".to_string()),
+                line: 1,
+                column: 1,
+            }],
+            citations: vec![Citation {
+                key: "knuth84".to_string(),
+                line: 3,
+                column: 1,
+            }],
+            bibliographies: vec!["refs.bib".to_string()],
+            packages: vec![PackageImport {
+                namespace: "preview".to_string(),
+                name: "cetz".to_string(),
+                version: "0.2.0".to_string(),
+                line: 4,
+                column: 9,
+            }],
+        };
+
+        cache.store_file(&file_path, &parsed).unwrap();
+
+        let retrieved = cache.get_file(&file_path).unwrap().unwrap();
+        assert_eq!(retrieved.metadata.title, Some("Test Note".to_string()));
+        assert_eq!(retrieved.wikilinks.len(), 1);
+        assert_eq!(retrieved.labels, parsed.labels);
+        assert_eq!(retrieved.blocks.len(), 1);
+        assert_eq!(retrieved.attachments, parsed.attachments);
+        assert_eq!(retrieved.stats, parsed.stats);
+        assert_eq!(retrieved.conflicts, parsed.conflicts);
+        assert_eq!(retrieved.figures, parsed.figures);
+        assert_eq!(retrieved.citations, parsed.citations);
+        assert_eq!(retrieved.bibliographies, parsed.bibliographies);
+        assert_eq!(retrieved.packages, parsed.packages);
+        assert_eq!(
+            cache.get_figures().unwrap(),
+            vec![(file_path.clone(), parsed.figures[0].clone())]
+        );
+
+        let block = cache.get_block(&file_path, "claim").unwrap().unwrap();
+        assert_eq!(block.line, 3);
+        assert!(cache.get_block(&file_path, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_content_hash_and_rename() {
+        let temp_dir = tempdir().unwrap();
+        let mut cache = Index::new(temp_dir.path()).unwrap();
+
+        let old_path = temp_dir.path().join("old.typ");
+        let new_path = temp_dir.path().join("new.typ");
+        std::fs::write(&old_path, "[[target]]").unwrap();
+
+        let parsed = ParsedFile {
+            path: old_path.clone(),
+            metadata: Metadata::default(),
+            wikilinks: vec![Wikilink {
+                target: "target".to_string(),
+                alias: None,
+                label: None,
+                block: None,
+                line: 1,
+                column: 1,
+            }],
+            labels: Vec::new(),
+            headings: Vec::new(),
+            blocks: Vec::new(),
+            attachments: Vec::new(),
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        };
+        cache.store_file(&old_path, &parsed).unwrap();
+        // Restoring the same path updates the row in place
+        cache.store_file(&old_path, &parsed).unwrap();
+
+        assert_eq!(
+            cache.get_content_hash(&old_path).unwrap(),
+            Some(content_hash(b"[[target]]"))
+        );
+
+        let target_path = temp_dir.path().join("target.typ");
+        std::fs::write(&target_path, "").unwrap();
+        cache
+            .store_file(&target_path, &note(&target_path, vec![], vec![]))
+            .unwrap();
+
+        cache.rename_file(&old_path, &new_path).unwrap();
+        assert!(cache.get_content_hash(&old_path).unwrap().is_none());
+        assert_eq!(cache.get_forward_links(&new_path).unwrap().len(), 1);
+        let backlinks = cache.get_backward_links(&target_path).unwrap();
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].0, new_path);
+    }
+
+    #[test]
+    fn test_rename_folder() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+        std::fs::create_dir_all(root.join("deep/path")).unwrap();
+
+        for (name, targets) in [
+            ("deep/path/file.typ", vec![]),
+            ("deep/path/other.typ", vec![("file", None)]),
+            ("deeper.typ", vec![("deep/path/file", None)]),
+        ] {
+            let path = root.join(name);
+            std::fs::write(&path, "").unwrap();
+            cache
+                .store_file(&path, &note(&path, targets, vec![]))
+                .unwrap();
+        }
+
+        let moved = cache
+            .rename_folder(&root.join("deep"), &root.join("shallow"))
+            .unwrap();
+        assert_eq!(moved, 2);
+        let mut files = cache.get_all_files().unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                root.join("deeper.typ"),
+                root.join("shallow/path/file.typ"),
+                root.join("shallow/path/other.typ"),
+            ]
+        );
+        // The stale path-qualified link still matches by name until it is rewritten
+        let backlinks = cache
+            .get_backward_links(&root.join("shallow/path/file.typ"))
+            .unwrap();
+        assert_eq!(backlinks.len(), 2);
+    }
+
+    fn note(path: &Path, targets: Vec<(&str, Option<&str>)>, labels: Vec<&str>) -> ParsedFile {
+        ParsedFile {
+            path: path.to_path_buf(),
+            metadata: Metadata::default(),
+            wikilinks: targets
+                .into_iter()
+                .map(|(target, label)| Wikilink {
+                    target: target.to_string(),
+                    alias: None,
+                    label: label.map(str::to_string),
+                    block: None,
+                    line: 1,
+                    column: 1,
+                })
+                .collect(),
+            labels: labels
+                .into_iter()
+                .map(|name| Label {
+                    name: name.to_string(),
+                    line: 1,
+                    column: 1,
+                    kind: LabelKind::Other,
+                    element: None,
+                })
+                .collect(),
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        }
+    }
+
+    fn resolved_label(cache: &Index) -> Option<String> {
+        cache
+            .conn
+            .query_row(
+                "SELECT l.name FROM resolved_links r LEFT JOIN labels l ON l.id = r.target_label_id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resolved_links() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+
+        let source = root.join("source.typ");
+        let first = root.join("a").join("idea.typ");
+        let second = root.join("b").join("idea.typ");
+        for path in [&source, &first, &second] {
+            std::fs::write(path, "").unwrap();
+        }
+
+        // Stored before its target exists, so it starts out unresolved
+        cache
+            .store_file(
+                &source,
+                &note(&source, vec![("b/idea", Some("sec"))], vec![]),
+            )
+            .unwrap();
+        assert_eq!(cache.get_unresolved_links(None).unwrap().len(), 1);
+
+        cache
+            .store_file(&first, &note(&first, vec![], vec![]))
+            .unwrap();
+        cache
+            .store_file(&second, &note(&second, vec![], vec!["sec"]))
+            .unwrap();
+        assert!(cache.get_unresolved_links(None).unwrap().is_empty());
+        assert!(cache.get_backward_links(&first).unwrap().is_empty());
+        assert_eq!(cache.get_backward_links(&second).unwrap().len(), 1);
+        assert_eq!(resolved_label(&cache), Some("sec".to_string()));
+
+        // Re-storing the target keeps the link but re-resolves its label
+        cache
+            .store_file(&second, &note(&second, vec![], vec![]))
+            .unwrap();
+        assert_eq!(resolved_label(&cache), None);
+
+        // With the exact match gone the link falls back to another note with the same stem
+        cache.remove_file(&second).unwrap();
+        assert_eq!(cache.get_backward_links(&first).unwrap().len(), 1);
+
+        cache.remove_file(&first).unwrap();
+        assert_eq!(cache.get_unresolved_links(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_search_and_broken_links() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+
+        let path = root.join("rust_notes.typ");
+        std::fs::write(&path, "").unwrap();
+        std::fs::write(root.join("paper.pdf"), "").unwrap();
+        let mut parsed = note(&path, vec![("missing", None), ("paper.pdf", None)], vec![]);
+        parsed.metadata.title = Some("Ownership 100%".to_string());
+        cache.store_file(&path, &parsed).unwrap();
+
+        assert_eq!(cache.search("OWNER").unwrap(), vec![path.clone()]);
+        assert_eq!(cache.search("100%").unwrap(), vec![path.clone()]);
+        assert_eq!(cache.search("rust_").unwrap(), vec![path.clone()]);
+        assert!(cache.search("rustx").unwrap().is_empty());
+
+        let broken = cache.get_broken_links(None).unwrap();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].1.target, "missing");
+    }
+
+    #[test]
+    fn test_backlinks() {
+        let temp_dir = tempdir().unwrap();
+        let mut cache = Index::new(temp_dir.path()).unwrap();
+
+        let file1_path = temp_dir.path().join("file1.typ");
+        let file2_path = temp_dir.path().join("file2.typ");
+
+        std::fs::write(&file1_path, "content1").unwrap();
+        std::fs::write(&file2_path, "content2").unwrap();
+
+        let parsed1 = ParsedFile {
+            path: file1_path.clone(),
+            metadata: Metadata::default(),
+            wikilinks: vec![Wikilink {
+                target: "file2".to_string(),
+                alias: None,
+                label: None,
+                block: None,
+                line: 1,
+                column: 1,
+            }],
+            labels: vec![],
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        };
+
+        let parsed2 = ParsedFile {
+            path: file2_path.clone(),
+            metadata: Metadata::default(),
+            wikilinks: vec![],
+            labels: vec![],
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        };
+
+        cache.store_file(&file1_path, &parsed1).unwrap();
+        cache.store_file(&file2_path, &parsed2).unwrap();
+
+        let backlinks = cache.get_backward_links(&file2_path).unwrap();
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].1.target, "file2");
+    }
+
+    #[test]
+    fn test_resolve_target() {
+        let temp_dir = tempdir().unwrap();
+        let mut cache = Index::new(temp_dir.path()).unwrap();
+
+        std::fs::create_dir(temp_dir.path().join("notes")).unwrap();
+        let note_path = temp_dir.path().join("notes").join("deep.typ");
+        std::fs::write(&note_path, "content").unwrap();
+        std::fs::write(temp_dir.path().join("paper.pdf"), "pdf").unwrap();
+        std::fs::write(temp_dir.path().join("notes").join("scan.pdf"), "pdf").unwrap();
+
+        let parsed = ParsedFile {
+            path: note_path.clone(),
+            metadata: Metadata::default(),
+            wikilinks: vec![],
+            labels: vec![],
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![Attachment {
+                target: "scan.pdf".to_string(),
+                embedded: false,
+                line: 1,
+                column: 1,
+            }],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        };
+        cache.store_file(&note_path, &parsed).unwrap();
+
+        assert_eq!(
+            cache.resolve_target("deep").unwrap(),
+            Some(note_path.clone())
+        );
+        assert_eq!(cache.resolve_target("notes/deep").unwrap(), Some(note_path));
+        assert_eq!(
+            cache.resolve_target("paper.pdf").unwrap(),
+            Some(temp_dir.path().join("paper.pdf"))
+        );
+        // Found next to the note that links it, not at the vault root
+        assert_eq!(
+            cache.resolve_target("scan.pdf").unwrap(),
+            Some(temp_dir.path().join("notes").join("scan.pdf"))
+        );
+        assert!(cache.resolve_target("missing").unwrap().is_none());
+        assert!(cache.resolve_target("missing.pdf").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_store_files_bulk() {
+        let temp_dir = tempdir().unwrap();
+        let mut cache = Index::new(temp_dir.path()).unwrap();
+
+        let files: Vec<ParsedFile> = (0..10)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("note{i}.typ"));
+                std::fs::write(&path, "content").unwrap();
+                ParsedFile {
+                    path,
+                    metadata: Metadata::default(),
+                    wikilinks: vec![],
+                    labels: vec![],
+                    headings: vec![],
+                    blocks: vec![],
+                    attachments: vec![],
+                    stats: Default::default(),
+                    conflicts: Vec::new(),
+                    figures: Vec::new(),
+                    citations: Vec::new(),
+                    bibliographies: Vec::new(),
+                    packages: Vec::new(),
+                }
+            })
+            .collect();
+
+        cache.store_files_bulk(&files).unwrap();
+
+        assert_eq!(cache.get_all_files().unwrap().len(), 10);
+        assert!(cache.get_file(&files[3].path).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_remove_file() {
+        let temp_dir = tempdir().unwrap();
+        let mut cache = Index::new(temp_dir.path()).unwrap();
+
+        let file_path = temp_dir.path().join("test.typ");
+        std::fs::write(&file_path, "test content").unwrap();
+
+        let parsed = ParsedFile {
+            path: file_path.clone(),
+            metadata: Metadata::default(),
+            wikilinks: vec![],
+            labels: vec![],
+            headings: vec![],
+            blocks: vec![],
+            attachments: vec![],
+            stats: Default::default(),
+            conflicts: Vec::new(),
+            figures: Vec::new(),
+            citations: Vec::new(),
+            bibliographies: Vec::new(),
+            packages: Vec::new(),
+        };
+
+        cache.store_file(&file_path, &parsed).unwrap();
+        assert!(cache.get_file(&file_path).unwrap().is_some());
+
+        cache.remove_file(&file_path).unwrap();
+        assert!(cache.get_file(&file_path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_case_insensitive_links() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+
+        std::fs::create_dir(root.join("Notes")).unwrap();
+        let target = root.join("Notes").join("Idea.typ");
+        let source = root.join("source.typ");
+        for path in [&target, &source] {
+            std::fs::write(path, "").unwrap();
+        }
+        cache
+            .store_file(&target, &note(&target, vec![], vec![]))
+            .unwrap();
+        let links = vec![("idea", None), ("notes/IDEA", None)];
+        cache
+            .store_file(&source, &note(&source, links, vec![]))
+            .unwrap();
+        assert!(!cache.case_insensitive_links().unwrap());
+        assert!(cache.get_backward_links(&target).unwrap().is_empty());
+
+        cache.set_case_insensitive_links(true).unwrap();
+        assert!(cache.case_insensitive_links().unwrap());
+        assert_eq!(cache.get_backward_links(&target).unwrap().len(), 2);
+        assert_eq!(cache.resolve_target("IDEA").unwrap(), Some(target.clone()));
+
+        // An exact match wins over one in another case
+        let exact = root.join("idea.typ");
+        std::fs::write(&exact, "").unwrap();
+        cache
+            .store_file(&exact, &note(&exact, vec![], vec![]))
+            .unwrap();
+        assert_eq!(cache.resolve_target("idea").unwrap(), Some(exact));
+
+        // Another connection switching the setting is noticed
+        let other = Index::new(root).unwrap();
+        other.set_case_insensitive_links(false).unwrap();
+        assert!(!cache.case_insensitive_links().unwrap());
+        assert!(cache.get_backward_links(&target).unwrap().is_empty());
+
+        // Case-insensitive lookups search an index rather than scanning
+        let plan = |sql: &str, params: &[&dyn rusqlite::ToSql]| -> String {
+            cache
+                .conn
+                .prepare(&format!("EXPLAIN QUERY PLAN {sql}"))
+                .unwrap()
+                .query_map(params, |row| row.get::<_, String>(3))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect()
+        };
+        let nocase_path = "SELECT id FROM files WHERE path COLLATE NOCASE IN (?1, ?2)";
+        assert!(plan(nocase_path, &[&"a", &"b"]).contains("idx_files_path_nocase"));
+        let nocase_stem = "SELECT id FROM files WHERE stem = ? COLLATE NOCASE";
+        assert!(plan(nocase_stem, &[&"idea"]).contains("idx_files_stem_nocase"));
+        assert!(plan(AFFECTED_LINKS, &[&1, &"idea"]).contains("idx_wikilinks_target_stem_nocase"));
+    }
+
+    #[test]
+    fn test_unicode_names_and_heading_labels() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+
+        // Stored decomposed, as macOS may write it
+        let target = add_note(
+            &mut cache,
+            "cafe\u{301}/Cre\u{300}me.typ",
+            "= Über uns\n== Rezepte <rezepte>\n",
+        );
+
+        let source = root.join("source.typ");
+        std::fs::write(&source, "").unwrap();
+        let links = vec![
+            ("Crème", Some("über-uns")),
+            ("café/Crème", Some("Über uns")),
+            ("Crème", Some("rezepte")),
+            ("Crème", Some("missing")),
+        ];
+        cache
+            .store_file(&source, &note(&source, links, vec![]))
+            .unwrap();
+
+        assert_eq!(cache.get_backward_links(&target).unwrap().len(), 4);
+        assert_eq!(cache.get_missing_label_links(None).unwrap().len(), 1);
+        assert_eq!(
+            cache
+                .get_backlinks_to_label(&target, "über-uns")
+                .unwrap()
+                .len(),
+            2
+        );
+        let heading = cache.get_label(&target, "Über uns").unwrap().unwrap();
+        assert_eq!((heading.line, heading.column), (1, 1));
+        assert_eq!(cache.get_file(&target).unwrap().unwrap().headings.len(), 2);
+        // Headings are not explicit labels, so repeating one is fine
+        assert!(cache.get_repeated_labels(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unusual_file_names() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+
+        std::fs::create_dir(root.join("sub")).unwrap();
+        let target = root.join("sub").join("100% done.typ");
+        std::fs::write(&target, "").unwrap();
+        let mut parsed = note(&target, vec![], vec![]);
+        parsed.metadata.title = Some("Done".to_string());
+        cache.store_file(&target, &parsed).unwrap();
+        let source = root.join("source.typ");
+        std::fs::write(&source, "").unwrap();
+        let links = vec![("100% done", None), ("sub/100% done", None)];
+        cache
+            .store_file(&source, &note(&source, links, vec![]))
+            .unwrap();
+
+        assert_eq!(
+            cache.get_all_files().unwrap(),
+            vec![source.clone(), target.clone()]
+        );
+        assert_eq!(cache.get_backward_links(&target).unwrap().len(), 2);
+        assert_eq!(
+            cache.resolve_target("sub/100% done").unwrap(),
+            Some(target.clone())
+        );
+        let (path, _, _) = &cache.get_all_metadata().unwrap()[0];
+        assert_eq!(root.join(path), target);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_file_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut cache = Index::new(root).unwrap();
+
+        let target = root.join(std::ffi::OsStr::from_bytes(b"caf\xe9.typ"));
+        std::fs::write(&target, "").unwrap();
+        cache
+            .store_file(&target, &note(&target, vec![], vec!["intro"]))
+            .unwrap();
+        let source = root.join("source.typ");
+        std::fs::write(&source, "").unwrap();
+        cache
+            .store_file(&source, &note(&source, vec![("caf\u{FFFD}", None)], vec![]))
+            .unwrap();
+
+        assert_eq!(
+            cache.get_all_files().unwrap(),
+            vec![target.clone(), source.clone()]
+        );
+        assert_eq!(cache.get_file(&target).unwrap().unwrap().path, target);
+        assert_eq!(cache.get_backward_links(&target).unwrap().len(), 1);
+        cache.remove_file(&target).unwrap();
+        assert_eq!(cache.get_all_files().unwrap(), vec![source]);
+    }
+
+    #[test]
+    fn test_encrypted_index() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let db_path = root.join("index.db");
+        let result = Index::open_with_key(root, &db_path, Some("secret"));
+        if cfg!(not(feature = "encryption")) {
+            assert!(result.is_err());
+            return;
+        }
+
+        let mut index = result.unwrap();
+        add_note(&mut index, "a.typ", "= Plaintext heading");
+        drop(index);
+
+        let bytes = std::fs::read(&db_path).unwrap();
+        assert!(!bytes.windows(9).any(|window| window == b"Plaintext"));
+        assert!(Index::open(root, &db_path).is_err());
+        assert!(Index::open_with_key(root, &db_path, Some("wrong")).is_err());
+        assert!(Index::open_with_key(root, &db_path, Some("secret")).is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::git::CommitInfo;
+use crate::index::Index;
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// How a metadata value is stored, so queries can compare and sort it by type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataType {
+    Text,
+    /// `number_value` holds the number
+    Number,
+    /// `number_value` is 0 or 1
+    Boolean,
+    /// An ISO 8601 date or date-time; `number_value` holds Unix seconds (UTC)
+    Date,
+    /// Arrays, objects and null, kept as JSON
+    Json,
+}
+
+impl MetadataType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetadataType::Text => "text",
+            MetadataType::Number => "number",
+            MetadataType::Boolean => "boolean",
+            MetadataType::Date => "date",
+            MetadataType::Json => "json",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "text" => MetadataType::Text,
+            "number" => MetadataType::Number,
+            "boolean" => MetadataType::Boolean,
+            "date" => MetadataType::Date,
+            "json" => MetadataType::Json,
+            _ => return None,
+        })
+    }
+}
+
+/// The type and numeric form of a custom metadata value.
+pub(crate) fn classify(value: &serde_json::Value) -> (MetadataType, Option<f64>) {
+    match value {
+        serde_json::Value::Number(n) => (MetadataType::Number, n.as_f64()),
+        serde_json::Value::Bool(flag) => (MetadataType::Boolean, Some(f64::from(u8::from(*flag)))),
+        serde_json::Value::String(text) => match iso_date(text) {
+            Some(date) => (MetadataType::Date, Some(date.and_utc().timestamp() as f64)),
+            None => (MetadataType::Text, None),
+        },
+        _ => (MetadataType::Json, None),
+    }
+}
+
+fn iso_date(text: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+}
+
+/// One metadata row. Built-in keys (`title`, `tags`, `alias`) hold plain text; custom
+/// values are JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataEntry {
+    pub key: String,
+    pub value: String,
+    pub value_type: MetadataType,
+    pub number: Option<f64>,
+}
+
+/// What the index knows about one note, for evaluating queries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteRecord {
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch
+    pub created_at: Option<i64>,
+    pub modified_at: Option<i64>,
+    pub metadata: Vec<MetadataEntry>,
+    pub link_count: usize,
+    pub backlink_count: usize,
+    /// Only recorded when `git_history` is on
+    pub last_commit: Option<CommitInfo>,
+}
+
+impl Index {
+    /// Every note with its metadata and resolved link counts, ordered by path.
+    pub fn get_note_records(&self) -> Result<Vec<NoteRecord>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT m.file_id, m.key, m.value, m.value_type, m.number_value
+                 FROM metadata m ORDER BY m.id",
+        )?;
+        let mut metadata: HashMap<i64, Vec<MetadataEntry>> = HashMap::new();
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                MetadataEntry {
+                    key: row.get(1)?,
+                    value: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    value_type: row
+                        .get::<_, Option<String>>(3)?
+                        .as_deref()
+                        .and_then(MetadataType::parse)
+                        .unwrap_or(MetadataType::Text),
+                    number: row.get(4)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            self.check_cancelled()?;
+            let (file_id, entry) = row?;
+            metadata.entry(file_id).or_default().push(entry);
+        }
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.id, f.path, f.created_at, f.modified_at,
+                (SELECT COUNT(*) FROM resolved_links r WHERE r.source_file_id = f.id),
+                (SELECT COUNT(*) FROM resolved_links r WHERE r.target_file_id = f.id),
+                g.hash, g.author, g.committed_at, g.commits_ago
+             FROM files f LEFT JOIN git_commits g ON g.file_id = f.id
+             ORDER BY f.path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                NoteRecord {
+                    path: self.vault_path(&row.get::<_, String>(1)?),
+                    created_at: row.get(2)?,
+                    modified_at: row.get(3)?,
+                    metadata: Vec::new(),
+                    link_count: row.get::<_, i64>(4)? as usize,
+                    backlink_count: row.get::<_, i64>(5)? as usize,
+                    last_commit: match row.get::<_, Option<String>>(6)? {
+                        Some(hash) => Some(CommitInfo {
+                            hash,
+                            author: row.get(7)?,
+                            committed_at: row.get(8)?,
+                            commits_ago: row.get::<_, i64>(9)? as usize,
+                        }),
+                        None => None,
+                    },
+                },
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (file_id, mut record) = row?;
+            record.metadata = metadata.remove(&file_id).unwrap_or_default();
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(&json!(2.5)), (MetadataType::Number, Some(2.5)));
+        assert_eq!(classify(&json!(true)), (MetadataType::Boolean, Some(1.0)));
+        assert_eq!(
+            classify(&json!("2024-01-02")),
+            (MetadataType::Date, Some(1_704_153_600.0))
+        );
+        assert_eq!(
+            classify(&json!("2024-01-02T00:00:10")),
+            (MetadataType::Date, Some(1_704_153_610.0))
+        );
+        assert_eq!(classify(&json!("soon")), (MetadataType::Text, None));
+        assert_eq!(classify(&json!(["a"])), (MetadataType::Json, None));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::index::Index;
+use crate::parser::models::Wikilink;
+use anyhow::Result;
+use rusqlite::OptionalExtension;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Longest chain of redirects followed, so a cycle can't loop forever
+const MAX_REDIRECTS: usize = 8;
+
+impl Index {
+    /// The note named by the `redirect` metadata field of `file`, if it resolves to a note.
+    pub fn redirect_of(&self, file: &Path) -> Result<Option<PathBuf>> {
+        let relative_path = self.stored_path(file)?;
+        let value: Option<String> = self
+            .conn
+            .prepare_cached(
+                "SELECT m.value FROM metadata m JOIN files f ON f.id = m.file_id
+                 WHERE f.path = ? AND m.key = 'redirect'",
+            )?
+            .query_row([&relative_path], |row| row.get(0))
+            .optional()?;
+        match value {
+            Some(value) => self.redirect_target(&value),
+            None => Ok(None),
+        }
+    }
+
+    /// The note reached by following `redirect` fields from `file`, which is `file` itself if it
+    /// has none. A cycle stops at the last note before it repeats.
+    pub fn follow_redirects(&self, file: &Path) -> Result<PathBuf> {
+        chain_end(file, |current| self.redirect_of(current))
+    }
+
+    /// Links that resolve to a note redirecting elsewhere, with the note the redirects end at;
+    /// only those in `file` if given.
+    pub fn get_redirected_links(
+        &self,
+        file: Option<&Path>,
+    ) -> Result<Vec<(PathBuf, Wikilink, PathBuf)>> {
+        let Some(scope) = self.file_scope(file)? else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT f.path, w.target, w.alias, w.label, w.block, w.line, w.column, t.path
+             FROM resolved_links r
+             JOIN wikilinks w ON w.id = r.wikilink_id
+             JOIN files f ON f.id = r.source_file_id
+             JOIN files t ON t.id = r.target_file_id
+             WHERE EXISTS (
+                 SELECT 1 FROM metadata m WHERE m.file_id = t.id AND m.key = 'redirect') {}
+             ORDER BY f.path, w.line, w.column",
+            scope.condition("r.source_file_id")
+        ))?;
+        let links: Vec<(PathBuf, Wikilink, PathBuf)> = stmt
+            .query_map(scope.params(), |row| {
+                let (source, wikilink) = self.sourced_link(row)?;
+                Ok((source, wikilink, self.vault_path(&row.get::<_, String>(7)?)))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        if links.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Follow the chains through every redirect at once rather than link by link
+        let redirects = self.redirects()?;
+        let mut redirected = Vec::new();
+        for (source, wikilink, target) in links {
+            let destination = chain_end(&target, |current| Ok(redirects.get(current).cloned()))?;
+            if destination != target && destination != source {
+                redirected.push((source, wikilink, destination));
+            }
+        }
+        Ok(redirected)
+    }
+
+    // Where each note with a `redirect` field that resolves to a note redirects to
+    fn redirects(&self) -> Result<HashMap<PathBuf, PathBuf>> {
+        let rows: Vec<(String, String)> = self
+            .conn
+            .prepare_cached(
+                "SELECT f.path, m.value FROM metadata m JOIN files f ON f.id = m.file_id
+                 WHERE m.key = 'redirect'",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut redirects = HashMap::new();
+        for (path, value) in rows {
+            if let Some(redirect) = self.redirect_target(&value)? {
+                redirects.insert(self.vault_path(&path), redirect);
+            }
+        }
+        Ok(redirects)
+    }
+
+    // The note a stored `redirect` value names
+    fn redirect_target(&self, value: &str) -> Result<Option<PathBuf>> {
+        // Custom fields are stored as JSON
+        let Ok(serde_json::Value::String(target)) = serde_json::from_str(value) else {
+            return Ok(None);
+        };
+        let redirect = self.resolve_target(&target)?;
+        Ok(redirect.filter(|path| path.extension().is_some_and(|ext| ext == "typ")))
+    }
+}
+
+// The last note of the chain from `file` through `next`, stopping before a note repeats
+fn chain_end(
+    file: &Path,
+    mut next: impl FnMut(&Path) -> Result<Option<PathBuf>>,
+) -> Result<PathBuf> {
+    let mut visited = vec![file.to_path_buf()];
+    while visited.len() <= MAX_REDIRECTS {
+        let current = &visited[visited.len() - 1];
+        match next(current)? {
+            Some(next) if !visited.contains(&next) => visited.push(next),
+            _ => break,
+        }
+    }
+    Ok(visited.pop().unwrap_or_else(|| file.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_redirects() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+        let parser = crate::parser::Parser::new().unwrap();
+        let notes = [
+            ("old", Some("older"), ""),
+            ("older", Some("new"), ""),
+            ("new", None, ""),
+            ("loop", Some("loop"), ""),
+            ("dangling", Some("missing"), ""),
+            ("source", None, "[[old]] [[new]] [[loop]] [[dangling]]"),
+        ];
+        for (name, redirect, content) in notes {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, content).unwrap();
+            let mut parsed = parser.parse_content(content, &path).unwrap();
+            if let Some(redirect) = redirect {
+                parsed
+                    .metadata
+                    .custom
+                    .insert("redirect".to_string(), redirect.into());
+            }
+            index.store_file(&path, &parsed).unwrap();
+        }
+        let note = |name: &str| root.join(format!("{name}.typ"));
+
+        assert_eq!(
+            index.redirect_of(&note("old")).unwrap(),
+            Some(note("older"))
+        );
+        assert_eq!(index.redirect_of(&note("dangling")).unwrap(), None);
+        assert_eq!(index.follow_redirects(&note("old")).unwrap(), note("new"));
+        assert_eq!(index.follow_redirects(&note("loop")).unwrap(), note("loop"));
+
+        let redirected = index.get_redirected_links(None).unwrap();
+        assert_eq!(redirected.len(), 1);
+        let (source, link, destination) = &redirected[0];
+        assert_eq!(
+            (source, link.target.as_str(), destination),
+            (&note("source"), "old", &note("new"))
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::index::Index;
+use anyhow::{Context, Result};
+use rusqlite::{OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// A note the user starred.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StarredNote {
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch
+    pub starred_at: i64,
+}
+
+impl Index {
+    /// Star or unstar an indexed note. Starring a starred note keeps its original time.
+    pub fn set_starred(&self, file_path: &Path, starred: bool, at: i64) -> Result<()> {
+        let relative_path = self.stored_path(file_path)?;
+        let file_id: i64 = self
+            .conn
+            .prepare_cached("SELECT id FROM files WHERE path = ?")?
+            .query_row([&relative_path], |row| row.get(0))
+            .optional()?
+            .with_context(|| format!("{} is not an indexed note", file_path.display()))?;
+        if starred {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO stars (file_id, starred_at) VALUES (?, ?)
+                     ON CONFLICT(file_id) DO NOTHING",
+                )?
+                .execute(params![file_id, at])?;
+        } else {
+            self.conn
+                .prepare_cached("DELETE FROM stars WHERE file_id = ?")?
+                .execute([file_id])?;
+        }
+        Ok(())
+    }
+
+    /// Starred notes in the order they were starred.
+    pub fn starred_notes(&self) -> Result<Vec<StarredNote>> {
+        let notes = self
+            .conn
+            .prepare_cached(
+                "SELECT f.path, s.starred_at
+                 FROM stars s JOIN files f ON f.id = s.file_id
+                 ORDER BY s.starred_at, f.path",
+            )?
+            .query_map([], |row| {
+                Ok(StarredNote {
+                    path: self.vault_path(&row.get::<_, String>(0)?),
+                    starred_at: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_stars() {
+        let (temp_dir, index) = vault(&[("a.typ", ""), ("b.typ", "")]);
+        let root = temp_dir.path();
+        let (a, b) = (root.join("a.typ"), root.join("b.typ"));
+
+        index.set_starred(&b, true, 1).unwrap();
+        index.set_starred(&a, true, 2).unwrap();
+        index.set_starred(&b, true, 3).unwrap();
+        let starred = index.starred_notes().unwrap();
+        assert_eq!(
+            starred,
+            vec![
+                StarredNote {
+                    path: b.clone(),
+                    starred_at: 1,
+                },
+                StarredNote {
+                    path: a.clone(),
+                    starred_at: 2,
+                },
+            ]
+        );
+
+        index.set_starred(&b, false, 4).unwrap();
+        index.remove_file(&a).unwrap();
+        assert!(index.starred_notes().unwrap().is_empty());
+        assert!(index.set_starred(&root.join("c.typ"), true, 5).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::index::Index;
+use crate::parser::stats::reading_minutes;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use ts_rs::TS;
+
+/// Aggregate counts for drawing a vault dashboard.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VaultStats {
+    pub note_count: usize,
+    pub link_count: usize,
+    pub tag_count: usize,
+    /// Notes with no resolved links in either direction
+    pub orphan_count: usize,
+    /// Words, non-whitespace characters, and headings in every note's prose
+    pub word_count: usize,
+    pub character_count: usize,
+    pub heading_count: usize,
+    /// Time to read the whole vault at 200 words per minute
+    pub reading_minutes: usize,
+    pub most_linked: Vec<LinkedNote>,
+    pub recently_modified: Vec<ModifiedNote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LinkedNote {
+    pub path: PathBuf,
+    pub backlink_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ModifiedNote {
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch
+    pub modified_at: i64,
+}
+
+const IS_ORPHAN: &str = "NOT EXISTS (SELECT 1 FROM resolved_links r WHERE r.source_file_id = f.id)
+     AND NOT EXISTS (SELECT 1 FROM resolved_links r WHERE r.target_file_id = f.id)";
+
+impl Index {
+    /// Notes with no resolved links in either direction.
+    pub fn get_orphans(&self) -> Result<Vec<PathBuf>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT f.path FROM files f WHERE {IS_ORPHAN} ORDER BY f.path"
+        ))?;
+        let orphans = stmt
+            .query_map([], |row| Ok(self.vault_path(&row.get::<_, String>(0)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(orphans)
+    }
+
+    /// Vault statistics, listing at most `limit` most-linked and recently modified notes.
+    pub fn stats(&self, limit: usize) -> Result<VaultStats> {
+        let count = |sql: &str| -> Result<usize> {
+            let count: i64 = self
+                .conn
+                .prepare_cached(sql)?
+                .query_row([], |row| row.get(0))?;
+            Ok(count as usize)
+        };
+
+        let note_count = count("SELECT COUNT(*) FROM files")?;
+        let link_count = count("SELECT COUNT(*) FROM wikilinks")?;
+        let tag_count = count("SELECT COUNT(DISTINCT value) FROM metadata WHERE key = 'tags'")?;
+        let orphan_count = count(&format!("SELECT COUNT(*) FROM files f WHERE {IS_ORPHAN}"))?;
+        let word_count = count("SELECT COALESCE(SUM(words), 0) FROM files")?;
+        let character_count = count("SELECT COALESCE(SUM(characters), 0) FROM files")?;
+        let heading_count = count("SELECT COALESCE(SUM(headings), 0) FROM files")?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT f.path, COUNT(*) AS backlinks
+             FROM resolved_links r
+             JOIN files f ON f.id = r.target_file_id
+             GROUP BY f.id
+             ORDER BY backlinks DESC, f.path
+             LIMIT ?",
+        )?;
+        let most_linked = stmt
+            .query_map([limit as i64], |row| {
+                Ok(LinkedNote {
+                    path: self.vault_path(&row.get::<_, String>(0)?),
+                    backlink_count: row.get::<_, i64>(1)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT path, modified_at FROM files
+             WHERE modified_at IS NOT NULL
+             ORDER BY modified_at DESC, path
+             LIMIT ?",
+        )?;
+        let recently_modified = stmt
+            .query_map([limit as i64], |row| {
+                Ok(ModifiedNote {
+                    path: self.vault_path(&row.get::<_, String>(0)?),
+                    modified_at: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(VaultStats {
+            note_count,
+            link_count,
+            tag_count,
+            orphan_count,
+            word_count,
+            character_count,
+            heading_count,
+            reading_minutes: reading_minutes(word_count),
+            most_linked,
+            recently_modified,
+        })
+    }
+
+    /// Notes last modified in `[start, end)` (Unix seconds), oldest first.
+    pub fn modified_between(&self, start: i64, end: i64) -> Result<Vec<ModifiedNote>> {
+        let notes = self
+            .conn
+            .prepare_cached(
+                "SELECT path, modified_at FROM files
+                 WHERE modified_at >= ?1 AND modified_at < ?2
+                 ORDER BY modified_at, path",
+            )?
+            .query_map([start, end], |row| {
+                Ok(ModifiedNote {
+                    path: self.vault_path(&row.get::<_, String>(0)?),
+                    modified_at: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::models::{ContentStats, Metadata, ParsedFile, Wikilink};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_stats() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+
+        let link = |target: &str| Wikilink {
+            target: target.to_string(),
+            alias: None,
+            label: None,
+            block: None,
+            line: 1,
+            column: 1,
+        };
+        let notes = [
+            ("hub", vec![], vec!["a", "b"]),
+            ("a", vec![link("hub")], vec!["a"]),
+            ("b", vec![link("hub"), link("a"), link("missing")], vec![]),
+            ("lonely", vec![], vec![]),
+        ];
+        for (name, wikilinks, tags) in notes {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, name).unwrap();
+            let stats = ContentStats {
+                words: 150,
+                characters: 700,
+                headings: 1,
+                reading_minutes: 1,
+            };
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata {
+                    tags: tags.into_iter().map(str::to_string).collect(),
+                    ..Default::default()
+                },
+                wikilinks,
+                labels: vec![],
+                headings: vec![],
+                blocks: vec![],
+                attachments: vec![],
+                stats,
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let stats = index.stats(1).unwrap();
+        assert_eq!(stats.note_count, 4);
+        assert_eq!(stats.link_count, 4);
+        assert_eq!(stats.tag_count, 2);
+        assert_eq!(stats.orphan_count, 1);
+        assert_eq!((stats.word_count, stats.character_count), (600, 2800));
+        assert_eq!((stats.heading_count, stats.reading_minutes), (4, 3));
+        assert_eq!(index.get_orphans().unwrap(), vec![root.join("lonely.typ")]);
+        assert_eq!(
+            stats.most_linked,
+            vec![LinkedNote {
+                path: root.join("hub.typ"),
+                backlink_count: 2,
+            }]
+        );
+        assert_eq!(stats.recently_modified.len(), 1);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::frontmatter::{self, MetaBlock};
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::metadata::parse_metadata_json;
+use crate::parser::models::{Metadata, ParsedFile};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use tempfile::TempDir;
+
+// Building a parser compiles its regexes, so every test shares one
+static PARSER: LazyLock<Parser> = LazyLock::new(|| Parser::new().unwrap());
+
+/// A temporary vault holding `notes`, given as relative path and content, each written to disk
+/// and indexed with the metadata its `#meta(...)` block declares.
+pub(crate) fn vault(notes: &[(&str, &str)]) -> (TempDir, Index) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut index = Index::new(temp_dir.path()).unwrap();
+    for (name, content) in notes {
+        add_note(&mut index, name, content);
+    }
+    (temp_dir, index)
+}
+
+/// Write `content` to `name` in the index's vault and index it, returning its path. The
+/// metadata comes from the note's `#meta(...)` block, read literally in place of `typst query`.
+pub(crate) fn add_note(index: &mut Index, name: &str, content: &str) -> PathBuf {
+    add_note_with(index, name, content, |_| {})
+}
+
+/// Like [`add_note`], letting `edit` change the parsed note before it is stored, as tests do
+/// to give it metadata.
+pub(crate) fn add_note_with(
+    index: &mut Index,
+    name: &str,
+    content: &str,
+    edit: impl FnOnce(&mut ParsedFile),
+) -> PathBuf {
+    let path = index.root().join(name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    std::fs::write(&path, content).unwrap();
+    let mut parsed = PARSER.parse_content(content, &path).unwrap();
+    parsed.metadata = declared_metadata(content);
+    edit(&mut parsed);
+    index.store_file(&path, &parsed).unwrap();
+    path
+}
+
+// The metadata the `#meta(...)` block in `content` declares, if its entries are plain literals
+fn declared_metadata(content: &str) -> Metadata {
+    MetaBlock::locate(content)
+        .and_then(|block| frontmatter::parse_literal(&content[block.open..=block.close]))
+        .and_then(|json| parse_metadata_json(&json.to_string()).ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,171 @@
+use crate::index::{Index, lock};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use ts_rs::TS;
+
+// Each table keyed to notes, with the condition its rows are orphaned by. Rows could outlive
+// their note when older versions replaced a note's `files` row, giving it a new id
+const ORPHANS: &[(&str, &str)] = &[
+    (
+        "metadata",
+        "file_id IS NULL OR file_id NOT IN (SELECT id FROM files)",
+    ),
+    (
+        "wikilinks",
+        "file_id IS NULL OR file_id NOT IN (SELECT id FROM files)",
+    ),
+    (
+        "labels",
+        "file_id IS NULL OR file_id NOT IN (SELECT id FROM files)",
+    ),
+    (
+        "blocks",
+        "file_id IS NULL OR file_id NOT IN (SELECT id FROM files)",
+    ),
+    (
+        "attachments",
+        "file_id IS NULL OR file_id NOT IN (SELECT id FROM files)",
+    ),
+    ("conflicts", "file_id NOT IN (SELECT id FROM files)"),
+    ("figures", "file_id NOT IN (SELECT id FROM files)"),
+    ("citations", "file_id NOT IN (SELECT id FROM files)"),
+    ("bibliographies", "file_id NOT IN (SELECT id FROM files)"),
+    ("packages", "file_id NOT IN (SELECT id FROM files)"),
+    ("embeddings", "file_id NOT IN (SELECT id FROM files)"),
+    ("visits", "file_id NOT IN (SELECT id FROM files)"),
+    ("stars", "file_id NOT IN (SELECT id FROM files)"),
+    ("git_commits", "file_id NOT IN (SELECT id FROM files)"),
+    ("snapshots", "file_id NOT IN (SELECT id FROM files)"),
+    (
+        "resolved_links",
+        "wikilink_id NOT IN (SELECT id FROM wikilinks)
+         OR source_file_id NOT IN (SELECT id FROM files)
+         OR target_file_id NOT IN (SELECT id FROM files)",
+    ),
+    ("bib_entries", "bib_id NOT IN (SELECT id FROM bib_files)"),
+];
+
+/// What verifying the index against the vault found, and whether it was repaired.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct IndexReport {
+    /// What SQLite's integrity check found wrong with the database file itself
+    pub integrity_errors: Vec<String>,
+    /// Indexed notes that were deleted or are now excluded
+    pub missing_files: Vec<PathBuf>,
+    /// Notes changed since they were indexed
+    pub stale_files: Vec<PathBuf>,
+    /// Notes the index doesn't have
+    pub unindexed_files: Vec<PathBuf>,
+    /// Rows left behind by notes no longer indexed, by table
+    pub orphaned_rows: BTreeMap<String, usize>,
+    /// Whether the problems were repaired, rather than only reported
+    pub repaired: bool,
+    /// Whether the index was emptied and rebuilt from the notes, as its database was damaged
+    pub rebuilt: bool,
+}
+
+impl IndexReport {
+    /// Whether nothing was found wrong.
+    pub fn is_clean(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.missing_files.is_empty()
+            && self.stale_files.is_empty()
+            && self.unindexed_files.is_empty()
+            && self.orphaned_rows.is_empty()
+    }
+}
+
+impl Index {
+    /// The problems SQLite's `integrity_check` finds in the database, none if it is sound.
+    pub fn integrity_errors(&self) -> Result<Vec<String>> {
+        let errors: Vec<String> = self
+            .conn
+            .prepare("PRAGMA integrity_check")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(errors.into_iter().filter(|error| error != "ok").collect())
+    }
+
+    /// How many rows of each table belong to no indexed note, leaving out tables with none.
+    pub fn orphaned_rows(&self) -> Result<BTreeMap<String, usize>> {
+        let mut orphaned = BTreeMap::new();
+        for (table, condition) in ORPHANS {
+            let count: i64 = self.conn.query_row(
+                &format!("SELECT COUNT(*) FROM {table} WHERE {condition}"),
+                [],
+                |row| row.get(0),
+            )?;
+            if count > 0 {
+                orphaned.insert(table.to_string(), count as usize);
+            }
+        }
+        Ok(orphaned)
+    }
+
+    /// Delete the rows [`Index::orphaned_rows`] counts, returning how many were deleted.
+    pub fn remove_orphaned_rows(&self) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut removed = 0;
+        for (table, condition) in ORPHANS {
+            removed += tx.execute(&format!("DELETE FROM {table} WHERE {condition}"), [])?;
+        }
+        tx.commit()?;
+        lock(&self.cache).clear();
+        Ok(removed)
+    }
+
+    /// Rebuild the database's indices, then, if it is still damaged, forget every note so the
+    /// next scan indexes the vault from scratch. Stars, visits and history go with the notes.
+    /// Returns whether the notes were forgotten.
+    pub fn reset_damaged(&mut self) -> Result<bool> {
+        self.conn.execute_batch("REINDEX")?;
+        let damaged = !self.integrity_errors()?.is_empty();
+        if damaged {
+            let tx = self.conn.transaction()?;
+            for (table, _) in ORPHANS {
+                tx.execute(&format!("DELETE FROM {table}"), [])?;
+            }
+            tx.execute("DELETE FROM files", [])?;
+            tx.execute("DELETE FROM bib_files", [])?;
+            tx.commit()?;
+            self.conn.execute_batch("VACUUM")?;
+        }
+        lock(&self.cache).clear();
+        Ok(damaged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_orphaned_rows() {
+        let (temp_dir, mut index) = vault(&[("a.typ", "[[a]] <x>")]);
+        let path = temp_dir.path().join("a.typ");
+        assert!(index.integrity_errors().unwrap().is_empty());
+        assert!(index.orphaned_rows().unwrap().is_empty());
+
+        // As left by replacing a note's row, which gave it a new id
+        index
+            .conn
+            .execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                 UPDATE files SET id = id + 100;
+                 PRAGMA foreign_keys = ON;",
+            )
+            .unwrap();
+        let orphaned = index.orphaned_rows().unwrap();
+        assert_eq!(orphaned.get("wikilinks"), Some(&1));
+        assert_eq!(orphaned.get("labels"), Some(&1));
+        assert_eq!(orphaned.get("resolved_links"), Some(&1));
+
+        assert!(index.remove_orphaned_rows().unwrap() >= 3);
+        assert!(index.orphaned_rows().unwrap().is_empty());
+        assert!(!index.reset_damaged().unwrap());
+        assert_eq!(index.get_all_files().unwrap(), vec![path]);
+    }
+}
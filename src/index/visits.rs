@@ -0,0 +1,153 @@
+use crate::index::Index;
+use anyhow::Result;
+use rusqlite::{OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+// Days after which a visit counts half as much towards frecency
+const HALF_LIFE_DAYS: f64 = 7.0;
+
+/// How recently visited notes are ranked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum VisitOrder {
+    /// Most recently visited first
+    Recent,
+    /// Most often visited first
+    Frequent,
+    /// Visit counts decayed by the age of the last visit, halving every week
+    #[default]
+    Frecency,
+}
+
+/// A note the user has opened or jumped to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct VisitedNote {
+    pub path: PathBuf,
+    pub visits: usize,
+    /// Seconds since the Unix epoch
+    pub last_visited: i64,
+}
+
+impl Index {
+    /// Count a visit to an indexed note at `at` (Unix seconds). Other paths are ignored.
+    pub fn record_visit(&self, file_path: &Path, at: i64) -> Result<()> {
+        let relative_path = self.stored_path(file_path)?;
+        let file_id: Option<i64> = self
+            .conn
+            .prepare_cached("SELECT id FROM files WHERE path = ?")?
+            .query_row([&relative_path], |row| row.get(0))
+            .optional()?;
+        if let Some(file_id) = file_id {
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO visits (file_id, count, last_visited) VALUES (?1, 1, ?2)
+                     ON CONFLICT(file_id) DO UPDATE SET
+                        count = count + 1,
+                        last_visited = MAX(last_visited, excluded.last_visited)",
+                )?
+                .execute(params![file_id, at])?;
+        }
+        Ok(())
+    }
+
+    /// At most `limit` visited notes ranked by `order`, as of `now` (Unix seconds).
+    pub fn recent_notes(
+        &self,
+        order: VisitOrder,
+        limit: usize,
+        now: i64,
+    ) -> Result<Vec<VisitedNote>> {
+        let mut notes: Vec<VisitedNote> = self
+            .conn
+            .prepare_cached(
+                "SELECT f.path, v.count, v.last_visited
+                 FROM visits v JOIN files f ON f.id = v.file_id
+                 ORDER BY f.path",
+            )?
+            .query_map([], |row| {
+                Ok(VisitedNote {
+                    path: self.vault_path(&row.get::<_, String>(0)?),
+                    visits: row.get::<_, i64>(1)? as usize,
+                    last_visited: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let frecency = |note: &VisitedNote| {
+            let age_days = (now - note.last_visited).max(0) as f64 / 86_400.0;
+            note.visits as f64 * 0.5f64.powf(age_days / HALF_LIFE_DAYS)
+        };
+        match order {
+            VisitOrder::Recent => notes.sort_by_key(|note| std::cmp::Reverse(note.last_visited)),
+            VisitOrder::Frequent => notes.sort_by(|a, b| {
+                b.visits
+                    .cmp(&a.visits)
+                    .then(b.last_visited.cmp(&a.last_visited))
+            }),
+            VisitOrder::Frecency => notes.sort_by(|a, b| frecency(b).total_cmp(&frecency(a))),
+        }
+        notes.truncate(limit);
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_recent_notes() {
+        let (temp_dir, index) = vault(&[("old.typ", ""), ("often.typ", ""), ("new.typ", "")]);
+        let root = temp_dir.path();
+
+        let day = 86_400;
+        let paths: Vec<PathBuf> = ["old", "often", "new"]
+            .iter()
+            .map(|name| root.join(format!("{name}.typ")))
+            .collect();
+        let (old, often, new) = (&paths[0], &paths[1], &paths[2]);
+        for _ in 0..3 {
+            index.record_visit(old, 0).unwrap();
+        }
+        index.record_visit(often, 20 * day).unwrap();
+        index.record_visit(often, 28 * day).unwrap();
+        index.record_visit(new, 30 * day).unwrap();
+        index.record_visit(&root.join("unindexed.typ"), 0).unwrap();
+
+        let ranked = |order| -> Vec<PathBuf> {
+            index
+                .recent_notes(order, 10, 30 * day)
+                .unwrap()
+                .into_iter()
+                .map(|note| note.path)
+                .collect()
+        };
+        assert_eq!(
+            ranked(VisitOrder::Recent),
+            vec![new.clone(), often.clone(), old.clone()]
+        );
+        assert_eq!(
+            ranked(VisitOrder::Frequent),
+            vec![old.clone(), often.clone(), new.clone()]
+        );
+        assert_eq!(
+            ranked(VisitOrder::Frecency),
+            vec![often.clone(), new.clone(), old.clone()]
+        );
+
+        let top = index.recent_notes(VisitOrder::Frequent, 1, 0).unwrap();
+        assert_eq!(
+            top,
+            vec![VisitedNote {
+                path: old.clone(),
+                visits: 3,
+                last_visited: 0,
+            }]
+        );
+    }
+}
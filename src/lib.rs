@@ -1,3 +1,59 @@
+#[cfg(feature = "native")]
 pub mod command;
+#[cfg(feature = "compile")]
+pub mod compile;
+#[cfg(feature = "native")]
+pub mod config;
+#[cfg(feature = "native")]
+pub mod daily;
+#[cfg(feature = "native")]
+pub mod diagnostics;
+#[cfg(feature = "embeddings")]
+pub mod embeddings;
+#[cfg(feature = "native")]
+pub mod events;
+#[cfg(feature = "native")]
+pub mod export;
+pub mod frontmatter;
+#[cfg(feature = "native")]
+pub mod git;
+pub mod graph;
+#[cfg(feature = "native")]
 pub mod index;
+#[cfg(feature = "native")]
+pub mod logging;
+#[cfg(feature = "native")]
+pub mod lsp;
+#[cfg(feature = "native")]
+pub mod mcp;
+#[cfg(feature = "native")]
+pub mod mentions;
+#[cfg(feature = "native")]
+pub mod outline;
 pub mod parser;
+#[cfg(feature = "native")]
+pub mod paths;
+#[cfg(feature = "native")]
+pub mod publish;
+#[cfg(feature = "native")]
+pub mod query;
+#[cfg(feature = "native")]
+pub mod refactor;
+#[cfg(feature = "native")]
+pub mod related;
+pub mod resolver;
+#[cfg(feature = "native")]
+pub mod sdk;
+#[cfg(feature = "native")]
+pub mod serve;
+pub mod storage;
+pub mod stored_path;
+#[cfg(feature = "native")]
+pub mod templates;
+#[cfg(feature = "native")]
+pub mod vault;
+#[cfg(feature = "native")]
+pub mod workspace;
+
+#[cfg(feature = "native")]
+pub use vault::Vault;
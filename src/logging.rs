@@ -0,0 +1,259 @@
+use crate::lsp::dispatch::panic_message;
+use anyhow::{Context, Result};
+use lsp_server::Notification;
+use lsp_types::notification::{LogMessage, LogTrace, Notification as _};
+use lsp_types::{LogMessageParams, LogTraceParams, MessageType, TraceValue};
+use std::backtrace::Backtrace;
+use std::fmt::{self, Write as _};
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, RwLock};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+type Sink = Box<dyn Fn(Notification) + Send + Sync>;
+
+// Where records go once the client is connected
+static CLIENT: RwLock<Option<Sink>> = RwLock::new(None);
+// `TraceValue` as 0 (off), 1 (messages) or 2 (verbose)
+static TRACE: AtomicU8 = AtomicU8::new(0);
+
+/// Install the subscriber. Records go, with the spans they happened in, to stderr (info and
+/// above) or, with `log_file`, to that file (this crate's debug records too), and each span
+/// logs how long it took when it closes. Dependencies logging through the `log` facade are
+/// forwarded at info and above.
+pub fn init(log_file: Option<&Path>) -> Result<()> {
+    let output = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_span_events(FmtSpan::CLOSE);
+    let output = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            output
+                .with_writer(Mutex::new(file))
+                .with_filter(
+                    Targets::new()
+                        .with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG)
+                        .with_default(Level::INFO),
+                )
+                .boxed()
+        }
+        None => output
+            .with_writer(std::io::stderr)
+            .with_filter(LevelFilter::INFO)
+            .boxed(),
+    };
+    // Dependencies log the messages we send, which would feed back into the client
+    let client =
+        ClientLayer.with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG));
+
+    tracing::subscriber::set_global_default(Registry::default().with(output).with(client))
+        .context("A logger is already installed")?;
+    tracing_log::LogTracer::builder()
+        .with_max_level(log::LevelFilter::Info)
+        .init()
+        .context("A logger is already installed")?;
+    Ok(())
+}
+
+/// Log panics with where they happened and a backtrace, on top of catching them per request.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let location = info
+            .location()
+            .map_or_else(|| "unknown location".to_string(), ToString::to_string);
+        tracing::error!(
+            "panic at {location}: {}\n{}",
+            panic_message(info.payload()),
+            Backtrace::force_capture()
+        );
+    }));
+}
+
+/// Also send records to the client through `send`.
+pub fn set_client(send: impl Fn(Notification) + Send + Sync + 'static) {
+    *CLIENT
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Box::new(send));
+}
+
+/// Stop sending records to the client, releasing whatever `send` held on to.
+pub fn clear_client() {
+    *CLIENT
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// How much to send as `$/logTrace`, from `initialize` or `$/setTrace`.
+pub fn set_trace(value: TraceValue) {
+    let level = match value {
+        TraceValue::Off => 0,
+        TraceValue::Messages => 1,
+        TraceValue::Verbose => 2,
+    };
+    TRACE.store(level, Ordering::Relaxed);
+}
+
+/// Sends records to the client once it is connected: warnings and errors as
+/// `window/logMessage`, and everything the client's `trace` level asks for as `$/logTrace`.
+struct ClientLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ClientLayer {
+    fn on_event(&self, event: &Event<'_>, ctx: LayerContext<'_, S>) {
+        let client = CLIENT
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(send) = client.as_ref() else {
+            return;
+        };
+        let level = *event.metadata().level();
+        let mut message = Message::default();
+        event.record(&mut message);
+        let message = message.to_string();
+
+        if let Some(typ) = message_type(level) {
+            send(Notification::new(
+                LogMessage::METHOD.into(),
+                LogMessageParams {
+                    typ,
+                    message: message.clone(),
+                },
+            ));
+        }
+        let spans: Vec<&str> = ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| span.name())
+            .collect();
+        let origin = || match spans.is_empty() {
+            true => event.metadata().target().to_string(),
+            false => format!("{} in {}", event.metadata().target(), spans.join(" > ")),
+        };
+        if let Some(params) = trace_params(TRACE.load(Ordering::Relaxed), level, message, origin) {
+            send(Notification::new(LogTrace::METHOD.into(), params));
+        }
+    }
+}
+
+// An event's message followed by its other fields as `key=value`
+#[derive(Default)]
+struct Message {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            name => {
+                let _ = write!(self.fields, " {name}={value:?}");
+            }
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.message, self.fields)
+    }
+}
+
+// Only problems are worth a `window/logMessage`
+fn message_type(level: Level) -> Option<MessageType> {
+    match level {
+        Level::ERROR => Some(MessageType::ERROR),
+        Level::WARN => Some(MessageType::WARNING),
+        _ => None,
+    }
+}
+
+// `messages` traces info and above, `verbose` also debug records with their module and the
+// spans they happened in
+fn trace_params(
+    trace: u8,
+    level: Level,
+    message: String,
+    origin: impl FnOnce() -> String,
+) -> Option<LogTraceParams> {
+    let wanted = match trace {
+        0 => false,
+        1 => level <= Level::INFO,
+        _ => true,
+    };
+    wanted.then(|| LogTraceParams {
+        message,
+        verbose: (trace > 1).then(origin),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_params() {
+        let trace = |trace, level| {
+            trace_params(
+                trace,
+                level,
+                "unresolved link target=draft".to_string(),
+                || "typst_oxide::index in request".to_string(),
+            )
+        };
+        assert_eq!(trace(0, Level::ERROR), None);
+        assert_eq!(trace(1, Level::DEBUG), None);
+        assert_eq!(
+            trace(1, Level::INFO),
+            Some(LogTraceParams {
+                message: "unresolved link target=draft".to_string(),
+                verbose: None,
+            })
+        );
+        assert_eq!(
+            trace(2, Level::DEBUG).and_then(|params| params.verbose),
+            Some("typst_oxide::index in request".to_string())
+        );
+        assert_eq!(message_type(Level::WARN), Some(MessageType::WARNING));
+        assert_eq!(message_type(Level::INFO), None);
+    }
+
+    #[test]
+    fn test_message() {
+        struct RecordMessage(std::sync::Arc<Mutex<String>>);
+        impl<S: Subscriber> Layer<S> for RecordMessage {
+            fn on_event(&self, event: &Event<'_>, _: LayerContext<'_, S>) {
+                let mut message = Message::default();
+                event.record(&mut message);
+                *self.0.lock().unwrap() = message.to_string();
+            }
+        }
+
+        let captured = std::sync::Arc::new(Mutex::new(String::new()));
+        let sink = std::sync::Arc::clone(&captured);
+        let subscriber = Registry::default().with(RecordMessage(sink));
+        tracing::subscriber::with_default(subscriber, || {
+            let target = "draft";
+            tracing::debug!(target = ?target, files = 2, "link matches no note");
+        });
+        assert_eq!(
+            *captured.lock().unwrap(),
+            r#"link matches no note target="draft" files=2"#
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::index::Index;
+use crate::lsp::{line_at, path_to_uri, point_range, uri_to_path};
+use crate::parser::Parser;
+use anyhow::Result;
+use lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind, Uri,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The note a link hierarchy starts from: the note linked under the cursor, or else the
+/// document `uri` itself.
+pub fn prepare(
+    index: &Index,
+    parser: &Parser,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Option<Vec<CallHierarchyItem>>> {
+    let linked = line_at(text, position.line)
+        .and_then(|line| {
+            parser.wikilink_at(
+                line,
+                position.line as usize + 1,
+                position.character as usize,
+            )
+        })
+        .map(|(wikilink, _)| index.resolve_target(&wikilink.target))
+        .transpose()?
+        .flatten();
+    let Some(path) = linked.or_else(|| uri_to_path(uri)) else {
+        return Ok(None);
+    };
+    if index.get_file(&path)?.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(vec![note_item(index, &path)?]))
+}
+
+/// Backlinks as incoming calls: each note linking to `item`, with the ranges of its links.
+pub fn incoming(
+    index: &Index,
+    item: &CallHierarchyItem,
+) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    let Some(path) = uri_to_path(&item.uri) else {
+        return Ok(None);
+    };
+    let mut sources: BTreeMap<PathBuf, Vec<Range>> = BTreeMap::new();
+    for (source, link) in index.get_backward_links(&path)? {
+        sources
+            .entry(source)
+            .or_default()
+            .push(point_range(link.line, link.column));
+    }
+
+    let mut calls = Vec::new();
+    for (source, from_ranges) in sources {
+        calls.push(CallHierarchyIncomingCall {
+            from: note_item(index, &source)?,
+            from_ranges,
+        });
+    }
+    Ok(Some(calls))
+}
+
+/// Forward links as outgoing calls: each note `item` links to, with the ranges of the links.
+pub fn outgoing(
+    index: &Index,
+    item: &CallHierarchyItem,
+) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+    let Some(path) = uri_to_path(&item.uri) else {
+        return Ok(None);
+    };
+    let mut targets: BTreeMap<PathBuf, Vec<Range>> = BTreeMap::new();
+    for link in index.get_forward_links(&path)? {
+        let Some(target) = index.resolve_target(&link.target)? else {
+            continue;
+        };
+        if index.get_file(&target)?.is_some() {
+            targets
+                .entry(target)
+                .or_default()
+                .push(point_range(link.line, link.column));
+        }
+    }
+
+    let mut calls = Vec::new();
+    for (target, from_ranges) in targets {
+        calls.push(CallHierarchyOutgoingCall {
+            to: note_item(index, &target)?,
+            from_ranges,
+        });
+    }
+    Ok(Some(calls))
+}
+
+fn note_item(index: &Index, path: &Path) -> Result<CallHierarchyItem> {
+    let title = index.get_file(path)?.and_then(|file| file.metadata.title);
+    let name = title.unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    let relative = path.strip_prefix(index.root()).unwrap_or(path);
+    Ok(CallHierarchyItem {
+        name,
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: Some(relative.display().to_string()),
+        uri: path_to_uri(path)?,
+        range: point_range(1, 1),
+        selection_range: point_range(1, 1),
+        data: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_link_hierarchy() {
+        let notes = [
+            ("a.typ", "[[b]] and [[b:sec]]\n[[c]] [[missing]]"),
+            ("b.typ", "= B <sec>"),
+            ("c.typ", "[[b]]"),
+        ];
+        let (temp_dir, index) = vault(&notes);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let uri = path_to_uri(&root.join("a.typ")).unwrap();
+
+        let items = prepare(&index, &parser, &uri, notes[0].1, Position::new(0, 3))
+            .unwrap()
+            .unwrap();
+        assert_eq!(items[0].name, "b");
+        let b = &items[0];
+        let incoming = incoming(&index, b).unwrap().unwrap();
+        let sources: Vec<_> = incoming
+            .iter()
+            .map(|call| (call.from.name.as_str(), call.from_ranges.len()))
+            .collect();
+        assert_eq!(sources, vec![("a", 2), ("c", 1)]);
+
+        let a = &prepare(&index, &parser, &uri, notes[0].1, Position::new(0, 6))
+            .unwrap()
+            .unwrap()[0];
+        assert_eq!(a.detail.as_deref(), Some("a.typ"));
+        let outgoing = outgoing(&index, a).unwrap().unwrap();
+        let targets: Vec<_> = outgoing
+            .iter()
+            .map(|call| (call.to.name.as_str(), call.from_ranges.clone()))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                ("b", vec![point_range(1, 1), point_range(1, 11)]),
+                ("c", vec![point_range(2, 1)]),
+            ]
+        );
+    }
+}
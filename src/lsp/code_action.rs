@@ -0,0 +1,1015 @@
+use crate::command::handle_tags;
+use crate::config::Config;
+use crate::frontmatter::{MetaBlock, MetadataEdit, metadata_edits, parse_literal};
+use crate::index::Index;
+use crate::lsp::{line_at, path_to_uri, to_position, uri_to_path};
+use crate::mentions::MentionFinder;
+use crate::parser::Parser;
+use crate::parser::conflicts::find_conflicts;
+use crate::parser::headings::heading_text;
+use crate::parser::models::Wikilink;
+use crate::parser::names::edit_distance;
+use crate::refactor::dead_labels::{DeadLabel, dead_labels_in, removal_edits};
+use crate::refactor::extract::{Heading, Replacement, extract_section, section_note};
+use crate::refactor::move_file::retarget;
+use crate::refactor::{self, rewrite::redirect_edits};
+use crate::templates::{self, TemplateVars};
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CreateFile, CreateFileOptions,
+    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
+    Position, Range, ResourceOp, TextDocumentEdit, TextEdit, Uri, WorkspaceEdit,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+// Tag actions offered inside a metadata block, most used tags first
+const MAX_TAG_SUGGESTIONS: usize = 5;
+// Replacement targets offered for an unresolved wikilink, closest first
+const MAX_LINK_SUGGESTIONS: usize = 3;
+
+/// What code actions are computed against besides the document they are requested for.
+pub struct ActionContext<'a> {
+    pub index: &'a Index,
+    pub parser: &'a Parser,
+    pub config: &'a Config,
+    pub today: NaiveDate,
+    /// The current text of a note, which may be open with unsaved changes
+    pub read: &'a dyn Fn(&Path) -> Option<String>,
+    /// Whether the client fills in chosen actions with `codeAction/resolve`, so the edits of
+    /// costly ones are only worked out then
+    pub resolve: bool,
+}
+
+// The `data` of an extract action whose edit is left to `codeAction/resolve`
+#[derive(Serialize, Deserialize)]
+struct ExtractData {
+    uri: Uri,
+    line: u32,
+    include: bool,
+}
+
+/// Quick fixes for the cursor position in the document `uri` with contents `text`.
+pub fn code_actions(
+    cx: &ActionContext,
+    uri: &Uri,
+    text: &str,
+    range: Range,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let ActionContext {
+        index,
+        parser,
+        config,
+        today,
+        ..
+    } = *cx;
+    let mut actions = Vec::new();
+    actions.extend(suggest_targets(
+        index,
+        parser,
+        config,
+        uri,
+        text,
+        range.start,
+    )?);
+    actions.extend(redirect_actions(cx, uri, text, range.start)?);
+    actions.extend(conflict_actions(uri, text, range.start));
+    actions.extend(create_missing_note(
+        index,
+        parser,
+        config,
+        today,
+        text,
+        range.start,
+    )?);
+    actions.extend(link_mention(index, parser, config, uri, text, range.start)?);
+    actions.extend(metadata_actions(index, uri, text, range.start)?);
+    actions.extend(extract_actions(cx, uri, text, range.start)?);
+    actions.extend(dead_label_actions(index, parser, uri, text, range.start)?);
+    Ok(actions)
+}
+
+/// Fill in the edit of an action `code_actions` left to `codeAction/resolve`.
+pub fn resolve_code_action(cx: &ActionContext, mut action: CodeAction) -> Result<CodeAction> {
+    let Some(data) = action.data.take() else {
+        return Ok(action);
+    };
+    let ExtractData { uri, line, include } =
+        serde_json::from_value(data).context("Unknown code action")?;
+    let note = uri_to_path(&uri).context("Not a file URI")?;
+    let text = (cx.read)(&note).with_context(|| format!("Cannot read {}", note.display()))?;
+    let heading = Heading {
+        note: &note,
+        text: &text,
+        line: line as usize,
+    };
+    let replacement = if include {
+        Replacement::Include
+    } else {
+        Replacement::Link
+    };
+    let (_, edit) = extract_section(
+        cx.index,
+        cx.parser,
+        &cx.config.wikilink_syntax,
+        heading,
+        replacement,
+        cx.read,
+    )?
+    .context("The section can no longer be extracted")?;
+    action.edit = Some(edit);
+    Ok(action)
+}
+
+/// Offer to create the note targeted by an unresolved wikilink under the cursor, filled
+/// from `default_template`.
+fn create_missing_note(
+    index: &Index,
+    parser: &Parser,
+    config: &Config,
+    today: NaiveDate,
+    text: &str,
+    position: Position,
+) -> Result<Option<CodeActionOrCommand>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(None);
+    };
+    let Some((wikilink, _)) = parser.wikilink_at(
+        line,
+        position.line as usize + 1,
+        position.character as usize,
+    ) else {
+        return Ok(None);
+    };
+
+    // The note must land inside the vault, so targets with `..`, a root or a drive are left be
+    let target = Path::new(&wikilink.target);
+    if target.extension().is_some()
+        || target.is_absolute()
+        || target
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        || index.resolve_target(&wikilink.target)?.is_some()
+    {
+        return Ok(None);
+    }
+
+    let title = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let vars = TemplateVars {
+        title: &title,
+        date: today,
+        tags: &[],
+    };
+    let content = templates::note_content(index.root(), config, None, &vars)?;
+    let uri = path_to_uri(&index.root().join(format!("{}.typ", wikilink.target)))?;
+
+    let edit = WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(vec![
+            DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                uri: uri.clone(),
+                options: Some(CreateFileOptions {
+                    overwrite: Some(false),
+                    ignore_if_exists: Some(true),
+                }),
+                annotation_id: None,
+            })),
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+                edits: vec![OneOf::Left(TextEdit {
+                    range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                    new_text: content,
+                })],
+            }),
+        ])),
+        ..Default::default()
+    };
+
+    Ok(Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Create note `{}`", wikilink.target),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(edit),
+        ..Default::default()
+    })))
+}
+
+/// For an unresolved wikilink under the cursor, offer to retarget it to the notes whose stem,
+/// title, or alias is closest to its target by edit distance.
+fn suggest_targets(
+    index: &Index,
+    parser: &Parser,
+    config: &Config,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(Vec::new());
+    };
+    let Some((wikilink, span)) = parser.wikilink_at(
+        line,
+        position.line as usize + 1,
+        position.character as usize,
+    ) else {
+        return Ok(Vec::new());
+    };
+    let target = Path::new(&wikilink.target);
+    if target.extension().is_some() || index.resolve_target(&wikilink.target)?.is_some() {
+        return Ok(Vec::new());
+    }
+    let Some(name) = target.file_name().map(|name| name.to_string_lossy()) else {
+        return Ok(Vec::new());
+    };
+    // Allow about one typo per three characters
+    let length = name.chars().count();
+    let max_distance = (length / 3).max(1);
+    // No name differing in length by more than that can be close enough
+    let names = index.note_names(length.saturating_sub(max_distance)..=length + max_distance)?;
+
+    let mut closest: HashMap<String, usize> = HashMap::new();
+    for (stem, candidate) in names {
+        let distance = edit_distance(&name, &candidate);
+        if distance <= max_distance {
+            let best = closest.entry(stem).or_insert(distance);
+            *best = (*best).min(distance);
+        }
+    }
+    let mut closest: Vec<(usize, String)> = closest
+        .into_iter()
+        .map(|(stem, distance)| (distance, stem))
+        .collect();
+    closest.sort();
+
+    let start = Position::new(position.line, span.start as u32);
+    let end = Position::new(position.line, span.end as u32);
+    let actions = closest
+        .into_iter()
+        .take(MAX_LINK_SUGGESTIONS)
+        .enumerate()
+        .map(|(rank, (_, stem))| {
+            let link = Wikilink {
+                target: stem.clone(),
+                ..wikilink.clone()
+            };
+            let edit = TextEdit::new(Range::new(start, end), config.wikilink_syntax.format(&link));
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Did you mean [[{stem}]]?"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                    ..Default::default()
+                }),
+                is_preferred: Some(rank == 0),
+                ..Default::default()
+            })
+        })
+        .collect();
+    Ok(actions)
+}
+
+/// On a link to a note with a `redirect` field, offer to point it, or every such link in the
+/// vault, at the note the redirects end at.
+fn redirect_actions(
+    cx: &ActionContext,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let ActionContext { index, parser, .. } = *cx;
+    let (Some(line), Some(note)) = (line_at(text, position.line), uri_to_path(uri)) else {
+        return Ok(Vec::new());
+    };
+    let Some((mut wikilink, span)) = parser.wikilink_at(
+        line,
+        position.line as usize + 1,
+        position.character as usize,
+    ) else {
+        return Ok(Vec::new());
+    };
+    let Some(target) = index.resolve_target(&wikilink.target)? else {
+        return Ok(Vec::new());
+    };
+    let destination = index.follow_redirects(&target)?;
+    let Some(new_target) = destination
+        .strip_prefix(index.root())
+        .ok()
+        .filter(|_| destination != target && destination != note)
+        .and_then(|relative| retarget(&wikilink.target, relative))
+    else {
+        return Ok(Vec::new());
+    };
+    wikilink.target = new_target;
+    let edit = TextEdit::new(
+        Range::new(
+            Position::new(position.line, span.start as u32),
+            Position::new(position.line, span.end as u32),
+        ),
+        cx.config.wikilink_syntax.format(&wikilink),
+    );
+
+    let mut actions = vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Update link to `{}`", edit.new_text),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })];
+    let count = index.get_redirected_links(None)?.len();
+    if count > 1 {
+        let edits = redirect_edits(index, parser, &cx.config.wikilink_syntax, cx.read)?;
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Update all {count} links to redirected notes"),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(refactor::workspace_edit(edits, &[])?),
+            ..Default::default()
+        }));
+    }
+    Ok(actions)
+}
+
+/// Offer to resolve the merge conflict under the cursor by keeping our side, their side, or
+/// both, dropping the markers and any merge base.
+fn conflict_actions(uri: &Uri, text: &str, position: Position) -> Vec<CodeActionOrCommand> {
+    let line_number = position.line as usize + 1;
+    let Some(conflict) = find_conflicts(text)
+        .into_iter()
+        .find(|conflict| (conflict.line..=conflict.end_line).contains(&line_number))
+    else {
+        return Vec::new();
+    };
+
+    let lines: Vec<&str> = text.lines().collect();
+    let ours = &lines[conflict.line..conflict.base_line.unwrap_or(conflict.separator_line) - 1];
+    let theirs = &lines[conflict.separator_line..conflict.end_line - 1];
+    let branch = |marker_line: usize| lines[marker_line - 1][7..].trim().to_string();
+    let (ours_name, theirs_name) = (branch(conflict.line), branch(conflict.end_line));
+
+    // Replace whole lines, keeping the newline after the closing marker if there is one
+    let newline = text
+        .split_inclusive('\n')
+        .nth(conflict.end_line - 1)
+        .is_some_and(|line| line.ends_with('\n'));
+    let end = if newline {
+        Position::new(conflict.end_line as u32, 0)
+    } else {
+        let last = lines[conflict.end_line - 1].chars().count();
+        Position::new(conflict.end_line as u32 - 1, last as u32)
+    };
+    let range = Range::new(Position::new(conflict.line as u32 - 1, 0), end);
+    let action = |title: String, kept: Vec<&str>| {
+        let mut new_text = kept.join("\n");
+        if newline && !kept.is_empty() {
+            new_text.push('\n');
+        }
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title,
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    uri.clone(),
+                    vec![TextEdit::new(range, new_text)],
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    };
+    let named = |side: &str, name: &str| match name {
+        "" => format!("Keep {side} change"),
+        name => format!("Keep {side} change ({name})"),
+    };
+
+    vec![
+        action(named("current", &ours_name), ours.to_vec()),
+        action(named("incoming", &theirs_name), theirs.to_vec()),
+        action(
+            "Keep both changes".to_string(),
+            ours.iter().chain(theirs).copied().collect(),
+        ),
+    ]
+}
+
+/// Offer to turn an unlinked mention of another note under the cursor into a wikilink.
+fn link_mention(
+    index: &Index,
+    parser: &Parser,
+    config: &Config,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Option<CodeActionOrCommand>> {
+    let Some(path) = uri_to_path(uri) else {
+        return Ok(None);
+    };
+    let line = position.line as usize + 1;
+    let character = position.character as usize + 1;
+    let Some(mention) = MentionFinder::cached(index)?
+        .find(parser, &path, text)
+        .into_iter()
+        .find(|m| m.line == line && m.column <= character && character <= m.column + m.length)
+    else {
+        return Ok(None);
+    };
+
+    let start = to_position(mention.line, mention.column);
+    let end = Position::new(start.line, start.character + mention.length as u32);
+    let edit = TextEdit::new(
+        Range::new(start, end),
+        mention.to_wikilink(&config.wikilink_syntax),
+    );
+
+    Ok(Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Link to `{}`", mention.link_target),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })))
+}
+
+/// Metadata edits for the cursor: on a heading, use it as the title (adding a metadata
+/// block if there is none); inside the metadata block, set the title from the first heading
+/// or add one of the vault's most used tags.
+fn metadata_actions(
+    index: &Index,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let block = MetaBlock::locate(text);
+    let value = |key: &str| {
+        let block = block.as_ref()?;
+        let entry = block.entries.iter().find(|entry| entry.key == key)?;
+        parse_literal(&text[entry.value.0..entry.value.1])
+    };
+    let title = value("title");
+    let set_title = |heading: &str| -> Result<Option<CodeActionOrCommand>> {
+        if title.as_ref().and_then(|t| t.as_str()) == Some(heading) {
+            return Ok(None);
+        }
+        let label = if block.is_some() {
+            format!("Set title to `{heading}`")
+        } else {
+            format!("Add metadata block titled `{heading}`")
+        };
+        let edits = metadata_edits(
+            text,
+            &[MetadataEdit::Set {
+                key: "title".to_string(),
+                value: heading.into(),
+            }],
+        )?;
+        Ok(Some(edit_action(label, uri, edits)))
+    };
+
+    let mut actions = Vec::new();
+    if let Some(heading) = line_at(text, position.line).and_then(heading_text) {
+        actions.extend(set_title(&heading)?);
+        return Ok(actions);
+    }
+
+    let Some(block) = &block else {
+        return Ok(actions);
+    };
+    let line_of = |offset: usize| text[..offset].matches('\n').count() as u32;
+    if !(line_of(block.open)..=line_of(block.close)).contains(&position.line) {
+        return Ok(actions);
+    }
+
+    if let Some(heading) = text.lines().find_map(heading_text) {
+        actions.extend(set_title(&heading)?);
+    }
+    let tags = match value("tags") {
+        Some(serde_json::Value::Array(tags)) => tags,
+        _ => Vec::new(),
+    };
+    let suggestions = handle_tags(index)?
+        .tags
+        .into_iter()
+        .filter(|count| !tags.iter().any(|tag| tag.as_str() == Some(&count.tag)))
+        .take(MAX_TAG_SUGGESTIONS);
+    for count in suggestions {
+        let edits = metadata_edits(
+            text,
+            &[MetadataEdit::AddTag {
+                tag: count.tag.clone(),
+            }],
+        );
+        // A computed `tags` value can't be edited as a list
+        if let Ok(edits) = edits {
+            actions.push(edit_action(format!("Add tag `{}`", count.tag), uri, edits));
+        }
+    }
+    Ok(actions)
+}
+
+/// On a heading, offer to move its section into a new note, replaced by a link or an
+/// `#include`.
+fn extract_actions(
+    cx: &ActionContext,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let Some(note) = uri_to_path(uri) else {
+        return Ok(Vec::new());
+    };
+    let heading = Heading {
+        note: &note,
+        text,
+        line: position.line as usize,
+    };
+    let mut actions = Vec::new();
+    for (replacement, suffix) in [
+        (Replacement::Link, ""),
+        (Replacement::Include, " and include it"),
+    ] {
+        // Extracting reads every note linking to the section's labels, so only on request
+        let (name, edit, data) = if cx.resolve {
+            let Some((name, _)) = section_note(heading)? else {
+                break;
+            };
+            let data = ExtractData {
+                uri: uri.clone(),
+                line: position.line,
+                include: replacement == Replacement::Include,
+            };
+            (name, None, Some(serde_json::to_value(data)?))
+        } else {
+            let extraction = extract_section(
+                cx.index,
+                cx.parser,
+                &cx.config.wikilink_syntax,
+                heading,
+                replacement,
+                cx.read,
+            )?;
+            let Some((name, edit)) = extraction else {
+                break;
+            };
+            (name, Some(edit), None)
+        };
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Extract section to `{name}`{suffix}"),
+            kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+            edit,
+            data,
+            ..Default::default()
+        }));
+    }
+    Ok(actions)
+}
+
+/// On a `<label>` that nothing references, offer to remove it, or every such label in the note.
+fn dead_label_actions(
+    index: &Index,
+    parser: &Parser,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Vec<CodeActionOrCommand>> {
+    let (Some(note), Some(line)) = (uri_to_path(uri), line_at(text, position.line)) else {
+        return Ok(Vec::new());
+    };
+    let Some((name, span)) = parser.label_at(line, position.character as usize) else {
+        return Ok(Vec::new());
+    };
+    let dead = dead_labels_in(index, parser, &note, text)?;
+    let Some(label) = dead.iter().find(|label| {
+        label.name == name
+            && label.line == position.line as usize + 1
+            && label.column == span.start + 1
+    }) else {
+        return Ok(Vec::new());
+    };
+
+    let action = |title: String, labels: &[DeadLabel]| {
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title,
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), removal_edits(text, labels))])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    };
+    let mut actions = vec![action(
+        format!("Remove unused label `<{name}>`"),
+        std::slice::from_ref(label),
+    )];
+    if dead.len() > 1 {
+        actions.push(action(
+            format!("Remove all {} unused labels", dead.len()),
+            &dead,
+        ));
+    }
+    Ok(actions)
+}
+
+fn edit_action(title: String, uri: &Uri, edits: Vec<TextEdit>) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::REFACTOR),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::index::test_support::{add_note_with, vault};
+    use tempfile::tempdir;
+
+    fn actions_at(
+        index: &Index,
+        today: NaiveDate,
+        uri: &Uri,
+        text: &str,
+        range: Range,
+    ) -> Vec<CodeActionOrCommand> {
+        let parser = Parser::new().unwrap();
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+        let cx = ActionContext {
+            index,
+            parser: &parser,
+            config: &Config::default(),
+            today,
+            read: &read,
+            resolve: false,
+        };
+        code_actions(&cx, uri, text, range).unwrap()
+    }
+
+    #[test]
+    fn test_create_missing_note() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let index = Index::new(root).unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let text = "See [[ideas/Next step]] and [[image.png]].";
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let at = |character| Range::new(Position::new(0, character), Position::new(0, character));
+
+        let actions = actions_at(&index, today, &uri, text, at(8));
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected one code action: {actions:?}");
+        };
+        assert_eq!(action.title, "Create note `ideas/Next step`");
+        let Some(DocumentChanges::Operations(operations)) =
+            &action.edit.as_ref().unwrap().document_changes
+        else {
+            panic!("expected document operations");
+        };
+        let DocumentChangeOperation::Edit(edit) = &operations[1] else {
+            panic!("expected a text edit");
+        };
+        assert!(
+            edit.text_document
+                .uri
+                .as_str()
+                .ends_with("ideas/Next%20step.typ")
+        );
+        let OneOf::Left(text_edit) = &edit.edits[0] else {
+            panic!("expected a plain text edit");
+        };
+        assert!(text_edit.new_text.contains("title: \"Next step\""));
+
+        assert!(actions_at(&index, today, &uri, text, at(32)).is_empty());
+        assert!(actions_at(&index, today, &uri, text, at(1)).is_empty());
+        for text in ["[[/tmp/outside]]", "[[../outside]]"] {
+            assert!(
+                actions_at(&index, today, &uri, text, at(4)).is_empty(),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_suggest_targets() {
+        let (temp_dir, mut index) = vault(&[("project-plan.typ", "")]);
+        let root = temp_dir.path();
+        add_note_with(&mut index, "roadmap.typ", "", |parsed| {
+            parsed.metadata.alias = vec!["Road map".to_string()];
+        });
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let actions = |text: &str| {
+            let position = Position::new(0, 4);
+            actions_at(
+                &index,
+                NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+                &uri,
+                text,
+                Range::new(position, position),
+            )
+        };
+
+        let suggested = actions("See [[Projcet-plan:goals|plan]]");
+        let CodeActionOrCommand::CodeAction(action) = &suggested[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!(action.title, "Did you mean [[project-plan]]?");
+        assert_eq!(action.is_preferred, Some(true));
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits[0],
+            TextEdit::new(
+                Range::new(Position::new(0, 4), Position::new(0, 31)),
+                "[[project-plan:goals|plan]]".to_string()
+            )
+        );
+
+        let titles: Vec<String> = actions("See [[road-map]]")
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => Some(action.title),
+                CodeActionOrCommand::Command(_) => None,
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            vec!["Did you mean [[roadmap]]?", "Create note `road-map`"]
+        );
+        assert_eq!(actions("See [[roadmap]]").len(), 0);
+    }
+
+    #[test]
+    fn test_redirect_actions() {
+        let text = "[[old|Old]] and [[old]]";
+        let (temp_dir, mut index) = vault(&[("new.typ", ""), ("note.typ", text)]);
+        let note = temp_dir.path().join("note.typ");
+        add_note_with(&mut index, "old.typ", "", |parsed| {
+            parsed
+                .metadata
+                .custom
+                .insert("redirect".to_string(), "new".into());
+        });
+
+        let uri = path_to_uri(&note).unwrap();
+        let position = Position::new(0, 3);
+        let actions = actions_at(
+            &index,
+            NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            &uri,
+            text,
+            Range::new(position, position),
+        );
+        let titles: Vec<_> = actions
+            .iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.title.as_str(),
+                CodeActionOrCommand::Command(command) => command.title.as_str(),
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Update link to `[[new|Old]]`",
+                "Update all 2 links to redirected notes"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflict_actions() {
+        let uri: Uri = "file:///vault/a.typ".parse().unwrap();
+        let text =
+            "Intro\n<<<<<<< HEAD\nours\n||||||| base\nold\n=======\ntheirs\n>>>>>>> topic\nEnd";
+        assert!(conflict_actions(&uri, text, Position::new(0, 0)).is_empty());
+
+        let actions = conflict_actions(&uri, text, Position::new(4, 0));
+        let edits: Vec<(String, TextEdit)> = actions
+            .into_iter()
+            .map(|action| {
+                let CodeActionOrCommand::CodeAction(action) = action else {
+                    panic!("expected a code action");
+                };
+                let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+                (action.title, edits[0].clone())
+            })
+            .collect();
+        let range = Range::new(Position::new(1, 0), Position::new(8, 0));
+        assert_eq!(
+            edits,
+            vec![
+                (
+                    "Keep current change (HEAD)".to_string(),
+                    TextEdit::new(range, "ours\n".to_string())
+                ),
+                (
+                    "Keep incoming change (topic)".to_string(),
+                    TextEdit::new(range, "theirs\n".to_string())
+                ),
+                (
+                    "Keep both changes".to_string(),
+                    TextEdit::new(range, "ours\ntheirs\n".to_string())
+                ),
+            ]
+        );
+
+        let actions = conflict_actions(&uri, "<<<<<<<\na\n=======\n>>>>>>>", Position::new(0, 0));
+        let CodeActionOrCommand::CodeAction(action) = &actions[1] else {
+            panic!("expected a code action");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(action.title, "Keep incoming change");
+        assert_eq!(
+            *edits,
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(3, 7)),
+                String::new()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_link_mention() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        add_note_with(&mut index, "idea.typ", "", |parsed| {
+            parsed.metadata.title = Some("Big Idea".to_string());
+        });
+
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let text = "A big idea here.";
+        let position = Position::new(0, 4);
+        let actions = actions_at(
+            &index,
+            NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            &uri,
+            text,
+            Range::new(position, position),
+        );
+
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected one code action: {actions:?}");
+        };
+        assert_eq!(action.title, "Link to `idea`");
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits[0],
+            TextEdit::new(
+                Range::new(Position::new(0, 2), Position::new(0, 10)),
+                "[[idea|big idea]]".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_metadata_actions() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        for (name, tags) in [("a.typ", &["rust", "ideas"][..]), ("b.typ", &["rust"][..])] {
+            add_note_with(&mut index, name, "", |parsed| {
+                parsed.metadata.tags = tags.iter().map(|t| t.to_string()).collect();
+            });
+        }
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let titles = |text: &str, line: u32| -> Vec<String> {
+            let position = Position::new(line, 0);
+            actions_at(
+                &index,
+                NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+                &uri,
+                text,
+                Range::new(position, position),
+            )
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.title,
+                CodeActionOrCommand::Command(command) => command.title,
+            })
+            .collect()
+        };
+
+        assert_eq!(
+            titles("= Intro\nBody\n", 0),
+            vec![
+                "Add metadata block titled `Intro`",
+                "Extract section to `Intro`",
+                "Extract section to `Intro` and include it"
+            ]
+        );
+        let text = "#meta(\n  title: \"Old\",\n  tags: (\"ideas\",),\n)\n= Intro\n";
+        assert_eq!(
+            titles(text, 1),
+            vec!["Set title to `Intro`", "Add tag `rust`"]
+        );
+        assert_eq!(
+            titles(text, 4),
+            vec![
+                "Set title to `Intro`",
+                "Extract section to `Intro`",
+                "Extract section to `Intro` and include it"
+            ]
+        );
+        assert!(titles(text, 5).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_extract_action() {
+        let text = "= Notes\n== Intro\nBody\n";
+        let (temp_dir, index) = vault(&[("note.typ", text)]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+        let cx = ActionContext {
+            index: &index,
+            parser: &parser,
+            config: &Config::default(),
+            today: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            read: &read,
+            resolve: true,
+        };
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let position = Position::new(1, 0);
+
+        let actions = code_actions(&cx, &uri, text, Range::new(position, position)).unwrap();
+        let Some(CodeActionOrCommand::CodeAction(action)) = actions.into_iter().last() else {
+            panic!("expected an extract action");
+        };
+        assert_eq!(action.title, "Extract section to `Intro` and include it");
+        assert!(action.edit.is_none());
+
+        let action = resolve_code_action(&cx, action).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("expected document operations");
+        };
+        let DocumentChangeOperation::Op(ResourceOp::Create(create)) = &operations[0] else {
+            panic!("expected the new note to be created");
+        };
+        assert!(create.uri.as_str().ends_with("/Intro.typ"));
+        let DocumentChangeOperation::Edit(edit) = &operations[2] else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(
+            edit.edits,
+            vec![OneOf::Left(TextEdit::new(
+                Range::new(Position::new(1, 0), Position::new(2, 4)),
+                "#include \"Intro.typ\"".to_string()
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_dead_label_actions() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let index = Index::new(root).unwrap();
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let text = "#figure[x] <fig>
+See @fig.
+Text <a>
+More <b>";
+        let actions = |line: u32, character: u32| {
+            let position = Position::new(line, character);
+            actions_at(
+                &index,
+                NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+                &uri,
+                text,
+                Range::new(position, position),
+            )
+        };
+
+        assert!(actions(0, 12).is_empty());
+        let actions = actions(2, 6);
+        let titles: Vec<&str> = actions
+            .iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.title.as_str(),
+                CodeActionOrCommand::Command(command) => command.title.as_str(),
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            vec!["Remove unused label `<a>`", "Remove all 2 unused labels"]
+        );
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            *edits,
+            vec![TextEdit::new(
+                Range::new(Position::new(2, 4), Position::new(2, 8)),
+                String::new()
+            )]
+        );
+    }
+}
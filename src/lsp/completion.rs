@@ -0,0 +1,839 @@
+use crate::config::{DailyNotesConfig, NoteFilter};
+use crate::daily::missing_daily_notes;
+use crate::frontmatter::{self, MetaBlock, MetaCursor, parse_literal};
+use crate::index::Index;
+use crate::lsp::{line_at, offset_at};
+use crate::parser::Parser;
+use crate::parser::models::{BibEntry, Figure};
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::workspace::discover_attachments;
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveTime};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionResponse,
+    CompletionTextEdit, InsertTextFormat, Position, Range, TextEdit,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+// Days after which an edit counts half as much towards a link target's rank
+const RECENCY_HALF_LIFE_DAYS: f64 = 7.0;
+// Tags in common beyond this don't rank a link target higher
+const MAX_SHARED_TAGS: usize = 3;
+
+/// Where wikilink targets are completed, and how.
+pub struct TargetContext<'a> {
+    /// The note being edited
+    pub note: &'a Path,
+    pub daily_notes: &'a DailyNotesConfig,
+    pub today: NaiveDate,
+    /// The client's link syntax, when it supports snippets
+    pub snippets: Option<&'a WikilinkSyntax>,
+}
+
+/// Complete wikilink targets in `cx.note` with existing notes other than archived ones and
+/// daily notes near `cx.today` that don't exist yet. Notes it already links to come first,
+/// then those sharing its tags, then recently edited ones. With `cx.snippets`, each note is
+/// also offered as a whole link with an alias and with a label and alias to fill in, and
+/// every item closes the link.
+pub fn complete(
+    index: &Index,
+    parser: &Parser,
+    cx: &TargetContext,
+    text: &str,
+    position: Position,
+) -> Result<Option<CompletionResponse>> {
+    let TargetContext {
+        note,
+        daily_notes,
+        today,
+        snippets,
+    } = *cx;
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(None);
+    };
+    let Some((start, _)) = parser.target_prefix_at(line, position.character as usize) else {
+        return Ok(None);
+    };
+    let mut range = Range::new(Position::new(position.line, start as u32), position);
+    // Snippets write the whole link, so not after an alias typed first
+    let opens_link = line
+        .chars()
+        .skip(start.saturating_sub(2))
+        .take(2)
+        .eq("[[".chars());
+    let forms = snippets.filter(|_| opens_link);
+    if forms.is_some() {
+        // Replace the `]]` the client may have inserted along with `[[`
+        let after: String = line.chars().skip(position.character as usize).collect();
+        if after.starts_with("]]") {
+            range.end.character += 2;
+        }
+    }
+    let close = if forms.is_some() { "]]" } else { "" };
+    let item = |label: String, detail: String, kind: CompletionItemKind| CompletionItem {
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+            range,
+            format!("{label}{close}"),
+        ))),
+        label,
+        detail: Some(detail),
+        kind: Some(kind),
+        ..Default::default()
+    };
+    let snippet_items = |target: &str, detail: &str| -> Vec<CompletionItem> {
+        let Some(syntax) = forms else {
+            return Vec::new();
+        };
+        let escaped = escape_snippet(target);
+        let separator = syntax.label_separator;
+        let (aliased, labeled) = if syntax.alias_first {
+            (
+                format!("${{1:alias}}|{escaped}]]"),
+                format!("${{2:alias}}|{escaped}{separator}${{1:label}}]]"),
+            )
+        } else {
+            (
+                format!("{escaped}|${{1:alias}}]]"),
+                format!("{escaped}{separator}${{1:label}}|${{2:alias}}]]"),
+            )
+        };
+        let (aliased_label, labeled_label) = if syntax.alias_first {
+            (
+                format!("alias|{target}"),
+                format!("alias|{target}{separator}label"),
+            )
+        } else {
+            (
+                format!("{target}|alias"),
+                format!("{target}{separator}label|alias"),
+            )
+        };
+        [
+            (aliased_label, aliased, "with alias"),
+            (labeled_label, labeled, "with label and alias"),
+        ]
+        .into_iter()
+        .map(|(label, snippet, description)| CompletionItem {
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, snippet))),
+            label,
+            label_details: Some(CompletionItemLabelDetails {
+                detail: None,
+                description: Some(description.to_string()),
+            }),
+            // Found by typing the target, whatever the form
+            filter_text: Some(target.to_string()),
+            detail: Some(detail.to_string()),
+            kind: Some(CompletionItemKind::SNIPPET),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        })
+        .collect()
+    };
+
+    // A name links to one note, so offer each once; ignoring case if links do
+    let fold_case = index.case_insensitive_links()?;
+    let mut offered = HashSet::new();
+    let mut is_new = |name: &str| {
+        if fold_case {
+            offered.insert(name.to_ascii_lowercase())
+        } else {
+            offered.insert(name.to_string())
+        }
+    };
+
+    // Rank notes by how close they are to the one being edited
+    let now = today.and_time(NaiveTime::MIN).and_utc().timestamp();
+    let archived = index.archived_notes()?;
+    let mut ranked: Vec<(f64, Vec<CompletionItem>)> = Vec::new();
+    for candidate in index.link_candidates(note)? {
+        if archived.contains(&candidate.path) {
+            continue;
+        }
+        let Some(stem) = candidate
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        if !is_new(&stem) {
+            continue;
+        }
+        // Linking a note to itself is the least likely
+        let score = if candidate.path == note {
+            0.0
+        } else {
+            link_score(
+                candidate.linked,
+                candidate.shared_tags,
+                candidate.modified_at,
+                now,
+            )
+        };
+        let detail = candidate.title.unwrap_or_else(|| {
+            candidate
+                .path
+                .strip_prefix(index.root())
+                .unwrap_or(&candidate.path)
+                .display()
+                .to_string()
+        });
+        let snippets = snippet_items(&stem, &detail);
+        let mut group = vec![item(stem, detail, CompletionItemKind::FILE)];
+        group.extend(snippets);
+        ranked.push((score, group));
+    }
+    // Stable, so equally close notes stay in path order
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    for (target, date) in missing_daily_notes(index.root(), daily_notes, today)? {
+        if !is_new(&target) {
+            continue;
+        }
+        let detail = format!("New daily note for {}", date.format("%A, %B %-d"));
+        ranked.push((0.0, vec![item(target, detail, CompletionItemKind::EVENT)]));
+    }
+
+    // Clients sort by `sortText`, keeping each note's link forms together
+    let mut items = Vec::new();
+    for (rank, (_, group)) in ranked.into_iter().enumerate() {
+        for (form, mut item) in group.into_iter().enumerate() {
+            item.sort_text = Some(format!("{rank:05}.{form}"));
+            items.push(item);
+        }
+    }
+
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+// How close a link target is to the note being edited: linked from it already first, then by
+// tags in common, then by how recently it was edited (halving every `RECENCY_HALF_LIFE_DAYS`)
+fn link_score(linked: bool, shared_tags: usize, modified_at: Option<i64>, now: i64) -> f64 {
+    let recency = modified_at.map_or(0.0, |at| {
+        let age_days = (now - at).max(0) as f64 / 86_400.0;
+        0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+    });
+    let linked = if linked {
+        MAX_SHARED_TAGS as f64 + 1.0
+    } else {
+        0.0
+    };
+    linked + shared_tags.min(MAX_SHARED_TAGS) as f64 + recency
+}
+
+// Escape the characters with a meaning in snippet syntax
+fn escape_snippet(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '$' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Complete the path argument of `#image("...")` and other asset calls with the vault's
+/// attachments, relative to `note` when they are below its folder and to the root otherwise.
+pub fn complete_asset(
+    root: &Path,
+    parser: &Parser,
+    filter: &NoteFilter,
+    note: &Path,
+    text: &str,
+    position: Position,
+) -> Option<CompletionResponse> {
+    let line = line_at(text, position.line)?;
+    let (start, _) = parser.asset_prefix_at(line, position.character as usize)?;
+    let range = Range::new(Position::new(position.line, start as u32), position);
+    let note_dir = note.parent().unwrap_or(root);
+
+    let items = discover_attachments(root, filter)
+        .into_iter()
+        .map(|file| {
+            let label = match file.strip_prefix(note_dir) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => {
+                    let relative = file.strip_prefix(root).unwrap_or(&file);
+                    format!("/{}", relative.to_string_lossy().replace('\\', "/"))
+                }
+            };
+            CompletionItem {
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    label.clone(),
+                ))),
+                label,
+                kind: Some(CompletionItemKind::FILE),
+                ..Default::default()
+            }
+        })
+        .collect();
+    Some(CompletionResponse::Array(items))
+}
+
+/// Inside the metadata block, complete keys with `title`, `tags`, `alias` and the other keys
+/// used in the vault, and strings in a value with the values other notes give the same key,
+/// such as existing tags. Both are ordered by how many notes use them.
+pub fn complete_metadata(
+    index: &Index,
+    text: &str,
+    position: Position,
+) -> Result<Option<CompletionResponse>> {
+    let Some(offset) = offset_at(text, position) else {
+        return Ok(None);
+    };
+    let Some(cursor) = frontmatter::cursor_at(text, offset) else {
+        return Ok(None);
+    };
+    let block = MetaBlock::locate(text);
+    let entries = block.as_ref().map_or(&[][..], |block| &block.entries[..]);
+    let start = match &cursor {
+        MetaCursor::Key { start } | MetaCursor::Value { start, .. } => *start,
+    };
+    let typed = text[start..offset].chars().count() as u32;
+    let range = Range::new(
+        Position::new(position.line, position.character - typed),
+        position,
+    );
+    let item = |label: String, new_text: String, count: usize, kind, rank: usize| CompletionItem {
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, new_text))),
+        detail: Some(match count {
+            1 => "Used in 1 note".to_string(),
+            count => format!("Used in {count} notes"),
+        }),
+        sort_text: Some(format!("{rank:05}")),
+        filter_text: Some(label.clone()),
+        label,
+        kind: Some(kind),
+        ..Default::default()
+    };
+
+    let items = match cursor {
+        MetaCursor::Key { .. } => {
+            let mut counts: Vec<(String, usize)> = ["title", "tags", "alias"]
+                .into_iter()
+                .map(|key| (key.to_string(), 0))
+                .collect();
+            for (key, count) in index.metadata_key_counts()? {
+                match counts.iter_mut().find(|(known, _)| *known == key) {
+                    Some(known) => known.1 = count,
+                    None => counts.push((key, count)),
+                }
+            }
+            counts
+                .into_iter()
+                .filter(|(key, _)| entries.iter().all(|entry| entry.key != *key))
+                .enumerate()
+                .map(|(rank, (key, count))| {
+                    let new_text = format!("{key}: ");
+                    item(key, new_text, count, CompletionItemKind::FIELD, rank)
+                })
+                .collect()
+        }
+        // Titles and aliases name a single note
+        MetaCursor::Value { key, .. } if key == "title" || key == "alias" => return Ok(None),
+        MetaCursor::Value { key, .. } => {
+            let written: Vec<String> = entries
+                .iter()
+                .filter(|entry| entry.key == key)
+                .filter_map(|entry| parse_literal(&text[entry.value.0..entry.value.1]))
+                .flat_map(|value| match value {
+                    serde_json::Value::Array(values) => values,
+                    value => vec![value],
+                })
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect();
+            index
+                .metadata_value_counts(&key)?
+                .into_iter()
+                .filter(|(value, _)| !written.contains(value))
+                .enumerate()
+                .map(|(rank, (value, count))| {
+                    let new_text = value.replace('\\', "\\\\").replace('"', "\\\"");
+                    item(value, new_text, count, CompletionItemKind::VALUE, rank)
+                })
+                .collect()
+        }
+    };
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+/// Complete an `@label` reference with the labels defined in the document, each marked with
+/// the kind of element it names (`heading`, `table`, `equation`, ...) so clients can filter
+/// and group them, then with the citation keys of the note's bibliography `entries`. Figures
+/// also show, and can be found by, their caption.
+pub fn complete_label(
+    parser: &Parser,
+    note: &Path,
+    entries: &[BibEntry],
+    text: &str,
+    position: Position,
+) -> Result<Option<CompletionResponse>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(None);
+    };
+    let before: String = line.chars().take(position.character as usize).collect();
+    let is_label_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | ':' | '.' | '-');
+    let Some(rest) = before.trim_end_matches(is_label_char).strip_suffix('@') else {
+        return Ok(None);
+    };
+    // An `@` inside a word is an e-mail address
+    if rest.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '@') {
+        return Ok(None);
+    }
+    let start = rest.chars().count() as u32 + 1;
+    let range = Range::new(Position::new(position.line, start), position);
+
+    let parsed = parser.parse_content(text, note)?;
+    let figures: HashMap<&str, &Figure> = parsed
+        .figures
+        .iter()
+        .filter_map(|figure| Some((figure.label.as_deref()?, figure)))
+        .collect();
+    let mut labels = parsed.labels;
+    labels.sort_by(|a, b| (a.kind as u8, &a.name).cmp(&(b.kind as u8, &b.name)));
+    labels.dedup_by(|a, b| a.name == b.name);
+    let defined: HashSet<String> = labels.iter().map(|label| label.name.clone()).collect();
+    let mut items: Vec<_> = labels
+        .into_iter()
+        .map(|label| {
+            let figure = figures.get(label.name.as_str());
+            let caption = figure.and_then(|figure| figure.caption.clone());
+            // A figure reads better as what it shows, e.g. "table"
+            let kind = match figure {
+                Some(figure) => figure.kind.as_str(),
+                None => label.kind.as_str(),
+            };
+            CompletionItem {
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    label.name.clone(),
+                ))),
+                detail: Some(caption.clone().unwrap_or_else(|| kind.to_string())),
+                label_details: Some(CompletionItemLabelDetails {
+                    detail: caption.as_ref().map(|caption| format!(" {caption}")),
+                    description: Some(kind.to_string()),
+                }),
+                // Let a figure be found by the words of its caption too
+                filter_text: caption.map(|caption| format!("{} {caption}", label.name)),
+                sort_text: Some(format!("{}{}", label.kind as u8, label.name)),
+                label: label.name,
+                kind: Some(CompletionItemKind::REFERENCE),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    // Citations sort after labels; a label wins over an entry with the same key
+    for entry in entries.iter().filter(|entry| !defined.contains(&entry.key)) {
+        items.push(CompletionItem {
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                entry.key.clone(),
+            ))),
+            detail: Some(entry.reference()),
+            label_details: Some(CompletionItemLabelDetails {
+                detail: entry.title.as_ref().map(|title| format!(" {title}")),
+                description: Some(entry.kind.clone()),
+            }),
+            filter_text: entry
+                .title
+                .as_ref()
+                .map(|title| format!("{} {title}", entry.key)),
+            sort_text: Some(format!("~{}", entry.key)),
+            label: entry.key.clone(),
+            kind: Some(CompletionItemKind::VALUE),
+            ..Default::default()
+        });
+    }
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, add_note_with, vault};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_complete_targets() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        add_note_with(&mut index, "idea.typ", "", |parsed| {
+            parsed.metadata.title = Some("Big Idea".to_string());
+        });
+
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        let config = DailyNotesConfig::default();
+        let note = root.join("new.typ");
+        let text = "See [[2024-";
+        let Some(CompletionResponse::Array(items)) = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: None,
+            },
+            text,
+            Position::new(0, 11),
+        )
+        .unwrap() else {
+            panic!("expected completions");
+        };
+
+        assert_eq!(items.len(), 16);
+        assert_eq!(items[0].label, "idea");
+        assert_eq!(items[0].detail.as_deref(), Some("Big Idea"));
+        let today_item = items
+            .iter()
+            .find(|item| item.label == "2024-05-10")
+            .unwrap();
+        assert_eq!(
+            today_item.text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                Range::new(Position::new(0, 6), Position::new(0, 11)),
+                "2024-05-10".to_string()
+            )))
+        );
+
+        let outside = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: None,
+            },
+            "plain",
+            Position::new(0, 3),
+        );
+        assert!(outside.unwrap().is_none());
+
+        add_note(&mut index, "old/IDEA.typ", "");
+        index.set_case_insensitive_links(true).unwrap();
+        let Some(CompletionResponse::Array(items)) = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: None,
+            },
+            text,
+            Position::new(0, 11),
+        )
+        .unwrap() else {
+            panic!("expected completions");
+        };
+        assert_eq!(items.len(), 16);
+
+        // Archived notes aren't offered
+        index.set_case_insensitive_links(false).unwrap();
+        index
+            .set_archive_folders(&[std::path::PathBuf::from("old")])
+            .unwrap();
+        let Some(CompletionResponse::Array(items)) = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: None,
+            },
+            text,
+            Position::new(0, 11),
+        )
+        .unwrap() else {
+            panic!("expected completions");
+        };
+        assert!(items.iter().all(|item| item.label != "IDEA"));
+    }
+
+    #[test]
+    fn test_completion_ranking() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::open(root, &root.join("index.db")).unwrap();
+        let parser = Parser::new().unwrap();
+        let day = 86_400;
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        let now = today.and_time(NaiveTime::MIN).and_utc().timestamp();
+        for (name, content, tags, age) in [
+            ("current", "[[linked]]", &["rust"][..], 0),
+            ("alpha", "", &[][..], 30),
+            ("linked", "", &[][..], 60),
+            ("recent", "", &[][..], 1),
+            ("tagged", "", &["rust"][..], 60),
+        ] {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, content).unwrap();
+            let modified =
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs((now - age * day) as u64);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+            let mut parsed = parser.parse_content(content, &path).unwrap();
+            parsed.metadata.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let config = DailyNotesConfig::default();
+        let note = root.join("current.typ");
+        let Some(CompletionResponse::Array(items)) = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: None,
+            },
+            "[[",
+            Position::new(0, 2),
+        )
+        .unwrap() else {
+            panic!("expected completions");
+        };
+        // Daily notes that don't exist yet come last
+        let mut ranked: Vec<_> = items
+            .iter()
+            .filter(|item| item.kind == Some(CompletionItemKind::FILE))
+            .map(|item| (item.sort_text.clone().unwrap(), item.label.as_str()))
+            .collect();
+        ranked.sort();
+        let labels: Vec<_> = ranked.into_iter().map(|(_, label)| label).collect();
+        assert_eq!(
+            labels,
+            vec!["linked", "tagged", "recent", "alpha", "current"]
+        );
+    }
+
+    #[test]
+    fn test_complete_snippets() {
+        let (temp_dir, index) = vault(&[("idea.typ", "")]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        let config = DailyNotesConfig::default();
+        let note = root.join("new.typ");
+        let syntax = WikilinkSyntax::default();
+        let text = "See [[id]] now";
+        let Some(CompletionResponse::Array(items)) = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: Some(&syntax),
+            },
+            text,
+            Position::new(0, 8),
+        )
+        .unwrap() else {
+            panic!("expected completions");
+        };
+        let forms: Vec<_> = items
+            .iter()
+            .take(3)
+            .map(|item| {
+                let Some(CompletionTextEdit::Edit(edit)) = &item.text_edit else {
+                    panic!("expected a text edit");
+                };
+                assert_eq!(
+                    edit.range,
+                    Range::new(Position::new(0, 6), Position::new(0, 10))
+                );
+                (item.label.as_str(), edit.new_text.as_str())
+            })
+            .collect();
+        assert_eq!(
+            forms,
+            vec![
+                ("idea", "idea]]"),
+                ("idea|alias", "idea|${1:alias}]]"),
+                ("idea:label|alias", "idea:${1:label}|${2:alias}]]"),
+            ]
+        );
+        assert_eq!(items[1].insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert_eq!(items[1].filter_text.as_deref(), Some("idea"));
+
+        // After an alias typed first, only the target is completed
+        let syntax = WikilinkSyntax {
+            alias_first: true,
+            ..WikilinkSyntax::default()
+        };
+        let parser = Parser::with_syntax(&syntax).unwrap();
+        let Some(CompletionResponse::Array(items)) = complete(
+            &index,
+            &parser,
+            &TargetContext {
+                note: &note,
+                daily_notes: &config,
+                today,
+                snippets: Some(&syntax),
+            },
+            "[[Idea|id",
+            Position::new(0, 9),
+        )
+        .unwrap() else {
+            panic!("expected completions");
+        };
+        assert!(items.iter().all(|item| item.insert_text_format.is_none()));
+        assert_eq!(escape_snippet("a$b}"), "a\\$b\\}");
+    }
+
+    #[test]
+    fn test_complete_asset() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("notes/img")).unwrap();
+        for name in ["notes/img/plot.png", "logo.svg", "notes/todo.txt"] {
+            std::fs::write(root.join(name), "").unwrap();
+        }
+        let parser = Parser::new().unwrap();
+        let filter = NoteFilter::default();
+        let note = root.join("notes/note.typ");
+
+        let text = "#figure(image(\"im";
+        let Some(CompletionResponse::Array(items)) =
+            complete_asset(root, &parser, &filter, &note, text, Position::new(0, 17))
+        else {
+            panic!("expected completions");
+        };
+
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["/logo.svg", "img/plot.png"]);
+        assert_eq!(
+            items[1].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                Range::new(Position::new(0, 15), Position::new(0, 17)),
+                "img/plot.png".to_string()
+            )))
+        );
+        assert!(
+            complete_asset(root, &parser, &filter, &note, "[[im", Position::new(0, 4)).is_none()
+        );
+    }
+
+    #[test]
+    fn test_complete_metadata() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::open(root, &root.join("index.db")).unwrap();
+        let parser = Parser::new().unwrap();
+        for (name, tags, status) in [
+            ("a", &["rust", "ideas"][..], "draft"),
+            ("b", &["rust"][..], "done"),
+            ("c", &["rust"][..], "draft"),
+        ] {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, "").unwrap();
+            let mut parsed = parser.parse_content("", &path).unwrap();
+            parsed.metadata.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            parsed
+                .metadata
+                .custom
+                .insert("status".to_string(), status.into());
+            index.store_file(&path, &parsed).unwrap();
+        }
+        let complete = |text: &str, position: Position| {
+            let Some(CompletionResponse::Array(items)) =
+                complete_metadata(&index, text, position).unwrap()
+            else {
+                panic!("expected completions");
+            };
+            items
+                .into_iter()
+                .map(|item| (item.label, item.detail.unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        let text = "#meta(\n  tags: (\"rust\", \"\"),\n  st\n)\nBody";
+        let labels = |items: Vec<(String, String)>| {
+            items
+                .into_iter()
+                .map(|(label, _)| label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            labels(complete(text, Position::new(2, 4))),
+            vec!["title", "alias", "status"]
+        );
+        assert_eq!(
+            complete(text, Position::new(1, 18)),
+            vec![("ideas".to_string(), "Used in 1 note".to_string())]
+        );
+        let text = "#meta(status: \"\")";
+        assert_eq!(
+            labels(complete(text, Position::new(0, 15))),
+            vec!["draft", "done"]
+        );
+        assert!(
+            complete_metadata(&index, text, Position::new(0, 19))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_complete_label() {
+        let parser = Parser::new().unwrap();
+        let note = Path::new("/vault/a.typ");
+        let text = "= Intro <intro>\n$ x $ <eq:x>\n#figure(table(), caption: [Results]) <fig:a>\n\
+                    See @f\nmail a@b";
+        let entry = |key: &str| BibEntry {
+            key: key.to_string(),
+            kind: "book".to_string(),
+            title: Some("Literate Programming".to_string()),
+            authors: vec!["Donald E. Knuth".to_string()],
+            year: Some("1992".to_string()),
+        };
+        let entries = [entry("intro"), entry("knuth92")];
+        let Some(CompletionResponse::Array(items)) =
+            complete_label(&parser, note, &entries, text, Position::new(3, 6)).unwrap()
+        else {
+            panic!("expected completions");
+        };
+        let labels: Vec<_> = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.detail.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("intro", "heading"),
+                ("fig:a", "Results"),
+                ("eq:x", "equation"),
+                ("knuth92", "Donald E. Knuth (1992). Literate Programming.")
+            ]
+        );
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(3, 5), Position::new(3, 6))
+        );
+        let details = items[1].label_details.as_ref().unwrap();
+        assert_eq!(details.description.as_deref(), Some("table"));
+        assert_eq!(items[1].filter_text.as_deref(), Some("fig:a Results"));
+        assert!(
+            complete_label(&parser, note, &entries, text, Position::new(4, 7))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
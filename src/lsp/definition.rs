@@ -0,0 +1,172 @@
+use crate::index::Index;
+use crate::lsp::{line_at, path_to_uri, point_range, resolve_wikilink, uri_to_path};
+use crate::parser::Parser;
+use crate::parser::models::Wikilink;
+use anyhow::Result;
+use lsp_types::{Location, Position, Range, Uri};
+use std::path::PathBuf;
+
+/// Jump from the wikilink under the cursor to the note, label, or block it targets, or from
+/// an `@label` reference or `<label>` to the label's definition in the document `uri`.
+pub fn goto_definition(
+    index: &Index,
+    parser: &Parser,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Option<Location>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(None);
+    };
+    let Some((wikilink, _)) = parser.wikilink_at(
+        line,
+        position.line as usize + 1,
+        position.character as usize,
+    ) else {
+        let Some((name, _)) = parser.label_at(line, position.character as usize) else {
+            return Ok(None);
+        };
+        let definition = parser
+            .parse_content(text, &uri_to_path(uri).unwrap_or_default())?
+            .labels
+            .into_iter()
+            .find(|label| label.name == name);
+        return Ok(definition.map(|label| {
+            let (line, column) = label.target();
+            Location::new(uri.clone(), point_range(line, column))
+        }));
+    };
+    let Some(destination) = resolve_wikilink(index, &wikilink)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(Location::new(
+        path_to_uri(&destination.path)?,
+        point_range(destination.line, destination.column),
+    )))
+}
+
+/// Every reference to what is under the cursor in the document `uri`: for a `<label>` or
+/// `@label`, the `@label` references in the document and the `[[note:label]]` links from any
+/// note; for a wikilink, every link to the same note, label, or block.
+pub fn references(
+    index: &Index,
+    parser: &Parser,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+    include_declaration: bool,
+) -> Result<Vec<Location>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(Vec::new());
+    };
+    let path = uri_to_path(uri).unwrap_or_default();
+    let link_location = |(source, link): (PathBuf, Wikilink)| {
+        Ok(Location::new(
+            path_to_uri(&source)?,
+            point_range(link.line, link.column),
+        ))
+    };
+
+    if let Some((wikilink, _)) = parser.wikilink_at(
+        line,
+        position.line as usize + 1,
+        position.character as usize,
+    ) {
+        let Some(target) = index.resolve_target(&wikilink.target)? else {
+            return Ok(Vec::new());
+        };
+        let links = match (&wikilink.block, &wikilink.label) {
+            (Some(block), _) => index
+                .get_backward_links(&target)?
+                .into_iter()
+                .filter(|(_, link)| link.block.as_ref() == Some(block))
+                .collect(),
+            (None, Some(label)) => index.get_backlinks_to_label(&target, label)?,
+            (None, None) => index.get_backward_links(&target)?,
+        };
+        return links.into_iter().map(link_location).collect();
+    }
+
+    let Some((name, _)) = parser.label_at(line, position.character as usize) else {
+        return Ok(Vec::new());
+    };
+    let mut locations = Vec::new();
+    if include_declaration {
+        let definitions = parser.parse_content(text, &path)?.labels;
+        locations.extend(
+            definitions
+                .into_iter()
+                .filter(|label| label.name == name)
+                .map(|label| Location::new(uri.clone(), point_range(label.line, label.column))),
+        );
+    }
+    locations.extend(
+        parser
+            .label_references(text)
+            .into_iter()
+            .filter(|reference| reference.name == name)
+            .map(|reference| {
+                let start = Position::new(reference.line as u32 - 1, reference.column as u32 - 1);
+                let end = Position::new(
+                    start.line,
+                    start.character + 1 + reference.name.chars().count() as u32,
+                );
+                Location::new(uri.clone(), Range::new(start, end))
+            }),
+    );
+    if index.get_file(&path)?.is_some() {
+        for link in index.get_backlinks_to_label(&path, &name)? {
+            locations.push(link_location(link)?);
+        }
+    }
+    Ok(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_label_definition_and_references() {
+        let text = "= Intro <intro>\nAs shown in @intro.\n";
+        let (temp_dir, index) = vault(&[("note.typ", text), ("other.typ", "See [[note:intro]].")]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let other = path_to_uri(&root.join("other.typ")).unwrap();
+
+        let definition = goto_definition(&index, &parser, &uri, text, Position::new(1, 14))
+            .unwrap()
+            .unwrap();
+        // The label names the heading it follows
+        assert_eq!(definition, Location::new(uri.clone(), point_range(1, 1)));
+
+        let locations =
+            references(&index, &parser, &uri, text, Position::new(0, 10), true).unwrap();
+        assert_eq!(
+            locations,
+            vec![
+                Location::new(uri.clone(), point_range(1, 9)),
+                Location::new(
+                    uri.clone(),
+                    Range::new(Position::new(1, 12), Position::new(1, 18))
+                ),
+                Location::new(other.clone(), point_range(1, 5)),
+            ]
+        );
+
+        // From the link, every link to the same label
+        let locations = references(
+            &index,
+            &parser,
+            &other,
+            "See [[note:intro]].",
+            Position::new(0, 7),
+            false,
+        )
+        .unwrap();
+        assert_eq!(locations, vec![Location::new(other, point_range(1, 5))]);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::index::Cancelled;
+use lsp_server::{ErrorCode, Request, RequestId, Response, ResponseError};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+/// How a handler uses the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+type Reply = Result<Value, ResponseError>;
+
+type ReadHandler<S> = Box<dyn Fn(&S, Value) -> Reply + Send + Sync>;
+type WriteHandler<S> = Box<dyn Fn(&mut S, Value) -> Reply + Send + Sync>;
+
+enum Handler<S> {
+    Read(ReadHandler<S>),
+    Write(WriteHandler<S>),
+}
+
+/// Request handlers keyed by method, each with typed params and result.
+///
+/// Read handlers only get shared access to the server state `S`, so they may run
+/// concurrently through [`Dispatcher::dispatch_read`]. Handler errors become
+/// response errors: bad params are `InvalidParams`, [`Cancelled`] queries `RequestCanceled`,
+/// and anything else `InternalError`.
+pub struct Dispatcher<S> {
+    routes: HashMap<&'static str, Handler<S>>,
+}
+
+impl<S> Default for Dispatcher<S> {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl<S> Dispatcher<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle `R` with a handler that only reads the index.
+    pub fn read<R>(
+        mut self,
+        handler: impl Fn(&S, R::Params) -> anyhow::Result<R::Result> + Send + Sync + 'static,
+    ) -> Self
+    where
+        R: lsp_types::request::Request,
+    {
+        let handler = move |state: &S, params| reply::<R>(params, |params| handler(state, params));
+        self.routes
+            .insert(R::METHOD, Handler::Read(Box::new(handler)));
+        self
+    }
+
+    /// Handle `R` with a handler that may update the index or the server state.
+    pub fn write<R>(
+        mut self,
+        handler: impl Fn(&mut S, R::Params) -> anyhow::Result<R::Result> + Send + Sync + 'static,
+    ) -> Self
+    where
+        R: lsp_types::request::Request,
+    {
+        let handler =
+            move |state: &mut S, params| reply::<R>(params, |params| handler(state, params));
+        self.routes
+            .insert(R::METHOD, Handler::Write(Box::new(handler)));
+        self
+    }
+
+    /// The access the handler for `method` declared, or `None` if there is none.
+    pub fn access(&self, method: &str) -> Option<Access> {
+        self.routes.get(method).map(|handler| match handler {
+            Handler::Read(_) => Access::Read,
+            Handler::Write(_) => Access::Write,
+        })
+    }
+
+    /// Run the handler for `request` in a `request` span and build its response.
+    pub fn dispatch(&self, state: &mut S, request: &Request) -> Response {
+        self.timed(request, |handler, params| match handler {
+            Handler::Read(handler) => handler(state, params),
+            Handler::Write(handler) => handler(state, params),
+        })
+    }
+
+    /// Like [`Dispatcher::dispatch`] with shared access to `state`, which fails for handlers
+    /// that need write access.
+    pub fn dispatch_read(&self, state: &S, request: &Request) -> Response {
+        self.timed(request, |handler, params| match handler {
+            Handler::Read(handler) => handler(state, params),
+            Handler::Write(_) => Err(ResponseError {
+                code: ErrorCode::InternalError as i32,
+                message: format!("{} needs write access", request.method),
+                data: None,
+            }),
+        })
+    }
+
+    fn timed(&self, request: &Request, run: impl FnOnce(&Handler<S>, Value) -> Reply) -> Response {
+        let Some(handler) = self.routes.get(request.method.as_str()) else {
+            return error_response(
+                request.id.clone(),
+                ErrorCode::MethodNotFound,
+                "Method not found",
+            );
+        };
+
+        // Closing the span logs how long the handler took
+        let _span =
+            tracing::info_span!("request", method = %request.method, id = %request.id).entered();
+        // A panicking handler fails its own request instead of taking down the server. State
+        // it left half-updated stays usable, since every lock recovers from poisoning
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run(handler, request.params.clone())))
+            .unwrap_or_else(|payload| {
+                let message = panic_message(payload.as_ref());
+                tracing::error!("{} panicked: {message}", request.method);
+                Err(ResponseError {
+                    code: ErrorCode::InternalError as i32,
+                    message: format!("{} panicked: {message}", request.method),
+                    data: None,
+                })
+            });
+        match result {
+            Ok(value) => Response::new_ok(request.id.clone(), value),
+            Err(error) => Response {
+                id: request.id.clone(),
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+// Parse the params for `R`, run the handler and serialize its result
+fn reply<R: lsp_types::request::Request>(
+    params: Value,
+    handler: impl FnOnce(R::Params) -> anyhow::Result<R::Result>,
+) -> Reply {
+    let params = parse_params(params).map_err(|e| ResponseError {
+        code: ErrorCode::InvalidParams as i32,
+        message: e.to_string(),
+        data: None,
+    })?;
+    let result = handler(params).map_err(|e| to_response_error(&e))?;
+    serde_json::to_value(result).map_err(|e| to_response_error(&e.into()))
+}
+
+/// The error response for `error`, so clients can tell cancelled requests from failures.
+pub fn to_response_error(error: &anyhow::Error) -> ResponseError {
+    let code = if error.is::<Cancelled>() {
+        ErrorCode::RequestCanceled
+    } else {
+        ErrorCode::InternalError
+    };
+    ResponseError {
+        code: code as i32,
+        message: error.to_string(),
+        data: None,
+    }
+}
+
+/// The message a panic was raised with, if it was a string.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+pub fn error_response(id: RequestId, code: ErrorCode, message: &str) -> Response {
+    Response::new_err(id, code as i32, message.to_string())
+}
+
+// Clients may omit params that are all optional, or send `{}` for methods without any
+fn parse_params<P: DeserializeOwned>(params: Value) -> serde_json::Result<P> {
+    let fallback = match &params {
+        Value::Null => Some(Value::Object(Default::default())),
+        Value::Object(map) if map.is_empty() => Some(Value::Null),
+        _ => None,
+    };
+    serde_json::from_value(params).or_else(|e| match fallback {
+        Some(fallback) => serde_json::from_value(fallback).map_err(|_| e),
+        None => Err(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::request::{Completion, HoverRequest, Request as _, Shutdown};
+
+    struct Counter(u32);
+
+    #[test]
+    fn test_dispatch() {
+        let dispatcher = Dispatcher::<Counter>::new()
+            .read::<Shutdown>(|counter, ()| {
+                anyhow::ensure!(counter.0 > 0, Cancelled);
+                Ok(())
+            })
+            .write::<HoverRequest>(|counter, _| {
+                counter.0 += 1;
+                Ok(None)
+            })
+            .read::<Completion>(|_, _| panic!("parser bug"));
+        assert_eq!(dispatcher.access(Shutdown::METHOD), Some(Access::Read));
+        assert_eq!(dispatcher.access(HoverRequest::METHOD), Some(Access::Write));
+        assert_eq!(dispatcher.access("unknown"), None);
+
+        let mut counter = Counter(0);
+        let request = |method: &str, params| Request {
+            id: RequestId::from(1),
+            method: method.to_string(),
+            params,
+        };
+        let code = |response: Response| response.error.map(|error| error.code);
+
+        let response = dispatcher.dispatch(&mut counter, &request(Shutdown::METHOD, Value::Null));
+        assert_eq!(code(response), Some(ErrorCode::RequestCanceled as i32));
+
+        let hover = serde_json::json!({
+            "textDocument": { "uri": "file:///note.typ" },
+            "position": { "line": 0, "character": 0 },
+        });
+        let response =
+            dispatcher.dispatch(&mut counter, &request(HoverRequest::METHOD, hover.clone()));
+        assert_eq!(response.result, Some(Value::Null));
+        assert_eq!(counter.0, 1);
+
+        // `{}` is accepted for methods without params
+        let response = dispatcher.dispatch(
+            &mut counter,
+            &request(Shutdown::METHOD, serde_json::json!({})),
+        );
+        assert_eq!(code(response), None);
+
+        let response =
+            dispatcher.dispatch(&mut counter, &request(HoverRequest::METHOD, Value::Null));
+        assert_eq!(code(response), Some(ErrorCode::InvalidParams as i32));
+        let response = dispatcher.dispatch(&mut counter, &request("unknown", Value::Null));
+        assert_eq!(code(response), Some(ErrorCode::MethodNotFound as i32));
+
+        let completion = serde_json::json!({
+            "textDocument": { "uri": "file:///note.typ" },
+            "position": { "line": 0, "character": 0 },
+        });
+        let response = dispatcher.dispatch_read(&counter, &request(Completion::METHOD, completion));
+        let error = response.error.unwrap();
+        assert_eq!(error.code, ErrorCode::InternalError as i32);
+        assert_eq!(
+            error.message,
+            "textDocument/completion panicked: parser bug"
+        );
+
+        let response = dispatcher.dispatch_read(&counter, &request(HoverRequest::METHOD, hover));
+        assert_eq!(code(response), Some(ErrorCode::InternalError as i32));
+        let response = dispatcher.dispatch_read(&counter, &request(Shutdown::METHOD, Value::Null));
+        assert_eq!(code(response), None);
+    }
+}
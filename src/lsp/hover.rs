@@ -0,0 +1,322 @@
+use crate::frontmatter::{self, tag_at};
+use crate::index::Index;
+use crate::lsp::{line_at, offset_at, path_to_uri, resolve_wikilink};
+use crate::parser::Parser;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use std::path::Path;
+
+// Number of lines shown when previewing a whole note or a label's surroundings
+const PREVIEW_LINES: usize = 10;
+const LABEL_CONTEXT_LINES: usize = 3;
+// Number of tagged notes listed when hovering a tag
+const TAGGED_NOTES: usize = 5;
+
+/// Preview the target of the wikilink under the cursor, show the bibliography entry an `@key`
+/// under it cites from the note at `note`, or summarize the metadata tag under it.
+pub fn hover(
+    index: &Index,
+    parser: &Parser,
+    note: &Path,
+    text: &str,
+    position: Position,
+) -> Result<Option<Hover>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(None);
+    };
+    let Some((wikilink, span)) = parser.wikilink_at(
+        line,
+        position.line as usize + 1,
+        position.character as usize,
+    ) else {
+        if let Some(hover) = citation_hover(index, parser, note, line, position)? {
+            return Ok(Some(hover));
+        }
+        return tag_hover(index, text, position);
+    };
+    let Some(destination) = resolve_wikilink(index, &wikilink)? else {
+        return Ok(None);
+    };
+
+    let Ok(content) = std::fs::read_to_string(&destination.path) else {
+        return Ok(None);
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let target_line = destination.line.saturating_sub(1);
+
+    let preview = if let Some(block_id) = &wikilink.block {
+        // Show the block itself without its trailing marker
+        lines
+            .get(target_line)
+            .map(|line| {
+                line.trim_end()
+                    .trim_end_matches(&format!("^{block_id}"))
+                    .trim_end()
+            })
+            .unwrap_or_default()
+            .to_string()
+    } else if let Some(label) = &wikilink.label {
+        // Show the labeled element itself, or the lines leading up to a bare label
+        let element = index
+            .get_label(&destination.path, label)?
+            .and_then(|label| label.element);
+        let (start, end) = match element {
+            Some(element) => (element.line - 1, element.end_line),
+            None => (
+                target_line.saturating_sub(LABEL_CONTEXT_LINES),
+                target_line + 1,
+            ),
+        };
+        lines[start.min(lines.len())..end.min(lines.len())].join("\n")
+    } else {
+        lines[..PREVIEW_LINES.min(lines.len())].join("\n")
+    };
+
+    let parsed = index.get_file(&destination.path)?;
+    let title = parsed
+        .as_ref()
+        .and_then(|parsed| parsed.metadata.title.clone())
+        .unwrap_or_else(|| wikilink.target.clone());
+    let mut value = format!("**{title}**");
+    if let Some(stats) = parsed.map(|parsed| parsed.stats) {
+        value.push_str(&format!(
+            " · {} words · {} min read",
+            stats.words, stats.reading_minutes
+        ));
+    }
+    if let Some(commit) = index.last_commit(&destination.path)? {
+        let date = DateTime::from_timestamp(commit.committed_at, 0)
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format(" on %Y-%m-%d")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        value.push_str(&format!(
+            "\n\nLast changed in `{}` by {}{date}",
+            commit.short_hash(),
+            commit.author
+        ));
+    }
+    value.push_str(&format!("\n\n```typst\n{preview}\n```"));
+
+    let line_number = position.line;
+    Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range::new(
+            Position::new(line_number, span.start as u32),
+            Position::new(line_number, span.end as u32),
+        )),
+    }))
+}
+
+/// The full reference of the bibliography entry cited by the `@key` under the cursor.
+fn citation_hover(
+    index: &Index,
+    parser: &Parser,
+    note: &Path,
+    line: &str,
+    position: Position,
+) -> Result<Option<Hover>> {
+    let Some((key, span)) = parser.label_at(line, position.character as usize) else {
+        return Ok(None);
+    };
+    let Some(entry) = index
+        .bib_entries(note)?
+        .into_iter()
+        .find(|entry| entry.key == key)
+    else {
+        return Ok(None);
+    };
+    Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "**{}** · {}\n\n{}",
+                entry.key,
+                entry.kind,
+                entry.reference()
+            ),
+        }),
+        range: Some(Range::new(
+            Position::new(position.line, span.start as u32),
+            Position::new(position.line, span.end as u32),
+        )),
+    }))
+}
+
+/// How many notes use the metadata tag under the cursor and the most linked of them, with a
+/// link to the client's `typst-oxide.query` command that finds them all.
+fn tag_hover(index: &Index, text: &str, position: Position) -> Result<Option<Hover>> {
+    let Some((tag, (start, end))) = offset_at(text, position).and_then(|at| tag_at(text, at))
+    else {
+        return Ok(None);
+    };
+
+    let notes = index.tagged_notes(&tag)?;
+    let mut value = format!("**Tag `{tag}`**: used by {} note(s)\n", notes.len());
+    for (path, title, backlinks) in notes.iter().take(TAGGED_NOTES) {
+        let title = title.clone().unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        value.push_str(&format!(
+            "\n- [{title}]({}) ({backlinks} backlink(s))",
+            path_to_uri(path)?.as_str()
+        ));
+    }
+    let query = format!("[{}]", serde_json::to_string(&format!("tag:{tag}"))?);
+    value.push_str(&format!(
+        "\n\n[Find all](command:typst-oxide.query?{})",
+        utf8_percent_encode(&query, NON_ALPHANUMERIC)
+    ));
+
+    Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range::new(
+            frontmatter::position(text, start),
+            frontmatter::position(text, end),
+        )),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, add_note_with, vault};
+
+    #[test]
+    fn test_tag_hover() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        for (name, content, tags) in [
+            ("a.typ", "", &["rust"][..]),
+            ("b.typ", "", &["rust", "ideas"][..]),
+            ("c.typ", "[[b]]", &["ideas"][..]),
+        ] {
+            add_note_with(&mut index, name, content, |parsed| {
+                parsed.metadata.tags = tags.iter().map(|t| t.to_string()).collect();
+            });
+        }
+
+        let text = "#meta(\n  tags: (\"rust\",),\n)\nrust";
+        let tag = hover(&index, &parser, Path::new(""), text, Position::new(1, 11))
+            .unwrap()
+            .unwrap();
+        let HoverContents::Markup(markup) = tag.contents else {
+            panic!("expected markdown");
+        };
+        assert!(
+            markup
+                .value
+                .starts_with("**Tag `rust`**: used by 2 note(s)\n\n- [b](")
+        );
+        assert!(markup.value.contains("(1 backlink(s))\n- [a]("));
+        assert!(
+            markup
+                .value
+                .ends_with("(command:typst-oxide.query?%5B%22tag%3Arust%22%5D)")
+        );
+        assert_eq!(
+            tag.range,
+            Some(Range::new(Position::new(1, 9), Position::new(1, 15)))
+        );
+        assert!(
+            hover(&index, &parser, Path::new(""), text, Position::new(3, 1))
+                .unwrap()
+                .is_none()
+        );
+
+        let link = hover(&index, &parser, Path::new(""), "[[c]]", Position::new(0, 2))
+            .unwrap()
+            .unwrap();
+        let HoverContents::Markup(markup) = link.contents else {
+            panic!("expected markdown");
+        };
+        assert!(markup.value.starts_with("**c** · 1 words · 1 min read\n"));
+
+        let commit = crate::git::CommitInfo {
+            hash: "0123456789".to_string(),
+            author: "Ada".to_string(),
+            committed_at: 0,
+            commits_ago: 0,
+        };
+        let commits = std::collections::HashMap::from([(root.join("c.typ"), commit)]);
+        index.set_last_commits(None, &commits).unwrap();
+        let link = hover(&index, &parser, Path::new(""), "[[c]]", Position::new(0, 2))
+            .unwrap()
+            .unwrap();
+        let HoverContents::Markup(markup) = link.contents else {
+            panic!("expected markdown");
+        };
+        assert!(
+            markup
+                .value
+                .contains("\n\nLast changed in `0123456` by Ada on ")
+        );
+    }
+
+    #[test]
+    fn test_label_hover() {
+        let content = "Intro\n#figure(\n  image(\"a.png\"),\n) <fig>\nAfter";
+        let (_temp_dir, index) = vault(&[("b.typ", content)]);
+        let parser = Parser::new().unwrap();
+
+        let link = hover(
+            &index,
+            &parser,
+            Path::new(""),
+            "[[b:fig]]",
+            Position::new(0, 2),
+        )
+        .unwrap()
+        .unwrap();
+        let HoverContents::Markup(markup) = link.contents else {
+            panic!("expected markdown");
+        };
+        assert!(
+            markup
+                .value
+                .ends_with("```typst\n#figure(\n  image(\"a.png\"),\n) <fig>\n```")
+        );
+    }
+
+    #[test]
+    fn test_citation_hover() {
+        let (temp_dir, mut index) = vault(&[]);
+        let parser = Parser::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join("refs.bib"),
+            "@book{knuth, author = {Donald Knuth}, title = {TAOCP}, year = 1968}",
+        )
+        .unwrap();
+        let content = "See @knuth.\n#bibliography(\"refs.bib\")";
+        let path = add_note(&mut index, "a.typ", content);
+        index.refresh_bibliographies(None).unwrap();
+
+        let citation = hover(&index, &parser, &path, content, Position::new(0, 6))
+            .unwrap()
+            .unwrap();
+        let HoverContents::Markup(markup) = citation.contents else {
+            panic!("expected markdown");
+        };
+        assert_eq!(
+            markup.value,
+            "**knuth** · book\n\nDonald Knuth (1968). TAOCP."
+        );
+        assert_eq!(
+            citation.range,
+            Some(Range::new(Position::new(0, 4), Position::new(0, 10)))
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::config::InlayHintsConfig;
+use crate::index::Index;
+use crate::parser::Parser;
+use anyhow::Result;
+use lsp_types::{InlayHint, InlayHintLabel, InlayHintTooltip, Position, Range};
+
+/// Hints after the wikilinks on the lines of `range`: the title of the note a link without an
+/// alias resolves to, and a marker on links that resolve to nothing.
+pub fn inlay_hints(
+    index: &Index,
+    parser: &Parser,
+    config: &InlayHintsConfig,
+    text: &str,
+    range: Range,
+) -> Result<Vec<InlayHint>> {
+    let mut hints = Vec::new();
+    if !config.titles && !config.unresolved {
+        return Ok(hints);
+    }
+
+    for (wikilink, span) in parser.wikilink_spans(text) {
+        let line = wikilink.line as u32 - 1;
+        if line < range.start.line || line > range.end.line {
+            continue;
+        }
+        let position = Position::new(line, span.end as u32);
+        let (label, tooltip) = match index.resolve_target(&wikilink.target)? {
+            Some(path) if config.titles && wikilink.alias.is_none() => {
+                let title = index.get_file(&path)?.and_then(|file| file.metadata.title);
+                match title {
+                    Some(title) if title != wikilink.target => (format!("→ \"{title}\""), None),
+                    _ => continue,
+                }
+            }
+            None if config.unresolved => (
+                "unresolved".to_string(),
+                Some(format!("No note or file matches `{}`", wikilink.target)),
+            ),
+            _ => continue,
+        };
+        hints.push(InlayHint {
+            position,
+            label: InlayHintLabel::String(label),
+            kind: None,
+            text_edits: None,
+            tooltip: tooltip.map(InlayHintTooltip::String),
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    Ok(hints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note_with, vault};
+
+    #[test]
+    fn test_inlay_hints() {
+        let (_temp_dir, mut index) = vault(&[]);
+        add_note_with(&mut index, "q3.typ", "", |parsed| {
+            parsed.metadata.title = Some("Quarterly Planning".to_string());
+        });
+        let parser = Parser::new().unwrap();
+
+        let text = "[[q3]] [[q3|Plan]] [[gone]]\n[[q3]]";
+        let range = Range::new(Position::new(0, 0), Position::new(0, 0));
+        let labels = |config: &InlayHintsConfig| -> Vec<(Position, String)> {
+            inlay_hints(&index, &parser, config, text, range)
+                .unwrap()
+                .into_iter()
+                .map(|hint| match hint.label {
+                    InlayHintLabel::String(label) => (hint.position, label),
+                    InlayHintLabel::LabelParts(_) => panic!("expected a plain label"),
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            labels(&InlayHintsConfig::default()),
+            vec![
+                (Position::new(0, 6), "→ \"Quarterly Planning\"".to_string()),
+                (Position::new(0, 27), "unresolved".to_string()),
+            ]
+        );
+        let titles_only = InlayHintsConfig {
+            unresolved: false,
+            ..InlayHintsConfig::default()
+        };
+        assert_eq!(labels(&titles_only).len(), 1);
+    }
+}
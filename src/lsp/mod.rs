@@ -0,0 +1,187 @@
+pub mod call_hierarchy;
+pub mod code_action;
+pub mod completion;
+pub mod definition;
+pub mod dispatch;
+pub mod hover;
+pub mod inlay_hint;
+pub mod on_type;
+pub mod pool;
+pub mod pull_diagnostics;
+pub mod reindex;
+pub mod rename;
+pub mod rpc;
+pub mod selection_range;
+
+use crate::diagnostics::{Problem, Severity};
+use crate::index::{Index, os_string_from_bytes};
+use crate::parser::models::Wikilink;
+use anyhow::{Context, Result};
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString,
+    Position, Range, Uri,
+};
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, percent_encode};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Characters that must be escaped in the path component of a file URI
+const PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    if !uri
+        .scheme()
+        .is_some_and(|scheme| scheme.eq_lowercase("file"))
+    {
+        return None;
+    }
+
+    let path: Vec<u8> = percent_decode_str(uri.path().as_str()).collect();
+
+    // `file:///C:/notes` carries a leading slash before the drive letter
+    if cfg!(windows) {
+        let slashes = path.iter().take_while(|&&byte| byte == b'/').count();
+        let trimmed = &path[slashes..];
+        if trimmed.get(1) == Some(&b':') {
+            return Some(PathBuf::from(os_string_from_bytes(trimmed.to_vec())));
+        }
+    }
+
+    Some(PathBuf::from(os_string_from_bytes(path)))
+}
+
+/// The `file://` URI of `path`. Bytes that are not valid UTF-8 are percent-encoded as they are.
+pub fn path_to_uri(path: &Path) -> Result<Uri> {
+    let mut path = path.as_os_str().as_encoded_bytes().to_vec();
+    if cfg!(windows) {
+        for byte in &mut path {
+            if *byte == b'\\' {
+                *byte = b'/';
+            }
+        }
+    }
+    let prefix = if path.first() == Some(&b'/') {
+        "file://"
+    } else {
+        "file:///"
+    };
+    let uri = format!("{prefix}{}", percent_encode(&path, PATH_ENCODE_SET));
+
+    Uri::from_str(&uri).with_context(|| format!("Invalid file URI: {uri}"))
+}
+
+/// Convert a 1-based parser line/column into an LSP position.
+pub fn to_position(line: usize, column: usize) -> Position {
+    Position::new(
+        line.saturating_sub(1) as u32,
+        column.saturating_sub(1) as u32,
+    )
+}
+
+pub fn point_range(line: usize, column: usize) -> Range {
+    let position = to_position(line, column);
+    Range::new(position, position)
+}
+
+/// Report a vault problem as an LSP diagnostic coded by its kind.
+pub fn to_diagnostic(problem: &Problem) -> Diagnostic {
+    let severity = match problem.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Information => DiagnosticSeverity::INFORMATION,
+    };
+    let related: Vec<DiagnosticRelatedInformation> = problem
+        .related
+        .iter()
+        .filter_map(|location| {
+            Some(DiagnosticRelatedInformation {
+                location: Location::new(
+                    path_to_uri(&location.file).ok()?,
+                    point_range(location.line, location.column),
+                ),
+                message: "Also defined here".to_string(),
+            })
+        })
+        .collect();
+    let code = serde_json::to_value(problem.kind)
+        .ok()
+        .and_then(|kind| kind.as_str().map(str::to_string));
+    let range = match problem.end {
+        Some((line, column)) => Range::new(
+            to_position(problem.line, problem.column),
+            to_position(line, column),
+        ),
+        None => point_range(problem.line, problem.column),
+    };
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: code.map(NumberOrString::String),
+        source: Some("typst-oxide".to_string()),
+        message: problem.message.clone(),
+        related_information: (!related.is_empty()).then_some(related),
+        ..Default::default()
+    }
+}
+
+pub fn line_at(text: &str, line: u32) -> Option<&str> {
+    text.lines().nth(line as usize)
+}
+
+/// The byte offset of `position` in `text`.
+pub fn offset_at(text: &str, position: Position) -> Option<usize> {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = line_at(text, position.line)?;
+    let column = line
+        .char_indices()
+        .nth(position.character as usize)
+        .map_or(line.len(), |(i, _)| i);
+    Some(line_start + column)
+}
+
+/// The file and 1-based line/column a wikilink points at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkDestination {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where `wikilink` leads, following `redirect` metadata to the note it ends at.
+pub fn resolve_wikilink(index: &Index, wikilink: &Wikilink) -> Result<Option<LinkDestination>> {
+    let Some(path) = index.resolve_target(&wikilink.target)? else {
+        return Ok(None);
+    };
+    let path = index.follow_redirects(&path)?;
+
+    let anchor = if let Some(block_id) = &wikilink.block {
+        index
+            .get_block(&path, block_id)?
+            .map(|block| (block.line, block.column))
+    } else if let Some(label) = &wikilink.label {
+        index.get_label(&path, label)?.map(|label| label.target())
+    } else {
+        None
+    };
+
+    let (line, column) = anchor.unwrap_or((1, 1));
+    Ok(Some(LinkDestination { path, line, column }))
+}
@@ -0,0 +1,83 @@
+use crate::index::Index;
+use crate::lsp::line_at;
+use crate::parser::wikilinks::WikilinkSyntax;
+use anyhow::Result;
+use lsp_types::{Position, Range, TextEdit};
+
+/// Edits after typing `ch` at `position` (just past the typed character): close a `[[` just
+/// opened with `]]`, and fill the alias after a `|` typed behind a link's target with the
+/// title of the note it points to.
+pub fn on_type_formatting(
+    index: &Index,
+    syntax: &WikilinkSyntax,
+    text: &str,
+    position: Position,
+    ch: &str,
+) -> Result<Vec<TextEdit>> {
+    let Some(line) = line_at(text, position.line) else {
+        return Ok(Vec::new());
+    };
+    let chars: Vec<char> = line.chars().collect();
+    let cursor = (position.character as usize).min(chars.len());
+    let before: String = chars[..cursor].iter().collect();
+    let after: String = chars[cursor..].iter().collect();
+    let insert = |new_text: String| vec![TextEdit::new(Range::new(position, position), new_text)];
+
+    match ch {
+        "[" if before.ends_with("[[") && !before.ends_with("[[[") && !after.starts_with("]]") => {
+            Ok(insert("]]".to_string()))
+        }
+        "|" if !syntax.alias_first && after.starts_with("]]") => {
+            let Some((_, link)) = before
+                .strip_suffix('|')
+                .and_then(|before| before.rsplit_once("[["))
+            else {
+                return Ok(Vec::new());
+            };
+            if link.contains("]]") || link.contains('|') {
+                return Ok(Vec::new());
+            }
+            let target = link
+                .split(['#', syntax.label_separator])
+                .next()
+                .unwrap_or_default();
+            let Some(path) = index.resolve_target(target)? else {
+                return Ok(Vec::new());
+            };
+            let title = index.get_file(&path)?.and_then(|file| file.metadata.title);
+            Ok(title.map(insert).unwrap_or_default())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note_with, vault};
+
+    #[test]
+    fn test_on_type_formatting() {
+        let (_temp_dir, mut index) = vault(&[]);
+        add_note_with(&mut index, "q3.typ", "", |parsed| {
+            parsed.metadata.title = Some("Quarterly Planning".to_string());
+        });
+        let syntax = WikilinkSyntax::default();
+        let edits = |text: &str, character, ch| {
+            on_type_formatting(&index, &syntax, text, Position::new(0, character), ch)
+                .unwrap()
+                .into_iter()
+                .map(|edit| edit.new_text)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(edits("See [[", 6, "["), vec!["]]"]);
+        assert!(edits("See [[]]", 6, "[").is_empty());
+        assert!(edits("See [", 5, "[").is_empty());
+        assert_eq!(edits("[[q3:sec|]]", 9, "|"), vec!["Quarterly Planning"]);
+        assert!(edits("[[q3|x]]", 5, "|").is_empty());
+        assert!(edits("[[gone|]]", 7, "|").is_empty());
+        assert!(edits("[[|]]", 2, "|").is_empty());
+        assert!(edits("[[é]]", 3, "|").is_empty());
+    }
+}
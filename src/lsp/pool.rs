@@ -0,0 +1,126 @@
+use crate::lsp::dispatch::panic_message;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::Scope;
+
+/// Which queue a task waits in. Workers always take the most urgent task first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Requests the user is waiting on while typing, like completion and hover
+    Interactive,
+    Normal,
+    /// Work nobody is waiting on, like reindexing saved notes
+    Background,
+}
+
+type Task<'scope> = Box<dyn FnOnce() + Send + 'scope>;
+
+#[derive(Default)]
+struct Queues<'scope> {
+    // Indexed by `Priority`
+    tasks: [VecDeque<Task<'scope>>; 3],
+    closed: bool,
+}
+
+struct Shared<'scope> {
+    queues: Mutex<Queues<'scope>>,
+    ready: Condvar,
+}
+
+/// A fixed set of worker threads running prioritized tasks within a thread scope, so tasks
+/// can borrow from outside it. Dropping the pool lets workers finish the queued tasks and exit.
+pub struct TaskPool<'scope> {
+    shared: Arc<Shared<'scope>>,
+}
+
+impl<'scope> TaskPool<'scope> {
+    pub fn new<'env>(scope: &'scope Scope<'scope, 'env>, workers: usize) -> Self {
+        let shared = Arc::new(Shared {
+            queues: Mutex::new(Queues::default()),
+            ready: Condvar::new(),
+        });
+        for _ in 0..workers.max(1) {
+            let shared = Arc::clone(&shared);
+            scope.spawn(move || {
+                while let Some(task) = shared.next() {
+                    // A panicking task must not take the worker down with it
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(task)) {
+                        tracing::error!("Task panicked: {}", panic_message(payload.as_ref()));
+                    }
+                }
+            });
+        }
+        Self { shared }
+    }
+
+    pub fn spawn(&self, priority: Priority, task: impl FnOnce() + Send + 'scope) {
+        self.shared.lock().tasks[priority as usize].push_back(Box::new(task));
+        self.shared.ready.notify_one();
+    }
+}
+
+impl Drop for TaskPool<'_> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.ready.notify_all();
+    }
+}
+
+impl<'scope> Shared<'scope> {
+    fn lock(&self) -> MutexGuard<'_, Queues<'scope>> {
+        self.queues
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // The most urgent queued task, waiting for one unless the pool is closed
+    fn next(&self) -> Option<Task<'scope>> {
+        let mut queues = self.lock();
+        loop {
+            if let Some(task) = queues.tasks.iter_mut().find_map(VecDeque::pop_front) {
+                return Some(task);
+            }
+            if queues.closed {
+                return None;
+            }
+            queues = self
+                .ready
+                .wait(queues)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_priorities() {
+        let order = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            let pool = TaskPool::new(scope, 1);
+            // Hold the only worker until every task is queued
+            let (release, blocked) = mpsc::channel::<()>();
+            pool.spawn(Priority::Normal, move || blocked.recv().unwrap());
+            let order = &order;
+            // The worker survives a panicking task
+            pool.spawn(Priority::Interactive, || panic!("handler bug"));
+            for (priority, name) in [
+                (Priority::Background, "reindex"),
+                (Priority::Normal, "query"),
+                (Priority::Interactive, "hover"),
+                (Priority::Interactive, "completion"),
+            ] {
+                pool.spawn(priority, move || order.lock().unwrap().push(name));
+            }
+            release.send(()).unwrap();
+        });
+        assert_eq!(
+            order.into_inner().unwrap(),
+            vec!["hover", "completion", "query", "reindex"]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::index::content_hash;
+use crate::lsp::{path_to_uri, uri_to_path};
+use anyhow::Result;
+use lsp_types::{
+    Diagnostic, DocumentDiagnosticReport, DocumentDiagnosticReportResult,
+    FullDocumentDiagnosticReport, PreviousResultId, RelatedFullDocumentDiagnosticReport,
+    RelatedUnchangedDocumentDiagnosticReport, UnchangedDocumentDiagnosticReport,
+    WorkspaceDiagnosticReport, WorkspaceDocumentDiagnosticReport,
+    WorkspaceFullDocumentDiagnosticReport, WorkspaceUnchangedDocumentDiagnosticReport,
+};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The result ID of a note's diagnostics: a hash of them, so the same problems always get the
+/// same ID and nothing has to be remembered between pulls.
+pub fn result_id(diagnostics: &[Diagnostic]) -> String {
+    let json = serde_json::to_vec(diagnostics).unwrap_or_default();
+    content_hash(&json)
+}
+
+/// The `textDocument/diagnostic` report of one note: `unchanged` if the client's
+/// `previous_result_id` still matches.
+pub fn document_report(
+    diagnostics: Vec<Diagnostic>,
+    previous_result_id: Option<&str>,
+) -> DocumentDiagnosticReportResult {
+    let id = result_id(&diagnostics);
+    let report = if previous_result_id == Some(id.as_str()) {
+        DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                result_id: id,
+            },
+        })
+    } else {
+        DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+            related_documents: None,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                result_id: Some(id),
+                items: diagnostics,
+            },
+        })
+    };
+    DocumentDiagnosticReportResult::Report(report)
+}
+
+/// The `workspace/diagnostic` report for the vault's diagnostics by note: every note with
+/// problems, `unchanged` where the client's previous result ID still matches, and an empty
+/// report for notes the client knew about whose problems are gone.
+pub fn workspace_report(
+    mut diagnostics: BTreeMap<PathBuf, Vec<Diagnostic>>,
+    previous_result_ids: &[PreviousResultId],
+) -> Result<WorkspaceDiagnosticReport> {
+    let previous: BTreeMap<PathBuf, &str> = previous_result_ids
+        .iter()
+        .filter_map(|previous| Some((uri_to_path(&previous.uri)?, previous.value.as_str())))
+        .collect();
+    for path in previous.keys() {
+        diagnostics.entry(path.clone()).or_default();
+    }
+
+    let mut items = Vec::new();
+    for (path, diagnostics) in diagnostics {
+        let uri = path_to_uri(&path)?;
+        let id = result_id(&diagnostics);
+        items.push(if previous.get(&path) == Some(&id.as_str()) {
+            WorkspaceDocumentDiagnosticReport::Unchanged(
+                WorkspaceUnchangedDocumentDiagnosticReport {
+                    uri,
+                    version: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id: id,
+                    },
+                },
+            )
+        } else {
+            WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                uri,
+                version: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(id),
+                    items: diagnostics,
+                },
+            })
+        });
+    }
+    Ok(WorkspaceDiagnosticReport { items })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+
+    #[test]
+    fn test_workspace_report() {
+        let diagnostic = Diagnostic::new_simple(
+            Range::new(Position::new(0, 0), Position::new(0, 4)),
+            "No note or file matches `x`".to_string(),
+        );
+        let (a, b, c) = (
+            PathBuf::from("/vault/a.typ"),
+            PathBuf::from("/vault/b.typ"),
+            PathBuf::from("/vault/c.typ"),
+        );
+        let diagnostics = BTreeMap::from([
+            (a.clone(), vec![diagnostic.clone()]),
+            (b.clone(), vec![diagnostic.clone()]),
+        ]);
+        let id = result_id(&[diagnostic]);
+        let previous = vec![
+            PreviousResultId {
+                uri: path_to_uri(&a).unwrap(),
+                value: id.clone(),
+            },
+            PreviousResultId {
+                uri: path_to_uri(&c).unwrap(),
+                value: id.clone(),
+            },
+        ];
+
+        let report = workspace_report(diagnostics, &previous).unwrap();
+        let summary: Vec<(String, Option<usize>)> = report
+            .items
+            .iter()
+            .map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => (
+                    full.uri.as_str().to_string(),
+                    Some(full.full_document_diagnostic_report.items.len()),
+                ),
+                WorkspaceDocumentDiagnosticReport::Unchanged(unchanged) => {
+                    (unchanged.uri.as_str().to_string(), None)
+                }
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("file:///vault/a.typ".to_string(), None),
+                ("file:///vault/b.typ".to_string(), Some(1)),
+                ("file:///vault/c.typ".to_string(), Some(0)),
+            ]
+        );
+
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_)) =
+            document_report(Vec::new(), Some(&result_id(&[])))
+        else {
+            panic!("expected an unchanged report");
+        };
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// A steady stream of changes still flushes after this many debounce periods
+const MAX_DELAY_PERIODS: u32 = 10;
+
+#[derive(Default)]
+struct State {
+    pending: BTreeSet<PathBuf>,
+    // When the oldest and the newest pending change arrived
+    first: Option<Instant>,
+    last: Option<Instant>,
+    // Notes taken by the batch being indexed
+    in_flight: usize,
+    debounce: Duration,
+    closed: bool,
+}
+
+/// Notes waiting to be reindexed. Changes to the same note coalesce, and notes are handed out
+/// in one batch once no change has arrived for the debounce period, so a burst of changes
+/// such as a `git checkout` is indexed in one go rather than note by note.
+///
+/// A batch and a [`ReindexQueue::hold`] exclude each other, so whoever renames or rewrites
+/// notes sees the batches taken before it fully indexed and the later ones only after it.
+pub struct ReindexQueue {
+    state: Mutex<State>,
+    changed: Condvar,
+    ordering: Mutex<()>,
+}
+
+/// Notes taken from the queue. Until it's dropped, [`ReindexQueue::hold`] waits.
+pub struct Batch<'a> {
+    pub paths: Vec<PathBuf>,
+    _ordering: MutexGuard<'a, ()>,
+}
+
+impl ReindexQueue {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            state: Mutex::new(State {
+                debounce,
+                ..State::default()
+            }),
+            changed: Condvar::new(),
+            ordering: Mutex::default(),
+        }
+    }
+
+    pub fn set_debounce(&self, debounce: Duration) {
+        self.lock().debounce = debounce;
+        self.changed.notify_all();
+    }
+
+    /// Queue `path` for reindexing, postponing the next batch by the debounce period.
+    pub fn push(&self, path: PathBuf) {
+        let mut state = self.lock();
+        let now = Instant::now();
+        state.pending.insert(path);
+        state.first.get_or_insert(now);
+        state.last = Some(now);
+        self.changed.notify_all();
+    }
+
+    /// Notes queued or being indexed.
+    pub fn depth(&self) -> usize {
+        let state = self.lock();
+        state.pending.len() + state.in_flight
+    }
+
+    /// Wait for the batch being indexed, and keep the next one from starting until the guard
+    /// is dropped.
+    pub fn hold(&self) -> MutexGuard<'_, ()> {
+        lock(&self.ordering)
+    }
+
+    /// Wait for the next batch, ordered by path. Once closed, returns what is still queued
+    /// without waiting, then `None`.
+    pub fn next_batch(&self) -> Option<Batch<'_>> {
+        let mut state = self.lock();
+        state.in_flight = 0;
+        loop {
+            if state.closed && state.pending.is_empty() {
+                return None;
+            }
+            let due = match (state.first, state.last) {
+                (Some(first), Some(last)) => {
+                    (last + state.debounce).min(first + state.debounce * MAX_DELAY_PERIODS)
+                }
+                _ => {
+                    state = self.wait(state, None);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            if state.closed || now >= due {
+                // Whoever holds the queue is waited for with the state unlocked, so it can
+                // still push changes meanwhile
+                drop(state);
+                let ordering = self.hold();
+                state = self.lock();
+                let paths: Vec<PathBuf> = std::mem::take(&mut state.pending).into_iter().collect();
+                state.first = None;
+                state.last = None;
+                state.in_flight = paths.len();
+                return Some(Batch {
+                    paths,
+                    _ordering: ordering,
+                });
+            }
+            state = self.wait(state, Some(due - now));
+        }
+    }
+
+    /// Stop waiting for changes, so `next_batch` drains the queue and then returns `None`.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    fn wait<'a>(
+        &self,
+        state: MutexGuard<'a, State>,
+        timeout: Option<Duration>,
+    ) -> MutexGuard<'a, State> {
+        match timeout {
+            Some(timeout) => {
+                self.changed
+                    .wait_timeout(state, timeout)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0
+            }
+            None => self
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalesces_changes() {
+        let queue = ReindexQueue::new(Duration::from_millis(50));
+        let started = Instant::now();
+        for _ in 0..3 {
+            queue.push(PathBuf::from("b.typ"));
+            queue.push(PathBuf::from("a.typ"));
+        }
+        assert_eq!(queue.depth(), 2);
+
+        let batch = queue.next_batch().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(
+            batch.paths,
+            vec![PathBuf::from("a.typ"), PathBuf::from("b.typ")]
+        );
+        // Still being indexed
+        assert_eq!(queue.depth(), 2);
+        drop(batch);
+
+        queue.push(PathBuf::from("c.typ"));
+        queue.close();
+        assert_eq!(
+            queue.next_batch().unwrap().paths,
+            vec![PathBuf::from("c.typ")]
+        );
+        assert!(queue.next_batch().is_none());
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[test]
+    fn test_waits_for_changes() {
+        let queue = ReindexQueue::new(Duration::ZERO);
+        std::thread::scope(|scope| {
+            let batches = scope.spawn(|| std::iter::from_fn(|| queue.next_batch()).count());
+            std::thread::sleep(Duration::from_millis(20));
+            queue.push(PathBuf::from("a.typ"));
+            std::thread::sleep(Duration::from_millis(20));
+            queue.close();
+            assert_eq!(batches.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_hold_delays_batches() {
+        let queue = ReindexQueue::new(Duration::ZERO);
+        queue.push(PathBuf::from("a.typ"));
+        std::thread::scope(|scope| {
+            let held = queue.hold();
+            let batch = scope.spawn(|| queue.next_batch().map(|batch| batch.paths));
+            std::thread::sleep(Duration::from_millis(20));
+            // Changes made while the queue is held join the waiting batch
+            queue.push(PathBuf::from("b.typ"));
+            assert!(!batch.is_finished());
+            drop(held);
+            assert_eq!(
+                batch.join().unwrap().unwrap(),
+                vec![PathBuf::from("a.typ"), PathBuf::from("b.typ")]
+            );
+        });
+    }
+}
@@ -0,0 +1,357 @@
+use crate::index::{Index, normalize};
+use crate::lsp::{line_at, uri_to_path};
+use crate::parser::Parser;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::refactor::move_file::{include_regex, move_edits};
+use crate::refactor::{self, FileEdits};
+use anyhow::{Result, bail};
+use lsp_types::{Position, PrepareRenameResponse, Range, TextEdit, Uri, WorkspaceEdit};
+use std::ops::Range as Span;
+use std::path::{Path, PathBuf};
+
+/// What a rename at the cursor changes.
+#[derive(Debug, PartialEq)]
+enum Renameable {
+    /// The note's file name
+    Note(PathBuf),
+    /// A label defined in `note`
+    Label { note: PathBuf, name: String },
+}
+
+/// The range a rename at `position` replaces: the stem in a wikilink target or an
+/// `#include`/`#import` path, or a label name in a wikilink, `<label>` or `@label`. `None`
+/// where nothing can be renamed.
+pub fn prepare_rename(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Option<PrepareRenameResponse>> {
+    let renameable = renameable_at(index, parser, syntax, uri, text, position)?;
+    Ok(renameable.map(|(range, _)| PrepareRenameResponse::Range(range)))
+}
+
+/// Rename what is under the cursor to `new_name`: a note keeps its folder and every link and
+/// include to it is updated; a label is renamed at its definitions, its `@label` references
+/// and every `[[note:label]]` link. `read` returns the current text of a note, including the
+/// one at `uri`.
+pub fn rename(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    uri: &Uri,
+    position: Position,
+    new_name: &str,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<Option<WorkspaceEdit>> {
+    let Some(text) = uri_to_path(uri).and_then(|path| read(&path)) else {
+        return Ok(None);
+    };
+    let Some((_, renameable)) = renameable_at(index, parser, syntax, uri, &text, position)? else {
+        return Ok(None);
+    };
+
+    match renameable {
+        Renameable::Note(path) => {
+            if new_name.is_empty() || new_name.contains(['/', '\\']) {
+                bail!("`{new_name}` is not a valid note name");
+            }
+            let to = path.with_file_name(format!("{new_name}.typ"));
+            if to.exists() {
+                bail!("{} already exists", to.display());
+            }
+            let moves = [(path, to)];
+            let edits = move_edits(index, parser, syntax, &moves, read)?;
+            Ok(Some(refactor::workspace_edit(edits, &moves)?))
+        }
+        Renameable::Label { note, name } => {
+            let valid = !new_name.is_empty()
+                && new_name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | ':' | '.' | '-'));
+            if !valid {
+                bail!("`{new_name}` is not a valid label name");
+            }
+            let edits = label_edits(index, parser, syntax, &note, &name, new_name, read)?;
+            Ok(Some(refactor::workspace_edit(edits, &[])?))
+        }
+    }
+}
+
+fn label_edits(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    note: &Path,
+    name: &str,
+    new_name: &str,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<FileEdits> {
+    let mut edits = FileEdits::new();
+    let mut push = |path: &Path, line: usize, span: Span<usize>, new_text: String| {
+        let range = Range::new(
+            Position::new(line as u32, span.start as u32),
+            Position::new(line as u32, span.end as u32),
+        );
+        edits
+            .entry(path.to_path_buf())
+            .or_default()
+            .push(TextEdit::new(range, new_text));
+    };
+    let length = name.chars().count();
+
+    if let Some(content) = read(note) {
+        // Definitions are positioned at `<`, references at `@`
+        let definitions = parser.parse_content(&content, note)?.labels;
+        for label in definitions
+            .into_iter()
+            .chain(parser.label_references(&content))
+            .filter(|label| label.name == name)
+        {
+            push(
+                note,
+                label.line - 1,
+                label.column..label.column + length,
+                new_name.to_string(),
+            );
+        }
+    }
+
+    let mut backlinks = index.get_backlinks_to_label(note, name)?;
+    backlinks.sort_by(|a, b| a.0.cmp(&b.0));
+    for links in backlinks.chunk_by(|a, b| a.0 == b.0) {
+        let source = &links[0].0;
+        let Some(content) = read(source) else {
+            continue;
+        };
+        for (mut wikilink, span) in parser.wikilink_spans(&content) {
+            if !links
+                .iter()
+                .any(|(_, link)| (link.line, link.column) == (wikilink.line, wikilink.column))
+            {
+                continue;
+            }
+            wikilink.label = Some(new_name.to_string());
+            push(source, wikilink.line - 1, span, syntax.format(&wikilink));
+        }
+    }
+
+    for file_edits in edits.values_mut() {
+        file_edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    }
+    Ok(edits)
+}
+
+fn renameable_at(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    uri: &Uri,
+    text: &str,
+    position: Position,
+) -> Result<Option<(Range, Renameable)>> {
+    let (Some(line), Some(note)) = (line_at(text, position.line), uri_to_path(uri)) else {
+        return Ok(None);
+    };
+    let character = position.character as usize;
+    let chars: Vec<char> = line.chars().collect();
+    let on_line = |span: Span<usize>| {
+        Range::new(
+            Position::new(position.line, span.start as u32),
+            Position::new(position.line, span.end as u32),
+        )
+    };
+    let contains = |span: &Span<usize>| span.start <= character && character <= span.end;
+
+    if let Some((wikilink, span)) = parser.wikilink_at(line, position.line as usize + 1, character)
+    {
+        let Some(target_path) = index.resolve_target(&wikilink.target)? else {
+            return Ok(None);
+        };
+        let (target, label) = link_parts(&chars, span.start + 2..span.end - 2, syntax);
+        if contains(&target) && target_path.extension().is_some_and(|ext| ext == "typ") {
+            return Ok(Some((
+                on_line(stem(&chars, target)),
+                Renameable::Note(target_path),
+            )));
+        }
+        if let (Some(label), Some(name)) = (label, wikilink.label)
+            && contains(&label)
+        {
+            let renameable = Renameable::Label {
+                note: target_path,
+                name,
+            };
+            return Ok(Some((on_line(label), renameable)));
+        }
+        return Ok(None);
+    }
+
+    if let Some((name, span)) = parser.label_at(line, character) {
+        let name_span = match chars[span.start] {
+            '<' => span.start + 1..span.end - 1,
+            _ => span.start + 1..span.end,
+        };
+        return Ok(Some((on_line(name_span), Renameable::Label { note, name })));
+    }
+
+    for cap in include_regex()?.captures_iter(line) {
+        let literal = cap.get(1).unwrap();
+        let start = line[..literal.start()].chars().count();
+        let span = start..start + literal.as_str().chars().count();
+        if !contains(&span) {
+            continue;
+        }
+        let dir = note
+            .strip_prefix(index.root())?
+            .parent()
+            .unwrap_or(Path::new(""));
+        let included = match literal.as_str().strip_prefix('/') {
+            Some(rooted) => normalize(Path::new(rooted)),
+            None => normalize(&dir.join(literal.as_str())),
+        };
+        let Some(included) = included.map(|path| index.root().join(path)) else {
+            return Ok(None);
+        };
+        if index.get_file(&included)?.is_none() {
+            return Ok(None);
+        }
+        return Ok(Some((
+            on_line(stem(&chars, span)),
+            Renameable::Note(included),
+        )));
+    }
+    Ok(None)
+}
+
+// The character ranges of the target and label within the link text `inner`
+fn link_parts(
+    chars: &[char],
+    inner: Span<usize>,
+    syntax: &WikilinkSyntax,
+) -> (Span<usize>, Option<Span<usize>>) {
+    let unescaped = |i: usize, c: char| chars[i] == c && (i == 0 || chars[i - 1] != '\\');
+    let bar = inner.clone().find(|&i| unescaped(i, '|'));
+    let link = match (bar, syntax.alias_first) {
+        (Some(bar), true) => bar + 1..inner.end,
+        (Some(bar), false) => inner.start..bar,
+        (None, _) => inner,
+    };
+    let Some(split) = link
+        .clone()
+        .find(|&i| unescaped(i, '#') || unescaped(i, syntax.label_separator))
+    else {
+        return (link, None);
+    };
+    let label = (chars[split] == syntax.label_separator).then_some(split + 1..link.end);
+    (link.start..split, label)
+}
+
+// The file stem within the path at `span`, without folders or a `.typ` extension
+fn stem(chars: &[char], span: Span<usize>) -> Span<usize> {
+    let start = span
+        .clone()
+        .rev()
+        .find(|&i| chars[i] == '/')
+        .map_or(span.start, |i| i + 1);
+    let path: String = chars[start..span.end].iter().collect();
+    let end = match path.strip_suffix(".typ") {
+        Some(_) => span.end - 4,
+        None => span.end,
+    };
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+    use crate::lsp::path_to_uri;
+    use lsp_types::{DocumentChangeOperation, DocumentChanges, OneOf, ResourceOp};
+
+    #[test]
+    fn test_prepare_rename_and_rename() {
+        let text =
+            "See [[notes/idea.typ:sec|Idea]] and @local.\n#include \"notes/idea.typ\"\n<local>";
+        let (temp_dir, index) = vault(&[
+            ("note.typ", text),
+            ("notes/idea.typ", "= Idea <sec>\nAs in @sec."),
+            ("other.typ", "[[idea:sec]]"),
+        ]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let syntax = WikilinkSyntax::default();
+        let uri = path_to_uri(&root.join("note.typ")).unwrap();
+        let prepare = |line, character| -> Option<((u32, u32), (u32, u32))> {
+            let response = prepare_rename(
+                &index,
+                &parser,
+                &syntax,
+                &uri,
+                text,
+                Position::new(line, character),
+            )
+            .unwrap()?;
+            let PrepareRenameResponse::Range(range) = response else {
+                panic!("expected a range");
+            };
+            Some((
+                (range.start.line, range.start.character),
+                (range.end.line, range.end.character),
+            ))
+        };
+
+        assert_eq!(prepare(0, 7), Some(((0, 12), (0, 16))));
+        assert_eq!(prepare(0, 22), Some(((0, 21), (0, 24))));
+        assert_eq!(prepare(0, 27), None);
+        assert_eq!(prepare(0, 38), Some(((0, 37), (0, 42))));
+        assert_eq!(prepare(1, 16), Some(((1, 16), (1, 20))));
+        assert_eq!(prepare(2, 3), Some(((2, 1), (2, 6))));
+        assert_eq!(prepare(0, 1), None);
+
+        let renamed = |line, character, new_name| {
+            rename(
+                &index,
+                &parser,
+                &syntax,
+                &uri,
+                Position::new(line, character),
+                new_name,
+                |path| std::fs::read_to_string(path).ok(),
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        // The label in the link renames its definition, references and links
+        let edit = renamed(0, 22, "section");
+        let texts = |name: &str| -> Vec<String> {
+            edit.changes.as_ref().unwrap()[&path_to_uri(&root.join(name)).unwrap()]
+                .iter()
+                .map(|edit| edit.new_text.clone())
+                .collect()
+        };
+        assert_eq!(texts("notes/idea.typ"), vec!["section", "section"]);
+        assert_eq!(texts("note.typ"), vec!["[[notes/idea.typ:section|Idea]]"]);
+        assert_eq!(texts("other.typ"), vec!["[[idea:section]]"]);
+
+        // The target stem renames the note in its folder
+        let edit = renamed(0, 13, "thought");
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document operations");
+        };
+        let Some(DocumentChangeOperation::Op(ResourceOp::Rename(op))) = operations.last() else {
+            panic!("expected a rename");
+        };
+        assert!(op.new_uri.as_str().ends_with("notes/thought.typ"));
+        let DocumentChangeOperation::Edit(edit) = &operations[0] else {
+            panic!("expected a text edit");
+        };
+        let OneOf::Left(first) = &edit.edits[0] else {
+            panic!("expected a plain text edit");
+        };
+        assert_eq!(first.new_text, "[[notes/thought.typ:sec|Idea]]");
+    }
+}
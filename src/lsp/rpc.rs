@@ -0,0 +1,99 @@
+use crate::lsp::dispatch::Dispatcher;
+use anyhow::Result;
+use lsp_server::{ErrorCode, Request, RequestId};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+use std::sync::Mutex;
+
+/// One line of `--rpc` input. Scripts may leave out `id`, in which case the line number is used.
+#[derive(Debug, Deserialize)]
+struct Command {
+    id: Option<RequestId>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Answer newline-delimited JSON commands (`{"id", "method", "params"}`) from `input` with one
+/// `{"id", "result"}` or `{"id", "error"}` line each on `output`, in order, without the LSP
+/// headers or handshake. `output` is shared so notifications can be written between responses.
+pub fn serve<S, W: Write>(
+    dispatcher: &Dispatcher<S>,
+    state: &mut S,
+    input: impl BufRead,
+    output: &Mutex<W>,
+) -> Result<()> {
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => {
+                let request = Request {
+                    id: command
+                        .id
+                        .unwrap_or_else(|| RequestId::from(number as i32 + 1)),
+                    method: command.method,
+                    params: command.params,
+                };
+                serde_json::to_value(dispatcher.dispatch(state, &request))?
+            }
+            Err(e) => json!({
+                "id": null,
+                "error": { "code": ErrorCode::ParseError as i32, "message": e.to_string() },
+            }),
+        };
+        write_line(output, &reply)?;
+    }
+    Ok(())
+}
+
+/// Write `value` to `output` as one line of JSON.
+pub fn write_line<W: Write>(output: &Mutex<W>, value: &impl serde::Serialize) -> Result<()> {
+    let mut output = output
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    serde_json::to_writer(&mut *output, value)?;
+    writeln!(output)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::request::Shutdown;
+
+    #[test]
+    fn test_serve() {
+        let dispatcher = Dispatcher::<u32>::new().write::<Shutdown>(|count, ()| {
+            *count += 1;
+            Ok(())
+        });
+        let input = r#"{"id": "a", "method": "shutdown"}
+
+{"method": "shutdown", "params": {}}
+{"id": 7, "method": "typst-oxide/unknown"}
+not json
+"#;
+        let output = Mutex::new(Vec::new());
+        let mut count = 0;
+        serve(&dispatcher, &mut count, input.as_bytes(), &output).unwrap();
+        assert_eq!(count, 2);
+
+        let lines: Vec<Value> = String::from_utf8(output.into_inner().unwrap())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "id": "a", "result": null }));
+        assert_eq!(lines[1], json!({ "id": 3, "result": null }));
+        assert_eq!(lines[2]["id"], 7);
+        assert_eq!(lines[2]["error"]["code"], ErrorCode::MethodNotFound as i32);
+        assert_eq!(lines[3]["id"], Value::Null);
+        assert_eq!(lines[3]["error"]["code"], ErrorCode::ParseError as i32);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::parser::Parser;
+use crate::parser::headings::heading_level;
+use crate::parser::wikilinks::WikilinkSyntax;
+use lsp_types::{Position, Range, SelectionRange};
+use std::ops::Range as Span;
+
+/// Expanding selections for each of `positions`: the part of a wikilink (target, label or
+/// alias) or label name under the cursor, the whole link or label, the line, each enclosing
+/// section, and the document. Elsewhere the word under the cursor starts the chain.
+pub fn selection_ranges(
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    text: &str,
+    positions: &[Position],
+) -> Vec<SelectionRange> {
+    let lines: Vec<&str> = text.lines().collect();
+    positions
+        .iter()
+        .map(|&position| selection_range(parser, syntax, &lines, position))
+        .collect()
+}
+
+fn selection_range(
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    lines: &[&str],
+    position: Position,
+) -> SelectionRange {
+    let line_idx = position.line as usize;
+    let on_line = |span: Span<usize>| {
+        Range::new(
+            Position::new(position.line, span.start as u32),
+            Position::new(position.line, span.end as u32),
+        )
+    };
+    let line_end = |idx: usize| Position::new(idx as u32, lines[idx].chars().count() as u32);
+
+    let mut ranges = vec![Range::new(position, position)];
+    if let Some(line) = lines.get(line_idx) {
+        let character = position.character as usize;
+        let chars: Vec<char> = line.chars().collect();
+        if let Some((_, span)) = parser.wikilink_at(line, line_idx + 1, character) {
+            let inner = span.start + 2..span.end - 2;
+            ranges.extend(link_part(&chars, inner.clone(), syntax, character).map(on_line));
+            ranges.push(on_line(inner));
+            ranges.push(on_line(span));
+        } else if let Some((_, span)) = parser.label_at(line, character) {
+            let name = match chars[span.start] {
+                '<' => span.start + 1..span.end - 1,
+                _ => span.start + 1..span.end,
+            };
+            ranges.push(on_line(name));
+            ranges.push(on_line(span));
+        } else if let Some(word) = word_at(&chars, character) {
+            ranges.push(on_line(word));
+        }
+
+        let start = chars.iter().take_while(|c| c.is_whitespace()).count();
+        let end = chars.len() - chars.iter().rev().take_while(|c| c.is_whitespace()).count();
+        if start < end {
+            ranges.push(on_line(start..end));
+        }
+
+        // Enclosing sections, innermost first
+        let headings: Vec<(usize, usize)> = lines
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, line)| Some((idx, heading_level(line)?)))
+            .collect();
+        for (i, &(start, level)) in headings.iter().enumerate().rev() {
+            if start > line_idx {
+                continue;
+            }
+            let end = headings[i + 1..]
+                .iter()
+                .find(|(_, other)| *other <= level)
+                .map_or(lines.len(), |(idx, _)| *idx);
+            if line_idx < end {
+                ranges.push(Range::new(
+                    Position::new(start as u32, 0),
+                    line_end(end - 1),
+                ));
+            }
+        }
+    }
+    if !lines.is_empty() {
+        ranges.push(Range::new(Position::new(0, 0), line_end(lines.len() - 1)));
+    }
+
+    // Each range must contain the one before it
+    let mut chain: Vec<Range> = Vec::new();
+    for range in ranges {
+        let contains = chain
+            .last()
+            .is_none_or(|last| range.start <= last.start && last.end <= range.end);
+        if contains && chain.last() != Some(&range) {
+            chain.push(range);
+        }
+    }
+    let mut selection = None;
+    for range in chain.into_iter().rev() {
+        selection = Some(SelectionRange {
+            range,
+            parent: selection.map(Box::new),
+        });
+    }
+    selection.unwrap_or(SelectionRange {
+        range: Range::new(position, position),
+        parent: None,
+    })
+}
+
+// The target, label, block or alias of the link inside `inner` that holds `character`
+fn link_part(
+    chars: &[char],
+    inner: Span<usize>,
+    syntax: &WikilinkSyntax,
+    character: usize,
+) -> Option<Span<usize>> {
+    let mut start = inner.start;
+    for i in inner.clone() {
+        let escaped = i > 0 && chars[i - 1] == '\\';
+        if !escaped && (chars[i] == '|' || chars[i] == '#' || chars[i] == syntax.label_separator) {
+            if (start..=i).contains(&character) {
+                return (start < i).then_some(start..i);
+            }
+            start = i + 1;
+        }
+    }
+    ((start..=inner.end).contains(&character) && start < inner.end).then_some(start..inner.end)
+}
+
+fn word_at(chars: &[char], character: usize) -> Option<Span<usize>> {
+    let is_word = |c: &char| c.is_alphanumeric() || matches!(c, '_' | '-');
+    let start = character
+        - chars[..character.min(chars.len())]
+            .iter()
+            .rev()
+            .take_while(|c| is_word(c))
+            .count();
+    let end = character
+        + chars
+            .get(character..)
+            .unwrap_or_default()
+            .iter()
+            .take_while(|c| is_word(c))
+            .count();
+    (start < end).then_some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(selection: &SelectionRange) -> Vec<((u32, u32), (u32, u32))> {
+        let mut ranges = Vec::new();
+        let mut current = Some(selection);
+        while let Some(selection) = current {
+            let Range { start, end } = selection.range;
+            ranges.push(((start.line, start.character), (end.line, end.character)));
+            current = selection.parent.as_deref();
+        }
+        ranges
+    }
+
+    #[test]
+    fn test_selection_ranges() {
+        let parser = Parser::new().unwrap();
+        let text = "= Top\n== Sub\n  See [[notes/idea:sec|The idea]] now.\n== Next <next>";
+        let selections = selection_ranges(
+            &parser,
+            &WikilinkSyntax::default(),
+            text,
+            &[
+                Position::new(2, 10),
+                Position::new(3, 10),
+                Position::new(2, 37),
+            ],
+        );
+
+        assert_eq!(
+            chain(&selections[0]),
+            vec![
+                ((2, 10), (2, 10)),
+                ((2, 8), (2, 18)),
+                ((2, 8), (2, 31)),
+                ((2, 6), (2, 33)),
+                ((2, 2), (2, 38)),
+                ((1, 0), (2, 38)),
+                ((0, 0), (3, 14)),
+            ]
+        );
+        assert_eq!(
+            chain(&selections[1]),
+            vec![
+                ((3, 10), (3, 10)),
+                ((3, 9), (3, 13)),
+                ((3, 8), (3, 14)),
+                ((3, 0), (3, 14)),
+                ((0, 0), (3, 14)),
+            ]
+        );
+        // A plain word
+        assert_eq!(chain(&selections[2])[1], ((2, 34), (2, 37)));
+    }
+}
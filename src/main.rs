@@ -1,40 +1,1224 @@
 use lsp_server::Request as ServerRequest;
-use lsp_server::{Connection, ErrorCode, Message, RequestId, Response};
+use lsp_server::{Connection, ErrorCode, Message, Notification, RequestId, Response};
 
-pub mod command;
-pub mod index;
-pub mod parser;
+use anyhow::{Context as _, Result};
+use lsp_types::notification::{
+    Cancel, DidChangeConfiguration, DidChangeTextDocument, DidChangeWatchedFiles,
+    DidCloseTextDocument, DidOpenTextDocument, DidRenameFiles, DidSaveTextDocument,
+    Notification as _, Progress, PublishDiagnostics, SetTrace,
+};
+use lsp_types::request::{
+    CallHierarchyIncomingCalls, CallHierarchyOutgoingCalls, CallHierarchyPrepare,
+    CodeActionRequest, CodeActionResolveRequest, Completion, DocumentDiagnosticRequest,
+    GotoDefinition, HoverRequest, InlayHintRequest, OnTypeFormatting, PrepareRenameRequest,
+    References, RegisterCapability, Rename, Request, SelectionRangeRequest, Shutdown,
+    WillRenameFiles, WorkDoneProgressCreate, WorkspaceDiagnosticRefresh,
+    WorkspaceDiagnosticRequest,
+};
+use lsp_types::{
+    CallHierarchyItem, CallHierarchyPrepareParams, CallHierarchyServerCapability, CancelParams,
+    CodeAction, CodeActionOptions, CodeActionParams, CodeActionProviderCapability,
+    CodeActionResponse, CompletionOptions, CompletionParams, CompletionResponse, DiagnosticOptions,
+    DiagnosticServerCapabilities, DidChangeConfigurationParams, DidChangeTextDocumentParams,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    DocumentDiagnosticParams, DocumentDiagnosticReportResult, DocumentOnTypeFormattingOptions,
+    DocumentOnTypeFormattingParams, FileOperationFilter, FileOperationPattern,
+    FileOperationPatternKind, FileOperationRegistrationOptions, FileRename, FileSystemWatcher,
+    GlobPattern, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams,
+    HoverProviderCapability, InitializeParams, InlayHint, InlayHintParams, Location,
+    NumberOrString, OneOf, PrepareRenameResponse, ProgressParams, ProgressParamsValue,
+    PublishDiagnosticsParams, ReferenceParams, Registration, RegistrationParams, RenameFilesParams,
+    RenameOptions, RenameParams, SelectionRange, SelectionRangeParams,
+    SelectionRangeProviderCapability, ServerCapabilities, SetTraceParams,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
+    TraceValue, Uri, WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressCreateParams,
+    WorkDoneProgressEnd, WorkDoneProgressReport, WorkspaceDiagnosticParams,
+    WorkspaceDiagnosticReportResult, WorkspaceEdit, WorkspaceFileOperationsServerCapabilities,
+    WorkspaceServerCapabilities,
+};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{
+    Arc, LazyLock, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, mpsc,
+};
+use std::time::Duration;
+use typst_oxide::command::{
+    self, ArchiveNote, ArchiveNoteRequest, BackwardLinks, Calendar, Clusters, DailyNote,
+    DailyNoteRequest, DailyNoteResponse, DeadLabels, EventNotification, Figures, FileInfo,
+    FindReplace, FixRedirects, FixRedirectsRequest, ForwardLinks, GenerateMoc, GenerateMocRequest,
+    History, LabelBacklinks, LocalGraph, MaterializeQueries, MaterializeQueriesRequest, MergeNotes,
+    MergeNotesRequest, MoveFile, MoveFileRequest, NewNote, NewNoteRequest, NewNoteResponse,
+    Outline, OutlineRequest, OutlineResponse, Packages, PathBetween, PeriodicNote, Query,
+    RecentNotes, Related, RewriteLinks, RewriteLinksRequest, Search, StarNote, StarredNotes, Stats,
+    Subscribe, SubscribeRequest, Tags, UnlinkedMentions, UnstarNote, UnusedAttachments,
+    UpdateMetadata, UpdateMetadataRequest, VaultGraph, VerifyIndex, VerifyIndexRequest,
+    VerifyIndexResponse,
+};
+use typst_oxide::config::{Config, NoteFilter};
+use typst_oxide::diagnostics::{self, Problem};
+use typst_oxide::events::EventBus;
+use typst_oxide::git;
+use typst_oxide::index::{CancellationToken, Cancelled, Index, IndexHandle};
+use typst_oxide::logging;
+use typst_oxide::lsp::code_action::ActionContext;
+use typst_oxide::lsp::completion::TargetContext;
+use typst_oxide::lsp::dispatch::{Access, Dispatcher};
+use typst_oxide::lsp::pool::{Priority, TaskPool};
+use typst_oxide::lsp::reindex::ReindexQueue;
+use typst_oxide::lsp::{self, pull_diagnostics, uri_to_path};
+use typst_oxide::mcp::McpServer;
+use typst_oxide::parser::Parser;
+use typst_oxide::paths::VaultPaths;
+use typst_oxide::refactor::{self, move_file};
+use typst_oxide::serve::{self, ApiServer};
+use typst_oxide::workspace;
 
-use anyhow::Result;
-use lsp_types::ServerCapabilities;
-use lsp_types::request::{GotoDefinition, Request};
-
-struct Context {}
+struct Context {
+    root: PathBuf,
+    config: Config,
+    note_filter: NoteFilter,
+    index: IndexHandle,
+    parser: Arc<Parser>,
+    runtime: tokio::runtime::Runtime,
+    documents: RwLock<HashMap<Uri, String>>,
+    events: Mutex<EventBus>,
+    subscription: Option<usize>,
+    /// Whether the client accepts server-initiated `$/progress`
+    work_done_progress: bool,
+    /// Compiler problems of each note as of its last save, with `compile_on_save`
+    compile_problems: Arc<Mutex<HashMap<PathBuf, Vec<Problem>>>>,
+    /// Whether the client pulls diagnostics, so none are published
+    pull_diagnostics: bool,
+    /// Whether the client re-pulls on `workspace/diagnostic/refresh`
+    diagnostic_refresh: bool,
+    /// Whether the client accepts snippets as completions
+    snippet_completion: bool,
+    /// Whether the client resolves code actions' edits with `codeAction/resolve`
+    code_action_resolve: bool,
+    /// Saved or externally changed notes waiting to be reindexed
+    reindex_queue: Arc<ReindexQueue>,
+}
 
 impl Context {
-    pub fn new() -> Self {
-        Context {}
+    pub fn new(
+        root: &Path,
+        config: Config,
+        on_progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let parser = Arc::new(Parser::with_syntax(&config.wikilink_syntax)?);
+        let note_filter = config.note_filter()?;
+        let reindex_queue = Arc::new(ReindexQueue::new(Duration::from_millis(
+            config.reindex_debounce_ms,
+        )));
+        let index =
+            IndexHandle::open_with_key(root, &config.index_path(root)?, config.index_key()?)?;
+
+        index.write(|index| {
+            index.set_case_insensitive_links(config.case_insensitive_links)?;
+            index.set_archive_folders(&config.archive_folders)?;
+            index.set_max_index_rows(config.max_index_rows)?;
+            runtime.block_on(workspace::index_workspace(
+                &parser,
+                index,
+                &note_filter,
+                on_progress,
+            ))?;
+            workspace::record_git_history(index, config.git_history)
+        })?;
+
+        Ok(Context {
+            root: root.to_path_buf(),
+            config,
+            note_filter,
+            index,
+            parser,
+            runtime,
+            documents: RwLock::default(),
+            events: Mutex::default(),
+            subscription: None,
+            work_done_progress: false,
+            compile_problems: Arc::default(),
+            pull_diagnostics: false,
+            diagnostic_refresh: false,
+            snippet_completion: false,
+            code_action_resolve: false,
+            reindex_queue,
+        })
+    }
+
+    fn reload_config(&mut self, settings: &serde_json::Value) -> Result<()> {
+        // Clients may nest our settings under the server's section name
+        let settings = settings.get("typst-oxide").unwrap_or(settings);
+        let config = Config::load(&self.root, Some(settings))?;
+        if config == self.config {
+            return Ok(());
+        }
+
+        let needs_reindex = config.wikilink_syntax != self.config.wikilink_syntax
+            || config.include != self.config.include
+            || config.exclude != self.config.exclude
+            || config.max_index_rows != self.config.max_index_rows;
+
+        self.parser = Arc::new(Parser::with_syntax(&config.wikilink_syntax)?);
+        self.note_filter = config.note_filter()?;
+        if config.case_insensitive_links != self.config.case_insensitive_links {
+            self.index
+                .write(|index| index.set_case_insensitive_links(config.case_insensitive_links))?;
+        }
+        if config.archive_folders != self.config.archive_folders {
+            self.index
+                .write(|index| index.set_archive_folders(&config.archive_folders))?;
+        }
+        if config.max_index_rows != self.config.max_index_rows {
+            self.index
+                .write(|index| index.set_max_index_rows(config.max_index_rows))?;
+        }
+        if config.reindex_debounce_ms != self.config.reindex_debounce_ms {
+            self.reindex_queue
+                .set_debounce(Duration::from_millis(config.reindex_debounce_ms));
+        }
+        let needs_git_history = needs_reindex || config.git_history != self.config.git_history;
+        if !config.compile_on_save {
+            lock(&self.compile_problems).clear();
+        }
+        self.config = config;
+
+        if needs_reindex {
+            self.index.write(|index| {
+                self.runtime.block_on(workspace::index_workspace(
+                    &self.parser,
+                    index,
+                    &self.note_filter,
+                    &mut |_, _| {},
+                ))
+            })?;
+        }
+        if needs_git_history {
+            self.index
+                .write(|index| workspace::record_git_history(index, self.config.git_history))?;
+        }
+
+        Ok(())
+    }
+
+    fn paths(&self) -> VaultPaths<'_> {
+        VaultPaths::new(&self.root, self.config.path_style)
+    }
+
+    fn document_text(&self, uri: &Uri) -> Option<String> {
+        if let Some(text) = read(&self.documents).get(uri) {
+            return Some(text.clone());
+        }
+        std::fs::read_to_string(uri_to_path(uri)?).ok()
+    }
+
+    fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let Some(text) = self.document_text(&position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let location = self.index.read(|index| {
+            lsp::definition::goto_definition(
+                index,
+                &self.parser,
+                &position.text_document.uri,
+                &text,
+                position.position,
+            )
+        })?;
+        if let Some(location) = &location
+            && location.uri != position.text_document.uri
+        {
+            self.visit(&location.uri);
+        }
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    /// Count a visit to the note at `uri` for `typst-oxide/recentNotes`. Visits are skipped
+    /// rather than waiting while the index is being written.
+    fn visit(&self, uri: &Uri) {
+        let Some(path) = uri_to_path(uri) else {
+            return;
+        };
+        let now = chrono::Utc::now().timestamp();
+        match self.index.try_write(|index| index.record_visit(&path, now)) {
+            Some(Err(e)) => tracing::warn!("Failed to record visit to {}: {e}", path.display()),
+            Some(Ok(())) => {}
+            None => tracing::debug!("Index busy, skipped visit to {}", path.display()),
+        }
+    }
+
+    fn inlay_hints(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let Some(text) = self.document_text(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let hints = self.index.read(|index| {
+            lsp::inlay_hint::inlay_hints(
+                index,
+                &self.parser,
+                &self.config.inlay_hints,
+                &text,
+                params.range,
+            )
+        })?;
+        Ok(Some(hints))
+    }
+
+    fn selection_ranges(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let Some(text) = self.document_text(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        Ok(Some(lsp::selection_range::selection_ranges(
+            &self.parser,
+            &self.config.wikilink_syntax,
+            &text,
+            &params.positions,
+        )))
+    }
+
+    fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let position = params.text_document_position;
+        let Some(text) = self.document_text(&position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let edits = self.index.read(|index| {
+            lsp::on_type::on_type_formatting(
+                index,
+                &self.config.wikilink_syntax,
+                &text,
+                position.position,
+                &params.ch,
+            )
+        })?;
+        Ok(Some(edits))
+    }
+
+    fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let Some(text) = self.document_text(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        self.index.read(|index| {
+            lsp::rename::prepare_rename(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                &params.text_document.uri,
+                &text,
+                params.position,
+            )
+        })
+    }
+
+    fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let position = params.text_document_position;
+        self.index.read(|index| {
+            lsp::rename::rename(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                &position.text_document.uri,
+                position.position,
+                &params.new_name,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+            )
+        })
+    }
+
+    fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let position = params.text_document_position_params;
+        let Some(text) = self.document_text(&position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        self.index.read(|index| {
+            lsp::call_hierarchy::prepare(
+                index,
+                &self.parser,
+                &position.text_document.uri,
+                &text,
+                position.position,
+            )
+        })
+    }
+
+    fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let position = params.text_document_position;
+        let Some(text) = self.document_text(&position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let locations = self.index.read(|index| {
+            lsp::definition::references(
+                index,
+                &self.parser,
+                &position.text_document.uri,
+                &text,
+                position.position,
+                params.context.include_declaration,
+            )
+        })?;
+        Ok(Some(locations))
+    }
+
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let Some(text) = self.document_text(&position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let note = uri_to_path(&position.text_document.uri).unwrap_or_default();
+        self.index
+            .read(|index| lsp::hover::hover(index, &self.parser, &note, &text, position.position))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let position = params.text_document_position;
+        let Some(text) = self.document_text(&position.text_document.uri) else {
+            return Ok(None);
+        };
+
+        if let Some(note) = uri_to_path(&position.text_document.uri)
+            && let Some(assets) = lsp::completion::complete_asset(
+                &self.root,
+                &self.parser,
+                &self.note_filter,
+                &note,
+                &text,
+                position.position,
+            )
+        {
+            return Ok(Some(assets));
+        }
+        if let Some(metadata) = self
+            .index
+            .read(|index| lsp::completion::complete_metadata(index, &text, position.position))?
+        {
+            return Ok(Some(metadata));
+        }
+        let note = uri_to_path(&position.text_document.uri).unwrap_or_default();
+        let entries = self.index.read(|index| index.bib_entries(&note))?;
+        if let Some(labels) = lsp::completion::complete_label(
+            &self.parser,
+            &note,
+            &entries,
+            &text,
+            position.position,
+        )? {
+            return Ok(Some(labels));
+        }
+
+        self.index.read(|index| {
+            let cx = TargetContext {
+                note: &note,
+                daily_notes: &self.config.daily_notes,
+                today: today(),
+                snippets: self
+                    .snippet_completion
+                    .then_some(&self.config.wikilink_syntax),
+            };
+            lsp::completion::complete(index, &self.parser, &cx, &text, position.position)
+        })
+    }
+
+    fn daily_note(&self, params: DailyNoteRequest) -> Result<DailyNoteResponse> {
+        let response =
+            command::handle_daily_note(&self.root, &self.config.daily_notes, today(), params)?;
+        if response.created {
+            self.index_path(&response.path)?;
+        }
+        Ok(response)
+    }
+
+    fn new_note(&self, params: NewNoteRequest) -> Result<NewNoteResponse> {
+        let response = command::handle_new_note(&self.root, &self.config, today(), params)?;
+        self.index_path(&response.path)?;
+        Ok(response)
+    }
+
+    /// Verify the index against the vault, repairing it unless `dry_run`, and publish the
+    /// resulting events.
+    fn verify_index(&self, params: VerifyIndexRequest) -> Result<VerifyIndexResponse> {
+        let report = self.index.write(|index| {
+            lock(&self.events).track(index, |index| {
+                self.runtime.block_on(workspace::verify_index(
+                    &self.parser,
+                    index,
+                    &self.note_filter,
+                    !params.dry_run,
+                ))
+            })
+        })?;
+        Ok(VerifyIndexResponse { report })
+    }
+
+    fn materialize_queries(&self, params: MaterializeQueriesRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_materialize_queries(
+                index,
+                &self.config.wikilink_syntax,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    fn generate_moc(&self, params: GenerateMocRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_generate_moc(
+                index,
+                &self.config.wikilink_syntax,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    fn outline(&self, params: OutlineRequest) -> Result<OutlineResponse> {
+        let uri = lsp::path_to_uri(&params.file)?;
+        let text = self
+            .document_text(&uri)
+            .with_context(|| format!("Cannot read {}", params.file.display()))?;
+        command::handle_outline(&self.parser, &text, params)
+    }
+
+    fn update_metadata(&self, params: UpdateMetadataRequest) -> Result<WorkspaceEdit> {
+        let uri = lsp::path_to_uri(&params.file_path)?;
+        let text = self
+            .document_text(&uri)
+            .with_context(|| format!("Cannot read {}", params.file_path.display()))?;
+        command::handle_update_metadata(&text, params)
+    }
+
+    fn move_file(&self, params: MoveFileRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_move_file(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    fn merge_notes(&self, params: MergeNotesRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_merge_notes(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    fn archive_note(&self, params: ArchiveNoteRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_archive_note(
+                index,
+                &self.parser,
+                &self.config,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    fn rewrite_links(&self, params: RewriteLinksRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_rewrite_links(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    fn fix_redirects(&self, params: FixRedirectsRequest) -> Result<WorkspaceEdit> {
+        self.index.read(|index| {
+            command::handle_fix_redirects(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+                params,
+            )
+        })
+    }
+
+    /// Link fixes for notes and folders the client is about to rename; the client renames
+    /// them itself.
+    fn will_rename_files(&self, params: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+        let moves: Vec<(PathBuf, PathBuf)> =
+            params.files.iter().filter_map(renamed_paths).collect();
+        let edits = self.index.read(|index| {
+            move_file::move_edits(
+                index,
+                &self.parser,
+                &self.config.wikilink_syntax,
+                &moves,
+                |path| self.document_text(&lsp::path_to_uri(path).ok()?),
+            )
+        })?;
+
+        if edits.is_empty() {
+            return Ok(None);
+        }
+        refactor::workspace_edit(edits, &[]).map(Some)
+    }
+
+    /// Move renamed notes and folders in the index, then reindex renamed notes.
+    fn did_rename_files(&self, params: RenameFilesParams) -> Result<()> {
+        for rename in params.files {
+            let Some((from, to)) = renamed_paths(&rename) else {
+                continue;
+            };
+            if to.is_dir() {
+                self.index.write(|index| {
+                    lock(&self.events)
+                        .track(index, |index| index.rename_folder(&from, &to).map(|_| ()))
+                })?;
+                continue;
+            }
+            self.index.write(|index| {
+                lock(&self.events).track(index, |index| index.rename_file(&from, &to))
+            })?;
+            if to.is_file() {
+                self.index_path(&to)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let Some(text) = self.document_text(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let actions = self.with_actions(|cx| {
+            lsp::code_action::code_actions(cx, &params.text_document.uri, &text, params.range)
+        })?;
+        Ok(Some(actions))
+    }
+
+    fn code_action_resolve(&self, action: CodeAction) -> Result<CodeAction> {
+        self.with_actions(|cx| lsp::code_action::resolve_code_action(cx, action))
+    }
+
+    // Code actions see open documents as they are in the editor
+    fn with_actions<T>(&self, f: impl FnOnce(&ActionContext) -> Result<T>) -> Result<T> {
+        let read = |path: &Path| self.document_text(&lsp::path_to_uri(path).ok()?);
+        self.index.read(|index| {
+            f(&ActionContext {
+                index,
+                parser: &self.parser,
+                config: &self.config,
+                today: today(),
+                read: &read,
+                resolve: self.code_action_resolve,
+            })
+        })
+    }
+
+    /// Every vault problem, including the compiler's, as diagnostics by note.
+    fn vault_diagnostics(&self) -> Result<BTreeMap<PathBuf, Vec<lsp_types::Diagnostic>>> {
+        let mut problems = self.index.read(diagnostics::check)?;
+        problems.extend(lock(&self.compile_problems).values().flatten().cloned());
+        let problems = diagnostics::apply_rules(problems, &self.config.severity, |path| {
+            self.document_text(&lsp::path_to_uri(path).ok()?)
+        });
+        let mut by_note: BTreeMap<PathBuf, Vec<lsp_types::Diagnostic>> = BTreeMap::new();
+        for problem in &problems {
+            by_note
+                .entry(problem.file.clone())
+                .or_default()
+                .push(lsp::to_diagnostic(problem));
+        }
+        Ok(by_note)
+    }
+
+    fn document_diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let diagnostics = self.file_diagnostics(&params.text_document.uri)?;
+        Ok(pull_diagnostics::document_report(
+            diagnostics,
+            params.previous_result_id.as_deref(),
+        ))
+    }
+
+    fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let report = pull_diagnostics::workspace_report(
+            self.vault_diagnostics()?,
+            &params.previous_result_ids,
+        )?;
+        Ok(WorkspaceDiagnosticReportResult::Report(report))
+    }
+
+    /// The vault problems located in the note at `uri`.
+    fn file_diagnostics(&self, uri: &Uri) -> Result<Vec<lsp_types::Diagnostic>> {
+        let Some(path) = uri_to_path(uri) else {
+            return Ok(Vec::new());
+        };
+        let problems = self
+            .index
+            .read(|index| diagnostics::check_file(index, &path))?;
+        let compile_problems = lock(&self.compile_problems);
+        let problems = problems
+            .into_iter()
+            .chain(compile_problems.get(&path).into_iter().flatten().cloned())
+            .collect();
+        let problems = diagnostics::apply_rules(problems, &self.config.severity, |path| {
+            self.document_text(&lsp::path_to_uri(path).ok()?)
+        });
+        Ok(problems.iter().map(lsp::to_diagnostic).collect())
+    }
+
+    /// A job compiling the saved note at `path` for its compiler problems, with
+    /// `compile_on_save`. It doesn't borrow the context, so it can run without the server lock.
+    #[cfg(feature = "compile")]
+    fn compile_job(&self, path: &Path) -> Option<Box<dyn FnOnce() -> Result<()>>> {
+        if !self.config.compile_on_save || !self.is_note(path) {
+            return None;
+        }
+        let (root, path) = (self.root.clone(), path.to_path_buf());
+        let compile_problems = Arc::clone(&self.compile_problems);
+        Some(Box::new(move || {
+            let problems = typst_oxide::compile::check(&root, &path)?;
+            lock(&compile_problems).insert(path, problems);
+            Ok(())
+        }))
+    }
+
+    #[cfg(not(feature = "compile"))]
+    fn compile_job(&self, _path: &Path) -> Option<Box<dyn FnOnce() -> Result<()>>> {
+        if self.config.compile_on_save {
+            tracing::warn!("compile_on_save needs the `compile` feature");
+        }
+        None
+    }
+
+    /// Whether `path` is a note of the vault, by the configured include and exclude globs.
+    fn is_note(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root)
+            .is_ok_and(|relative| self.note_filter.is_note(relative))
+    }
+
+    /// Whether a change to `path` needs reindexing: it's a note or a bibliography in the vault.
+    fn is_indexed(&self, path: &Path) -> bool {
+        self.is_note(path) || path.starts_with(&self.root) && workspace::is_bibliography(path)
+    }
+
+    /// Reindex a batch of queued notes in one transaction, publishing the resulting events.
+    fn reindex(&self, paths: &[PathBuf]) -> Result<()> {
+        self.index.write(|index| {
+            lock(&self.events).track(index, |index| {
+                self.runtime
+                    .block_on(workspace::index_files(&self.parser, index, paths))
+            })
+        })
+    }
+
+    /// Reindex one note, publishing the resulting events to subscribers.
+    fn index_path(&self, path: &Path) -> Result<()> {
+        self.index.write(|index| {
+            lock(&self.events).track(index, |index| {
+                self.runtime
+                    .block_on(workspace::index_file(&self.parser, index, path))
+            })
+        })
+    }
+
+    fn subscribe(
+        &mut self,
+        params: SubscribeRequest,
+        send: impl Fn(Message) -> Result<()> + Send + 'static,
+    ) {
+        if let Some(id) = self.subscription.take() {
+            lock(&self.events).unsubscribe(id);
+        }
+        let id = lock(&self.events).subscribe(params.kinds, move |event| {
+            send(Message::Notification(Notification::new(
+                EventNotification::METHOD.into(),
+                event,
+            )))
+        });
+        self.subscription = Some(id);
     }
 }
 
-trait LSPOperation {
-    fn initialize(&self, capabilities: &ServerCapabilities) -> Result<()>;
-    fn send_ok<T: serde::Serialize>(&self, id: RequestId, result: &T) -> Result<()>;
-    fn send_err(&self, id: &RequestId, code: ErrorCode, msg: &str) -> Result<()>;
-    fn dispatch_request(&self, ctx: &mut Context, req: &ServerRequest) -> Result<()>;
+/// The state request handlers run against.
+struct Server<'a> {
+    conn: &'a Connection,
+    ctx: Context,
 }
 
-impl LSPOperation for Connection {
-    fn send_ok<T: serde::Serialize>(&self, id: RequestId, result: &T) -> Result<()> {
-        self.sender.send(Message::Response(Response {
-            id,
-            result: Some(serde_json::to_value(result)?),
-            error: None,
-        }))?;
+impl Server<'_> {
+    /// Report a full-vault analysis through `$/progress`, if the client supports it.
+    fn with_progress<T>(&self, title: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn
+            .with_progress(self.ctx.work_done_progress, title, f)
+    }
 
-        Ok(())
+    /// Read the index, stopping once the client cancels the current request.
+    fn read_cancellable<T>(&self, f: impl FnOnce(&Index) -> Result<T>) -> Result<T> {
+        let token = CANCELLATION.with_borrow(Clone::clone);
+        self.ctx.index.read_cancellable(token.as_ref(), f)
+    }
+}
+
+thread_local! {
+    // Cancelled when the client sends `$/cancelRequest` for the request this thread handles
+    static CANCELLATION: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+fn with_cancellation<T>(token: CancellationToken, f: impl FnOnce() -> T) -> T {
+    CANCELLATION.set(Some(token));
+    let result = f();
+    CANCELLATION.set(None);
+    result
+}
+
+// Requests answered while the user types go ahead of analyses and reindexing
+fn priority(method: &str) -> Priority {
+    match method {
+        Completion::METHOD
+        | HoverRequest::METHOD
+        | InlayHintRequest::METHOD
+        | SelectionRangeRequest::METHOD
+        | OnTypeFormatting::METHOD
+        | GotoDefinition::METHOD
+        | References::METHOD
+        | PrepareRenameRequest::METHOD
+        | CodeActionRequest::METHOD => Priority::Interactive,
+        _ => Priority::Normal,
     }
+}
 
+/// Apply document changes in order on the main loop and queue saved notes for reindexing.
+fn dispatch_notification(server: &RwLock<Server<'_>>, noti: Notification) -> Result<()> {
+    match noti.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(noti.params)?;
+            let server = read(server);
+            let uri = params.text_document.uri;
+            server.ctx.visit(&uri);
+            // Diagnostics read `pkm-ignore-next-line` comments from the buffer, not the disk
+            write(&server.ctx.documents).insert(uri.clone(), params.text_document.text);
+            server.conn.publish_diagnostics(&server.ctx, &uri)?;
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(noti.params)?;
+            // Full sync: the last change carries the whole document
+            if let Some(change) = params.content_changes.into_iter().last() {
+                write(&read(server).ctx.documents).insert(params.text_document.uri, change.text);
+            }
+        }
+        DidSaveTextDocument::METHOD => {
+            let params: DidSaveTextDocumentParams = serde_json::from_value(noti.params)?;
+            let server = read(server);
+            if let Some(path) = uri_to_path(&params.text_document.uri)
+                && server.ctx.is_indexed(&path)
+            {
+                server.ctx.reindex_queue.push(path);
+            }
+        }
+        DidChangeWatchedFiles::METHOD => {
+            let params: DidChangeWatchedFilesParams = serde_json::from_value(noti.params)?;
+            let server = read(server);
+            for change in params.changes {
+                // A moved `HEAD` only wakes the reindex thread, which refreshes git history
+                if let Some(path) = uri_to_path(&change.uri)
+                    && (server.ctx.is_indexed(&path) || git::is_git_metadata(&path))
+                {
+                    server.ctx.reindex_queue.push(path);
+                }
+            }
+        }
+        DidChangeConfiguration::METHOD => {
+            let params: DidChangeConfigurationParams = serde_json::from_value(noti.params)?;
+            if let Err(e) = write(server).ctx.reload_config(&params.settings) {
+                tracing::error!("Failed to reload configuration: {e}");
+            }
+        }
+        DidRenameFiles::METHOD => {
+            let params: RenameFilesParams = serde_json::from_value(noti.params)?;
+            let server = read(server);
+            // A save queued before the rename is indexed before it, never after
+            let _held = server.ctx.reindex_queue.hold();
+            if let Err(e) = server.ctx.did_rename_files(params) {
+                tracing::error!("Failed to update renamed files: {e}");
+            }
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(noti.params)?;
+            write(&read(server).ctx.documents).remove(&params.text_document.uri);
+        }
+        SetTrace::METHOD => {
+            let params: SetTraceParams = serde_json::from_value(noti.params)?;
+            logging::set_trace(params.value);
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn dispatcher<'a>() -> Dispatcher<Server<'a>> {
+    let dispatcher = Dispatcher::<Server<'a>>::new()
+        .read::<GotoDefinition>(|s, params| s.ctx.goto_definition(params))
+        .read::<InlayHintRequest>(|s, params| s.ctx.inlay_hints(params))
+        .read::<SelectionRangeRequest>(|s, params| s.ctx.selection_ranges(params))
+        .read::<OnTypeFormatting>(|s, params| s.ctx.on_type_formatting(params))
+        .read::<PrepareRenameRequest>(|s, params| s.ctx.prepare_rename(params))
+        .read::<Rename>(|s, params| s.ctx.rename(params))
+        .read::<CallHierarchyPrepare>(|s, params| s.ctx.prepare_call_hierarchy(params))
+        .read::<CallHierarchyIncomingCalls>(|s, params| {
+            s.ctx
+                .index
+                .read(|index| lsp::call_hierarchy::incoming(index, &params.item))
+        })
+        .read::<CallHierarchyOutgoingCalls>(|s, params| {
+            s.ctx
+                .index
+                .read(|index| lsp::call_hierarchy::outgoing(index, &params.item))
+        })
+        .read::<References>(|s, params| s.ctx.references(params))
+        .read::<HoverRequest>(|s, params| s.ctx.hover(params))
+        .read::<Completion>(|s, params| s.ctx.completion(params))
+        .read::<CodeActionRequest>(|s, params| s.ctx.code_action(params))
+        .read::<CodeActionResolveRequest>(|s, action| s.ctx.code_action_resolve(action))
+        .read::<DocumentDiagnosticRequest>(|s, params| s.ctx.document_diagnostic(params))
+        .read::<WorkspaceDiagnosticRequest>(|s, params| {
+            s.with_progress("Checking the vault", || s.ctx.workspace_diagnostic(params))
+        })
+        .write::<NewNote>(|s, params| s.ctx.paths().run(params, |params| s.ctx.new_note(params)))
+        .read::<LabelBacklinks>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_label_backlinks(index, params))
+            })
+        })
+        .read::<Outline>(|s, params| s.ctx.paths().run(params, |params| s.ctx.outline(params)))
+        .read::<RecentNotes>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                let now = chrono::Utc::now().timestamp();
+                s.ctx
+                    .index
+                    .read(|index| command::handle_recent_notes(index, now, params))
+            })
+        })
+        .write::<StarNote>(|s, params| {
+            let now = chrono::Utc::now().timestamp();
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .write(|index| command::handle_star_note(index, true, now, params))
+            })
+        })
+        .write::<UnstarNote>(|s, params| {
+            let now = chrono::Utc::now().timestamp();
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .write(|index| command::handle_star_note(index, false, now, params))
+            })
+        })
+        .read::<FileInfo>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_file_info(index, params))
+            })
+        })
+        .read::<History>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_history(index, params))
+            })
+        })
+        .read::<Figures>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_figures(index, params))
+            })
+        })
+        .read::<Packages>(|s, ()| {
+            s.ctx
+                .paths()
+                .run((), |()| s.ctx.index.read(command::handle_packages))
+        })
+        .read::<StarredNotes>(|s, ()| {
+            s.ctx
+                .paths()
+                .run((), |()| s.ctx.index.read(command::handle_starred_notes))
+        })
+        .read::<ForwardLinks>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_forward_links(index, params))
+            })
+        })
+        .read::<BackwardLinks>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_backward_links(index, params))
+            })
+        })
+        .read::<Search>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx
+                    .index
+                    .read(|index| command::handle_search(index, params))
+            })
+        })
+        .read::<Tags>(|s, ()| {
+            s.ctx.paths().run((), |()| {
+                s.ctx.index.read(|index| command::handle_tags(index))
+            })
+        })
+        .read::<VaultGraph>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.read_cancellable(|index| command::handle_graph(index, params))
+            })
+        })
+        .read::<LocalGraph>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.read_cancellable(|index| command::handle_local_graph(index, params))
+            })
+        })
+        .read::<PathBetween>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.read_cancellable(|index| command::handle_path_between(index, params))
+            })
+        })
+        .read::<UnlinkedMentions>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Finding unlinked mentions", || {
+                    s.read_cancellable(|index| {
+                        command::handle_unlinked_mentions(index, &s.ctx.parser, params)
+                    })
+                })
+            })
+        })
+        .read::<DeadLabels>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Finding dead labels", || {
+                    s.read_cancellable(|index| {
+                        command::handle_dead_labels(index, &s.ctx.parser, params, |path| {
+                            s.ctx.document_text(&lsp::path_to_uri(path).ok()?)
+                        })
+                    })
+                })
+            })
+        })
+        .read::<Clusters>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Finding clusters", || {
+                    s.read_cancellable(|index| command::handle_clusters(index, params))
+                })
+            })
+        })
+        .write::<DailyNote>(|s, params| {
+            s.ctx.paths().run(params, |params| s.ctx.daily_note(params))
+        })
+        .read::<Calendar>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.ctx.index.read(|index| {
+                    command::handle_calendar(
+                        index,
+                        &s.ctx.config.daily_notes,
+                        &chrono::Local,
+                        today(),
+                        params,
+                    )
+                })
+            })
+        })
+        .read::<PeriodicNote>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                command::handle_periodic_note(
+                    &s.ctx.root,
+                    &s.ctx.config.daily_notes,
+                    today(),
+                    params,
+                )
+            })
+        })
+        .read::<Related>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.read_cancellable(|index| command::handle_related(index, params))
+            })
+        })
+        .read::<Query>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.read_cancellable(|index| command::handle_query(index, params))
+            })
+        })
+        .read::<MoveFile>(|s, params| s.ctx.paths().run(params, |params| s.ctx.move_file(params)))
+        .read::<MergeNotes>(|s, params| {
+            s.ctx
+                .paths()
+                .run(params, |params| s.ctx.merge_notes(params))
+        })
+        .read::<ArchiveNote>(|s, params| {
+            s.ctx
+                .paths()
+                .run(params, |params| s.ctx.archive_note(params))
+        })
+        .read::<RewriteLinks>(|s, params| {
+            s.ctx
+                .paths()
+                .run(params, |params| s.ctx.rewrite_links(params))
+        })
+        .read::<FindReplace>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Finding matches", || {
+                    s.read_cancellable(|index| {
+                        command::handle_find_replace(
+                            index,
+                            &s.ctx.parser,
+                            |path| s.ctx.document_text(&lsp::path_to_uri(path).ok()?),
+                            params,
+                        )
+                    })
+                })
+            })
+        })
+        .read::<FixRedirects>(|s, params| {
+            s.ctx
+                .paths()
+                .run(params, |params| s.ctx.fix_redirects(params))
+        })
+        .read::<WillRenameFiles>(|s, params| s.ctx.will_rename_files(params))
+        .read::<UpdateMetadata>(|s, params| {
+            s.ctx
+                .paths()
+                .run(params, |params| s.ctx.update_metadata(params))
+        })
+        .write::<VerifyIndex>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Verifying the index", || s.ctx.verify_index(params))
+            })
+        })
+        .read::<MaterializeQueries>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Materializing queries", || {
+                    s.ctx.materialize_queries(params)
+                })
+            })
+        })
+        .read::<GenerateMoc>(|s, params| {
+            s.ctx
+                .paths()
+                .run(params, |params| s.ctx.generate_moc(params))
+        })
+        .write::<Subscribe>(|s, params| {
+            let sender = s.conn.sender.clone();
+            s.ctx
+                .subscribe(params, move |message| Ok(sender.send(message)?));
+            Ok(())
+        })
+        .read::<Stats>(|s, params| {
+            s.ctx.paths().run(params, |params| {
+                s.with_progress("Collecting vault statistics", || {
+                    let mut response = s
+                        .ctx
+                        .index
+                        .read(|index| command::handle_stats(index, params))?;
+                    response.queue_depth = s.ctx.reindex_queue.depth();
+                    Ok(response)
+                })
+            })
+        })
+        .read::<UnusedAttachments>(|s, ()| {
+            s.ctx.paths().run((), |()| {
+                s.with_progress("Finding unused attachments", || {
+                    s.ctx
+                        .index
+                        .read(|index| command::handle_unused_attachments(index, &s.ctx.note_filter))
+                })
+            })
+        });
+    #[cfg(feature = "embeddings")]
+    let dispatcher = dispatcher.read::<command::SemanticSearch>(|s, params| {
+        s.ctx.paths().run(params, |params| {
+            command::handle_semantic_search(&s.ctx.index, &s.ctx.config.embeddings, params)
+        })
+    });
+    #[cfg(feature = "compile")]
+    let dispatcher = dispatcher.read::<command::Export>(|s, params| {
+        s.ctx.paths().run(params, |params| {
+            s.ctx.index.read(|index| {
+                command::handle_export(
+                    index,
+                    &s.ctx.parser,
+                    s.ctx.config.build_folder.as_deref(),
+                    params,
+                    |path| s.ctx.document_text(&lsp::path_to_uri(path).ok()?),
+                )
+            })
+        })
+    });
+    dispatcher
+}
+
+trait LSPOperation {
+    fn initialize(&self, capabilities: &ServerCapabilities) -> Result<InitializeParams>;
+    fn send_err(&self, id: &RequestId, code: ErrorCode, msg: &str) -> Result<()>;
+    fn send_progress(&self, token: &str, progress: WorkDoneProgress) -> Result<()>;
+    fn create_progress(&self, token: &str) -> Result<()>;
+    fn with_progress<T>(
+        &self,
+        enabled: bool,
+        title: &str,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T>;
+    fn publish_diagnostics(&self, ctx: &Context, uri: &Uri) -> Result<()>;
+    fn refresh_diagnostics(&self) -> Result<()>;
+    fn watch_notes(&self) -> Result<()>;
+}
+
+impl LSPOperation for Connection {
     fn send_err(&self, id: &RequestId, code: ErrorCode, msg: &str) -> Result<()> {
         let resp = Response {
             id: id.clone(),
@@ -50,42 +1234,658 @@ impl LSPOperation for Connection {
         Ok(())
     }
 
-    fn dispatch_request(&self, ctx: &mut Context, req: &ServerRequest) -> Result<()> {
-        match req.method.as_str() {
-            GotoDefinition::METHOD => {}
-            _ => self.send_err(&req.id, ErrorCode::MethodNotFound, "Method not found")?,
+    fn send_progress(&self, token: &str, progress: WorkDoneProgress) -> Result<()> {
+        let params = ProgressParams {
+            token: NumberOrString::String(token.into()),
+            value: ProgressParamsValue::WorkDone(progress),
+        };
+        self.sender.send(Message::Notification(Notification::new(
+            Progress::METHOD.into(),
+            params,
+        )))?;
+
+        Ok(())
+    }
+
+    fn create_progress(&self, token: &str) -> Result<()> {
+        // The token may only be used once the client accepted it
+        let id = RequestId::from(token.to_string());
+        let (sender, receiver) = mpsc::channel();
+        lock(&AWAITED).insert(id.clone(), sender);
+        let create = WorkDoneProgressCreateParams {
+            token: NumberOrString::String(token.into()),
+        };
+        self.sender.send(Message::Request(ServerRequest::new(
+            id.clone(),
+            WorkDoneProgressCreate::METHOD.into(),
+            create,
+        )))?;
+
+        let response = receiver.recv_timeout(PROGRESS_CREATE_TIMEOUT);
+        lock(&AWAITED).remove(&id);
+        match response {
+            Ok(Response { error: None, .. }) => Ok(()),
+            Ok(Response {
+                error: Some(error), ..
+            }) => anyhow::bail!(
+                "The client refused progress token {token}: {}",
+                error.message
+            ),
+            Err(_) => anyhow::bail!("The client didn't accept progress token {token} in time"),
+        }
+    }
+
+    fn with_progress<T>(
+        &self,
+        enabled: bool,
+        title: &str,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if !enabled {
+            return f();
         }
-        todo!()
+
+        // Progress failures must not fail the analysis itself
+        let token = format!(
+            "typst-oxide/analysis/{}",
+            NEXT_PROGRESS.fetch_add(1, atomic::Ordering::Relaxed)
+        );
+        let begin = WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.into(),
+            ..Default::default()
+        });
+        if let Err(e) = self.create_progress(&token) {
+            tracing::warn!("Failed to report progress: {e}");
+            return f();
+        }
+        if let Err(e) = self.send_progress(&token, begin) {
+            tracing::error!("Failed to report progress: {e}");
+        }
+        let result = f();
+        let end = WorkDoneProgress::End(WorkDoneProgressEnd::default());
+        if let Err(e) = self.send_progress(&token, end) {
+            tracing::error!("Failed to report progress: {e}");
+        }
+        result
+    }
+
+    fn publish_diagnostics(&self, ctx: &Context, uri: &Uri) -> Result<()> {
+        if ctx.pull_diagnostics {
+            return Ok(());
+        }
+        let diagnostics = match ctx.file_diagnostics(uri) {
+            Ok(diagnostics) => diagnostics,
+            Err(e) => {
+                tracing::error!("Failed to check {}: {e}", uri.as_str());
+                return Ok(());
+            }
+        };
+        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics, None);
+        self.sender.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.into(),
+            params,
+        )))?;
+
+        Ok(())
+    }
+
+    fn refresh_diagnostics(&self) -> Result<()> {
+        // The client's empty reply is ignored by the main loop
+        let id = NEXT_REFRESH.fetch_add(1, atomic::Ordering::Relaxed);
+        self.sender.send(Message::Request(ServerRequest::new(
+            RequestId::from(format!("diagnostic-refresh-{id}")),
+            WorkspaceDiagnosticRefresh::METHOD.into(),
+            (),
+        )))?;
+        Ok(())
+    }
+
+    fn watch_notes(&self) -> Result<()> {
+        // Notes and bibliographies changed outside the editor, e.g. by `git checkout`, arrive as
+        // `workspace/didChangeWatchedFiles`, as do commits and checkouts moving `HEAD`. The
+        // client's reply is ignored by the main loop
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: [
+                "**/*.typ",
+                "**/*.bib",
+                "**/*.{yml,yaml}",
+                "**/.git/HEAD",
+                "**/.git/refs/heads/**",
+            ]
+            .into_iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.to_string()),
+                kind: None,
+            })
+            .collect(),
+        };
+        let registration = Registration {
+            id: "typst-oxide/watch-notes".to_string(),
+            method: DidChangeWatchedFiles::METHOD.to_string(),
+            register_options: Some(serde_json::to_value(options)?),
+        };
+        self.sender.send(Message::Request(ServerRequest::new(
+            RequestId::from(registration.id.clone()),
+            RegisterCapability::METHOD.into(),
+            RegistrationParams {
+                registrations: vec![registration],
+            },
+        )))?;
+        Ok(())
     }
 
-    fn initialize(&self, capabilities: &ServerCapabilities) -> Result<()> {
+    fn initialize(&self, capabilities: &ServerCapabilities) -> Result<InitializeParams> {
+        let (id, params) = self.initialize_start()?;
         let init_value = serde_json::json!({
             "capabilities": capabilities,
             "offsetEncoding": ["utf-8"],
         });
-        let init_params = self.initialize(init_value)?;
+        self.initialize_finish(id, init_value)?;
 
-        Ok(())
+        Ok(serde_json::from_value(params)?)
+    }
+}
+
+/// Serve the vault in the current directory over MCP instead of LSP.
+fn serve_mcp() -> Result<()> {
+    let root = std::env::current_dir()?;
+    let config = Config::load(&root, None)?;
+    let mut server = McpServer::new(&root, config)?;
+    server.serve(std::io::stdin().lock(), std::io::stdout().lock())
+}
+
+/// Serve the vault in the current directory over newline-delimited JSON-RPC on stdin and
+/// stdout, answering the same methods as the language server.
+fn serve_rpc() -> Result<()> {
+    let root = std::env::current_dir()?;
+    let config = Config::load(&root, None)?;
+    let ctx = Context::new(&root, config, &mut |_, _| {})?;
+    let output = Arc::new(Mutex::new(std::io::stdout()));
+    // Notifications from handlers, such as subscribed events, are written between responses
+    let (conn, client) = Connection::memory();
+    let notifications = std::thread::spawn({
+        let output = Arc::clone(&output);
+        move || {
+            for msg in client.receiver {
+                if let Message::Notification(noti) = msg
+                    && let Err(e) = lsp::rpc::write_line(&output, &noti)
+                {
+                    tracing::error!("Failed to write notification: {e}");
+                }
+            }
+        }
+    });
+
+    let mut server = Server { conn: &conn, ctx };
+    lsp::rpc::serve(&dispatcher(), &mut server, std::io::stdin().lock(), &output)?;
+    // The notification thread exits once every sender is gone, including subscriptions'
+    drop(server);
+    drop(conn);
+    if notifications.join().is_err() {
+        tracing::error!("Notification writer panicked");
+    }
+    Ok(())
+}
+
+fn serve_http(address: &str) -> Result<()> {
+    let root = std::env::current_dir()?;
+    let config = Config::load(&root, None)?;
+    let server = Arc::new(ApiServer::new(&root, config)?);
+    let listener = std::net::TcpListener::bind(address)?;
+    tracing::info!("Serving {} on http://{address}", root.display());
+    server.serve(listener)
+}
+
+#[allow(deprecated)]
+fn workspace_root(params: &InitializeParams) -> Result<PathBuf> {
+    let folder_uri = params
+        .workspace_folders
+        .as_ref()
+        .and_then(|folders| folders.first())
+        .map(|folder| &folder.uri)
+        .or(params.root_uri.as_ref());
+
+    match folder_uri.and_then(uri_to_path) {
+        Some(root) => Ok(root),
+        None => Ok(std::env::current_dir()?),
+    }
+}
+
+const INDEXING_TOKEN: &str = "typst-oxide/indexing";
+
+// Numbers the progress tokens of full-vault analyses
+static NEXT_PROGRESS: AtomicUsize = AtomicUsize::new(0);
+// Numbers the `workspace/diagnostic/refresh` requests
+static NEXT_REFRESH: AtomicUsize = AtomicUsize::new(0);
+// Where to hand the client's replies to requests a thread is waiting on, by request id
+static AWAITED: LazyLock<Mutex<HashMap<RequestId, mpsc::Sender<Response>>>> =
+    LazyLock::new(Mutex::default);
+// How long to wait for the client to accept a progress token
+const PROGRESS_CREATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Report initial indexing through `$/progress`, if the client supports it.
+fn index_with_progress(
+    conn: &Connection,
+    params: &InitializeParams,
+    root: &Path,
+    config: Config,
+) -> Result<Context> {
+    let supported = params
+        .capabilities
+        .window
+        .as_ref()
+        .and_then(|window| window.work_done_progress)
+        .unwrap_or(false);
+    if !supported {
+        return Context::new(root, config, &mut |_, _| {});
+    }
+    if let Err(e) = conn.create_progress(INDEXING_TOKEN) {
+        tracing::warn!("Failed to report progress: {e}");
+        let mut ctx = Context::new(root, config, &mut |_, _| {});
+        if let Ok(ctx) = &mut ctx {
+            ctx.work_done_progress = true;
+        }
+        return ctx;
+    }
+    conn.send_progress(
+        INDEXING_TOKEN,
+        WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: "Indexing notes".into(),
+            percentage: Some(0),
+            ..Default::default()
+        }),
+    )?;
+
+    let mut last_percentage = 0;
+    let mut ctx = Context::new(root, config, &mut |done, total| {
+        // Only report when the visible percentage changes
+        let percentage = (done * 100 / total.max(1)) as u32;
+        if percentage == last_percentage {
+            return;
+        }
+        last_percentage = percentage;
+        let report = WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(format!("{done}/{total}")),
+            percentage: Some(percentage),
+            ..Default::default()
+        });
+        if let Err(e) = conn.send_progress(INDEXING_TOKEN, report) {
+            tracing::error!("Failed to report progress: {e}");
+        }
+    });
+
+    conn.send_progress(
+        INDEXING_TOKEN,
+        WorkDoneProgress::End(WorkDoneProgressEnd::default()),
+    )?;
+    if let Ok(ctx) = &mut ctx {
+        ctx.work_done_progress = true;
+    }
+    ctx
+}
+
+/// Forward messages from the client to the main loop, registering a token for each request
+/// and cancelling the one named by any `$/cancelRequest` on the way. Replies a thread is
+/// waiting on go to that thread instead. Stops after forwarding
+/// `shutdown`, so the main loop can read the `exit` notification itself.
+fn forward_messages(
+    conn: &Connection,
+    pending: Arc<Mutex<HashMap<RequestId, CancellationToken>>>,
+) -> mpsc::Receiver<Message> {
+    let (sender, receiver) = mpsc::channel();
+    let incoming = conn.receiver.clone();
+    std::thread::spawn(move || {
+        for msg in incoming {
+            if let Message::Notification(noti) = &msg
+                && noti.method == Cancel::METHOD
+            {
+                if let Ok(params) = serde_json::from_value::<CancelParams>(noti.params.clone()) {
+                    let id = match params.id {
+                        NumberOrString::Number(id) => RequestId::from(id),
+                        NumberOrString::String(id) => RequestId::from(id),
+                    };
+                    if let Some(token) = lock(&pending).get(&id) {
+                        token.cancel();
+                    }
+                }
+                continue;
+            }
+            let msg = match msg {
+                Message::Response(response) => match lock(&AWAITED).remove(&response.id) {
+                    Some(waiter) => {
+                        let _ = waiter.send(response);
+                        continue;
+                    }
+                    None => Message::Response(response),
+                },
+                msg => msg,
+            };
+            let mut shutdown = false;
+            if let Message::Request(req) = &msg {
+                lock(&pending).insert(req.id.clone(), CancellationToken::new());
+                shutdown = req.method == Shutdown::METHOD;
+            }
+            if sender.send(msg).is_err() || shutdown {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn renamed_paths(rename: &FileRename) -> Option<(PathBuf, PathBuf)> {
+    let path = |uri: &str| uri_to_path(&uri.parse().ok()?);
+    Some((path(&rename.old_uri)?, path(&rename.new_uri)?))
+}
+
+fn today() -> chrono::NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+fn server_capabilities() -> ServerCapabilities {
+    let filter = |glob: &str, matches| FileOperationFilter {
+        scheme: Some("file".to_string()),
+        pattern: FileOperationPattern {
+            glob: glob.to_string(),
+            matches: Some(matches),
+            options: None,
+        },
+    };
+    let note_operations = FileOperationRegistrationOptions {
+        filters: vec![
+            filter("**/*.typ", FileOperationPatternKind::File),
+            filter("**", FileOperationPatternKind::Folder),
+        ],
+    };
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "[".to_string(),
+            more_trigger_character: Some(vec!["|".to_string()]),
+        }),
+        selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![
+                "[".to_string(),
+                "\"".to_string(),
+                "/".to_string(),
+                "@".to_string(),
+            ]),
+            ..Default::default()
+        }),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            resolve_provider: Some(true),
+            ..Default::default()
+        })),
+        diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+            identifier: Some("typst-oxide".to_string()),
+            // A note's problems depend on the notes it links to
+            inter_file_dependencies: true,
+            workspace_diagnostics: true,
+            work_done_progress_options: Default::default(),
+        })),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: None,
+            file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                will_rename: Some(note_operations.clone()),
+                did_rename: Some(note_operations),
+                ..Default::default()
+            }),
+        }),
+        ..Default::default()
     }
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let log_file = args
+        .iter()
+        .position(|arg| arg == "--log-file")
+        .and_then(|at| args.get(at + 1))
+        .map(PathBuf::from);
+    logging::init(log_file.as_deref())?;
+    logging::install_panic_hook();
+    if args.iter().any(|arg| arg == "--mcp") {
+        return serve_mcp();
+    }
+    if args.iter().any(|arg| arg == "--rpc") {
+        return serve_rpc();
+    }
+    if let Some(at) = args.iter().position(|arg| arg == "--serve") {
+        let address = args
+            .get(at + 1)
+            .filter(|arg| !arg.starts_with("--"))
+            .map_or(serve::DEFAULT_ADDRESS, String::as_str);
+        return serve_http(address);
+    }
+
     let (conn, io_thread) = Connection::stdio();
-    let mut ctx = Context::new();
+    run(&conn)?;
+    // The writer thread only exits once every sender is gone, including subscriptions' and
+    // the logger's
+    drop(conn);
+    io_thread.join()?;
+    Ok(())
+}
 
-    for msg in &conn.receiver {
-        match msg {
-            Message::Request(req) => {
-                if conn.handle_shutdown(&req)? {
-                    break;
+/// Reindex queued notes batch by batch until the queue is closed, then publish their problems.
+fn reindex_queued(server: &RwLock<Server<'_>>, queue: &ReindexQueue) {
+    while let Some(mut batch) = queue.next_batch() {
+        let _span = tracing::info_span!("reindex", files = batch.paths.len()).entered();
+        batch.paths.retain(|path| !git::is_git_metadata(path));
+        let mut notes = Vec::new();
+        {
+            let server = read(server);
+            if !batch.paths.is_empty()
+                && let Err(e) = server.ctx.reindex(&batch.paths)
+            {
+                tracing::error!("Failed to reindex: {e}");
+            }
+            for path in batch.paths.iter().cloned() {
+                if !workspace::is_bibliography(&path) {
+                    notes.push(path);
+                    continue;
+                }
+                // A changed bibliography changes the problems of the notes citing from it
+                match server
+                    .ctx
+                    .index
+                    .read(|index| index.bibliography_users(&path))
+                {
+                    Ok(users) => notes.extend(users),
+                    Err(e) => {
+                        tracing::error!("Failed to find the notes using {}: {e}", path.display())
+                    }
                 }
-                conn.dispatch_request(&mut ctx, &req)?;
             }
-            Message::Response(resp) => todo!(),
-            Message::Notification(noti) => todo!(),
+        }
+        // The index is up to date, so the main loop may move notes again
+        drop(batch);
+        // Commits and checkouts move `HEAD`, which is checked after every save and `.git` change
+        {
+            let server = read(server);
+            let ctx = &server.ctx;
+            if let Err(e) = workspace::refresh_git_history(&ctx.index, ctx.config.git_history) {
+                tracing::error!("Failed to refresh git history: {e}");
+            }
+        }
+        notes.sort();
+        notes.dedup();
+        for path in &notes {
+            let Ok(uri) = lsp::path_to_uri(path) else {
+                continue;
+            };
+            // Compiling takes a while, and holding the server lock meanwhile would stall
+            // configuration changes and everything else waiting to write
+            let job = read(server).ctx.compile_job(path);
+            if path.is_file()
+                && let Some(job) = job
+                && let Err(e) = job()
+            {
+                tracing::error!("Failed to compile: {e}");
+            }
+            let server = read(server);
+            if let Err(e) = server.conn.publish_diagnostics(&server.ctx, &uri) {
+                tracing::error!("Failed to publish diagnostics: {e}");
+            }
+        }
+        // Reindexing can change the problems of other notes too
+        let server = read(server);
+        if server.ctx.diagnostic_refresh
+            && let Err(e) = server.conn.refresh_diagnostics()
+        {
+            tracing::error!("Failed to refresh diagnostics: {e}");
         }
     }
+}
 
-    io_thread.join()?;
+// Closes the queue when dropped, however the session ends
+struct Closing<'a>(&'a ReindexQueue);
+
+impl Drop for Closing<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Run a language server session on `conn`, from `initialize` through `exit`.
+fn run(conn: &Connection) -> Result<()> {
+    let init_params = LSPOperation::initialize(conn, &server_capabilities())?;
+    let sender = conn.sender.clone();
+    logging::set_client(move |noti| {
+        // Logging must not fail, and the client may already be gone
+        let _ = sender.send(Message::Notification(noti));
+    });
+    logging::set_trace(init_params.trace.unwrap_or(TraceValue::Off));
+    let root = workspace_root(&init_params)?;
+    let config = Config::load(&root, init_params.initialization_options.as_ref())?;
+    // Forwarding starts first, so the client's answer to creating the indexing progress
+    // token reaches it while everything else queues up
+    let pending = Arc::new(Mutex::new(HashMap::new()));
+    let messages = forward_messages(conn, Arc::clone(&pending));
+    let mut ctx = index_with_progress(conn, &init_params, &root, config)?;
+    let capabilities = &init_params.capabilities;
+    ctx.pull_diagnostics = capabilities
+        .text_document
+        .as_ref()
+        .is_some_and(|text_document| text_document.diagnostic.is_some());
+    ctx.diagnostic_refresh = ctx.pull_diagnostics
+        && capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostic.as_ref())
+            .and_then(|diagnostic| diagnostic.refresh_support)
+            .unwrap_or(false);
+    ctx.snippet_completion = capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.completion.as_ref())
+        .and_then(|completion| completion.completion_item.as_ref())
+        .and_then(|item| item.snippet_support)
+        .unwrap_or(false);
+    ctx.code_action_resolve = capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.code_action.as_ref())
+        .and_then(|code_action| code_action.resolve_support.as_ref())
+        .is_some_and(|support| support.properties.iter().any(|property| property == "edit"));
+    let watches_files = capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+        .and_then(|watched| watched.dynamic_registration)
+        .unwrap_or(false);
+    if watches_files {
+        conn.watch_notes()?;
+    }
+    let queue = Arc::clone(&ctx.reindex_queue);
+    let server = RwLock::new(Server { conn, ctx });
+    let dispatcher = dispatcher();
+    let workers = std::thread::available_parallelism().map_or(2, NonZeroUsize::get);
+
+    // Read requests run on the pool and queued notes are reindexed on their own thread; write
+    // requests and notifications run here in the order they arrive
+    std::thread::scope(|scope| -> Result<()> {
+        let pool = TaskPool::new(scope, workers);
+        let (conn, server, dispatcher, pending) = (conn, &server, &dispatcher, &*pending);
+        let queue = &*queue;
+        scope.spawn(move || reindex_queued(server, queue));
+        // The reindexing thread finishes the queue and exits when the session ends
+        let _closing = Closing(queue);
+        for msg in messages {
+            match msg {
+                Message::Request(req) => {
+                    if conn.handle_shutdown(&req)? {
+                        break;
+                    }
+                    let token = lock(pending).get(&req.id).cloned().unwrap_or_default();
+                    // Cancelled while still queued
+                    if token.is_cancelled() {
+                        lock(pending).remove(&req.id);
+                        conn.send_err(&req.id, ErrorCode::RequestCanceled, &Cancelled.to_string())?;
+                        continue;
+                    }
+                    if dispatcher.access(&req.method) == Some(Access::Read) {
+                        pool.spawn(priority(&req.method), move || {
+                            let response = with_cancellation(token, || {
+                                dispatcher.dispatch_read(&read(server), &req)
+                            });
+                            lock(pending).remove(&req.id);
+                            if let Err(e) = conn.sender.send(Message::Response(response)) {
+                                tracing::error!("Failed to respond to {}: {e}", req.id);
+                            }
+                        });
+                        continue;
+                    }
+                    // Writes may move or rewrite notes, so they wait for the batch being
+                    // reindexed and the next one waits for them
+                    let _held = queue.hold();
+                    let response =
+                        with_cancellation(token, || dispatcher.dispatch(&mut write(server), &req));
+                    lock(pending).remove(&req.id);
+                    conn.sender.send(Message::Response(response))?;
+                }
+                Message::Response(_) => {}
+                Message::Notification(noti) => {
+                    let method = noti.method.clone();
+                    let _span = tracing::info_span!("notification", %method).entered();
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        dispatch_notification(server, noti)
+                    }));
+                    match result {
+                        Ok(result) => result?,
+                        Err(_) => tracing::error!("Notification {method} panicked"),
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    logging::clear_client();
     Ok(())
 }
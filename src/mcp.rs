@@ -0,0 +1,304 @@
+use crate::command::{self, BackwardLinksRequest, NewNoteRequest};
+use crate::config::Config;
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::paths::{PathStyle, VaultPaths};
+use crate::workspace;
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// A Model Context Protocol server exposing the vault as tools over newline-delimited
+/// JSON-RPC on stdio.
+pub struct McpServer {
+    root: PathBuf,
+    config: Config,
+    index: Index,
+    parser: Arc<Parser>,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl McpServer {
+    /// Open and index the vault at `root`.
+    pub fn new(root: &Path, config: Config) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let (index, parser) = runtime.block_on(workspace::open_vault(root, &config))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            config,
+            index,
+            parser,
+            runtime,
+        })
+    }
+
+    /// Answer messages from `input` until it closes.
+    pub fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => self.handle(&message),
+                Err(e) => Some(error_reply(Value::Null, -32700, &e.to_string())),
+            };
+            if let Some(reply) = reply {
+                writeln!(output, "{reply}")?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The reply to one JSON-RPC message, or `None` for notifications.
+    pub fn handle(&mut self, message: &Value) -> Option<Value> {
+        let id = message.get("id")?.clone();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result: Result<Value> = match message.get("method").and_then(Value::as_str) {
+            Some("initialize") => Ok(json!({
+                "protocolVersion": params
+                    .get("protocolVersion")
+                    .and_then(Value::as_str)
+                    .unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "typst-oxide", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            Some("ping") => Ok(json!({})),
+            Some("tools/list") => Ok(json!({ "tools": tools() })),
+            Some("tools/call") => Ok(self.call_tool(&params)),
+            _ => return Some(error_reply(id, -32601, "Method not found")),
+        };
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => error_reply(id, -32603, &e.to_string()),
+        })
+    }
+
+    /// Tool failures are reported in the result so the model can see and react to them.
+    fn call_tool(&mut self, params: &Value) -> Value {
+        let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+        let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+        match self.run_tool(name, &arguments) {
+            Ok(text) => json!({ "content": [{ "type": "text", "text": text }] }),
+            Err(e) => json!({
+                "content": [{ "type": "text", "text": e.to_string() }],
+                "isError": true,
+            }),
+        }
+    }
+
+    fn run_tool(&mut self, name: &str, arguments: &Value) -> Result<String> {
+        let string = |key: &str| {
+            arguments
+                .get(key)
+                .and_then(Value::as_str)
+                .with_context(|| format!("Missing argument `{key}`"))
+        };
+
+        match name {
+            "search_notes" => {
+                let notes = self.index.search(string("query")?)?;
+                Ok(self.relative_lines(&notes))
+            }
+            "read_note" => {
+                let path = self.note_path(string("path")?)?;
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+            "list_backlinks" => {
+                let request = BackwardLinksRequest {
+                    file_path: self.note_path(string("path")?)?,
+                    context_lines: Some(0),
+                };
+                let response = command::handle_backward_links(&self.index, request)?;
+                Ok(response
+                    .links
+                    .iter()
+                    .map(|link| {
+                        format!(
+                            "{}:{}: {}",
+                            self.relative(&link.source_file),
+                            link.wikilink.line,
+                            link.context.join(" ").trim()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            "create_note" => {
+                let request: NewNoteRequest = serde_json::from_value(arguments.clone())?;
+                let response = command::handle_new_note(
+                    &self.root,
+                    &self.config,
+                    chrono::Local::now().date_naive(),
+                    request,
+                )?;
+                self.runtime.block_on(workspace::index_file(
+                    &self.parser,
+                    &mut self.index,
+                    &response.path,
+                ))?;
+                Ok(format!("Created {}", self.relative(&response.path)))
+            }
+            _ => Err(anyhow!("Unknown tool `{name}`")),
+        }
+    }
+
+    /// Resolve a vault-relative note path (the `.typ` extension is optional).
+    fn note_path(&self, path: &str) -> Result<PathBuf> {
+        let mut path = VaultPaths::new(&self.root, PathStyle::Absolute).resolve(Path::new(path))?;
+        if path.extension().is_none() {
+            path.set_extension("typ");
+        }
+        Ok(path)
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn relative_lines(&self, paths: &[PathBuf]) -> String {
+        paths
+            .iter()
+            .map(|path| self.relative(path))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn tools() -> Value {
+    let path = json!({ "type": "string", "description": "Vault-relative note path" });
+    json!([
+        {
+            "name": "search_notes",
+            "description": "Find notes whose path, title, alias, or tags contain the query",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "query": { "type": "string" } },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "read_note",
+            "description": "Return the Typst source of a note",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "path": path },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "list_backlinks",
+            "description": "List the notes linking to a note, with the linking line",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "path": path },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "create_note",
+            "description": "Create a note from a template",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "folder": { "type": "string", "description": "Vault-relative folder" },
+                    "template": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                },
+                "required": ["title"],
+            },
+        },
+    ])
+}
+
+fn error_reply(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IndexLocation;
+    use tempfile::tempdir;
+
+    fn call(server: &mut McpServer, name: &str, arguments: Value) -> Value {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        });
+        server.handle(&message).unwrap()["result"].clone()
+    }
+
+    #[test]
+    fn test_tools() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("target.typ"), "= Target\n").unwrap();
+        std::fs::write(root.join("source.typ"), "see [[target]]\n").unwrap();
+        let config = Config {
+            index_location: IndexLocation::Vault,
+            ..Config::default()
+        };
+        let mut server = McpServer::new(root, config).unwrap();
+
+        let input = "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/list\"}\n\
+                     {\"jsonrpc\":\"2.0\",\"method\":\"notifications/initialized\"}\n";
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output).unwrap();
+        let reply: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(reply["result"]["tools"].as_array().unwrap().len(), 4);
+
+        let text = |result: Value| result["content"][0]["text"].as_str().unwrap().to_string();
+        assert_eq!(
+            text(call(
+                &mut server,
+                "search_notes",
+                json!({ "query": "targ" })
+            )),
+            "target.typ"
+        );
+        assert_eq!(
+            text(call(&mut server, "read_note", json!({ "path": "target" }))),
+            "= Target\n"
+        );
+        assert_eq!(
+            text(call(
+                &mut server,
+                "list_backlinks",
+                json!({ "path": "target.typ" })
+            )),
+            "source.typ:1: see [[target]]"
+        );
+        assert_eq!(
+            text(call(
+                &mut server,
+                "create_note",
+                json!({ "title": "New", "tags": ["x"] })
+            )),
+            "Created New.typ"
+        );
+        assert_eq!(
+            text(call(&mut server, "search_notes", json!({ "query": "new" }))),
+            "New.typ"
+        );
+
+        let outside = call(&mut server, "read_note", json!({ "path": "../secret" }));
+        assert_eq!(outside["isError"], true);
+    }
+}
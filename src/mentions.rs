@@ -0,0 +1,210 @@
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::models::Wikilink;
+use crate::parser::wikilinks::WikilinkSyntax;
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use ts_rs::TS;
+
+// Shorter titles and aliases match too much ordinary prose to be useful
+const MIN_NAME_LENGTH: usize = 3;
+
+/// A plain-text occurrence of another note's title or alias.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct UnlinkedMention {
+    pub file: PathBuf,
+    /// 1-based line and column of the first character
+    pub line: usize,
+    pub column: usize,
+    /// Length in characters
+    pub length: usize,
+    pub text: String,
+    /// The mentioned note
+    pub target: PathBuf,
+    /// The wikilink target that links to the mentioned note
+    pub link_target: String,
+}
+
+impl UnlinkedMention {
+    /// Wikilink source replacing the mention, keeping its text as the alias if it differs.
+    pub fn to_wikilink(&self, syntax: &WikilinkSyntax) -> String {
+        syntax.format(&Wikilink {
+            target: self.link_target.clone(),
+            alias: (self.text != self.link_target).then(|| self.text.clone()),
+            label: None,
+            block: None,
+            line: self.line,
+            column: self.column,
+        })
+    }
+}
+
+/// Finds note titles and aliases in text with a single Aho–Corasick pass.
+pub struct MentionFinder {
+    automaton: Option<AhoCorasick>,
+    /// The note each automaton pattern names, by pattern index
+    notes: Vec<PathBuf>,
+}
+
+impl MentionFinder {
+    /// The finder for the index as it is now, built again only once a note has changed. Each
+    /// thread keeps its own, so request handlers running in parallel never wait on each other.
+    pub fn cached(index: &Index) -> Result<Rc<Self>> {
+        thread_local! {
+            static CACHED: RefCell<Option<(u64, Rc<MentionFinder>)>> = const { RefCell::new(None) };
+        }
+        let generation = index.generation();
+        if let Some(finder) = CACHED.with_borrow(|cached| match cached {
+            Some((cached, finder)) if *cached == generation => Some(Rc::clone(finder)),
+            _ => None,
+        }) {
+            return Ok(finder);
+        }
+
+        let finder = Rc::new(Self::new(index)?);
+        CACHED.set(Some((generation, Rc::clone(&finder))));
+        Ok(finder)
+    }
+
+    pub fn new(index: &Index) -> Result<Self> {
+        let mut names = Vec::new();
+        let mut notes = Vec::new();
+        for (path, key, value) in index.get_all_metadata()? {
+            let name = value.trim();
+            if matches!(key.as_str(), "title" | "alias") && name.chars().count() >= MIN_NAME_LENGTH
+            {
+                names.push(name.to_string());
+                notes.push(index.root().join(path));
+            }
+        }
+
+        let automaton = if names.is_empty() {
+            None
+        } else {
+            Some(
+                AhoCorasickBuilder::new()
+                    .ascii_case_insensitive(true)
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(&names)?,
+            )
+        };
+        Ok(Self { automaton, notes })
+    }
+
+    /// Mentions in `content` of notes other than `file` that aren't already inside a wikilink.
+    pub fn find(&self, parser: &Parser, file: &Path, content: &str) -> Vec<UnlinkedMention> {
+        let Some(automaton) = &self.automaton else {
+            return Vec::new();
+        };
+        let spans = parser.wikilink_spans(content);
+
+        let mut mentions = Vec::new();
+        for (line_idx, line) in content.lines().enumerate() {
+            let linked: Vec<_> = spans
+                .iter()
+                .filter(|(wikilink, _)| wikilink.line == line_idx + 1)
+                .map(|(_, span)| span)
+                .collect();
+
+            for found in automaton.find_iter(line) {
+                let target = &self.notes[found.pattern().as_usize()];
+                if target == file || !is_word(line, found.start(), found.end()) {
+                    continue;
+                }
+                let column = line[..found.start()].chars().count();
+                let text = &line[found.start()..found.end()];
+                let length = text.chars().count();
+                if linked
+                    .iter()
+                    .any(|span| span.start < column + length && column < span.end)
+                {
+                    continue;
+                }
+
+                mentions.push(UnlinkedMention {
+                    file: file.to_path_buf(),
+                    line: line_idx + 1,
+                    column: column + 1,
+                    length,
+                    text: text.to_string(),
+                    target: target.clone(),
+                    link_target: target
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+        mentions
+    }
+}
+
+/// Whether `line[start..end]` is not part of a longer word.
+fn is_word(line: &str, start: usize, end: usize) -> bool {
+    let before = line[..start].chars().next_back();
+    let after = line[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note_with, vault};
+    use crate::parser::models::ParsedFile;
+
+    #[test]
+    fn test_find_mentions() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+
+        let named = |parsed: &mut ParsedFile| {
+            parsed.metadata.title = Some("Machine Learning".to_string());
+            parsed.metadata.alias = vec!["ML".to_string(), "Machine".to_string()];
+        };
+        let target = add_note_with(&mut index, "ml.typ", "", named);
+
+        let file = root.join("note.typ");
+        let content = "machine learning is fun.\nSee [[ml|Machine Learning]], not Machinery.\n";
+        let mentions = MentionFinder::new(&index)
+            .unwrap()
+            .find(&parser, &file, content);
+
+        assert_eq!(mentions.len(), 1);
+        let mention = &mentions[0];
+        assert_eq!((mention.line, mention.column, mention.length), (1, 1, 16));
+        assert_eq!(mention.target, target);
+        assert_eq!(
+            mention.to_wikilink(&WikilinkSyntax::default()),
+            "[[ml|machine learning]]"
+        );
+
+        let own = MentionFinder::new(&index)
+            .unwrap()
+            .find(&parser, &target, content);
+        assert!(own.is_empty());
+
+        let finder = MentionFinder::cached(&index).unwrap();
+        assert!(Rc::ptr_eq(&finder, &MentionFinder::cached(&index).unwrap()));
+        add_note_with(&mut index, "ml.typ", "", named);
+        assert!(!Rc::ptr_eq(
+            &finder,
+            &MentionFinder::cached(&index).unwrap()
+        ));
+
+        let mention = UnlinkedMention {
+            text: "a|b".to_string(),
+            link_target: "x]]".to_string(),
+            ..mention.clone()
+        };
+        assert_eq!(
+            mention.to_wikilink(&WikilinkSyntax::default()),
+            "[[x\\]\\]|a\\|b]]"
+        );
+    }
+}
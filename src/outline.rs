@@ -0,0 +1,115 @@
+use crate::parser::Parser;
+use crate::parser::headings::{heading_level, heading_text};
+use crate::parser::names::slug;
+use crate::parser::stats::count_words;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use ts_rs::TS;
+
+/// A heading and the section it opens, up to the next heading of any level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OutlineSection {
+    pub title: String,
+    pub level: usize,
+    /// The label links can use for the heading, e.g. `über-uns` for `Über uns`
+    pub slug: String,
+    /// 1-based line of the heading
+    pub line: usize,
+    /// Explicit labels defined in the heading or the section's own text
+    pub labels: Vec<String>,
+    /// Words in the section's own text, not counting subsections
+    pub words: usize,
+    /// Wikilinks in the heading or the section's own text
+    pub links: usize,
+    pub subsections: Vec<OutlineSection>,
+}
+
+/// The heading tree of `content`. Text before the first heading belongs to no section.
+pub fn outline(parser: &Parser, path: &Path, content: &str) -> Result<Vec<OutlineSection>> {
+    let parsed = parser.parse_content(content, path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    let headings: Vec<usize> = (0..lines.len())
+        .filter(|&i| heading_level(lines[i]).is_some())
+        .collect();
+
+    let mut sections = Vec::new();
+    for (n, &start) in headings.iter().enumerate() {
+        let end = headings.get(n + 1).copied().unwrap_or(lines.len());
+        // Parsed positions are 1-based
+        let in_section = |line: usize| (start + 1..end + 1).contains(&line);
+        let title = heading_text(lines[start]).unwrap_or_default();
+        sections.push(OutlineSection {
+            slug: slug(&title),
+            title,
+            level: heading_level(lines[start]).unwrap_or(1),
+            line: start + 1,
+            labels: parsed
+                .labels
+                .iter()
+                .filter(|label| in_section(label.line))
+                .map(|label| label.name.clone())
+                .collect(),
+            words: lines[start + 1..end].iter().map(|l| count_words(l)).sum(),
+            links: parsed
+                .wikilinks
+                .iter()
+                .filter(|link| in_section(link.line))
+                .count(),
+            subsections: Vec::new(),
+        });
+    }
+    Ok(nest(sections))
+}
+
+// Each section becomes a subsection of the nearest section above it with a lower level
+fn nest(sections: Vec<OutlineSection>) -> Vec<OutlineSection> {
+    let mut roots = Vec::new();
+    let mut open: Vec<OutlineSection> = Vec::new();
+    let close = |open: &mut Vec<OutlineSection>, roots: &mut Vec<OutlineSection>| {
+        let Some(section) = open.pop() else {
+            return;
+        };
+        match open.last_mut() {
+            Some(parent) => parent.subsections.push(section),
+            None => roots.push(section),
+        }
+    };
+    for section in sections {
+        while open.last().is_some_and(|last| last.level >= section.level) {
+            close(&mut open, &mut roots);
+        }
+        open.push(section);
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outline() {
+        let parser = Parser::new().unwrap();
+        let content = "#meta(title: \"Note\")\nIntro words\n= Über uns <about>\nSee [[team]] and [[history]].\n== Team\nAlice and Bob -\n=== Alice\n= Contact\n";
+        let sections = outline(&parser, Path::new("note.typ"), content).unwrap();
+
+        assert_eq!(sections.len(), 2);
+        let about = &sections[0];
+        assert_eq!(
+            (about.title.as_str(), about.slug.as_str(), about.line),
+            ("Über uns", "über-uns", 3)
+        );
+        assert_eq!(about.labels, vec!["about"]);
+        assert_eq!((about.words, about.links), (4, 2));
+        let team = &about.subsections[0];
+        assert_eq!((team.level, team.words, team.links), (2, 3, 0));
+        assert_eq!(team.subsections[0].title, "Alice");
+        assert_eq!(sections[1].title, "Contact");
+        assert!(sections[1].subsections.is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::parser::models::{Attachment, Wikilink};
+use crate::parser::positions::LineIndex;
+use anyhow::Result;
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Extensions of files that notes may link to or embed as attachments.
+pub const ATTACHMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "mp3", "mp4", "wav", "csv", "bib",
+];
+
+// Typst functions whose first argument is a path relative to the calling file
+const ASSET_FUNCTIONS: &str = "image|read|csv|json|yaml|toml|xml|bibliography";
+
+pub struct AttachmentParser {
+    call_regex: Regex,
+    call_prefix_regex: Regex,
+    string_regex: Regex,
+}
+
+impl AttachmentParser {
+    pub fn new() -> Result<Self> {
+        // Matches asset calls with a literal path, with or without `#`:
+        // `#image("plot.png")`, `#figure(image("plot.png"))`, `#bibliography("refs.bib")`
+        let call_regex = Regex::new(&format!(
+            r#"(?:^|[^\w.-])(?:{ASSET_FUNCTIONS})\(\s*"([^"]+)""#
+        ))?;
+        // An unterminated path argument ending at the cursor
+        let call_prefix_regex = Regex::new(&format!(
+            r#"(?:^|[^\w.-])(?:{ASSET_FUNCTIONS})\(\s*"([^"]*)$"#
+        ))?;
+        // Any other string literal naming a file: `let logo = "assets/logo.svg"`
+        let string_regex = Regex::new(r#""([^"\s:]+\.[A-Za-z0-9]+)""#)?;
+
+        Ok(Self {
+            call_regex,
+            call_prefix_regex,
+            string_regex,
+        })
+    }
+
+    /// Files used by asset calls and by string literals with an attachment extension, plus
+    /// wikilinks that name a non-Typst file.
+    pub fn parse_attachments(&self, content: &str, wikilinks: &[Wikilink]) -> Vec<Attachment> {
+        let mut attachments = Vec::new();
+
+        for (line_idx, line) in content.lines().enumerate() {
+            if line.trim_start().starts_with("//") {
+                continue;
+            }
+            let columns = LineIndex::new(line);
+            let column = |offset: usize| columns.column(offset);
+
+            let mut calls = HashSet::new();
+            for cap in self.call_regex.captures_iter(line) {
+                let path = cap.get(1).unwrap();
+                calls.insert(path.start());
+                attachments.push(Attachment {
+                    target: path.as_str().to_string(),
+                    embedded: true,
+                    line: line_idx + 1,
+                    column: column(path.start() - 1),
+                });
+            }
+
+            for cap in self.string_regex.captures_iter(line) {
+                let path = cap.get(1).unwrap();
+                if calls.contains(&path.start()) || !is_attachment_path(path.as_str()) {
+                    continue;
+                }
+                attachments.push(Attachment {
+                    target: path.as_str().to_string(),
+                    embedded: true,
+                    line: line_idx + 1,
+                    column: column(path.start() - 1),
+                });
+            }
+        }
+
+        for wikilink in wikilinks {
+            if Path::new(&wikilink.target)
+                .extension()
+                .is_some_and(|ext| ext != "typ")
+            {
+                attachments.push(Attachment {
+                    target: wikilink.target.clone(),
+                    embedded: false,
+                    line: wikilink.line,
+                    column: wikilink.column,
+                });
+            }
+        }
+
+        attachments.sort_by_key(|attachment| (attachment.line, attachment.column));
+        attachments
+    }
+
+    /// The start (in characters) and text of the path argument being typed at `character`
+    /// inside an asset call such as `#image("`.
+    pub fn asset_prefix_at(&self, line: &str, character: usize) -> Option<(usize, String)> {
+        let before: String = line.chars().take(character).collect();
+        let path = self.call_prefix_regex.captures(&before)?.get(1)?;
+        Some((
+            before[..path.start()].chars().count(),
+            path.as_str().to_string(),
+        ))
+    }
+}
+
+/// Whether `path` has one of the `ATTACHMENT_EXTENSIONS`, ignoring case.
+pub fn is_attachment_path(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| {
+        ATTACHMENT_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attachments() {
+        let parser = AttachmentParser::new().unwrap();
+        let content = "See [[paper.pdf]]\n#figure(image(\"img/plot.png\", width: 50%))\n\
+                       #let logo = \"/assets/logo.SVG\"\n#bibliography(\"refs.bib\")\n\
+                       // #image(\"commented.png\")\n#link(\"https://example.com/a.pdf\")[x]";
+        let wikilinks = vec![
+            Wikilink {
+                target: "paper.pdf".to_string(),
+                alias: None,
+                label: None,
+                block: None,
+                line: 1,
+                column: 5,
+            },
+            Wikilink {
+                target: "other".to_string(),
+                alias: None,
+                label: None,
+                block: None,
+                line: 1,
+                column: 20,
+            },
+        ];
+
+        let attachments = parser.parse_attachments(content, &wikilinks);
+
+        let targets: Vec<&str> = attachments.iter().map(|a| a.target.as_str()).collect();
+        assert_eq!(
+            targets,
+            vec!["paper.pdf", "img/plot.png", "/assets/logo.SVG", "refs.bib"]
+        );
+        assert!(!attachments[0].embedded);
+        assert!(attachments[1].embedded);
+        assert_eq!((attachments[1].line, attachments[1].column), (2, 15));
+    }
+
+    #[test]
+    fn test_asset_prefix_at() {
+        let parser = AttachmentParser::new().unwrap();
+        let line = "#figure(image(\"img/pl";
+
+        assert_eq!(
+            parser.asset_prefix_at(line, 22),
+            Some((15, "img/pl".to_string()))
+        );
+        assert_eq!(parser.asset_prefix_at(line, 13), None);
+        assert_eq!(parser.asset_prefix_at("#image(\"a.png\") x", 18), None);
+        assert_eq!(parser.asset_prefix_at("#myimage(\"", 10), None);
+    }
+}
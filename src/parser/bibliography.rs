@@ -0,0 +1,312 @@
+use crate::parser::models::BibEntry;
+use anyhow::Result;
+use regex::Regex;
+use std::path::Path;
+
+pub struct BibliographyParser {
+    call_regex: Regex,
+    string_regex: Regex,
+}
+
+impl BibliographyParser {
+    pub fn new() -> Result<Self> {
+        // Matches `#bibliography("refs.bib")` and `#bibliography(("a.bib", "b.yml"))`
+        let call_regex = Regex::new(r#"(?:^|[^\w.-])bibliography\(\s*("[^"]+"|\([^)]*\))"#)?;
+        let string_regex = Regex::new(r#""([^"]+)""#)?;
+
+        Ok(Self {
+            call_regex,
+            string_regex,
+        })
+    }
+
+    /// The files passed to `#bibliography(...)` in `content`, as written.
+    pub fn parse_bibliographies(&self, content: &str) -> Vec<String> {
+        content
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .flat_map(|line| self.call_regex.captures_iter(line))
+            .flat_map(|cap| {
+                let files = cap.get(1).unwrap().as_str();
+                self.string_regex
+                    .captures_iter(files)
+                    .map(|cap| cap[1].to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// The entries of the bibliography file at `path`: Hayagriva for `.yml` and `.yaml`, BibTeX
+/// otherwise.
+pub fn parse_bibliography(path: &Path, content: &str) -> Vec<BibEntry> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yml" | "yaml") => parse_hayagriva(content),
+        _ => parse_bibtex(content),
+    }
+}
+
+/// Entries of a BibTeX or BibLaTeX file, skipping `@string`, `@preamble` and `@comment`.
+pub fn parse_bibtex(content: &str) -> Vec<BibEntry> {
+    let mut entries = Vec::new();
+    let mut rest = content;
+    while let Some(at) = rest.find('@') {
+        rest = &rest[at + 1..];
+        let Some(open) = rest.find(['{', '(']) else {
+            break;
+        };
+        let kind = rest[..open].trim().to_lowercase();
+        if kind.is_empty() || !kind.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        let body_start = open + 1;
+        let Some(body_end) = closing_brace(&rest[body_start..]).map(|end| body_start + end) else {
+            break;
+        };
+        let body = &rest[body_start..body_end];
+        rest = &rest[body_end..];
+        if matches!(kind.as_str(), "string" | "preamble" | "comment") {
+            continue;
+        }
+
+        let mut fields = split_fields(body).into_iter();
+        let Some(key) = fields.next().map(str::trim).filter(|key| !key.is_empty()) else {
+            continue;
+        };
+        let mut entry = BibEntry {
+            key: key.to_string(),
+            kind,
+            title: None,
+            authors: Vec::new(),
+            year: None,
+        };
+        for field in fields {
+            let Some((name, value)) = field.split_once('=') else {
+                continue;
+            };
+            let value = bibtex_value(value);
+            match name.trim().to_lowercase().as_str() {
+                "title" => entry.title = Some(value),
+                "author" => {
+                    entry.authors = value.split(" and ").map(|a| a.trim().to_string()).collect()
+                }
+                "year" => entry.year = Some(value),
+                "date" if entry.year.is_none() => {
+                    entry.year = Some(value.chars().take(4).collect())
+                }
+                _ => {}
+            }
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+// The offset of the `}` or `)` closing an entry body that starts `text`
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' | '(' => depth += 1,
+            '}' | ')' if depth == 0 => return Some(i),
+            '}' | ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+// Top-level comma-separated parts of an entry body: its key, then `name = value` fields
+fn split_fields(body: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let (mut depth, mut in_quotes, mut start) = (0usize, false, 0);
+    for (i, c) in body.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            '"' if depth == 0 => in_quotes = !in_quotes,
+            ',' if depth == 0 && !in_quotes => {
+                fields.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(&body[start..]);
+    fields.retain(|field| !field.trim().is_empty());
+    fields
+}
+
+// A field value without its braces and quotes, with whitespace collapsed
+fn bibtex_value(value: &str) -> String {
+    let value: String = value
+        .trim()
+        .split('#')
+        .map(|part| part.trim().trim_matches('"'))
+        .collect::<Vec<_>>()
+        .concat()
+        .chars()
+        .filter(|c| !matches!(c, '{' | '}'))
+        .collect();
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Entries of a Hayagriva YAML file: top-level keys whose fields include a `type`.
+pub fn parse_hayagriva(content: &str) -> Vec<BibEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<BibEntry> = None;
+    // The field whose `- item` lines are being read, and the indentation of its name
+    let mut list_field: Option<(String, usize)> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+
+        if indent == 0 {
+            entries.extend(current.take().filter(|entry| !entry.kind.is_empty()));
+            list_field = None;
+            if let Some((key, _)) = trimmed.split_once(':') {
+                current = Some(BibEntry {
+                    key: yaml_scalar(key),
+                    kind: String::new(),
+                    title: None,
+                    authors: Vec::new(),
+                    year: None,
+                });
+            }
+            continue;
+        }
+        let Some(entry) = current.as_mut() else {
+            continue;
+        };
+
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            if list_field
+                .as_ref()
+                .is_some_and(|(field, _)| field == "author")
+            {
+                entry.authors.push(yaml_scalar(item));
+            }
+            continue;
+        }
+        let Some((name, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if list_field
+            .as_ref()
+            .is_some_and(|(_, field_indent)| indent <= *field_indent)
+        {
+            list_field = None;
+        }
+        match (name, list_field.as_ref()) {
+            // `title:` with nested `value:` and the like
+            ("value", Some((field, _))) if field == "title" => {
+                entry.title = Some(yaml_scalar(value));
+            }
+            (_, Some(_)) => {}
+            ("type", None) => entry.kind = yaml_scalar(value).to_lowercase(),
+            ("title", None) if !value.is_empty() => entry.title = Some(yaml_scalar(value)),
+            ("author", None) if value.starts_with('[') => {
+                entry.authors = value
+                    .trim_matches(['[', ']'])
+                    .split(',')
+                    .map(yaml_scalar)
+                    .filter(|author| !author.is_empty())
+                    .collect();
+            }
+            ("author", None) if !value.is_empty() => entry.authors = vec![yaml_scalar(value)],
+            ("date", None) => entry.year = Some(yaml_scalar(value).chars().take(4).collect()),
+            (field, None) if value.is_empty() => list_field = Some((field.to_string(), indent)),
+            _ => {}
+        }
+    }
+    entries.extend(current.filter(|entry| !entry.kind.is_empty()));
+    entries
+}
+
+fn yaml_scalar(value: &str) -> String {
+    value.trim().trim_matches(['"', '\'']).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bibliographies() {
+        let parser = BibliographyParser::new().unwrap();
+        let content = "#bibliography(\"refs.bib\")\n\
+                       #bibliography((\"a.bib\", \"/b.yml\"), style: \"apa\")\n\
+                       // #bibliography(\"old.bib\")";
+        assert_eq!(
+            parser.parse_bibliographies(content),
+            vec!["refs.bib", "a.bib", "/b.yml"]
+        );
+    }
+
+    #[test]
+    fn test_parse_bibtex() {
+        let content = "@string{acm = \"ACM\"}\n\
+                       @Article{knuth84,\n  author = {Donald E. Knuth},\n  \
+                       title = {Literate {P}rogramming},\n  year = 1984,\n}\n\
+                       % a comment\n\
+                       @book(lamport, title = \"LaTeX\", author = \"Leslie Lamport and Ada Lovelace\",\n  \
+                       date = {1986-01-01})";
+        assert_eq!(
+            parse_bibtex(content),
+            vec![
+                BibEntry {
+                    key: "knuth84".to_string(),
+                    kind: "article".to_string(),
+                    title: Some("Literate Programming".to_string()),
+                    authors: vec!["Donald E. Knuth".to_string()],
+                    year: Some("1984".to_string()),
+                },
+                BibEntry {
+                    key: "lamport".to_string(),
+                    kind: "book".to_string(),
+                    title: Some("LaTeX".to_string()),
+                    authors: vec!["Leslie Lamport".to_string(), "Ada Lovelace".to_string()],
+                    year: Some("1986".to_string()),
+                },
+            ]
+        );
+        assert_eq!(
+            parse_bibtex(content)[1].reference(),
+            "Leslie Lamport and Ada Lovelace (1986). LaTeX."
+        );
+    }
+
+    #[test]
+    fn test_parse_hayagriva() {
+        let content = "# References\n\
+                       harry:\n  type: Book\n  title: Harry Potter\n  author:\n    - Rowling, J. K.\n  \
+                       date: 1997-06-26\n\
+                       electronic:\n  type: Web\n  title:\n    value: \"Ye old web\"\n  \
+                       author: [Alice, Bob]\n\
+                       settings:\n  color: blue\n";
+        assert_eq!(
+            parse_hayagriva(content),
+            vec![
+                BibEntry {
+                    key: "harry".to_string(),
+                    kind: "book".to_string(),
+                    title: Some("Harry Potter".to_string()),
+                    authors: vec!["Rowling, J. K.".to_string()],
+                    year: Some("1997".to_string()),
+                },
+                BibEntry {
+                    key: "electronic".to_string(),
+                    kind: "web".to_string(),
+                    title: Some("Ye old web".to_string()),
+                    authors: vec!["Alice".to_string(), "Bob".to_string()],
+                    year: None,
+                },
+            ]
+        );
+    }
+}
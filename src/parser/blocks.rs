@@ -0,0 +1,66 @@
+use crate::parser::models::Block;
+use anyhow::Result;
+use regex::Regex;
+
+pub struct BlockParser {
+    block_regex: Regex,
+}
+
+impl BlockParser {
+    pub fn new() -> Result<Self> {
+        // Matches block markers at the end of a line: `Some paragraph ^block-id`
+        let block_regex = Regex::new(r"(?:^|\s)\^([a-zA-Z0-9-]+)\s*$")?;
+
+        Ok(Self { block_regex })
+    }
+
+    pub fn parse_blocks(&self, content: &str) -> Vec<Block> {
+        let mut blocks = Vec::new();
+
+        for (line_idx, line) in content.lines().enumerate() {
+            if let Some(cap) = self.block_regex.captures(line) {
+                let id = cap.get(1).unwrap();
+                // Point at the `^` marker rather than the preceding whitespace
+                let column = line[..id.start() - 1].chars().count() + 1;
+
+                blocks.push(Block {
+                    id: id.as_str().to_string(),
+                    line: line_idx + 1,
+                    column,
+                });
+            }
+        }
+
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block_marker() {
+        let parser = BlockParser::new().unwrap();
+        let content = "First line\nAn important claim. ^claim-1\nLast line";
+
+        let blocks = parser.parse_blocks(content);
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].id, "claim-1");
+        assert_eq!(blocks[0].line, 2);
+        assert_eq!(blocks[0].column, 21);
+    }
+
+    #[test]
+    fn test_block_marker_must_end_line() {
+        let parser = BlockParser::new().unwrap();
+        let content = "x^2 is not a block\nneither is ^this one\n^standalone";
+
+        let blocks = parser.parse_blocks(content);
+
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].id, "standalone");
+        assert_eq!(blocks[0].column, 1);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::parser::models::Conflict;
+
+/// Find git conflict blocks. A block needs its opening, separator and closing markers in
+/// order; stray markers are left alone.
+pub fn find_conflicts(content: &str) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    let mut open: Option<Conflict> = None;
+
+    for (line_idx, line) in content.lines().enumerate() {
+        let line_number = line_idx + 1;
+        if is_marker(line, '<') {
+            open = Some(Conflict {
+                line: line_number,
+                base_line: None,
+                separator_line: 0,
+                end_line: 0,
+            });
+            continue;
+        }
+        let Some(conflict) = open.as_mut() else {
+            continue;
+        };
+        if is_marker(line, '|') && conflict.separator_line == 0 {
+            conflict.base_line.get_or_insert(line_number);
+        } else if line.trim_end() == "=======" && conflict.separator_line == 0 {
+            conflict.separator_line = line_number;
+        } else if is_marker(line, '>') && conflict.separator_line != 0 {
+            conflict.end_line = line_number;
+            conflicts.extend(open.take());
+        }
+    }
+
+    conflicts
+}
+
+// Seven marker characters, then the end of the line or a space before the branch name
+fn is_marker(line: &str, marker: char) -> bool {
+    let rest = line.trim_start_matches(marker);
+    line.len() - rest.len() == 7 && (rest.is_empty() || rest.starts_with(' '))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_conflicts() {
+        let content = "= Note\n\
+                       <<<<<<< HEAD\n\
+                       ours\n\
+                       ||||||| base\n\
+                       base\n\
+                       =======\n\
+                       theirs\n\
+                       >>>>>>> feature\n\
+                       ======= Deep heading\n\
+                       <<<<<<<< not a marker\n\
+                       <<<<<<<\n\
+                       a\n\
+                       =======\n\
+                       >>>>>>>\n\
+                       >>>>>>> stray";
+
+        assert_eq!(
+            find_conflicts(content),
+            vec![
+                Conflict {
+                    line: 2,
+                    base_line: Some(4),
+                    separator_line: 6,
+                    end_line: 8,
+                },
+                Conflict {
+                    line: 11,
+                    base_line: None,
+                    separator_line: 13,
+                    end_line: 14,
+                },
+            ]
+        );
+        assert!(find_conflicts("<<<<<<< HEAD\nunfinished\n=======").is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use crate::parser::models::{Figure, FigureKind, Label, LabelKind};
+use crate::parser::positions::LineIndex;
+use anyhow::Result;
+use regex::Regex;
+use std::collections::HashMap;
+
+pub struct FigureParser {
+    label_regex: Regex,
+}
+
+impl FigureParser {
+    pub fn new() -> Result<Self> {
+        // Matches a label right after a figure's closing parenthesis: `) <fig-plot>`
+        let label_regex = Regex::new(r"^[ \t]*<([\w:.-]+)>")?;
+
+        Ok(Self { label_regex })
+    }
+
+    /// Every `#figure(...)` call in `content` with its kind, caption and label, and every
+    /// equation that one of `labels` names, in document order.
+    pub fn parse_figures(&self, content: &str, labels: &[Label]) -> Vec<Figure> {
+        let mut figures = Vec::new();
+
+        let lines = LineIndex::new(content);
+        // Where each `(` scanned so far closes; a scan from one figure covers those nested in it
+        let mut closes = HashMap::new();
+        let mut scanned = 0;
+        for (start, _) in content.match_indices("#figure(") {
+            let open = start + "#figure".len();
+            if open >= scanned {
+                scanned = scan_parens(content, open, &mut closes);
+            }
+            let Some(&close) = closes.get(&open) else {
+                continue;
+            };
+            let args_start = open + 1;
+
+            let (mut body, mut kind, mut caption) = (None, None, None);
+            for arg in split_args(&content[args_start..close]) {
+                match arg.split_once(':') {
+                    Some((name, value)) if is_ident(name.trim()) => match name.trim() {
+                        "caption" => caption = caption_text(value),
+                        "kind" => kind = Some(kind_argument(value.trim())),
+                        _ => {}
+                    },
+                    _ => body = body.or(Some(arg)),
+                }
+            }
+
+            let (line, column) = lines.position(start);
+            figures.push(Figure {
+                kind: kind.unwrap_or_else(|| body.map_or(FigureKind::Figure, body_kind)),
+                caption,
+                label: self
+                    .label_regex
+                    .captures(&content[close + 1..])
+                    .map(|cap| cap[1].to_string()),
+                line,
+                column,
+            });
+        }
+
+        for label in labels
+            .iter()
+            .filter(|label| label.kind == LabelKind::Equation)
+        {
+            let (line, column) = label.target();
+            figures.push(Figure {
+                kind: FigureKind::Equation,
+                caption: None,
+                label: Some(label.name.clone()),
+                line,
+                column,
+            });
+        }
+
+        figures.sort_by_key(|figure| (figure.line, figure.column));
+        figures
+    }
+}
+
+// Match the brackets of the argument list opening at byte `open` of `content`, skipping the
+// strings of code (but not the quotes of content blocks), and record where each `(` among them
+// closes in `closes`. Returns the offset the scan stopped at: past the list's `)`, or at a
+// mismatched bracket or the end of `content`
+fn scan_parens(content: &str, open: usize, closes: &mut HashMap<usize, usize>) -> usize {
+    // The closing bracket expected for each open one, with the offset of the `(`
+    let mut stack = vec![(')', open)];
+    let mut chars = content[open + 1..]
+        .char_indices()
+        .map(|(i, c)| (open + 1 + i, c));
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if stack.last().is_some_and(|(closer, _)| *closer == ')') => {
+                let mut escaped = false;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => break,
+                        _ => escaped = false,
+                    }
+                }
+            }
+            '(' => stack.push((')', i)),
+            '[' => stack.push((']', i)),
+            ')' | ']' => {
+                match stack.pop() {
+                    Some((closer, at)) if closer == c => {
+                        if c == ')' {
+                            closes.insert(at, i);
+                        }
+                    }
+                    _ => return i,
+                }
+                if stack.is_empty() {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    content.len()
+}
+
+// Top-level arguments of an argument list, trimmed
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = args;
+    while !rest.trim().is_empty() {
+        let end = next_comma(rest).unwrap_or(rest.len());
+        parts.push(rest[..end].trim());
+        rest = rest.get(end + 1..).unwrap_or_default();
+    }
+    parts
+}
+
+fn next_comma(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => in_string = false,
+                _ => escaped = false,
+            }
+            continue;
+        }
+        match c {
+            '"' if depth == 0 => in_string = true,
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_ident(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn kind_argument(value: &str) -> FigureKind {
+    match value {
+        "image" => FigureKind::Image,
+        "table" => FigureKind::Table,
+        "raw" => FigureKind::Raw,
+        _ => FigureKind::Figure,
+    }
+}
+
+// The kind of figure whose body is `body`, from the call or markup it starts with
+fn body_kind(body: &str) -> FigureKind {
+    let body = body.trim_start_matches(['[', '#', ' ', '\t', '\n', '\r']);
+    if body.starts_with("image(") {
+        FigureKind::Image
+    } else if body.starts_with("table(") {
+        FigureKind::Table
+    } else if body.starts_with("raw(") || body.starts_with('`') {
+        FigureKind::Raw
+    } else {
+        FigureKind::Figure
+    }
+}
+
+// A `[content]` or `"string"` caption, with its whitespace collapsed
+fn caption_text(value: &str) -> Option<String> {
+    let value = value.trim();
+    let text = if let Some(content) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        content
+    } else if let Some(string) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        string
+    } else {
+        // `figure.caption(...)[...]` and the like: the last content block
+        let open = value.rfind('[')?;
+        value[open + 1..].strip_suffix(']')?
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::labels::LabelParser;
+
+    #[test]
+    fn test_find_figures() {
+        let content = "Intro\n\
+                       #figure(\n  image(\"a.png\"),\n  caption: [A *bold*\n  plot (left), see \"x\"],\n) <fig-plot>\n\
+                       #figure(table(columns: 2)[a][b], caption: \"Results, in full\")\n\
+                       $ E = m c^2 $ <eq-energy>\n\
+                       #figure([```rust\nfn main() {}\n```], kind: raw)";
+        let labels = LabelParser::new().unwrap().parse_labels(content);
+        let figures = FigureParser::new().unwrap().parse_figures(content, &labels);
+        assert_eq!(
+            figures,
+            vec![
+                Figure {
+                    kind: FigureKind::Image,
+                    caption: Some("A *bold* plot (left), see \"x\"".to_string()),
+                    label: Some("fig-plot".to_string()),
+                    line: 2,
+                    column: 1,
+                },
+                Figure {
+                    kind: FigureKind::Table,
+                    caption: Some("Results, in full".to_string()),
+                    label: None,
+                    line: 7,
+                    column: 1,
+                },
+                Figure {
+                    kind: FigureKind::Equation,
+                    caption: None,
+                    label: Some("eq-energy".to_string()),
+                    line: 8,
+                    column: 1,
+                },
+                Figure {
+                    kind: FigureKind::Raw,
+                    caption: None,
+                    label: None,
+                    line: 9,
+                    column: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_and_unclosed_figures() {
+        let parser = FigureParser::new().unwrap();
+        let positions = |content: &str| -> Vec<_> {
+            parser
+                .parse_figures(content, &[])
+                .into_iter()
+                .map(|figure| (figure.line, figure.column, figure.label))
+                .collect()
+        };
+        // An unclosed figure doesn't hide a closed one inside it
+        assert_eq!(
+            positions("#figure(\n  #figure(table()) <inner>"),
+            vec![(2, 3, Some("inner".to_string()))]
+        );
+        assert_eq!(
+            positions("#figure([#figure(a) <a>]) <b>\n#figure(c)"),
+            vec![
+                (1, 1, Some("b".to_string())),
+                (1, 10, Some("a".to_string())),
+                (2, 1, None)
+            ]
+        );
+        // Nor are calls inside code strings figures
+        assert_eq!(positions("#figure(\"#figure(a)\")"), vec![(1, 1, None)]);
+        assert!(positions(&"#figure(".repeat(10_000)).is_empty());
+    }
+}
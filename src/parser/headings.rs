@@ -0,0 +1,82 @@
+use crate::parser::models::{ElementRange, Label, LabelKind};
+use crate::parser::names::slug;
+
+/// The text of a Typst heading line (`== Text <label>`), without its label.
+pub fn heading_text(line: &str) -> Option<String> {
+    let rest = line.trim_start().strip_prefix('=')?.trim_start_matches('=');
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let text = rest.trim();
+    let text = match text.rfind(" <") {
+        Some(at) if text.ends_with('>') => text[..at].trim_end(),
+        _ => text,
+    };
+    Some(text.to_string())
+}
+
+/// The level of a heading line, e.g. 2 for `== Details`.
+pub fn heading_level(line: &str) -> Option<usize> {
+    heading_text(line)?;
+    Some(line.trim_start().chars().take_while(|&c| c == '=').count())
+}
+
+/// The slug label of every heading in `content`, positioned at the heading's first `=`.
+/// Headings whose text has no letters or digits get none.
+pub fn heading_labels(content: &str) -> Vec<Label> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(line_idx, line)| {
+            let name = slug(&heading_text(line)?);
+            let indent = line.len() - line.trim_start().len();
+            let column = line[..indent].chars().count() + 1;
+            // The heading spans its text, not a trailing `<label>`
+            let trimmed = line.trim_end();
+            let end = match trimmed.rfind(" <") {
+                Some(at) if trimmed.ends_with('>') => trimmed[..at].trim_end(),
+                _ => trimmed,
+            };
+            (!name.is_empty()).then(|| Label {
+                name,
+                line: line_idx + 1,
+                column,
+                kind: LabelKind::Heading,
+                element: Some(ElementRange {
+                    line: line_idx + 1,
+                    column,
+                    end_line: line_idx + 1,
+                    end_column: end.chars().count() + 1,
+                }),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heading_text() {
+        assert_eq!(
+            heading_text("== Details <details>").as_deref(),
+            Some("Details")
+        );
+        assert_eq!(heading_text("  = Intro").as_deref(), Some("Intro"));
+        assert_eq!(heading_text("=not a heading"), None);
+        assert_eq!(heading_text("a = b"), None);
+        assert_eq!(heading_level("  === Deep"), Some(3));
+        assert_eq!(heading_level("Body"), None);
+    }
+
+    #[test]
+    fn test_heading_labels() {
+        let labels = heading_labels("= Über uns\nBody\n  == Details <details>\n= ?!\n");
+        let names: Vec<_> = labels
+            .iter()
+            .map(|l| (l.name.as_str(), l.line, l.column))
+            .collect();
+        assert_eq!(names, vec![("über-uns", 1, 1), ("details", 3, 3)]);
+    }
+}
@@ -1,42 +1,300 @@
-use crate::parser::models::Label;
+use crate::parser::headings::heading_text;
+use crate::parser::models::{ElementRange, Label, LabelKind};
+use crate::parser::positions::LineIndex;
 use anyhow::Result;
 use regex::Regex;
+use std::collections::HashMap;
+use std::ops::Range;
 
 pub struct LabelParser {
     label_regex: Regex,
+    reference_regex: Regex,
 }
 
 impl LabelParser {
     pub fn new() -> Result<Self> {
         // Matches explicit labels: <label-name>
-        let explicit_label_regex = Regex::new(r"<([a-zA-Z0-9_:.-]+)>")?;
+        let explicit_label_regex = Regex::new(r"<([\w:.-]+)>")?;
+
+        // Matches references: @label, but not the `@` of an e-mail address
+        let reference_regex = Regex::new(r"(?:^|[^\w@])@([\w:.-]+)")?;
 
         Ok(Self {
             label_regex: explicit_label_regex,
+            reference_regex,
         })
     }
 
     pub fn parse_labels(&self, content: &str) -> Vec<Label> {
         let mut labels = Vec::new();
-        let lines: Vec<&str> = content.lines().collect();
+        let mut line_start = 0;
+        let elements = Elements::new(content);
 
-        for (line_idx, line) in lines.iter().enumerate() {
+        for (line_idx, raw_line) in content.split_inclusive('\n').enumerate() {
+            let line = raw_line.trim_end_matches(['\n', '\r']);
+            let columns = LineIndex::new(line);
             // Parse explicit labels
             for cap in self.label_regex.captures_iter(line) {
                 let label_name = cap.get(1).unwrap().as_str().to_string();
                 let full_match = cap.get(0).unwrap();
-                let column = line[..full_match.start()].chars().count() + 1;
+                let column = columns.column(full_match.start());
+                let (kind, element) =
+                    elements.labeled(line_start, line, line_start + full_match.start());
 
                 labels.push(Label {
                     name: label_name,
                     line: line_idx + 1,
                     column,
+                    kind,
+                    element,
                 });
             }
+            line_start += raw_line.len();
+        }
+
+        labels
+    }
+
+    /// Every `@label` reference in `content`, positioned at its `@`.
+    pub fn parse_references(&self, content: &str) -> Vec<Label> {
+        content
+            .lines()
+            .enumerate()
+            .flat_map(|(line_idx, line)| {
+                self.references_in(line).map(move |(name, span)| Label {
+                    name,
+                    line: line_idx + 1,
+                    column: span.start + 1,
+                    kind: LabelKind::Other,
+                    element: None,
+                })
+            })
+            .collect()
+    }
+
+    /// The label named by the `<label>` or `@label` under the 0-based `character` of `line`,
+    /// with the character range it spans.
+    pub fn label_at(&self, line: &str, character: usize) -> Option<(String, Range<usize>)> {
+        let columns = LineIndex::new(line);
+        let definitions = self.label_regex.captures_iter(line).map(|cap| {
+            let full = cap.get(0).unwrap();
+            let start = columns.column(full.start()) - 1;
+            (
+                cap[1].to_string(),
+                start..start + full.as_str().chars().count(),
+            )
+        });
+        definitions
+            .chain(self.references_in(line))
+            .find(|(_, span)| span.start <= character && character <= span.end)
+    }
+
+    /// The byte ranges of every `<label>` and `@label` reference in `content`.
+    pub fn spans(&self, content: &str) -> Vec<Range<usize>> {
+        let definitions = self
+            .label_regex
+            .find_iter(content)
+            .map(|found| found.range());
+        let references = self
+            .reference_regex
+            .captures_iter(content)
+            .filter_map(|cap| {
+                let name = cap.get(1).unwrap();
+                let trimmed = name.as_str().trim_end_matches(['.', ':']);
+                (!trimmed.is_empty()).then(|| name.start() - 1..name.start() + trimmed.len())
+            });
+        definitions.chain(references).collect()
+    }
+
+    // References in one line with their character spans, `@` included
+    fn references_in<'a>(
+        &self,
+        line: &'a str,
+    ) -> impl Iterator<Item = (String, Range<usize>)> + 'a {
+        let captures: Vec<_> = self.reference_regex.captures_iter(line).collect();
+        let columns = LineIndex::new(line);
+        captures.into_iter().filter_map(move |cap| {
+            let name = cap.get(1).unwrap();
+            // Trailing punctuation ends the sentence, not the label
+            let trimmed = name.as_str().trim_end_matches(['.', ':']);
+            if trimmed.is_empty() {
+                return None;
+            }
+            // At the `@`
+            let start = columns.column(name.start()) - 2;
+            Some((
+                trimmed.to_string(),
+                start..start + 1 + trimmed.chars().count(),
+            ))
+        })
+    }
+}
+
+/// What a note's labels attach to, with the lookups that takes prepared once for the note.
+struct Elements<'a> {
+    content: &'a str,
+    lines: LineIndex<'a>,
+    // Where the bracket closing at each byte opens, counting `()` and `[]` alike
+    opens: HashMap<usize, usize>,
+}
+
+impl<'a> Elements<'a> {
+    fn new(content: &'a str) -> Self {
+        let mut unclosed = Vec::new();
+        let mut opens = HashMap::new();
+        for (i, c) in content.char_indices() {
+            match c {
+                '(' | '[' => unclosed.push(i),
+                ')' | ']' => {
+                    if let Some(open) = unclosed.pop() {
+                        opens.insert(i, open);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self {
+            content,
+            lines: LineIndex::new(content),
+            opens,
+        }
+    }
 
+    /// What the label at byte `at` attaches to: the heading on its line, or the function call,
+    /// content block or equation that ends right before it.
+    fn labeled(
+        &self,
+        line_start: usize,
+        line: &str,
+        at: usize,
+    ) -> (LabelKind, Option<ElementRange>) {
+        let span = |start: usize, end: usize| {
+            let (line, column) = self.lines.position(start);
+            let (end_line, end_column) = self.lines.position(end);
+            Some(ElementRange {
+                line,
+                column,
+                end_line,
+                end_column,
+            })
+        };
+        let before = self.content[..at].trim_end();
 
+        if heading_text(line).is_some() {
+            let indent = line.len() - line.trim_start().len();
+            return (LabelKind::Heading, span(line_start + indent, before.len()));
+        }
+        if let Some(open) = before.strip_suffix('$').and_then(|rest| rest.rfind('$')) {
+            return (LabelKind::Equation, span(open, before.len()));
+        }
+        match self.call_before(before) {
+            Some((start, name)) => {
+                let kind = match name {
+                    "figure" => LabelKind::Figure,
+                    "equation" | "math.equation" => LabelKind::Equation,
+                    _ => LabelKind::Block,
+                };
+                (kind, span(start, before.len()))
+            }
+            None => (LabelKind::Other, None),
         }
+    }
 
-        labels
+    // The start (at its `#`, if any) and name of a function call with its argument lists and
+    // trailing content blocks ending `text`, like `#figure(image("a.png"))[...]`
+    fn call_before<'t>(&self, text: &'t str) -> Option<(usize, &'t str)> {
+        let mut end = text.len();
+        while text[..end].ends_with([')', ']']) {
+            end = *self.opens.get(&(end - 1))?;
+        }
+        if end == text.len() {
+            return None;
+        }
+
+        let is_name = |c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | '-');
+        let name_start = text[..end].trim_end_matches(is_name).len();
+        let name = &text[name_start..end];
+        if name.is_empty() {
+            return None;
+        }
+        let start = match text[..name_start].strip_suffix('#') {
+            Some(hash) => hash.len(),
+            None => name_start,
+        };
+        Some((start, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references() {
+        let parser = LabelParser::new().unwrap();
+        let refs = parser.parse_references("See @intro and @fig:plot.\nMail a@b.com <intro>");
+        let names: Vec<_> = refs
+            .iter()
+            .map(|r| (r.name.as_str(), r.line, r.column))
+            .collect();
+        assert_eq!(names, vec![("intro", 1, 5), ("fig:plot", 1, 16)]);
+
+        let line = "= Intro <intro> see @intro.";
+        assert_eq!(
+            parser.label_at(line, 10),
+            Some(("intro".to_string(), 8..15))
+        );
+        assert_eq!(
+            parser.label_at(line, 22),
+            Some(("intro".to_string(), 20..26))
+        );
+        assert_eq!(parser.label_at(line, 2), None);
+
+        let labels = parser.parse_labels("== Über uns <über-uns>, see @über-uns");
+        assert_eq!(labels[0].name, "über-uns");
+        assert_eq!(parser.parse_references("see @über-uns")[0].name, "über-uns");
+        assert_eq!(parser.spans("<a> see @b. and x@y"), vec![0..3, 8..10]);
+    }
+
+    #[test]
+    fn test_labeled_elements() {
+        let parser = LabelParser::new().unwrap();
+        let content = "= Intro <intro>\n\
+                       #figure(\n  image(\"a.png\"),\n  caption: [Plot (left)],\n) <fig:plot>\n\
+                       $ x^2 $ <eq:square>\n\
+                       #block(fill: red)[Boxed] <boxed>\n\
+                       Plain text <text>";
+        let labels: Vec<_> = parser
+            .parse_labels(content)
+            .into_iter()
+            .map(|label| (label.name, label.kind, label.element))
+            .collect();
+        let range = |line, column, end_line, end_column| {
+            Some(ElementRange {
+                line,
+                column,
+                end_line,
+                end_column,
+            })
+        };
+        assert_eq!(
+            labels,
+            vec![
+                ("intro".to_string(), LabelKind::Heading, range(1, 1, 1, 8)),
+                ("fig:plot".to_string(), LabelKind::Figure, range(2, 1, 5, 2)),
+                (
+                    "eq:square".to_string(),
+                    LabelKind::Equation,
+                    range(6, 1, 6, 8)
+                ),
+                ("boxed".to_string(), LabelKind::Block, range(7, 1, 7, 25)),
+                ("text".to_string(), LabelKind::Other, None),
+            ]
+        );
+
+        // Unbalanced brackets on one long line
+        let labels = parser.parse_labels(&"x)<a>".repeat(10_000));
+        assert!(labels.iter().all(|label| label.kind == LabelKind::Other));
+        assert_eq!(labels.last().unwrap().column, 49_998);
     }
 }
@@ -1,9 +1,12 @@
 use crate::parser::models::Metadata;
 use anyhow::Result;
 use serde_json::Value;
+#[cfg(feature = "native")]
 use std::path::Path;
+#[cfg(feature = "native")]
 use tokio::process::Command;
 
+#[cfg(feature = "native")]
 pub async fn extract_metadata(file_path: &Path) -> Result<Metadata> {
     let output = Command::new("typst")
         .arg("query")
@@ -30,7 +33,9 @@ pub async fn extract_metadata(file_path: &Path) -> Result<Metadata> {
     parse_metadata_json(&json_str)
 }
 
-fn parse_metadata_json(json_str: &str) -> Result<Metadata> {
+/// The metadata in the JSON `typst query` prints for a note's `metadata` value, for callers
+/// that run the query themselves.
+pub fn parse_metadata_json(json_str: &str) -> Result<Metadata> {
     let value: Value = serde_json::from_str(json_str)?;
 
     let mut metadata = Metadata::default();
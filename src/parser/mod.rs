@@ -1,12 +1,36 @@
+pub mod attachments;
+pub mod bibliography;
+pub mod blocks;
+pub mod conflicts;
+pub mod figures;
+pub mod headings;
 pub mod labels;
 pub mod metadata;
 pub mod models;
+pub mod names;
+pub mod packages;
+mod positions;
+pub mod stats;
+mod stream;
 pub mod wikilinks;
 
 use crate::parser::{
-    labels::LabelParser, metadata::extract_metadata, models::ParsedFile, wikilinks::WikilinkParser,
+    attachments::AttachmentParser,
+    bibliography::BibliographyParser,
+    blocks::BlockParser,
+    conflicts::find_conflicts,
+    figures::FigureParser,
+    headings::heading_labels,
+    labels::LabelParser,
+    models::{Citation, Label, ParsedFile, Wikilink},
+    packages::PackageParser,
+    stats::content_stats,
+    wikilinks::{WikilinkParser, WikilinkSyntax},
 };
 use anyhow::Result;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::ops::Range;
 use std::path::Path;
 
 pub type ParseError = anyhow::Error;
@@ -14,41 +38,187 @@ pub type ParseError = anyhow::Error;
 pub struct Parser {
     wikilink_parser: WikilinkParser,
     label_parser: LabelParser,
+    block_parser: BlockParser,
+    attachment_parser: AttachmentParser,
+    figure_parser: FigureParser,
+    bibliography_parser: BibliographyParser,
+    package_parser: PackageParser,
 }
 
 impl Parser {
     pub fn new() -> Result<Self> {
+        Self::with_syntax(&WikilinkSyntax::default())
+    }
+
+    pub fn with_syntax(syntax: &WikilinkSyntax) -> Result<Self> {
         Ok(Self {
-            wikilink_parser: WikilinkParser::new()?,
+            wikilink_parser: WikilinkParser::with_syntax(syntax)?,
             label_parser: LabelParser::new()?,
+            block_parser: BlockParser::new()?,
+            attachment_parser: AttachmentParser::new()?,
+            figure_parser: FigureParser::new()?,
+            bibliography_parser: BibliographyParser::new()?,
+            package_parser: PackageParser::new()?,
         })
     }
 
+    /// Parse a note on disk, with its metadata from `typst query`.
+    #[cfg(feature = "native")]
     pub async fn parse_file(&self, file_path: &Path) -> Result<ParsedFile> {
-        let content = tokio::fs::read_to_string(file_path).await?;
+        let metadata = metadata::extract_metadata(file_path).await?;
+        let mut parsed = self.parse_file_content(file_path).await?;
+        parsed.metadata = metadata;
+        Ok(parsed)
+    }
 
-        let metadata = extract_metadata(file_path).await?;
-        let wikilinks = self.wikilink_parser.parse_wikilinks(&content, file_path);
-        let labels = self.label_parser.parse_labels(&content);
+    /// Parse a note on disk without its metadata. The note is read in chunks as by
+    /// [`Parser::parse_reader`], without blocking the runtime.
+    #[cfg(feature = "native")]
+    pub async fn parse_file_content(&self, file_path: &Path) -> Result<ParsedFile> {
+        use tokio::io::AsyncBufReadExt;
 
-        Ok(ParsedFile {
-            path: file_path.to_path_buf(),
-            metadata,
-            wikilinks,
-            labels,
-        })
+        let file = tokio::fs::File::open(file_path).await?;
+        let mut reader = tokio::io::BufReader::new(file);
+        let mut parsed = self.parse_content("", file_path)?;
+        let mut lines = 0;
+        let mut chunker = stream::Chunker::default();
+        let mut line = String::new();
+        while reader.read_line(&mut line).await? > 0 {
+            if let Some(chunk) = chunker.push(&line) {
+                self.append_chunk(&mut parsed, &chunk, &mut lines)?;
+            }
+            line.clear();
+        }
+        if let Some(chunk) = chunker.finish() {
+            self.append_chunk(&mut parsed, &chunk, &mut lines)?;
+        }
+        drop_resolved_citations(&mut parsed);
+        Ok(parsed)
+    }
+
+    /// Parse a note read from `reader` in chunks of whole lines, without its metadata.
+    ///
+    /// Chunks break at blank lines outside raw blocks where they can, so for notes under a few
+    /// hundred KiB this is [`Parser::parse_content`] on the whole text. Beyond that, a call
+    /// spanning a chunk boundary is parsed as two halves.
+    pub fn parse_reader(&self, reader: impl BufRead, file_path: &Path) -> Result<ParsedFile> {
+        let mut parsed = self.parse_content("", file_path)?;
+        let mut lines = 0;
+        for chunk in stream::Chunks::new(reader) {
+            self.append_chunk(&mut parsed, &chunk?, &mut lines)?;
+        }
+        drop_resolved_citations(&mut parsed);
+        Ok(parsed)
+    }
+
+    // Parse `chunk` onto the end of `parsed`, which holds the note's first `lines` lines
+    fn append_chunk(&self, parsed: &mut ParsedFile, chunk: &str, lines: &mut usize) -> Result<()> {
+        let chunk_parsed = self.parse_content(chunk, &parsed.path)?;
+        stream::append(parsed, chunk_parsed, *lines);
+        *lines += chunk.matches('\n').count();
+        Ok(())
     }
 
     pub fn parse_content(&self, content: &str, file_path: &Path) -> Result<ParsedFile> {
         let metadata = crate::parser::models::Metadata::default();
         let wikilinks = self.wikilink_parser.parse_wikilinks(content, file_path);
         let labels = self.label_parser.parse_labels(content);
+        let headings = heading_labels(content);
+        let stats = content_stats(content);
+        let blocks = self.block_parser.parse_blocks(content);
+        let attachments = self
+            .attachment_parser
+            .parse_attachments(content, &wikilinks);
+        let conflicts = find_conflicts(content);
+        let figures = self.figure_parser.parse_figures(content, &labels);
+        let citations = self.citations(content, &labels, &headings);
+        let bibliographies = self.bibliography_parser.parse_bibliographies(content);
+        let packages = self.package_parser.parse_packages(content);
 
         Ok(ParsedFile {
             path: file_path.to_path_buf(),
             metadata,
             wikilinks,
             labels,
+            headings,
+            blocks,
+            attachments,
+            stats,
+            conflicts,
+            figures,
+            citations,
+            bibliographies,
+            packages,
         })
     }
+
+    // `@key` references in `content` that no label or heading slug of the note defines
+    fn citations(&self, content: &str, labels: &[Label], headings: &[Label]) -> Vec<Citation> {
+        let defined = |name: &str| {
+            labels
+                .iter()
+                .chain(headings)
+                .any(|label| label.name == name)
+        };
+        self.label_parser
+            .parse_references(content)
+            .into_iter()
+            .filter(|reference| !defined(&reference.name))
+            .map(|reference| Citation {
+                key: reference.name,
+                line: reference.line,
+                column: reference.column,
+            })
+            .collect()
+    }
+
+    pub fn target_prefix_at(&self, line: &str, character: usize) -> Option<(usize, String)> {
+        self.wikilink_parser.target_prefix_at(line, character)
+    }
+
+    pub fn asset_prefix_at(&self, line: &str, character: usize) -> Option<(usize, String)> {
+        self.attachment_parser.asset_prefix_at(line, character)
+    }
+
+    pub fn label_references(&self, content: &str) -> Vec<Label> {
+        self.label_parser.parse_references(content)
+    }
+
+    pub fn label_at(&self, line: &str, character: usize) -> Option<(String, Range<usize>)> {
+        self.label_parser.label_at(line, character)
+    }
+
+    pub fn label_spans(&self, content: &str) -> Vec<Range<usize>> {
+        self.label_parser.spans(content)
+    }
+
+    pub fn wikilink_spans(&self, content: &str) -> Vec<(Wikilink, Range<usize>)> {
+        self.wikilink_parser.wikilink_spans(content)
+    }
+
+    pub fn wikilink_at(
+        &self,
+        line: &str,
+        line_number: usize,
+        character: usize,
+    ) -> Option<(Wikilink, Range<usize>)> {
+        self.wikilink_parser
+            .wikilink_at(line, line_number, character)
+    }
+}
+
+// Each chunk of a note parsed in chunks only knew its own labels, so drop the citations that
+// name a label of another chunk
+fn drop_resolved_citations(parsed: &mut ParsedFile) {
+    let defined: HashSet<&str> = parsed
+        .labels
+        .iter()
+        .chain(&parsed.headings)
+        .map(|label| label.name.as_str())
+        .collect();
+    let citations = std::mem::take(&mut parsed.citations);
+    parsed.citations = citations
+        .into_iter()
+        .filter(|citation| !defined.contains(citation.key.as_str()))
+        .collect();
 }
@@ -10,9 +10,36 @@ pub struct ParsedFile {
     pub metadata: Metadata,
     pub wikilinks: Vec<Wikilink>,
     pub labels: Vec<Label>,
+    /// The slug label of each heading, which links can target like an explicit label.
+    pub headings: Vec<Label>,
+    pub blocks: Vec<Block>,
+    pub attachments: Vec<Attachment>,
+    pub stats: ContentStats,
+    /// Unresolved git merge conflicts left in the note
+    pub conflicts: Vec<Conflict>,
+    /// `#figure(...)` calls and labeled equations
+    pub figures: Vec<Figure>,
+    /// `@key` references that name no label in the note, so may cite its bibliography
+    pub citations: Vec<Citation>,
+    /// The files passed to `#bibliography(...)`, as written
+    pub bibliographies: Vec<String>,
+    /// `#import "@namespace/name:version"` statements
+    pub packages: Vec<PackageImport>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+/// Size of a note's prose, without its metadata block or comments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ContentStats {
+    pub words: usize,
+    /// Characters other than whitespace
+    pub characters: usize,
+    pub headings: usize,
+    /// At 200 words per minute, rounded up
+    pub reading_minutes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct Metadata {
     pub title: Option<String>,
@@ -28,6 +55,7 @@ pub struct Wikilink {
     pub target: String,
     pub alias: Option<String>,
     pub label: Option<String>,
+    pub block: Option<String>,
     pub line: usize,
     pub column: usize,
 }
@@ -36,17 +64,217 @@ pub struct Wikilink {
 #[ts(export)]
 pub struct Label {
     pub name: String,
+    /// Position of the `<label>` itself (or of a heading's first `=` for its slug)
     pub line: usize,
     pub column: usize,
+    #[serde(default)]
+    pub kind: LabelKind,
+    /// The element the label attaches to, when it could be told from the source
+    #[serde(default)]
+    pub element: Option<ElementRange>,
 }
 
-impl Default for Metadata {
-    fn default() -> Self {
-        Self {
-            title: None,
-            tags: Vec::new(),
-            alias: Vec::new(),
-            custom: HashMap::new(),
+impl Label {
+    /// Where to jump to for this label: its element if known, else the label itself.
+    pub fn target(&self) -> (usize, usize) {
+        match &self.element {
+            Some(element) => (element.line, element.column),
+            None => (self.line, self.column),
         }
     }
 }
+
+/// What a label is attached to: Typst labels name the element right before them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum LabelKind {
+    Heading,
+    /// `#figure(...)`
+    Figure,
+    /// `$ ... $` or `#math.equation(...)`
+    Equation,
+    /// Any other function call or content block, e.g. `#table(...)` or `#block[...]`
+    Block,
+    /// Text, or anything else the parser can't place
+    #[default]
+    Other,
+}
+
+impl LabelKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LabelKind::Heading => "heading",
+            LabelKind::Figure => "figure",
+            LabelKind::Equation => "equation",
+            LabelKind::Block => "block",
+            LabelKind::Other => "other",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "heading" => LabelKind::Heading,
+            "figure" => LabelKind::Figure,
+            "equation" => LabelKind::Equation,
+            "block" => LabelKind::Block,
+            "other" => LabelKind::Other,
+            _ => return None,
+        })
+    }
+}
+
+/// A 1-based span of source, with an exclusive end column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ElementRange {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+/// An anchorable block marked with a trailing `^block-id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Block {
+    pub id: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A `<<<<<<<` ... `=======` ... `>>>>>>>` block left by a git merge, by 1-based line.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Conflict {
+    /// The `<<<<<<<` line opening our side
+    pub line: usize,
+    /// The `|||||||` line opening the merge base, with `merge.conflictStyle = diff3`
+    pub base_line: Option<usize>,
+    /// The `=======` line opening their side
+    pub separator_line: usize,
+    /// The closing `>>>>>>>` line
+    pub end_line: usize,
+}
+
+/// A figure or labeled equation, positioned at its `#` or opening `$`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Figure {
+    pub kind: FigureKind,
+    /// The caption as plain text
+    pub caption: Option<String>,
+    pub label: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// What a figure shows, from its `kind:` argument or the call its body starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum FigureKind {
+    Image,
+    Table,
+    /// Code, from `raw(...)` or a backtick block
+    Raw,
+    Equation,
+    /// Any other content
+    Figure,
+}
+
+impl FigureKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FigureKind::Image => "image",
+            FigureKind::Table => "table",
+            FigureKind::Raw => "raw",
+            FigureKind::Equation => "equation",
+            FigureKind::Figure => "figure",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "image" => FigureKind::Image,
+            "table" => FigureKind::Table,
+            "raw" => FigureKind::Raw,
+            "equation" => FigureKind::Equation,
+            "figure" => FigureKind::Figure,
+            _ => return None,
+        })
+    }
+}
+
+/// An `@key` reference, positioned at its `@`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Citation {
+    pub key: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// An entry of a BibTeX or Hayagriva bibliography file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BibEntry {
+    pub key: String,
+    /// The entry type, e.g. `article` or `book`
+    pub kind: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub year: Option<String>,
+}
+
+impl BibEntry {
+    /// The entry as one line of text: `Authors (year). Title.`
+    pub fn reference(&self) -> String {
+        let mut reference = match self.authors.as_slice() {
+            [] => String::new(),
+            [author] => author.clone(),
+            [first, second] => format!("{first} and {second}"),
+            [rest @ .., last] => format!("{}, and {last}", rest.join(", ")),
+        };
+        if let Some(year) = &self.year {
+            reference.push_str(&format!(" ({year})"));
+        }
+        if let Some(title) = &self.title {
+            if !reference.is_empty() {
+                reference.push_str(". ");
+            }
+            reference.push_str(title);
+        }
+        if reference.is_empty() {
+            reference = self.key.clone();
+        }
+        if !reference.ends_with(['.', '?', '!']) {
+            reference.push('.');
+        }
+        reference
+    }
+}
+
+/// A Typst package import such as `#import "@preview/cetz:0.2.0"`, positioned at its `"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PackageImport {
+    /// `preview` for packages from Typst Universe, or a local namespace
+    pub namespace: String,
+    pub name: String,
+    pub version: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A reference to a non-Typst file such as a PDF or an image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Attachment {
+    /// The path as written in the note
+    pub target: String,
+    /// Whether the file is embedded with `#image(...)` rather than linked
+    pub embedded: bool,
+    pub line: usize,
+    pub column: usize,
+}
@@ -0,0 +1,80 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// `name` in Unicode NFC, the form link targets, note stems, and labels are compared in. macOS
+/// often writes file names decomposed (NFD) while most editors type composed characters, so
+/// `café` must match either spelling.
+pub fn normalize(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// `name` fully decomposed (NFD), the other spelling a file name may be stored in on disk.
+pub fn decompose(name: &str) -> String {
+    name.nfd().collect()
+}
+
+/// The label a heading can be linked by, valid as a Typst label: the text in NFC, lowercased,
+/// with each run of characters other than letters, digits, and combining marks replaced by
+/// `-`. `Über uns` becomes `über-uns`.
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.nfc().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || is_combining_mark(c) {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
+/// Levenshtein distance between `a` and `b` in characters, ignoring case and Unicode
+/// normalization differences.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let fold = |text: &str| -> Vec<char> { text.nfc().flat_map(char::to_lowercase).collect() };
+    let (a, b) = (fold(a), fold(b));
+    // Distances from a prefix of `a` to each prefix of `b`, one row at a time
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let decomposed = "cafe\u{301}";
+        assert_eq!(normalize(decomposed), "café");
+        assert_eq!(decompose("café"), decomposed);
+        assert_eq!(normalize("plain"), "plain");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("Project", "projcet"), 2);
+        assert_eq!(edit_distance("Cafe\u{301}", "café"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Über uns"), "über-uns");
+        assert_eq!(slug("U\u{308}ber uns"), "über-uns");
+        assert_eq!(slug("  What's new? (2024) "), "what-s-new-2024");
+        assert_eq!(slug("日本語 の 見出し"), "日本語-の-見出し");
+        assert_eq!(slug("नमस्ते"), "नमस्ते");
+        assert_eq!(slug("--"), "");
+    }
+}
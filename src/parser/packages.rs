@@ -0,0 +1,73 @@
+use crate::parser::models::PackageImport;
+use crate::parser::positions::LineIndex;
+use anyhow::Result;
+use regex::Regex;
+
+pub struct PackageParser {
+    import_regex: Regex,
+}
+
+impl PackageParser {
+    pub fn new() -> Result<Self> {
+        // Matches `#import "@preview/cetz:0.2.0"` and `import "@local/notes:1.0.0": *`
+        let import_regex =
+            Regex::new(r#"(?:^|[^\w.-])import\s+("@([\w-]+)/([\w-]+):([0-9A-Za-z.+-]+)")"#)?;
+
+        Ok(Self { import_regex })
+    }
+
+    /// Every package import in `content` outside line comments, in document order.
+    pub fn parse_packages(&self, content: &str) -> Vec<PackageImport> {
+        let mut packages = Vec::new();
+        for (line_idx, line) in content.lines().enumerate() {
+            if line.trim_start().starts_with("//") {
+                continue;
+            }
+            let columns = LineIndex::new(line);
+            for cap in self.import_regex.captures_iter(line) {
+                let spec = cap.get(1).unwrap();
+                packages.push(PackageImport {
+                    namespace: cap[2].to_string(),
+                    name: cap[3].to_string(),
+                    version: cap[4].to_string(),
+                    line: line_idx + 1,
+                    column: columns.column(spec.start()),
+                });
+            }
+        }
+        packages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_packages() {
+        let content = "#import \"@preview/cetz:0.2.0\": canvas, draw\n\
+                       // #import \"@preview/old:1.0.0\"\n\
+                       #import \"utils.typ\"\n\
+                       #{ import \"@local/notes:1.0.0-rc.1\" as n }";
+        let packages = PackageParser::new().unwrap().parse_packages(content);
+        assert_eq!(
+            packages,
+            vec![
+                PackageImport {
+                    namespace: "preview".to_string(),
+                    name: "cetz".to_string(),
+                    version: "0.2.0".to_string(),
+                    line: 1,
+                    column: 9,
+                },
+                PackageImport {
+                    namespace: "local".to_string(),
+                    name: "notes".to_string(),
+                    version: "1.0.0-rc.1".to_string(),
+                    line: 4,
+                    column: 11,
+                },
+            ]
+        );
+    }
+}
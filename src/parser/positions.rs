@@ -0,0 +1,66 @@
+use std::cell::Cell;
+
+/// Converts byte offsets in a text to the 1-based line and column (in characters) the parser
+/// reports, without rescanning the text before every offset.
+///
+/// Lines are found by binary search. Columns are counted from the previous offset when it lies
+/// earlier on the same line, so increasing offsets cost time linear in the text overall.
+pub(crate) struct LineIndex<'a> {
+    text: &'a str,
+    // Byte offset where each line starts
+    starts: Vec<usize>,
+    // The last offset asked for, with its 0-based line and column
+    last: Cell<(usize, usize, usize)>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            text,
+            starts,
+            last: Cell::new((0, 0, 0)),
+        }
+    }
+
+    /// The 1-based line and column of byte `offset`.
+    pub(crate) fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let (last_offset, last_line, last_column) = self.last.get();
+        let (from, column) = if last_line == line && last_offset <= offset {
+            (last_offset, last_column)
+        } else {
+            (self.starts[line], 0)
+        };
+        let column = column + self.text[from..offset].chars().count();
+        self.last.set((offset, line, column));
+        (line + 1, column + 1)
+    }
+
+    /// The 1-based column of byte `offset`, for a text holding a single line.
+    pub(crate) fn column(&self, offset: usize) -> usize {
+        self.position(offset).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let text = "ab\ncé d\n\nx";
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(0), (1, 1));
+        assert_eq!(index.position(2), (1, 3));
+        assert_eq!(index.position(3), (2, 1));
+        assert_eq!(index.position(7), (2, 4));
+        // Earlier offsets after later ones
+        assert_eq!(index.position(6), (2, 3));
+        assert_eq!(index.position(1), (1, 2));
+        assert_eq!(index.position(9), (3, 1));
+        assert_eq!(index.position(text.len()), (4, 2));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::frontmatter::MetaBlock;
+use crate::parser::headings::heading_level;
+use crate::parser::models::ContentStats;
+
+// Average silent reading speed used for reading times
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Word, character, and heading counts of a note's prose, leaving out its metadata block and
+/// `//` comment lines.
+pub fn content_stats(content: &str) -> ContentStats {
+    let text = match MetaBlock::locate(content) {
+        Some(block) => {
+            let start = content[..block.open].rfind('#').unwrap_or(block.open);
+            format!("{}{}", &content[..start], &content[block.close + 1..])
+        }
+        None => content.to_string(),
+    };
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"));
+
+    let mut stats = ContentStats::default();
+    for line in lines {
+        stats.words += count_words(line);
+        stats.characters += line.chars().filter(|c| !c.is_whitespace()).count();
+        stats.headings += usize::from(heading_level(line).is_some());
+    }
+    stats.reading_minutes = reading_minutes(stats.words);
+    stats
+}
+
+/// Whitespace-separated words containing a letter or digit, so markup like `-` or `*` alone
+/// doesn't count.
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Minutes needed to read `words`, rounded up.
+pub fn reading_minutes(words: usize) -> usize {
+    words.div_ceil(WORDS_PER_MINUTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_stats() {
+        let content = "#meta(\n  title: \"Long title words\",\n)\n= Intro\n// not counted\nOne two - three.\n";
+        let stats = content_stats(content);
+        assert_eq!(stats.words, 4);
+        assert_eq!(stats.characters, "=IntroOnetwo-three.".len());
+        assert_eq!(stats.headings, 1);
+        assert_eq!(stats.reading_minutes, 1);
+
+        assert_eq!(content_stats("").reading_minutes, 0);
+        assert_eq!(reading_minutes(401), 3);
+    }
+
+    #[test]
+    fn test_count_words() {
+        assert_eq!(count_words("  one two\tthree - * "), 3);
+        assert_eq!(count_words(""), 0);
+    }
+}
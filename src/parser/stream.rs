@@ -0,0 +1,224 @@
+use crate::parser::models::{ElementRange, ParsedFile};
+use crate::parser::stats::reading_minutes;
+use std::io::BufRead;
+
+// A chunk ends at the first blank line after this many bytes, so paragraphs, and with them
+// most links, labels and calls, stay whole
+const CHUNK_BYTES: usize = 256 * 1024;
+// Notes without blank lines are cut at any line end once a chunk grows this large
+const MAX_CHUNK_BYTES: usize = 4 * CHUNK_BYTES;
+
+/// Gathers lines into chunks, each ending at a paragraph break outside raw blocks where
+/// possible.
+#[derive(Default)]
+pub(crate) struct Chunker {
+    chunk: String,
+    // The backtick run of the raw block still open, whose blank lines are no paragraph breaks
+    fence: Option<usize>,
+}
+
+impl Chunker {
+    /// Add the next line, with its line ending, returning the chunk it completes.
+    pub(crate) fn push(&mut self, line: &str) -> Option<String> {
+        self.chunk.push_str(line);
+        self.fence = fence_after(self.fence, line);
+        let blank = line.trim().is_empty() && self.fence.is_none();
+        if (blank && self.chunk.len() >= CHUNK_BYTES) || self.chunk.len() >= MAX_CHUNK_BYTES {
+            self.fence = None;
+            return Some(std::mem::take(&mut self.chunk));
+        }
+        None
+    }
+
+    /// The last chunk, once every line was pushed.
+    pub(crate) fn finish(self) -> Option<String> {
+        (!self.chunk.is_empty()).then_some(self.chunk)
+    }
+}
+
+// Which raw block is open after `line`, given the one open before it. Raw blocks open and
+// close with runs of three or more backticks of the same length
+fn fence_after(mut open: Option<usize>, line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+        let run = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+        open = match open {
+            Some(fence) if fence == run => None,
+            None if run >= 3 => Some(run),
+            open => open,
+        };
+        i += run;
+    }
+    open
+}
+
+/// Reads `reader` in chunks of whole lines, each ending at a paragraph break where possible.
+pub(crate) struct Chunks<R> {
+    reader: R,
+    chunker: Option<Chunker>,
+}
+
+impl<R: BufRead> Chunks<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            chunker: Some(Chunker::default()),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Chunks<R> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunker = self.chunker.as_mut()?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return self.chunker.take()?.finish().map(Ok),
+                Ok(_) => {
+                    if let Some(chunk) = chunker.push(&line) {
+                        return Some(Ok(chunk));
+                    }
+                }
+                Err(e) => {
+                    self.chunker = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Append `chunk`, parsed on its own, to `parsed`, moving its positions down by the `lines`
+/// read before it.
+pub(crate) fn append(parsed: &mut ParsedFile, chunk: ParsedFile, lines: usize) {
+    let shift = |element: &mut Option<ElementRange>| {
+        if let Some(element) = element {
+            element.line += lines;
+            element.end_line += lines;
+        }
+    };
+
+    parsed
+        .wikilinks
+        .extend(chunk.wikilinks.into_iter().map(|mut link| {
+            link.line += lines;
+            link
+        }));
+    for (labels, chunk_labels) in [
+        (&mut parsed.labels, chunk.labels),
+        (&mut parsed.headings, chunk.headings),
+    ] {
+        labels.extend(chunk_labels.into_iter().map(|mut label| {
+            label.line += lines;
+            shift(&mut label.element);
+            label
+        }));
+    }
+    parsed
+        .blocks
+        .extend(chunk.blocks.into_iter().map(|mut block| {
+            block.line += lines;
+            block
+        }));
+    parsed
+        .attachments
+        .extend(chunk.attachments.into_iter().map(|mut attachment| {
+            attachment.line += lines;
+            attachment
+        }));
+    parsed
+        .conflicts
+        .extend(chunk.conflicts.into_iter().map(|mut conflict| {
+            conflict.line += lines;
+            conflict.base_line = conflict.base_line.map(|line| line + lines);
+            conflict.separator_line += lines;
+            conflict.end_line += lines;
+            conflict
+        }));
+    parsed
+        .figures
+        .extend(chunk.figures.into_iter().map(|mut figure| {
+            figure.line += lines;
+            figure
+        }));
+    parsed
+        .citations
+        .extend(chunk.citations.into_iter().map(|mut citation| {
+            citation.line += lines;
+            citation
+        }));
+    parsed.bibliographies.extend(chunk.bibliographies);
+    parsed
+        .packages
+        .extend(chunk.packages.into_iter().map(|mut package| {
+            package.line += lines;
+            package
+        }));
+
+    parsed.stats.words += chunk.stats.words;
+    parsed.stats.characters += chunk.stats.characters;
+    parsed.stats.headings += chunk.stats.headings;
+    parsed.stats.reading_minutes = reading_minutes(parsed.stats.words);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_reader() {
+        let parser = Parser::new().unwrap();
+        let path = Path::new("/vault/log.typ");
+        let mut content = String::from("= Log <top>\n\n");
+        for i in 0..20_000 {
+            content.push_str(&format!(
+                "Entry {i} links [[note-{i}]] and cites @top. ^e{i}\n\n"
+            ));
+        }
+        content.push_str("== End\n@missing $ x $ <eq>\n");
+
+        let whole = parser.parse_content(&content, path).unwrap();
+        let streamed = parser.parse_reader(content.as_bytes(), path).unwrap();
+        assert!(content.len() > 2 * super::CHUNK_BYTES);
+        assert_eq!(streamed, whole);
+    }
+
+    #[test]
+    fn test_chunks_without_blank_lines() {
+        let line = "x".repeat(1000) + "\n";
+        let content = line.repeat(3000);
+        let chunks: Vec<String> = super::Chunks::new(content.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.ends_with('\n')));
+        assert_eq!(chunks.concat(), content);
+    }
+
+    #[test]
+    fn test_chunks_keep_raw_blocks_whole() {
+        let paragraph = "x".repeat(1000) + "\n\n";
+        let raw = format!("```\n{}```\n\n", "`code`\n\n".repeat(150_000));
+        let content = paragraph.repeat(300) + &raw + &paragraph;
+        let chunks: Vec<String> = super::Chunks::new(content.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(chunks.len() > 1);
+        // The raw block is only cut once the chunk holding it reaches the hard limit
+        let opened = chunks
+            .iter()
+            .position(|chunk| chunk.contains("```\n`code`"))
+            .unwrap();
+        assert!(chunks[opened].len() >= super::MAX_CHUNK_BYTES);
+        assert_eq!(chunks.concat(), content);
+    }
+}
@@ -1,49 +1,338 @@
 use crate::parser::models::Wikilink;
-use anyhow::Result;
-use regex::Regex;
+use anyhow::{Result, ensure};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
 
 pub type WikilinkError = anyhow::Error;
 
+/// The wikilink grammar used by a vault.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WikilinkSyntax {
+    /// Character separating the target from a label, e.g. `:` in `[[target:label]]`
+    /// or `#` in `[[target#heading]]`.
+    pub label_separator: char,
+    /// Whether aliases come before the target, as in `[[alias|target]]`.
+    pub alias_first: bool,
+}
+
+impl Default for WikilinkSyntax {
+    fn default() -> Self {
+        Self {
+            label_separator: ':',
+            alias_first: false,
+        }
+    }
+}
+
+impl WikilinkSyntax {
+    /// Write `wikilink` in this syntax, escaping characters that would end its parts early.
+    pub fn format(&self, wikilink: &Wikilink) -> String {
+        let escape = |text: &str, specials: &[char]| {
+            let mut escaped = String::with_capacity(text.len());
+            for c in text.chars() {
+                if matches!(c, '\\' | '[' | ']' | '|') || specials.contains(&c) {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+            escaped
+        };
+
+        let mut link = escape(&wikilink.target, &[self.label_separator, '#']);
+        if let Some(block) = &wikilink.block {
+            link.push_str(&format!("#^{block}"));
+        } else if let Some(label) = &wikilink.label {
+            link.push(self.label_separator);
+            // Else `#^` in the label would read as a block reference
+            link.push_str(&escape(label, &['#', '^']));
+        }
+
+        match &wikilink.alias {
+            Some(alias) if self.alias_first => format!("[[{}|{link}]]", escape(alias, &[])),
+            Some(alias) => format!("[[{link}|{}]]", escape(alias, &[])),
+            None => format!("[[{link}]]"),
+        }
+    }
+}
+
 pub struct WikilinkParser {
-    wikilink_regex: Regex,
+    syntax: WikilinkSyntax,
 }
 
+// A character inside a wikilink body, remembering whether it was backslash-escaped
+type BodyChar = (char, bool);
+
 impl WikilinkParser {
     pub fn new() -> Result<Self> {
-        // Matches: [[target]], [[target|alias]], [[target:label]], [[target:label|alias]]
-        let regex = Regex::new(r"\[\[([^|\]:\n]+)(?::([^|\]\n]+))?(?:\|([^|\]\n]+))?\]\]")?;
+        Self::with_syntax(&WikilinkSyntax::default())
+    }
+
+    pub fn with_syntax(syntax: &WikilinkSyntax) -> Result<Self> {
+        let separator = syntax.label_separator;
+        ensure!(
+            !matches!(separator, '|' | '[' | ']' | '^' | '\\' | '\n'),
+            "Invalid wikilink label separator: {separator:?}"
+        );
 
         Ok(Self {
-            wikilink_regex: regex,
+            syntax: syntax.clone(),
         })
     }
 
-    pub fn parse_wikilinks(&self, content: &str, file_path: &Path) -> Vec<Wikilink> {
+    pub fn parse_wikilinks(&self, content: &str, _file_path: &Path) -> Vec<Wikilink> {
+        self.scan(content)
+            .into_iter()
+            .map(|(wikilink, _)| wikilink)
+            .collect()
+    }
+
+    /// Every wikilink in `content` with the 0-based character range it spans within its line.
+    pub fn wikilink_spans(&self, content: &str) -> Vec<(Wikilink, Range<usize>)> {
+        self.scan(content)
+    }
+
+    /// If the cursor at 0-based `character` is in the target of a wikilink being typed,
+    /// the offset where the target starts and the text typed so far.
+    pub fn target_prefix_at(&self, line: &str, character: usize) -> Option<(usize, String)> {
+        let chars: Vec<char> = line.chars().take(character).collect();
+        let open = (1..chars.len())
+            .rev()
+            .find(|&i| chars[i - 1] == '[' && chars[i] == '[')?;
+        let mut start = open + 1;
+        if chars[start..].contains(&']') {
+            return None;
+        }
+
+        if let Some(bar) = chars[start..].iter().position(|&c| c == '|') {
+            if !self.syntax.alias_first {
+                return None;
+            }
+            start += bar + 1;
+        }
+        let target = &chars[start..];
+        if target
+            .iter()
+            .any(|&c| c == '#' || c == '|' || c == self.syntax.label_separator)
+        {
+            return None;
+        }
+
+        Some((start, target.iter().collect()))
+    }
+
+    /// Find the wikilink covering the 0-based character offset `character` in `line`,
+    /// along with the character range it spans.
+    pub fn wikilink_at(
+        &self,
+        line: &str,
+        line_number: usize,
+        character: usize,
+    ) -> Option<(Wikilink, Range<usize>)> {
+        self.scan(line)
+            .into_iter()
+            .find(|(_, range)| range.contains(&character))
+            .map(|(mut wikilink, range)| {
+                wikilink.line = line_number;
+                (wikilink, range)
+            })
+    }
+
+    /// Scan `content` for wikilinks, skipping math and raw spans and honoring `\` escapes.
+    ///
+    /// Returned ranges are 0-based character offsets within the link's line.
+    fn scan(&self, content: &str) -> Vec<(Wikilink, Range<usize>)> {
+        let chars: Vec<char> = content.chars().collect();
         let mut wikilinks = Vec::new();
-        let lines: Vec<&str> = content.lines().collect();
-
-        for (line_idx, line) in lines.iter().enumerate() {
-            for cap in self.wikilink_regex.captures_iter(line) {
-                let full_match = cap.get(0).unwrap();
-                let target = cap.get(1).unwrap().as_str().to_string();
-                let label = cap.get(2).map(|m| m.as_str().to_string());
-                let alias = cap.get(3).map(|m| m.as_str().to_string());
-
-                let column = line[..full_match.start()].chars().count() + 1;
-
-                wikilinks.push(Wikilink {
-                    target,
-                    alias,
-                    label,
-                    line: line_idx + 1,
-                    column,
-                });
+        let mut cursor = Cursor {
+            pos: 0,
+            line: 1,
+            line_start: 0,
+        };
+        // For each backtick run length, the earliest offset after which no run of that length
+        // closes a raw span, so unclosed runs aren't searched for again and again
+        let mut unclosed: HashMap<usize, usize> = HashMap::new();
+
+        while cursor.pos < chars.len() {
+            match chars[cursor.pos] {
+                '\n' => cursor.advance_to(&chars, cursor.pos + 1),
+                '\\' => {
+                    // Escaped characters never open links, math, or raw spans
+                    let next = (cursor.pos + 2).min(chars.len());
+                    cursor.advance_to(&chars, next);
+                }
+                '$' => match find_unescaped(&chars, cursor.pos + 1, '$') {
+                    Some(end) => cursor.advance_to(&chars, end + 1),
+                    None => cursor.pos += 1,
+                },
+                '`' => {
+                    let run = chars[cursor.pos..]
+                        .iter()
+                        .take_while(|&&c| c == '`')
+                        .count();
+                    let body_start = cursor.pos + run;
+                    let end = match unclosed.get(&run) {
+                        Some(&after) if after <= body_start => None,
+                        _ => find_backtick_run(&chars, body_start, run),
+                    };
+                    if end.is_none() {
+                        unclosed.insert(run, body_start);
+                    }
+                    match end {
+                        Some(end) if run != 2 => cursor.advance_to(&chars, end + run),
+                        _ => cursor.pos = body_start,
+                    }
+                }
+                '[' if chars.get(cursor.pos + 1) == Some(&'[') => {
+                    match self.scan_link(&chars, cursor.pos) {
+                        Some((mut wikilink, end)) => {
+                            let start = cursor.pos - cursor.line_start;
+                            wikilink.line = cursor.line;
+                            wikilink.column = start + 1;
+                            wikilinks.push((wikilink, start..start + (end - cursor.pos)));
+                            cursor.pos = end;
+                        }
+                        None => cursor.pos += 1,
+                    }
+                }
+                _ => cursor.pos += 1,
             }
         }
 
         wikilinks
     }
+
+    /// Parse the link opening at `start`, returning it and the index just past its `]]`.
+    fn scan_link(&self, chars: &[char], start: usize) -> Option<(Wikilink, usize)> {
+        let mut body: Vec<BodyChar> = Vec::new();
+        let mut pos = start + 2;
+
+        loop {
+            match *chars.get(pos)? {
+                '\\' => {
+                    let escaped = *chars.get(pos + 1)?;
+                    if escaped == '\n' {
+                        return None;
+                    }
+                    body.push((escaped, true));
+                    pos += 2;
+                }
+                ']' if chars.get(pos + 1) == Some(&']') => break,
+                // Nested brackets and line breaks make this ordinary markup
+                '[' | ']' | '\n' => return None,
+                c => {
+                    body.push((c, false));
+                    pos += 1;
+                }
+            }
+        }
+
+        let wikilink = self.split_body(&body)?;
+        Some((wikilink, pos + 2))
+    }
+
+    fn split_body(&self, body: &[BodyChar]) -> Option<Wikilink> {
+        let pipes: Vec<usize> = positions(body, |c| c == '|');
+        let (link_part, alias) = match pipes.as_slice() {
+            [] => (body, None),
+            [pipe] => {
+                let (left, right) = (&body[..*pipe], &body[pipe + 1..]);
+                if self.syntax.alias_first {
+                    (right, Some(non_empty(left)?))
+                } else {
+                    (left, Some(non_empty(right)?))
+                }
+            }
+            _ => return None,
+        };
+
+        let separator = self.syntax.label_separator;
+        let block_marker = (0..link_part.len().saturating_sub(1))
+            .find(|&i| link_part[i] == ('#', false) && link_part[i + 1] == ('^', false));
+
+        let (target, label, block) = if let Some(marker) = block_marker {
+            let block = non_empty(&link_part[marker + 2..])?;
+            if !block.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return None;
+            }
+            (&link_part[..marker], None, Some(block))
+        } else if let Some(&split) = positions(link_part, |c| c == separator).first() {
+            let label = non_empty(&link_part[split + 1..])?;
+            (&link_part[..split], Some(label), None)
+        } else {
+            (link_part, None, None)
+        };
+
+        Some(Wikilink {
+            target: non_empty(target)?,
+            alias,
+            label,
+            block,
+            line: 0,
+            column: 0,
+        })
+    }
+}
+
+struct Cursor {
+    pos: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl Cursor {
+    fn advance_to(&mut self, chars: &[char], target: usize) {
+        while self.pos < target {
+            if chars[self.pos] == '\n' {
+                self.line += 1;
+                self.line_start = self.pos + 1;
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+fn find_unescaped(chars: &[char], from: usize, needle: char) -> Option<usize> {
+    let mut pos = from;
+    while pos < chars.len() {
+        match chars[pos] {
+            '\\' => pos += 2,
+            c if c == needle => return Some(pos),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+fn find_backtick_run(chars: &[char], from: usize, len: usize) -> Option<usize> {
+    let mut pos = from;
+    while pos < chars.len() {
+        if chars[pos] == '`' {
+            let run = chars[pos..].iter().take_while(|&&c| c == '`').count();
+            if run == len {
+                return Some(pos);
+            }
+            pos += run;
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+fn positions(body: &[BodyChar], predicate: impl Fn(char) -> bool) -> Vec<usize> {
+    body.iter()
+        .enumerate()
+        .filter(|(_, (c, escaped))| !escaped && predicate(*c))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn non_empty(part: &[BodyChar]) -> Option<String> {
+    let text: String = part.iter().map(|(c, _)| c).collect();
+    (!text.is_empty()).then_some(text)
 }
 
 #[cfg(test)]
@@ -148,4 +437,246 @@ mod tests {
         assert_eq!(wikilinks.len(), 1);
         assert_eq!(wikilinks[0].target, "document.pdf");
     }
+
+    #[test]
+    fn test_parse_block_reference() {
+        let parser = WikilinkParser::new().unwrap();
+        let content = "As argued in [[note#^claim-1|the claim]].";
+        let path = PathBuf::from("test.typ");
+
+        let wikilinks = parser.parse_wikilinks(content, &path);
+
+        assert_eq!(wikilinks.len(), 1);
+        assert_eq!(wikilinks[0].target, "note");
+        assert_eq!(wikilinks[0].block, Some("claim-1".to_string()));
+        assert_eq!(wikilinks[0].label, None);
+        assert_eq!(wikilinks[0].alias, Some("the claim".to_string()));
+    }
+
+    #[test]
+    fn test_wikilink_at_position() {
+        let parser = WikilinkParser::new().unwrap();
+        let line = "See [[a]] and [[b#^x]].";
+
+        let (wikilink, range) = parser.wikilink_at(line, 1, 16).unwrap();
+        assert_eq!(wikilink.target, "b");
+        assert_eq!(range, 14..22);
+
+        assert!(parser.wikilink_at(line, 1, 11).is_none());
+    }
+
+    #[test]
+    fn test_hash_label_separator() {
+        let syntax = WikilinkSyntax {
+            label_separator: '#',
+            alias_first: false,
+        };
+        let parser = WikilinkParser::with_syntax(&syntax).unwrap();
+        let content = "[[target#heading|Alias]] and [[note#^block]] and [[a:b]]";
+        let path = PathBuf::from("test.typ");
+
+        let wikilinks = parser.parse_wikilinks(content, &path);
+
+        assert_eq!(wikilinks.len(), 3);
+        assert_eq!(wikilinks[0].target, "target");
+        assert_eq!(wikilinks[0].label, Some("heading".to_string()));
+        assert_eq!(wikilinks[0].alias, Some("Alias".to_string()));
+        assert_eq!(wikilinks[1].block, Some("block".to_string()));
+        assert_eq!(wikilinks[1].label, None);
+        assert_eq!(wikilinks[2].target, "a:b");
+        assert_eq!(wikilinks[2].label, None);
+    }
+
+    #[test]
+    fn test_alias_first_syntax() {
+        let syntax = WikilinkSyntax {
+            label_separator: ':',
+            alias_first: true,
+        };
+        let parser = WikilinkParser::with_syntax(&syntax).unwrap();
+        let content = "[[Shown text|target:section]] and [[plain]]";
+        let path = PathBuf::from("test.typ");
+
+        let wikilinks = parser.parse_wikilinks(content, &path);
+
+        assert_eq!(wikilinks.len(), 2);
+        assert_eq!(wikilinks[0].target, "target");
+        assert_eq!(wikilinks[0].label, Some("section".to_string()));
+        assert_eq!(wikilinks[0].alias, Some("Shown text".to_string()));
+        assert_eq!(wikilinks[1].target, "plain");
+        assert_eq!(wikilinks[1].alias, None);
+    }
+
+    #[test]
+    fn test_invalid_separator() {
+        let syntax = WikilinkSyntax {
+            label_separator: '|',
+            alias_first: false,
+        };
+        assert!(WikilinkParser::with_syntax(&syntax).is_err());
+    }
+
+    fn targets(content: &str) -> Vec<String> {
+        let parser = WikilinkParser::new().unwrap();
+        parser
+            .parse_wikilinks(content, &PathBuf::from("test.typ"))
+            .into_iter()
+            .map(|wikilink| wikilink.target)
+            .collect()
+    }
+
+    #[test]
+    fn test_escaped_closing_bracket() {
+        let parser = WikilinkParser::new().unwrap();
+        let wikilinks = parser.parse_wikilinks(r"[[a\]b]]", &PathBuf::from("test.typ"));
+
+        assert_eq!(wikilinks.len(), 1);
+        assert_eq!(wikilinks[0].target, "a]b");
+    }
+
+    #[test]
+    fn test_escaped_separators() {
+        let parser = WikilinkParser::new().unwrap();
+        let wikilinks = parser.parse_wikilinks(r"[[a\:b\|c|x\|y]]", &PathBuf::from("test.typ"));
+
+        assert_eq!(wikilinks.len(), 1);
+        assert_eq!(wikilinks[0].target, "a:b|c");
+        assert_eq!(wikilinks[0].label, None);
+        assert_eq!(wikilinks[0].alias, Some("x|y".to_string()));
+    }
+
+    #[test]
+    fn test_escaped_opening_brackets() {
+        assert!(targets(r"\[[not]] a link").is_empty());
+        assert!(targets(r"[\[not]] a link").is_empty());
+    }
+
+    #[test]
+    fn test_nested_brackets_are_not_links() {
+        assert!(targets("[[ [not a link] ]]").is_empty());
+        assert!(targets("[[a]b]]").is_empty());
+        assert_eq!(targets("[[[inner]]]"), vec!["inner"]);
+    }
+
+    #[test]
+    fn test_adjacent_links() {
+        assert_eq!(targets("[[a]][[b]]"), vec!["a", "b"]);
+        assert_eq!(targets("[[a|x]][[b:l]]"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_unterminated_links() {
+        assert!(targets("[[open").is_empty());
+        assert!(targets("[[split\nacross]]").is_empty());
+        assert_eq!(targets("[[open [[closed]]"), vec!["closed"]);
+    }
+
+    #[test]
+    fn test_empty_parts_are_rejected() {
+        assert!(targets("[[]]").is_empty());
+        assert!(targets("[[a|]]").is_empty());
+        assert!(targets("[[a:]]").is_empty());
+        assert!(targets("[[|a]]").is_empty());
+        assert!(targets("[[a|b|c]]").is_empty());
+    }
+
+    #[test]
+    fn test_links_in_math_are_ignored() {
+        assert_eq!(targets("$[[x]]$ and [[real]]"), vec!["real"]);
+        assert_eq!(targets("$\n[[x]]\n$\n[[real]]"), vec!["real"]);
+        assert_eq!(targets(r"costs \$5 [[real]]"), vec!["real"]);
+        // An unclosed dollar sign does not swallow the rest of the document
+        assert_eq!(targets("$ [[real]]"), vec!["real"]);
+    }
+
+    #[test]
+    fn test_links_in_raw_are_ignored() {
+        assert_eq!(targets("`[[x]]` and [[real]]"), vec!["real"]);
+        assert_eq!(targets("```\n[[x]]\n```\n[[real]]"), vec!["real"]);
+        assert_eq!(targets("`` [[real]]"), vec!["real"]);
+        // A backtick left after an escaped one opens nothing, as no run of one follows
+        assert_eq!(targets("\\`` [[a]] \\`` [[b]]"), vec!["a", "b"]);
+        assert_eq!(targets(&"\\``".repeat(10_000)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_positions_after_skipped_spans() {
+        let parser = WikilinkParser::new().unwrap();
+        let content = "$\nx\n$ [[a]]\n  [[b]]";
+
+        let wikilinks = parser.parse_wikilinks(content, &PathBuf::from("test.typ"));
+
+        assert_eq!(wikilinks.len(), 2);
+        assert_eq!((wikilinks[0].line, wikilinks[0].column), (3, 3));
+        assert_eq!((wikilinks[1].line, wikilinks[1].column), (4, 3));
+    }
+
+    #[test]
+    fn test_block_id_must_be_valid() {
+        assert!(targets("[[note#^bad id]]").is_empty());
+        assert!(targets("[[note#^]]").is_empty());
+    }
+
+    #[test]
+    fn test_target_prefix_at() {
+        let parser = WikilinkParser::new().unwrap();
+        assert_eq!(
+            parser.target_prefix_at("See [[2024-", 11),
+            Some((6, "2024-".to_string()))
+        );
+        assert_eq!(
+            parser.target_prefix_at("See [[", 6),
+            Some((6, String::new()))
+        );
+        assert_eq!(parser.target_prefix_at("[[done]] x", 10), None);
+        assert_eq!(parser.target_prefix_at("[[note:lab", 10), None);
+        assert_eq!(parser.target_prefix_at("[[note|ali", 10), None);
+
+        let parser = WikilinkParser::with_syntax(&WikilinkSyntax {
+            alias_first: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            parser.target_prefix_at("[[alias|no", 10),
+            Some((8, "no".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        for syntax in [
+            WikilinkSyntax::default(),
+            WikilinkSyntax {
+                label_separator: '#',
+                alias_first: true,
+            },
+        ] {
+            let parser = WikilinkParser::with_syntax(&syntax).unwrap();
+            for content in [
+                "[[a]]",
+                "[[dir/a:sec|Alias]]",
+                "[[a#^claim|x]]",
+                "[[a\\]b|c\\|d]]",
+                "[[a:b\\#^c]]",
+                "[[a#\\^b]]",
+            ] {
+                let wikilink = parser.parse_wikilinks(content, Path::new("")).remove(0);
+                let formatted = syntax.format(&wikilink);
+                let reparsed = parser.parse_wikilinks(&formatted, Path::new("")).remove(0);
+                assert_eq!(reparsed, wikilink, "{formatted}");
+            }
+        }
+        assert_eq!(
+            WikilinkSyntax::default().format(&Wikilink {
+                target: "a".to_string(),
+                alias: Some("x".to_string()),
+                label: Some("sec".to_string()),
+                block: None,
+                line: 1,
+                column: 1,
+            }),
+            "[[a:sec|x]]"
+        );
+    }
 }
@@ -0,0 +1,517 @@
+use crate::command::{
+    ArchiveNoteRequest, BackwardLinksRequest, BackwardLinksResponse, CalendarRequest,
+    CalendarResponse, ClustersRequest, ClustersResponse, DailyNoteRequest, DailyNoteResponse,
+    DeadLabelsRequest, DeadLabelsResponse, FiguresRequest, FiguresResponse, FileInfoRequest,
+    FileInfoResponse, FindReplaceRequest, FixRedirectsRequest, ForwardLinksRequest,
+    ForwardLinksResponse, GenerateMocRequest, GraphRequest, HistoryRequest, HistoryResponse,
+    LabelBacklinksRequest, LocalGraphRequest, LocalGraphResponse, MaterializeQueriesRequest,
+    MergeNotesRequest, MoveFileRequest, NewNoteRequest, NewNoteResponse, OutlineRequest,
+    OutlineResponse, PackagesResponse, PathBetweenRequest, PathBetweenResponse,
+    PeriodicNoteRequest, PeriodicNoteResponse, QueryRequest, QueryResponse, RecentNotesRequest,
+    RecentNotesResponse, RelatedRequest, RelatedResponse, RewriteLinksRequest, SearchRequest,
+    SearchResponse, StarNoteRequest, StarredNotesResponse, StatsRequest, StatsResponse,
+    TagsResponse, UnlinkedMentionsRequest, UnlinkedMentionsResponse, UnusedAttachmentsResponse,
+    UpdateMetadataRequest, VerifyIndexRequest, VerifyIndexResponse,
+};
+#[cfg(feature = "compile")]
+use crate::command::{ExportRequest, ExportResponse};
+#[cfg(feature = "embeddings")]
+use crate::command::{SemanticSearchRequest, SemanticSearchResponse};
+use crate::graph::Graph;
+use crate::index::attachments;
+use crate::lsp::{path_to_uri, uri_to_path};
+use anyhow::Result;
+use lsp_types::Uri;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use ts_rs::TS;
+
+/// The error for a request path that leads out of the vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutsideVault(pub PathBuf);
+
+impl fmt::Display for OutsideVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is outside the vault", self.0.display())
+    }
+}
+
+impl std::error::Error for OutsideVault {}
+
+/// How paths are written in command responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum PathStyle {
+    /// Absolute file system paths
+    #[default]
+    Absolute,
+    /// Relative to the vault root, for clients that see the vault under another path
+    Relative,
+    /// `file://` URIs
+    Uri,
+}
+
+/// Translates paths between the client and the vault: request paths may be absolute, relative
+/// to the vault root, or `file://` URIs, and response paths are written in `style`.
+#[derive(Debug, Clone, Copy)]
+pub struct VaultPaths<'a> {
+    root: &'a Path,
+    style: PathStyle,
+}
+
+impl<'a> VaultPaths<'a> {
+    pub fn new(root: &'a Path, style: PathStyle) -> Self {
+        Self { root, style }
+    }
+
+    /// The absolute path a client meant by `path`, with `.` and `..` resolved. Paths outside
+    /// the vault are rejected.
+    pub fn resolve(&self, path: &Path) -> Result<PathBuf> {
+        let uri = path
+            .to_str()
+            .filter(|path| path.starts_with("file:"))
+            .and_then(|path| Uri::from_str(path).ok());
+        let absolute = match uri.as_ref().and_then(uri_to_path) {
+            Some(path) => path,
+            None => self.root.join(path),
+        };
+        absolute
+            .strip_prefix(self.root)
+            .ok()
+            .and_then(attachments::normalize)
+            .map(|relative| self.root.join(relative))
+            .ok_or_else(|| OutsideVault(path.to_path_buf()).into())
+    }
+
+    /// `path` written in the configured style. Paths outside the vault stay absolute when
+    /// relative paths are requested.
+    pub fn present(&self, path: &Path) -> Result<PathBuf> {
+        Ok(match self.style {
+            PathStyle::Absolute => path.to_path_buf(),
+            PathStyle::Relative => path.strip_prefix(self.root).unwrap_or(path).to_path_buf(),
+            PathStyle::Uri => PathBuf::from(path_to_uri(path)?.as_str()),
+        })
+    }
+
+    /// Run a command with the paths in `request` resolved and those in its response presented.
+    pub fn run<P: HasPaths, R: HasPaths>(
+        &self,
+        mut request: P,
+        handle: impl FnOnce(P) -> Result<R>,
+    ) -> Result<R> {
+        for path in request.paths_mut() {
+            *path = self.resolve(path)?;
+        }
+        let mut response = handle(request)?;
+        for path in response.paths_mut() {
+            *path = self.present(path)?;
+        }
+        Ok(response)
+    }
+}
+
+/// Requests and responses carrying note or attachment paths.
+pub trait HasPaths {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        Vec::new()
+    }
+}
+
+impl HasPaths for () {}
+impl HasPaths for lsp_types::WorkspaceEdit {}
+impl HasPaths for ClustersRequest {}
+impl HasPaths for QueryRequest {}
+impl HasPaths for SearchRequest {}
+impl HasPaths for StatsRequest {}
+impl HasPaths for VerifyIndexRequest {}
+impl HasPaths for DailyNoteRequest {}
+impl HasPaths for CalendarRequest {}
+impl HasPaths for RewriteLinksRequest {}
+impl HasPaths for FixRedirectsRequest {}
+impl HasPaths for ForwardLinksResponse {}
+impl HasPaths for OutlineResponse {}
+impl HasPaths for RecentNotesRequest {}
+// Graph node ids are always vault-relative
+impl HasPaths for LocalGraphResponse {}
+impl HasPaths for Graph {}
+impl HasPaths for GraphRequest {}
+impl HasPaths for TagsResponse {}
+
+impl HasPaths for ForwardLinksRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file_path]
+    }
+}
+
+impl HasPaths for BackwardLinksRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file_path]
+    }
+}
+
+impl HasPaths for LabelBacklinksRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file_path]
+    }
+}
+
+impl HasPaths for OutlineRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for StarNoteRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for FileInfoRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for FileInfoResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.path]
+    }
+}
+
+impl HasPaths for HistoryRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for HistoryResponse {}
+
+impl HasPaths for PackagesResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.packages
+            .iter_mut()
+            .flat_map(|package| &mut package.versions)
+            .flat_map(|version| &mut version.notes)
+            .collect()
+    }
+}
+
+impl HasPaths for FiguresRequest {}
+
+impl HasPaths for FiguresResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.figures
+            .iter_mut()
+            .map(|entry| &mut entry.path)
+            .collect()
+    }
+}
+
+impl HasPaths for CalendarResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.days
+            .iter_mut()
+            .flat_map(|day| day.daily_note.iter_mut().chain(day.modified.iter_mut()))
+            .collect()
+    }
+}
+
+impl HasPaths for PeriodicNoteRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.from.iter_mut().collect()
+    }
+}
+
+impl HasPaths for PeriodicNoteResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.notes.iter_mut().map(|note| &mut note.path).collect()
+    }
+}
+
+impl HasPaths for LocalGraphRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for PathBetweenRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.from, &mut self.to]
+    }
+}
+
+impl HasPaths for RelatedRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for MaterializeQueriesRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.file_path.iter_mut().collect()
+    }
+}
+
+// `folder` is already relative to the vault root
+impl HasPaths for GenerateMocRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.file_path.iter_mut().collect()
+    }
+}
+
+impl HasPaths for FindReplaceRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.scope.iter_mut().collect()
+    }
+}
+
+impl HasPaths for UpdateMetadataRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file_path]
+    }
+}
+
+impl HasPaths for MoveFileRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.from, &mut self.to]
+    }
+}
+
+impl HasPaths for ArchiveNoteRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+impl HasPaths for MergeNotesRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.source, &mut self.target]
+    }
+}
+
+impl HasPaths for UnlinkedMentionsRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.file_path.iter_mut().collect()
+    }
+}
+
+impl HasPaths for DeadLabelsRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.file_path.iter_mut().collect()
+    }
+}
+
+// `folder` is already relative to the vault root
+impl HasPaths for NewNoteRequest {}
+
+impl HasPaths for BackwardLinksResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.links
+            .iter_mut()
+            .map(|link| &mut link.source_file)
+            .collect()
+    }
+}
+
+impl HasPaths for PathBetweenResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.path.iter_mut().flatten().collect()
+    }
+}
+
+impl HasPaths for ClustersResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.clusters
+            .iter_mut()
+            .flat_map(|cluster| {
+                cluster
+                    .notes
+                    .iter_mut()
+                    .chain([&mut cluster.representative])
+            })
+            .collect()
+    }
+}
+
+impl HasPaths for RecentNotesResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.notes.iter_mut().map(|note| &mut note.path).collect()
+    }
+}
+
+impl HasPaths for StarredNotesResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.notes.iter_mut().map(|note| &mut note.path).collect()
+    }
+}
+
+impl HasPaths for RelatedResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.notes.iter_mut().map(|note| &mut note.path).collect()
+    }
+}
+
+impl HasPaths for QueryResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.notes.iter_mut().map(|row| &mut row.path).collect()
+    }
+}
+
+impl HasPaths for SearchResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.files.iter_mut().collect()
+    }
+}
+
+impl HasPaths for VerifyIndexResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        let report = &mut self.report;
+        report
+            .missing_files
+            .iter_mut()
+            .chain(&mut report.stale_files)
+            .chain(&mut report.unindexed_files)
+            .collect()
+    }
+}
+
+impl HasPaths for StatsResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        let stats = &mut self.stats;
+        let linked = stats.most_linked.iter_mut().map(|note| &mut note.path);
+        let modified = stats
+            .recently_modified
+            .iter_mut()
+            .map(|note| &mut note.path);
+        linked.chain(modified).collect()
+    }
+}
+
+impl HasPaths for UnusedAttachmentsResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.files.iter_mut().collect()
+    }
+}
+
+impl HasPaths for DailyNoteResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.path]
+    }
+}
+
+impl HasPaths for NewNoteResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.path]
+    }
+}
+
+impl HasPaths for UnlinkedMentionsResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.mentions
+            .iter_mut()
+            .flat_map(|mention| [&mut mention.file, &mut mention.target])
+            .collect()
+    }
+}
+
+impl HasPaths for DeadLabelsResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.labels
+            .iter_mut()
+            .map(|label| &mut label.file)
+            .collect()
+    }
+}
+
+#[cfg(feature = "compile")]
+impl HasPaths for ExportRequest {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.file]
+    }
+}
+
+#[cfg(feature = "compile")]
+impl HasPaths for ExportResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        vec![&mut self.path]
+    }
+}
+
+#[cfg(feature = "embeddings")]
+impl HasPaths for SemanticSearchRequest {}
+
+#[cfg(feature = "embeddings")]
+impl HasPaths for SemanticSearchResponse {
+    fn paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        self.matches.iter_mut().map(|m| &mut m.path).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_and_present() {
+        let root = std::env::temp_dir().join("vault");
+        let note = root.join("notes").join("a b.typ");
+        let relative = VaultPaths::new(&root, PathStyle::Relative);
+
+        assert_eq!(relative.resolve(Path::new("notes/a b.typ")).unwrap(), note);
+        assert_eq!(relative.resolve(&note).unwrap(), note);
+        let uri = path_to_uri(&note).unwrap();
+        assert_eq!(relative.resolve(Path::new(uri.as_str())).unwrap(), note);
+        assert_eq!(
+            relative
+                .resolve(Path::new("notes/../notes/./a b.typ"))
+                .unwrap(),
+            note
+        );
+        assert!(relative.resolve(Path::new("../elsewhere.typ")).is_err());
+        assert!(
+            relative
+                .resolve(Path::new("notes/../../vault2/a.typ"))
+                .is_err()
+        );
+        assert!(
+            relative
+                .resolve(&std::env::temp_dir().join("a.typ"))
+                .is_err()
+        );
+        let outside_uri = path_to_uri(&std::env::temp_dir().join("a.typ")).unwrap();
+        assert!(relative.resolve(Path::new(outside_uri.as_str())).is_err());
+
+        assert_eq!(
+            relative.present(&note).unwrap(),
+            Path::new("notes").join("a b.typ")
+        );
+        let outside = std::env::temp_dir().join("elsewhere.typ");
+        assert_eq!(relative.present(&outside).unwrap(), outside);
+        let uris = VaultPaths::new(&root, PathStyle::Uri);
+        assert_eq!(uris.present(&note).unwrap(), Path::new(uri.as_str()));
+        let absolute = VaultPaths::new(&root, PathStyle::Absolute);
+        assert_eq!(absolute.present(&note).unwrap(), note);
+
+        let request = PathBetweenRequest {
+            from: PathBuf::from("a.typ"),
+            to: root.join("b.typ"),
+            direction: Default::default(),
+        };
+        let response = relative
+            .run(request, |request| {
+                assert_eq!(request.from, root.join("a.typ"));
+                Ok(SearchResponse {
+                    files: vec![request.from, request.to],
+                })
+            })
+            .unwrap();
+        assert_eq!(
+            response.files,
+            vec![PathBuf::from("a.typ"), PathBuf::from("b.typ")]
+        );
+    }
+}
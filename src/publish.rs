@@ -0,0 +1,340 @@
+#[cfg(feature = "compile")]
+use crate::compile::{self, ExportFormat};
+use crate::export::relative_path_between;
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::models::Wikilink;
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Outcome of publishing a vault; a note that fails to compile doesn't stop the rest.
+#[derive(Debug, Default)]
+pub struct PublishReport {
+    pub published: usize,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Compiles every note to an HTML page with the embedded Typst compiler, embedding the note as
+/// SVG followed by its links and backlinks, then writes an index and a tag page.
+pub struct Publisher<'a> {
+    index: &'a Index,
+    parser: &'a Parser,
+    site_title: String,
+}
+
+impl<'a> Publisher<'a> {
+    pub fn new(index: &'a Index, parser: &'a Parser, site_title: impl Into<String>) -> Self {
+        Self {
+            index,
+            parser,
+            site_title: site_title.into(),
+        }
+    }
+
+    pub async fn publish(&self, out_dir: &Path) -> Result<PublishReport> {
+        if !cfg!(feature = "compile") {
+            return Err(anyhow!("Publishing requires the compile feature"));
+        }
+        let mut report = PublishReport::default();
+        let notes = self.index.get_all_files()?;
+
+        for note in &notes {
+            match self.publish_note(note, out_dir).await {
+                Ok(()) => report.published += 1,
+                Err(e) => report.failed.push((note.clone(), e.to_string())),
+            }
+        }
+
+        std::fs::create_dir_all(out_dir)?;
+        std::fs::write(out_dir.join("index.html"), self.index_page(&notes)?)?;
+        std::fs::write(out_dir.join("tags.html"), self.tag_page()?)?;
+
+        Ok(report)
+    }
+
+    async fn publish_note(&self, note: &Path, out_dir: &Path) -> Result<()> {
+        let content = tokio::fs::read_to_string(note)
+            .await
+            .with_context(|| format!("Failed to read {}", note.display()))?;
+        let source = self.rewrite_source(note, &content)?;
+        let svg = self.compile(note, source).await?;
+
+        let out_path = out_dir.join(self.relative(note)).with_extension("html");
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let body = format!("{svg}\n{}", self.links_section(note)?);
+        std::fs::write(&out_path, page(&self.title(note)?, &body))?;
+
+        Ok(())
+    }
+
+    // The compiled note; the source is compiled in place of the note, so relative imports and
+    // assets still resolve and nothing is written to the vault
+    #[cfg(feature = "compile")]
+    async fn compile(&self, note: &Path, source: String) -> Result<String> {
+        let root = self.index.root().to_path_buf();
+        let note = note.to_path_buf();
+        let svg = tokio::task::spawn_blocking(move || {
+            compile::export(&root, &note, Some(source), ExportFormat::Svg)
+        })
+        .await??;
+        Ok(String::from_utf8(svg)?)
+    }
+
+    #[cfg(not(feature = "compile"))]
+    async fn compile(&self, _note: &Path, _source: String) -> Result<String> {
+        Err(anyhow!("Publishing requires the compile feature"))
+    }
+
+    // SVG keeps no hyperlinks, so the notes a page links to and from are listed after it
+    fn links_section(&self, note: &Path) -> Result<String> {
+        let mut targets = Vec::new();
+        for link in self.index.get_forward_links(note)? {
+            if let Some(target) = self.index.resolve_target(&link.target)?
+                && target.extension().is_some_and(|ext| ext == "typ")
+                && !targets.contains(&target)
+            {
+                targets.push(target);
+            }
+        }
+        let mut sources: Vec<_> = self
+            .index
+            .get_backward_links(note)?
+            .into_iter()
+            .map(|(source, _)| source)
+            .collect();
+        sources.dedup();
+
+        let mut out = String::new();
+        for (heading, notes) in [("Links", targets), ("Backlinks", sources)] {
+            if notes.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "<h2>{heading}</h2>\n<ul>");
+            for target in notes {
+                let _ = writeln!(
+                    out,
+                    "<li><a href=\"{}\">{}</a></li>",
+                    escape_html(&self.href(note, &target, None)),
+                    escape_html(&self.title(&target)?)
+                );
+            }
+            out.push_str("</ul>\n");
+        }
+        Ok(out)
+    }
+
+    /// The note's source with wikilinks replaced by `#link`s.
+    pub fn rewrite_source(&self, note: &Path, content: &str) -> Result<String> {
+        let links = self.parser.wikilink_spans(content);
+        let mut out = String::new();
+
+        for (idx, line) in content.split_inclusive('\n').enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            let mut pos = 0;
+            for (link, range) in links.iter().filter(|(link, _)| link.line == idx + 1) {
+                out.extend(&chars[pos..range.start]);
+                out.push_str(&self.render_link(note, link)?);
+                pos = range.end;
+            }
+            out.extend(&chars[pos..]);
+        }
+
+        Ok(out)
+    }
+
+    fn render_link(&self, note: &Path, link: &Wikilink) -> Result<String> {
+        let Some(target) = self.index.resolve_target(&link.target)? else {
+            return Ok(escape_markup(link.alias.as_deref().unwrap_or(&link.target)));
+        };
+
+        let text = match &link.alias {
+            Some(alias) => alias.clone(),
+            None if target.extension().is_some_and(|ext| ext == "typ") => self.title(&target)?,
+            None => link.target.clone(),
+        };
+        let anchor = link.block.as_deref().or(link.label.as_deref());
+        Ok(format!(
+            "#link(\"{}\")[{}]",
+            escape_string(&self.href(note, &target, anchor)),
+            escape_markup(&text)
+        ))
+    }
+
+    // Notes link to each other's pages; attachments are referenced where they are
+    fn href(&self, from: &Path, target: &Path, anchor: Option<&str>) -> String {
+        let from_dir = from.parent().unwrap_or(from);
+        let target = if target.extension().is_some_and(|ext| ext == "typ") {
+            target.with_extension("html")
+        } else {
+            target.to_path_buf()
+        };
+        let path = relative_path_between(from_dir, &target)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        match anchor {
+            Some(anchor) => format!("{path}#{anchor}"),
+            None => path,
+        }
+    }
+
+    fn title(&self, note: &Path) -> Result<String> {
+        let title = self
+            .index
+            .get_file(note)?
+            .and_then(|file| file.metadata.title);
+        Ok(title.unwrap_or_else(|| {
+            note.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }))
+    }
+
+    fn relative(&self, note: &Path) -> PathBuf {
+        note.strip_prefix(self.index.root())
+            .unwrap_or(note)
+            .to_path_buf()
+    }
+
+    fn page_link(&self, note: &Path) -> Result<String> {
+        let href = self.href(self.index.root().join("index.html").as_path(), note, None);
+        Ok(format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(&href),
+            escape_html(&self.title(note)?)
+        ))
+    }
+
+    fn index_page(&self, notes: &[PathBuf]) -> Result<String> {
+        let mut body = String::from("<ul>\n");
+        for note in notes {
+            let _ = writeln!(body, "<li>{}</li>", self.page_link(note)?);
+        }
+        body.push_str("</ul>\n<p><a href=\"tags.html\">Tags</a></p>\n");
+        Ok(page(&self.site_title, &body))
+    }
+
+    fn tag_page(&self) -> Result<String> {
+        let mut tags: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for (path, key, value) in self.index.get_all_metadata()? {
+            if key == "tags" {
+                tags.entry(value)
+                    .or_default()
+                    .push(self.index.root().join(path));
+            }
+        }
+
+        let mut body = String::new();
+        for (tag, notes) in tags {
+            let _ = writeln!(body, "<h2 id=\"{0}\">#{0}</h2>\n<ul>", escape_html(&tag));
+            for note in notes {
+                let _ = writeln!(body, "<li>{}</li>", self.page_link(&note)?);
+            }
+            body.push_str("</ul>\n");
+        }
+        Ok(page(&format!("{} · Tags", self.site_title), &body))
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n\
+         <body>\n<h1>{0}</h1>\n{body}</body>\n</html>\n",
+        escape_html(title)
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Contents of a Typst string literal
+fn escape_string(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Text placed inside a Typst content block, where markup characters must be escaped
+pub(crate) fn escape_markup(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '[' | ']' | '*' | '_' | '#' | '$' | '<' | '>' | '@' | '`' | '=' | '~'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note_with, vault};
+
+    #[test]
+    fn test_rewrite_source() {
+        let content = "See [[other:sec]] and [[missing|gone_]].\n";
+        let (temp_dir, mut index) = vault(&[("a.typ", content)]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        add_note_with(&mut index, "sub/other.typ", "= Other <sec>\n", |parsed| {
+            parsed.metadata.title = Some("Other *note*".to_string());
+            parsed.metadata.tags = vec!["t".to_string()];
+        });
+
+        let publisher = Publisher::new(&index, &parser, "Vault");
+        let a = root.join("a.typ");
+        assert_eq!(
+            publisher.rewrite_source(&a, content).unwrap(),
+            "See #link(\"sub/other.html#sec\")[Other \\*note\\*] and gone\\_.\n"
+        );
+
+        let other = root.join("sub").join("other.typ");
+        assert_eq!(
+            publisher.links_section(&other).unwrap(),
+            "<h2>Backlinks</h2>\n<ul>\n<li><a href=\"../a.html\">a</a></li>\n</ul>\n"
+        );
+        assert!(publisher.links_section(&a).unwrap().starts_with(
+            "<h2>Links</h2>\n<ul>\n<li><a href=\"sub/other.html\">Other *note*</a></li>"
+        ));
+
+        let tags = publisher.tag_page().unwrap();
+        assert!(tags.contains("<a href=\"sub/other.html\">Other *note*</a>"));
+    }
+
+    #[cfg(feature = "compile")]
+    #[test]
+    fn test_publish() {
+        let (temp_dir, index) = vault(&[("a.typ", "= A\nSee [[a]].")]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+
+        let out = root.join("site");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let report = runtime
+            .block_on(Publisher::new(&index, &parser, "Vault").publish(&out))
+            .unwrap();
+        assert_eq!(report.published, 1);
+        let page = std::fs::read_to_string(out.join("a.html")).unwrap();
+        assert!(page.contains("<svg"));
+        assert!(page.contains("<a href=\"a.html\">a</a>"));
+        // Nothing staged next to the note
+        assert!(std::fs::read_dir(root).unwrap().all(|entry| {
+            !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with('.')
+        }));
+    }
+}
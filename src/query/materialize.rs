@@ -0,0 +1,198 @@
+use crate::index::Index;
+use crate::parser::models::Wikilink;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::query;
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+pub const MARKER: &str = "#pkm-query(";
+pub const BEGIN: &str = "// pkm-query:begin";
+pub const END: &str = "// pkm-query:end";
+
+/// Regenerate the block below every `#pkm-query("...")` marker in `content`, the note at
+/// `path`: a list of links to the matching notes between `BEGIN` and `END` comments. Returns
+/// the new content, or `None` if nothing changes.
+pub fn materialize(
+    index: &Index,
+    syntax: &WikilinkSyntax,
+    path: &Path,
+    content: &str,
+) -> Result<Option<String>> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut output: Vec<String> = Vec::with_capacity(lines.len());
+    let mut changed = false;
+
+    let mut line_idx = 0;
+    while line_idx < lines.len() {
+        let line = lines[line_idx];
+        output.push(line.to_string());
+        line_idx += 1;
+        let Some(query) = marker_query(line) else {
+            continue;
+        };
+
+        let generated = render(index, syntax, path, &query)
+            .with_context(|| format!("Invalid query on line {line_idx}"))?;
+        let old_start = line_idx;
+        if lines.get(line_idx).is_some_and(|l| l.trim() == BEGIN) {
+            let Some(end) = lines[line_idx..].iter().position(|l| l.trim() == END) else {
+                bail!("Generated block after line {line_idx} has no `{END}`");
+            };
+            line_idx += end + 1;
+        }
+        changed |= lines[old_start..line_idx].iter().ne(generated.iter());
+        output.extend(generated);
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+    let mut content_out = output.join("\n");
+    if content.ends_with('\n') {
+        content_out.push('\n');
+    }
+    Ok(Some(content_out))
+}
+
+/// The query in a `#pkm-query("...")` marker line.
+pub fn marker_query(line: &str) -> Option<String> {
+    let mut chars = line.trim().strip_prefix(MARKER)?.strip_prefix('"')?.chars();
+    let mut query = String::new();
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+                'n' => query.push('\n'),
+                't' => query.push('\t'),
+                c => query.push(c),
+            },
+            c => query.push(c),
+        }
+    }
+    (chars.as_str().trim() == ")").then_some(query)
+}
+
+fn render(index: &Index, syntax: &WikilinkSyntax, path: &Path, query: &str) -> Result<Vec<String>> {
+    let rows = query::run(index, &query::parse(query)?, &["title".to_string()])?;
+    let mut lines = vec![BEGIN.to_string()];
+    for row in rows.iter().filter(|row| row.path != path) {
+        let link = note_link(
+            index.root(),
+            syntax,
+            &row.path,
+            row.fields["title"].as_str(),
+        );
+        lines.push(format!("- {link}"));
+    }
+    lines.push(END.to_string());
+    Ok(lines)
+}
+
+/// A wikilink to `path` by its vault-relative path, showing `title` if it has one.
+pub(crate) fn note_link(
+    root: &Path,
+    syntax: &WikilinkSyntax,
+    path: &Path,
+    title: Option<&str>,
+) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    syntax.format(&Wikilink {
+        target: relative.with_extension("").to_string_lossy().into_owned(),
+        alias: title.map(str::to_string),
+        label: None,
+        block: None,
+        line: 0,
+        column: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::models::{Metadata, ParsedFile};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_marker_query() {
+        assert_eq!(
+            marker_query("  #pkm-query(\"title = \\\"A\\\" SORT path\")").as_deref(),
+            Some("title = \"A\" SORT path")
+        );
+        assert_eq!(marker_query("#pkm-query(\"open"), None);
+        assert_eq!(marker_query("#pkm-query(\"x\") trailing"), None);
+    }
+
+    #[test]
+    fn test_materialize() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+        for (name, title, tags) in [
+            ("moc", None, &["project"][..]),
+            ("notes/alpha", Some("Alpha"), &["project"][..]),
+            ("beta", None, &["project"][..]),
+            ("other", None, &[][..]),
+        ] {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata {
+                    title: title.map(str::to_string),
+                    tags: tags.iter().map(|t| t.to_string()).collect(),
+                    ..Metadata::default()
+                },
+                wikilinks: Vec::new(),
+                labels: Vec::new(),
+                headings: Vec::new(),
+                blocks: Vec::new(),
+                attachments: Vec::new(),
+                stats: Default::default(),
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+        let syntax = WikilinkSyntax::default();
+        let moc = root.join("moc.typ");
+
+        let content = "= Projects\n#pkm-query(\"tag:project SORT path\")\nafter\n";
+        let first = materialize(&index, &syntax, &moc, content)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            first,
+            "= Projects\n#pkm-query(\"tag:project SORT path\")\n// pkm-query:begin\n\
+             - [[beta]]\n- [[notes/alpha|Alpha]]\n// pkm-query:end\nafter\n"
+        );
+        assert!(
+            materialize(&index, &syntax, &moc, &first)
+                .unwrap()
+                .is_none()
+        );
+
+        let stale = first.replace("- [[beta]]\n", "");
+        let updated = materialize(&index, &syntax, &moc, &stale).unwrap().unwrap();
+        assert_eq!(updated, first);
+
+        let unterminated = "#pkm-query(\"tag:project\")\n// pkm-query:begin\n- [[beta]]\n";
+        assert!(materialize(&index, &syntax, &moc, unterminated).is_err());
+        assert!(materialize(&index, &syntax, &moc, "#pkm-query(\"tag:\")\n").is_err());
+    }
+
+    #[test]
+    fn test_note_link() {
+        let root = Path::new("/vault");
+        let note = root.join("notes/a.typ");
+        let syntax = WikilinkSyntax::default();
+        assert_eq!(
+            note_link(root, &syntax, &note, Some("A | [B]")),
+            "[[notes/a|A \\| \\[B\\]]]"
+        );
+        assert_eq!(note_link(root, &syntax, &note, None), "[[notes/a]]");
+    }
+}
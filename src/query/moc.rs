@@ -0,0 +1,262 @@
+use crate::index::Index;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::publish::escape_markup;
+use crate::query::materialize::note_link;
+use anyhow::{Result, bail};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+pub const BEGIN: &str = "// pkm-moc:begin";
+pub const END: &str = "// pkm-moc:end";
+
+/// What a map of content lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MocSource<'a> {
+    /// Notes with the tag, grouped by subtag (`tag/sub`)
+    Tag(&'a str),
+    /// Notes in the vault-relative folder, grouped by subfolder
+    Folder(&'a Path),
+}
+
+impl MocSource<'_> {
+    /// The begin marker of this map's block, so a note can hold several maps.
+    pub fn marker(&self) -> String {
+        match self {
+            MocSource::Tag(tag) => format!("{BEGIN} tag:{tag}"),
+            MocSource::Folder(folder) => format!("{BEGIN} folder:{}", folder.display()),
+        }
+    }
+}
+
+/// The content of the map note at `path`, given its
+/// current `content` if it exists: the generated block replaces the one with the same marker,
+/// or is appended, or starts a new note with a heading. Regenerating an up-to-date map returns
+/// `content` unchanged.
+pub fn generate(
+    index: &Index,
+    syntax: &WikilinkSyntax,
+    source: MocSource,
+    path: &Path,
+    content: Option<&str>,
+) -> Result<String> {
+    let block = render(index, syntax, source, path)?;
+    let Some(content) = content else {
+        let title = match source {
+            MocSource::Tag(tag) => tag.to_string(),
+            MocSource::Folder(folder) => folder.display().to_string(),
+        };
+        return Ok(format!(
+            "= {}\n\n{}\n",
+            escape_markup(&title),
+            block.join("\n")
+        ));
+    };
+
+    let lines: Vec<&str> = content.lines().collect();
+    let marker = source.marker();
+    let Some(begin) = lines.iter().position(|line| line.trim() == marker) else {
+        let separator = match content {
+            "" => "",
+            content if content.ends_with('\n') => "\n",
+            _ => "\n\n",
+        };
+        return Ok(format!("{content}{separator}{}\n", block.join("\n")));
+    };
+    let Some(end) = lines[begin..].iter().position(|line| line.trim() == END) else {
+        bail!("Map of content on line {} has no `{END}`", begin + 1);
+    };
+
+    let mut output: Vec<&str> = lines[..begin].to_vec();
+    output.extend(block.iter().map(String::as_str));
+    output.extend(&lines[begin + end + 1..]);
+    let mut output = output.join("\n");
+    if content.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Where a map goes: `path` if given, otherwise `<tag>.typ` at the vault root for a tag and
+/// `<folder>/index.typ` for a folder. Fails for a folder outside the vault.
+pub fn moc_path(root: &Path, source: MocSource, path: Option<PathBuf>) -> Result<PathBuf> {
+    if let MocSource::Folder(folder) = source
+        && !folder
+            .components()
+            .all(|part| matches!(part, Component::Normal(_) | Component::CurDir))
+    {
+        bail!("{} is not a folder of the vault", folder.display());
+    }
+    Ok(path.unwrap_or_else(|| match source {
+        MocSource::Tag(tag) => root.join(format!("{}.typ", tag.replace(['/', '\\'], "-"))),
+        MocSource::Folder(folder) => root.join(folder).join("index.typ"),
+    }))
+}
+
+// The generated block: links to the matching notes, those directly in the tag or folder first
+// and the rest under a heading per subtag or subfolder
+fn render(
+    index: &Index,
+    syntax: &WikilinkSyntax,
+    source: MocSource,
+    path: &Path,
+) -> Result<Vec<String>> {
+    let root = index.root();
+    let archived = index.archived_notes()?;
+    let mut titles: HashMap<PathBuf, String> = HashMap::new();
+    let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+
+    match source {
+        MocSource::Tag(tag) => {
+            for (note, key, value) in index.get_all_metadata()? {
+                let note = root.join(note);
+                match key.as_str() {
+                    "title" => {
+                        titles.insert(note, value);
+                    }
+                    "tags" => {
+                        let group = if value == tag {
+                            String::new()
+                        } else if let Some(sub) = value
+                            .strip_prefix(tag)
+                            .and_then(|rest| rest.strip_prefix('/'))
+                        {
+                            sub.to_string()
+                        } else {
+                            continue;
+                        };
+                        groups.entry(group).or_default().push(note);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        MocSource::Folder(folder) => {
+            let folder = root.join(folder);
+            for note in index.get_all_files()? {
+                let Ok(relative) = note.strip_prefix(&folder) else {
+                    continue;
+                };
+                let group = relative
+                    .parent()
+                    .map(|parent| parent.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                groups.entry(group).or_default().push(note);
+            }
+            for (note, key, value) in index.get_all_metadata()? {
+                if key == "title" {
+                    titles.insert(root.join(note), value);
+                }
+            }
+        }
+    }
+
+    let mut lines = vec![source.marker()];
+    for (group, mut notes) in groups {
+        notes.retain(|note| note != path && !archived.contains(note));
+        if notes.is_empty() {
+            continue;
+        }
+        let name = |note: &PathBuf| match titles.get(note) {
+            Some(title) => title.to_lowercase(),
+            None => note
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        };
+        notes.sort_by_cached_key(|note| (name(note), note.clone()));
+        notes.dedup();
+
+        if !group.is_empty() {
+            if lines.len() > 1 {
+                lines.push(String::new());
+            }
+            lines.push(format!("== {}", escape_markup(&group)));
+        }
+        for note in &notes {
+            let title = titles.get(note).map(String::as_str);
+            lines.push(format!("- {}", note_link(root, syntax, note, title)));
+        }
+    }
+    lines.push(END.to_string());
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::models::{Metadata, ParsedFile};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generate() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+        for (name, title, tags) in [
+            ("projects/alpha", Some("Alpha"), &["project"][..]),
+            ("projects/web/site", None, &["project/web"][..]),
+            ("beta", None, &["project", "project/web"][..]),
+            ("other", None, &["projects"][..]),
+        ] {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata {
+                    title: title.map(str::to_string),
+                    tags: tags.iter().map(|t| t.to_string()).collect(),
+                    ..Metadata::default()
+                },
+                wikilinks: Vec::new(),
+                labels: Vec::new(),
+                headings: Vec::new(),
+                blocks: Vec::new(),
+                attachments: Vec::new(),
+                stats: Default::default(),
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+        let syntax = WikilinkSyntax::default();
+
+        let tag = MocSource::Tag("project");
+        let path = moc_path(root, tag, None).unwrap();
+        assert_eq!(path, root.join("project.typ"));
+        let created = generate(&index, &syntax, tag, &path, None).unwrap();
+        assert_eq!(
+            created,
+            "= project\n\n// pkm-moc:begin tag:project\n- [[projects/alpha|Alpha]]\n\
+             - [[beta]]\n\n== web\n- [[beta]]\n- [[projects/web/site]]\n// pkm-moc:end\n"
+        );
+        assert_eq!(
+            generate(&index, &syntax, tag, &path, Some(&created)).unwrap(),
+            created
+        );
+
+        // The block is replaced in place, keeping what the user wrote around it
+        let edited = created.replace("- [[beta]]\n\n", "") + "My notes\n";
+        let regenerated = generate(&index, &syntax, tag, &path, Some(&edited)).unwrap();
+        assert_eq!(regenerated, created.clone() + "My notes\n");
+
+        let folder = MocSource::Folder(Path::new("projects"));
+        let path = moc_path(root, folder, None).unwrap();
+        assert_eq!(path, root.join("projects/index.typ"));
+        for outside in ["../elsewhere", "projects/../..", "/etc"] {
+            assert!(moc_path(root, MocSource::Folder(Path::new(outside)), None).is_err());
+        }
+        let appended = generate(&index, &syntax, folder, &path, Some("= Projects")).unwrap();
+        assert_eq!(
+            appended,
+            "= Projects\n\n// pkm-moc:begin folder:projects\n- [[projects/alpha|Alpha]]\n\n\
+             == web\n- [[projects/web/site]]\n// pkm-moc:end\n"
+        );
+
+        let unterminated = "// pkm-moc:begin tag:project\n- [[beta]]\n";
+        assert!(generate(&index, &syntax, tag, &path, Some(unterminated)).is_err());
+    }
+}
@@ -0,0 +1,448 @@
+pub mod materialize;
+pub mod moc;
+pub mod parser;
+
+use crate::git::CommitInfo;
+use crate::index::{Index, MetadataEntry, MetadataType, NoteRecord};
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+pub use parser::parse;
+
+/// A parsed query: an optional filter, sort keys, and a limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub filter: Option<Expr>,
+    pub sort: Vec<SortKey>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub field: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare {
+        field: String,
+        op: CompareOp,
+        value: Value,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// `:` matches tags and their nested tags, substrings of text, dates on the same day,
+    /// and equal values otherwise. Its literal is kept as text.
+    Matches,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A typed field or literal value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(f64),
+    Date(NaiveDateTime),
+    Bool(bool),
+}
+
+impl Value {
+    /// Type an unquoted literal: number, `YYYY-MM-DD` date, boolean, or text.
+    pub fn parse(word: &str) -> Value {
+        if let Some(number) = word.parse::<f64>().ok().filter(|n| n.is_finite()) {
+            Value::Number(number)
+        } else if let Some(date) = parse_date(word) {
+            Value::Date(date)
+        } else if let Ok(flag) = word.parse() {
+            Value::Bool(flag)
+        } else {
+            Value::Text(word.to_string())
+        }
+    }
+
+    fn from_json(json: serde_json::Value) -> Vec<Value> {
+        match json {
+            serde_json::Value::Number(n) => n.as_f64().map(Value::Number).into_iter().collect(),
+            serde_json::Value::Bool(flag) => vec![Value::Bool(flag)],
+            serde_json::Value::String(text) => vec![match parse_date(&text) {
+                Some(date) => Value::Date(date),
+                None => Value::Text(text),
+            }],
+            serde_json::Value::Array(items) => {
+                items.into_iter().flat_map(Value::from_json).collect()
+            }
+            serde_json::Value::Null => Vec::new(),
+            other => vec![Value::Text(other.to_string())],
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Text(text) => text.clone().into(),
+            Value::Number(number) => (*number).into(),
+            Value::Date(date) => date.format("%Y-%m-%dT%H:%M:%S").to_string().into(),
+            Value::Bool(flag) => (*flag).into(),
+        }
+    }
+
+    /// Compare across types where one side can be read as the other's type.
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Date(a), Value::Date(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+            (Value::Text(text), other) => Value::parse(text).typed_compare(other),
+            (value, Value::Text(text)) => value.typed_compare(&Value::parse(text)),
+            _ => None,
+        }
+    }
+
+    fn typed_compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Text(_), _) | (_, Value::Text(_)) => None,
+            _ => self.compare(other),
+        }
+    }
+}
+
+/// An ISO 8601 date or date-time, or `today`, `yesterday` or `tomorrow` (local midnight).
+fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    let today = Local::now().date_naive();
+    let date = match text.to_ascii_lowercase().as_str() {
+        "today" => today,
+        "yesterday" => today.pred_opt()?,
+        "tomorrow" => today.succ_opt()?,
+        _ => {
+            return NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+                .ok()
+                .or_else(|| {
+                    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                        .ok()?
+                        .and_hms_opt(0, 0, 0)
+                });
+        }
+    };
+    date.and_hms_opt(0, 0, 0)
+}
+
+/// A matching note and the requested fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct QueryRow {
+    pub path: PathBuf,
+    /// Missing fields are `null`; tags, aliases and list metadata are arrays
+    #[ts(type = "Record<string, any>")]
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+/// Evaluate `query` against the index, returning `fields` of each matching note.
+pub fn run(index: &Index, query: &Query, fields: &[String]) -> Result<Vec<QueryRow>> {
+    let root = index.root();
+    let mut records: Vec<NoteRecord> = index
+        .get_note_records()?
+        .into_iter()
+        .filter(|record| {
+            query
+                .filter
+                .as_ref()
+                .is_none_or(|filter| matches(filter, root, record))
+        })
+        .collect();
+
+    for key in query.sort.iter().rev() {
+        records.sort_by(|a, b| {
+            let a = field_values(root, a, &key.field);
+            let b = field_values(root, b, &key.field);
+            let ordering = match (a.first(), b.first()) {
+                (Some(a), Some(b)) => a.compare(b).unwrap_or(Ordering::Equal),
+                // Notes without the field sort last either way
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            if key.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+    records.truncate(query.limit.unwrap_or(usize::MAX));
+
+    Ok(records
+        .iter()
+        .map(|record| QueryRow {
+            path: record.path.clone(),
+            fields: fields
+                .iter()
+                .map(|field| {
+                    let key = field.to_lowercase();
+                    let values = field_values(root, record, &key);
+                    let json = if is_list(&key) {
+                        values.iter().map(Value::to_json).collect()
+                    } else {
+                        match values.as_slice() {
+                            [] => serde_json::Value::Null,
+                            [value] => value.to_json(),
+                            values => values.iter().map(Value::to_json).collect(),
+                        }
+                    };
+                    (field.clone(), json)
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+fn matches(expr: &Expr, root: &Path, record: &NoteRecord) -> bool {
+    match expr {
+        Expr::And(a, b) => matches(a, root, record) && matches(b, root, record),
+        Expr::Or(a, b) => matches(a, root, record) || matches(b, root, record),
+        Expr::Not(expr) => !matches(expr, root, record),
+        Expr::Compare { field, op, value } => {
+            let values = field_values(root, record, field);
+            // `!=` holds when no value is equal, including when the field is missing
+            if *op == CompareOp::Ne {
+                return !values
+                    .iter()
+                    .any(|v| v.compare(value) == Some(Ordering::Equal));
+            }
+            values.iter().any(|v| compare(field, v, *op, value))
+        }
+    }
+}
+
+fn compare(field: &str, actual: &Value, op: CompareOp, expected: &Value) -> bool {
+    let ordering = actual.compare(expected);
+    match op {
+        CompareOp::Matches => match (actual, expected) {
+            (Value::Text(actual), Value::Text(expected)) if is_tag(field) => {
+                let (actual, expected) = (actual.to_lowercase(), expected.to_lowercase());
+                actual == expected || actual.starts_with(&format!("{expected}/"))
+            }
+            (Value::Text(actual), Value::Text(expected)) => {
+                actual.to_lowercase().contains(&expected.to_lowercase())
+            }
+            (Value::Date(actual), Value::Text(expected)) => {
+                parse_date(expected).is_some_and(|expected| actual.date() == expected.date())
+            }
+            _ => ordering == Some(Ordering::Equal),
+        },
+        CompareOp::Eq => ordering == Some(Ordering::Equal),
+        CompareOp::Ne => ordering.is_some_and(|o| o != Ordering::Equal),
+        CompareOp::Lt => ordering == Some(Ordering::Less),
+        CompareOp::Le => ordering.is_some_and(|o| o != Ordering::Greater),
+        CompareOp::Gt => ordering == Some(Ordering::Greater),
+        CompareOp::Ge => ordering.is_some_and(|o| o != Ordering::Less),
+    }
+}
+
+fn is_tag(field: &str) -> bool {
+    matches!(field, "tag" | "tags")
+}
+
+fn is_list(field: &str) -> bool {
+    matches!(field, "tag" | "tags" | "alias" | "aliases")
+}
+
+/// The values of `field` for a note. Built-in fields are `path`, `name`, `title`,
+/// `tag(s)`, `alias(es)`, `created`, `modified`, `links`, `backlinks`, and with git history
+/// `commit`, `author`, `committed` and `commits_ago`; anything else is looked up in the
+/// note's custom metadata.
+fn field_values(root: &Path, record: &NoteRecord, field: &str) -> Vec<Value> {
+    let metadata = |key: &str| -> Vec<Value> {
+        record
+            .metadata
+            .iter()
+            .filter(|entry| entry.key == key)
+            .map(|entry| Value::Text(entry.value.clone()))
+            .collect()
+    };
+    let timestamp = |seconds: Option<i64>| -> Vec<Value> {
+        seconds
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .map(|time| Value::Date(time.with_timezone(&Local).naive_local()))
+            .into_iter()
+            .collect()
+    };
+
+    let commit = |value: &dyn Fn(&CommitInfo) -> Value| -> Vec<Value> {
+        record.last_commit.iter().map(value).collect()
+    };
+
+    match field {
+        "path" => {
+            let relative = record.path.strip_prefix(root).unwrap_or(&record.path);
+            vec![Value::Text(relative.to_string_lossy().into_owned())]
+        }
+        "name" => record
+            .path
+            .file_stem()
+            .map(|stem| Value::Text(stem.to_string_lossy().into_owned()))
+            .into_iter()
+            .collect(),
+        "title" => metadata("title"),
+        "tag" | "tags" => metadata("tags"),
+        "alias" | "aliases" => metadata("alias"),
+        "created" => timestamp(record.created_at),
+        "modified" => timestamp(record.modified_at),
+        "links" => vec![Value::Number(record.link_count as f64)],
+        "backlinks" => vec![Value::Number(record.backlink_count as f64)],
+        "commit" => commit(&|commit| Value::Text(commit.hash.clone())),
+        "author" => commit(&|commit| Value::Text(commit.author.clone())),
+        "committed" => timestamp(record.last_commit.as_ref().map(|c| c.committed_at)),
+        "commits_ago" => commit(&|commit| Value::Number(commit.commits_ago as f64)),
+        key => record
+            .metadata
+            .iter()
+            .filter(|entry| entry.key.eq_ignore_ascii_case(key))
+            .flat_map(typed_value)
+            .collect(),
+    }
+}
+
+/// A custom metadata value, typed from its stored column where possible.
+fn typed_value(entry: &MetadataEntry) -> Vec<Value> {
+    let typed = match (entry.value_type, entry.number) {
+        (MetadataType::Number, Some(number)) => Some(Value::Number(number)),
+        (MetadataType::Boolean, Some(number)) => Some(Value::Bool(number != 0.0)),
+        (MetadataType::Date, Some(seconds)) => {
+            DateTime::from_timestamp(seconds as i64, 0).map(|time| Value::Date(time.naive_utc()))
+        }
+        _ => None,
+    };
+    if let Some(value) = typed {
+        return vec![value];
+    }
+    match serde_json::from_str(&entry.value) {
+        Ok(serde_json::Value::String(text)) => vec![Value::Text(text)],
+        Ok(json) => Value::from_json(json),
+        Err(_) => vec![Value::Text(entry.value.clone())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::models::{Metadata, ParsedFile};
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_run() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+        let notes = [
+            ("alpha", "Alpha", &["project/web"][..], 3, "2024-02-01"),
+            ("beta", "Beta", &["project"][..], 1, "2023-06-01"),
+            ("gamma", "Gamma", &["personal"][..], 5, "2024-03-01"),
+        ];
+        for (name, title, tags, priority, due) in notes {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, "").unwrap();
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata {
+                    title: Some(title.to_string()),
+                    tags: tags.iter().map(|t| t.to_string()).collect(),
+                    alias: Vec::new(),
+                    custom: HashMap::from([
+                        ("priority".to_string(), priority.into()),
+                        ("due".to_string(), due.into()),
+                    ]),
+                },
+                wikilinks: Vec::new(),
+                labels: Vec::new(),
+                headings: Vec::new(),
+                blocks: Vec::new(),
+                attachments: Vec::new(),
+                stats: Default::default(),
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let names = |query: &str| -> Vec<String> {
+            run(&index, &parse(query).unwrap(), &["name".to_string()])
+                .unwrap()
+                .into_iter()
+                .map(|row| row.fields["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(names("tag:project SORT title DESC"), vec!["beta", "alpha"]);
+        assert_eq!(names("priority >= 3 SORT priority"), vec!["alpha", "gamma"]);
+        assert_eq!(
+            names("due > 2024-01-01 AND NOT tag:personal"),
+            vec!["alpha"]
+        );
+        assert_eq!(names("title:amm OR priority = 1"), vec!["beta", "gamma"]);
+        assert_eq!(
+            names("modified > 2000-01-01 SORT name LIMIT 1"),
+            vec!["alpha"]
+        );
+        assert_eq!(names("missing != 1").len(), 3);
+        assert_eq!(
+            names("due <= today SORT due DESC"),
+            vec!["gamma", "alpha", "beta"]
+        );
+        assert_eq!(names("due:2023-06-01"), vec!["beta"]);
+        assert!(names("due > tomorrow").is_empty());
+
+        let commit = |commits_ago| CommitInfo {
+            hash: format!("{commits_ago}abc"),
+            author: "Ada".to_string(),
+            committed_at: 0,
+            commits_ago,
+        };
+        index
+            .set_last_commits(
+                None,
+                &HashMap::from([
+                    (root.join("alpha.typ"), commit(0)),
+                    (root.join("gamma.typ"), commit(4)),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(names("commits_ago < 3"), vec!["alpha"]);
+        assert_eq!(
+            names("author:ada SORT commits_ago DESC"),
+            vec!["gamma", "alpha"]
+        );
+
+        let rows = run(
+            &index,
+            &parse("name = beta").unwrap(),
+            &[
+                "tags".to_string(),
+                "priority".to_string(),
+                "missing".to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(rows[0].fields["tags"], serde_json::json!(["project"]));
+        assert_eq!(rows[0].fields["priority"], serde_json::json!(1.0));
+        assert_eq!(rows[0].fields["missing"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::query::{CompareOp, Expr, Query, SortKey, Value};
+use anyhow::{Result, bail};
+
+// Parentheses and `NOT`s nested deeper than this are rejected rather than recursed into
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(CompareOp),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// Parse `filter [SORT field [ASC|DESC], ...] [LIMIT n]`. Terms are `field:value` or
+/// `field <op> value`, combined with `AND`, `OR`, `NOT` and parentheses; adjacent terms
+/// are ANDed. Keywords are case-insensitive.
+pub fn parse(input: &str) -> Result<Query> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        depth: 0,
+    };
+
+    let filter = if parser.at_end() || parser.at_keyword("SORT") || parser.at_keyword("LIMIT") {
+        None
+    } else {
+        Some(parser.or()?)
+    };
+
+    let mut sort = Vec::new();
+    if parser.eat_keyword("SORT") {
+        loop {
+            let field = parser.word("a field to sort by")?.to_lowercase();
+            let descending = if parser.eat_keyword("DESC") {
+                true
+            } else {
+                parser.eat_keyword("ASC");
+                false
+            };
+            sort.push(SortKey { field, descending });
+            if !parser.eat(&Token::Comma) {
+                break;
+            }
+        }
+    }
+
+    let mut limit = None;
+    if parser.eat_keyword("LIMIT") {
+        let count = parser.word("a limit")?;
+        match count.parse() {
+            Ok(count) => limit = Some(count),
+            Err(_) => bail!("Invalid limit `{count}`"),
+        }
+    }
+
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        bail!("Unexpected {} in query", describe(token));
+    }
+    Ok(Query {
+        filter,
+        sort,
+        limit,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.pos) == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn word(&mut self, expected: &str) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) => {
+                self.pos += 1;
+                Ok(word.clone())
+            }
+            Some(token) => bail!("Expected {expected}, found {}", describe(token)),
+            None => bail!("Expected {expected} at end of query"),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            if !self.eat_keyword("AND") && !self.at_term_start() {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    /// Parse with `parse` one level deeper, failing past [`MAX_DEPTH`].
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth == MAX_DEPTH {
+            bail!("Query is nested more than {MAX_DEPTH} levels deep");
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Whether another term follows directly, making an implicit AND.
+    fn at_term_start(&self) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::LParen) => true,
+            Some(Token::Word(_)) => !["OR", "SORT", "LIMIT"]
+                .iter()
+                .any(|keyword| self.at_keyword(keyword)),
+            _ => false,
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            let expr = self.nested(Self::unary)?;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        if self.eat(&Token::LParen) {
+            let expr = self.nested(Self::or)?;
+            if !self.eat(&Token::RParen) {
+                bail!("Expected `)`");
+            }
+            return Ok(expr);
+        }
+
+        let field = self.word("a field")?;
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => *op,
+            Some(token) => bail!(
+                "Expected an operator after `{field}`, found {}",
+                describe(token)
+            ),
+            None => bail!("Expected an operator after `{field}`"),
+        };
+        self.pos += 1;
+        let value = match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) if op == CompareOp::Matches => Value::Text(word.clone()),
+            Some(Token::Word(word)) => Value::parse(word),
+            Some(Token::Quoted(text)) => Value::Text(text.clone()),
+            Some(token) => bail!(
+                "Expected a value after `{field}`, found {}",
+                describe(token)
+            ),
+            None => bail!("Expected a value after `{field}`"),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare {
+            field: field.to_lowercase(),
+            op,
+            value,
+        })
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' | ':' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ',' => Token::Comma,
+                    _ => Token::Op(CompareOp::Matches),
+                });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Op(match (c, equals) {
+                    ('=', _) => CompareOp::Eq,
+                    ('!', true) => CompareOp::Ne,
+                    ('<', false) => CompareOp::Lt,
+                    ('<', true) => CompareOp::Le,
+                    ('>', false) => CompareOp::Gt,
+                    ('>', true) => CompareOp::Ge,
+                    _ => bail!("Expected `!=`"),
+                }));
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => text.extend(chars.next()),
+                        Some(c) => text.push(c),
+                        None => bail!("Unterminated string in query"),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()\",:=!<>".contains(*c))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("`{word}`"),
+        Token::Quoted(text) => format!("\"{text}\""),
+        Token::Op(_) => "an operator".to_string(),
+        Token::LParen => "`(`".to_string(),
+        Token::RParen => "`)`".to_string(),
+        Token::Comma => "`,`".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn compare(field: &str, op: CompareOp, value: Value) -> Expr {
+        Expr::Compare {
+            field: field.to_string(),
+            op,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let query =
+            parse("tag:project AND (modified > 2024-01-01 OR NOT title = \"A (draft)\") SORT title DESC, path LIMIT 5")
+                .unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert_eq!(
+            query.filter,
+            Some(Expr::And(
+                Box::new(compare(
+                    "tag",
+                    CompareOp::Matches,
+                    Value::Text("project".into())
+                )),
+                Box::new(Expr::Or(
+                    Box::new(compare("modified", CompareOp::Gt, Value::Date(date))),
+                    Box::new(Expr::Not(Box::new(compare(
+                        "title",
+                        CompareOp::Eq,
+                        Value::Text("A (draft)".into())
+                    )))),
+                )),
+            ))
+        );
+        assert_eq!(
+            query.sort,
+            vec![
+                SortKey {
+                    field: "title".into(),
+                    descending: true
+                },
+                SortKey {
+                    field: "path".into(),
+                    descending: false
+                },
+            ]
+        );
+        assert_eq!(query.limit, Some(5));
+
+        let implicit = parse("tag:a links>=2").unwrap();
+        assert!(matches!(implicit.filter, Some(Expr::And(..))));
+        assert_eq!(parse("sort title").unwrap().filter, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for query in [
+            "tag",
+            "tag:",
+            "(tag:a",
+            "tag:a)",
+            "title = \"open",
+            "LIMIT many",
+            "a ! b",
+        ] {
+            assert!(parse(query).is_err(), "{query}");
+        }
+
+        let nested = |depth| format!("{}tag:a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(100_000)).is_err());
+        assert!(parse(&"NOT ".repeat(100_000)).is_err());
+    }
+}
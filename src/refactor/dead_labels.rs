@@ -0,0 +1,160 @@
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::refactor::FileEdits;
+use anyhow::Result;
+use lsp_types::{Position, Range, TextEdit};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+/// An explicit `<label>` that nothing refers to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DeadLabel {
+    pub file: PathBuf,
+    pub name: String,
+    /// 1-based line and column of the `<`
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Labels in `notes` that no `@label` or other use such as `#link(<label>)` in their own note
+/// refers to, and that no `[[note:label]]` links to. Heading slugs are not labels and never
+/// dead. `read` returns the current text of a note.
+pub fn dead_labels(
+    index: &Index,
+    parser: &Parser,
+    notes: &[PathBuf],
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<Vec<DeadLabel>> {
+    let mut dead = Vec::new();
+    for note in notes {
+        index.check_cancelled()?;
+        let Some(text) = read(note) else {
+            continue;
+        };
+        dead.extend(dead_labels_in(index, parser, note, &text)?);
+    }
+    Ok(dead)
+}
+
+/// The dead labels of one note with contents `text`.
+pub fn dead_labels_in(
+    index: &Index,
+    parser: &Parser,
+    note: &Path,
+    text: &str,
+) -> Result<Vec<DeadLabel>> {
+    let labels = parser.parse_content(text, note)?.labels;
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for label in &labels {
+        *uses.entry(&label.name).or_default() += 1;
+    }
+    let references = parser.label_references(text);
+
+    let mut dead = Vec::new();
+    for label in &labels {
+        // A second `<label>` is code pointing at the first, e.g. `#ref(<label>)`
+        if uses[label.name.as_str()] > 1
+            || references
+                .iter()
+                .any(|reference| reference.name == label.name)
+            || !index.get_backlinks_to_label(note, &label.name)?.is_empty()
+        {
+            continue;
+        }
+        dead.push(DeadLabel {
+            file: note.to_path_buf(),
+            name: label.name.clone(),
+            line: label.line,
+            column: label.column,
+        });
+    }
+    Ok(dead)
+}
+
+/// Edits removing each dead label from `text`, its note, along with the spaces before it.
+pub fn removal_edits(text: &str, dead: &[DeadLabel]) -> Vec<TextEdit> {
+    let lines: Vec<&str> = text.lines().collect();
+    dead.iter()
+        .filter_map(|label| {
+            let line = lines.get(label.line - 1)?;
+            let start = label.column - 1;
+            let before: Vec<char> = line.chars().take(start).collect();
+            let spaces = before
+                .iter()
+                .rev()
+                .take_while(|c| c.is_whitespace())
+                .count();
+            let end = start + label.name.chars().count() + 2;
+            Some(TextEdit::new(
+                Range::new(
+                    Position::new(label.line as u32 - 1, (start - spaces) as u32),
+                    Position::new(label.line as u32 - 1, end as u32),
+                ),
+                String::new(),
+            ))
+        })
+        .collect()
+}
+
+/// Edits removing every dead label, grouped by note. `read` returns the current text of a note.
+pub fn cleanup_edits(dead: &[DeadLabel], read: impl Fn(&Path) -> Option<String>) -> FileEdits {
+    let mut by_note: HashMap<&Path, Vec<DeadLabel>> = HashMap::new();
+    for label in dead {
+        by_note.entry(&label.file).or_default().push(label.clone());
+    }
+    let mut edits = FileEdits::new();
+    for (note, labels) in by_note {
+        let Some(text) = read(note) else {
+            continue;
+        };
+        let note_edits = removal_edits(&text, &labels);
+        if !note_edits.is_empty() {
+            edits.insert(note.to_path_buf(), note_edits);
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+    use crate::refactor::apply_edits;
+
+    #[test]
+    fn test_dead_labels() {
+        let notes = [
+            (
+                "a.typ",
+                "= Intro <intro>\nSee @fig.\n#figure[x] <fig>\n#link(<eq>)[eq]\n$ x $ <eq>\n\
+                 = Linked <linked>\nUnused <unused>",
+            ),
+            ("b.typ", "[[a:linked]]"),
+        ];
+        let (temp_dir, index) = vault(&notes);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+
+        let note = root.join("a.typ");
+        let dead = dead_labels(&index, &parser, &index.get_all_files().unwrap(), read).unwrap();
+        let names: Vec<&str> = dead.iter().map(|label| label.name.as_str()).collect();
+        assert_eq!(names, vec!["intro", "unused"]);
+        assert_eq!(
+            (dead[0].file.clone(), dead[0].line, dead[0].column),
+            (note.clone(), 1, 9)
+        );
+
+        let edits = cleanup_edits(&dead, read);
+        assert_eq!(edits.len(), 1);
+        let text = read(&note).unwrap();
+        assert_eq!(
+            apply_edits(&text, &edits[&note]),
+            "= Intro\nSee @fig.\n#figure[x] <fig>\n#link(<eq>)[eq]\n$ x $ <eq>\n\
+             = Linked <linked>\nUnused"
+        );
+    }
+}
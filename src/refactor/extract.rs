@@ -0,0 +1,368 @@
+use crate::frontmatter::quote;
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::headings::{heading_level, heading_text};
+use crate::parser::models::Wikilink;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::refactor::{FileEdits, create_file_edit, relative_path};
+use anyhow::{Context, Result};
+use lsp_types::{Position, Range, TextEdit, WorkspaceEdit};
+use std::collections::HashSet;
+use std::ops::Range as Span;
+use std::path::{Path, PathBuf};
+
+/// What takes the place of an extracted section in its note.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Replacement {
+    /// A wikilink to the new note
+    Link,
+    /// `#include` of the new note, so the document still renders the section
+    Include,
+}
+
+/// The heading on 0-based `line` of `note`, whose current contents are `text`.
+#[derive(Clone, Copy)]
+pub struct Heading<'a> {
+    pub note: &'a Path,
+    pub text: &'a str,
+    pub line: usize,
+}
+
+/// Move the section under `heading` into a new note named after it, in the same folder. The
+/// section's labels and blocks move with it, so links to them are pointed at the new note.
+/// Returns the new note's name and the edit, or `None` if the line is not a heading or the
+/// note already exists.
+pub fn extract_section(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    heading: Heading,
+    replacement: Replacement,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<Option<(String, WorkspaceEdit)>> {
+    let Heading { note, text, line } = heading;
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(level) = lines.get(line).and_then(|l| heading_level(l)) else {
+        return Ok(None);
+    };
+    let Some((name, path)) = section_note(heading)? else {
+        return Ok(None);
+    };
+    let end = (line + 1..lines.len())
+        .find(|&i| heading_level(lines[i]).is_some_and(|l| l <= level))
+        .unwrap_or(lines.len());
+    // Blank lines before the next heading stay behind
+    let end = (line + 1..end)
+        .rev()
+        .find(|&i| !lines[i].trim().is_empty())
+        .map_or(line + 1, |i| i + 1);
+
+    let relative = path
+        .strip_prefix(index.root())
+        .with_context(|| format!("{} is not in the vault", path.display()))?;
+    // A bare name would resolve to another note with the same stem
+    let target = match index.resolve_target(&name)? {
+        Some(_) => relative_path(Path::new(""), &relative.with_extension("")),
+        None => name.clone(),
+    };
+
+    let section = parser.parse_content(&lines[line..end].join("\n"), &path)?;
+    let labels: HashSet<&str> = section.labels.iter().map(|l| l.name.as_str()).collect();
+    let blocks: HashSet<&str> = section.blocks.iter().map(|b| b.id.as_str()).collect();
+    let retarget = |wikilink: &Wikilink| {
+        let moved = wikilink
+            .block
+            .as_deref()
+            .is_some_and(|b| blocks.contains(b))
+            || (wikilink.block.is_none()
+                && wikilink
+                    .label
+                    .as_deref()
+                    .is_some_and(|l| labels.contains(l)));
+        moved.then(|| {
+            syntax.format(&Wikilink {
+                target: target.clone(),
+                ..wikilink.clone()
+            })
+        })
+    };
+
+    let mut edits = FileEdits::new();
+    let mut push = |path: &Path, line: usize, span: Span<usize>, text: String| {
+        let range = Range::new(
+            Position::new(line as u32, span.start as u32),
+            Position::new(line as u32, span.end as u32),
+        );
+        edits
+            .entry(path.to_path_buf())
+            .or_default()
+            .push(TextEdit::new(range, text));
+    };
+
+    // Links within the note itself, which may move along with the section
+    let mut section_edits = Vec::new();
+    for (wikilink, span) in parser.wikilink_spans(text) {
+        if index.resolve_target(&wikilink.target)?.as_deref() != Some(note) {
+            continue;
+        }
+        let Some(new) = retarget(&wikilink) else {
+            continue;
+        };
+        let link_line = wikilink.line - 1;
+        if (line..end).contains(&link_line) {
+            section_edits.push((link_line, span, new));
+        } else {
+            push(note, link_line, span, new);
+        }
+    }
+
+    let mut backlinks: Vec<_> = index
+        .get_backward_links(note)?
+        .into_iter()
+        .filter(|(source, link)| source != note && retarget(link).is_some())
+        .collect();
+    backlinks.sort_by(|a, b| a.0.cmp(&b.0));
+    for links in backlinks.chunk_by(|a, b| a.0 == b.0) {
+        let source = &links[0].0;
+        let Some(content) = read(source) else {
+            continue;
+        };
+        for (wikilink, span) in parser.wikilink_spans(&content) {
+            let indexed = links
+                .iter()
+                .any(|(_, link)| (link.line, link.column) == (wikilink.line, wikilink.column));
+            if let Some(new) = retarget(&wikilink).filter(|_| indexed) {
+                push(source, wikilink.line - 1, span, new);
+            }
+        }
+    }
+
+    let mut content = String::new();
+    for (i, text) in lines.iter().enumerate().take(end).skip(line) {
+        let mut chars: Vec<char> = text.chars().collect();
+        let mut replaced: Vec<_> = section_edits.iter().filter(|(l, ..)| *l == i).collect();
+        replaced.sort_by_key(|(_, span, _)| std::cmp::Reverse(span.start));
+        for (_, span, new) in replaced {
+            chars.splice(span.clone(), new.chars());
+        }
+        let text: String = chars.into_iter().collect();
+        // The section heading becomes a top-level heading
+        match heading_level(&text) {
+            Some(_) => {
+                let indent = text.len() - text.trim_start().len();
+                content.push_str(&text[indent + level - 1..]);
+            }
+            None => content.push_str(&text),
+        }
+        content.push('\n');
+    }
+
+    let mut replacement = match replacement {
+        Replacement::Link => syntax.format(&Wikilink {
+            target,
+            alias: None,
+            label: None,
+            block: None,
+            line: 0,
+            column: 0,
+        }),
+        Replacement::Include => format!("#include {}", quote(&format!("{name}.typ"))),
+    };
+    let range_end = if end < lines.len() {
+        replacement.push('\n');
+        Position::new(end as u32, 0)
+    } else {
+        Position::new((end - 1) as u32, lines[end - 1].chars().count() as u32)
+    };
+    edits
+        .entry(note.to_path_buf())
+        .or_default()
+        .push(TextEdit::new(
+            Range::new(Position::new(line as u32, 0), range_end),
+            replacement,
+        ));
+    for file_edits in edits.values_mut() {
+        file_edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    }
+
+    Ok(Some((name, create_file_edit(&path, content, edits)?)))
+}
+
+/// The name and path of the note the section under `heading` would be extracted to, named
+/// after the heading in the same folder; `None` if the line is not a heading or the note
+/// already exists.
+pub fn section_note(heading: Heading) -> Result<Option<(String, PathBuf)>> {
+    let Some(title) = heading
+        .text
+        .lines()
+        .nth(heading.line)
+        .and_then(heading_text)
+    else {
+        return Ok(None);
+    };
+    let name: String = title
+        .chars()
+        .map(|c| {
+            if matches!(c, '/' | '\\' | ':') {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let path = heading
+        .note
+        .parent()
+        .context("Note has no parent folder")?
+        .join(format!("{name}.typ"));
+    if path.exists() {
+        return Ok(None);
+    }
+    Ok(Some((name.to_string(), path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+    use lsp_types::{DocumentChangeOperation, DocumentChanges, OneOf};
+
+    #[test]
+    fn test_extract_section() {
+        let note_text = "= Notes\n\n== Details <details>\nSee [[note:details]].\n=== Sub\nText\n\n== Next\n[[note:details]]\n";
+        let notes = [
+            ("note.typ", note_text),
+            ("other.typ", "[[note:details|Details]] and [[note:next]]"),
+        ];
+        let (temp_dir, index) = vault(&notes);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let note = root.join("note.typ");
+        let extract = |line, replacement| {
+            extract_section(
+                &index,
+                &parser,
+                &WikilinkSyntax::default(),
+                Heading {
+                    note: &note,
+                    text: note_text,
+                    line,
+                },
+                replacement,
+                |path| std::fs::read_to_string(path).ok(),
+            )
+            .unwrap()
+        };
+
+        assert!(extract(1, Replacement::Link).is_none());
+        let (name, edit) = extract(2, Replacement::Link).unwrap();
+        assert_eq!(name, "Details");
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document operations");
+        };
+        let edits: Vec<(String, Vec<TextEdit>)> = operations
+            .into_iter()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => Some((
+                    edit.text_document
+                        .uri
+                        .as_str()
+                        .rsplit('/')
+                        .next()?
+                        .to_string(),
+                    edit.edits
+                        .into_iter()
+                        .map(|edit| match edit {
+                            OneOf::Left(edit) => edit,
+                            OneOf::Right(edit) => edit.text_edit,
+                        })
+                        .collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(edits[0].0, "Details.typ");
+        assert_eq!(
+            edits[0].1[0].new_text,
+            "= Details <details>\nSee [[Details:details]].\n== Sub\nText\n"
+        );
+        assert_eq!(edits[1].0, "note.typ");
+        assert_eq!(
+            edits[1].1,
+            vec![
+                TextEdit::new(
+                    Range::new(Position::new(2, 0), Position::new(6, 0)),
+                    "[[Details]]\n".to_string()
+                ),
+                TextEdit::new(
+                    Range::new(Position::new(8, 0), Position::new(8, 16)),
+                    "[[Details:details]]".to_string()
+                ),
+            ]
+        );
+        assert_eq!(edits[2].0, "other.typ");
+        assert_eq!(
+            edits[2].1,
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 24)),
+                "[[Details:details|Details]]".to_string()
+            )]
+        );
+
+        let (_, edit) = extract(7, Replacement::Include).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document operations");
+        };
+        let DocumentChangeOperation::Edit(edit) = &operations[2] else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(
+            edit.edits,
+            vec![OneOf::Left(TextEdit::new(
+                Range::new(Position::new(7, 0), Position::new(8, 16)),
+                "#include \"Next.typ\"".to_string()
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_include_name_is_quoted() {
+        let note_text = "= Notes\n== Say \"hi\" \\o/\nText\n";
+        let (temp_dir, index) = vault(&[("note.typ", note_text)]);
+        let note = temp_dir.path().join("note.typ");
+        let heading = Heading {
+            note: &note,
+            text: note_text,
+            line: 1,
+        };
+        let (name, path) = section_note(heading).unwrap().unwrap();
+        assert_eq!(name, "Say \"hi\" -o-");
+        assert_eq!(path, temp_dir.path().join("Say \"hi\" -o-.typ"));
+
+        let (_, edit) = extract_section(
+            &index,
+            &Parser::new().unwrap(),
+            &WikilinkSyntax::default(),
+            heading,
+            Replacement::Include,
+            |path| std::fs::read_to_string(path).ok(),
+        )
+        .unwrap()
+        .unwrap();
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected document operations");
+        };
+        let DocumentChangeOperation::Edit(edit) = &operations[2] else {
+            panic!("expected a text edit");
+        };
+        let OneOf::Left(edit) = &edit.edits[0] else {
+            panic!("expected a plain text edit");
+        };
+        assert_eq!(edit.new_text, "#include \"Say \\\"hi\\\" -o-.typ\"");
+    }
+}
@@ -0,0 +1,345 @@
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::refactor::FileEdits;
+use anyhow::Result;
+use lsp_types::{Position, Range, TextEdit};
+use regex::Regex;
+use std::ops::Range as ByteRange;
+use std::path::{Path, PathBuf};
+
+/// Edits replacing each match of `pattern` with `replacement` in the notes under `scope` (a
+/// note or folder; the whole vault if `None`). Matches touching anything but prose, as found by
+/// [`masked_ranges`], are left alone. With `regex`, `replacement` may refer to groups as `$1` or
+/// `${name}`; otherwise both are taken literally. `read` returns the current text of a note.
+pub fn find_replace(
+    index: &Index,
+    parser: &Parser,
+    pattern: &str,
+    replacement: &str,
+    regex: bool,
+    scope: Option<&Path>,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<FileEdits> {
+    let matcher = if regex {
+        Regex::new(pattern)?
+    } else {
+        Regex::new(&regex::escape(pattern))?
+    };
+    let notes: Vec<PathBuf> = index
+        .get_all_files()?
+        .into_iter()
+        .filter(|note| scope.is_none_or(|scope| note.starts_with(scope)))
+        .collect();
+
+    let mut edits = FileEdits::new();
+    for note in notes {
+        index.check_cancelled()?;
+        let Some(text) = read(&note) else {
+            continue;
+        };
+        let note_edits = replace_in(parser, &text, &matcher, replacement, regex);
+        if !note_edits.is_empty() {
+            edits.insert(note, note_edits);
+        }
+    }
+    Ok(edits)
+}
+
+/// The edits replacing matches of `matcher` in the prose of `text`.
+pub fn replace_in(
+    parser: &Parser,
+    text: &str,
+    matcher: &Regex,
+    replacement: &str,
+    expand: bool,
+) -> Vec<TextEdit> {
+    let masked = masked_ranges(parser, text);
+    let mut edits = Vec::new();
+    for captures in matcher.captures_iter(text) {
+        let found = captures.get(0).expect("group 0 is the whole match");
+        if found.is_empty()
+            || masked
+                .iter()
+                .any(|mask| mask.start < found.end() && found.start() < mask.end)
+        {
+            continue;
+        }
+        let new_text = if expand {
+            let mut expanded = String::new();
+            captures.expand(replacement, &mut expanded);
+            expanded
+        } else {
+            replacement.to_string()
+        };
+        edits.push(TextEdit::new(
+            Range::new(
+                position_of(text, found.start()),
+                position_of(text, found.end()),
+            ),
+            new_text,
+        ));
+    }
+    edits
+}
+
+/// Byte ranges of `text` that aren't prose: wikilinks, labels and references as the parser finds
+/// them, raw text between backticks, comments, math, URLs, and code after `#` (but not the
+/// markup in its content blocks).
+pub fn masked_ranges(parser: &Parser, text: &str) -> Vec<ByteRange<usize>> {
+    let mut ranges = syntax_ranges(text);
+    ranges.extend(parser.label_spans(text));
+
+    // Wikilink spans count characters within the link's first line
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(newline, _)| newline + 1))
+        .collect();
+    let byte_at = |line_start: usize, character: usize| {
+        text[line_start..]
+            .char_indices()
+            .nth(character)
+            .map_or(text.len(), |(offset, _)| line_start + offset)
+    };
+    for (link, span) in parser.wikilink_spans(text) {
+        let line_start = line_starts[link.line - 1];
+        ranges.push(byte_at(line_start, span.start)..byte_at(line_start, span.end));
+    }
+    ranges
+}
+
+// Raw text, comments, math, URLs and code, found by scanning markup
+fn syntax_ranges(text: &str) -> Vec<ByteRange<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let run = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+                let fence = "`".repeat(run);
+                let body = i + run;
+                // Two backticks are an empty raw span
+                match text[body..].find(&fence).filter(|_| run != 2) {
+                    Some(offset) => {
+                        ranges.push(i..body + offset + run);
+                        i = body + offset + run;
+                    }
+                    None => i = body,
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                let end = text[i..].find('\n').map_or(text.len(), |offset| i + offset);
+                ranges.push(i..end);
+                i = end;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = text[i + 2..]
+                    .find("*/")
+                    .map_or(text.len(), |offset| i + 2 + offset + 2);
+                ranges.push(i..end);
+                i = end;
+            }
+            b'$' => match (i + 1..bytes.len()).find(|&j| bytes[j] == b'$' && bytes[j - 1] != b'\\')
+            {
+                Some(end) => {
+                    ranges.push(i..end + 1);
+                    i = end + 1;
+                }
+                None => i += 1,
+            },
+            b'h' if (text[i..].starts_with("https://") || text[i..].starts_with("http://"))
+                && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric()) =>
+            {
+                let end = text[i..]
+                    .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | ')' | ']'))
+                    .map_or(text.len(), |offset| i + offset);
+                // Trailing punctuation ends the sentence, not the URL
+                let url = text[i..end].trim_end_matches(['.', ',', ';', ':', '!', '?', '\'']);
+                ranges.push(i..i + url.len());
+                i += url.len();
+            }
+            b'#' if bytes
+                .get(i + 1)
+                .is_some_and(|&b| b == b'{' || b == b'(' || is_ident_start(b)) =>
+            {
+                i = code_end(text, i, &mut ranges);
+            }
+            _ => i += 1,
+        }
+    }
+    ranges
+}
+
+// Keywords whose code runs to the end of the line rather than ending with an expression
+const STATEMENTS: [&str; 5] = ["let", "set", "show", "import", "include"];
+
+// Scan the code starting with the `#` at `hash`, pushing its ranges to `ranges`, and return
+// where it ends. Content blocks in it are markup again, scanned like the rest of the text.
+fn code_end(text: &str, hash: usize, ranges: &mut Vec<ByteRange<usize>>) -> usize {
+    let bytes = text.as_bytes();
+    let is_statement = |keyword: &&str| {
+        text[hash + 1..].starts_with(*keyword)
+            && !bytes
+                .get(hash + 1 + keyword.len())
+                .is_some_and(|&b| is_ident(b))
+    };
+    let statement = STATEMENTS.iter().any(is_statement);
+    // An expression goes on through field accesses, calls and trailing content blocks
+    let continues = |at: usize| match bytes.get(at) {
+        Some(b'(' | b'[') => true,
+        Some(b'.') => bytes.get(at + 1).is_some_and(|&b| is_ident_start(b)),
+        _ => false,
+    };
+
+    let mut depth = 0;
+    let mut segment = hash;
+    let mut i = hash + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let mut end = i + 1;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                i = end + 1;
+                continue;
+            }
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' if depth == 0 => break,
+            b')' | b'}' => {
+                depth -= 1;
+                if depth == 0 && !statement && !continues(i + 1) {
+                    i += 1;
+                    break;
+                }
+            }
+            b'[' => {
+                let close = closing_bracket(bytes, i);
+                ranges.push(segment..i + 1);
+                let body = i + 1..close;
+                ranges.extend(
+                    syntax_ranges(&text[body.clone()])
+                        .into_iter()
+                        .map(|range| range.start + body.start..range.end + body.start),
+                );
+                segment = close;
+                i = close + 1;
+                if depth == 0 && !statement && !continues(i) {
+                    break;
+                }
+                continue;
+            }
+            b'\n' | b';' if depth == 0 => break,
+            b'.' if depth == 0 && !statement && continues(i) => {}
+            b if depth == 0 && !statement && !is_ident(b) => break,
+            _ => {}
+        }
+        i += 1;
+    }
+    let end = i.min(bytes.len());
+    if segment < end {
+        ranges.push(segment..end);
+    }
+    end
+}
+
+// The `]` closing the content block opened at `open`, or the end of the text
+fn closing_bracket(bytes: &[u8], open: usize) -> usize {
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn is_ident_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_ident(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+// The position of byte `offset` in `text`, counting characters within the line
+fn position_of(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].chars().count() as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+    use crate::refactor::apply_edits;
+
+    #[test]
+    fn test_find_replace() {
+        let notes = [
+            (
+                "work/a.typ",
+                "Use the colour map. // colour\n`colour` and ```\ncolour\n```\n\
+                 /* colour */ See https://colour.org colours.\n\
+                 [[colour|the colour]] <colour> @colour $colour$ #link(\"colour\")[colour] \
+                 #emph[a colour].\n#let colour = \"colour\"\nA colour.",
+            ),
+            ("b.typ", "colour"),
+        ];
+        let (temp_dir, index) = vault(&notes);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+
+        let note = root.join("work/a.typ");
+        let edits = find_replace(
+            &index,
+            &parser,
+            "colour",
+            "color",
+            false,
+            Some(&root.join("work")),
+            read,
+        )
+        .unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            apply_edits(&read(&note).unwrap(), &edits[&note]),
+            "Use the color map. // colour\n`colour` and ```\ncolour\n```\n\
+             /* colour */ See https://colour.org colors.\n\
+             [[colour|the colour]] <colour> @colour $colour$ #link(\"colour\")[color] \
+             #emph[a color].\n#let colour = \"colour\"\nA color."
+        );
+
+        let edits =
+            find_replace(&index, &parser, r"colou?r(s?)\b", "hue$1", true, None, read).unwrap();
+        assert_eq!(edits.len(), 2);
+        assert!(apply_edits(&read(&note).unwrap(), &edits[&note]).ends_with("A hue."));
+        assert!(find_replace(&index, &parser, "(", "", true, None, read).is_err());
+    }
+
+    #[test]
+    fn test_masked_ranges() {
+        let parser = Parser::new().unwrap();
+        let text = "Über\nein [[Ärger]] #f(x, \"]\")[y] z.";
+        let masked: Vec<&str> = masked_ranges(&parser, text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(masked, vec!["#f(x, \"]\")[", "]", "[[Ärger]]"]);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::frontmatter::MetaBlock;
+use crate::index::Index;
+use crate::lsp::path_to_uri;
+use crate::parser::Parser;
+use crate::parser::headings::heading_text;
+use crate::parser::models::Wikilink;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::refactor::move_file::{
+    asset_paths, include_paths, include_regex, retarget, rewrite_path,
+};
+use crate::refactor::{FileEdits, apply_edits, end_position, relative_path, text_document_edits};
+use anyhow::{Context, Result};
+use lsp_types::{
+    DeleteFile, DocumentChangeOperation, DocumentChanges, Position, Range, ResourceOp, TextEdit,
+    WorkspaceEdit,
+};
+use std::path::{Path, PathBuf};
+
+/// Merge the note `source` into `target`.
+pub struct Merge<'a> {
+    pub source: &'a Path,
+    pub target: &'a Path,
+    /// Leave a note linking to `target` in place of `source` instead of deleting it
+    pub stub: bool,
+}
+
+/// Append the content of `merge.source` to `merge.target` under a heading with its title, point
+/// every link to the source (including its labels) at the target, then delete or stub the
+/// source. `read` returns the current text of a note.
+pub fn merge_notes(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    merge: Merge,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<WorkspaceEdit> {
+    let Merge {
+        source,
+        target,
+        stub,
+    } = merge;
+    let root = index.root();
+    let relative = |path: &Path| -> Result<PathBuf> {
+        Ok(path
+            .strip_prefix(root)
+            .with_context(|| format!("{} is not in the vault", path.display()))?
+            .to_path_buf())
+    };
+    let source_rel = relative(source)?;
+    let target_rel = relative(target)?;
+    let source_text = read(source).with_context(|| format!("Cannot read {}", source.display()))?;
+    let target_text = read(target).with_context(|| format!("Cannot read {}", target.display()))?;
+
+    let mut edits = FileEdits::new();
+    let mut backlinks: Vec<_> = index
+        .get_backward_links(source)?
+        .into_iter()
+        .filter(|(origin, _)| origin != source)
+        .collect();
+    backlinks.sort_by(|a, b| a.0.cmp(&b.0));
+    for links in backlinks.chunk_by(|a, b| a.0 == b.0) {
+        let origin = &links[0].0;
+        let Some(content) = read(origin) else {
+            continue;
+        };
+        for (mut wikilink, span) in parser.wikilink_spans(&content) {
+            if !links
+                .iter()
+                .any(|(_, link)| (link.line, link.column) == (wikilink.line, wikilink.column))
+            {
+                continue;
+            }
+            let Some(new) = retarget(&wikilink.target, &target_rel) else {
+                continue;
+            };
+            wikilink.target = new;
+            let line = wikilink.line as u32 - 1;
+            edits.entry(origin.clone()).or_default().push(TextEdit::new(
+                Range::new(
+                    Position::new(line, span.start as u32),
+                    Position::new(line, span.end as u32),
+                ),
+                syntax.format(&wikilink),
+            ));
+        }
+    }
+
+    // The source's links to itself and its relative paths, as they read from the target
+    let mut source_edits = Vec::new();
+    for (mut wikilink, span) in parser.wikilink_spans(&source_text) {
+        if index.resolve_target(&wikilink.target)?.as_deref() != Some(source) {
+            continue;
+        }
+        let Some(new) = retarget(&wikilink.target, &target_rel) else {
+            continue;
+        };
+        wikilink.target = new;
+        let line = wikilink.line as u32 - 1;
+        source_edits.push(TextEdit::new(
+            Range::new(
+                Position::new(line, span.start as u32),
+                Position::new(line, span.end as u32),
+            ),
+            syntax.format(&wikilink),
+        ));
+    }
+    let old_dir = source_rel.parent().unwrap_or(Path::new(""));
+    let new_dir = target_rel.parent().unwrap_or(Path::new(""));
+    let mut literals = include_paths(&include_regex()?, &source_text);
+    literals.extend(asset_paths(parser, source, &source_text)?);
+    for literal in literals {
+        if let Some(path) = rewrite_path(&literal.path, old_dir, new_dir, |_| None) {
+            source_edits.push(TextEdit::new(
+                Range::new(
+                    Position::new(literal.line, literal.start),
+                    Position::new(literal.line, literal.end),
+                ),
+                path,
+            ));
+        }
+    }
+
+    let title = index
+        .get_file(source)?
+        .and_then(|file| file.metadata.title)
+        .or_else(|| Some(source.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let end = end_position(&target_text);
+    let separator = if target_text.is_empty() {
+        ""
+    } else if target_text.ends_with('\n') {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    let body = section_body(&apply_edits(&source_text, &source_edits));
+    let appended = if body.is_empty() {
+        format!("{separator}= {title}\n")
+    } else {
+        format!("{separator}= {title}\n\n{body}\n")
+    };
+    edits
+        .entry(target.to_path_buf())
+        .or_default()
+        .push(TextEdit::new(Range::new(end, end), appended));
+    for file_edits in edits.values_mut() {
+        file_edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    }
+
+    let mut operations = text_document_edits(edits)?;
+    if stub {
+        let stem = target.file_stem().map(|stem| stem.to_string_lossy());
+        let link_target = match &stem {
+            Some(stem) if index.resolve_target(stem)?.as_deref() == Some(target) => {
+                stem.to_string()
+            }
+            _ => relative_path(Path::new(""), &target_rel.with_extension("")),
+        };
+        let link = syntax.format(&Wikilink {
+            target: link_target,
+            alias: None,
+            label: None,
+            block: None,
+            line: 0,
+            column: 0,
+        });
+        let stub_edits = FileEdits::from([(
+            source.to_path_buf(),
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 0), end_position(&source_text)),
+                format!("Merged into {link}.\n"),
+            )],
+        )]);
+        operations.extend(text_document_edits(stub_edits)?);
+    } else {
+        operations.push(DocumentChangeOperation::Op(ResourceOp::Delete(
+            DeleteFile {
+                uri: path_to_uri(source)?,
+                options: None,
+            },
+        )));
+    }
+
+    Ok(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..Default::default()
+    })
+}
+
+// A note's content without its metadata block, one heading level down and trimmed
+fn section_body(text: &str) -> String {
+    let line_of = |offset: usize| text[..offset].matches('\n').count();
+    let meta = MetaBlock::locate(text).map(|block| line_of(block.open)..=line_of(block.close));
+    let lines: Vec<String> = text
+        .lines()
+        .enumerate()
+        .filter(|(i, _)| !meta.as_ref().is_some_and(|meta| meta.contains(i)))
+        .map(|(_, line)| match heading_text(line) {
+            Some(_) => {
+                let indent = line.len() - line.trim_start().len();
+                format!("{}={}", &line[..indent], &line[indent..])
+            }
+            None => line.to_string(),
+        })
+        .collect();
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note, vault};
+    use lsp_types::OneOf;
+
+    #[test]
+    fn test_merge_notes() {
+        let (temp_dir, mut index) = vault(&[
+            ("main.typ", "= Main\nText"),
+            ("other.typ", "[[draft]] and [[old/draft:idea|the idea]]"),
+        ]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        add_note(
+            &mut index,
+            "old/draft.typ",
+            "#meta(\n  title: \"Draft\",\n)\n\n= Idea <idea>\nSee [[draft:idea]].\n#image(\"plot.png\")\n",
+        );
+        let source = root.join("old/draft.typ");
+        let target = root.join("main.typ");
+        let merge = |stub| {
+            merge_notes(
+                &index,
+                &parser,
+                &WikilinkSyntax::default(),
+                Merge {
+                    source: &source,
+                    target: &target,
+                    stub,
+                },
+                |path| std::fs::read_to_string(path).ok(),
+            )
+            .unwrap()
+        };
+
+        let Some(DocumentChanges::Operations(operations)) = merge(false).document_changes else {
+            panic!("expected document operations");
+        };
+        let texts: Vec<(String, Vec<String>)> = operations
+            .iter()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(edit) => Some((
+                    edit.text_document
+                        .uri
+                        .as_str()
+                        .rsplit('/')
+                        .next()?
+                        .to_string(),
+                    edit.edits
+                        .iter()
+                        .map(|edit| match edit {
+                            OneOf::Left(edit) => edit.new_text.clone(),
+                            OneOf::Right(edit) => edit.text_edit.new_text.clone(),
+                        })
+                        .collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                (
+                    "main.typ".to_string(),
+                    vec![
+                        "\n\n= Draft\n\n== Idea <idea>\nSee [[main:idea]].\n#image(\"old/plot.png\")\n"
+                            .to_string()
+                    ]
+                ),
+                (
+                    "other.typ".to_string(),
+                    vec![
+                        "[[main]]".to_string(),
+                        "[[main:idea|the idea]]".to_string()
+                    ]
+                ),
+            ]
+        );
+        assert!(matches!(
+            operations.last(),
+            Some(DocumentChangeOperation::Op(ResourceOp::Delete(_)))
+        ));
+
+        let Some(DocumentChanges::Operations(operations)) = merge(true).document_changes else {
+            panic!("expected document operations");
+        };
+        let Some(DocumentChangeOperation::Edit(stub)) = operations.last() else {
+            panic!("expected a stub edit");
+        };
+        assert!(stub.text_document.uri.as_str().ends_with("old/draft.typ"));
+        assert_eq!(
+            stub.edits,
+            vec![OneOf::Left(TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(7, 0)),
+                "Merged into [[main]].\n".to_string()
+            ))]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+pub mod dead_labels;
+pub mod extract;
+pub mod find_replace;
+pub mod merge;
+pub mod move_file;
+pub mod rewrite;
+
+use crate::lsp::path_to_uri;
+use anyhow::{Context, Result};
+use lsp_types::{
+    AnnotatedTextEdit, ChangeAnnotation, CreateFile, CreateFileOptions, DocumentChangeOperation,
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, Position, Range, RenameFile,
+    ResourceOp, TextDocumentEdit, TextEdit, WorkspaceEdit,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Text edits for each file touched by a refactoring.
+pub type FileEdits = BTreeMap<PathBuf, Vec<TextEdit>>;
+
+/// Combine per-file edits and file renames into one `WorkspaceEdit`. Renames need
+/// `documentChanges`, so they are applied after the text edits, which address files by
+/// their old paths.
+pub fn workspace_edit(edits: FileEdits, renames: &[(PathBuf, PathBuf)]) -> Result<WorkspaceEdit> {
+    if renames.is_empty() {
+        return Ok(WorkspaceEdit {
+            changes: Some(
+                edits
+                    .into_iter()
+                    .map(|(path, edits)| Ok((path_to_uri(&path)?, edits)))
+                    .collect::<Result<HashMap<_, _>>>()?,
+            ),
+            ..Default::default()
+        });
+    }
+
+    let mut operations = text_document_edits(edits)?;
+    for (from, to) in renames {
+        operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+            RenameFile {
+                old_uri: path_to_uri(from)?,
+                new_uri: path_to_uri(to)?,
+                options: None,
+                annotation_id: None,
+            },
+        )));
+    }
+    Ok(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..Default::default()
+    })
+}
+
+/// A `WorkspaceEdit` that creates the note `path` with `content`, then applies `edits`.
+pub fn create_file_edit(path: &Path, content: String, edits: FileEdits) -> Result<WorkspaceEdit> {
+    let uri = path_to_uri(path)?;
+    let mut operations = vec![
+        DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+            uri: uri.clone(),
+            options: Some(CreateFileOptions {
+                overwrite: Some(false),
+                ignore_if_exists: Some(false),
+            }),
+            annotation_id: None,
+        })),
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: vec![OneOf::Left(TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                content,
+            ))],
+        }),
+    ];
+    operations.extend(text_document_edits(edits)?);
+    Ok(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..Default::default()
+    })
+}
+
+pub(crate) fn text_document_edits(edits: FileEdits) -> Result<Vec<DocumentChangeOperation>> {
+    let mut operations = Vec::new();
+    for (path, edits) in edits {
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: path_to_uri(&path)?,
+                version: None,
+            },
+            edits: edits.into_iter().map(OneOf::Left).collect(),
+        }));
+    }
+    Ok(operations)
+}
+
+/// Mark every change in `edit` as needing confirmation, so the client previews it under
+/// `label` before applying it. Only edits made of `documentChanges` operations are marked.
+pub fn needs_confirmation(edit: &mut WorkspaceEdit, label: String) {
+    const ID: &str = "preview";
+    let Some(DocumentChanges::Operations(operations)) = &mut edit.document_changes else {
+        return;
+    };
+    for operation in operations {
+        match operation {
+            DocumentChangeOperation::Edit(edit) => {
+                for text_edit in &mut edit.edits {
+                    if let OneOf::Left(plain) = text_edit {
+                        *text_edit = OneOf::Right(AnnotatedTextEdit {
+                            text_edit: plain.clone(),
+                            annotation_id: ID.to_string(),
+                        });
+                    }
+                }
+            }
+            DocumentChangeOperation::Op(ResourceOp::Create(op)) => {
+                op.annotation_id = Some(ID.to_string())
+            }
+            DocumentChangeOperation::Op(ResourceOp::Rename(op)) => {
+                op.annotation_id = Some(ID.to_string())
+            }
+            // `DeleteFile` has no annotation in lsp-types
+            DocumentChangeOperation::Op(ResourceOp::Delete(_)) => {}
+        }
+    }
+    edit.change_annotations = Some(HashMap::from([(
+        ID.to_string(),
+        ChangeAnnotation {
+            label,
+            needs_confirmation: Some(true),
+            description: None,
+        },
+    )]));
+}
+
+/// Apply single-line `edits` to the files on disk, for callers without an editor.
+pub fn write_edits(edits: &FileEdits) -> Result<()> {
+    for (path, edits) in edits {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?;
+        std::fs::write(path, apply_edits(&text, edits))
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The position just past the end of `text`.
+pub(crate) fn end_position(text: &str) -> Position {
+    let lines = text.lines().count();
+    match text.lines().last() {
+        Some(last) if !text.ends_with('\n') => {
+            Position::new(lines as u32 - 1, last.chars().count() as u32)
+        }
+        _ => Position::new(lines as u32, 0),
+    }
+}
+
+/// `text` with single-line `edits` applied.
+pub(crate) fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut chars: Vec<char> = line.chars().collect();
+        let mut on_line: Vec<_> = edits
+            .iter()
+            .filter(|edit| edit.range.start.line as usize == i)
+            .collect();
+        on_line.sort_by_key(|edit| std::cmp::Reverse(edit.range.start.character));
+        for edit in on_line {
+            let start = edit.range.start.character as usize;
+            let end = edit.range.end.character as usize;
+            chars.splice(start..end, edit.new_text.chars());
+        }
+        lines.push(chars.into_iter().collect::<String>());
+    }
+    let mut output = lines.join("\n");
+    if text.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+/// The `/`-separated path from the directory `from_dir` to `to`, both vault-relative.
+pub(crate) fn relative_path(from_dir: &Path, to: &Path) -> String {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path(Path::new(""), Path::new("a/b.typ")),
+            "a/b.typ"
+        );
+        assert_eq!(relative_path(Path::new("a"), Path::new("a/b.typ")), "b.typ");
+        assert_eq!(
+            relative_path(Path::new("a/c"), Path::new("a/b.typ")),
+            "../b.typ"
+        );
+        assert_eq!(
+            relative_path(Path::new("x/y"), Path::new("b.typ")),
+            "../../b.typ"
+        );
+    }
+}
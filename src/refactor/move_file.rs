@@ -0,0 +1,351 @@
+use crate::index::{Index, normalize};
+use crate::parser::Parser;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::refactor::{FileEdits, relative_path};
+use anyhow::{Context, Result, bail};
+use lsp_types::{Position, Range, TextEdit};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// A quoted path in a note: 0-based line, the character range inside the quotes, and the path.
+pub(crate) struct PathLiteral {
+    pub line: u32,
+    pub start: u32,
+    pub end: u32,
+    pub path: String,
+}
+
+/// Edits that keep every link working when notes, attachments or folders move, given as
+/// `(from, to)` pairs: wikilinks that resolve to a moved note, `#include`/`#import` and asset
+/// paths that point into a move, and the relative paths inside moved notes. `read` returns the
+/// current text of a note.
+pub fn move_edits(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    moves: &[(PathBuf, PathBuf)],
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<FileEdits> {
+    let root = index.root();
+    let mut relative_moves = Vec::new();
+    for (from, to) in moves {
+        let from_rel = from
+            .strip_prefix(root)
+            .with_context(|| format!("{} is not in the vault", from.display()))?;
+        let to_rel = to
+            .strip_prefix(root)
+            .with_context(|| format!("{} is not in the vault", to.display()))?;
+        if from_rel.extension().is_some_and(|ext| ext == "typ")
+            && to_rel.extension().is_none_or(|ext| ext != "typ")
+        {
+            bail!("{} is not a Typst note", to.display());
+        }
+        relative_moves.push((from_rel.to_path_buf(), to_rel.to_path_buf()));
+    }
+    let destination = |path: &Path| moved_path(&relative_moves, path);
+
+    let mut edits = FileEdits::new();
+    let mut push = |path: &Path, line: u32, start: u32, end: u32, text: String| {
+        let range = Range::new(Position::new(line, start), Position::new(line, end));
+        edits
+            .entry(path.to_path_buf())
+            .or_default()
+            .push(TextEdit::new(range, text));
+    };
+
+    let notes = index.get_all_files()?;
+    let moved_notes: Vec<(&PathBuf, PathBuf)> = notes
+        .iter()
+        .filter_map(|note| Some((note, destination(note.strip_prefix(root).ok()?)?)))
+        .collect();
+
+    // Incoming wikilinks
+    let mut backlinks = Vec::new();
+    for (note, to_rel) in &moved_notes {
+        for (source, link) in index.get_backward_links(note)? {
+            backlinks.push((source, link, to_rel));
+        }
+    }
+    backlinks.sort_by(|a, b| a.0.cmp(&b.0));
+    for links in backlinks.chunk_by(|a, b| a.0 == b.0) {
+        let source = &links[0].0;
+        let Some(content) = read(source) else {
+            continue;
+        };
+        for (mut wikilink, span) in parser.wikilink_spans(&content) {
+            let Some((_, _, to_rel)) = links
+                .iter()
+                .find(|(_, link, _)| (link.line, link.column) == (wikilink.line, wikilink.column))
+            else {
+                continue;
+            };
+            let Some(target) = retarget(&wikilink.target, to_rel) else {
+                continue;
+            };
+            wikilink.target = target;
+            let line = wikilink.line as u32 - 1;
+            push(
+                source,
+                line,
+                span.start as u32,
+                span.end as u32,
+                syntax.format(&wikilink),
+            );
+        }
+    }
+
+    // Relative paths into and out of moved notes, e.g. `#include "chapter.typ"`,
+    // `#import "lib.typ": f` and `#image("plot.png")`
+    let include_regex = include_regex()?;
+    let names: Vec<String> = relative_moves
+        .iter()
+        .filter_map(|(from, _)| Some(from.file_name()?.to_string_lossy().into_owned()))
+        .chain(
+            moved_notes
+                .iter()
+                .filter_map(|(note, _)| Some(note.file_name()?.to_string_lossy().into_owned())),
+        )
+        .collect();
+    for note in &notes {
+        let Ok(note_rel) = note.strip_prefix(root) else {
+            continue;
+        };
+        let new_rel = destination(note_rel);
+        let Some(content) = read(note) else {
+            continue;
+        };
+        if new_rel.is_none() && !names.iter().any(|name| content.contains(name.as_str())) {
+            continue;
+        }
+        let old_dir = note_rel.parent().unwrap_or(Path::new(""));
+        let new_dir = match &new_rel {
+            Some(new_rel) => new_rel.parent().unwrap_or(Path::new("")),
+            None => old_dir,
+        };
+
+        let mut literals = include_paths(&include_regex, &content);
+        literals.extend(asset_paths(parser, note, &content)?);
+        for literal in literals {
+            if let Some(path) = rewrite_path(&literal.path, old_dir, new_dir, destination) {
+                push(note, literal.line, literal.start, literal.end, path);
+            }
+        }
+    }
+
+    for file_edits in edits.values_mut() {
+        file_edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    }
+    Ok(edits)
+}
+
+/// Where the vault-relative `path` ends up after `moves`, if it is moved itself or lives in a
+/// moved folder.
+fn moved_path(moves: &[(PathBuf, PathBuf)], path: &Path) -> Option<PathBuf> {
+    moves.iter().find_map(|(from, to)| {
+        let rest = path.strip_prefix(from).ok()?;
+        Some(if rest.as_os_str().is_empty() {
+            to.clone()
+        } else {
+            to.join(rest)
+        })
+    })
+}
+
+/// The target a wikilink should use once its note lives at `to`, keeping its style: a path
+/// stays a path, a bare name stays a name, and a `.typ` extension is kept.
+pub(crate) fn retarget(target: &str, to: &Path) -> Option<String> {
+    let keep_extension = target.ends_with(".typ");
+    let path = if target.contains('/') {
+        relative_path(Path::new(""), &to.with_extension(""))
+    } else {
+        to.file_stem()?.to_string_lossy().into_owned()
+    };
+    let new = if keep_extension {
+        format!("{path}.typ")
+    } else {
+        path
+    };
+    (new != target).then_some(new)
+}
+
+/// The new text of `literal`, written in a note whose folder moves from `old_dir` to `new_dir`,
+/// if it points at something that moves or is relative and its note moved.
+pub(crate) fn rewrite_path(
+    literal: &str,
+    old_dir: &Path,
+    new_dir: &Path,
+    destination: impl Fn(&Path) -> Option<PathBuf>,
+) -> Option<String> {
+    // Packages and URLs are not vault paths
+    if literal.starts_with('@') || literal.contains("://") {
+        return None;
+    }
+    let rooted = literal.strip_prefix('/');
+    let resolved = match rooted {
+        Some(path) => normalize(Path::new(path))?,
+        None => normalize(&old_dir.join(literal))?,
+    };
+    let target = destination(&resolved);
+    if target.is_none() && (rooted.is_some() || old_dir == new_dir) {
+        return None;
+    }
+
+    let target = target.unwrap_or(resolved);
+    let new = match rooted {
+        Some(_) => format!("/{}", relative_path(Path::new(""), &target)),
+        None => relative_path(new_dir, &target),
+    };
+    (new != literal).then_some(new)
+}
+
+pub(crate) fn include_regex() -> Result<Regex> {
+    Ok(Regex::new(r#"\b(?:include|import)\s+"([^"]+)""#)?)
+}
+
+pub(crate) fn include_paths(include_regex: &Regex, content: &str) -> Vec<PathLiteral> {
+    let mut literals = Vec::new();
+    for (line_idx, line) in content.lines().enumerate() {
+        for cap in include_regex.captures_iter(line) {
+            let path = cap.get(1).unwrap();
+            let start = line[..path.start()].chars().count() as u32;
+            literals.push(PathLiteral {
+                line: line_idx as u32,
+                start,
+                end: start + path.as_str().chars().count() as u32,
+                path: path.as_str().to_string(),
+            });
+        }
+    }
+    literals
+}
+
+// `#image(...)` and other asset paths, which are relative to the note
+pub(crate) fn asset_paths(parser: &Parser, note: &Path, content: &str) -> Result<Vec<PathLiteral>> {
+    Ok(parser
+        .parse_content(content, note)?
+        .attachments
+        .into_iter()
+        .filter(|attachment| attachment.embedded)
+        .map(|attachment| {
+            // `column` is the 1-based opening quote, so the path starts at that 0-based offset
+            let start = attachment.column as u32;
+            PathLiteral {
+                line: attachment.line as u32 - 1,
+                start,
+                end: start + attachment.target.chars().count() as u32,
+                path: attachment.target,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::vault;
+
+    #[test]
+    fn test_retarget() {
+        assert_eq!(retarget("idea", Path::new("archive/idea.typ")), None);
+        assert_eq!(
+            retarget("notes/idea", Path::new("archive/idea.typ")).as_deref(),
+            Some("archive/idea")
+        );
+        assert_eq!(
+            retarget("idea.typ", Path::new("archive/big-idea.typ")).as_deref(),
+            Some("big-idea.typ")
+        );
+    }
+
+    #[test]
+    fn test_move_edits() {
+        let notes = [
+            (
+                "notes/idea.typ",
+                "#include \"part.typ\"\n#image(\"../img/plot.png\")\n[[other]]",
+            ),
+            ("notes/part.typ", "Part"),
+            (
+                "other.typ",
+                "[[idea|The idea]] and [[notes/idea:sec]]\n#include \"notes/idea.typ\"",
+            ),
+        ];
+        let (temp_dir, index) = vault(&notes);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        std::fs::create_dir(root.join("img")).unwrap();
+        std::fs::write(root.join("img/plot.png"), "").unwrap();
+
+        let from = root.join("notes/idea.typ");
+        let to = root.join("archive/2024/big-idea.typ");
+        let edits = move_edits(
+            &index,
+            &parser,
+            &WikilinkSyntax::default(),
+            &[(from.clone(), to)],
+            |path| std::fs::read_to_string(path).ok(),
+        )
+        .unwrap();
+
+        let texts = |path: &str| -> Vec<String> {
+            edits[&root.join(path)]
+                .iter()
+                .map(|edit| edit.new_text.clone())
+                .collect()
+        };
+        assert_eq!(
+            texts("notes/idea.typ"),
+            vec!["../../notes/part.typ", "../../img/plot.png"]
+        );
+        assert_eq!(
+            texts("other.typ"),
+            vec![
+                "[[big-idea|The idea]]",
+                "[[archive/2024/big-idea:sec]]",
+                "archive/2024/big-idea.typ"
+            ]
+        );
+        assert_eq!(
+            edits[&root.join("other.typ")][1].range,
+            Range::new(Position::new(0, 22), Position::new(0, 40))
+        );
+        assert!(!edits.contains_key(&root.join("notes/part.typ")));
+
+        let not_a_note = (from, root.join("idea.md"));
+        assert!(
+            move_edits(
+                &index,
+                &parser,
+                &WikilinkSyntax::default(),
+                &[not_a_note],
+                |_| None
+            )
+            .is_err()
+        );
+
+        // Moving the whole folder keeps paths between its notes
+        let edits = move_edits(
+            &index,
+            &parser,
+            &WikilinkSyntax::default(),
+            &[(root.join("notes"), root.join("archive/notes"))],
+            |path| std::fs::read_to_string(path).ok(),
+        )
+        .unwrap();
+        assert_eq!(
+            texts_of(&edits, &root.join("notes/idea.typ")),
+            vec!["../../img/plot.png"]
+        );
+        assert_eq!(
+            texts_of(&edits, &root.join("other.typ")),
+            vec!["[[archive/notes/idea:sec]]", "archive/notes/idea.typ"]
+        );
+    }
+
+    fn texts_of(edits: &FileEdits, path: &Path) -> Vec<String> {
+        edits[path]
+            .iter()
+            .map(|edit| edit.new_text.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,181 @@
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::names::normalize;
+use crate::parser::wikilinks::WikilinkSyntax;
+use crate::refactor::FileEdits;
+use crate::refactor::move_file::retarget;
+use anyhow::{Context, Result, bail};
+use lsp_types::{Position, Range, TextEdit};
+use std::path::{Path, PathBuf};
+
+/// Point every wikilink whose target is `from` at `to`, keeping its label, block, and alias.
+/// Targets are compared in NFC, ignoring case if the vault's links do, so links left behind
+/// by a rename outside the editor can be fixed even though they no longer resolve. `read`
+/// returns the current text of a note.
+pub fn rewrite_links(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    from: &str,
+    to: &str,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<FileEdits> {
+    if from.trim().is_empty() || to.trim().is_empty() {
+        bail!("Link targets cannot be empty");
+    }
+    let fold_case = index.case_insensitive_links()?;
+    let key = |target: &str| {
+        let target = normalize(target.trim());
+        if fold_case {
+            target.to_lowercase()
+        } else {
+            target
+        }
+    };
+    let from = key(from);
+
+    // The index knows which notes link to `from` without reading every note
+    let mut notes: Vec<PathBuf> = index
+        .get_all_links()?
+        .into_iter()
+        .filter(|(_, link)| key(&link.target) == from)
+        .map(|(note, _)| note)
+        .collect();
+    notes.dedup();
+
+    let mut edits = FileEdits::new();
+    for note in notes {
+        let Some(content) = read(&note) else {
+            continue;
+        };
+        for (mut wikilink, span) in parser.wikilink_spans(&content) {
+            if key(&wikilink.target) != from {
+                continue;
+            }
+            wikilink.target = to.to_string();
+            let line = wikilink.line as u32 - 1;
+            edits.entry(note.clone()).or_default().push(TextEdit::new(
+                Range::new(
+                    Position::new(line, span.start as u32),
+                    Position::new(line, span.end as u32),
+                ),
+                syntax.format(&wikilink),
+            ));
+        }
+    }
+    Ok(edits)
+}
+
+/// Point every link to a note with a `redirect` field at the note its redirects end at,
+/// keeping the link's style (path or bare name). `read` returns the current text of a note.
+pub fn redirect_edits(
+    index: &Index,
+    parser: &Parser,
+    syntax: &WikilinkSyntax,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Result<FileEdits> {
+    let mut redirected = index.get_redirected_links(None)?;
+    redirected.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut edits = FileEdits::new();
+    for links in redirected.chunk_by(|a, b| a.0 == b.0) {
+        let source = &links[0].0;
+        let Some(content) = read(source) else {
+            continue;
+        };
+        for (mut wikilink, span) in parser.wikilink_spans(&content) {
+            let Some((_, _, destination)) = links
+                .iter()
+                .find(|(_, link, _)| (link.line, link.column) == (wikilink.line, wikilink.column))
+            else {
+                continue;
+            };
+            let relative = destination
+                .strip_prefix(index.root())
+                .with_context(|| format!("{} is not in the vault", destination.display()))?;
+            let Some(target) = retarget(&wikilink.target, relative) else {
+                continue;
+            };
+            wikilink.target = target;
+            let line = wikilink.line as u32 - 1;
+            edits.entry(source.clone()).or_default().push(TextEdit::new(
+                Range::new(
+                    Position::new(line, span.start as u32),
+                    Position::new(line, span.end as u32),
+                ),
+                syntax.format(&wikilink),
+            ));
+        }
+    }
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::test_support::{add_note_with, vault};
+
+    #[test]
+    fn test_rewrite_links() {
+        let (temp_dir, index) = vault(&[
+            (
+                "a.typ",
+                "See [[old-name]] and [[old-name:intro|the intro]].\n",
+            ),
+            ("b.typ", "Also [[Old-Name]], not [[old-names]].\n"),
+        ]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let syntax = WikilinkSyntax::default();
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+
+        let edits = rewrite_links(&index, &parser, &syntax, "old-name", "new", read).unwrap();
+        assert_eq!(edits.len(), 1);
+        let new_text: Vec<_> = edits[&root.join("a.typ")]
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect();
+        assert_eq!(new_text, vec!["[[new]]", "[[new:intro|the intro]]"]);
+
+        index.set_case_insensitive_links(true).unwrap();
+        let edits = rewrite_links(&index, &parser, &syntax, "old-name", "new", read).unwrap();
+        assert_eq!(edits[&root.join("b.typ")].len(), 1);
+        assert!(rewrite_links(&index, &parser, &syntax, "old-name", " ", read).is_err());
+    }
+
+    #[test]
+    fn test_redirect_edits() {
+        let (temp_dir, mut index) = vault(&[]);
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let notes = [
+            ("old.typ", "", Some("archive/new")),
+            ("archive/new.typ", "", None),
+            (
+                "a.typ",
+                "[[old:intro]] and [[./old|Old]] and [[old]]\n",
+                None,
+            ),
+        ];
+        for (name, content, redirect) in notes {
+            add_note_with(&mut index, name, content, |parsed| {
+                if let Some(redirect) = redirect {
+                    parsed
+                        .metadata
+                        .custom
+                        .insert("redirect".to_string(), redirect.into());
+                }
+            });
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).ok();
+        let edits = redirect_edits(&index, &parser, &WikilinkSyntax::default(), read).unwrap();
+        let new_text: Vec<_> = edits[&root.join("a.typ")]
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect();
+        assert_eq!(
+            new_text,
+            vec!["[[new:intro]]", "[[archive/new|Old]]", "[[new]]"]
+        );
+    }
+}
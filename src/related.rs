@@ -0,0 +1,190 @@
+use crate::graph::{Direction, adjacency};
+use crate::storage::Storage;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use ts_rs::TS;
+
+// Relative weight of each signal in the combined score
+const TAG_WEIGHT: f64 = 2.0;
+const NEIGHBOR_WEIGHT: f64 = 1.5;
+const TERM_WEIGHT: f64 = 3.0;
+const LINK_WEIGHT: f64 = 1.0;
+
+/// A note scored by how related it is to another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RelatedNote {
+    pub path: PathBuf,
+    pub score: f64,
+    pub shared_tags: Vec<String>,
+    /// Notes both link to or from
+    pub shared_neighbors: usize,
+    /// Jaccard similarity of the words in the titles and file names
+    pub term_overlap: f64,
+    pub linked: bool,
+}
+
+/// Other notes ranked by shared tags, shared graph neighbors, title terms, and direct links.
+/// Notes with no signal at all are omitted.
+pub fn related(index: &dyn Storage, file: &Path, limit: usize) -> Result<Vec<RelatedNote>> {
+    let root = index.root();
+    let mut tags: HashMap<PathBuf, BTreeSet<String>> = HashMap::new();
+    let mut titles: HashMap<PathBuf, String> = HashMap::new();
+    for (path, key, value) in index.get_all_metadata()? {
+        match key.as_str() {
+            "tags" => {
+                tags.entry(root.join(path)).or_default().insert(value);
+            }
+            "title" => {
+                titles.insert(root.join(path), value);
+            }
+            _ => {}
+        }
+    }
+
+    let adjacent = adjacency(index, Direction::Both)?;
+    let neighbors = |note: &Path| -> BTreeSet<&Path> {
+        adjacent
+            .get(note)
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+            .filter(|neighbor| *neighbor != note)
+            .collect()
+    };
+    let terms = |note: &Path| {
+        let stem = note.file_stem().unwrap_or_default().to_string_lossy();
+        let title = titles.get(note).map(String::as_str).unwrap_or_default();
+        terms(&format!("{stem} {title}"))
+    };
+
+    let empty = BTreeSet::new();
+    let own_tags = tags.get(file).unwrap_or(&empty);
+    let own_neighbors = neighbors(file);
+    let own_terms = terms(file);
+
+    let mut scored = Vec::new();
+    for note in index.get_all_files()? {
+        if note == file {
+            continue;
+        }
+        let shared_tags: Vec<String> = tags
+            .get(&note)
+            .unwrap_or(&empty)
+            .intersection(own_tags)
+            .cloned()
+            .collect();
+        let shared_neighbors = neighbors(&note).intersection(&own_neighbors).count();
+        let term_overlap = jaccard(&terms(&note), &own_terms);
+        let linked = own_neighbors.contains(note.as_path());
+
+        let score = TAG_WEIGHT * shared_tags.len() as f64
+            + NEIGHBOR_WEIGHT * shared_neighbors as f64
+            + TERM_WEIGHT * term_overlap
+            + if linked { LINK_WEIGHT } else { 0.0 };
+        if score > 0.0 {
+            scored.push(RelatedNote {
+                path: note,
+                score,
+                shared_tags,
+                shared_neighbors,
+                term_overlap,
+                linked,
+            });
+        }
+    }
+
+    scored.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    scored.truncate(limit);
+    Ok(scored)
+}
+
+/// Lowercased words of at least three characters.
+fn terms(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::parser::models::{Metadata, ParsedFile, Wikilink};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_related() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let mut index = Index::new(root).unwrap();
+
+        let notes: [(&str, &[&str], &[&str]); 5] = [
+            ("rust-ownership", &["rust"], &["memory"]),
+            ("rust-borrowing", &["rust"], &["memory"]),
+            ("memory", &[], &[]),
+            ("ownership-law", &[], &[]),
+            ("cooking", &["food"], &[]),
+        ];
+        for (name, note_tags, targets) in notes {
+            let path = root.join(format!("{name}.typ"));
+            std::fs::write(&path, name).unwrap();
+            let wikilinks = targets
+                .iter()
+                .map(|target| Wikilink {
+                    target: target.to_string(),
+                    alias: None,
+                    label: None,
+                    block: None,
+                    line: 1,
+                    column: 1,
+                })
+                .collect();
+            let parsed = ParsedFile {
+                path: path.clone(),
+                metadata: Metadata {
+                    tags: note_tags.iter().map(|tag| tag.to_string()).collect(),
+                    ..Default::default()
+                },
+                wikilinks,
+                labels: vec![],
+                headings: vec![],
+                blocks: vec![],
+                attachments: vec![],
+                stats: Default::default(),
+                conflicts: Vec::new(),
+                figures: Vec::new(),
+                citations: Vec::new(),
+                bibliographies: Vec::new(),
+                packages: Vec::new(),
+            };
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let related = related(&index, &root.join("rust-ownership.typ"), 10).unwrap();
+        let names: Vec<_> = related
+            .iter()
+            .map(|note| note.path.file_stem().unwrap().to_string_lossy())
+            .collect();
+        assert_eq!(names, vec!["rust-borrowing", "memory", "ownership-law"]);
+        assert_eq!(related[0].shared_tags, vec!["rust"]);
+        assert_eq!(related[0].shared_neighbors, 1);
+        assert!(related[1].linked);
+        assert_eq!(related[2].term_overlap, 1.0 / 3.0);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::parser::names;
+use crate::stored_path;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The stored paths (see [`stored_path::encode`]) a link target names as a note, composed and
+/// decomposed, or `None` if the target has an extension other than `.typ`.
+pub fn note_paths(target: &str) -> Option<(String, String)> {
+    let target_path = Path::new(target);
+    if target_path
+        .extension()
+        .is_some_and(|extension| extension != "typ")
+    {
+        return None;
+    }
+    let relative_path = stored_path::encode(&target_path.with_extension("typ"));
+    Some((
+        names::normalize(&relative_path),
+        names::decompose(&relative_path),
+    ))
+}
+
+/// The name a link target matches notes by, e.g. `notes/idea.typ` becomes `idea`, in NFC.
+pub fn target_stem(target: &str) -> String {
+    let stem = Path::new(target)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(target);
+    names::normalize(stem)
+}
+
+/// Resolves link targets against a list of notes by the rules the index applies, for clients
+/// without the SQLite index such as a browser build.
+///
+/// An exact vault-relative path wins over the first note (by path) with the same stem. Under
+/// case-insensitive matching, a match in the target's own case wins over one in another case;
+/// like SQLite's `NOCASE`, only ASCII letters are folded.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    // Stored path to stem, ordered by path as the index orders matches
+    notes: BTreeMap<String, String>,
+    case_insensitive: bool,
+}
+
+impl Resolver {
+    /// A resolver over notes given by their stored, `/`-separated vault-relative paths.
+    pub fn new(notes: impl IntoIterator<Item = impl Into<String>>, case_insensitive: bool) -> Self {
+        let mut resolver = Self {
+            notes: BTreeMap::new(),
+            case_insensitive,
+        };
+        for path in notes {
+            resolver.insert(path);
+        }
+        resolver
+    }
+
+    /// Add the note at stored path `path`.
+    pub fn insert(&mut self, path: impl Into<String>) {
+        let path = path.into();
+        let stem = stored_path::stem(&stored_path::decode(&path));
+        self.notes.insert(path, stem);
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        self.notes.remove(path);
+    }
+
+    /// The stored path of the note `target` links to.
+    pub fn resolve(&self, target: &str) -> Option<&str> {
+        let (composed, decomposed) = note_paths(target)?;
+        let by_path = self.find(
+            |path, _| path == composed || path == decomposed,
+            |path, _| {
+                path.eq_ignore_ascii_case(&composed) || path.eq_ignore_ascii_case(&decomposed)
+            },
+        );
+        if by_path.is_some() {
+            return by_path;
+        }
+        let stem = target_stem(target);
+        self.find(
+            |_, note| note == stem,
+            |_, note| note.eq_ignore_ascii_case(&stem),
+        )
+    }
+
+    // The first note by path matching `exact`, or else `folded` if case is ignored
+    fn find(
+        &self,
+        exact: impl Fn(&str, &str) -> bool,
+        folded: impl Fn(&str, &str) -> bool,
+    ) -> Option<&str> {
+        let first = |matches: &dyn Fn(&str, &str) -> bool| {
+            self.notes
+                .iter()
+                .find(|(path, stem)| matches(path, stem))
+                .map(|(path, _)| path.as_str())
+        };
+        first(&exact).or_else(|| self.case_insensitive.then(|| first(&folded)).flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTES: [&str; 6] = [
+        "notes/idea.typ",
+        "archive/idea.typ",
+        "Idea.typ",
+        "cafe\u{301}.typ",
+        "work/Plan.typ",
+        "100%25 done.typ",
+    ];
+
+    #[test]
+    fn test_resolve() {
+        let resolver = Resolver::new(NOTES, false);
+        assert_eq!(resolver.resolve("idea"), Some("archive/idea.typ"));
+        assert_eq!(resolver.resolve("notes/idea"), Some("notes/idea.typ"));
+        assert_eq!(resolver.resolve("café"), Some("cafe\u{301}.typ"));
+        assert_eq!(resolver.resolve("100% done"), Some("100%25 done.typ"));
+        assert_eq!(resolver.resolve("plan"), None);
+        assert_eq!(resolver.resolve("image.png"), None);
+
+        let resolver = Resolver::new(NOTES, true);
+        assert_eq!(resolver.resolve("Idea"), Some("Idea.typ"));
+        assert_eq!(resolver.resolve("IDEA"), Some("Idea.typ"));
+        assert_eq!(resolver.resolve("work/plan.typ"), Some("work/Plan.typ"));
+    }
+
+    // The resolver must agree with the index, which resolves links in SQL
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_matches_index() {
+        use crate::index::Index;
+        use crate::parser::Parser;
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let parser = Parser::new().unwrap();
+        let mut index = Index::new(root).unwrap();
+        for note in NOTES {
+            let path = root.join(stored_path::decode(note));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            let parsed = parser.parse_content("", &path).unwrap();
+            index.store_file(&path, &parsed).unwrap();
+        }
+
+        let targets = [
+            "idea",
+            "notes/idea",
+            "IDEA",
+            "café",
+            "plan",
+            "work/plan.typ",
+            "100% done",
+            "image.png",
+            "missing",
+            "ARCHIVE/IDEA",
+        ];
+        for case_insensitive in [false, true] {
+            index.set_case_insensitive_links(case_insensitive).unwrap();
+            let resolver = Resolver::new(NOTES, case_insensitive);
+            for target in targets {
+                let expected = index
+                    .resolve_target(target)
+                    .unwrap()
+                    .map(|path| stored_path::encode(path.strip_prefix(root).unwrap()));
+                assert_eq!(
+                    resolver.resolve(target),
+                    expected.as_deref(),
+                    "{target} with case_insensitive={case_insensitive}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::command::*;
+use anyhow::{Context, Result};
+use lsp_types::WorkspaceEdit;
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::Path;
+use ts_rs::TS;
+
+/// Version of the `typst-oxide/...` requests and their types, raised whenever a change would
+/// break an existing client. Exported as `SDK_VERSION` in `methods.ts`.
+pub const SDK_VERSION: u32 = 1;
+
+/// Where `cargo test` and `typst-oxide-cli bindings` put the TypeScript bindings by default.
+pub const BINDINGS_DIR: &str = "bindings/src";
+
+/// A custom LSP method with the TypeScript types of its params and result.
+pub struct Method {
+    pub name: &'static str,
+    /// Whether it is a notification from the server rather than a request
+    pub notification: bool,
+    pub params: String,
+    pub result: String,
+    export: fn(&Path) -> Result<(), ts_rs::ExportError>,
+}
+
+impl Method {
+    fn request<R>() -> Self
+    where
+        R: Request,
+        R::Params: TS + 'static,
+        R::Result: TS + 'static,
+    {
+        Self {
+            name: R::METHOD,
+            notification: false,
+            params: R::Params::name(),
+            result: R::Result::name(),
+            export: |dir| {
+                export::<R::Params>(dir)?;
+                export::<R::Result>(dir)
+            },
+        }
+    }
+
+    // A request answered with an `lsp_types::WorkspaceEdit`, which clients already have types for
+    fn edit<R>() -> Self
+    where
+        R: Request<Result = WorkspaceEdit>,
+        R::Params: TS + 'static,
+    {
+        Self {
+            name: R::METHOD,
+            notification: false,
+            params: R::Params::name(),
+            result: "WorkspaceEdit".to_string(),
+            export: export::<R::Params>,
+        }
+    }
+
+    fn notification<N>() -> Self
+    where
+        N: Notification,
+        N::Params: TS + 'static,
+    {
+        Self {
+            name: N::METHOD,
+            notification: true,
+            params: N::Params::name(),
+            result: "null".to_string(),
+            export: export::<N::Params>,
+        }
+    }
+}
+
+// Export `T` and the types it uses, unless it is a primitive such as `()`
+fn export<T: TS + 'static>(dir: &Path) -> Result<(), ts_rs::ExportError> {
+    match T::output_path() {
+        Some(_) => T::export_all_to(dir),
+        None => Ok(()),
+    }
+}
+
+/// Every `typst-oxide/...` method the server handles or sends.
+pub fn methods() -> Vec<Method> {
+    #[cfg_attr(
+        not(any(feature = "embeddings", feature = "compile")),
+        allow(unused_mut)
+    )]
+    let mut methods = vec![
+        Method::request::<ForwardLinks>(),
+        Method::request::<BackwardLinks>(),
+        Method::request::<LabelBacklinks>(),
+        Method::request::<Search>(),
+        Method::request::<Tags>(),
+        Method::request::<VaultGraph>(),
+        Method::request::<LocalGraph>(),
+        Method::request::<PathBetween>(),
+        Method::request::<Clusters>(),
+        Method::request::<Related>(),
+        Method::request::<Query>(),
+        Method::edit::<MaterializeQueries>(),
+        Method::edit::<GenerateMoc>(),
+        Method::edit::<UpdateMetadata>(),
+        Method::edit::<MoveFile>(),
+        Method::edit::<ArchiveNote>(),
+        Method::edit::<MergeNotes>(),
+        Method::edit::<RewriteLinks>(),
+        Method::edit::<FixRedirects>(),
+        Method::edit::<FindReplace>(),
+        Method::request::<Subscribe>(),
+        Method::notification::<EventNotification>(),
+        Method::request::<Stats>(),
+        Method::request::<VerifyIndex>(),
+        Method::request::<UnusedAttachments>(),
+        Method::request::<DailyNote>(),
+        Method::request::<PeriodicNote>(),
+        Method::request::<Calendar>(),
+        Method::request::<NewNote>(),
+        Method::request::<UnlinkedMentions>(),
+        Method::request::<DeadLabels>(),
+        Method::request::<Outline>(),
+        Method::request::<RecentNotes>(),
+        Method::request::<StarNote>(),
+        Method::request::<UnstarNote>(),
+        Method::request::<StarredNotes>(),
+        Method::request::<FileInfo>(),
+        Method::request::<History>(),
+        Method::request::<Figures>(),
+        Method::request::<Packages>(),
+    ];
+    #[cfg(feature = "embeddings")]
+    methods.push(Method::request::<SemanticSearch>());
+    #[cfg(feature = "compile")]
+    methods.push(Method::request::<Export>());
+    methods
+}
+
+/// Write the TypeScript bindings into `dir`: a file per type, `methods.ts` mapping each method
+/// to its params and result along with `SDK_VERSION`, and an `index.ts` re-exporting them all.
+/// Returns the number of types.
+pub fn export_bindings(dir: &Path) -> Result<usize> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    let methods = methods();
+    for method in &methods {
+        (method.export)(dir).with_context(|| format!("Cannot export {}", method.name))?;
+    }
+
+    let mut types = BTreeSet::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "ts")
+            && let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+            && !matches!(stem, "index" | "methods")
+        {
+            types.insert(stem.to_string());
+        }
+    }
+
+    let mut source = String::from("// Generated by `typst-oxide-cli bindings`. Do not edit.\n");
+    source.push_str("import type { WorkspaceEdit } from \"vscode-languageserver-types\";\n");
+    let used: BTreeSet<&str> = methods
+        .iter()
+        .flat_map(|method| [method.params.as_str(), method.result.as_str()])
+        .filter(|name| types.contains(*name))
+        .collect();
+    for name in &used {
+        writeln!(source, "import type {{ {name} }} from \"./{name}\";")?;
+    }
+    writeln!(source, "\nexport const SDK_VERSION = {SDK_VERSION};")?;
+    for (interface, notification) in [("Requests", false), ("Notifications", true)] {
+        writeln!(source, "\nexport interface {interface} {{")?;
+        for method in methods.iter().filter(|m| m.notification == notification) {
+            writeln!(
+                source,
+                "  \"{}\": {{ params: {}; result: {} }};",
+                method.name, method.params, method.result
+            )?;
+        }
+        source.push_str("}\n");
+    }
+    std::fs::write(dir.join("methods.ts"), source)?;
+
+    let mut index = String::from("// Generated by `typst-oxide-cli bindings`. Do not edit.\n");
+    for name in &types {
+        writeln!(index, "export type * from \"./{name}\";")?;
+    }
+    index.push_str("export * from \"./methods\";\n");
+    std::fs::write(dir.join("index.ts"), index)?;
+    Ok(types.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_export_bindings() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path();
+        let count = export_bindings(dir).unwrap();
+        assert!(count > 50);
+
+        let methods = std::fs::read_to_string(dir.join("methods.ts")).unwrap();
+        assert!(methods.contains(&format!("export const SDK_VERSION = {SDK_VERSION};")));
+        assert!(methods.contains(
+            "\"typst-oxide/search\": { params: SearchRequest; result: SearchResponse };"
+        ));
+        assert!(methods.contains(
+            "\"typst-oxide/findReplace\": { params: FindReplaceRequest; result: WorkspaceEdit };"
+        ));
+        assert!(methods.contains("\"typst-oxide/tags\": { params: null; result: TagsResponse };"));
+        assert!(methods.contains("\"typst-oxide/event\": { params: Event; result: null };"));
+        assert!(dir.join("Wikilink.ts").exists());
+
+        let index = std::fs::read_to_string(dir.join("index.ts")).unwrap();
+        assert!(index.contains("export type * from \"./Graph\";"));
+    }
+}
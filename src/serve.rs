@@ -0,0 +1,408 @@
+use crate::command::{
+    self, BackwardLinksRequest, ForwardLinksRequest, LabelBacklinksRequest, LocalGraphRequest,
+    SearchRequest, StatsRequest,
+};
+use crate::config::Config;
+use crate::events::{EventBus, EventKind};
+use crate::graph;
+use crate::index::IndexHandle;
+use crate::parser::Parser;
+use crate::paths::{OutsideVault, VaultPaths};
+use crate::workspace;
+use anyhow::Result;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, mpsc};
+use std::time::Duration;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+// Requests larger than this are rejected rather than buffered
+const MAX_BODY_BYTES: usize = 1 << 20;
+// A stalled client gives up its connection thread after this long
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// Events an event stream may fall behind by before it is unsubscribed
+const EVENT_BACKLOG: usize = 256;
+// An event stream whose client doesn't accept a write for this long is unsubscribed
+const EVENT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the command layer as a JSON API over HTTP/1.1. Bodies use the ts-rs request and
+/// response types; request paths may be absolute, relative to the vault root or `file://` URIs,
+/// and response paths follow `path_style`.
+/// `GET /events` keeps the connection open as a Server-Sent Events stream.
+pub struct ApiServer {
+    root: PathBuf,
+    config: Config,
+    index: IndexHandle,
+    parser: Arc<Parser>,
+    runtime: tokio::runtime::Runtime,
+    events: Mutex<EventBus>,
+}
+
+impl ApiServer {
+    /// Open and index the vault at `root`.
+    pub fn new(root: &Path, config: Config) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let (index, parser) = workspace::open_vault_handle(root, &config, &runtime)?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            config,
+            index,
+            parser,
+            runtime,
+            events: Mutex::default(),
+        })
+    }
+
+    /// Answer each connection on `listener` on its own thread until the listener fails.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = Arc::clone(&self);
+            std::thread::spawn(move || {
+                if let Err(e) = server.handle_connection(stream) {
+                    tracing::error!("Failed to handle request: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+
+        // `None` once a `Content-Length` header fails to parse
+        let mut content_length = Some(0);
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.trim().eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse().ok();
+            }
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let (status, body) = match content_length {
+            None => error(400, "Invalid Content-Length header"),
+            Some(length) if length > MAX_BODY_BYTES => error(413, "Request body too large"),
+            Some(_) if method == "GET" && path == "/events" => match event_kinds(query) {
+                Ok(kinds) => return self.stream_events(stream, kinds),
+                Err(e) => e,
+            },
+            Some(length) => {
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body)?;
+                self.route(&method, path, &body)
+            }
+        };
+
+        let body = body.to_string();
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            reason(status),
+            body.len()
+        )?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// Answer with an event stream, written from this connection's thread until the client
+    /// goes away. Publishing only queues events, so a slow client can't hold up indexing: one
+    /// that falls `EVENT_BACKLOG` events behind, or takes longer than `EVENT_WRITE_TIMEOUT` to
+    /// accept one, is unsubscribed.
+    fn stream_events(&self, mut stream: TcpStream, kinds: Vec<EventKind>) -> Result<()> {
+        stream.set_write_timeout(Some(EVENT_WRITE_TIMEOUT))?;
+        let (sender, receiver) = mpsc::sync_channel(EVENT_BACKLOG);
+        // Subscribed before the headers go out, so clients miss nothing once they see them
+        let id = lock(&self.events).subscribe(kinds, move |event| {
+            Ok(sender.try_send(serde_json::to_string(event)?)?)
+        });
+        let written = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+        )
+        .and_then(|()| stream.flush())
+        .and_then(|()| {
+            receiver.iter().try_for_each(|event| {
+                write!(stream, "data: {event}\n\n")?;
+                stream.flush()
+            })
+        });
+        lock(&self.events).unsubscribe(id);
+        if let Err(e) = written {
+            tracing::debug!("Event stream closed: {e}");
+        }
+        Ok(())
+    }
+
+    /// The status and JSON body answering `method path` with request `body`.
+    pub fn route(&self, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        match (method, path) {
+            ("GET", "/stats") => respond(self.paths().run(StatsRequest::default(), |request| {
+                self.index
+                    .read(|index| command::handle_stats(index, request))
+            })),
+            ("GET", "/graph") => respond(self.index.read(|index| graph::build(index, false))),
+            ("GET", "/tags") => respond(self.index.read(|index| command::handle_tags(index))),
+            ("POST", "/forward-links") => match parse::<ForwardLinksRequest>(body) {
+                Ok(request) => respond(self.paths().run(request, |request| {
+                    self.index
+                        .read(|index| command::handle_forward_links(index, request))
+                })),
+                Err(e) => e,
+            },
+            ("POST", "/backlinks") => match parse::<BackwardLinksRequest>(body) {
+                Ok(request) => respond(self.paths().run(request, |request| {
+                    self.index
+                        .read(|index| command::handle_backward_links(index, request))
+                })),
+                Err(e) => e,
+            },
+            ("POST", "/label-backlinks") => match parse::<LabelBacklinksRequest>(body) {
+                Ok(request) => respond(self.paths().run(request, |request| {
+                    self.index
+                        .read(|index| command::handle_label_backlinks(index, request))
+                })),
+                Err(e) => e,
+            },
+            ("POST", "/local-graph") => match parse::<LocalGraphRequest>(body) {
+                Ok(request) => respond(self.paths().run(request, |request| {
+                    self.index
+                        .read(|index| command::handle_local_graph(index, request))
+                })),
+                Err(e) => e,
+            },
+            ("POST", "/search") => match parse::<SearchRequest>(body) {
+                Ok(request) => respond(self.paths().run(request, |request| {
+                    self.index
+                        .read(|index| command::handle_search(index, request))
+                })),
+                Err(e) => e,
+            },
+            ("POST", "/reindex") => {
+                let indexed = self.config.note_filter().and_then(|filter| {
+                    self.index.write(|index| {
+                        lock(&self.events).track(index, |index| {
+                            self.runtime.block_on(workspace::index_workspace(
+                                &self.parser,
+                                index,
+                                &filter,
+                                &mut |_, _| {},
+                            ))
+                        })
+                    })
+                });
+                respond(indexed.map(|indexed| json!({ "indexed": indexed })))
+            }
+            _ => error(404, "Not found"),
+        }
+    }
+
+    fn paths(&self) -> VaultPaths<'_> {
+        VaultPaths::new(&self.root, self.config.path_style)
+    }
+}
+
+/// The kinds listed in a `kinds=fileIndexed,linkGraphChanged` query, none meaning all.
+fn event_kinds(query: &str) -> Result<Vec<EventKind>, (u16, Value)> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("kinds="))
+        .flat_map(|kinds| kinds.split(','))
+        .filter(|kind| !kind.is_empty())
+        .map(|kind| {
+            serde_json::from_value(Value::String(kind.to_string()))
+                .map_err(|_| error(400, &format!("Unknown event kind `{kind}`")))
+        })
+        .collect()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, (u16, Value)> {
+    serde_json::from_slice(body).map_err(|e| error(400, &e.to_string()))
+}
+
+fn respond<T: Serialize>(result: Result<T>) -> (u16, Value) {
+    match result.and_then(|value| Ok(serde_json::to_value(value)?)) {
+        Ok(value) => (200, value),
+        Err(e) if e.is::<OutsideVault>() => error(400, &e.to_string()),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IndexLocation;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_routes() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("target.typ"), "= Target\n").unwrap();
+        std::fs::write(root.join("source.typ"), "see [[target]]\n").unwrap();
+        let config = Config {
+            index_location: IndexLocation::Vault,
+            ..Config::default()
+        };
+        let server = ApiServer::new(root, config).unwrap();
+
+        let (status, body) = server.route(
+            "POST",
+            "/backlinks",
+            br#"{"file_path": "target.typ", "context_lines": 0}"#,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["links"][0]["context"][0], "see [[target]]");
+
+        let (status, body) = server.route("POST", "/search", br#"{"query": "sour"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(body["files"].as_array().unwrap().len(), 1);
+
+        let (status, body) = server.route("GET", "/graph", b"");
+        assert_eq!(status, 200);
+        assert_eq!(body["edges"].as_array().unwrap().len(), 1);
+
+        let (status, _) = server.route(
+            "POST",
+            "/backlinks",
+            br#"{"file_path": "../elsewhere.typ", "context_lines": 0}"#,
+        );
+        assert_eq!(status, 400);
+
+        assert_eq!(server.route("POST", "/search", b"{").0, 400);
+        assert_eq!(server.route("GET", "/nope", b"").0, 404);
+    }
+
+    #[test]
+    fn test_serve_over_tcp() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("note.typ"), "").unwrap();
+        let config = Config {
+            index_location: IndexLocation::Vault,
+            ..Config::default()
+        };
+        let server = Arc::new(ApiServer::new(root, config).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(listener));
+
+        // A client that never sends its request must not hold up the others
+        let _idle = TcpStream::connect(address).unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let body = r#"{"query":"note"}"#;
+            write!(
+                stream,
+                "POST /search HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("note.typ\"]}"));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST /search HTTP/1.1\r\nContent-Length: many\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().to_path_buf();
+        let config = Config {
+            index_location: IndexLocation::Vault,
+            ..Config::default()
+        };
+        let server = Arc::new(ApiServer::new(&root, config).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let streaming = Arc::clone(&server);
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            streaming.handle_connection(stream)
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET /events?kinds=fileIndexed HTTP/1.1\r\n\r\n").unwrap();
+        let mut lines = BufReader::new(stream).lines().map(Result::unwrap);
+        assert_eq!(lines.next().unwrap(), "HTTP/1.1 200 OK");
+
+        std::fs::write(root.join("note.typ"), "").unwrap();
+        assert_eq!(server.route("POST", "/reindex", b"").0, 200);
+        let data = lines.find(|line| line.starts_with("data: ")).unwrap();
+        let event: Value = serde_json::from_str(&data[6..]).unwrap();
+        assert_eq!(event["kind"], "fileIndexed");
+        assert_eq!(event["path"], json!(root.join("note.typ")));
+
+        // A client that goes away is unsubscribed by its connection thread
+        drop(lines);
+        std::fs::write(root.join("other.typ"), "").unwrap();
+        assert_eq!(server.route("POST", "/reindex", b"").0, 200);
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !lock(&server.events).is_empty() {
+            assert!(std::time::Instant::now() < deadline);
+            std::fs::write(
+                root.join("other.typ"),
+                format!("{:?}", std::time::Instant::now()),
+            )
+            .unwrap();
+            server.route("POST", "/reindex", b"");
+        }
+
+        assert_eq!(event_kinds("kinds=bogus").unwrap_err().0, 400);
+    }
+}
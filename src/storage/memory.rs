@@ -0,0 +1,364 @@
+use crate::parser::models::{ParsedFile, Wikilink};
+use crate::resolver::Resolver;
+use crate::storage::Storage;
+use crate::stored_path;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Notes kept in memory and resolved with [`Resolver`], for tests and clients without SQLite.
+///
+/// Only notes are tracked, so targets naming attachments never resolve.
+#[derive(Debug, Clone)]
+pub struct MemoryStorage {
+    root: PathBuf,
+    // By stored path, so iteration follows the index's path order
+    notes: BTreeMap<String, ParsedFile>,
+    resolver: Resolver,
+    archive_folders: Vec<PathBuf>,
+}
+
+impl MemoryStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            notes: BTreeMap::new(),
+            resolver: Resolver::default(),
+            archive_folders: Vec::new(),
+        }
+    }
+
+    /// Match links to notes ignoring ASCII case, as `case_insensitive_links` does for the index.
+    pub fn set_case_insensitive_links(&mut self, enabled: bool) {
+        self.resolver = Resolver::new(self.notes.keys().cloned(), enabled);
+    }
+
+    /// Set the vault-relative folders whose notes count as archived.
+    pub fn set_archive_folders(&mut self, folders: &[PathBuf]) {
+        self.archive_folders = folders.to_vec();
+    }
+
+    fn stored_path(&self, file_path: &Path) -> Result<String> {
+        let relative = file_path
+            .strip_prefix(&self.root)
+            .with_context(|| format!("File {} is not in workspace", file_path.display()))?;
+        Ok(stored_path::encode(relative))
+    }
+
+    fn vault_path(&self, stored: &str) -> PathBuf {
+        self.root.join(stored_path::decode(stored))
+    }
+
+    // Each note's links with the stored path of the note they resolve to, if any
+    fn resolved_links(&self) -> impl Iterator<Item = (&str, &Wikilink, Option<&str>)> {
+        self.notes.iter().flat_map(move |(path, parsed)| {
+            parsed
+                .wikilinks
+                .iter()
+                .map(move |link| (path.as_str(), link, self.resolver.resolve(&link.target)))
+        })
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn store_file(&mut self, file_path: &Path, parsed: &ParsedFile) -> Result<()> {
+        let path = self.stored_path(file_path)?;
+        self.resolver.insert(path.clone());
+        self.notes.insert(path, parsed.clone());
+        Ok(())
+    }
+
+    fn remove_file(&mut self, file_path: &Path) -> Result<()> {
+        let path = self.stored_path(file_path)?;
+        self.resolver.remove(&path);
+        self.notes.remove(&path);
+        Ok(())
+    }
+
+    fn get_all_files(&self) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .notes
+            .keys()
+            .map(|path| self.vault_path(path))
+            .collect())
+    }
+
+    fn resolve_target(&self, target: &str) -> Result<Option<PathBuf>> {
+        Ok(self
+            .resolver
+            .resolve(target)
+            .map(|path| self.vault_path(path)))
+    }
+
+    fn get_forward_links(&self, file_path: &Path) -> Result<Vec<Wikilink>> {
+        let path = self.stored_path(file_path)?;
+        Ok(self
+            .notes
+            .get(&path)
+            .map(|parsed| parsed.wikilinks.clone())
+            .unwrap_or_default())
+    }
+
+    fn get_backward_links(&self, target_file: &Path) -> Result<Vec<(PathBuf, Wikilink)>> {
+        let target = self.stored_path(target_file)?;
+        let mut links: Vec<(&str, &Wikilink)> = self
+            .resolved_links()
+            .filter(|(_, _, resolved)| *resolved == Some(target.as_str()))
+            .map(|(source, link, _)| (source, link))
+            .collect();
+        links.sort_by_key(|(source, link)| (*source, link.line, link.column));
+        Ok(links
+            .into_iter()
+            .map(|(source, link)| (self.vault_path(source), link.clone()))
+            .collect())
+    }
+
+    fn get_link_graph(&self) -> Result<Vec<(PathBuf, PathBuf, usize)>> {
+        let mut counts: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+        for (source, _, target) in self.resolved_links() {
+            if let Some(target) = target {
+                *counts.entry((source, target)).or_default() += 1;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|((source, target), count)| {
+                (
+                    stored_path::decode(source),
+                    stored_path::decode(target),
+                    count,
+                )
+            })
+            .collect())
+    }
+
+    fn get_all_metadata(&self) -> Result<Vec<(PathBuf, String, String)>> {
+        let mut entries = Vec::new();
+        for (path, parsed) in &self.notes {
+            let metadata = &parsed.metadata;
+            let mut note: Vec<(String, String)> = metadata
+                .title
+                .iter()
+                .map(|title| ("title".to_string(), title.clone()))
+                .chain(
+                    metadata
+                        .tags
+                        .iter()
+                        .map(|tag| ("tags".to_string(), tag.clone())),
+                )
+                .chain(
+                    metadata
+                        .alias
+                        .iter()
+                        .map(|alias| ("alias".to_string(), alias.clone())),
+                )
+                .chain(
+                    metadata
+                        .custom
+                        .iter()
+                        .map(|(key, value)| (key.clone(), value.to_string())),
+                )
+                .collect();
+            note.sort_by(|a, b| a.0.cmp(&b.0));
+            let relative = stored_path::decode(path);
+            entries.extend(
+                note.into_iter()
+                    .map(|(key, value)| (relative.clone(), key, value)),
+            );
+        }
+        Ok(entries)
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PathBuf>> {
+        let query = query.to_ascii_lowercase();
+        let matches = |text: &str| text.to_ascii_lowercase().contains(&query);
+        Ok(self
+            .notes
+            .iter()
+            .filter(|(path, parsed)| {
+                let metadata = &parsed.metadata;
+                matches(path)
+                    || metadata.title.as_deref().is_some_and(matches)
+                    || metadata
+                        .tags
+                        .iter()
+                        .chain(&metadata.alias)
+                        .any(|v| matches(v))
+            })
+            .map(|(path, _)| self.vault_path(path))
+            .collect())
+    }
+
+    fn archived_notes(&self) -> Result<Arc<HashSet<PathBuf>>> {
+        Ok(Arc::new(
+            self.notes
+                .iter()
+                .filter(|(path, parsed)| {
+                    parsed.metadata.custom.get("archived") == Some(&serde_json::Value::Bool(true))
+                        || self
+                            .archive_folders
+                            .iter()
+                            .any(|folder| stored_path::decode(path).starts_with(folder))
+                })
+                .map(|(path, _)| self.vault_path(path))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const NOTES: [(&str, &str); 4] = [
+        (
+            "a.typ",
+            "See [[b]], [[b:intro]] and [[Missing]].\nAlso [[notes/c|C]].",
+        ),
+        ("b.typ", "= Intro\nBack to [[a]]."),
+        ("notes/c.typ", "[[b]] [[image.png]]"),
+        ("archive/d.typ", "[[a]]"),
+    ];
+
+    // Store `NOTES` under `root`, with a title and tags on `a.typ` and `d.typ` archived
+    fn fill(storage: &mut dyn Storage, root: &Path) {
+        let parser = Parser::new().unwrap();
+        for (name, content) in NOTES {
+            let path = root.join(name);
+            let mut parsed = parser.parse_content(content, &path).unwrap();
+            if name == "a.typ" {
+                parsed.metadata.title = Some("Alpha".to_string());
+                parsed.metadata.tags = vec!["todo".to_string(), "idea".to_string()];
+                parsed
+                    .metadata
+                    .custom
+                    .insert("rating".to_string(), serde_json::json!(3));
+            }
+            if name == "archive/d.typ" {
+                parsed
+                    .metadata
+                    .custom
+                    .insert("archived".to_string(), serde_json::Value::Bool(true));
+            }
+            storage.store_file(&path, &parsed).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let root = Path::new("/vault");
+        let mut storage = MemoryStorage::new(root);
+        fill(&mut storage, root);
+
+        let backlinks = storage.get_backward_links(&root.join("b.typ")).unwrap();
+        let sources: Vec<_> = backlinks.iter().map(|(source, _)| source.clone()).collect();
+        assert_eq!(
+            sources,
+            [
+                root.join("a.typ"),
+                root.join("a.typ"),
+                root.join("notes/c.typ")
+            ]
+        );
+        assert_eq!(
+            storage.resolve_target("c").unwrap(),
+            Some(root.join("notes/c.typ"))
+        );
+        assert_eq!(storage.resolve_target("image.png").unwrap(), None);
+        assert_eq!(storage.search("ALPHA").unwrap(), [root.join("a.typ")]);
+        assert_eq!(
+            *storage.archived_notes().unwrap(),
+            HashSet::from([root.join("archive/d.typ")])
+        );
+        let outside = Parser::new()
+            .unwrap()
+            .parse_content("", Path::new("/elsewhere/e.typ"))
+            .unwrap();
+        assert!(storage.store_file(&outside.path, &outside).is_err());
+
+        storage.remove_file(&root.join("b.typ")).unwrap();
+        assert_eq!(storage.resolve_target("b").unwrap(), None);
+        assert!(
+            storage
+                .get_backward_links(&root.join("b.typ"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    // Both backends must answer every query alike
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_matches_index() {
+        use crate::index::Index;
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        for (name, content) in NOTES {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let mut index = Index::new(root).unwrap();
+        let mut memory = MemoryStorage::new(root);
+        fill(&mut index, root);
+        fill(&mut memory, root);
+        let folders = [PathBuf::from("notes")];
+        index.set_archive_folders(&folders).unwrap();
+        memory.set_archive_folders(&folders);
+
+        let backends: [&dyn Storage; 2] = [&index, &memory];
+        let [index, memory] = backends;
+        assert_eq!(
+            index.get_all_files().unwrap(),
+            memory.get_all_files().unwrap()
+        );
+        assert_eq!(
+            index.get_link_graph().unwrap(),
+            memory.get_link_graph().unwrap()
+        );
+        assert_eq!(
+            index.get_all_metadata().unwrap(),
+            memory.get_all_metadata().unwrap()
+        );
+        assert_eq!(
+            index.archived_notes().unwrap(),
+            memory.archived_notes().unwrap()
+        );
+        for query in ["a", "TODO", "notes/", "zzz"] {
+            assert_eq!(index.search(query).unwrap(), memory.search(query).unwrap());
+        }
+        for target in ["a", "notes/c", "C", "missing"] {
+            assert_eq!(
+                index.resolve_target(target).unwrap(),
+                memory.resolve_target(target).unwrap()
+            );
+        }
+        for (name, _) in NOTES {
+            let path = root.join(name);
+            assert_eq!(
+                index.get_forward_links(&path).unwrap(),
+                memory.get_forward_links(&path).unwrap()
+            );
+            assert_eq!(
+                index.get_backward_links(&path).unwrap(),
+                memory.get_backward_links(&path).unwrap()
+            );
+            assert_eq!(
+                crate::related::related(index, &path, 10).unwrap(),
+                crate::related::related(memory, &path, 10).unwrap()
+            );
+        }
+        assert_eq!(
+            crate::graph::algorithms::clusters(index).unwrap(),
+            crate::graph::algorithms::clusters(memory).unwrap()
+        );
+    }
+}
@@ -0,0 +1,53 @@
+mod memory;
+#[cfg(feature = "native")]
+mod sqlite;
+
+pub use memory::MemoryStorage;
+
+use crate::parser::models::{ParsedFile, Wikilink};
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Where parsed notes are kept and the link graph is queried. [`Index`](crate::index::Index)
+/// keeps them in SQLite; [`MemoryStorage`] in memory, for tests and the wasm build.
+///
+/// Note paths are absolute unless stated otherwise, and notes outside [`Storage::root`] are
+/// rejected. Results are ordered by path.
+pub trait Storage {
+    fn root(&self) -> &Path;
+
+    /// Store `parsed` as the note at `file_path`, replacing what was stored for it before.
+    fn store_file(&mut self, file_path: &Path, parsed: &ParsedFile) -> Result<()>;
+
+    fn remove_file(&mut self, file_path: &Path) -> Result<()>;
+
+    fn get_all_files(&self) -> Result<Vec<PathBuf>>;
+
+    /// The note or attachment a wikilink target resolves to.
+    fn resolve_target(&self, target: &str) -> Result<Option<PathBuf>>;
+
+    fn get_forward_links(&self, file_path: &Path) -> Result<Vec<Wikilink>>;
+
+    /// Links from any note that resolve to `target_file`, ordered by source, line and column.
+    fn get_backward_links(&self, target_file: &Path) -> Result<Vec<(PathBuf, Wikilink)>>;
+
+    /// Vault-relative `(source, target, link count)` for every pair of linked notes.
+    fn get_link_graph(&self) -> Result<Vec<(PathBuf, PathBuf, usize)>>;
+
+    /// Vault-relative `(path, key, value)` for every metadata entry, with custom values as JSON.
+    fn get_all_metadata(&self) -> Result<Vec<(PathBuf, String, String)>>;
+
+    /// Notes whose path, title, aliases, or tags contain `query`, ignoring ASCII case.
+    fn search(&self, query: &str) -> Result<Vec<PathBuf>>;
+
+    /// Notes marked `archived: true` or stored under an archive folder.
+    fn archived_notes(&self) -> Result<Arc<HashSet<PathBuf>>>;
+
+    /// Fail once the current query has been cancelled, so graph walks stop early. Storage
+    /// without cancellation never fails.
+    fn check_cancelled(&self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use crate::index::Index;
+use crate::parser::models::{ParsedFile, Wikilink};
+use crate::storage::Storage;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+impl Storage for Index {
+    fn root(&self) -> &Path {
+        Index::root(self)
+    }
+
+    fn store_file(&mut self, file_path: &Path, parsed: &ParsedFile) -> Result<()> {
+        Index::store_file(self, file_path, parsed)
+    }
+
+    fn remove_file(&mut self, file_path: &Path) -> Result<()> {
+        Index::remove_file(self, file_path)
+    }
+
+    fn get_all_files(&self) -> Result<Vec<PathBuf>> {
+        Index::get_all_files(self)
+    }
+
+    fn resolve_target(&self, target: &str) -> Result<Option<PathBuf>> {
+        Index::resolve_target(self, target)
+    }
+
+    fn get_forward_links(&self, file_path: &Path) -> Result<Vec<Wikilink>> {
+        Index::get_forward_links(self, file_path)
+    }
+
+    fn get_backward_links(&self, target_file: &Path) -> Result<Vec<(PathBuf, Wikilink)>> {
+        Index::get_backward_links(self, target_file)
+    }
+
+    fn get_link_graph(&self) -> Result<Vec<(PathBuf, PathBuf, usize)>> {
+        Index::get_link_graph(self)
+    }
+
+    fn get_all_metadata(&self) -> Result<Vec<(PathBuf, String, String)>> {
+        Index::get_all_metadata(self)
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<PathBuf>> {
+        Index::search(self, query)
+    }
+
+    fn archived_notes(&self) -> Result<Arc<HashSet<PathBuf>>> {
+        Index::archived_notes(self)
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        Index::check_cancelled(self)
+    }
+}
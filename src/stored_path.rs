@@ -0,0 +1,113 @@
+use crate::parser::names;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// The stored form of a vault-relative path: components joined with `/` on every platform, as
+/// UTF-8 text. Bytes that are not valid UTF-8 are written as `%XX` and `%` itself as `%25`, so
+/// every file name round-trips.
+pub fn encode(relative: &Path) -> String {
+    let mut encoded = String::new();
+    for component in relative.components() {
+        let part = match component {
+            Component::Normal(part) => part,
+            Component::ParentDir => "..".as_ref(),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => continue,
+        };
+        if !encoded.is_empty() {
+            encoded.push('/');
+        }
+        for chunk in part.as_encoded_bytes().utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '%' => encoded.push_str("%25"),
+                    c => encoded.push(c),
+                }
+            }
+            for byte in chunk.invalid() {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// The vault-relative path `stored` was encoded from.
+pub fn decode(stored: &str) -> PathBuf {
+    stored.split('/').map(decode_component).collect()
+}
+
+fn decode_component(part: &str) -> OsString {
+    let mut bytes = Vec::with_capacity(part.len());
+    let mut rest = part.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    os_string_from_bytes(bytes)
+}
+
+/// A file name from its raw bytes, as produced by `OsStr::as_encoded_bytes`.
+#[cfg(unix)]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+// Windows names are UTF-16, so only unpaired surrogates fail to decode here
+#[cfg(not(unix))]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    match String::from_utf8(bytes) {
+        Ok(name) => name.into(),
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned().into(),
+    }
+}
+
+/// The name a note is linked by: its file name without extension, lossily as UTF-8 and in NFC.
+pub fn stem(relative: &Path) -> String {
+    relative
+        .file_stem()
+        .map(|stem| names::normalize(&stem.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let nested: PathBuf = ["notes", "100% done.typ"].iter().collect();
+        assert_eq!(encode(&nested), "notes/100%25 done.typ");
+        assert_eq!(decode("notes/100%25 done.typ"), nested);
+        assert_eq!(encode(Path::new("./a/b.typ")), "a/b.typ");
+        assert_eq!(decode("ünï/cödé.typ"), Path::new("ünï").join("cödé.typ"));
+        assert_eq!(stem(&nested), "100% done");
+        assert_eq!(stem(Path::new("cafe\u{301}.typ")), "café");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.typ");
+        let path = Path::new("notes").join(name);
+        let encoded = encode(&path);
+        assert_eq!(encoded, "notes/caf%E9.typ");
+        assert_eq!(decode(&encoded), path);
+        assert_eq!(stem(&path), "caf\u{FFFD}");
+    }
+}
@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::publish::escape_markup;
+use anyhow::{Context, Result, bail};
+use chrono::NaiveDate;
+use std::path::{Component, Path, PathBuf};
+
+/// Values substituted for the `{{title}}`, `{{date}}`, and `{{tags}}` placeholders.
+#[derive(Debug, Clone)]
+pub struct TemplateVars<'a> {
+    pub title: &'a str,
+    pub date: NaiveDate,
+    pub tags: &'a [String],
+}
+
+/// Substitute placeholders in `template`.
+///
+/// `{{title}}` is escaped for where it stands: as the contents of a string when it follows
+/// an open quote on its line (`title: "{{title}}"`), and as markup anywhere else
+/// (`= {{title}}`). `{{tags}}` expands to the elements of a Typst array (`"a", "b",`), so
+/// templates should wrap it in parentheses: `tags: ({{tags}})`.
+pub fn render(template: &str, vars: &TemplateVars) -> String {
+    let tags: String = vars
+        .tags
+        .iter()
+        .map(|tag| format!("\"{}\", ", escape(tag)))
+        .collect();
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut copied = 0;
+    for (start, placeholder) in template.match_indices(TITLE) {
+        let line_start = template[..start].rfind('\n').map_or(0, |i| i + 1);
+        rendered.push_str(&template[copied..start]);
+        rendered.push_str(&match in_string(&template[line_start..start]) {
+            true => escape(vars.title),
+            false => escape_markup(vars.title),
+        });
+        copied = start + placeholder.len();
+    }
+    rendered.push_str(&template[copied..]);
+
+    rendered
+        .replace("{{date}}", &vars.date.to_string())
+        .replace("{{tags}}", tags.trim_end())
+}
+
+const TITLE: &str = "{{title}}";
+
+// Whether `line` ends inside a string literal, going by its unescaped quotes
+fn in_string(line: &str) -> bool {
+    let mut inside = false;
+    let mut escaped = false;
+    for c in line.chars() {
+        match c {
+            '\\' => escaped = !escaped,
+            '"' if !escaped => inside = !inside,
+            _ => escaped = false,
+        }
+    }
+    inside
+}
+
+/// Names (file stems) of the templates in the configured template folder.
+pub fn list_templates(root: &Path, config: &Config) -> Result<Vec<String>> {
+    let Some(folder) = &config.template_folder else {
+        return Ok(Vec::new());
+    };
+    let dir = root.join(folder);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "typ")
+            && let Some(stem) = path.file_stem()
+        {
+            names.push(stem.to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Content of a new note: the named template (or `default_template`) rendered with
+/// `vars`, or a bare `#meta` block when no template applies.
+pub fn note_content(
+    root: &Path,
+    config: &Config,
+    template: Option<&str>,
+    vars: &TemplateVars,
+) -> Result<String> {
+    let Some(name) = template.or(config.default_template.as_deref()) else {
+        return Ok(render(DEFAULT_TEMPLATE, vars));
+    };
+    let folder = config
+        .template_folder
+        .as_ref()
+        .context("No template_folder configured")?;
+    let path = root.join(folder).join(format!("{name}.typ"));
+    let template = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read template {}", path.display()))?;
+    Ok(render(&template, vars))
+}
+
+/// Create a note titled `vars.title` in `folder` (relative to the vault root).
+pub fn create_note(
+    root: &Path,
+    config: &Config,
+    template: Option<&str>,
+    folder: &Path,
+    vars: &TemplateVars,
+) -> Result<PathBuf> {
+    if folder.is_absolute() || folder.components().any(|c| c == Component::ParentDir) {
+        bail!("Folder {} is outside the vault", folder.display());
+    }
+    let path = root
+        .join(folder)
+        .join(format!("{}.typ", file_name(vars.title)));
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+
+    let content = note_content(root, config, template, vars)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+const DEFAULT_TEMPLATE: &str = "#meta(\n  title: \"{{title}}\",\n  tags: ({{tags}}),\n)\n\n";
+
+/// A file name for `title`, replacing characters that aren't valid in paths.
+fn file_name(title: &str) -> String {
+    title
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c => c,
+        })
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn vars<'a>(title: &'a str, tags: &'a [String]) -> TemplateVars<'a> {
+        TemplateVars {
+            title,
+            date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            tags,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let tags = vec!["rust".to_string(), "a\"b".to_string()];
+        assert_eq!(
+            render("{{title}} {{date}} ({{tags}})", &vars("Note", &tags)),
+            "Note 2024-05-01 (\"rust\", \"a\\\"b\",)"
+        );
+        assert_eq!(render("({{tags}})", &vars("Note", &[])), "()");
+
+        // Titles can't break out of the string or markup they're placed in
+        let title = "#set [x] \"quoted\"";
+        assert_eq!(
+            render(
+                "= {{title}}\n#meta(title: \"{{title}}\")",
+                &vars(title, &[])
+            ),
+            "= \\#set \\[x\\] \"quoted\"\n#meta(title: \"#set [x] \\\"quoted\\\"\")"
+        );
+    }
+
+    #[test]
+    fn test_create_note() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("templates")).unwrap();
+        std::fs::write(root.join("templates/idea.typ"), "= {{title}}\n").unwrap();
+        std::fs::write(root.join("templates/notes.md"), "").unwrap();
+        let mut config = Config {
+            template_folder: Some(PathBuf::from("templates")),
+            ..Config::default()
+        };
+        assert_eq!(list_templates(root, &config).unwrap(), vec!["idea"]);
+
+        let path = create_note(root, &config, None, Path::new("inbox"), &vars("A/B", &[])).unwrap();
+        assert_eq!(path, root.join("inbox/A-B.typ"));
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("title: \"A/B\"")
+        );
+        assert!(create_note(root, &config, None, Path::new("inbox"), &vars("A/B", &[])).is_err());
+
+        config.default_template = Some("idea".to_string());
+        let path = create_note(root, &config, None, Path::new(""), &vars("Idea", &[])).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "= Idea\n");
+        assert!(create_note(root, &config, None, Path::new("../out"), &vars("X", &[])).is_err());
+        assert!(create_note(root, &config, Some("nope"), Path::new(""), &vars("X", &[])).is_err());
+    }
+}
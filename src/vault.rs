@@ -0,0 +1,172 @@
+use crate::command::{
+    self, BacklinkInfo, BackwardLinksRequest, ForwardLinksRequest, GraphRequest, SearchRequest,
+};
+use crate::config::Config;
+use crate::graph::Graph;
+use crate::index::Index;
+use crate::parser::Parser;
+use crate::parser::models::Wikilink;
+use crate::paths::VaultPaths;
+use crate::workspace;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// An indexed vault, for programs that embed the engine instead of speaking LSP.
+///
+/// Note paths may be absolute, relative to the vault root or `file://` URIs; returned paths
+/// are absolute. Archived notes are left out of search results and the graph, as they are
+/// by default in the language server.
+///
+/// Opening and reindexing are async and run on the caller's Tokio runtime, which must have
+/// its IO and time drivers enabled; programs without one can drive them with
+/// `Runtime::block_on`. Queries are synchronous.
+pub struct Vault {
+    root: PathBuf,
+    config: Config,
+    index: Index,
+    parser: Arc<Parser>,
+}
+
+impl Vault {
+    /// Open the vault at `root` with its `.typst-oxide.toml`, indexing notes that changed
+    /// since it was last opened.
+    pub async fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let config = Config::load(root.as_ref(), None)?;
+        Self::with_config(root, config).await
+    }
+
+    /// Like [`Vault::open`] with `config` in place of the vault's own configuration.
+    pub async fn with_config(root: impl AsRef<Path>, config: Config) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let (index, parser) = workspace::open_vault(&root, &config).await?;
+        Ok(Self {
+            root,
+            config,
+            index,
+            parser,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The underlying index, for queries the facade does not cover.
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Notes whose path, title, aliases or tags contain `query`.
+    pub fn search(&self, query: &str) -> Result<Vec<PathBuf>> {
+        let request = SearchRequest {
+            query: query.to_string(),
+            include_archived: false,
+        };
+        Ok(command::handle_search(&self.index, request)?.files)
+    }
+
+    /// Links to `note` from other notes, each with its section and two lines of context.
+    pub fn backlinks(&self, note: impl AsRef<Path>) -> Result<Vec<BacklinkInfo>> {
+        let request = BackwardLinksRequest {
+            file_path: self.resolve_path(note.as_ref())?,
+            context_lines: None,
+        };
+        Ok(command::handle_backward_links(&self.index, request)?.links)
+    }
+
+    /// Links written in `note`.
+    pub fn forward_links(&self, note: impl AsRef<Path>) -> Result<Vec<Wikilink>> {
+        let request = ForwardLinksRequest {
+            file_path: self.resolve_path(note.as_ref())?,
+        };
+        Ok(command::handle_forward_links(&self.index, request)?.links)
+    }
+
+    /// The file a wikilink target such as `notes/idea` or `idea` resolves to.
+    pub fn resolve(&self, target: &str) -> Result<Option<PathBuf>> {
+        self.index.resolve_target(target)
+    }
+
+    /// The resolved note graph.
+    pub fn graph(&self) -> Result<Graph> {
+        command::handle_graph(&self.index, GraphRequest::default())
+    }
+
+    /// Bring the index up to date with the notes on disk, returning how many there are.
+    pub async fn reindex(&mut self) -> Result<usize> {
+        let filter = self.config.note_filter()?;
+        workspace::index_workspace(&self.parser, &mut self.index, &filter, &mut |_, _| {}).await
+    }
+
+    /// Reindex one note after it changed on disk.
+    pub async fn reindex_note(&mut self, note: impl AsRef<Path>) -> Result<()> {
+        let path = self.resolve_path(note.as_ref())?;
+        workspace::index_file(&self.parser, &mut self.index, &path).await
+    }
+
+    fn resolve_path(&self, path: &Path) -> Result<PathBuf> {
+        VaultPaths::new(&self.root, self.config.path_style).resolve(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_vault() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("a.typ"), "See [[b]].").unwrap();
+        std::fs::write(root.join("b.typ"), "Nothing yet.").unwrap();
+
+        // Runs on the test's runtime, which owning a runtime of its own would panic on
+        let mut vault = Vault::open(root).await.unwrap();
+        assert_eq!(vault.root(), root);
+        assert_eq!(vault.search("a").unwrap(), vec![root.join("a.typ")]);
+        let backlinks = vault.backlinks("b.typ").unwrap();
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].source_file, root.join("a.typ"));
+        assert_eq!(vault.forward_links(root.join("a.typ")).unwrap().len(), 1);
+        assert_eq!(vault.resolve("b").unwrap(), Some(root.join("b.typ")));
+        assert_eq!(vault.graph().unwrap().edges.len(), 1);
+
+        std::fs::write(root.join("c.typ"), "Back to [[a]].").unwrap();
+        assert_eq!(vault.reindex().await.unwrap(), 3);
+        assert_eq!(vault.backlinks("a.typ").unwrap().len(), 1);
+        std::fs::write(root.join("c.typ"), "Back to [[b]].").unwrap();
+        vault.reindex_note("c.typ").await.unwrap();
+        assert!(vault.backlinks("a.typ").unwrap().is_empty());
+        assert_eq!(vault.backlinks("b.typ").unwrap().len(), 2);
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn test_encrypt_index_needs_feature() {
+        use crate::config::IndexLocation;
+
+        let temp_dir = tempdir().unwrap();
+        let config = Config {
+            encrypt_index: true,
+            index_location: IndexLocation::Vault,
+            ..Config::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let error = runtime
+            .block_on(Vault::with_config(temp_dir.path(), config))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("`encryption` feature"));
+        assert!(!temp_dir.path().join(".pkm-cache.db").exists());
+    }
+}
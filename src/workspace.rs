@@ -0,0 +1,528 @@
+use crate::config::{Config, NoteFilter};
+use crate::git;
+use crate::index::{Index, IndexHandle, IndexReport, file_hash};
+use crate::parser::Parser;
+use crate::parser::models::ParsedFile;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use walkdir::WalkDir;
+
+/// Collect every note under `root` accepted by `filter`, skipping hidden files and directories.
+pub fn discover_notes(root: &Path, filter: &NoteFilter) -> Vec<PathBuf> {
+    discover(root, |relative| filter.is_note(relative))
+}
+
+/// Collect every attachment under `root` not excluded by `filter`, ordered by path.
+pub fn discover_attachments(root: &Path, filter: &NoteFilter) -> Vec<PathBuf> {
+    let mut attachments = discover(root, |relative| filter.is_attachment(relative));
+    attachments.sort();
+    attachments
+}
+
+/// Attachments on disk that no note links to or embeds, ordered by path.
+pub fn unused_attachments(index: &Index, filter: &NoteFilter) -> Result<Vec<PathBuf>> {
+    let referenced = index.get_referenced_attachments()?;
+    Ok(discover_attachments(index.root(), filter)
+        .into_iter()
+        .filter(|path| !referenced.contains(path))
+        .collect())
+}
+
+fn discover(root: &Path, accept: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.strip_prefix(root).is_ok_and(&accept))
+        .collect()
+}
+
+// Number of parsed notes written per transaction during a full scan
+const BATCH_SIZE: usize = 256;
+// Maximum number of notes parsed concurrently
+const PARALLELISM: usize = 16;
+
+pub async fn parse_note(parser: &Parser, file_path: &Path) -> Result<ParsedFile> {
+    match parser.parse_file(file_path).await {
+        Ok(parsed) => Ok(parsed),
+        // Still index links and labels when `typst query` is unavailable
+        Err(_) => parser.parse_file_content(file_path).await,
+    }
+}
+
+/// Open the configured index for `root` and bring it up to date, for the non-LSP servers.
+pub async fn open_vault(root: &Path, config: &Config) -> Result<(Index, Arc<Parser>)> {
+    let parser = Arc::new(Parser::with_syntax(&config.wikilink_syntax)?);
+    let mut index = Index::open_with_key(
+        root,
+        &config.index_path(root)?,
+        config.index_key()?.as_deref(),
+    )?;
+    prepare_index(&parser, &mut index, config).await?;
+    Ok((index, parser))
+}
+
+/// Like [`open_vault`], with the index behind a handle that several threads can share.
+pub fn open_vault_handle(
+    root: &Path,
+    config: &Config,
+    runtime: &tokio::runtime::Runtime,
+) -> Result<(IndexHandle, Arc<Parser>)> {
+    let parser = Arc::new(Parser::with_syntax(&config.wikilink_syntax)?);
+    let index = IndexHandle::open_with_key(root, &config.index_path(root)?, config.index_key()?)?;
+    index.write(|index| runtime.block_on(prepare_index(&parser, index, config)))?;
+    Ok((index, parser))
+}
+
+// Apply the index settings in `config` and bring the index up to date with the vault
+async fn prepare_index(parser: &Arc<Parser>, index: &mut Index, config: &Config) -> Result<()> {
+    index.set_case_insensitive_links(config.case_insensitive_links)?;
+    index.set_archive_folders(&config.archive_folders)?;
+    index.set_max_index_rows(config.max_index_rows)?;
+    index_workspace(parser, index, &config.note_filter()?, &mut |_, _| {}).await?;
+    record_git_history(index, config.git_history)
+}
+
+/// Record each note's last commit if `enabled`, or forget them otherwise. A vault outside a
+/// git repository is logged and left without history.
+pub fn record_git_history(index: &Index, enabled: bool) -> Result<()> {
+    let (head, commits) = read_git_history(index.root(), enabled);
+    index.set_last_commits(head.as_deref(), &commits)
+}
+
+/// Like [`record_git_history`], but only once `HEAD` has moved since history was recorded, as
+/// it does on commits and checkouts. The history is read without holding the index.
+pub fn refresh_git_history(index: &IndexHandle, enabled: bool) -> Result<()> {
+    if !enabled {
+        return Ok(());
+    }
+    let head = git::head(index.root()).ok().flatten();
+    if head == index.read(|index| index.git_head())? {
+        return Ok(());
+    }
+    let (head, commits) = read_git_history(index.root(), enabled);
+    index.write(|index| index.set_last_commits(head.as_deref(), &commits))
+}
+
+// The `HEAD` commit and the last commit of each file under `root`, or nothing unless `enabled`
+fn read_git_history(
+    root: &Path,
+    enabled: bool,
+) -> (Option<String>, HashMap<PathBuf, git::CommitInfo>) {
+    if !enabled {
+        return (None, HashMap::new());
+    }
+    git::head(root)
+        .and_then(|head| Ok((head, git::last_commits(root)?)))
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to read git history: {e:#}");
+            (None, HashMap::new())
+        })
+}
+
+// Whether the indexed copy of `file_path` already matches `hash`
+fn is_unchanged(index: &Index, file_path: &Path, hash: &str) -> Result<bool> {
+    Ok(index.get_content_hash(file_path)?.as_deref() == Some(hash))
+}
+
+pub async fn index_file(parser: &Parser, index: &mut Index, file_path: &Path) -> Result<()> {
+    let hash = file_hash(file_path)?;
+    if is_unchanged(index, file_path, &hash)? {
+        return Ok(());
+    }
+    let parsed = parse_note(parser, file_path).await?;
+    index.store_file(file_path, &parsed)?;
+    index.refresh_bibliographies(Some(&[file_path.to_path_buf()]))
+}
+
+/// Whether `path` has the extension of a bibliography file: BibTeX (`.bib`) or Hayagriva
+/// (`.yml`, `.yaml`).
+pub fn is_bibliography(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "bib" || ext == "yml" || ext == "yaml")
+}
+
+/// Reindex the changed notes among `paths` and forget the ones that no longer exist, writing
+/// them all in one transaction. Bibliography files among `paths` are re-read along with those
+/// of the changed notes.
+#[tracing::instrument(skip_all, fields(files = paths.len()))]
+pub async fn index_files(parser: &Parser, index: &mut Index, paths: &[PathBuf]) -> Result<()> {
+    let mut parsed = Vec::new();
+    let mut removed = Vec::new();
+    for path in paths {
+        if is_bibliography(path) {
+            continue;
+        }
+        // One path outside the vault must not keep the rest of the batch from being indexed
+        if !path.starts_with(index.root()) {
+            tracing::warn!("Skipping {}: not in workspace", path.display());
+            continue;
+        }
+        if !path.is_file() {
+            removed.push(path.clone());
+            continue;
+        }
+        match file_hash(path) {
+            Ok(hash) if is_unchanged(index, path, &hash)? => continue,
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!("Failed to read {}: {e}", path.display());
+                continue;
+            }
+        }
+        match parse_note(parser, path).await {
+            Ok(note) => parsed.push(note),
+            Err(e) => tracing::warn!("Failed to index {}: {e}", path.display()),
+        }
+    }
+    index.store_changes(&parsed, &removed)?;
+    index.refresh_bibliographies(Some(paths))
+}
+
+/// Index every note in the vault, parsing up to `PARALLELISM` notes at once and streaming
+/// results into the index in batches. `on_progress` receives `(done, total)` as notes finish.
+///
+/// Notes whose content hash is unchanged are skipped, and a new note with the same hash as a
+/// vanished one is treated as a rename so it keeps its identity in the index.
+#[tracing::instrument(skip_all)]
+pub async fn index_workspace(
+    parser: &Arc<Parser>,
+    index: &mut Index,
+    filter: &NoteFilter,
+    on_progress: &mut dyn FnMut(usize, usize),
+) -> Result<usize> {
+    let notes = discover_notes(index.root(), filter);
+    let total = notes.len();
+
+    let indexed: HashSet<PathBuf> = index.get_all_files()?.into_iter().collect();
+    let current: HashSet<&PathBuf> = notes.iter().collect();
+
+    // Notes that vanished may reappear under a new path with the same content
+    let mut vanished: HashMap<String, PathBuf> = HashMap::new();
+    for path in indexed.iter().filter(|path| !current.contains(path)) {
+        match index.get_content_hash(path)? {
+            Some(hash) => {
+                vanished.insert(hash, path.clone());
+            }
+            None => index.remove_file(path)?,
+        }
+    }
+
+    let mut done = 0;
+    let mut changed = Vec::new();
+    for note in notes {
+        let hash = match file_hash(&note) {
+            Ok(hash) => hash,
+            Err(e) => {
+                tracing::warn!("Failed to read {}: {e}", note.display());
+                done += 1;
+                continue;
+            }
+        };
+
+        if indexed.contains(&note) {
+            if is_unchanged(index, &note, &hash)? {
+                done += 1;
+                continue;
+            }
+        } else if let Some(old_path) = vanished.remove(&hash) {
+            index.rename_file(&old_path, &note)?;
+            done += 1;
+            continue;
+        }
+        changed.push(note);
+    }
+
+    // Whatever was not matched to a rename is gone
+    for path in vanished.values() {
+        index.remove_file(path)?;
+    }
+    on_progress(done, total);
+
+    let permits = Arc::new(Semaphore::new(PARALLELISM));
+    let mut tasks = JoinSet::new();
+    for note in changed {
+        let parser = Arc::clone(parser);
+        let permits = Arc::clone(&permits);
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let parsed = parse_note(&parser, &note).await;
+            (note, parsed)
+        });
+    }
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(result) = tasks.join_next().await {
+        match result? {
+            (_, Ok(parsed)) => batch.push(parsed),
+            (note, Err(e)) => tracing::warn!("Failed to index {}: {e}", note.display()),
+        }
+        if batch.len() >= BATCH_SIZE {
+            index.store_files_bulk(&batch)?;
+            batch.clear();
+        }
+
+        done += 1;
+        on_progress(done, total);
+    }
+    index.store_files_bulk(&batch)?;
+    index.refresh_bibliographies(None)?;
+
+    Ok(total)
+}
+
+/// Cross-check the index against the notes on disk and the database against itself, then,
+/// with `repair`, fix what was found: a damaged database is rebuilt from the notes, and
+/// otherwise orphaned rows are deleted and missing, stale and new notes are reindexed.
+pub async fn verify_index(
+    parser: &Arc<Parser>,
+    index: &mut Index,
+    filter: &NoteFilter,
+    repair: bool,
+) -> Result<IndexReport> {
+    let mut report = IndexReport {
+        integrity_errors: index.integrity_errors()?,
+        orphaned_rows: index.orphaned_rows()?,
+        ..IndexReport::default()
+    };
+
+    let mut notes = discover_notes(index.root(), filter);
+    notes.sort();
+    let indexed: HashSet<PathBuf> = index.get_all_files()?.into_iter().collect();
+    let current: HashSet<&PathBuf> = notes.iter().collect();
+    report.missing_files = indexed
+        .iter()
+        .filter(|path| !current.contains(path))
+        .cloned()
+        .collect();
+    report.missing_files.sort();
+    for note in notes {
+        if !indexed.contains(&note) {
+            report.unindexed_files.push(note);
+        } else if index.get_content_hash(&note)? != file_hash(&note).ok() {
+            report.stale_files.push(note);
+        }
+    }
+
+    if repair && !report.is_clean() {
+        if !report.integrity_errors.is_empty() {
+            report.rebuilt = index.reset_damaged()?;
+        }
+        index.remove_orphaned_rows()?;
+        index_workspace(parser, index, filter, &mut |_, _| {}).await?;
+        report.repaired = true;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_index_workspace() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::create_dir(root.join(".hidden")).unwrap();
+        for i in 0..40 {
+            std::fs::write(root.join(format!("note{i}.typ")), "[[other]]").unwrap();
+        }
+        std::fs::write(root.join("sub").join("other.typ"), "<label>").unwrap();
+        std::fs::write(root.join(".hidden").join("skipped.typ"), "").unwrap();
+        std::fs::write(root.join("readme.md"), "").unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parser = Arc::new(Parser::new().unwrap());
+        let mut index = Index::new(root).unwrap();
+        let mut reports = Vec::new();
+
+        let total = runtime
+            .block_on(index_workspace(
+                &parser,
+                &mut index,
+                &NoteFilter::default(),
+                &mut |done, total| reports.push((done, total)),
+            ))
+            .unwrap();
+
+        assert_eq!(total, 41);
+        assert_eq!(reports.first(), Some(&(0, 41)));
+        assert_eq!(reports.last(), Some(&(41, 41)));
+        assert_eq!(index.get_all_files().unwrap().len(), 41);
+        assert_eq!(
+            index
+                .get_backward_links(&root.join("sub").join("other.typ"))
+                .unwrap()
+                .len(),
+            40
+        );
+    }
+
+    #[test]
+    fn test_index_files() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parser = Parser::new().unwrap();
+        let mut index = Index::new(root).unwrap();
+        let (a, b) = (root.join("a.typ"), root.join("b.typ"));
+        std::fs::write(&a, "[[b]]").unwrap();
+        std::fs::write(&b, "").unwrap();
+        runtime
+            .block_on(index_files(&parser, &mut index, &[a.clone(), b.clone()]))
+            .unwrap();
+        assert_eq!(index.get_backward_links(&b).unwrap().len(), 1);
+
+        // A checkout deleting one note and changing the other, alongside a note outside the vault
+        let outside = tempdir().unwrap();
+        let stray = outside.path().join("stray.typ");
+        std::fs::write(&stray, "").unwrap();
+        std::fs::remove_file(&b).unwrap();
+        std::fs::write(&a, "[[b]] [[c]]").unwrap();
+        runtime
+            .block_on(index_files(
+                &parser,
+                &mut index,
+                &[stray, a.clone(), b.clone()],
+            ))
+            .unwrap();
+        assert_eq!(index.get_all_files().unwrap(), vec![a.clone()]);
+        assert_eq!(index.get_forward_links(&a).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_verify_index() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("a.typ"), "[[b]]").unwrap();
+        std::fs::write(root.join("b.typ"), "").unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parser = Arc::new(Parser::new().unwrap());
+        let mut index = Index::new(root).unwrap();
+        let filter = NoteFilter::default();
+        let verify = |index: &mut Index, repair| {
+            runtime
+                .block_on(verify_index(&parser, index, &filter, repair))
+                .unwrap()
+        };
+        runtime
+            .block_on(index_workspace(
+                &parser,
+                &mut index,
+                &filter,
+                &mut |_, _| {},
+            ))
+            .unwrap();
+        assert!(verify(&mut index, true).is_clean());
+
+        std::fs::remove_file(root.join("b.typ")).unwrap();
+        std::fs::write(root.join("a.typ"), "[[c]]").unwrap();
+        std::fs::write(root.join("c.typ"), "").unwrap();
+        let report = verify(&mut index, false);
+        assert_eq!(report.missing_files, vec![root.join("b.typ")]);
+        assert_eq!(report.stale_files, vec![root.join("a.typ")]);
+        assert_eq!(report.unindexed_files, vec![root.join("c.typ")]);
+        assert!(!report.repaired);
+        assert_eq!(verify(&mut index, false), report);
+
+        assert!(verify(&mut index, true).repaired);
+        assert!(verify(&mut index, false).is_clean());
+        assert_eq!(
+            index.get_backward_links(&root.join("c.typ")).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_reindex_skips_unchanged_and_detects_renames() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(root.join("a.typ"), "[[b]]").unwrap();
+        std::fs::write(root.join("b.typ"), "text").unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parser = Arc::new(Parser::new().unwrap());
+        let mut index = Index::new(root).unwrap();
+        let run = |index: &mut Index| {
+            let mut reports = Vec::new();
+            runtime
+                .block_on(index_workspace(
+                    &parser,
+                    index,
+                    &NoteFilter::default(),
+                    &mut |done, total| reports.push((done, total)),
+                ))
+                .unwrap();
+            reports
+        };
+        run(&mut index);
+
+        // Nothing changed, so nothing is parsed
+        assert_eq!(run(&mut index), vec![(2, 2)]);
+
+        std::fs::rename(root.join("a.typ"), root.join("renamed.typ")).unwrap();
+        assert_eq!(run(&mut index), vec![(2, 2)]);
+        assert!(index.get_file(&root.join("a.typ")).unwrap().is_none());
+        let links = index.get_forward_links(&root.join("renamed.typ")).unwrap();
+        assert_eq!(links[0].target, "b");
+
+        std::fs::write(root.join("b.typ"), "[[renamed]]").unwrap();
+        assert_eq!(run(&mut index), vec![(1, 2), (2, 2)]);
+        assert_eq!(
+            index
+                .get_backward_links(&root.join("renamed.typ"))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unused_attachments() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("img")).unwrap();
+        std::fs::write(
+            root.join("note.typ"),
+            "[[paper.pdf]]\n#image(\"img/plot.png\")",
+        )
+        .unwrap();
+        for name in [
+            "paper.pdf",
+            "img/plot.png",
+            "img/old.JPG",
+            "unused.pdf",
+            "todo.txt",
+        ] {
+            std::fs::write(root.join(name), "").unwrap();
+        }
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parser = Arc::new(Parser::new().unwrap());
+        let mut index = Index::new(root).unwrap();
+        let filter = NoteFilter::default();
+        runtime
+            .block_on(index_workspace(
+                &parser,
+                &mut index,
+                &filter,
+                &mut |_, _| {},
+            ))
+            .unwrap();
+
+        assert_eq!(
+            unused_attachments(&index, &filter).unwrap(),
+            vec![root.join("img/old.JPG"), root.join("unused.pdf")]
+        );
+    }
+}
@@ -0,0 +1,17 @@
+//! Snapshots of what the parser extracts from the notes in `tests/corpus/`, to catch any change
+//! in its output. Review changes with `cargo insta review`, or accept them all by running the
+//! tests with `INSTA_UPDATE=always`.
+
+use typst_oxide::parser::Parser;
+
+#[test]
+fn test_corpus() {
+    let parser = Parser::new().unwrap();
+    insta::glob!("corpus/*.typ", |path| {
+        let content = std::fs::read_to_string(path).unwrap();
+        // Relative, so snapshots don't depend on where the repository is checked out
+        let name = std::path::Path::new(path.file_name().unwrap());
+        let parsed = parser.parse_content(&content, name).unwrap();
+        insta::assert_json_snapshot!(parsed);
+    });
+}
@@ -0,0 +1,11 @@
+= Meeting notes
+
+<<<<<<< HEAD
+Decided to follow [[Plan A]].
+=======
+Decided to follow [[Plan B]].
+>>>>>>> feature
+Attendees: see [[People:team]].
+
+#image("../assets/whiteboard.png")
+#bibliography(("refs.bib", "extra.yml"))
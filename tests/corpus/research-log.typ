@@ -0,0 +1,38 @@
+#import "@preview/cetz:0.3.1": canvas, draw
+#import "../templates/note.typ": *
+
+#metadata((
+  title: "Research log",
+  tags: ("research", "log"),
+  alias: ("Lab notebook",),
+)) <note-meta>
+
+= Research log
+
+Started from [[papers/attention|the attention paper]] and the notes in
+[[Reading list:queue]]. See @vaswani2017 for the original model and
+@fig:pipeline for how the data flows. ^log-intro
+
+== Week 1 <week-1>
+
+Reproduced the baseline from [[experiments/baseline#^setup]]. The loss
+follows @eq:loss closely.
+
+$ cal(L) = -sum_i y_i log hat(y)_i $ <eq:loss>
+
+#figure(
+  image("figures/pipeline.svg", width: 80%),
+  caption: [The preprocessing pipeline, see [[Pipeline]].],
+) <fig:pipeline>
+
+== Week 2
+
+#table(
+  columns: 2,
+  [Run], [Accuracy],
+  [a], [0.81],
+) <tab:runs>
+
+Compare with [[experiments/baseline|last week's run]] and [[Missing note]].
+
+#bibliography("refs.bib")
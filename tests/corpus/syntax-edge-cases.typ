@@ -0,0 +1,22 @@
+= Edge cases for the scanners
+
+Math never holds links: $[[not-a-link]]$, nor does raw text: `[[also-not]]`
+and ```typ [[inside a raw block]]```.
+
+Escaped brackets stay text: \[[escaped]] but [[escaped\]target|alias\|pipe]] is one link.
+
+Nested [[outer [[inner]] ]] brackets, an empty link [[]], and
+a link split over
+lines [[first
+half]] are ordinary markup.
+
+Labels may use colons and dots: <sec:intro.part-1>, and an e-mail like
+someone@example.com is no reference, but @sec:intro.part-1 is.
+
+Unicode targets: [[café]] [[日本語のノート|ノート]] ^unicode
+
+```rust
+// [[code]] and <not-a-label> in a fenced block
+```
+
+#link("https://example.com")[External link]
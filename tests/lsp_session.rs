@@ -0,0 +1,294 @@
+//! A language server session with the `typst-oxide` binary over stdio, from `initialize`
+//! through `exit`, the way an editor drives it.
+
+use lsp_server::{ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeWatchedFiles, DidOpenTextDocument, Exit, Initialized, Notification as _, Progress,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, Initialize, Request as _, Shutdown, WorkDoneProgressCreate,
+};
+use lsp_types::{
+    ClientCapabilities, CompletionParams, CompletionResponse, DidChangeWatchedFilesParams,
+    DidOpenTextDocumentParams, FileChangeType, FileEvent, GotoDefinitionParams,
+    GotoDefinitionResponse, InitializeParams, InitializedParams, NumberOrString, Position,
+    ProgressParams, PublishDiagnosticsParams, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, Uri, WindowClientCapabilities, WorkDoneProgressCreateParams,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::io::BufReader;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+use typst_oxide::command::{
+    Clusters, DailyNote, MaterializeQueries, Stats, StatsResponse, Subscribe, UnlinkedMentions,
+};
+use typst_oxide::lsp::path_to_uri;
+
+// How long to wait for any one message before giving up on the server
+const TIMEOUT: Duration = Duration::from_secs(30);
+// The token initial indexing reports progress on
+const INDEXING_TOKEN: &str = "typst-oxide/indexing";
+
+// The client end of a session, with the server's messages read on their own thread
+struct Client {
+    server: Child,
+    stdin: ChildStdin,
+    messages: mpsc::Receiver<Message>,
+    next_id: i32,
+    // Progress tokens the client accepted, and how many `$/progress` it received
+    accepted: HashSet<String>,
+    progress: usize,
+}
+
+impl Client {
+    fn start(root: &std::path::Path) -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_typst-oxide"))
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let stdin = server.stdin.take().unwrap();
+        let mut stdout = BufReader::new(server.stdout.take().unwrap());
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(Some(message)) = Message::read(&mut stdout) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            server,
+            stdin,
+            messages,
+            next_id: 0,
+            accepted: HashSet::new(),
+            progress: 0,
+        }
+    }
+
+    fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result
+    where
+        R::Params: Serialize,
+        R::Result: DeserializeOwned,
+    {
+        serde_json::from_value(self.send(R::METHOD, params)).unwrap()
+    }
+
+    fn send(&mut self, method: &str, params: impl Serialize) -> serde_json::Value {
+        let response = self.respond(method, params);
+        assert!(response.error.is_none(), "{:?}", response.error);
+        response.result.unwrap_or_default()
+    }
+
+    // Send a request and wait for its response
+    fn respond(&mut self, method: &str, params: impl Serialize) -> Response {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        self.write(Message::Request(Request::new(
+            id.clone(),
+            method.into(),
+            params,
+        )));
+        loop {
+            if let Message::Response(response) = self.next()
+                && response.id == id
+            {
+                return response;
+            }
+        }
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(&mut self, params: N::Params)
+    where
+        N::Params: Serialize,
+    {
+        self.write(Message::Notification(Notification::new(
+            N::METHOD.into(),
+            params,
+        )));
+    }
+
+    // Wait for the diagnostics published for `uri`
+    fn diagnostics(&mut self, uri: &Uri) -> PublishDiagnosticsParams {
+        loop {
+            if let Message::Notification(notification) = self.next()
+                && notification.method == PublishDiagnostics::METHOD
+            {
+                let params: PublishDiagnosticsParams =
+                    serde_json::from_value(notification.params).unwrap();
+                if params.uri == *uri {
+                    return params;
+                }
+            }
+        }
+    }
+
+    // The next message from the server, accepting progress tokens (except the indexing one,
+    // which is refused) and checking that progress is only reported on accepted ones
+    fn next(&mut self) -> Message {
+        let message = self
+            .messages
+            .recv_timeout(TIMEOUT)
+            .expect("The server stopped answering");
+        match &message {
+            Message::Request(request) if request.method == WorkDoneProgressCreate::METHOD => {
+                let params: WorkDoneProgressCreateParams =
+                    serde_json::from_value(request.params.clone()).unwrap();
+                let NumberOrString::String(token) = params.token else {
+                    panic!("Expected a string token");
+                };
+                let reply = if token == INDEXING_TOKEN {
+                    Response::new_err(request.id.clone(), 0, "refused".into())
+                } else {
+                    self.accepted.insert(token);
+                    Response::new_ok(request.id.clone(), ())
+                };
+                self.write(Message::Response(reply));
+            }
+            Message::Notification(notification) if notification.method == Progress::METHOD => {
+                let params: ProgressParams =
+                    serde_json::from_value(notification.params.clone()).unwrap();
+                let NumberOrString::String(token) = params.token else {
+                    panic!("Expected a string token");
+                };
+                assert!(
+                    self.accepted.contains(&token),
+                    "{token} used before accepted"
+                );
+                self.progress += 1;
+            }
+            _ => {}
+        }
+        message
+    }
+
+    fn write(&mut self, message: Message) {
+        message.write(&mut self.stdin).unwrap();
+    }
+}
+
+fn position(uri: &Uri, line: u32, character: u32) -> TextDocumentPositionParams {
+    TextDocumentPositionParams {
+        text_document: TextDocumentIdentifier { uri: uri.clone() },
+        position: Position { line, character },
+    }
+}
+
+#[test]
+fn test_session() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    std::fs::write(root.join("alpha.typ"), "= Alpha\nSee [[beta]].").unwrap();
+    std::fs::write(root.join("beta.typ"), "= Beta\nBack to [[alpha]].").unwrap();
+    let mut client = Client::start(root);
+
+    // Read as JSON, as `offsetEncoding` is sent as a list where `InitializeResult` expects a
+    // string
+    #[allow(deprecated)]
+    let result = client.send(
+        Initialize::METHOD,
+        InitializeParams {
+            root_uri: Some(path_to_uri(root).unwrap()),
+            capabilities: ClientCapabilities {
+                window: Some(WindowClientCapabilities {
+                    work_done_progress: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            // Keep the index inside the temporary vault
+            initialization_options: Some(serde_json::json!({
+                "index_location": "vault",
+                "reindex_debounce_ms": 10,
+            })),
+            ..Default::default()
+        },
+    );
+    let capabilities = &result["capabilities"];
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert!(capabilities["completionProvider"].is_object());
+    client.notify::<Initialized>(InitializedParams {});
+
+    let alpha = path_to_uri(&root.join("alpha.typ")).unwrap();
+    let text = "= Alpha\nSee [[beta]] and [[be";
+    client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(alpha.clone(), "typst".into(), 1, text.into()),
+    });
+
+    let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+        text_document_position_params: position(&alpha, 1, 7),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    });
+    let Some(GotoDefinitionResponse::Scalar(location)) = definition else {
+        panic!("Expected a single location, got {definition:?}");
+    };
+    assert_eq!(location.uri, path_to_uri(&root.join("beta.typ")).unwrap());
+
+    let completion = client.request::<Completion>(CompletionParams {
+        text_document_position: position(&alpha, 1, 22),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    });
+    let items = match completion {
+        Some(CompletionResponse::Array(items)) => items,
+        Some(CompletionResponse::List(list)) => list.items,
+        None => panic!("Expected completions"),
+    };
+    assert!(
+        items.iter().any(|item| item.label == "beta"),
+        "{:?}",
+        items.iter().map(|item| &item.label).collect::<Vec<_>>()
+    );
+
+    // A note created outside the editor is indexed once its change is debounced, and its
+    // problems are published after that
+    let gamma = root.join("gamma.typ");
+    std::fs::write(&gamma, "[[alpha]]").unwrap();
+    let gamma = path_to_uri(&gamma).unwrap();
+    client.notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+        changes: vec![FileEvent::new(gamma.clone(), FileChangeType::CREATED)],
+    });
+    client.diagnostics(&gamma);
+    let response: StatsResponse = client.request::<Stats>(Default::default());
+    assert_eq!(response.stats.note_count, 3);
+    // The statistics reported progress, unlike indexing
+    assert!(client.progress > 0);
+
+    // Malformed params are rejected rather than read as the defaults
+    for (method, params) in [
+        (DailyNote::METHOD, serde_json::json!({ "date": 5 })),
+        (
+            UnlinkedMentions::METHOD,
+            serde_json::json!({ "file_path": 5 }),
+        ),
+        (Clusters::METHOD, serde_json::json!({ "min_size": "two" })),
+        (
+            Subscribe::METHOD,
+            serde_json::json!({ "kinds": ["unknown"] }),
+        ),
+        (
+            MaterializeQueries::METHOD,
+            serde_json::json!({ "preview": "yes" }),
+        ),
+    ] {
+        let error = client.respond(method, params).error;
+        assert_eq!(
+            error.map(|error| error.code),
+            Some(ErrorCode::InvalidParams as i32),
+            "{method}"
+        );
+    }
+
+    client.request::<Shutdown>(());
+    client.notify::<Exit>(());
+    assert!(client.server.wait().unwrap().success());
+}
@@ -0,0 +1,162 @@
+//! Round-trip properties of the parser: wikilinks and labels embedded at known positions in
+//! random prose must be extracted exactly as written.
+
+use proptest::prelude::*;
+use std::path::Path;
+use std::sync::LazyLock;
+use typst_oxide::parser::Parser;
+use typst_oxide::parser::models::Wikilink;
+use typst_oxide::parser::wikilinks::WikilinkSyntax;
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Word(String),
+    Space,
+    Newline,
+    Link(Wikilink),
+    Label(String),
+}
+
+// Link parts may hold the link delimiters, which `WikilinkSyntax::format` must escape
+fn part() -> impl Strategy<Value = String> {
+    "[a-zé0-9 _/.:|#^\\[\\]\\\\-]{1,12}"
+}
+
+fn wikilink() -> impl Strategy<Value = Wikilink> {
+    let anchor = prop_oneof![
+        Just((None, None)),
+        part().prop_map(|label| (Some(label), None)),
+        "[a-zA-Z0-9-]{1,8}".prop_map(|block| (None, Some(block))),
+    ];
+    (part(), proptest::option::of(part()), anchor).prop_map(|(target, alias, (label, block))| {
+        Wikilink {
+            target,
+            alias,
+            label,
+            block,
+            line: 0,
+            column: 0,
+        }
+    })
+}
+
+fn piece() -> impl Strategy<Value = Piece> {
+    prop_oneof![
+        4 => "[a-zA-Zé0-9,.!?]{1,8}".prop_map(Piece::Word),
+        3 => Just(Piece::Space),
+        1 => Just(Piece::Newline),
+        2 => wikilink().prop_map(Piece::Link),
+        1 => "[a-z][a-z0-9:.-]{0,10}".prop_map(Piece::Label),
+    ]
+}
+
+// Building a parser compiles its regexes, so each syntax gets one shared by every case
+static PARSERS: LazyLock<Vec<(WikilinkSyntax, Parser)>> = LazyLock::new(|| {
+    [(':', false), (':', true), ('#', false), ('#', true)]
+        .into_iter()
+        .map(|(label_separator, alias_first)| {
+            let syntax = WikilinkSyntax {
+                label_separator,
+                alias_first,
+            };
+            let parser = Parser::with_syntax(&syntax).unwrap();
+            (syntax, parser)
+        })
+        .collect()
+});
+
+/// A document built from `pieces`, with its links and labels at the position they were written.
+fn render(
+    pieces: &[Piece],
+    syntax: &WikilinkSyntax,
+) -> (String, Vec<Wikilink>, Vec<(String, usize, usize)>) {
+    let mut content = String::new();
+    let mut links = Vec::new();
+    let mut labels = Vec::new();
+    let (mut line, mut column) = (1, 1);
+    // Separate pieces so adjacent links and labels can't run together
+    let push = |content: &mut String, text: &str, column: &mut usize| {
+        if !content.is_empty() && !content.ends_with([' ', '\n']) {
+            content.push(' ');
+            *column += 1;
+        }
+        content.push_str(text);
+        *column += text.chars().count();
+    };
+    for piece in pieces {
+        match piece {
+            Piece::Word(word) => push(&mut content, word, &mut column),
+            Piece::Space => {
+                content.push(' ');
+                column += 1;
+            }
+            Piece::Newline => {
+                content.push('\n');
+                line += 1;
+                column = 1;
+            }
+            Piece::Link(link) => {
+                let text = syntax.format(link);
+                let mut placed = link.clone();
+                placed.line = line;
+                push(&mut content, "", &mut column);
+                placed.column = column;
+                push(&mut content, &text, &mut column);
+                links.push(placed);
+            }
+            Piece::Label(name) => {
+                push(&mut content, "", &mut column);
+                labels.push((name.clone(), line, column));
+                push(&mut content, &format!("<{name}>"), &mut column);
+            }
+        }
+    }
+    (content, links, labels)
+}
+
+proptest! {
+    #[test]
+    fn test_round_trip(
+        pieces in proptest::collection::vec(piece(), 0..40),
+        syntax in 0..PARSERS.len(),
+    ) {
+        let (syntax, parser) = &PARSERS[syntax];
+        let (content, links, labels) = render(&pieces, syntax);
+        let parsed = parser.parse_content(&content, Path::new("/vault/note.typ")).unwrap();
+
+        prop_assert_eq!(&parsed.wikilinks, &links, "in {:?}", content);
+        let found: Vec<_> = parsed
+            .labels
+            .iter()
+            .map(|label| (label.name.clone(), label.line, label.column))
+            .collect();
+        prop_assert_eq!(found, labels, "in {:?}", content);
+    }
+
+    // Whatever the input, parsing must not panic and positions must lie within the note
+    #[test]
+    fn test_positions_in_bounds(content in "([a-z \\n\\[\\]|:#^<>@$`\\\\=*_()-]|figure){0,80}") {
+        let (_, parser) = &PARSERS[0];
+        let parsed = parser.parse_content(&content, Path::new("/vault/note.typ")).unwrap();
+        let lines: Vec<&str> = content.split('\n').collect();
+        let positions = parsed
+            .wikilinks
+            .iter()
+            .map(|link| (link.line, link.column))
+            .chain(parsed.labels.iter().map(|label| (label.line, label.column)))
+            .chain(parsed.headings.iter().map(|label| (label.line, label.column)))
+            .chain(parsed.blocks.iter().map(|block| (block.line, block.column)))
+            .chain(parsed.figures.iter().map(|figure| (figure.line, figure.column)))
+            .chain(parsed.citations.iter().map(|citation| (citation.line, citation.column)));
+        for (line, column) in positions {
+            prop_assert!(line >= 1 && line <= lines.len(), "line {} in {:?}", line, content);
+            prop_assert!(
+                column >= 1 && column <= lines[line - 1].chars().count(),
+                "column {} on line {} in {:?}",
+                column,
+                line,
+                content
+            );
+        }
+    }
+}